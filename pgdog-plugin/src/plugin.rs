@@ -1,7 +1,8 @@
 //! Plugin interface.
 use std::ops::Deref;
 
-use crate::bindings::{self, Input, Output};
+use crate::bindings::{self, Input, Output, Parameter};
+use libc::c_int;
 use libloading::{library_filename, Library, Symbol};
 
 /// Plugin interface.
@@ -14,6 +15,8 @@ pub struct Plugin<'a> {
     fini: Option<Symbol<'a, unsafe extern "C" fn()>>,
     /// Route query to a shard.
     route: Option<Symbol<'a, unsafe extern "C" fn(bindings::Input) -> Output>>,
+    /// Shard for a sharding key.
+    shard_key: Option<Symbol<'a, unsafe extern "C" fn(Parameter, c_int) -> c_int>>,
 }
 
 impl<'a> Plugin<'a> {
@@ -28,12 +31,14 @@ impl<'a> Plugin<'a> {
         let route = unsafe { library.get(b"pgdog_route_query\0") }.ok();
         let init = unsafe { library.get(b"pgdog_init\0") }.ok();
         let fini = unsafe { library.get(b"pgdog_fini\0") }.ok();
+        let shard_key = unsafe { library.get(b"pgdog_shard_key\0") }.ok();
 
         Self {
             name: name.to_owned(),
             route,
             init,
             fini,
+            shard_key,
         }
     }
 
@@ -42,6 +47,14 @@ impl<'a> Plugin<'a> {
         self.route.as_ref().map(|route| unsafe { route(input) })
     }
 
+    /// Get the shard for a sharding key. The plugin returns
+    /// a shard number, or a negative number if it can't tell.
+    pub fn shard_key(&self, value: Parameter, shards: usize) -> Option<i32> {
+        self.shard_key
+            .as_ref()
+            .map(|shard_key| unsafe { shard_key(value, shards as c_int) })
+    }
+
     /// Perform initialization.
     pub fn init(&self) -> bool {
         if let Some(init) = &self.init {
@@ -67,7 +80,7 @@ impl<'a> Plugin<'a> {
 
     /// Check that we have the required methods.
     pub fn valid(&self) -> bool {
-        self.route.is_some()
+        self.route.is_some() || self.shard_key.is_some()
     }
 }
 
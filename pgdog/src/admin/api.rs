@@ -0,0 +1,251 @@
+//! Admin commands over HTTP, for tools that don't speak Postgres.
+//!
+//! `GET /<name>` runs `SHOW <name>`, e.g. `GET /pools`, and `POST /<command>[/<arg>...]`
+//! runs any other command, e.g. `POST /pause/prod` or `POST /reload`.
+//! Rows are returned as a JSON array of objects. `GET /config/effective` returns
+//! the whole configuration and users, with passwords masked. Every request
+//! must send `Authorization: Bearer <http_token>`; without `http_token` in the
+//! config, the API isn't started. It listens on `http_host`, 127.0.0.1 by default.
+//! Commands, and requests with a wrong token, are recorded in the admin audit log
+//! as sent by the `http` user.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use http_body_util::Full;
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{AUTHORIZATION, CONTENT_TYPE},
+    Method, Request, Response, StatusCode,
+};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Map, Value};
+use subtle::ConstantTimeEq;
+use tracing::{error, info};
+
+use super::{audit, parser::Parser, Error};
+use crate::config::{config, reload};
+use crate::net::messages::{DataRow, Format, FromBytes, Message, Protocol, RowDescription};
+use crate::stats::http_server::serve;
+
+/// Path of the configuration PgDog is running with.
+pub const EFFECTIVE: &str = "/config/effective";
+
+/// Serve the admin API on this address.
+pub async fn server(host: &str, port: u16) -> std::io::Result<()> {
+    if config().config.admin.http_token.is_none() {
+        error!("admin API not started, http_token isn't set");
+        return Ok(());
+    }
+
+    info!("admin API http://{}:{}", host, port);
+    serve(host, port, handle).await
+}
+
+async fn handle(
+    request: Request<Incoming>,
+    peer: SocketAddr,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    let authorized = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .is_some_and(|token| authorized(token, config().config.admin.http_token.as_deref()));
+
+    if !authorized {
+        audit::record(
+            "http",
+            Some(peer),
+            &format!("{} {}", request.method(), request.uri().path()),
+            Some(&Error::Unauthorized),
+        );
+        return Ok(response(
+            StatusCode::UNAUTHORIZED,
+            json!({"error": "unauthorized"}),
+        ));
+    }
+
+    if request.method() == Method::GET
+        && request
+            .uri()
+            .path()
+            .trim_end_matches('/')
+            .eq_ignore_ascii_case(EFFECTIVE)
+    {
+        let mut config = reload::to_json(&config());
+        reload::mask(&mut config);
+        audit::record("http", Some(peer), &format!("GET {}", EFFECTIVE), None);
+        return Ok(response(StatusCode::OK, config));
+    }
+
+    let Some(sql) = command(request.method(), request.uri().path()) else {
+        return Ok(response(
+            StatusCode::NOT_FOUND,
+            json!({"error": "not found"}),
+        ));
+    };
+
+    let result = execute(&sql).await;
+    audit::record("http", Some(peer), &sql, result.as_ref().err());
+
+    Ok(match result {
+        Ok(rows) => response(StatusCode::OK, rows),
+        Err(err) => response(StatusCode::BAD_REQUEST, json!({"error": err.to_string()})),
+    })
+}
+
+/// Compare tokens in constant time. Without a configured token, nothing is allowed.
+fn authorized(token: &str, expected: Option<&str>) -> bool {
+    expected.is_some_and(|expected| bool::from(token.as_bytes().ct_eq(expected.as_bytes())))
+}
+
+fn response(status: StatusCode, body: Value) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    response
+}
+
+/// Admin command for this request. Like SQL admin commands,
+/// paths are case-insensitive.
+fn command(method: &Method, path: &str) -> Option<String> {
+    let parts = path
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            percent_decode_str(part)
+                .decode_utf8()
+                .ok()
+                .map(|part| part.to_lowercase())
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    if parts.is_empty() || parts.iter().any(|part| part.contains(';')) {
+        return None;
+    }
+
+    match *method {
+        Method::GET if parts.len() == 1 => Some(format!("show {}", parts[0])),
+        Method::POST => Some(parts.join(" ")),
+        _ => None,
+    }
+}
+
+/// Execute an admin command and return its rows as JSON.
+async fn execute(sql: &str) -> Result<Value, Error> {
+    let messages = Parser::parse(sql)?.execute().await?;
+    rows(&messages)
+}
+
+fn rows(messages: &[Message]) -> Result<Value, Error> {
+    let mut fields = vec![];
+    let mut rows = vec![];
+
+    for message in messages {
+        match message.code() {
+            'T' => {
+                let rd = RowDescription::from_bytes(message.payload())?;
+                fields = rd
+                    .fields
+                    .iter()
+                    .map(|field| (field.name.clone(), field.type_oid))
+                    .collect();
+            }
+            'D' => {
+                let dr = DataRow::from_bytes(message.payload())?;
+                let mut row = Map::new();
+                for (index, (name, oid)) in fields.iter().enumerate() {
+                    let value = match dr.get::<String>(index, Format::Text) {
+                        Some(value) => value_of(*oid, value),
+                        None => Value::Null,
+                    };
+                    row.insert(name.clone(), value);
+                }
+                rows.push(Value::Object(row));
+            }
+            _ => (),
+        }
+    }
+
+    Ok(Value::Array(rows))
+}
+
+/// JSON value of a column, using its type.
+fn value_of(oid: i32, value: String) -> Value {
+    match oid {
+        // bool
+        16 => Value::Bool(value == "t"),
+        // int8, int2, int4, float4, float8, numeric
+        20 | 21 | 23 | 700 | 701 | 1700 => value
+            .parse::<i64>()
+            .map(Value::from)
+            .or_else(|_| value.parse::<f64>().map(Value::from))
+            .unwrap_or(Value::String(value)),
+        _ => Value::String(value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::messages::Field;
+
+    #[test]
+    fn test_command() {
+        assert_eq!(
+            command(&Method::GET, "/pools").as_deref(),
+            Some("show pools")
+        );
+        assert_eq!(
+            command(&Method::POST, "/pause/prod/").as_deref(),
+            Some("pause prod")
+        );
+        assert_eq!(command(&Method::POST, "/reload").as_deref(), Some("reload"));
+        assert_eq!(
+            command(&Method::GET, "/Pools").as_deref(),
+            Some("show pools")
+        );
+        assert_eq!(
+            command(&Method::POST, "/PAUSE/Prod").as_deref(),
+            Some("pause prod")
+        );
+        assert!(command(&Method::GET, "/").is_none());
+        assert!(command(&Method::GET, "/pause/prod").is_none());
+        assert!(command(&Method::DELETE, "/pools").is_none());
+        assert!(command(&Method::POST, "/pause;reload").is_none());
+        assert_eq!(
+            command(&Method::POST, "/pause/my%20db").as_deref(),
+            Some("pause my db")
+        );
+        assert!(command(&Method::POST, "/pause%3Breload").is_none());
+        assert!(command(&Method::POST, "/pause/%ff").is_none());
+    }
+
+    #[test]
+    fn test_authorized() {
+        assert!(authorized("secret", Some("secret")));
+        assert!(!authorized("secret", Some("other")));
+        assert!(!authorized("", Some("secret")));
+        assert!(!authorized("", None));
+    }
+
+    #[test]
+    fn test_rows() {
+        let rd = RowDescription::new(&[
+            Field::text("database"),
+            Field::numeric("clients"),
+            Field::bool("paused"),
+        ]);
+        let mut dr = DataRow::new();
+        dr.add("prod").add(5_i64).add(false);
+        let messages = vec![rd.message().unwrap(), dr.message().unwrap()];
+
+        assert_eq!(
+            rows(&messages).unwrap(),
+            json!([{"database": "prod", "clients": 5, "paused": false}])
+        );
+    }
+}
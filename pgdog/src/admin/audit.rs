@@ -0,0 +1,69 @@
+//! Audit log of admin commands.
+//!
+//! Every command sent to the admin database or the HTTP API is appended
+//! to `audit_log` in `[admin]`, one JSON object per line, with
+//! who sent it, from where, and if it succeeded.
+
+use std::net::SocketAddr;
+
+use chrono::Local;
+use serde::Serialize;
+use tracing::error;
+
+use super::Error;
+use crate::config::config;
+use crate::frontend::audit::append;
+
+/// Admin command and its result.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Record {
+    pub timestamp: String,
+    pub user: String,
+    pub client: String,
+    pub command: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+impl Record {
+    fn new(user: &str, client: Option<SocketAddr>, command: &str, error: Option<&Error>) -> Self {
+        Self {
+            timestamp: Local::now().format("%Y-%m-%d %H:%M:%S%.3f %Z").to_string(),
+            user: user.to_owned(),
+            client: client.map(|client| client.to_string()).unwrap_or_default(),
+            command: command.trim().to_owned(),
+            success: error.is_none(),
+            error: error.map(|err| err.to_string()),
+        }
+    }
+}
+
+/// Record the command, if the audit log is enabled.
+pub fn record(user: &str, client: Option<SocketAddr>, command: &str, error: Option<&Error>) {
+    let Some(path) = config().config.admin.audit_log.clone() else {
+        return;
+    };
+
+    match serde_json::to_string(&Record::new(user, client, command, error)) {
+        Ok(line) => append(path, line + "\n", None),
+        Err(err) => error!("admin audit record serialization error: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let client = "10.0.0.1:5555".parse().unwrap();
+        let record = Record::new("admin", Some(client), " PAUSE prod; ", None);
+        assert_eq!(record.client, "10.0.0.1:5555");
+        assert_eq!(record.command, "PAUSE prod;");
+        assert!(record.success);
+
+        let record = Record::new("admin", None, "KILL 1", Some(&Error::NoClient(1)));
+        assert!(!record.success);
+        assert_eq!(record.error.as_deref(), Some("no client with id 1"));
+    }
+}
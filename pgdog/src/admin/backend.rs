@@ -1,6 +1,7 @@
 //! Handles client connections.
 
 use std::collections::VecDeque;
+use std::net::SocketAddr;
 use std::time::Duration;
 
 use tokio::time::sleep;
@@ -12,6 +13,7 @@ use crate::net::messages::command_complete::CommandComplete;
 use crate::net::messages::{ErrorResponse, FromBytes, Protocol, Query, ReadyForQuery};
 use crate::net::ToBytes;
 
+use super::audit;
 use super::parser::Parser;
 use super::prelude::Message;
 use super::Error;
@@ -20,6 +22,8 @@ use super::Error;
 #[derive(Debug)]
 pub struct Backend {
     messages: VecDeque<Message>,
+    user: String,
+    client: Option<SocketAddr>,
 }
 
 impl Default for Backend {
@@ -33,9 +37,17 @@ impl Backend {
     pub fn new() -> Self {
         Self {
             messages: VecDeque::new(),
+            user: String::new(),
+            client: None,
         }
     }
 
+    /// Admin user and their address, for the audit log.
+    pub fn client(&mut self, user: &str, addr: SocketAddr) {
+        self.user = user.to_owned();
+        self.client = Some(addr);
+    }
+
     /// Handle command.
     pub async fn send(&mut self, messages: &Buffer) -> Result<(), Error> {
         let message = messages.first().ok_or(Error::Empty)?;
@@ -50,12 +62,22 @@ impl Backend {
 
         let messages = match Parser::parse(&query.query().to_lowercase()) {
             Ok(command) => {
-                let mut messages = command.execute().await?;
+                let result = command.execute().await;
+                audit::record(
+                    &self.user,
+                    self.client,
+                    query.query(),
+                    result.as_ref().err(),
+                );
+                let mut messages = result?;
                 messages.push(CommandComplete::new(command.name()).message()?);
 
                 messages
             }
-            Err(err) => vec![ErrorResponse::syntax(err.to_string().as_str()).message()?],
+            Err(err) => {
+                audit::record(&self.user, self.client, query.query(), Some(&err));
+                vec![ErrorResponse::syntax(err.to_string().as_str()).message()?]
+            }
         };
 
         self.messages.extend(messages);
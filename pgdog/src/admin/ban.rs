@@ -0,0 +1,112 @@
+//! BAN <host:port> [duration_ms];
+//! UNBAN <host:port>;
+//!
+//! Take a server out of rotation in all databases, until it's unbanned
+//! or the duration, in milliseconds, expires. Healthchecks don't
+//! unban it.
+
+use std::time::Duration;
+
+use crate::backend::databases::databases;
+
+use super::prelude::*;
+
+/// Ban or unban a server.
+pub struct Ban {
+    host: String,
+    port: u16,
+    duration: Option<Duration>,
+    unban: bool,
+}
+
+impl Ban {
+    fn new(cmd: &str, addr: &str, duration: Option<Duration>) -> Result<Self, Error> {
+        let (host, port) = addr.rsplit_once(':').ok_or(Error::Syntax)?;
+        if host.is_empty() {
+            return Err(Error::Syntax);
+        }
+
+        Ok(Self {
+            host: host.to_owned(),
+            port: port.parse()?,
+            duration,
+            unban: cmd == "unban",
+        })
+    }
+}
+
+#[async_trait]
+impl Command for Ban {
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            [cmd @ ("ban" | "unban"), addr] => Self::new(cmd, addr, None),
+            ["ban", addr, duration] => {
+                Self::new("ban", addr, Some(Duration::from_millis(duration.parse()?)))
+            }
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut found = false;
+
+        for cluster in databases().all().values() {
+            for shard in cluster.shards() {
+                for pool in shard.pools() {
+                    let addr = pool.addr();
+                    if addr.host != self.host || addr.port != self.port {
+                        continue;
+                    }
+                    found = true;
+                    if self.unban {
+                        pool.unban_manually();
+                    } else {
+                        pool.ban_manually(self.duration);
+                    }
+                }
+            }
+        }
+
+        if found {
+            Ok(vec![])
+        } else {
+            Err(Error::NoServer(format!("{}:{}", self.host, self.port)))
+        }
+    }
+
+    fn name(&self) -> String {
+        if self.unban {
+            "UNBAN".into()
+        } else {
+            "BAN".into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ban = Ban::parse("ban 10.0.0.1:5432").unwrap();
+        assert_eq!(ban.host, "10.0.0.1");
+        assert_eq!(ban.port, 5432);
+        assert!(ban.duration.is_none());
+        assert!(!ban.unban);
+
+        let ban = Ban::parse("ban replica-1:6432 30000").unwrap();
+        assert_eq!(ban.host, "replica-1");
+        assert_eq!(ban.duration, Some(Duration::from_secs(30)));
+
+        let unban = Ban::parse("unban 10.0.0.1:5432").unwrap();
+        assert!(unban.unban);
+
+        assert!(Ban::parse("ban 10.0.0.1").is_err());
+        assert!(Ban::parse("ban :5432").is_err());
+        assert!(Ban::parse("unban 10.0.0.1:5432 30000").is_err());
+        assert!(Ban::parse("ban 10.0.0.1:5432 soon").is_err());
+    }
+}
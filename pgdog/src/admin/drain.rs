@@ -0,0 +1,109 @@
+//! Drain pool(s) for maintenance: stop serving new clients
+//! and close server connections once their transactions are done.
+//!
+//! Clients are sent to other replicas, if any, or wait
+//! until the pool is undrained.
+
+use crate::backend::databases::databases;
+
+use super::prelude::*;
+
+/// Drain pool(s).
+#[derive(Default, Debug, PartialEq)]
+pub struct Drain {
+    database: String,
+    host: Option<String>,
+    port: Option<u16>,
+    undrain: bool,
+}
+
+#[async_trait]
+impl Command for Drain {
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        let (cmd, database, addr) = match parts[..] {
+            [cmd, database] => (cmd, database, None),
+            [cmd, database, addr] => (cmd, database, Some(addr)),
+            _ => return Err(Error::Syntax),
+        };
+
+        let (host, port) = match addr.map(|addr| addr.split_once(':')) {
+            None => (None, None),
+            Some(None) => (addr.map(|addr| addr.to_owned()), None),
+            Some(Some((host, port))) => (Some(host.to_owned()), Some(port.parse()?)),
+        };
+
+        Ok(Self {
+            database: database.to_owned(),
+            host,
+            port,
+            undrain: cmd == "undrain",
+        })
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        for (name, cluster) in databases().all() {
+            if name.database != self.database {
+                continue;
+            }
+
+            for shard in cluster.shards() {
+                for pool in shard.pools() {
+                    let addr = pool.addr();
+                    if let Some(ref host) = self.host {
+                        if &addr.host != host {
+                            continue;
+                        }
+                    }
+                    if let Some(port) = self.port {
+                        if addr.port != port {
+                            continue;
+                        }
+                    }
+
+                    if self.undrain {
+                        pool.undrain();
+                    } else {
+                        pool.drain();
+                    }
+                }
+            }
+        }
+
+        Ok(vec![])
+    }
+
+    fn name(&self) -> String {
+        if self.undrain {
+            "UNDRAIN".into()
+        } else {
+            "DRAIN".into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_drain_parse() {
+        let drain = Drain::parse("drain pgdog").unwrap();
+        assert_eq!(drain.database, "pgdog");
+        assert!(drain.host.is_none());
+        assert!(!drain.undrain);
+
+        let drain = Drain::parse("undrain pgdog 10.0.0.1:5433").unwrap();
+        assert_eq!(drain.host.as_deref(), Some("10.0.0.1"));
+        assert_eq!(drain.port, Some(5433));
+        assert!(drain.undrain);
+
+        let drain = Drain::parse("drain pgdog replica-1").unwrap();
+        assert_eq!(drain.host.as_deref(), Some("replica-1"));
+        assert!(drain.port.is_none());
+
+        assert!(Drain::parse("drain").is_err());
+        assert!(Drain::parse("drain pgdog host:port").is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! ENABLE <database>;
+//! DISABLE <database>;
+//!
+//! Stop accepting new clients for a database, letting connected
+//! ones finish, and start accepting them again.
+
+use crate::backend::databases::disable;
+
+use super::prelude::*;
+
+/// Enable or disable a database.
+pub struct Enable {
+    database: String,
+    enable: bool,
+}
+
+#[async_trait]
+impl Command for Enable {
+    fn name(&self) -> String {
+        if self.enable {
+            "ENABLE".into()
+        } else {
+            "DISABLE".into()
+        }
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            [cmd @ ("enable" | "disable"), database] => Ok(Enable {
+                database: database.to_owned(),
+                enable: cmd == "enable",
+            }),
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        if disable(&self.database, !self.enable) {
+            Ok(vec![])
+        } else {
+            Err(Error::NoDatabase(self.database.clone()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let disable = Enable::parse("disable prod").unwrap();
+        assert_eq!(disable.database, "prod");
+        assert!(!disable.enable);
+        assert!(Enable::parse("enable prod").unwrap().enable);
+        assert!(Enable::parse("disable").is_err());
+        assert!(Enable::parse("enable prod now").is_err());
+    }
+}
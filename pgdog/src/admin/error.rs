@@ -19,6 +19,9 @@ pub enum Error {
     #[error("{0}")]
     SerdeJson(#[from] serde_json::Error),
 
+    #[error("{0}")]
+    Toml(#[from] toml::ser::Error),
+
     #[error("{0}")]
     Backend(Box<crate::backend::Error>),
 
@@ -27,4 +30,31 @@ pub enum Error {
 
     #[error("{0}")]
     Config(#[from] crate::config::error::Error),
+
+    #[error("no client with id {0}")]
+    NoClient(i32),
+
+    #[error("no database \"{0}\"")]
+    NoDatabase(String),
+
+    #[error("unknown setting \"{0}\"")]
+    UnknownSetting(String),
+
+    #[error("no server \"{0}\"")]
+    NoServer(String),
+
+    #[error("no view \"{0}\"")]
+    NoView(String),
+
+    #[error("no column \"{0}\"")]
+    NoColumn(String),
+
+    #[error("unauthorized")]
+    Unauthorized,
+}
+
+impl From<crate::backend::Error> for Error {
+    fn from(value: crate::backend::Error) -> Self {
+        Self::Backend(Box::new(value))
+    }
 }
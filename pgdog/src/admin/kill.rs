@@ -0,0 +1,48 @@
+//! KILL <client_id>;
+//!
+//! Disconnect a client, using the `id` from `SHOW CLIENTS`.
+//! Its transaction, if any, is rolled back.
+
+use crate::frontend::comms::comms;
+
+use super::prelude::*;
+
+pub struct Kill {
+    id: i32,
+}
+
+#[async_trait]
+impl Command for Kill {
+    fn name(&self) -> String {
+        "KILL".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            ["kill", id] => Ok(Kill { id: id.parse()? }),
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        if comms().kill(self.id) {
+            Ok(vec![])
+        } else {
+            Err(Error::NoClient(self.id))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Kill::parse("kill 1234").unwrap().id, 1234);
+        assert!(Kill::parse("kill").is_err());
+        assert!(Kill::parse("kill abc").is_err());
+    }
+}
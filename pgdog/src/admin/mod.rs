@@ -4,26 +4,49 @@ use async_trait::async_trait;
 
 use crate::net::messages::Message;
 
+pub mod api;
+pub mod audit;
 pub mod backend;
+pub mod ban;
+pub mod drain;
+pub mod enable;
 pub mod error;
+pub mod kill;
 pub mod parser;
 pub mod pause;
 pub mod prelude;
 pub mod reconnect;
 pub mod reload;
+pub mod replication_slot;
 pub mod reset_query_cache;
+pub mod reshard;
+pub mod rollback;
+pub mod select;
 pub mod set;
 pub mod setup_schema;
+pub mod show_changes;
 pub mod show_clients;
 pub mod show_config;
+pub mod show_copy;
+pub mod show_errors;
+pub mod show_failovers;
 pub mod show_lists;
+pub mod show_mirrors;
 pub mod show_peers;
 pub mod show_pools;
 pub mod show_prepared_statements;
+pub mod show_queries;
 pub mod show_query_cache;
+pub mod show_query_stats;
+pub mod show_reload;
+pub mod show_replication;
+pub mod show_replication_slots;
+pub mod show_reshard;
 pub mod show_servers;
+pub mod show_shutdown;
 pub mod show_stats;
 pub mod show_version;
+pub mod show_wait_events;
 pub mod shutdown;
 
 pub use error::Error;
@@ -1,12 +1,19 @@
 //! Admin command parser.
 
 use super::{
-    pause::Pause, prelude::Message, reconnect::Reconnect, reload::Reload,
-    reset_query_cache::ResetQueryCache, set::Set, setup_schema::SetupSchema,
-    show_clients::ShowClients, show_config::ShowConfig, show_lists::ShowLists,
+    ban::Ban, drain::Drain, enable::Enable, kill::Kill, pause::Pause, prelude::Message,
+    reconnect::Reconnect, reload::Reload, replication_slot::ReplicationSlot,
+    reset_query_cache::ResetQueryCache, reshard::Reshard, rollback::Rollback, select::Select,
+    set::Set, setup_schema::SetupSchema, show_changes::ShowChanges, show_clients::ShowClients,
+    show_config::ShowConfig, show_copy::ShowCopy, show_errors::ShowErrors,
+    show_failovers::ShowFailovers, show_lists::ShowLists, show_mirrors::ShowMirrors,
     show_peers::ShowPeers, show_pools::ShowPools, show_prepared_statements::ShowPreparedStatements,
-    show_query_cache::ShowQueryCache, show_servers::ShowServers, show_stats::ShowStats,
-    show_version::ShowVersion, shutdown::Shutdown, Command, Error,
+    show_queries::ShowQueries, show_query_cache::ShowQueryCache, show_query_stats::ShowQueryStats,
+    show_reload::ShowReload, show_replication::ShowReplication,
+    show_replication_slots::ShowReplicationSlots, show_reshard::ShowReshard,
+    show_servers::ShowServers, show_shutdown::ShowShutdown, show_stats::ShowStats,
+    show_version::ShowVersion, show_wait_events::ShowWaitEvents, shutdown::Shutdown, Command,
+    Error,
 };
 
 use tracing::debug;
@@ -14,11 +21,14 @@ use tracing::debug;
 /// Parser result.
 pub enum ParseResult {
     Pause(Pause),
+    Drain(Drain),
     Reconnect(Reconnect),
     ShowClients(ShowClients),
     Reload(Reload),
     ShowPools(ShowPools),
     ShowConfig(ShowConfig),
+    ShowCopy(ShowCopy),
+    ShowFailovers(ShowFailovers),
     ShowServers(ShowServers),
     ShowPeers(ShowPeers),
     ShowQueryCache(ShowQueryCache),
@@ -30,6 +40,24 @@ pub enum ParseResult {
     ShowLists(ShowLists),
     ShowPrepared(ShowPreparedStatements),
     Set(Set),
+    ReplicationSlot(ReplicationSlot),
+    ShowReplicationSlots(ShowReplicationSlots),
+    Reshard(Reshard),
+    ShowReshard(ShowReshard),
+    ShowMirrors(ShowMirrors),
+    Kill(Kill),
+    ShowChanges(ShowChanges),
+    ShowQueries(ShowQueries),
+    ShowErrors(ShowErrors),
+    Ban(Ban),
+    Enable(Enable),
+    ShowReplication(ShowReplication),
+    ShowShutdown(ShowShutdown),
+    ShowQueryStats(ShowQueryStats),
+    Select(Select),
+    ShowWaitEvents(ShowWaitEvents),
+    ShowReload(ShowReload),
+    Rollback(Rollback),
 }
 
 impl ParseResult {
@@ -39,11 +67,14 @@ impl ParseResult {
 
         match self {
             Pause(pause) => pause.execute().await,
+            Drain(drain) => drain.execute().await,
             Reconnect(reconnect) => reconnect.execute().await,
             ShowClients(show_clients) => show_clients.execute().await,
             Reload(reload) => reload.execute().await,
             ShowPools(show_pools) => show_pools.execute().await,
             ShowConfig(show_config) => show_config.execute().await,
+            ShowCopy(show_copy) => show_copy.execute().await,
+            ShowFailovers(show_failovers) => show_failovers.execute().await,
             ShowServers(show_servers) => show_servers.execute().await,
             ShowPeers(show_peers) => show_peers.execute().await,
             ShowQueryCache(show_query_cache) => show_query_cache.execute().await,
@@ -55,6 +86,24 @@ impl ParseResult {
             ShowLists(show_lists) => show_lists.execute().await,
             ShowPrepared(cmd) => cmd.execute().await,
             Set(set) => set.execute().await,
+            ReplicationSlot(replication_slot) => replication_slot.execute().await,
+            ShowReplicationSlots(show_replication_slots) => show_replication_slots.execute().await,
+            Reshard(reshard) => reshard.execute().await,
+            ShowReshard(show_reshard) => show_reshard.execute().await,
+            ShowMirrors(show_mirrors) => show_mirrors.execute().await,
+            Kill(kill) => kill.execute().await,
+            ShowChanges(show_changes) => show_changes.execute().await,
+            ShowQueries(show_queries) => show_queries.execute().await,
+            ShowErrors(show_errors) => show_errors.execute().await,
+            Ban(ban) => ban.execute().await,
+            Enable(enable) => enable.execute().await,
+            ShowReplication(show_replication) => show_replication.execute().await,
+            ShowShutdown(show_shutdown) => show_shutdown.execute().await,
+            ShowQueryStats(show_query_stats) => show_query_stats.execute().await,
+            Select(select) => select.execute().await,
+            ShowWaitEvents(show_wait_events) => show_wait_events.execute().await,
+            ShowReload(show_reload) => show_reload.execute().await,
+            Rollback(rollback) => rollback.execute().await,
         }
     }
 
@@ -64,11 +113,14 @@ impl ParseResult {
 
         match self {
             Pause(pause) => pause.name(),
+            Drain(drain) => drain.name(),
             Reconnect(reconnect) => reconnect.name(),
             ShowClients(show_clients) => show_clients.name(),
             Reload(reload) => reload.name(),
             ShowPools(show_pools) => show_pools.name(),
             ShowConfig(show_config) => show_config.name(),
+            ShowCopy(show_copy) => show_copy.name(),
+            ShowFailovers(show_failovers) => show_failovers.name(),
             ShowServers(show_servers) => show_servers.name(),
             ShowPeers(show_peers) => show_peers.name(),
             ShowQueryCache(show_query_cache) => show_query_cache.name(),
@@ -80,6 +132,24 @@ impl ParseResult {
             ShowLists(show_lists) => show_lists.name(),
             ShowPrepared(show) => show.name(),
             Set(set) => set.name(),
+            ReplicationSlot(replication_slot) => replication_slot.name(),
+            ShowReplicationSlots(show_replication_slots) => show_replication_slots.name(),
+            Reshard(reshard) => reshard.name(),
+            ShowReshard(show_reshard) => show_reshard.name(),
+            ShowMirrors(show_mirrors) => show_mirrors.name(),
+            Kill(kill) => kill.name(),
+            ShowChanges(show_changes) => show_changes.name(),
+            ShowQueries(show_queries) => show_queries.name(),
+            ShowErrors(show_errors) => show_errors.name(),
+            Ban(ban) => ban.name(),
+            Enable(enable) => enable.name(),
+            ShowReplication(show_replication) => show_replication.name(),
+            ShowShutdown(show_shutdown) => show_shutdown.name(),
+            ShowQueryStats(show_query_stats) => show_query_stats.name(),
+            Select(select) => select.name(),
+            ShowWaitEvents(show_wait_events) => show_wait_events.name(),
+            ShowReload(show_reload) => show_reload.name(),
+            Rollback(rollback) => rollback.name(),
         }
     }
 }
@@ -90,18 +160,30 @@ pub struct Parser;
 impl Parser {
     /// Parse the query and return a command we can execute.
     pub fn parse(sql: &str) -> Result<ParseResult, Error> {
-        let sql = sql.trim().replace(";", "").to_lowercase();
+        // Views are queried with SQL, which is case-sensitive.
+        let query = sql.trim();
+        let sql = query.replace(";", "").to_lowercase();
         let mut iter = sql.split(" ");
 
         Ok(match iter.next().ok_or(Error::Syntax)?.trim() {
             "pause" | "resume" => ParseResult::Pause(Pause::parse(&sql)?),
+            "drain" | "undrain" => ParseResult::Drain(Drain::parse(&sql)?),
             "shutdown" => ParseResult::Shutdown(Shutdown::parse(&sql)?),
             "reconnect" => ParseResult::Reconnect(Reconnect::parse(&sql)?),
             "reload" => ParseResult::Reload(Reload::parse(&sql)?),
+            "create" | "drop" => ParseResult::ReplicationSlot(ReplicationSlot::parse(&sql)?),
+            "reshard" => ParseResult::Reshard(Reshard::parse(&sql)?),
+            "kill" => ParseResult::Kill(Kill::parse(&sql)?),
+            "ban" | "unban" => ParseResult::Ban(Ban::parse(&sql)?),
+            "enable" | "disable" => ParseResult::Enable(Enable::parse(&sql)?),
+            "select" => ParseResult::Select(Select::parse(query)?),
+            "rollback" => ParseResult::Rollback(Rollback::parse(&sql)?),
             "show" => match iter.next().ok_or(Error::Syntax)?.trim() {
                 "clients" => ParseResult::ShowClients(ShowClients::parse(&sql)?),
                 "pools" => ParseResult::ShowPools(ShowPools::parse(&sql)?),
                 "config" => ParseResult::ShowConfig(ShowConfig::parse(&sql)?),
+                "copy" => ParseResult::ShowCopy(ShowCopy::parse(&sql)?),
+                "failovers" => ParseResult::ShowFailovers(ShowFailovers::parse(&sql)?),
                 "servers" => ParseResult::ShowServers(ShowServers::parse(&sql)?),
                 "peers" => ParseResult::ShowPeers(ShowPeers::parse(&sql)?),
                 "query_cache" => ParseResult::ShowQueryCache(ShowQueryCache::parse(&sql)?),
@@ -109,6 +191,21 @@ impl Parser {
                 "version" => ParseResult::ShowVersion(ShowVersion::parse(&sql)?),
                 "lists" => ParseResult::ShowLists(ShowLists::parse(&sql)?),
                 "prepared" => ParseResult::ShowPrepared(ShowPreparedStatements::parse(&sql)?),
+                "replication_slots" => {
+                    ParseResult::ShowReplicationSlots(ShowReplicationSlots::parse(&sql)?)
+                }
+                "reshard" => ParseResult::ShowReshard(ShowReshard::parse(&sql)?),
+                "mirrors" => ParseResult::ShowMirrors(ShowMirrors::parse(&sql)?),
+                "changes" => ParseResult::ShowChanges(ShowChanges::parse(&sql)?),
+                "queries" => ParseResult::ShowQueries(ShowQueries::parse(&sql)?),
+                "errors" => ParseResult::ShowErrors(ShowErrors::parse(&sql)?),
+                "replication" => ParseResult::ShowReplication(ShowReplication::parse(&sql)?),
+                "shutdown" => ParseResult::ShowShutdown(ShowShutdown::parse(&sql)?),
+                "query" | "query_stats" => {
+                    ParseResult::ShowQueryStats(ShowQueryStats::parse(&sql)?)
+                }
+                "wait" | "wait_events" => ParseResult::ShowWaitEvents(ShowWaitEvents::parse(&sql)?),
+                "reload" => ParseResult::ShowReload(ShowReload::parse(&sql)?),
                 command => {
                     debug!("unknown admin show command: '{}'", command);
                     return Err(Error::Syntax);
@@ -128,9 +225,6 @@ impl Parser {
                     return Err(Error::Syntax);
                 }
             },
-            // TODO: This is not ready yet. We have a race and
-            // also the changed settings need to be propagated
-            // into the pools.
             "set" => ParseResult::Set(Set::parse(&sql)?),
             command => {
                 debug!("unknown admin command: {}", command);
@@ -1,11 +1,15 @@
-//! Recreate all connections to all databases.
+//! RECONNECT [<database>];
+//!
+//! Recreate all connections to all databases, or just one of them.
 
-use crate::backend::databases::reconnect;
+use crate::backend::databases::{reconnect, reconnect_database};
 
 use super::prelude::*;
 
 /// Recreate connections.
-pub struct Reconnect;
+pub struct Reconnect {
+    database: Option<String>,
+}
 
 #[async_trait]
 impl Command for Reconnect {
@@ -14,14 +18,45 @@ impl Command for Reconnect {
     }
 
     fn parse(sql: &str) -> Result<Self, Error> {
-        match sql {
-            "reconnect" => Ok(Reconnect),
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            ["reconnect"] => Ok(Reconnect { database: None }),
+            ["reconnect", database] => Ok(Reconnect {
+                database: Some(database.to_owned()),
+            }),
             _ => Err(Error::Syntax),
         }
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
-        reconnect();
+        match self.database {
+            Some(ref database) => {
+                if reconnect_database(database) == 0 {
+                    return Err(Error::NoDatabase(database.clone()));
+                }
+            }
+            None => reconnect(),
+        }
+
         Ok(vec![])
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(Reconnect::parse("reconnect").unwrap().database.is_none());
+        assert_eq!(
+            Reconnect::parse("reconnect prod")
+                .unwrap()
+                .database
+                .as_deref(),
+            Some("prod")
+        );
+        assert!(Reconnect::parse("reconnect prod now").is_err());
+    }
+}
@@ -1,9 +1,14 @@
-//! RELOAD command.
+//! RELOAD [<database>];
+//!
+//! Reload the config and re-create pools of all databases,
+//! or just one of them.
 
 use super::prelude::*;
-use crate::backend::databases::reload;
+use crate::backend::databases::{reload, reload_database};
 
-pub struct Reload;
+pub struct Reload {
+    database: Option<String>,
+}
 
 #[async_trait]
 impl Command for Reload {
@@ -11,12 +16,48 @@ impl Command for Reload {
         "RELOAD".into()
     }
 
-    fn parse(_sql: &str) -> Result<Self, Error> {
-        Ok(Reload)
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            ["reload"] => Ok(Reload { database: None }),
+            ["reload", database] => Ok(Reload {
+                database: Some(database.to_owned()),
+            }),
+            _ => Err(Error::Syntax),
+        }
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
-        let _ = reload(); // TODO: error check.
+        match self.database {
+            Some(ref database) => {
+                if reload_database(database).map_err(|e| Error::Backend(Box::new(e)))? == 0 {
+                    return Err(Error::NoDatabase(database.clone()));
+                }
+            }
+            None => {
+                let _ = reload(); // TODO: error check.
+            }
+        }
+
         Ok(vec![])
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(Reload::parse("reload").unwrap().database.is_none());
+        assert_eq!(
+            Reload::parse("reload tenant_1")
+                .unwrap()
+                .database
+                .as_deref(),
+            Some("tenant_1")
+        );
+        assert!(Reload::parse("reload tenant_1 now").is_err());
+    }
+}
@@ -0,0 +1,58 @@
+//! CREATE REPLICATION_SLOT <database> <slot>;
+//! DROP REPLICATION_SLOT <database> <slot>;
+//!
+//! Create or drop a logical replication slot on all shards of a database.
+
+use crate::backend::{
+    replication::slots::{clusters, create_slot, drop_slot},
+    Error as BackendError,
+};
+
+use super::prelude::*;
+
+pub struct ReplicationSlot {
+    database: String,
+    slot: String,
+    drop: bool,
+}
+
+#[async_trait]
+impl Command for ReplicationSlot {
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            [cmd @ ("create" | "drop"), "replication_slot", database, slot] => Ok(Self {
+                database: database.to_owned(),
+                slot: slot.to_owned(),
+                drop: cmd == "drop",
+            }),
+
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let cluster = clusters()
+            .into_iter()
+            .find(|cluster| cluster.name() == self.database)
+            .ok_or(Error::Backend(Box::new(BackendError::NoCluster)))?;
+
+        if self.drop {
+            drop_slot(&cluster, &self.slot).await
+        } else {
+            create_slot(&cluster, &self.slot).await
+        }
+        .map_err(|e| Error::Backend(Box::new(e)))?;
+
+        Ok(vec![])
+    }
+
+    fn name(&self) -> String {
+        if self.drop {
+            "DROP REPLICATION_SLOT".into()
+        } else {
+            "CREATE REPLICATION_SLOT".into()
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! RESHARD <source> <destination>;
+//! RESHARD CANCEL <source>;
+//!
+//! Split a database into the shards of another one, online.
+
+use crate::backend::{replication::reshard, Error as BackendError};
+
+use super::prelude::*;
+
+pub struct Reshard {
+    source: String,
+    destination: Option<String>,
+}
+
+#[async_trait]
+impl Command for Reshard {
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            ["reshard", "cancel", source] => Ok(Self {
+                source: source.to_owned(),
+                destination: None,
+            }),
+
+            ["reshard", source, destination] => Ok(Self {
+                source: source.to_owned(),
+                destination: Some(destination.to_owned()),
+            }),
+
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        match self.destination {
+            Some(ref destination) => reshard::start(&self.source, destination),
+            None => reshard::cancel(&self.source),
+        }
+        .map_err(|e| Error::Backend(Box::new(BackendError::Replication(e))))?;
+
+        Ok(vec![])
+    }
+
+    fn name(&self) -> String {
+        if self.destination.is_some() {
+            "RESHARD".into()
+        } else {
+            "RESHARD CANCEL".into()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cmd = Reshard::parse("reshard prod prod_split").unwrap();
+        assert_eq!(cmd.source, "prod");
+        assert_eq!(cmd.destination.as_deref(), Some("prod_split"));
+
+        let cmd = Reshard::parse("reshard cancel prod").unwrap();
+        assert_eq!(cmd.name(), "RESHARD CANCEL");
+        assert!(cmd.destination.is_none());
+
+        assert!(Reshard::parse("reshard prod").is_err());
+    }
+}
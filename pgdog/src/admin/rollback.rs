@@ -0,0 +1,61 @@
+//! ROLLBACK CONFIG [<version>];
+//!
+//! Apply the previous configuration again, or the version
+//! from `SHOW CONFIG HISTORY`.
+
+use super::prelude::*;
+use crate::backend::databases::rollback;
+
+pub struct Rollback {
+    version: Option<usize>,
+}
+
+#[async_trait]
+impl Command for Rollback {
+    fn name(&self) -> String {
+        "ROLLBACK CONFIG".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        match parts[..] {
+            ["rollback", "config"] => Ok(Rollback { version: None }),
+            ["rollback", "config", version] => Ok(Rollback {
+                version: Some(version.parse()?),
+            }),
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let version = rollback(self.version).map_err(|e| Error::Backend(Box::new(e)))?;
+
+        let mut dr = DataRow::new();
+        dr.add(version as i64);
+
+        Ok(vec![
+            RowDescription::new(&[Field::bigint("version")]).message()?,
+            dr.message()?,
+        ])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(Rollback::parse("rollback config")
+            .unwrap()
+            .version
+            .is_none());
+        assert_eq!(
+            Rollback::parse("rollback config 3").unwrap().version,
+            Some(3)
+        );
+        assert!(Rollback::parse("rollback").is_err());
+        assert!(Rollback::parse("rollback config three").is_err());
+    }
+}
@@ -0,0 +1,460 @@
+//! SELECT ... FROM pgdog.stat_statements [WHERE ...] [ORDER BY ...] [LIMIT ...];
+//!
+//! Admin views queryable with plain SQL, so dashboards written for
+//! `pg_stat_statements` work against PgDog too. Only a subset of SQL is supported:
+//! a list of columns or `*`, comparisons and `LIKE` joined with `AND`,
+//! `ORDER BY` and `LIMIT`.
+
+use std::cmp::Ordering;
+
+use pg_query::{
+    parse,
+    protobuf::{a_const::Val, AExprKind, BoolExprType, Node, SelectStmt, SortByDir},
+    NodeEnum,
+};
+
+use crate::stats::query_stats::stats;
+use crate::util::wildcard;
+
+use super::prelude::*;
+
+/// Value of a column in a view.
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Text(String),
+    Integer(i64),
+    Float(f64),
+}
+
+impl Value {
+    fn number(&self) -> Option<f64> {
+        match self {
+            Self::Integer(value) => Some(*value as f64),
+            Self::Float(value) => Some(*value),
+            Self::Text(value) => value.parse().ok(),
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Self::Text(value) => value.clone(),
+            Self::Integer(value) => value.to_string(),
+            Self::Float(value) => value.to_string(),
+        }
+    }
+
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Text(left), Self::Text(right)) => Some(left.cmp(right)),
+            _ => self.number()?.partial_cmp(&other.number()?),
+        }
+    }
+}
+
+/// Rows of a view, computed when it's queried.
+struct View {
+    columns: Vec<(&'static str, bool)>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl View {
+    fn load(name: &str) -> Option<Self> {
+        match name {
+            "stat_statements" => Some(Self::stat_statements()),
+            _ => None,
+        }
+    }
+
+    /// Same columns as `pg_stat_statements`, where we have them.
+    fn stat_statements() -> Self {
+        let rows = stats()
+            .into_iter()
+            .map(|stats| {
+                let shards = stats
+                    .shards
+                    .iter()
+                    .map(|(shard, calls)| format!("{}:{}", shard, calls))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                vec![
+                    Value::Text(stats.fingerprint.clone()),
+                    Value::Text(stats.query.clone()),
+                    Value::Integer(stats.calls as i64),
+                    Value::Float(stats.total().as_secs_f64() * 1000.0),
+                    Value::Float(stats.mean().as_secs_f64() * 1000.0),
+                    Value::Float(stats.histogram.percentile(0.99).as_secs_f64() * 1000.0),
+                    Value::Integer(stats.rows as i64),
+                    Value::Text(shards),
+                ]
+            })
+            .collect();
+
+        Self {
+            columns: vec![
+                ("queryid", false),
+                ("query", false),
+                ("calls", true),
+                ("total_exec_time", true),
+                ("mean_exec_time", true),
+                ("p99_exec_time", true),
+                ("rows", true),
+                ("shards", false),
+            ],
+            rows,
+        }
+    }
+
+    fn column(&self, name: &str) -> Result<usize, Error> {
+        self.columns
+            .iter()
+            .position(|(column, _)| *column == name)
+            .ok_or_else(|| Error::NoColumn(name.to_owned()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Like,
+    ILike,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Filter {
+    column: String,
+    op: Op,
+    value: Value,
+}
+
+impl Filter {
+    fn matches(&self, value: &Value) -> bool {
+        match self.op {
+            Op::Like => wildcard(&self.value.text(), &value.text(), '%', '_'),
+            Op::ILike => wildcard(
+                &self.value.text().to_lowercase(),
+                &value.text().to_lowercase(),
+                '%',
+                '_',
+            ),
+            op => match value.compare(&self.value) {
+                Some(ordering) => match op {
+                    Op::Eq => ordering.is_eq(),
+                    Op::NotEq => ordering.is_ne(),
+                    Op::Lt => ordering.is_lt(),
+                    Op::LtEq => ordering.is_le(),
+                    Op::Gt => ordering.is_gt(),
+                    Op::GtEq => ordering.is_ge(),
+                    Op::Like | Op::ILike => unreachable!(),
+                },
+                None => false,
+            },
+        }
+    }
+}
+
+pub struct Select {
+    view: String,
+    /// Selected columns, all of them if empty.
+    columns: Vec<String>,
+    filters: Vec<Filter>,
+    /// Columns to sort by, and if descending.
+    order_by: Vec<(String, bool)>,
+    limit: Option<usize>,
+}
+
+#[async_trait]
+impl Command for Select {
+    fn name(&self) -> String {
+        "SELECT".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let ast = parse(sql).map_err(|_| Error::Syntax)?;
+        let stmt = ast
+            .protobuf
+            .stmts
+            .first()
+            .and_then(|stmt| stmt.stmt.as_ref())
+            .and_then(|stmt| stmt.node.as_ref());
+        let Some(NodeEnum::SelectStmt(stmt)) = stmt else {
+            return Err(Error::Syntax);
+        };
+
+        Self::from_stmt(stmt)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let view = View::load(&self.view).ok_or_else(|| Error::NoView(self.view.clone()))?;
+
+        let columns = if self.columns.is_empty() {
+            (0..view.columns.len()).collect::<Vec<_>>()
+        } else {
+            self.columns
+                .iter()
+                .map(|column| view.column(column))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let filters = self
+            .filters
+            .iter()
+            .map(|filter| Ok((view.column(&filter.column)?, filter)))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let order_by = self
+            .order_by
+            .iter()
+            .map(|(column, desc)| Ok((view.column(column)?, *desc)))
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let mut rows = view
+            .rows
+            .into_iter()
+            .filter(|row| {
+                filters
+                    .iter()
+                    .all(|(column, filter)| filter.matches(&row[*column]))
+            })
+            .collect::<Vec<_>>();
+
+        rows.sort_by(|a, b| {
+            for (column, desc) in &order_by {
+                let ordering = a[*column].compare(&b[*column]).unwrap_or(Ordering::Equal);
+                let ordering = if *desc { ordering.reverse() } else { ordering };
+                if ordering.is_ne() {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+
+        if let Some(limit) = self.limit {
+            rows.truncate(limit);
+        }
+
+        let fields = columns
+            .iter()
+            .map(|column| {
+                let (name, numeric) = view.columns[*column];
+                if numeric {
+                    Field::numeric(name)
+                } else {
+                    Field::text(name)
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut messages = vec![RowDescription::new(&fields).message()?];
+
+        for row in rows {
+            let mut data_row = DataRow::new();
+            for column in &columns {
+                match &row[*column] {
+                    Value::Text(value) => data_row.add(value.as_str()),
+                    Value::Integer(value) => data_row.add(*value),
+                    Value::Float(value) => data_row.add(*value),
+                };
+            }
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
+
+impl Select {
+    fn from_stmt(stmt: &SelectStmt) -> Result<Self, Error> {
+        // Views live in the pgdog schema, but it's optional.
+        let view = match stmt.from_clause.as_slice() {
+            [Node {
+                node: Some(NodeEnum::RangeVar(table)),
+            }] if table.schemaname.is_empty() || table.schemaname == "pgdog" => {
+                table.relname.clone()
+            }
+            _ => return Err(Error::Syntax),
+        };
+
+        let mut columns = vec![];
+        for target in &stmt.target_list {
+            let Some(NodeEnum::ResTarget(target)) = &target.node else {
+                return Err(Error::Syntax);
+            };
+            match target.val.as_deref().and_then(column) {
+                Some(Some(column)) => columns.push(column),
+                // SELECT *
+                Some(None) => {
+                    columns.clear();
+                    break;
+                }
+                None => return Err(Error::Syntax),
+            }
+        }
+
+        let mut filters = vec![];
+        if let Some(ref node) = stmt.where_clause {
+            Self::filters(node, &mut filters)?;
+        }
+
+        let mut order_by = vec![];
+        for sort_by in &stmt.sort_clause {
+            let Some(NodeEnum::SortBy(sort_by)) = &sort_by.node else {
+                return Err(Error::Syntax);
+            };
+            let Some(Some(column)) = sort_by.node.as_deref().and_then(column) else {
+                return Err(Error::Syntax);
+            };
+            order_by.push((column, sort_by.sortby_dir() == SortByDir::SortbyDesc));
+        }
+
+        let limit = match stmt.limit_count.as_deref().map(constant) {
+            None => None,
+            Some(Some(Value::Integer(limit))) if limit >= 0 => Some(limit as usize),
+            Some(_) => return Err(Error::Syntax),
+        };
+
+        Ok(Self {
+            view,
+            columns,
+            filters,
+            order_by,
+            limit,
+        })
+    }
+
+    fn filters(node: &Node, filters: &mut Vec<Filter>) -> Result<(), Error> {
+        match &node.node {
+            Some(NodeEnum::BoolExpr(expr)) if expr.boolop() == BoolExprType::AndExpr => {
+                for arg in &expr.args {
+                    Self::filters(arg, filters)?;
+                }
+                Ok(())
+            }
+
+            Some(NodeEnum::AExpr(expr)) => {
+                let name = expr.name.first().and_then(|node| match &node.node {
+                    Some(NodeEnum::String(name)) => Some(name.sval.as_str()),
+                    _ => None,
+                });
+                let op = match (expr.kind(), name) {
+                    (AExprKind::AexprOp, Some("=")) => Op::Eq,
+                    (AExprKind::AexprOp, Some("<>" | "!=")) => Op::NotEq,
+                    (AExprKind::AexprOp, Some("<")) => Op::Lt,
+                    (AExprKind::AexprOp, Some("<=")) => Op::LtEq,
+                    (AExprKind::AexprOp, Some(">")) => Op::Gt,
+                    (AExprKind::AexprOp, Some(">=")) => Op::GtEq,
+                    (AExprKind::AexprLike, Some("~~")) => Op::Like,
+                    (AExprKind::AexprIlike, Some("~~*")) => Op::ILike,
+                    _ => return Err(Error::Syntax),
+                };
+                let Some(Some(column)) = expr.lexpr.as_deref().and_then(column) else {
+                    return Err(Error::Syntax);
+                };
+                let value = expr
+                    .rexpr
+                    .as_deref()
+                    .and_then(constant)
+                    .ok_or(Error::Syntax)?;
+                filters.push(Filter { column, op, value });
+                Ok(())
+            }
+
+            _ => Err(Error::Syntax),
+        }
+    }
+}
+
+/// Column name, or `None` for `*`.
+fn column(node: &Node) -> Option<Option<String>> {
+    let Some(NodeEnum::ColumnRef(column)) = &node.node else {
+        return None;
+    };
+    // Qualified names, e.g. stat_statements.calls, use the last part.
+    match &column.fields.last()?.node {
+        Some(NodeEnum::String(name)) => Some(Some(name.sval.clone())),
+        Some(NodeEnum::AStar(_)) => Some(None),
+        _ => None,
+    }
+}
+
+fn constant(node: &Node) -> Option<Value> {
+    let Some(NodeEnum::AConst(constant)) = &node.node else {
+        return None;
+    };
+    match constant.val.as_ref()? {
+        Val::Ival(value) => Some(Value::Integer(value.ival as i64)),
+        Val::Fval(value) => value.fval.parse().ok().map(Value::Float),
+        Val::Sval(value) => Some(Value::Text(value.sval.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let select = Select::parse(
+            "SELECT queryid, calls FROM pgdog.stat_statements WHERE calls > 10 AND query ILIKE '%Users%' ORDER BY total_exec_time DESC, calls LIMIT 5",
+        )
+        .unwrap();
+        assert_eq!(select.view, "stat_statements");
+        assert_eq!(select.columns, vec!["queryid", "calls"]);
+        assert_eq!(
+            select.filters,
+            vec![
+                Filter {
+                    column: "calls".into(),
+                    op: Op::Gt,
+                    value: Value::Integer(10),
+                },
+                Filter {
+                    column: "query".into(),
+                    op: Op::ILike,
+                    value: Value::Text("%Users%".into()),
+                },
+            ]
+        );
+        assert_eq!(
+            select.order_by,
+            vec![("total_exec_time".into(), true), ("calls".into(), false)]
+        );
+        assert_eq!(select.limit, Some(5));
+
+        let select = Select::parse("select * from stat_statements").unwrap();
+        assert!(select.columns.is_empty());
+
+        assert!(Select::parse("SELECT * FROM public.stat_statements").is_err());
+        assert!(
+            Select::parse("SELECT * FROM stat_statements WHERE calls > 1 OR rows > 1").is_err()
+        );
+        assert!(Select::parse("SELECT count(*) FROM stat_statements").is_err());
+    }
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter {
+            column: "calls".into(),
+            op: Op::GtEq,
+            value: Value::Integer(3),
+        };
+        assert!(filter.matches(&Value::Integer(3)));
+        assert!(filter.matches(&Value::Float(3.5)));
+        assert!(!filter.matches(&Value::Integer(2)));
+        assert!(!filter.matches(&Value::Text("abc".into())));
+    }
+
+    #[test]
+    fn test_like() {
+        let like = |pattern, text| wildcard(pattern, text, '%', '_');
+        assert!(like("%users%", "SELECT * FROM users WHERE id = $1"));
+        assert!(like("SELECT%", "SELECT 1"));
+        assert!(like("SELECT _", "SELECT 1"));
+        assert!(like("%", ""));
+        assert!(!like("SELECT _", "SELECT 10"));
+        assert!(!like("%orders%", "SELECT * FROM users"));
+        assert!(like("%a%b%c", "xaxbxbxc"));
+    }
+}
@@ -1,11 +1,8 @@
-use crate::{
-    backend::databases,
-    config::{self, config},
-};
+use crate::backend::databases;
 
 use super::prelude::*;
 use pg_query::{parse, protobuf::a_const, NodeEnum};
-use serde::de::DeserializeOwned;
+use serde_json::Value;
 
 pub struct Set {
     name: String,
@@ -40,6 +37,11 @@ impl Command for Set {
                             value: sval.sval.to_string(),
                         }),
 
+                        Some(a_const::Val::Fval(fval)) => Ok(Self {
+                            name,
+                            value: fval.fval.to_string(),
+                        }),
+
                         _ => Err(Error::Syntax),
                     },
 
@@ -52,46 +54,46 @@ impl Command for Set {
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
-        let _lock = databases::lock();
-        let mut config = (*config()).clone();
-        match self.name.as_str() {
-            "query_timeout" => {
-                config.config.general.query_timeout = self.value.parse()?;
-            }
-
-            "checkout_timeout" => {
-                config.config.general.checkout_timeout = self.value.parse()?;
-            }
-
-            "auth_type" => {
-                config.config.general.auth_type = Self::from_json(&self.value)?;
-            }
-
-            "read_write_strategy" => {
-                config.config.general.read_write_strategy = Self::from_json(&self.value)?;
-            }
-
-            "read_write_split" => {
-                config.config.general.read_write_split = Self::from_json(&self.value)?;
-            }
-
-            "load_balancing_strategy" => {
-                config.config.general.load_balancing_strategy = Self::from_json(&self.value)?;
-            }
-
-            _ => return Err(Error::Syntax),
-        }
-
-        config::set(config)?;
-        databases::init();
+        // Pools are re-created like on reload, keeping their connections.
+        databases::change(|config| {
+            // Reflection using JSON, like in SHOW CONFIG.
+            let mut general = serde_json::to_value(&config.config.general)?;
+            let settings = general.as_object_mut().ok_or(Error::Syntax)?;
+            let current = settings
+                .get(&self.name)
+                .ok_or_else(|| Error::UnknownSetting(self.name.clone()))?;
+            let value = Self::value(current, &self.value);
+            settings.insert(self.name.clone(), value);
+            config.config.general = serde_json::from_value(general)?;
+            config.overridden.insert(format!("general.{}", self.name));
+
+            Ok::<(), Error>(())
+        })?;
 
         Ok(vec![])
     }
 }
 
 impl Set {
-    fn from_json<'a, T: DeserializeOwned>(value: &'a str) -> serde_json::Result<T> {
-        Ok(serde_json::from_str::<T>(&format!(r#""{}""#, value))?)
+    /// Convert the value to the type of the setting it replaces.
+    /// Settings that aren't set yet can be numbers, booleans or strings.
+    fn value(current: &Value, value: &str) -> Value {
+        let boolean = match value.to_lowercase().as_str() {
+            "true" | "on" => Some(Value::Bool(true)),
+            "false" | "off" => Some(Value::Bool(false)),
+            _ => None,
+        };
+        let string = || Value::String(value.to_owned());
+
+        match current {
+            Value::String(_) => string(),
+            Value::Bool(_) => boolean.unwrap_or_else(string),
+            _ => serde_json::from_str::<Value>(value)
+                .ok()
+                .filter(|value| value.is_number())
+                .or(boolean)
+                .unwrap_or_else(string),
+        }
     }
 }
 
@@ -105,5 +107,44 @@ mod test {
         let cmd = Set::parse(cmd).unwrap();
         assert_eq!(cmd.name, "query_timeout");
         assert_eq!(cmd.value, "5000");
+
+        let cmd = Set::parse("SET default_pool_size = 20").unwrap();
+        assert_eq!(cmd.name, "default_pool_size");
+        assert_eq!(cmd.value, "20");
+    }
+
+    #[tokio::test]
+    async fn test_set_execute() {
+        let old = crate::config::config();
+
+        Set::parse("SET query_timeout TO 1234")
+            .unwrap()
+            .execute()
+            .await
+            .unwrap();
+        let config = crate::config::config();
+        assert_eq!(config.config.general.query_timeout, 1234);
+        assert!(config.overridden.contains("general.query_timeout"));
+
+        let err = Set::parse("SET not_a_setting TO 1")
+            .unwrap()
+            .execute()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::UnknownSetting(_)));
+
+        crate::config::set((*old).clone()).unwrap();
+    }
+
+    #[test]
+    fn test_set_value() {
+        assert_eq!(Set::value(&Value::from(10), "20"), Value::from(20));
+        assert_eq!(Set::value(&Value::Bool(false), "on"), Value::Bool(true));
+        assert_eq!(
+            Set::value(&Value::from("transaction"), "session"),
+            Value::from("session")
+        );
+        assert_eq!(Set::value(&Value::Null, "5000"), Value::from(5000));
+        assert_eq!(Set::value(&Value::Null, "/tmp"), Value::from("/tmp"));
     }
 }
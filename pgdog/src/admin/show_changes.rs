@@ -0,0 +1,77 @@
+//! SHOW CHANGES;
+//!
+//! Settings changed with `SET` that are different
+//! from the ones in the config file.
+
+use crate::config::{config, source};
+
+use super::prelude::*;
+
+pub struct ShowChanges;
+
+#[async_trait]
+impl Command for ShowChanges {
+    fn name(&self) -> String {
+        "SHOW CHANGES".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowChanges)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let config = config();
+        let file = source::load(&config.config_path, &config.users_path).await?;
+
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("name"),
+            Field::text("value"),
+            Field::text("file_value"),
+        ])
+        .message()?];
+
+        for (name, value, file_value) in changes(
+            &serde_json::to_value(&config.config.general)?,
+            &serde_json::to_value(&file.config.general)?,
+        ) {
+            let mut data_row = DataRow::new();
+            data_row.add(name).add(value).add(file_value);
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
+
+/// Settings with different values, and their values.
+fn changes(current: &serde_json::Value, file: &serde_json::Value) -> Vec<(String, String, String)> {
+    let mut changes = vec![];
+
+    if let (Some(current), Some(file)) = (current.as_object(), file.as_object()) {
+        for (name, value) in current {
+            let file_value = file.get(name).cloned().unwrap_or_default();
+            if *value != file_value {
+                changes.push((name.clone(), value.to_string(), file_value.to_string()));
+            }
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_changes() {
+        let current = json!({"default_pool_size": 20, "query_timeout": 5000});
+        let file = json!({"default_pool_size": 10, "query_timeout": 5000});
+        assert_eq!(
+            changes(&current, &file),
+            vec![("default_pool_size".into(), "20".into(), "10".into())]
+        );
+    }
+}
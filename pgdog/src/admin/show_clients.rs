@@ -41,12 +41,19 @@ impl Command for ShowClients {
             Field::text("application_name"),
             Field::numeric("memory_used"),
             Field::bool("locked"),
+            Field::numeric("buffer_time"),
+            Field::numeric("max_buffer_time"),
+            Field::numeric("buffer_size"),
+            Field::numeric("max_buffer_size"),
+            Field::numeric("oversized_requests"),
+            Field::text("password"),
+            Field::numeric("id"),
         ]);
 
         let mut rows = vec![];
         let clients = comms().clients();
 
-        for client in clients.values() {
+        for (id, client) in clients.iter() {
             let user = client.paramters.get_default("user", "postgres");
             let mut row = DataRow::new();
             row.add(user)
@@ -77,7 +84,20 @@ impl Command for ShowClients {
                 .add(client.stats.errors)
                 .add(client.paramters.get_default("application_name", ""))
                 .add(client.stats.memory_used)
-                .add(client.stats.locked);
+                .add(client.stats.locked)
+                .add(format!(
+                    "{:.3}",
+                    client.stats.buffer_time.as_secs_f64() * 1000.0
+                ))
+                .add(format!(
+                    "{:.3}",
+                    client.stats.max_buffer_time.as_secs_f64() * 1000.0
+                ))
+                .add(client.stats.buffer_size)
+                .add(client.stats.max_buffer_size)
+                .add(client.stats.oversized_requests)
+                .add(client.credential.to_string())
+                .add(id.pid as i64);
             rows.push(row.message()?);
         }
 
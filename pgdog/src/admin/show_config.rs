@@ -1,17 +1,38 @@
 //! SHOW CONFIG command.
+//!
+//! `SHOW CONFIG` lists general and TCP settings, and where their value comes from:
+//! the default, pgdog.toml or an override (command line options or `SET`).
+//! `SHOW CONFIG TOML` returns the whole pgdog.toml PgDog is running with,
+//! with passwords masked. `SHOW CONFIG HISTORY` lists the configurations
+//! applied since startup, which `ROLLBACK CONFIG` can go back to.
 
 use crate::{
     backend::databases::databases,
-    config::config,
+    config::{
+        config, history,
+        reload::{changes, mask, to_json},
+        ConfigAndUsers,
+    },
     net::messages::{DataRow, Field, Protocol, RowDescription},
-    util::human_duration,
+    util::{format_time, human_duration},
 };
 
 use std::time::Duration;
 
+use serde_json::Value;
+
 use super::prelude::*;
 
-pub struct ShowConfig;
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Output {
+    Settings,
+    Toml,
+    History,
+}
+
+pub struct ShowConfig {
+    output: Output,
+}
 
 #[async_trait]
 impl Command for ShowConfig {
@@ -19,28 +40,57 @@ impl Command for ShowConfig {
         "SHOW".into()
     }
 
-    fn parse(_sql: &str) -> Result<Self, Error> {
-        Ok(Self {})
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let output = match sql.split_whitespace().nth(2) {
+            None => Output::Settings,
+            Some("toml") => Output::Toml,
+            Some("history") => Output::History,
+            Some(_) => return Err(Error::Syntax),
+        };
+
+        Ok(Self { output })
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
         let config = config();
         let _databases = databases();
 
-        let mut messages =
-            vec![RowDescription::new(&[Field::text("name"), Field::text("value")]).message()?];
+        if self.output == Output::History {
+            return history_rows();
+        }
+
+        if self.output == Output::Toml {
+            let mut dr = DataRow::new();
+            dr.add(toml(&config)?);
+            return Ok(vec![
+                RowDescription::new(&[Field::text("config")]).message()?,
+                dr.message()?,
+            ]);
+        }
+
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("name"),
+            Field::text("value"),
+            Field::text("source"),
+        ])
+        .message()?];
 
         // Reflection using JSON.
         let general = serde_json::to_value(&config.config.general)?;
         let tcp = serde_json::to_value(config.config.tcp)?;
-        let objects = [("", general.as_object()), ("tcp_", tcp.as_object())];
+        let objects = [
+            ("", "general", general.as_object()),
+            ("tcp_", "tcp", tcp.as_object()),
+        ];
 
-        for (prefix, object) in objects.iter() {
+        for (prefix, section, object) in objects.iter() {
             if let Some(object) = object {
                 for (key, value) in *object {
                     let mut dr = DataRow::new();
                     let name = prefix.to_string() + key.as_str();
-                    dr.add(&name).add(pretty_value(&name, value)?);
+                    dr.add(&name)
+                        .add(pretty_value(&name, value)?)
+                        .add(source(&config, &format!("{}.{}", section, key)));
                     messages.push(dr.message()?);
                 }
             }
@@ -50,6 +100,77 @@ impl Command for ShowConfig {
     }
 }
 
+/// Applied configurations, newest first, with the number of settings
+/// changed from the version before.
+fn history_rows() -> Result<Vec<Message>, Error> {
+    let mut messages = vec![RowDescription::new(&[
+        Field::bigint("version"),
+        Field::text("hash"),
+        Field::text("applied_at"),
+        Field::bigint("changes"),
+        Field::bool("current"),
+    ])
+    .message()?];
+
+    let versions = history::versions();
+    let mut previous = None;
+    let mut rows = vec![];
+
+    for (i, version) in versions.iter().enumerate() {
+        let json = to_json(&version.config);
+        let changed = previous
+            .as_ref()
+            .map(|previous| changes(previous, &json).len())
+            .unwrap_or_default();
+        previous = Some(json);
+
+        let mut dr = DataRow::new();
+        dr.add(version.version as i64)
+            .add(version.hash.as_str())
+            .add(format_time(version.applied_at.into()))
+            .add(changed as i64)
+            .add(i + 1 == versions.len());
+        rows.push(dr.message()?);
+    }
+
+    messages.extend(rows.into_iter().rev());
+    Ok(messages)
+}
+
+/// Where the setting's value comes from.
+fn source(config: &ConfigAndUsers, setting: &str) -> &'static str {
+    if config.overridden.contains(setting) {
+        "override"
+    } else if config.explicit.contains(setting) {
+        "config file"
+    } else {
+        "default"
+    }
+}
+
+/// Configuration as pgdog.toml, with passwords masked.
+fn toml(config: &ConfigAndUsers) -> Result<String, Error> {
+    let mut value = serde_json::to_value(&config.config)?;
+    mask(&mut value);
+    remove_unset(&mut value);
+    Ok(toml::to_string(&value)?)
+}
+
+/// Leave out settings TOML can't represent: ones that aren't set (null)
+/// and unlimited ones (larger than `i64::MAX`), which are the defaults anyway.
+fn remove_unset(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|_, value| {
+                !(value.is_null() || (value.is_u64() && value.as_i64().is_none()))
+            });
+            object.values_mut().for_each(remove_unset);
+        }
+        Value::Array(list) => list.iter_mut().for_each(remove_unset),
+        _ => (),
+    }
+}
+
 /// Format the value in a human-readable way.
 fn pretty_value(name: &str, value: &serde_json::Value) -> Result<String, serde_json::Error> {
     let s = serde_json::to_string(value)?;
@@ -77,3 +198,33 @@ fn pretty_value(name: &str, value: &serde_json::Value) -> Result<String, serde_j
 
     Ok(value)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_show_config_toml() {
+        assert_eq!(
+            ShowConfig::parse("show config toml").unwrap().output,
+            Output::Toml
+        );
+        assert_eq!(
+            ShowConfig::parse("show config history").unwrap().output,
+            Output::History
+        );
+        assert!(ShowConfig::parse("show config yaml").is_err());
+
+        let mut config = ConfigAndUsers::default();
+        config.config.admin.password = "hunter2".into();
+        config.explicit.insert("general.port".into());
+
+        let toml = toml(&config).unwrap();
+        assert!(!toml.contains("hunter2"));
+        let parsed: crate::config::Config = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.general.port, config.config.general.port);
+
+        assert_eq!(source(&config, "general.port"), "config file");
+        assert_eq!(source(&config, "general.workers"), "default");
+    }
+}
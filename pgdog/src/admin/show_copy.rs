@@ -0,0 +1,54 @@
+//! SHOW COPY;
+
+use crate::frontend::router::parser::progress::copies;
+
+use super::prelude::*;
+
+pub struct ShowCopy;
+
+#[async_trait]
+impl Command for ShowCopy {
+    fn name(&self) -> String {
+        "SHOW COPY".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowCopy)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::bigint("id"),
+            Field::text("table"),
+            Field::numeric("rows"),
+            Field::text("shard_rows"),
+            Field::numeric("bytes"),
+            Field::numeric("elapsed"),
+            Field::numeric("rows_per_sec"),
+        ])
+        .message()?];
+
+        for copy in copies() {
+            let shard_rows = copy
+                .shards
+                .iter()
+                .enumerate()
+                .map(|(shard, rows)| format!("{}: {}", shard, rows))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            let mut data_row = DataRow::new();
+            data_row
+                .add(copy.id as i64)
+                .add(copy.table.as_str())
+                .add(copy.rows)
+                .add(shard_rows)
+                .add(copy.bytes)
+                .add(copy.elapsed().as_secs_f64())
+                .add(copy.rate());
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
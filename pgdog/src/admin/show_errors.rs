@@ -0,0 +1,48 @@
+//! SHOW ERRORS;
+
+use crate::{stats::errors::errors, util::format_time};
+
+use super::prelude::*;
+
+pub struct ShowErrors;
+
+#[async_trait]
+impl Command for ShowErrors {
+    fn name(&self) -> String {
+        "SHOW ERRORS".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowErrors)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("created_at"),
+            Field::text("pool"),
+            Field::text("sqlstate"),
+            Field::text("message"),
+            Field::text("client"),
+        ])
+        .message()?];
+
+        // Most recent first.
+        for error in errors().into_iter().rev() {
+            let mut data_row = DataRow::new();
+            data_row
+                .add(format_time(error.created_at.into()))
+                .add(error.pool.as_str())
+                .add(error.code.as_str())
+                .add(error.message.as_str())
+                .add(
+                    error
+                        .client
+                        .map(|client| client.to_string())
+                        .unwrap_or_default(),
+                );
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
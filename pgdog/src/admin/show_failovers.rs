@@ -0,0 +1,42 @@
+//! SHOW FAILOVERS;
+
+use crate::{backend::pool::failover::events, util::format_time};
+
+use super::prelude::*;
+
+pub struct ShowFailovers;
+
+#[async_trait]
+impl Command for ShowFailovers {
+    fn name(&self) -> String {
+        "SHOW FAILOVERS".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowFailovers)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("created_at"),
+            Field::text("primary"),
+            Field::text("reason"),
+            Field::text("promoted"),
+            Field::numeric("databases"),
+        ])
+        .message()?];
+
+        for event in events() {
+            let mut data_row = DataRow::new();
+            data_row
+                .add(format_time(event.created_at.into()))
+                .add(event.primary.as_str())
+                .add(event.reason.to_string())
+                .add(event.promoted.unwrap_or_default())
+                .add(event.databases);
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
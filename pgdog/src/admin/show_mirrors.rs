@@ -0,0 +1,56 @@
+//! SHOW MIRRORS;
+
+use crate::{backend::pool::connection::mirror::stats, config::config};
+
+use super::prelude::*;
+
+pub struct ShowMirrors;
+
+#[async_trait]
+impl Command for ShowMirrors {
+    fn name(&self) -> String {
+        "SHOW MIRRORS".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowMirrors)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("database"),
+            Field::text("user"),
+            Field::text("mirror"),
+            Field::numeric("queue_depth"),
+            Field::numeric("mirror_queue"),
+            Field::numeric("sent"),
+            Field::numeric("dropped"),
+            Field::numeric("replayed"),
+            Field::numeric("errors"),
+            Field::numeric("lag_ms"),
+            Field::numeric("max_lag_ms"),
+        ])
+        .message()?];
+
+        let mirror_queue = config().config.general.mirror_queue;
+
+        for mirror in stats() {
+            let mut data_row = DataRow::new();
+            data_row
+                .add(mirror.database.as_str())
+                .add(mirror.user.as_str())
+                .add(mirror.mirror.as_str())
+                .add(mirror.queue)
+                .add(mirror_queue)
+                .add(mirror.sent)
+                .add(mirror.dropped)
+                .add(mirror.replayed)
+                .add(mirror.errors)
+                .add(mirror.lag.as_millis() as i64)
+                .add(mirror.max_lag.as_millis() as i64);
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
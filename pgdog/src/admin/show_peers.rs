@@ -37,6 +37,7 @@ impl Command for ShowPeers {
             Field::text("addr"),
             Field::text("last_seen"),
             Field::numeric("clients"),
+            Field::text("down"),
         ])
         .message()?];
 
@@ -50,7 +51,8 @@ impl Command for ShowPeers {
                     now.duration_since(state.last_message)
                         .unwrap_or(Duration::from_secs(0))
                 ))
-                .add(state.clients);
+                .add(state.clients)
+                .add(state.down.join(", "));
             rows.push(row.message()?);
         }
 
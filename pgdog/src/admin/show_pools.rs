@@ -22,6 +22,7 @@ impl Command for ShowPools {
         let rd = RowDescription::new(&[
             Field::text("database"),
             Field::text("user"),
+            Field::text("application_name"),
             Field::text("addr"),
             Field::numeric("port"),
             Field::numeric("shard"),
@@ -34,15 +35,30 @@ impl Command for ShowPools {
             Field::numeric("maxwait_us"),
             Field::text("pool_mode"),
             Field::bool("paused"),
+            Field::bool("draining"),
             Field::bool("banned"),
             Field::numeric("errors"),
             Field::numeric("re_synced"),
             Field::numeric("out_of_sync"),
             Field::bool("online"),
+            Field::numeric("wait_p50_us"),
+            Field::numeric("wait_p95_us"),
+            Field::numeric("wait_p99_us"),
+            Field::numeric("query_p50_us"),
+            Field::numeric("query_p95_us"),
+            Field::numeric("query_p99_us"),
+            Field::numeric("xact_p50_us"),
+            Field::numeric("xact_p95_us"),
+            Field::numeric("xact_p99_us"),
+            Field::numeric("replica_lag_ms"),
+            Field::numeric("replica_lag_bytes"),
+            Field::bool("lagging"),
+            Field::text("sync_state"),
         ]);
         let mut messages = vec![rd.message()?];
         for (user, cluster) in databases().all() {
             for (shard_num, shard) in cluster.shards().iter().enumerate() {
+                let primary_lsn = shard.primary_lsn();
                 for (role, pool) in shard.pools_with_roles() {
                     let mut row = DataRow::new();
                     let state = pool.state();
@@ -50,6 +66,7 @@ impl Command for ShowPools {
                     let maxwait_us = state.maxwait.subsec_micros() as i64;
                     row.add(user.database.as_str())
                         .add(user.user.as_str())
+                        .add(user.application_name.as_deref().unwrap_or(""))
                         .add(pool.addr().host.as_str())
                         .add(pool.addr().port as i64)
                         .add(shard_num as i64)
@@ -62,11 +79,34 @@ impl Command for ShowPools {
                         .add(maxwait_us)
                         .add(state.pooler_mode.to_string())
                         .add(state.paused)
+                        .add(state.draining)
                         .add(state.banned)
                         .add(state.errors)
                         .add(state.re_synced)
                         .add(state.out_of_sync)
                         .add(state.online);
+                    let histograms = state.stats.histograms;
+                    for histogram in [histograms.wait, histograms.query, histograms.xact] {
+                        for percentile in [0.5, 0.95, 0.99] {
+                            row.add(histogram.percentile(percentile).as_micros() as i64);
+                        }
+                    }
+                    let lag = pool.replication_lag();
+                    row.add(
+                        lag.map(|lag| lag.lag.as_millis() as i64)
+                            .unwrap_or_default(),
+                    )
+                    .add(
+                        lag.and_then(|lag| lag.bytes(primary_lsn))
+                            .map(|bytes| bytes as i64)
+                            .unwrap_or_default(),
+                    )
+                    .add(pool.lagging(primary_lsn))
+                    .add(
+                        pool.sync_state()
+                            .map(|state| state.to_string())
+                            .unwrap_or_default(),
+                    );
                     messages.push(row.message()?);
                 }
             }
@@ -0,0 +1,71 @@
+//! SHOW QUERIES;
+
+use std::time::Instant;
+
+use crate::frontend::comms::comms;
+
+use super::prelude::*;
+
+pub struct ShowQueries;
+
+#[async_trait]
+impl Command for ShowQueries {
+    fn name(&self) -> String {
+        "SHOW QUERIES".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowQueries)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::numeric("id"),
+            Field::text("user"),
+            Field::text("database"),
+            Field::text("state"),
+            Field::text("shard"),
+            Field::text("role"),
+            Field::text("backend_pid"),
+            Field::numeric("duration_ms"),
+            Field::text("query"),
+        ])
+        .message()?];
+
+        let now = Instant::now();
+        let mut queries = comms()
+            .clients()
+            .into_iter()
+            .filter_map(|(id, client)| client.query.clone().map(|query| (id, client, query)))
+            .collect::<Vec<_>>();
+        // Longest running first.
+        queries.sort_by_key(|(_, _, query)| query.started_at);
+
+        for (id, client, query) in queries {
+            let user = client.paramters.get_default("user", "postgres");
+            // Don't show parameters, they could contain sensitive data.
+            let text = pg_query::normalize(&query.query).unwrap_or(query.query);
+            let pids = query
+                .backend_pids
+                .iter()
+                .map(|pid| pid.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let mut data_row = DataRow::new();
+            data_row
+                .add(id.pid as i64)
+                .add(user)
+                .add(client.paramters.get_default("database", user))
+                .add(client.stats.state.to_string())
+                .add(query.shard.as_str())
+                .add(if query.read { "replica" } else { "primary" })
+                .add(pids)
+                .add(now.duration_since(query.started_at).as_secs_f64() * 1000.0)
+                .add(text);
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
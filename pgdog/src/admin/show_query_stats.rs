@@ -0,0 +1,70 @@
+//! SHOW QUERY STATS;
+
+use crate::stats::query_stats::stats;
+
+use super::prelude::*;
+
+pub struct ShowQueryStats;
+
+#[async_trait]
+impl Command for ShowQueryStats {
+    fn name(&self) -> String {
+        "SHOW QUERY STATS".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        match sql.split_whitespace().collect::<Vec<_>>()[..] {
+            ["show", "query", "stats"] | ["show", "query_stats"] => Ok(ShowQueryStats),
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("fingerprint"),
+            Field::text("query"),
+            Field::numeric("calls"),
+            Field::numeric("total_ms"),
+            Field::numeric("mean_ms"),
+            Field::numeric("p99_ms"),
+            Field::numeric("rows"),
+            Field::text("shards"),
+        ])
+        .message()?];
+
+        for stats in stats() {
+            let shards = stats
+                .shards
+                .iter()
+                .map(|(shard, calls)| format!("{}:{}", shard, calls))
+                .collect::<Vec<_>>()
+                .join(",");
+
+            let mut data_row = DataRow::new();
+            data_row
+                .add(stats.fingerprint.as_str())
+                .add(stats.query.as_str())
+                .add(stats.calls)
+                .add(stats.total().as_secs_f64() * 1000.0)
+                .add(stats.mean().as_secs_f64() * 1000.0)
+                .add(stats.histogram.percentile(0.99).as_secs_f64() * 1000.0)
+                .add(stats.rows)
+                .add(shards);
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(ShowQueryStats::parse("show query stats").is_ok());
+        assert!(ShowQueryStats::parse("show query_stats").is_ok());
+        assert!(ShowQueryStats::parse("show query").is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! SHOW RELOAD;
+
+use crate::{config::reload::last, util::format_time};
+
+use super::prelude::*;
+
+pub struct ShowReload;
+
+#[async_trait]
+impl Command for ShowReload {
+    fn name(&self) -> String {
+        "SHOW RELOAD".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowReload)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("reloaded_at"),
+            Field::text("setting"),
+            Field::text("old_value"),
+            Field::text("new_value"),
+            Field::text("status"),
+        ])
+        .message()?];
+
+        let Some(report) = last() else {
+            return Ok(messages);
+        };
+        let reloaded_at = format_time(report.reloaded_at.into());
+
+        if let Some(error) = report.error {
+            let mut data_row = DataRow::new();
+            data_row
+                .add(reloaded_at.as_str())
+                .add("")
+                .add("")
+                .add("")
+                .add(format!("error: {}", error));
+            messages.push(data_row.message()?);
+        } else if report.changes.is_empty() {
+            let mut data_row = DataRow::new();
+            data_row
+                .add(reloaded_at.as_str())
+                .add("")
+                .add("")
+                .add("")
+                .add("no changes");
+            messages.push(data_row.message()?);
+        }
+
+        for change in report.changes {
+            let mut data_row = DataRow::new();
+            data_row
+                .add(reloaded_at.as_str())
+                .add(change.path)
+                .add(change.old.unwrap_or_default())
+                .add(change.new.unwrap_or_default())
+                .add(if change.restart {
+                    "restart required"
+                } else {
+                    "applied"
+                });
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
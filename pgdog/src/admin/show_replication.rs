@@ -0,0 +1,132 @@
+//! SHOW REPLICATION;
+//!
+//! Replication state of each shard: its primary, how far behind
+//! the replicas are, and sharded logical replication streams
+//! reading from it.
+
+use crate::backend::replication::{
+    progress::{format_lsn, streams},
+    slots::clusters,
+};
+use crate::config::Role;
+use crate::frontend::comms::comms;
+
+use super::prelude::*;
+
+pub struct ShowReplication;
+
+#[async_trait]
+impl Command for ShowReplication {
+    fn name(&self) -> String {
+        "SHOW REPLICATION".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowReplication)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("database"),
+            Field::numeric("shard"),
+            Field::text("primary"),
+            Field::text("primary_lsn"),
+            Field::numeric("replicas"),
+            Field::numeric("sync_replicas"),
+            Field::numeric("lagging_replicas"),
+            Field::numeric("max_lag_ms"),
+            Field::numeric("max_lag_bytes"),
+            Field::numeric("replication_clients"),
+            Field::numeric("streams"),
+            Field::text("stream_lsn"),
+            Field::numeric("stream_buffered"),
+        ])
+        .message()?];
+
+        let clients = comms().clients();
+        let streams = streams();
+
+        for cluster in clusters() {
+            let replication_clients = clients
+                .values()
+                .filter(|client| {
+                    client.paramters.get("replication").is_some()
+                        && client.paramters.get_default("database", "") == cluster.name()
+                })
+                .count();
+
+            for (number, shard) in cluster.shards().iter().enumerate() {
+                let primary_lsn = shard.primary_lsn();
+                let pools = shard.pools_with_roles();
+                let primary = pools
+                    .iter()
+                    .find(|(role, _)| *role == Role::Primary)
+                    .map(|(_, pool)| pool);
+                let replicas = pools
+                    .iter()
+                    .filter(|(role, _)| *role == Role::Replica)
+                    .map(|(_, pool)| pool)
+                    .collect::<Vec<_>>();
+
+                let mut sync = 0_usize;
+                let mut lagging = 0_usize;
+                let mut max_lag = 0_u64;
+                let mut max_lag_bytes = 0_u64;
+                for replica in &replicas {
+                    if replica.sync_state().is_some_and(|state| state.sync()) {
+                        sync += 1;
+                    }
+                    if replica.lagging(primary_lsn) {
+                        lagging += 1;
+                    }
+                    if let Some(lag) = replica.replication_lag() {
+                        max_lag = max_lag.max(lag.lag.as_millis() as u64);
+                        max_lag_bytes = max_lag_bytes.max(lag.bytes(primary_lsn).unwrap_or(0));
+                    }
+                }
+
+                // Streams for this shard, or for all of them if not sharded.
+                let shard_streams = streams
+                    .iter()
+                    .filter(|stream| {
+                        stream.database == cluster.name()
+                            && (stream.shard == number.to_string() || stream.shard == "all")
+                    })
+                    .collect::<Vec<_>>();
+                // The stream furthest behind.
+                let stream_lsn = shard_streams.iter().map(|stream| stream.lsn()).min();
+                let buffered = shard_streams
+                    .iter()
+                    .map(|stream| stream.buffered())
+                    .sum::<usize>();
+
+                let mut data_row = DataRow::new();
+                data_row
+                    .add(cluster.name())
+                    .add(number)
+                    .add(
+                        primary
+                            .map(|primary| primary.addr().addr())
+                            .unwrap_or_default(),
+                    )
+                    .add(
+                        primary_lsn
+                            .map(|lsn| format_lsn(lsn as i64))
+                            .unwrap_or_default(),
+                    )
+                    .add(replicas.len())
+                    .add(sync)
+                    .add(lagging)
+                    .add(max_lag)
+                    .add(max_lag_bytes)
+                    .add(replication_clients)
+                    .add(shard_streams.len())
+                    .add(stream_lsn.map(format_lsn).unwrap_or_default())
+                    .add(buffered);
+                messages.push(data_row.message()?);
+            }
+        }
+
+        Ok(messages)
+    }
+}
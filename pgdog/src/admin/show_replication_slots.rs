@@ -0,0 +1,55 @@
+//! SHOW REPLICATION_SLOTS;
+
+use crate::backend::replication::slots::{clusters, slots};
+
+use super::prelude::*;
+
+pub struct ShowReplicationSlots;
+
+#[async_trait]
+impl Command for ShowReplicationSlots {
+    fn name(&self) -> String {
+        "SHOW REPLICATION_SLOTS".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowReplicationSlots)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("database"),
+            Field::numeric("shard"),
+            Field::text("host"),
+            Field::text("slot_name"),
+            Field::text("plugin"),
+            Field::text("slot_type"),
+            Field::bool("active"),
+            Field::numeric("retained_wal"),
+        ])
+        .message()?];
+
+        for cluster in clusters() {
+            let slots = slots(&cluster)
+                .await
+                .map_err(|e| Error::Backend(Box::new(e)))?;
+
+            for shard_slot in slots {
+                let slot = shard_slot.slot;
+                let mut data_row = DataRow::new();
+                data_row
+                    .add(cluster.name())
+                    .add(shard_slot.shard)
+                    .add(shard_slot.addr)
+                    .add(slot.name)
+                    .add(slot.plugin)
+                    .add(slot.slot_type)
+                    .add(slot.active)
+                    .add(slot.retained_wal);
+                messages.push(data_row.message()?);
+            }
+        }
+
+        Ok(messages)
+    }
+}
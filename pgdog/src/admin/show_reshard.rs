@@ -0,0 +1,54 @@
+//! SHOW RESHARD;
+
+use crate::{backend::replication::reshard::reshards, util::format_time};
+
+use super::prelude::*;
+
+pub struct ShowReshard;
+
+#[async_trait]
+impl Command for ShowReshard {
+    fn name(&self) -> String {
+        "SHOW RESHARD".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowReshard)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("source"),
+            Field::text("destination"),
+            Field::text("started_at"),
+            Field::text("phase"),
+            Field::numeric("shard"),
+            Field::numeric("rows"),
+            Field::bool("streaming"),
+            Field::numeric("lag_bytes"),
+            Field::text("error"),
+            Field::text("prepared"),
+        ])
+        .message()?];
+
+        for reshard in reshards() {
+            for (shard, progress) in reshard.shards.iter().enumerate() {
+                let mut data_row = DataRow::new();
+                data_row
+                    .add(reshard.source.as_str())
+                    .add(reshard.destination.as_str())
+                    .add(format_time(reshard.started_at.into()))
+                    .add(reshard.phase.to_string())
+                    .add(shard)
+                    .add(progress.rows)
+                    .add(progress.streaming)
+                    .add(progress.lag() as usize)
+                    .add(reshard.error.clone().unwrap_or_default())
+                    .add(progress.prepared.join(", "));
+                messages.push(data_row.message()?);
+            }
+        }
+
+        Ok(messages)
+    }
+}
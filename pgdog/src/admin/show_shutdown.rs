@@ -0,0 +1,73 @@
+//! SHOW SHUTDOWN;
+//!
+//! Progress of a shutdown started with `SHUTDOWN`.
+
+use std::time::Instant;
+
+use crate::config::config;
+use crate::frontend::comms::{comms, ShutdownMode};
+use crate::state::State;
+
+use super::prelude::*;
+
+pub struct ShowShutdown;
+
+#[async_trait]
+impl Command for ShowShutdown {
+    fn name(&self) -> String {
+        "SHOW SHUTDOWN".into()
+    }
+
+    fn parse(_: &str) -> Result<Self, Error> {
+        Ok(ShowShutdown)
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("mode"),
+            Field::numeric("elapsed_ms"),
+            Field::numeric("clients"),
+            Field::numeric("in_transaction"),
+            Field::numeric("timeout_remaining_ms"),
+        ])
+        .message()?];
+
+        let comms = comms();
+        let clients = comms.clients();
+        let in_transaction = clients
+            .values()
+            .filter(|client| {
+                matches!(
+                    client.stats.state,
+                    State::IdleInTransaction | State::TransactionError
+                )
+            })
+            .count();
+
+        let (mode, elapsed) = match comms.shutdown_mode() {
+            Some((mode, started_at)) => (mode.to_string(), Instant::now() - started_at),
+            None => ("none".into(), Default::default()),
+        };
+        // Only fast shutdowns give up on clients after a while.
+        let remaining = match comms.shutdown_mode() {
+            Some((ShutdownMode::Fast, _)) => config()
+                .config
+                .general
+                .shutdown_timeout()
+                .saturating_sub(elapsed)
+                .as_millis() as u64,
+            _ => 0,
+        };
+
+        let mut data_row = DataRow::new();
+        data_row
+            .add(mode)
+            .add(elapsed.as_millis() as u64)
+            .add(clients.len())
+            .add(in_transaction)
+            .add(remaining);
+        messages.push(data_row.message()?);
+
+        Ok(messages)
+    }
+}
@@ -1,5 +1,5 @@
 //! SHOW STATS.
-use crate::backend::databases::databases;
+use crate::{backend::databases::databases, util::format_time};
 
 use super::prelude::*;
 
@@ -23,6 +23,7 @@ impl Command for ShowStats {
             Field::numeric("port"),
             Field::numeric("shard"),
             Field::text("role"),
+            Field::text("stats_reset"),
         ];
         fields.extend(
             ["total", "avg"]
@@ -40,6 +41,10 @@ impl Command for ShowStats {
                         // Field::numeric(&format!("{}_client_parse_count", prefix)),
                         Field::numeric(&format!("{}_server_parse_count", prefix)),
                         Field::numeric(&format!("{}_bind_count", prefix)),
+                        Field::numeric(&format!("{}_reads", prefix)),
+                        Field::numeric(&format!("{}_writes", prefix)),
+                        Field::numeric(&format!("{}_cross_shard", prefix)),
+                        Field::numeric(&format!("{}_errors", prefix)),
                     ]
                 })
                 .collect::<Vec<Field>>(),
@@ -66,7 +71,8 @@ impl Command for ShowStats {
                         .add(&pool.addr().host)
                         .add(pool.addr().port as i64)
                         .add(shard_num)
-                        .add(role.to_string());
+                        .add(role.to_string())
+                        .add(format_time(stats.stats_reset.into()));
 
                     for stat in [totals, averages] {
                         dr.add(stat.xact_count)
@@ -79,7 +85,11 @@ impl Command for ShowStats {
                             .add(stat.wait_time.as_millis() as u64)
                             // .add(0_i64)
                             .add(stat.parse_count)
-                            .add(stat.bind_count);
+                            .add(stat.bind_count)
+                            .add(stat.reads)
+                            .add(stat.writes)
+                            .add(stat.cross_shard)
+                            .add(stat.errors);
                     }
 
                     messages.push(dr.message()?);
@@ -0,0 +1,61 @@
+//! SHOW WAIT EVENTS;
+
+use crate::stats::wait_events::wait_events;
+
+use super::prelude::*;
+
+pub struct ShowWaitEvents;
+
+#[async_trait]
+impl Command for ShowWaitEvents {
+    fn name(&self) -> String {
+        "SHOW WAIT EVENTS".into()
+    }
+
+    fn parse(sql: &str) -> Result<Self, Error> {
+        match sql.split_whitespace().collect::<Vec<_>>()[..] {
+            ["show", "wait", "events"] | ["show", "wait_events"] => Ok(ShowWaitEvents),
+            _ => Err(Error::Syntax),
+        }
+    }
+
+    async fn execute(&self) -> Result<Vec<Message>, Error> {
+        let mut messages = vec![RowDescription::new(&[
+            Field::text("database"),
+            Field::text("user"),
+            Field::numeric("checkout_ms"),
+            Field::numeric("server_ms"),
+            Field::numeric("client_ms"),
+            Field::numeric("idle_in_transaction_ms"),
+            Field::text("bottleneck"),
+        ])
+        .message()?];
+
+        for ((user, database), events) in wait_events() {
+            let mut data_row = DataRow::new();
+            data_row
+                .add(database.as_str())
+                .add(user.as_str())
+                .add(events.checkout.as_secs_f64() * 1000.0)
+                .add(events.server.as_secs_f64() * 1000.0)
+                .add(events.client.as_secs_f64() * 1000.0)
+                .add(events.idle_in_transaction.as_secs_f64() * 1000.0)
+                .add(events.bottleneck());
+            messages.push(data_row.message()?);
+        }
+
+        Ok(messages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(ShowWaitEvents::parse("show wait events").is_ok());
+        assert!(ShowWaitEvents::parse("show wait_events").is_ok());
+        assert!(ShowWaitEvents::parse("show wait").is_err());
+    }
+}
@@ -1,8 +1,16 @@
-use crate::frontend::comms::comms;
+//! SHUTDOWN [SMART|FAST|IMMEDIATE];
+//!
+//! Stop accepting new clients and shut down once connected ones
+//! disconnect (smart), finish their transactions (fast, the default)
+//! or right away (immediate).
+
+use crate::frontend::comms::{comms, ShutdownMode};
 
 use super::prelude::*;
 
-pub struct Shutdown;
+pub struct Shutdown {
+    mode: ShutdownMode,
+}
 
 #[async_trait]
 impl Command for Shutdown {
@@ -10,13 +18,44 @@ impl Command for Shutdown {
         "SHUTDOWN".into()
     }
 
-    fn parse(_: &str) -> Result<Self, Error> {
-        Ok(Shutdown {})
+    fn parse(sql: &str) -> Result<Self, Error> {
+        let parts = sql.split_whitespace().collect::<Vec<_>>();
+
+        let mode = match parts[..] {
+            ["shutdown"] | ["shutdown", "fast"] => ShutdownMode::Fast,
+            ["shutdown", "smart"] => ShutdownMode::Smart,
+            ["shutdown", "immediate"] => ShutdownMode::Immediate,
+            _ => return Err(Error::Syntax),
+        };
+
+        Ok(Shutdown { mode })
     }
 
     async fn execute(&self) -> Result<Vec<Message>, Error> {
-        comms().shutdown();
+        comms().request_shutdown(self.mode);
 
         Ok(vec![])
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Shutdown::parse("shutdown").unwrap().mode,
+            ShutdownMode::Fast
+        );
+        assert_eq!(
+            Shutdown::parse("shutdown smart").unwrap().mode,
+            ShutdownMode::Smart
+        );
+        assert_eq!(
+            Shutdown::parse("shutdown immediate").unwrap().mode,
+            ShutdownMode::Immediate
+        );
+        assert!(Shutdown::parse("shutdown now").is_err());
+    }
+}
@@ -34,19 +34,38 @@ impl<'a> Client<'a> {
         })
     }
 
+    /// Same challenge, checked against a different password.
+    pub fn with_password(&self, password: &'a str) -> Self {
+        Self {
+            password,
+            ..self.clone()
+        }
+    }
+
     /// Challenge
     pub fn challenge(&self) -> Authentication {
         Authentication::Md5(Bytes::from(self.salt.to_vec()))
     }
 
-    pub fn encrypted(&self) -> String {
-        let mut md5 = Context::new();
-        md5.consume(self.password);
-        md5.consume(self.user);
-        let first_pass = md5.compute();
+    /// Password hashed with the user name, like in `pg_shadow`.
+    /// Passwords can be stored hashed already.
+    fn hashed(&self) -> String {
+        match self.password.strip_prefix("md5") {
+            Some(hash) if hash.len() == 32 && hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                hash.to_owned()
+            }
+            _ => {
+                let mut md5 = Context::new();
+                md5.consume(self.password);
+                md5.consume(self.user);
+                format!("{:x}", md5.compute())
+            }
+        }
+    }
 
+    pub fn encrypted(&self) -> String {
         let mut md5 = Context::new();
-        md5.consume(format!("{:x}", first_pass));
+        md5.consume(self.hashed());
         md5.consume(self.salt);
         let password = format!("md5{:x}", md5.compute());
 
@@ -62,3 +81,22 @@ impl<'a> Client<'a> {
         self.encrypted() == encrypted
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_next_password() {
+        let current = Client::new("alice", "old");
+        let client = Client::new_salt("alice", "new", &current.salt).unwrap();
+        let response = client.encrypted();
+
+        assert!(!current.check(&response));
+        assert!(current.with_password("new").check(&response));
+
+        // Hashed like in pg_shadow: md5(password + user).
+        let hashed = format!("md5{:x}", md5::compute("newalice"));
+        assert!(current.with_password(&hashed).check(&response));
+    }
+}
@@ -6,3 +6,22 @@ pub mod scram;
 
 pub use error::Error;
 pub use md5::Client;
+
+/// Which of the user's passwords the client authenticated with.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Credential {
+    /// The `password` from users.toml.
+    #[default]
+    Current,
+    /// The `next_password`, during a password rotation.
+    Next,
+}
+
+impl std::fmt::Display for Credential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Current => write!(f, "current"),
+            Self::Next => write!(f, "next"),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 //! SCRAM-SHA-256 server.
 
+use crate::auth::Credential;
 use crate::frontend::Error;
 use crate::net::messages::*;
 use crate::net::Stream;
@@ -7,10 +8,12 @@ use crate::net::Stream;
 use scram::server::ClientFinal;
 use tracing::error;
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use scram::{
     hash_password, AuthenticationProvider, AuthenticationStatus, PasswordInfo, ScramServer,
 };
+use std::cell::OnceCell;
 use std::num::NonZeroU32;
 
 enum Provider {
@@ -20,9 +23,14 @@ enum Provider {
 
 /// Derive the SCRAM-SHA-256 auth
 /// from a plain text password.
+///
+/// The salt is picked once per authentication, so the same
+/// challenge can be checked against more than one password.
 #[derive(Clone)]
 pub struct UserPassword {
     password: String,
+    salt: Vec<u8>,
+    iterations: u16,
 }
 
 /// Used a prehashed password obtained from
@@ -51,10 +59,31 @@ use base64::prelude::*;
 impl AuthenticationProvider for UserPassword {
     fn get_password_for(&self, _user: &str) -> Option<PasswordInfo> {
         // TODO: This is slow. We should move it to its own thread pool.
-        let iterations = 4096;
-        let salt = rand::thread_rng().gen::<[u8; 16]>().to_vec();
-        let hash = hash_password(&self.password, NonZeroU32::new(iterations).unwrap(), &salt);
-        Some(PasswordInfo::new(hash.to_vec(), iterations as u16, salt))
+        let hash = hash_password(
+            &self.password,
+            NonZeroU32::new(self.iterations as u32)?,
+            &self.salt,
+        );
+        Some(PasswordInfo::new(
+            hash.to_vec(),
+            self.iterations,
+            self.salt.clone(),
+        ))
+    }
+}
+
+impl HashedPassword {
+    /// Salt and iterations the hash was computed with.
+    fn salt(&self) -> Option<(Vec<u8>, u16)> {
+        let mut parts = self.hash.split('$');
+        if parts.next() != Some("SCRAM-SHA-256") {
+            return None;
+        }
+        let mut iter_salt = parts.next()?.split(':');
+        let iterations = iter_salt.next()?.parse::<u16>().ok()?;
+        let salt = BASE64_STANDARD.decode(iter_salt.next()?).ok()?;
+
+        Some((salt, iterations))
     }
 }
 
@@ -106,7 +135,7 @@ impl AuthenticationProvider for HashedPassword {
 /// authenticating clients.
 pub struct Server {
     provider: Provider,
-    client_response: String,
+    next: Option<UserPassword>,
 }
 
 impl Server {
@@ -115,8 +144,10 @@ impl Server {
         Self {
             provider: Provider::Plain(UserPassword {
                 password: password.to_owned(),
+                salt: rand::thread_rng().gen::<[u8; 16]>().to_vec(),
+                iterations: 4096,
             }),
-            client_response: String::new(),
+            next: None,
         }
     }
 
@@ -125,18 +156,44 @@ impl Server {
             provider: Provider::Hashed(HashedPassword {
                 hash: hash.to_owned(),
             }),
-            client_response: String::new(),
+            next: None,
+        }
+    }
+
+    /// Also accept this password, e.g. while rotating credentials.
+    ///
+    /// Both passwords share the salt and the server nonce, so the client
+    /// receives a single challenge and its proof is checked against each.
+    /// With a hashed password, the next one uses the salt and
+    /// iterations from the hash.
+    pub fn next_password(mut self, password: &str) -> Self {
+        let salt = match self.provider {
+            Provider::Plain(ref plain) => Some((plain.salt.clone(), plain.iterations)),
+            Provider::Hashed(ref hashed) => hashed.salt(),
+        };
+        if let Some((salt, iterations)) = salt {
+            self.next = Some(UserPassword {
+                password: password.to_owned(),
+                salt,
+                iterations,
+            });
         }
+        self
     }
 
-    /// Handle authentication.
-    pub async fn handle(mut self, stream: &mut Stream) -> Result<bool, Error> {
+    /// Handle authentication. Returns which password the client used,
+    /// if authenticated.
+    pub async fn handle(self, stream: &mut Stream) -> Result<Option<Credential>, Error> {
         let scram = match self.provider {
             Provider::Plain(plain) => Scram::Plain(ScramServer::new(plain)),
             Provider::Hashed(hashed) => Scram::Hashed(ScramServer::new(hashed)),
         };
+        let next = self.next.map(ScramServer::new);
 
+        // Outlives the SCRAM state that borrows it.
+        let client_response = OnceCell::new();
         let mut scram_client = None;
+        let mut next_client = None;
 
         loop {
             let message = stream.read().await?;
@@ -146,23 +203,30 @@ impl Server {
 
                     match password {
                         Password::SASLInitialResponse { response, .. } => {
-                            self.client_response = response;
+                            let client_response = client_response.get_or_init(|| response);
+                            // Same nonce for both passwords.
+                            let rng = StdRng::from_entropy();
                             let reply = match scram {
                                 Scram::Plain(ref plain) => {
-                                    let server =
-                                        plain.handle_client_first(&self.client_response)?;
-                                    let (client, reply) = server.server_first();
+                                    let server = plain.handle_client_first(client_response)?;
+                                    let (client, reply) =
+                                        server.server_first_with_rng(&mut rng.clone());
                                     scram_client = Some(ScramFinal::Plain(client));
                                     reply
                                 }
                                 Scram::Hashed(ref hashed) => {
-                                    let server =
-                                        hashed.handle_client_first(&self.client_response)?;
-                                    let (client, reply) = server.server_first();
+                                    let server = hashed.handle_client_first(client_response)?;
+                                    let (client, reply) =
+                                        server.server_first_with_rng(&mut rng.clone());
                                     scram_client = Some(ScramFinal::Hashed(client));
                                     reply
                                 }
                             };
+                            if let Some(ref next) = next {
+                                let server = next.handle_client_first(client_response)?;
+                                let (client, _) = server.server_first_with_rng(&mut rng.clone());
+                                next_client = Some(client);
+                            }
                             let reply = Authentication::SaslContinue(reply);
                             stream.send_flush(&reply).await?;
                         }
@@ -179,14 +243,21 @@ impl Server {
                                 };
                                 let (status, reply) = server_final.server_final();
 
-                                match status {
-                                    AuthenticationStatus::Authenticated => {
+                                if matches!(status, AuthenticationStatus::Authenticated) {
+                                    stream.send(&Authentication::SaslFinal(reply)).await?;
+                                    return Ok(Some(Credential::Current));
+                                }
+
+                                if let Some(next_client) = next_client {
+                                    let (status, reply) =
+                                        next_client.handle_client_final(&response)?.server_final();
+                                    if matches!(status, AuthenticationStatus::Authenticated) {
                                         stream.send(&Authentication::SaslFinal(reply)).await?;
-                                        return Ok(true);
+                                        return Ok(Some(Credential::Next));
                                     }
-
-                                    _ => return Ok(false),
                                 }
+
+                                return Ok(None);
                             }
                         }
                     }
@@ -195,7 +266,7 @@ impl Server {
                 'E' => {
                     let err = ErrorResponse::from_bytes(message.to_bytes()?)?;
                     error!("{}", err);
-                    return Ok(false);
+                    return Ok(None);
                 }
 
                 c => return Err(Error::UnexpectedMessage(c)),
@@ -216,4 +287,16 @@ mod test {
         let info = hashed.get_password_for("user");
         assert!(info.is_some());
     }
+
+    #[test]
+    fn test_next_password_hashed() {
+        let hash = "SCRAM-SHA-256$4096:lApbvrTR0W7WOZLcVrbz0A==$O+AwRnblFCJwEezpaozQfC6iKmbJFHQ7+0WZBsR+hFU=:wWjPizZvFjc5jmIkdN/EsuLGz/9FMjOhJ7IHxZI8eqE=";
+        let server = Server::hashed(hash).next_password("next");
+        let next = server.next.unwrap();
+        assert_eq!(next.iterations, 4096);
+        assert_eq!(
+            next.salt,
+            BASE64_STANDARD.decode("lApbvrTR0W7WOZLcVrbz0A==").unwrap()
+        );
+    }
 }
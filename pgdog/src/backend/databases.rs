@@ -1,31 +1,42 @@
 //! Databases behind pgDog.
 
-use std::collections::BTreeSet;
 use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{BTreeSet, HashSet};
 use std::sync::Arc;
 
 use arc_swap::ArcSwap;
 use once_cell::sync::Lazy;
 use parking_lot::lock_api::MutexGuard;
 use parking_lot::{Mutex, RawMutex};
-use tracing::{info, warn};
+use regex::Regex;
+use tracing::{error, info, warn};
 
 use crate::{
     backend::pool::PoolConfig,
-    config::{config, load, ConfigAndUsers, ManualQuery, Role},
-    net::messages::BackendKeyData,
+    config::{config, history, reload::Report, set, ConfigAndUsers, ManualQuery, Role},
+    events::{emit, Event},
+    frontend::{comms::comms, router::parser::Cache},
+    net::{messages::BackendKeyData, tls},
 };
 
 use super::{
-    pool::{Address, ClusterConfig, Config},
+    discovery,
+    pool::{Address, ClusterConfig, Config, Pool},
     reload_notify,
-    replication::ReplicationConfig,
-    Cluster, ClusterShardConfig, Error, ShardedTables,
+    replication::{reshard, slots, ReplicationConfig},
+    shard_map, Cluster, ClusterShardConfig, Error, ShardedTables,
 };
 
 static DATABASES: Lazy<ArcSwap<Databases>> =
     Lazy::new(|| ArcSwap::from_pointee(Databases::default()));
 static LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+/// Serializes config changes, so reloads, rollbacks and `SET` don't overwrite each other.
+static RELOAD: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+/// Databases not accepting new clients. Kept across config reloads.
+static DISABLED: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+/// Failovers, old primary and promoted replica, applied again to pools
+/// created from config. Kept across config reloads.
+static FAILOVERS: Lazy<Mutex<Vec<(Address, Address)>>> = Lazy::new(|| Mutex::new(vec![]));
 
 /// Sync databases during modification.
 pub fn lock() -> MutexGuard<'static, RawMutex, ()> {
@@ -47,11 +58,14 @@ pub fn replace_databases(new_databases: Databases, reload: bool) {
     let new_databases = Arc::new(new_databases);
     reload_notify::started();
     if reload {
+        old_databases.copy_stats_to(&new_databases);
         // Move whatever connections we can over to new pools.
         old_databases.move_conns_to(&new_databases);
     }
     new_databases.launch();
     DATABASES.store(new_databases);
+    // Routing decisions depend on the sharding config.
+    Cache::invalidate_routes();
     old_databases.shutdown();
     reload_notify::done();
 }
@@ -61,10 +75,68 @@ pub fn reconnect() {
     replace_databases(databases().duplicate(), false);
 }
 
+/// Re-create all connections to one database, for all users.
+///
+/// Returns the number of users reconnected.
+pub(crate) fn reconnect_database(database: &str) -> usize {
+    let _lock = LOCK.lock();
+    let mut databases = (*databases()).clone();
+
+    let mut old = vec![];
+    for (user, cluster) in databases.databases.iter_mut() {
+        if user.database == database {
+            old.push(cluster.clone());
+            *cluster = cluster.duplicate();
+            cluster.launch();
+        }
+    }
+
+    if !old.is_empty() {
+        databases.mirrors = mirrors(&databases.databases);
+        // Don't use replace_databases, it would shut down the other databases.
+        DATABASES.store(Arc::new(databases));
+        for cluster in &old {
+            cluster.shutdown();
+        }
+    }
+
+    old.len()
+}
+
+/// Stop or start accepting new clients for this database.
+///
+/// Connected clients aren't affected. Returns false if the database doesn't exist.
+pub(crate) fn disable(database: &str, disabled: bool) -> bool {
+    if !databases()
+        .all()
+        .keys()
+        .any(|user| user.database == database)
+    {
+        return false;
+    }
+
+    let mut guard = DISABLED.lock();
+    if disabled {
+        guard.insert(database.to_owned());
+    } else {
+        guard.remove(database);
+    }
+
+    true
+}
+
+/// Database doesn't accept new clients.
+pub fn disabled(database: &str) -> bool {
+    DISABLED.lock().contains(database)
+}
+
 /// Initialize the databases for the first time.
 pub fn init() {
     let config = config();
     replace_databases(from_config(&config), false);
+    shard_map::launch(&config.config);
+    discovery::launch(&config.config);
+    slots::launch(&config.config);
 }
 
 /// Shutdown all databases.
@@ -74,18 +146,315 @@ pub fn shutdown() {
 
 /// Re-create pools from config.
 ///
-/// TODO: Avoid creating new pools if they haven't changed at all
-/// or the configuration between the two is compatible.
+/// Connections are moved to the new pools if they connect to the same
+/// databases, so new pool sizes and timeouts apply to them too. Idle connections
+/// over the new pool size are closed right away, checked out ones when they're
+/// returned. TLS certificates are read again; if that fails, the previous ones
+/// are kept. Plugins are only loaded on startup. What changed is logged and shown
+/// in `SHOW RELOAD`.
 pub fn reload() -> Result<(), Error> {
+    let _lock = RELOAD.lock();
     let old_config = config();
-    let new_config = load(&old_config.config_path, &old_config.users_path)?;
-    let databases = from_config(&new_config);
+    let new_config = ConfigAndUsers::load(&old_config.config_path, &old_config.users_path)
+        .inspect_err(|err| Report::failed(err).record())?;
+    reshard::check(&new_config.config).inspect_err(|err| Report::failed(err).record())?;
+    let new_config = set(new_config).inspect_err(|err| Report::failed(err).record())?;
+    apply(&old_config, &new_config);
+    Ok(())
+}
 
-    replace_databases(databases, true);
+/// Apply a configuration from history again, the previous one
+/// or `version`. Returns the version applied.
+///
+/// Passwords are read from their `password_file` again, since they may have been
+/// rotated since. Only the configuration is rolled back: failovers, resharded
+/// databases, mappings loaded from tables and discovered replicas stay as they are.
+pub fn rollback(version: Option<usize>) -> Result<usize, Error> {
+    let _lock = RELOAD.lock();
+    let old_config = config();
+    let target = history::rollback(version)?;
+    let new_config = rolled_back(&target.config).inspect_err(|err| {
+        Report::failed(err).record();
+        history::record(&old_config);
+    })?;
+    info!("configuration rolled back to version {}", target.version);
+    apply(&old_config, &new_config);
+    Ok(target.version)
+}
 
+/// Check and set a configuration from history, with passwords read from files again.
+fn rolled_back(config: &ConfigAndUsers) -> Result<ConfigAndUsers, Error> {
+    let mut config = config.clone();
+    config.config.load_password_files()?;
+    config.users.load_password_files()?;
+    reshard::check(&config.config)?;
+    Ok(set(config)?)
+}
+
+/// Change the configuration at runtime, e.g. with `SET`,
+/// and re-create pools like a reload.
+pub fn change<E: From<Error>>(
+    change: impl FnOnce(&mut ConfigAndUsers) -> Result<(), E>,
+) -> Result<(), E> {
+    let _lock = RELOAD.lock();
+    let old_config = config();
+    let mut new_config = (*old_config).clone();
+    change(&mut new_config)?;
+    reshard::check(&new_config.config)
+        .inspect_err(|err| Report::failed(err).record())
+        .map_err(Error::from)?;
+    let new_config = set(new_config)
+        .inspect_err(|err| Report::failed(err).record())
+        .map_err(Error::from)?;
+    apply(&old_config, &new_config);
     Ok(())
 }
 
+/// Re-create pools from the new configuration.
+fn apply(old_config: &ConfigAndUsers, new_config: &ConfigAndUsers) {
+    let databases = from_config(new_config);
+
+    replace_databases(databases, true);
+    shard_map::launch(&new_config.config);
+    discovery::launch(&new_config.config);
+    slots::launch(&new_config.config);
+
+    if let Err(err) = tls::load() {
+        error!("TLS reload error, keeping previous certificates: {}", err);
+    }
+
+    Report::new(old_config, new_config).record();
+    history::record(new_config);
+
+    emit(Event::Reload { database: None });
+}
+
+/// Re-create pools of one database from config, for all of its users.
+///
+/// Only the database, its users and its sharded tables are re-read from disk.
+/// Pools of other databases are left alone. Returns the number of users
+/// reloaded, or removed if the database isn't in the config anymore.
+pub fn reload_database(database: &str) -> Result<usize, Error> {
+    let _reload = RELOAD.lock();
+    let old_config = config();
+    let file = ConfigAndUsers::load(&old_config.config_path, &old_config.users_path)
+        .inspect_err(|err| Report::failed(err).record())?;
+
+    let mut new_config = (*old_config).clone();
+    new_config.config.databases.retain(|db| db.name != database);
+    new_config.config.databases.extend(
+        file.config
+            .databases
+            .into_iter()
+            .filter(|db| db.name == database),
+    );
+    new_config
+        .config
+        .sharded_tables
+        .retain(|table| table.database != database);
+    new_config.config.sharded_tables.extend(
+        file.config
+            .sharded_tables
+            .into_iter()
+            .filter(|table| table.database == database),
+    );
+    new_config
+        .users
+        .users
+        .retain(|user| user.database != database);
+    new_config.users.users.extend(
+        file.users
+            .users
+            .into_iter()
+            .filter(|user| user.database == database),
+    );
+    reshard::check(&new_config.config).inspect_err(|err| Report::failed(err).record())?;
+    let new_config = set(new_config).inspect_err(|err| Report::failed(err).record())?;
+    Report::new(&old_config, &new_config).record();
+    history::record(&new_config);
+
+    let _lock = LOCK.lock();
+    let mut databases = (*databases()).clone();
+
+    let mut old = HashMap::new();
+    databases.databases.retain(|user, cluster| {
+        if user.database == database {
+            old.insert(user.clone(), cluster.clone());
+            false
+        } else {
+            true
+        }
+    });
+
+    reload_notify::started();
+    let mut reloaded = 0;
+    for user in new_config
+        .users
+        .users
+        .iter()
+        .filter(|user| user.database == database)
+    {
+        if let Some((user, mut cluster)) = new_pool(user, &new_config.config) {
+            promote(&mut FAILOVERS.lock(), &mut [&mut cluster], false);
+            if let Some(old) = old.get(&user) {
+                old.copy_stats_to(&cluster);
+                if old.can_move_conns_to(&cluster) {
+                    old.move_conns_to(&cluster);
+                }
+            }
+            cluster.launch();
+            databases.databases.insert(user, cluster);
+            reloaded += 1;
+        }
+    }
+
+    databases.mirrors = mirrors(&databases.databases);
+    // Don't use replace_databases, it would shut down the other databases.
+    DATABASES.store(Arc::new(databases));
+    Cache::invalidate_routes();
+    for cluster in old.values() {
+        cluster.shutdown();
+    }
+    reload_notify::done();
+
+    if reloaded > 0 || !old.is_empty() {
+        info!("reloaded database \"{}\" for {} users", database, reloaded);
+    }
+
+    emit(Event::Reload {
+        database: Some(database.to_owned()),
+    });
+
+    Ok(reloaded.max(old.len()))
+}
+
+/// Send writes to a promoted replica instead of the old primary,
+/// in all databases, without reloading the config.
+///
+/// Pools are shared with the current databases, so no connections are closed.
+/// Returns the number of databases that changed.
+pub(crate) fn failover(primary: &Address, promoted: &Address) -> usize {
+    let _lock = LOCK.lock();
+    let mut databases = (*databases()).clone();
+
+    let mut changed = 0;
+    for cluster in databases.databases.values_mut() {
+        if cluster.failover(primary, promoted) {
+            changed += 1;
+        }
+    }
+    for cluster in databases.mirrors.values_mut().flatten() {
+        cluster.failover(primary, promoted);
+    }
+
+    if changed > 0 {
+        // Don't use replace_databases, it would shut down the pools.
+        DATABASES.store(Arc::new(databases));
+        FAILOVERS.lock().push((primary.clone(), promoted.clone()));
+    }
+
+    changed
+}
+
+/// Apply failovers again to clusters just created from config,
+/// so a reload doesn't send writes back to the old primary.
+///
+/// With `forget`, failovers that don't apply to any cluster anymore, e.g. because
+/// the config now has the promoted replica as the primary, are dropped.
+fn promote(failovers: &mut Vec<(Address, Address)>, clusters: &mut [&mut Cluster], forget: bool) {
+    failovers.retain(|(primary, promoted)| {
+        let mut changed = false;
+        for cluster in clusters.iter_mut() {
+            changed |= cluster.failover(primary, promoted);
+        }
+        if changed {
+            info!(
+                "keeping failover to {}:{} [{}]",
+                promoted.host, promoted.port, primary
+            );
+        }
+        changed || !forget
+    });
+}
+
+/// Add and remove replicas discovered streaming from the primary,
+/// in all databases, without reloading the config.
+///
+/// Returns the number of replicas added and removed.
+pub(crate) fn discover(primary: &Address, hosts: &[String], port: u16) -> usize {
+    let _lock = LOCK.lock();
+    let mut databases = (*databases()).clone();
+
+    let mut added = vec![];
+    let mut removed = vec![];
+    for cluster in databases.databases.values_mut() {
+        let (cluster_added, cluster_removed) = cluster.discover(primary, hosts, port);
+        added.extend(cluster_added);
+        removed.extend(cluster_removed);
+    }
+
+    let changed = added.len() + removed.len();
+    if changed > 0 {
+        databases.mirrors = mirrors(&databases.databases);
+        for pool in &added {
+            pool.launch();
+        }
+        // Don't use replace_databases, it would shut down the pools.
+        DATABASES.store(Arc::new(databases));
+        for pool in &removed {
+            pool.shutdown();
+        }
+    }
+
+    changed
+}
+
+/// Send queries for the source database to the shards of the destination,
+/// once it has a copy of all the data. Users are matched by name.
+///
+/// Source pools are shut down, so clients waiting on them reconnect
+/// to the new shards. Returns the number of users switched.
+pub(crate) fn reshard(source: &str, destination: &str) -> usize {
+    let _lock = LOCK.lock();
+    let mut databases = (*databases()).clone();
+
+    let targets = databases
+        .databases
+        .iter()
+        .filter(|(user, _)| user.database == destination)
+        .map(|(user, cluster)| (user.user.clone(), cluster.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut old = vec![];
+    for (user, cluster) in databases.databases.iter_mut() {
+        if user.database != source {
+            continue;
+        }
+        match targets.get(&user.user) {
+            Some(target) => {
+                old.push(cluster.clone());
+                *cluster = cluster.reshard(target);
+            }
+            None => warn!(
+                "user \"{}\" doesn't exist in \"{}\", not resharded",
+                user.user, destination
+            ),
+        }
+    }
+
+    if !old.is_empty() {
+        databases.mirrors = mirrors(&databases.databases);
+        // Don't use replace_databases, it would shut down the destination pools.
+        DATABASES.store(Arc::new(databases));
+        Cache::invalidate_routes();
+        for cluster in &old {
+            cluster.shutdown();
+        }
+    }
+
+    old.len()
+}
+
 /// Add new user to pool.
 pub(crate) fn add(mut user: crate::config::User) {
     let config = config();
@@ -111,6 +480,127 @@ pub(crate) fn add(mut user: crate::config::User) {
     }
 }
 
+/// Add a pool dedicated to the client's `application_name`,
+/// if pooling by application name is enabled for the user.
+///
+/// Returns true if the pool exists.
+pub(crate) fn add_application(user: &str, database: &str, application_name: &str) -> bool {
+    if application_name.is_empty() {
+        return false;
+    }
+
+    let key = (user, database, Some(application_name)).to_user();
+    if databases().databases.contains_key(&key) {
+        return true;
+    }
+
+    let config = config();
+    let general = &config.config.general;
+    let Some(existing) = config.users.find(user, database) else {
+        return false;
+    };
+    if !existing
+        .pool_by_application_name
+        .unwrap_or(general.pool_by_application_name)
+    {
+        return false;
+    }
+
+    let _lock = LOCK.lock();
+    let databases = (*databases()).clone();
+    if databases.databases.contains_key(&key) {
+        return true;
+    }
+
+    let pools = databases
+        .databases
+        .keys()
+        .filter(|u| u.user == user && u.database == database && u.application_name.is_some())
+        .count();
+    if pools >= general.max_application_pools {
+        warn!(
+            r#"user "{}" on database "{}" reached {} application pools, "{}" will use the shared pool"#,
+            user, database, pools, application_name,
+        );
+        return false;
+    }
+
+    // Passthrough users get their password at runtime.
+    let mut existing = existing.clone();
+    if existing.password().is_empty() {
+        if let Ok(cluster) = databases.cluster((user, database)) {
+            existing.password = Some(cluster.password().to_owned());
+        }
+    }
+
+    let Some((_, cluster)) = new_pool(&existing, &config.config) else {
+        return false;
+    };
+    let (added, databases) = databases.add(key, cluster);
+    if added {
+        databases.launch();
+        DATABASES.store(Arc::new(databases));
+        info!(
+            r#"created pool for application "{}" [{}/{}]"#,
+            application_name, user, database
+        );
+    }
+
+    true
+}
+
+/// Pools created for each `application_name` of a user share its pool size:
+/// together, they don't open more connections to the same server than it allows.
+/// At the limit, an idle connection of another pool is closed to make room.
+///
+/// Returns true if this pool can't open another connection.
+pub(crate) fn application_limit_reached(pool: &Pool) -> bool {
+    let Some(owner) = pool.owner() else {
+        return false;
+    };
+    let databases = databases();
+    let group = databases
+        .databases
+        .iter()
+        .filter(|(user, _)| user.user == owner.user && user.database == owner.database)
+        .collect::<Vec<_>>();
+    if group
+        .iter()
+        .all(|(user, _)| user.application_name.is_none())
+    {
+        return false;
+    }
+
+    let mut counted = HashSet::new();
+    let mut others = vec![];
+    let mut total = 0;
+    let mut limit = None;
+    for (user, cluster) in group {
+        let pools = cluster
+            .shards()
+            .iter()
+            .flat_map(|shard| shard.pools())
+            .filter(|other| other.addr().same_database(pool.addr()));
+        for other in pools {
+            if !counted.insert(other.id()) {
+                continue;
+            }
+            let state = other.state();
+            total += state.total;
+            if user.application_name.is_none() {
+                limit = Some(state.config.max);
+            }
+            if other.id() != pool.id() && state.idle > 0 {
+                others.push(other);
+            }
+        }
+    }
+
+    let reached = limit.is_some_and(|limit| total >= limit);
+
+    reached && !others.iter().any(|other| other.close_idle_conn())
+}
+
 /// Database/user pair that identifies a database cluster pool.
 #[derive(Debug, PartialEq, Hash, Eq, Clone)]
 pub struct User {
@@ -118,11 +608,17 @@ pub struct User {
     pub user: String,
     /// Database name.
     pub database: String,
+    /// Application name, if pooling by `application_name`.
+    pub application_name: Option<String>,
 }
 
 impl std::fmt::Display for User {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}/{}", self.user, self.database)
+        write!(f, "{}/{}", self.user, self.database)?;
+        if let Some(ref application_name) = self.application_name {
+            write!(f, "/{}", application_name)?;
+        }
+        Ok(())
     }
 }
 
@@ -137,6 +633,17 @@ impl ToUser for (&str, &str) {
         User {
             user: self.0.to_string(),
             database: self.1.to_string(),
+            application_name: None,
+        }
+    }
+}
+
+impl ToUser for (&str, &str, Option<&str>) {
+    fn to_user(&self) -> User {
+        User {
+            user: self.0.to_string(),
+            database: self.1.to_string(),
+            application_name: self.2.map(|a| a.to_string()),
         }
     }
 }
@@ -146,6 +653,7 @@ impl ToUser for (&str, Option<&str>) {
         User {
             user: self.0.to_string(),
             database: self.1.map_or(self.0.to_string(), |d| d.to_string()),
+            application_name: None,
         }
     }
 }
@@ -155,6 +663,7 @@ impl ToUser for (&str, Option<&str>) {
 pub struct Databases {
     databases: HashMap<User, Cluster>,
     manual_queries: HashMap<String, ManualQuery>,
+    manual_patterns: Vec<(Regex, ManualQuery)>,
     mirrors: HashMap<String, Vec<Cluster>>,
 }
 
@@ -226,7 +735,25 @@ impl Databases {
     }
 
     /// Cancel a query running on one of the databases proxied by the pooler.
+    ///
+    /// If the client is still connected, only the cluster it's using is checked.
+    /// Otherwise, we look for the client in all pools.
     pub async fn cancel(&self, id: &BackendKeyData) -> Result<(), Error> {
+        if let Some(client) = comms().client(id) {
+            let user = client.paramters.get_default("user", "postgres");
+            let database = client.paramters.get_default("database", user);
+            let application_name = client.paramters.get_default("application_name", "");
+
+            for key in [
+                (user, database, Some(application_name)).to_user(),
+                (user, database).to_user(),
+            ] {
+                if let Some(cluster) = self.databases.get(&key) {
+                    return cluster.cancel(id).await;
+                }
+            }
+        }
+
         for cluster in self.databases.values() {
             cluster.cancel(id).await?;
         }
@@ -244,6 +771,28 @@ impl Databases {
         &self.manual_queries
     }
 
+    /// Get manual query matching the query text, if any.
+    pub fn manual_pattern(&self, query: &str) -> Option<&ManualQuery> {
+        self.manual_patterns
+            .iter()
+            .find(|(regex, _)| regex.is_match(query))
+            .map(|(_, manual_query)| manual_query)
+    }
+
+    /// Manual queries matched by regular expressions.
+    pub fn manual_patterns(&self) -> &[(Regex, ManualQuery)] {
+        &self.manual_patterns
+    }
+
+    /// Keep statistics of pools that connect to the same databases.
+    pub(crate) fn copy_stats_to(&self, destination: &Databases) {
+        for (user, cluster) in &self.databases {
+            if let Some(dest) = destination.databases.get(user) {
+                cluster.copy_stats_to(dest);
+            }
+        }
+    }
+
     /// Move all connections we can from old databases config to new
     /// databases config.
     pub(crate) fn move_conns_to(&self, destination: &Databases) -> usize {
@@ -271,6 +820,7 @@ impl Databases {
                 .map(|(k, v)| (k.clone(), v.duplicate()))
                 .collect(),
             manual_queries: self.manual_queries.clone(),
+            manual_patterns: self.manual_patterns.clone(),
             mirrors: self.mirrors.clone(),
         }
     }
@@ -309,6 +859,7 @@ pub(crate) fn new_pool(
     let databases = config.databases();
     let shards = databases.get(&user.database);
     let mut mirrors_of = BTreeSet::new();
+    let owner = (user.name.as_str(), user.database.as_str()).to_user();
 
     if let Some(shards) = shards {
         let mut shard_configs = vec![];
@@ -321,6 +872,7 @@ pub(crate) fn new_pool(
                     PoolConfig {
                         address: Address::new(primary, user),
                         config: Config::new(general, primary, user),
+                        owner: Some(owner.clone()),
                     }
                 });
             let replicas = user_databases
@@ -331,6 +883,7 @@ pub(crate) fn new_pool(
                     PoolConfig {
                         address: Address::new(replica, user),
                         config: Config::new(general, replica, user),
+                        owner: Some(owner.clone()),
                     }
                 })
                 .collect::<Vec<_>>();
@@ -372,13 +925,7 @@ pub(crate) fn new_pool(
             config.multi_tenant(),
         );
 
-        Some((
-            User {
-                user: user.name.clone(),
-                database: user.database.clone(),
-            },
-            Cluster::new(cluster_config),
-        ))
+        Some((owner, Cluster::new(cluster_config)))
     } else {
         None
     }
@@ -386,6 +933,14 @@ pub(crate) fn new_pool(
 
 /// Load databases from config.
 pub fn from_config(config: &ConfigAndUsers) -> Databases {
+    from_config_with_failovers(config, &mut FAILOVERS.lock())
+}
+
+/// Load databases from config, keeping these failovers.
+fn from_config_with_failovers(
+    config: &ConfigAndUsers,
+    failovers: &mut Vec<(Address, Address)>,
+) -> Databases {
     let mut databases = HashMap::new();
 
     for user in &config.users.users {
@@ -394,6 +949,22 @@ pub fn from_config(config: &ConfigAndUsers) -> Databases {
         }
     }
 
+    promote(
+        failovers,
+        &mut databases.values_mut().collect::<Vec<_>>(),
+        true,
+    );
+
+    Databases {
+        mirrors: mirrors(&databases),
+        databases,
+        manual_queries: config.config.manual_queries(),
+        manual_patterns: config.config.manual_patterns(),
+    }
+}
+
+/// Clusters mirroring each cluster.
+fn mirrors(databases: &HashMap<User, Cluster>) -> HashMap<String, Vec<Cluster>> {
     let mut mirrors = HashMap::new();
 
     for cluster in databases.values() {
@@ -406,9 +977,141 @@ pub fn from_config(config: &ConfigAndUsers) -> Databases {
         mirrors.insert(cluster.name().to_owned(), mirror_clusters);
     }
 
-    Databases {
-        databases,
-        manual_queries: config.config.manual_queries(),
-        mirrors,
+    mirrors
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backend::pool::Request;
+    use crate::config::{set, test::ConfigGuard, ConfigAndUsers, Database, User as ConfigUser};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_application_pools() {
+        let _guard = ConfigGuard::default();
+        let mut config = ConfigAndUsers::default();
+        config.config.general.pool_by_application_name = true;
+        config.config.general.max_application_pools = 1;
+        config.config.general.default_pool_size = 1;
+        config.config.general.min_pool_size = 0;
+        config.config.databases = vec![Database {
+            name: "pgdog".into(),
+            host: "127.0.0.1".into(),
+            port: 5432,
+            ..Default::default()
+        }];
+        config.users.users = vec![ConfigUser {
+            name: "pgdog".into(),
+            database: "pgdog".into(),
+            password: Some("pgdog".into()),
+            ..Default::default()
+        }];
+        set(config).unwrap();
+        init();
+
+        assert!(!add_application("pgdog", "pgdog", ""));
+        assert!(add_application("pgdog", "pgdog", "billing"));
+        assert!(add_application("pgdog", "pgdog", "billing"));
+        // Over the limit, uses the shared pool.
+        assert!(!add_application("pgdog", "pgdog", "search"));
+        assert!(!add_application("unknown", "pgdog", "billing"));
+
+        let user = ("pgdog", "pgdog", Some("billing")).to_user();
+        assert_eq!(user.to_string(), "pgdog/pgdog/billing");
+        assert!(databases().exists(("pgdog", "pgdog", Some("billing"))));
+        assert!(!databases().exists(("pgdog", "pgdog", Some("search"))));
+
+        // Application pools share the user's pool size.
+        let shared = databases().cluster(("pgdog", "pgdog")).unwrap();
+        let billing = databases()
+            .cluster(("pgdog", "pgdog", Some("billing")))
+            .unwrap()
+            .shards()[0]
+            .pools()[0]
+            .clone();
+        let conn = shared.primary(0, &Request::default()).await.unwrap();
+        assert!(application_limit_reached(&billing));
+
+        // The idle connection is closed to make room.
+        drop(conn);
+        assert!(!application_limit_reached(&billing));
+        assert_eq!(shared.shards()[0].pools()[0].state().total, 0);
+    }
+
+    #[test]
+    fn test_failover_kept() {
+        let address = |port| Address {
+            host: "127.0.0.1".into(),
+            port,
+            ..Default::default()
+        };
+        let database = |role, port| Database {
+            name: "failover_kept".into(),
+            host: "127.0.0.1".into(),
+            port,
+            role,
+            ..Default::default()
+        };
+        let primary = |databases: &Databases| {
+            databases
+                .cluster(("pgdog", "failover_kept"))
+                .unwrap()
+                .shards()[0]
+                .pools_with_roles()
+                .into_iter()
+                .find(|(role, _)| *role == Role::Primary)
+                .unwrap()
+                .1
+                .addr()
+                .port
+        };
+
+        let mut config = ConfigAndUsers::default();
+        config.config.databases =
+            vec![database(Role::Primary, 5432), database(Role::Replica, 5433)];
+        config.users.users = vec![ConfigUser {
+            name: "pgdog".into(),
+            database: "failover_kept".into(),
+            password: Some("pgdog".into()),
+            ..Default::default()
+        }];
+
+        // Not the global failovers, other tests load databases in parallel.
+        let mut failovers = vec![(address(5432), address(5433))];
+        let mut from_config =
+            |config: &ConfigAndUsers| from_config_with_failovers(config, &mut failovers);
+        assert_eq!(primary(&from_config(&config)), 5433);
+        assert_eq!(primary(&from_config(&config)), 5433);
+
+        // The config has the promoted replica as the primary now.
+        config.config.databases =
+            vec![database(Role::Replica, 5432), database(Role::Primary, 5433)];
+        assert_eq!(primary(&from_config(&config)), 5433);
+        assert!(failovers.is_empty());
+    }
+
+    #[test]
+    fn test_rolled_back_password_file() {
+        let _guard = ConfigGuard::default();
+        let path = std::env::temp_dir().join(format!("pgdog_rollback_{}", std::process::id()));
+        std::fs::write(&path, "before\n").unwrap();
+
+        let mut config = ConfigAndUsers::default();
+        config.users.users = vec![ConfigUser {
+            name: "pgdog".into(),
+            database: "pgdog".into(),
+            password_file: Some(path.clone()),
+            ..Default::default()
+        }];
+        config.users.load_password_files().unwrap();
+
+        // Password was rotated after this configuration was applied.
+        std::fs::write(&path, "after\n").unwrap();
+        let config = rolled_back(&config).unwrap();
+        assert_eq!(config.users.users[0].password(), "after");
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(rolled_back(&config).is_err());
     }
 }
@@ -0,0 +1,137 @@
+//! Replica discovery.
+//!
+//! Primaries are asked which replicas are streaming from them, using
+//! `pg_stat_replication`, and pools are created for them without reloading the config.
+//! Replicas that stop streaming are removed. Logical replication consumers,
+//! e.g. Debezium, are ignored.
+//!
+//! Postgres only reports the replicas' IP addresses, so replicas from the config
+//! are recognized by resolving their hosts, and new ones are expected to listen
+//! on `replica_discovery_port`.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::{net::lookup_host, spawn, time::sleep};
+use tracing::{debug, error, info, warn};
+
+use crate::config::{Config, Role};
+
+use super::{databases, pool::Request, Error, Pool};
+
+const QUERY: &str = "SELECT DISTINCT host(r.client_addr) \
+    FROM pg_stat_replication r \
+    WHERE r.client_addr IS NOT NULL \
+    AND r.state = 'streaming' \
+    AND NOT EXISTS ( \
+        SELECT 1 FROM pg_replication_slots s \
+        WHERE s.active_pid = r.pid AND s.slot_type = 'logical' \
+    )";
+
+/// Incremented every time the config is loaded,
+/// stopping discovery started for the previous one.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Start discovering replicas, if enabled in the config.
+pub fn launch(config: &Config) {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+
+    if !config.general.replica_discovery {
+        return;
+    }
+
+    let interval = Duration::from_millis(config.general.replica_discovery_interval);
+    let port = config.general.replica_discovery_port;
+
+    spawn(async move {
+        debug!("replica discovery started");
+
+        while GENERATION.load(Ordering::Relaxed) == generation {
+            discover(port).await;
+            sleep(interval).await;
+        }
+
+        debug!("replica discovery stopped");
+    });
+}
+
+/// Look for replicas of all primaries once.
+async fn discover(port: u16) {
+    let mut primaries: Vec<Pool> = vec![];
+    for cluster in databases::databases().all().values() {
+        for shard in cluster.shards() {
+            for (role, pool) in shard.pools_with_roles() {
+                if role == Role::Primary
+                    && !primaries
+                        .iter()
+                        .any(|primary| primary.addr().same_server(pool.addr()))
+                {
+                    primaries.push(pool);
+                }
+            }
+        }
+    }
+
+    for primary in primaries {
+        match replicas(&primary).await {
+            Ok(hosts) => {
+                let configured = configured(&primary).await;
+                let hosts = hosts
+                    .into_iter()
+                    .filter(|host| !configured.contains(host))
+                    .collect::<Vec<_>>();
+                let changed = databases::discover(primary.addr(), &hosts, port);
+                if changed > 0 {
+                    info!(
+                        "replicas changed, {} streaming: [{}] [{}]",
+                        hosts.len(),
+                        hosts.join(", "),
+                        primary.addr()
+                    );
+                }
+            }
+
+            // Keep the replicas we have until the primary is back.
+            Err(err) => error!("replica discovery error: {} [{}]", err, primary.addr()),
+        }
+    }
+}
+
+/// Hosts of this primary's replicas from the config,
+/// and the IP addresses they resolve to.
+async fn configured(primary: &Pool) -> HashSet<String> {
+    let mut hosts = HashSet::new();
+    for cluster in databases::databases().all().values() {
+        for shard in cluster.shards() {
+            let pools = shard.pools_with_roles();
+            if !pools.iter().any(|(role, pool)| {
+                *role == Role::Primary && pool.addr().same_server(primary.addr())
+            }) {
+                continue;
+            }
+            for (role, pool) in pools {
+                if role == Role::Replica && !pool.discovered() {
+                    hosts.insert(pool.addr().host.clone());
+                }
+            }
+        }
+    }
+
+    let mut addresses = HashSet::new();
+    for host in hosts {
+        match lookup_host((host.as_str(), 0)).await {
+            Ok(resolved) => addresses.extend(resolved.map(|addr| addr.ip().to_string())),
+            Err(err) => warn!("can't resolve replica \"{}\": {}", host, err),
+        }
+        addresses.insert(host);
+    }
+
+    addresses
+}
+
+/// Hosts of replicas streaming from the primary.
+async fn replicas(primary: &Pool) -> Result<Vec<String>, Error> {
+    let mut server = primary.get(&Request::default()).await?;
+    server.fetch_all(QUERY).await
+}
@@ -21,6 +21,9 @@ pub enum Error {
     #[error("server did not provide key data")]
     NoBackendKeyData,
 
+    #[error("server doesn't support TLS, but it's required")]
+    TlsRequired,
+
     #[error("unexpected transaction status: {0}")]
     UnexpectedTransactionStatus(char),
 
@@ -33,6 +36,9 @@ pub enum Error {
     #[error("server not connected")]
     NotConnected,
 
+    #[error("work_mem exceeded: more than {0} bytes buffered from multiple shards")]
+    WorkMem(usize),
+
     #[error("multi shard copy not connected")]
     CopyNotConnected,
 
@@ -89,6 +95,18 @@ pub enum Error {
 
     #[error("router error: {0}")]
     Router(String),
+
+    #[error("distinct values need more than {0} bytes of memory")]
+    DistinctMemoryLimit(usize),
+
+    #[error("bigint out of range")]
+    IntegerOutOfRange,
+
+    #[error("{0} can't be merged across shards without rewriting the query")]
+    AggregateNotRewritten(&'static str),
+
+    #[error("data type with OID {0} is different on some shards")]
+    OidMismatch(i32),
 }
 
 impl Error {
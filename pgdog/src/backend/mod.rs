@@ -1,6 +1,7 @@
 //! pgDog backend managers connections to PostgreSQL.
 
 pub mod databases;
+pub mod discovery;
 pub mod error;
 pub mod pool;
 pub mod prepared_statements;
@@ -10,6 +11,7 @@ pub mod replication;
 pub mod schema;
 pub mod server;
 pub mod server_options;
+pub mod shard_map;
 pub mod stats;
 
 pub use error::Error;
@@ -1,8 +1,10 @@
 //! Server address.
 
+use std::path::PathBuf;
+
 use serde::{Deserialize, Serialize};
 
-use crate::config::{Database, User};
+use crate::config::{Database, ServerTlsMode, User};
 
 /// Server address.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
@@ -17,6 +19,10 @@ pub struct Address {
     pub user: String,
     /// Password.
     pub password: String,
+    /// TLS mode.
+    pub tls_mode: ServerTlsMode,
+    /// CA used to verify the server certificate.
+    pub tls_ca: Option<PathBuf>,
 }
 
 impl Address {
@@ -44,6 +50,8 @@ impl Address {
             } else {
                 user.password().to_string()
             },
+            tls_mode: database.server_tls_mode,
+            tls_ca: database.server_tls_ca.clone(),
         }
     }
 
@@ -52,6 +60,11 @@ impl Address {
         format!("{}:{}", self.host, self.port)
     }
 
+    /// Both addresses point to the same server.
+    pub fn same_server(&self, other: &Address) -> bool {
+        self.host == other.host && self.port == other.port
+    }
+
     #[cfg(test)]
     pub fn new_test() -> Self {
         Self {
@@ -60,10 +73,21 @@ impl Address {
             user: "pgdog".into(),
             password: "pgdog".into(),
             database_name: "pgdog".into(),
+            ..Default::default()
         }
     }
 }
 
+impl Address {
+    /// Same server, database and user, even if the password or TLS settings changed.
+    pub fn same_database(&self, other: &Address) -> bool {
+        self.host == other.host
+            && self.port == other.port
+            && self.database_name == other.database_name
+            && self.user == other.user
+    }
+}
+
 impl std::fmt::Display for Address {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}:{}, {}", self.host, self.port, self.database_name)
@@ -108,4 +132,48 @@ mod test {
         assert_eq!(address.user, "alice");
         assert_eq!(address.password, "hunter3");
     }
+    #[test]
+    fn test_tls() {
+        let database = Database {
+            name: "pgdog".into(),
+            host: "shard-1.rds.amazonaws.com".into(),
+            server_tls_mode: ServerTlsMode::VerifyFull,
+            server_tls_ca: Some("/etc/ssl/rds-ca.pem".into()),
+            ..Default::default()
+        };
+
+        let address = Address::new(&database, &User::default());
+        assert_eq!(address.tls_mode, ServerTlsMode::VerifyFull);
+        assert!(address.tls_mode.required());
+        assert_eq!(address.tls_ca, Some(PathBuf::from("/etc/ssl/rds-ca.pem")));
+
+        let address = Address::new(&Database::default(), &User::default());
+        assert_eq!(address.tls_mode, ServerTlsMode::Prefer);
+        assert!(!address.tls_mode.required());
+    }
+
+    #[test]
+    fn test_same_database() {
+        let address = Address {
+            host: "127.0.0.1".into(),
+            port: 5432,
+            database_name: "pgdog".into(),
+            user: "pgdog".into(),
+            password: "pgdog".into(),
+            ..Default::default()
+        };
+
+        let other = Address {
+            password: "hunter2".into(),
+            tls_mode: ServerTlsMode::VerifyFull,
+            ..address.clone()
+        };
+        assert!(address.same_database(&other));
+
+        let other = Address {
+            port: 5433,
+            ..address.clone()
+        };
+        assert!(!address.same_database(&other));
+    }
 }
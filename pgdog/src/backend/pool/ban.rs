@@ -13,6 +13,8 @@ pub struct Ban {
     pub(super) reason: Error,
     /// Ban timeout
     pub(super) ban_timeout: Duration,
+    /// Manual ban that expires after the ban timeout, like automatic ones.
+    pub(super) timed: bool,
 }
 
 impl std::fmt::Display for Ban {
@@ -24,7 +26,7 @@ impl std::fmt::Display for Ban {
 impl Ban {
     /// Check if the ban has expired.
     pub(super) fn expired(&self, now: Instant) -> bool {
-        if self.reason == Error::ManualBan {
+        if self.reason == Error::ManualBan && !self.timed {
             false
         } else {
             let duration = now.duration_since(self.created_at);
@@ -47,6 +49,7 @@ mod test {
             created_at,
             reason: Error::CheckoutTimeout,
             ban_timeout,
+            timed: false,
         };
 
         let later = created_at + ban_timeout + Duration::from_secs(1);
@@ -56,5 +59,9 @@ mod test {
 
         ban.reason = Error::ManualBan;
         assert!(!ban.expired(later));
+
+        ban.timed = true;
+        assert!(!ban.expired(Instant::now()));
+        assert!(ban.expired(later));
     }
 }
@@ -1,13 +1,15 @@
 //! A collection of replicas and a primary.
 
+use futures::future::join_all;
 use parking_lot::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::spawn;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
     backend::{
-        databases::databases,
+        databases::{self, databases},
         replication::{ReplicationConfig, ShardedColumn},
         Schema, ShardedTables,
     },
@@ -17,16 +19,18 @@ use crate::{
     net::messages::BackendKeyData,
 };
 
-use super::{Address, Config, Error, Guard, Request, Shard};
+use super::{Address, Config, Error, Guard, Oids, Pool, Request, Shard};
 use crate::config::LoadBalancingStrategy;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 /// Database configuration.
 pub struct PoolConfig {
     /// Database address.
     pub(crate) address: Address,
     /// Pool settings.
     pub(crate) config: Config,
+    /// User and database of the cluster the pool belongs to.
+    pub(crate) owner: Option<databases::User>,
 }
 
 /// A collection of sharded replicas and primaries
@@ -37,6 +41,7 @@ pub struct Cluster {
     shards: Vec<Shard>,
     user: String,
     password: String,
+    next_password: Option<String>,
     pooler_mode: PoolerMode,
     sharded_tables: ShardedTables,
     replication_sharding: Option<String>,
@@ -45,6 +50,7 @@ pub struct Cluster {
     multi_tenant: Option<MultiTenant>,
     rw_strategy: ReadWriteStrategy,
     rw_split: ReadWriteSplit,
+    version_warning: Arc<AtomicBool>,
 }
 
 /// Sharding configuration from the cluster.
@@ -74,6 +80,7 @@ pub struct ClusterConfig<'a> {
     pub lb_strategy: LoadBalancingStrategy,
     pub user: &'a str,
     pub password: &'a str,
+    pub next_password: Option<&'a str>,
     pub pooler_mode: PoolerMode,
     pub sharded_tables: ShardedTables,
     pub replication_sharding: Option<String>,
@@ -81,6 +88,7 @@ pub struct ClusterConfig<'a> {
     pub multi_tenant: &'a Option<MultiTenant>,
     pub rw_strategy: ReadWriteStrategy,
     pub rw_split: ReadWriteSplit,
+    pub replica_fallback: bool,
 }
 
 impl<'a> ClusterConfig<'a> {
@@ -95,6 +103,7 @@ impl<'a> ClusterConfig<'a> {
         Self {
             name: &user.database,
             password: user.password(),
+            next_password: user.next_password.as_deref(),
             user: &user.name,
             replication_sharding: user.replication_sharding.clone(),
             pooler_mode: user.pooler_mode.unwrap_or(general.pooler_mode),
@@ -105,6 +114,7 @@ impl<'a> ClusterConfig<'a> {
             multi_tenant,
             rw_strategy: general.read_write_strategy,
             rw_split: general.read_write_split,
+            replica_fallback: general.replica_fallback_to_primary,
         }
     }
 }
@@ -118,6 +128,7 @@ impl Cluster {
             lb_strategy,
             user,
             password,
+            next_password,
             pooler_mode,
             sharded_tables,
             replication_sharding,
@@ -125,15 +136,25 @@ impl Cluster {
             multi_tenant,
             rw_strategy,
             rw_split,
+            replica_fallback,
         } = config;
 
         Self {
             shards: shards
                 .iter()
-                .map(|config| Shard::new(&config.primary, &config.replicas, lb_strategy, rw_split))
+                .map(|config| {
+                    Shard::new(
+                        &config.primary,
+                        &config.replicas,
+                        lb_strategy,
+                        rw_split,
+                        replica_fallback,
+                    )
+                })
                 .collect(),
             name: name.to_owned(),
             password: password.to_owned(),
+            next_password: next_password.map(|p| p.to_owned()),
             user: user.to_owned(),
             pooler_mode,
             sharded_tables,
@@ -143,6 +164,7 @@ impl Cluster {
             multi_tenant: multi_tenant.clone(),
             rw_strategy,
             rw_split,
+            version_warning: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -168,6 +190,25 @@ impl Cluster {
                 .all(|(a, b)| a.can_move_conns_to(b))
     }
 
+    /// Carry statistics over to pools of the other cluster that connect
+    /// to the same database, so counters don't reset on reload.
+    pub(crate) fn copy_stats_to(&self, other: &Cluster) {
+        let destinations = other
+            .shards
+            .iter()
+            .flat_map(|shard| shard.pools())
+            .collect::<Vec<_>>();
+
+        for pool in self.shards.iter().flat_map(|shard| shard.pools()) {
+            if let Some(destination) = destinations
+                .iter()
+                .find(|destination| destination.addr().same_database(pool.addr()))
+            {
+                pool.copy_stats_to(destination);
+            }
+        }
+    }
+
     /// Move connections from cluster to another, saving them.
     pub(crate) fn move_conns_to(&self, other: &Cluster) {
         for (from, to) in self.shards.iter().zip(other.shards.iter()) {
@@ -185,6 +226,7 @@ impl Cluster {
             name: self.name.clone(),
             user: self.user.clone(),
             password: self.password.clone(),
+            next_password: self.next_password.clone(),
             pooler_mode: self.pooler_mode,
             sharded_tables: self.sharded_tables.clone(),
             replication_sharding: self.replication_sharding.clone(),
@@ -193,13 +235,63 @@ impl Cluster {
             multi_tenant: self.multi_tenant.clone(),
             rw_strategy: self.rw_strategy,
             rw_split: self.rw_split,
+            version_warning: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Send writes to the promoted replica instead of the primary, in all shards
+    /// where it was a replica of that primary.
+    ///
+    /// Returns true if any shard changed.
+    pub(crate) fn failover(&mut self, primary: &Address, promoted: &Address) -> bool {
+        let mut changed = false;
+        for shard in &mut self.shards {
+            changed |= shard.failover(primary, promoted);
+        }
+        changed
+    }
+
+    /// Add and remove discovered replicas of the primary, in all shards it's in.
+    ///
+    /// Returns the pools added and removed.
+    pub(crate) fn discover(
+        &mut self,
+        primary: &Address,
+        hosts: &[String],
+        port: u16,
+    ) -> (Vec<Pool>, Vec<Pool>) {
+        let mut added = vec![];
+        let mut removed = vec![];
+        for shard in &mut self.shards {
+            let (shard_added, shard_removed) = shard.discover(primary, hosts, port);
+            added.extend(shard_added);
+            removed.extend(shard_removed);
+        }
+        (added, removed)
+    }
+
+    /// Same database and user, using the shards and sharding config
+    /// of the destination. Pools are shared with the destination.
+    pub(crate) fn reshard(&self, destination: &Cluster) -> Self {
+        Self {
+            shards: destination.shards.clone(),
+            sharded_tables: destination.sharded_tables.clone(),
+            replication_sharding: destination.replication_sharding.clone(),
+            schema: destination.schema.clone(),
+            ..self.clone()
         }
     }
 
     /// Cancel a query executed by one of the shards.
+    ///
+    /// Multi-shard queries run on all shards at once, so the cancellation
+    /// is sent to all of them concurrently. One failing shard doesn't prevent
+    /// the others from being cancelled.
     pub async fn cancel(&self, id: &BackendKeyData) -> Result<(), super::super::Error> {
-        for shard in &self.shards {
-            shard.cancel(id).await?;
+        let results = join_all(self.shards.iter().map(|shard| shard.cancel(id))).await;
+
+        for result in results {
+            result?;
         }
 
         Ok(())
@@ -210,6 +302,17 @@ impl Cluster {
         &self.shards
     }
 
+    /// Custom data types loaded from the databases in this cluster.
+    pub fn oids(&self) -> Oids {
+        let mut oids = Oids::default();
+        for pool in self.shards.iter().flat_map(|shard| shard.pools()) {
+            if let Some(ref pool_oids) = pool.oids() {
+                oids.merge(pool_oids);
+            }
+        }
+        oids
+    }
+
     /// Mirrors getter.
     pub fn mirror_of(&self) -> Option<&str> {
         self.mirror_of.as_deref()
@@ -220,6 +323,11 @@ impl Cluster {
         &self.password
     }
 
+    /// Password also accepted from clients while it's being rotated.
+    pub fn next_password(&self) -> Option<&str> {
+        self.next_password.as_deref()
+    }
+
     /// User name.
     pub fn user(&self) -> &str {
         &self.user
@@ -314,6 +422,43 @@ impl Cluster {
         &self.rw_strategy
     }
 
+    /// Lowest server version reported by the shards, so drivers
+    /// don't rely on features some shards don't have.
+    pub fn server_version(&self) -> Option<String> {
+        let versions = self
+            .shards
+            .iter()
+            .flat_map(|shard| shard.pools())
+            .filter_map(|pool| pool.server_params())
+            .filter_map(|params| {
+                params
+                    .get("server_version")
+                    .and_then(|value| value.as_str())
+                    .map(|value| value.to_owned())
+            })
+            .collect::<Vec<_>>();
+
+        let lowest = versions
+            .iter()
+            .min_by_key(|version| version_number(version))?
+            .clone();
+
+        if versions
+            .iter()
+            .any(|version| version_number(version) != version_number(&lowest))
+            && !self.version_warning.swap(true, Ordering::Relaxed)
+        {
+            warn!(
+                "shards are running different server versions ({}), reporting \"{}\" [{}]",
+                versions.join(", "),
+                lowest,
+                self.name
+            );
+        }
+
+        Some(lowest)
+    }
+
     /// Launch the connection pools.
     pub(crate) fn launch(&self) {
         for shard in self.shards() {
@@ -338,11 +483,29 @@ impl Cluster {
     }
 }
 
+/// Numeric parts of a version string, e.g. "16.4 (Debian 16.4-1)" is [16, 4].
+fn version_number(version: &str) -> Vec<u32> {
+    version
+        .split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map_while(|part| {
+            let digits = part
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>();
+            digits.parse().ok()
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
     use crate::{
         backend::{Pool, Replicas, Shard, ShardedTables},
         config::{DataType, ReadWriteStrategy, ShardedTable},
+        net::Parameters,
     };
 
     use super::Cluster;
@@ -360,6 +523,7 @@ mod test {
                         data_type: DataType::Bigint,
                         centroids_path: None,
                         centroid_probes: 1,
+                        ..Default::default()
                     }],
                     vec!["sharded_omni".into()],
                     false,
@@ -390,4 +554,22 @@ mod test {
             self.rw_strategy = rw_strategy;
         }
     }
+
+    #[test]
+    fn test_server_version() {
+        let cluster = Cluster::new_test();
+        assert!(cluster.server_version().is_none());
+
+        for (shard, version) in cluster.shards().iter().zip(["16.4 (Debian)", "15.10"]) {
+            for pool in shard.pools() {
+                let mut params = Parameters::default();
+                params.insert("server_version", version);
+                pool.lock().params = Some(params);
+            }
+        }
+
+        assert_eq!(cluster.server_version().as_deref(), Some("15.10"));
+        assert_eq!(super::version_number("16.4 (Debian 16.4-1)"), vec![16, 4]);
+        assert_eq!(super::version_number("17beta1"), vec![17]);
+    }
 }
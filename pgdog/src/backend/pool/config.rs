@@ -13,6 +13,10 @@ pub struct Config {
     pub min: usize,
     /// Maximum connections allowed in the pool.
     pub max: usize,
+    /// Connections allowed above the maximum if clients are waiting.
+    pub reserve: usize,
+    /// How long clients wait before the reserve is used.
+    pub reserve_timeout: Duration, // ms
     /// How long to wait for a connection before giving up.
     pub checkout_timeout: Duration, // ms
     /// Close connections that have been idle for longer than this.
@@ -49,6 +53,17 @@ pub struct Config {
     pub pooler_mode: PoolerMode,
     /// Read only mode.
     pub read_only: bool,
+    /// Stop reading from replicas that are this far behind.
+    pub max_replica_lag: Option<Duration>,
+    /// Stop reading from replicas that are this many bytes behind.
+    pub max_replica_lag_bytes: Option<u64>,
+    /// Look for a new primary after this many failed healthchecks in a row.
+    pub failover_threshold: Option<usize>,
+    /// Replica found by topology discovery, not in the config.
+    pub discovered: bool,
+    /// Measure replication lag during healthchecks. Set for replicas,
+    /// and primaries with replicas, which report their WAL position and sync standbys.
+    pub replication_lag: bool,
 }
 
 impl Config {
@@ -62,6 +77,11 @@ impl Config {
         self.checkout_timeout
     }
 
+    /// Reserve pool timeout duration.
+    pub fn reserve_timeout(&self) -> Duration {
+        self.reserve_timeout
+    }
+
     /// Idle timeout duration.
     pub fn idle_timeout(&self) -> Duration {
         self.idle_timeout
@@ -134,6 +154,10 @@ impl Config {
             max: database
                 .pool_size
                 .unwrap_or(user.pool_size.unwrap_or(general.default_pool_size)),
+            reserve: database
+                .reserve_pool_size
+                .unwrap_or(general.reserve_pool_size),
+            reserve_timeout: Duration::from_millis(general.reserve_pool_timeout),
             healthcheck_interval: Duration::from_millis(general.healthcheck_interval),
             idle_healthcheck_interval: Duration::from_millis(general.idle_healthcheck_interval),
             idle_healthcheck_delay: Duration::from_millis(general.idle_healthcheck_delay),
@@ -151,7 +175,13 @@ impl Config {
                 .unwrap_or(user.pooler_mode.unwrap_or(general.pooler_mode)),
             connect_timeout: Duration::from_millis(general.connect_timeout),
             query_timeout: Duration::from_millis(general.query_timeout),
-            checkout_timeout: Duration::from_millis(general.checkout_timeout),
+            checkout_timeout: Duration::from_millis(
+                user.checkout_timeout.unwrap_or(
+                    database
+                        .checkout_timeout
+                        .unwrap_or(general.checkout_timeout),
+                ),
+            ),
             idle_timeout: Duration::from_millis(
                 user.idle_timeout
                     .unwrap_or(database.idle_timeout.unwrap_or(general.idle_timeout)),
@@ -159,6 +189,17 @@ impl Config {
             read_only: database
                 .read_only
                 .unwrap_or(user.read_only.unwrap_or_default()),
+            max_replica_lag: match general.max_replica_lag {
+                0 => None,
+                lag => Some(Duration::from_millis(lag)),
+            },
+            max_replica_lag_bytes: match general.max_replica_lag_bytes {
+                0 => None,
+                bytes => Some(bytes),
+            },
+            failover_threshold: general
+                .failover
+                .then_some(general.failover_threshold.max(1)),
             ..Default::default()
         }
     }
@@ -169,6 +210,8 @@ impl Default for Config {
         Self {
             min: 1,
             max: 10,
+            reserve: 0,
+            reserve_timeout: Duration::from_millis(1_000),
             checkout_timeout: Duration::from_millis(5_000),
             idle_timeout: Duration::from_millis(60_000),
             connect_timeout: Duration::from_millis(5_000),
@@ -187,6 +230,11 @@ impl Default for Config {
             replication_mode: false,
             pooler_mode: PoolerMode::default(),
             read_only: false,
+            max_replica_lag: None,
+            max_replica_lag_bytes: None,
+            failover_threshold: None,
+            discovered: false,
+            replication_lag: false,
         }
     }
 }
@@ -1,11 +1,16 @@
 //! Aggregate buffer.
 
-use std::collections::{HashMap, VecDeque};
+use std::collections::{hash_map::Entry, HashMap, VecDeque};
+use std::mem::size_of;
+
+use bigdecimal::{BigDecimal, Signed};
+use bytes::Bytes;
 
 use crate::{
+    config::config,
     frontend::router::parser::{Aggregate, AggregateFunction, AggregateTarget},
     net::{
-        messages::{DataRow, Datum},
+        messages::{Array, DataRow, DataType, Datum, Decimal, Format, Numeric},
         Decoder,
     },
 };
@@ -40,6 +45,20 @@ impl Grouping {
 struct Accumulator<'a> {
     target: &'a AggregateTarget,
     datum: Datum,
+    sum: Datum,
+    count: Datum,
+    sum_squares: Datum,
+    distinct: Option<Distinct>,
+}
+
+/// Distinct values from all shards.
+///
+/// Values are compared by their type, so `1.0` and `1` are the same NUMERIC,
+/// like in Postgres. Each value is kept as the shard returned it.
+#[derive(Debug, Default)]
+struct Distinct {
+    element_oid: i32,
+    values: HashMap<Datum, Option<Bytes>>,
 }
 
 impl<'a> Accumulator<'a> {
@@ -47,26 +66,40 @@ impl<'a> Accumulator<'a> {
         aggregate
             .targets()
             .iter()
-            .map(|target| match target.function() {
-                AggregateFunction::Count => Accumulator {
-                    target,
-                    datum: Datum::Bigint(0),
-                },
-                _ => Accumulator {
-                    target,
-                    datum: Datum::Null,
+            .map(|target| Accumulator {
+                target,
+                datum: match target.function() {
+                    AggregateFunction::Count | AggregateFunction::CountDistinct => Datum::Bigint(0),
+                    _ => Datum::Null,
                 },
+                sum: Datum::Null,
+                count: Datum::Null,
+                sum_squares: Datum::Null,
+                distinct: None,
             })
             .collect()
     }
 
     /// Transform COUNT(*), MIN, MAX, etc., from multiple shards into a single value.
-    fn accumulate(&mut self, row: &DataRow, decoder: &Decoder) -> Result<(), Error> {
+    ///
+    /// Returns the number of bytes used by new distinct values.
+    fn accumulate(&mut self, row: &DataRow, decoder: &Decoder) -> Result<usize, Error> {
+        if let Some(distinct) = self.target.distinct() {
+            return self.accumulate_distinct(row, distinct, decoder);
+        }
+
         let column = row
             .get_column(self.target.column(), decoder)?
             .ok_or(Error::DecoderRowError)?;
         match self.target.function() {
-            AggregateFunction::Count => self.datum = self.datum.clone() + column.value,
+            AggregateFunction::Count => {
+                self.datum = Self::add(self.datum.clone(), column.value)?;
+            }
+            // Adding up each shard's distinct values counts
+            // the ones on more than one shard again.
+            AggregateFunction::CountDistinct => {
+                return Err(Error::AggregateNotRewritten("count(DISTINCT)"));
+            }
             AggregateFunction::Max => {
                 if !self.datum.is_null() {
                     if self.datum < column.value {
@@ -86,25 +119,212 @@ impl<'a> Accumulator<'a> {
                 }
             }
             AggregateFunction::Sum => {
-                if !self.datum.is_null() {
-                    self.datum = self.datum.clone() + column.value;
-                } else {
-                    self.datum = column.value;
+                self.datum = Self::add(self.datum.clone(), column.value)?;
+            }
+            _ => {
+                if let Some(helpers) = self.target.helpers() {
+                    self.sum =
+                        Self::add(self.sum.clone(), Self::column(row, helpers.sum, decoder)?)?;
+                    self.count = Self::add(
+                        self.count.clone(),
+                        Self::column(row, helpers.count, decoder)?,
+                    )?;
+                    if let Some(sum_squares) = helpers.sum_squares {
+                        self.sum_squares = Self::add(
+                            self.sum_squares.clone(),
+                            Self::column(row, sum_squares, decoder)?,
+                        )?;
+                    }
                 }
             }
-            _ => (),
         }
 
-        Ok(())
+        Ok(0)
+    }
+
+    /// Remove duplicates from each shard's distinct values.
+    fn accumulate_distinct(
+        &mut self,
+        row: &DataRow,
+        column: usize,
+        decoder: &Decoder,
+    ) -> Result<usize, Error> {
+        // No rows on this shard.
+        let Some(bytes) = row.column(column).filter(|bytes| !bytes.is_empty()) else {
+            return Ok(0);
+        };
+        let array_oid = decoder.rd().field(column).map_or(0, |field| field.type_oid);
+        let format = decoder.format(column);
+        let array = Array::decode(&bytes, array_oid, format)?;
+        let data_type = DataType::from_oid(array.element_oid);
+
+        let distinct = self.distinct.get_or_insert_with(Distinct::default);
+        distinct.element_oid = array.element_oid;
+        let mut memory = 0;
+
+        for element in array.elements {
+            let value = match element {
+                None => Datum::Null,
+                // Empty strings aren't NULL.
+                Some(ref element) if element.is_empty() => match data_type {
+                    DataType::Text => Datum::Text(String::new()),
+                    _ => Datum::Unknown(Bytes::new()),
+                },
+                // Types we can't decode are compared as they are.
+                Some(ref element) => Datum::new(element, data_type, format)
+                    .unwrap_or_else(|_| Datum::Unknown(element.clone())),
+            };
+            let size = size_of::<(Datum, Option<Bytes>)>()
+                + 2 * element.as_ref().map_or(0, |element| element.len());
+            if let Entry::Vacant(entry) = distinct.values.entry(value) {
+                entry.insert(element);
+                memory += size;
+            }
+        }
+
+        Ok(memory)
+    }
+
+    /// Add values returned by two shards. NULLs are skipped, like in SUM.
+    fn add(a: Datum, b: Datum) -> Result<Datum, Error> {
+        a.checked_add(b).ok_or(Error::IntegerOutOfRange)
+    }
+
+    fn column(row: &DataRow, index: usize, decoder: &Decoder) -> Result<Datum, Error> {
+        Ok(row
+            .get_column(index, decoder)?
+            .ok_or(Error::DecoderRowError)?
+            .value)
+    }
+
+    /// Compute AVG, VARIANCE and STDDEV from the sums and counts
+    /// returned by all shards.
+    fn finish(self, format: Format) -> Result<Datum, Error> {
+        if self.target.distinct().is_some() {
+            return Self::finish_distinct(self.target.function(), self.distinct, format);
+        }
+
+        let Some(helpers) = self.target.helpers() else {
+            if self.target.function().decomposed() {
+                return Err(Error::AggregateNotRewritten(self.target.function().name()));
+            }
+            return Ok(self.datum);
+        };
+
+        // COUNT returns a BIGINT.
+        let Datum::Bigint(count) = self.count else {
+            return Ok(Datum::Null);
+        };
+        let sample = matches!(
+            self.target.function(),
+            AggregateFunction::VarSamp | AggregateFunction::StddevSamp
+        );
+        let stddev = matches!(
+            self.target.function(),
+            AggregateFunction::StddevSamp | AggregateFunction::StddevPop
+        );
+
+        // Same as Postgres: sample variance needs at least two rows.
+        if count <= sample as i64 {
+            return Ok(Datum::Null);
+        }
+
+        // SUM of floating point numbers is a float, and so are AVG, VARIANCE and STDDEV.
+        if let Datum::Numeric(sum) = self.sum {
+            let (sum, count) = (*sum, count as f64);
+            let value = if helpers.sum_squares.is_none() {
+                sum / count
+            } else {
+                let Some(sum_squares) = self.sum_squares.as_f64() else {
+                    return Ok(Datum::Null);
+                };
+                let n = if sample { count - 1.0 } else { count };
+                let variance = ((sum_squares - sum * sum / count) / n).max(0.0);
+                if stddev {
+                    variance.sqrt()
+                } else {
+                    variance
+                }
+            };
+
+            return Ok(Datum::Numeric(Numeric::from(value)));
+        }
+
+        // Integers and NUMERIC are exact.
+        let Some(sum) = self.sum.as_decimal() else {
+            return Ok(Datum::Null);
+        };
+        if helpers.sum_squares.is_none() {
+            return Ok(Datum::Decimal(sum.div(&Decimal::from(count))));
+        }
+
+        let Some(Decimal::Finite(sum_squares)) = self.sum_squares.as_decimal() else {
+            return Ok(Datum::Decimal(Decimal::NaN));
+        };
+        let Decimal::Finite(sum) = sum else {
+            return Ok(Datum::Decimal(Decimal::NaN));
+        };
+
+        // Same as Postgres: (N * SUM(x * x) - SUM(x)^2) / (N * (N - 1)).
+        let n = BigDecimal::from(count);
+        let numerator = &n * sum_squares - &sum * &sum;
+        if !numerator.is_positive() {
+            return Ok(Datum::Decimal(Decimal::from(0)));
+        }
+        let denominator = &n * BigDecimal::from(if sample { count - 1 } else { count });
+        let variance = Decimal::from(numerator).div(&Decimal::from(denominator));
+
+        Ok(Datum::Decimal(if stddev {
+            variance.sqrt()
+        } else {
+            variance
+        }))
+    }
+
+    fn finish_distinct(
+        function: &AggregateFunction,
+        distinct: Option<Distinct>,
+        format: Format,
+    ) -> Result<Datum, Error> {
+        let distinct = distinct.unwrap_or_default();
+
+        if *function == AggregateFunction::CountDistinct {
+            // COUNT ignores NULLs.
+            return Ok(Datum::Bigint(
+                distinct
+                    .values
+                    .keys()
+                    .filter(|value| !value.is_null())
+                    .count() as i64,
+            ));
+        }
+
+        // array_agg of no rows is NULL.
+        if distinct.values.is_empty() {
+            return Ok(Datum::Null);
+        }
+
+        // Postgres sorts values to remove duplicates, so we do the same. NULLs go last.
+        let mut values = distinct.values.into_iter().collect::<Vec<_>>();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let array = Array {
+            element_oid: distinct.element_oid,
+            elements: values.into_iter().map(|(_, element)| element).collect(),
+        };
+
+        Ok(Datum::Unknown(array.encode(format)?))
     }
 }
 
 #[derive(Debug)]
 pub(super) struct Aggregates<'a> {
     rows: &'a VecDeque<DataRow>,
-    mappings: HashMap<Grouping, Vec<Accumulator<'a>>>,
+    mappings: HashMap<Grouping, (&'a DataRow, Vec<Accumulator<'a>>)>,
     decoder: &'a Decoder,
     aggregate: &'a Aggregate,
+    memory: usize,
+    memory_limit: usize,
 }
 
 impl<'a> Aggregates<'a> {
@@ -118,43 +338,67 @@ impl<'a> Aggregates<'a> {
             decoder,
             mappings: HashMap::new(),
             aggregate,
+            memory: 0,
+            memory_limit: config().config.general.distinct_memory_limit,
         }
     }
 
     pub(super) fn aggregate(mut self) -> Result<VecDeque<DataRow>, Error> {
         for row in self.rows {
             let grouping = Grouping::new(row, self.aggregate.group_by(), self.decoder)?;
-            let entry = self
+            let (_, entry) = self
                 .mappings
                 .entry(grouping)
-                .or_insert_with(|| Accumulator::from_aggregate(self.aggregate));
+                .or_insert_with(|| (row, Accumulator::from_aggregate(self.aggregate)));
 
             for aggregate in entry {
-                aggregate.accumulate(row, self.decoder)?;
+                self.memory += aggregate.accumulate(row, self.decoder)?;
+            }
+
+            if self.memory_limit > 0 && self.memory > self.memory_limit {
+                return Err(Error::DistinctMemoryLimit(self.memory_limit));
             }
         }
 
         let mut rows = VecDeque::new();
-        for (grouping, accumulator) in self.mappings {
+        for (grouping, (first, accumulator)) in self.mappings {
             //
             // Aggregate rules in Postgres dictate that the only
             // columns present in the row are either:
             //
-            // 1. part of the GROUP BY, which means they are
-            //    stored in the grouping
+            // 1. part of the GROUP BY (or depend on it), which means
+            //    they are the same in all rows of the group
             // 2. are aggregate functions, which means they
             //    are stored in the accumulator
             //
-            let mut row = DataRow::new();
+            let mut row = first.clone();
+            let mut values = vec![Datum::Null; row.len()];
             for (idx, datum) in grouping.columns {
-                row.insert(idx, datum.encode(self.decoder.format(idx))?);
+                if let Some(value) = values.get_mut(idx) {
+                    *value = datum;
+                }
             }
             for acc in accumulator {
-                row.insert(
-                    acc.target.column(),
-                    acc.datum.encode(self.decoder.format(acc.target.column()))?,
-                );
+                let column = acc.target.column();
+                let datum = acc.finish(self.decoder.format(column))?;
+                if datum.is_null() {
+                    row.insert(column, Datum::Null);
+                } else {
+                    row.insert(column, datum.encode(self.decoder.format(column))?);
+                }
+                if let Some(value) = values.get_mut(column) {
+                    *value = datum;
+                }
             }
+
+            // HAVING is applied once groups from all shards are merged.
+            if let Some(having) = self.aggregate.having() {
+                if !having.matches(&values) {
+                    continue;
+                }
+            }
+
+            row.truncate(row.len().saturating_sub(self.aggregate.hidden()));
             rows.push_back(row);
         }
 
@@ -1,5 +1,7 @@
 //! Binding between frontend client and a connection on the backend.
 
+use futures::future::{select_all, try_join_all};
+
 use crate::{backend::ProtocolMessage, net::parameter::Parameters, state::State};
 
 use super::*;
@@ -80,24 +82,32 @@ impl Binding {
                     // or there are no more messages to be read.
                     loop {
                         // Return all sorted data rows if any.
-                        if let Some(message) = state.message() {
+                        if let Some(message) = state.message().await? {
                             return Ok(message);
                         }
-                        let mut read = false;
-                        for server in shards.iter_mut() {
-                            if !server.has_more_messages() {
-                                continue;
-                            }
 
-                            let message = server.read().await?;
-                            read = true;
-                            if let Some(message) = state.forward(message)? {
-                                return Ok(message);
-                            }
+                        // Read from whichever shard has data first,
+                        // so a slow shard doesn't hold up the others.
+                        let ready = shards
+                            .iter_mut()
+                            .enumerate()
+                            .filter(|(_, server)| server.has_more_messages())
+                            .map(|(shard, server)| {
+                                Box::pin(async move { server.ready().await.map(|_| shard) })
+                            })
+                            .collect::<Vec<_>>();
+
+                        if ready.is_empty() {
+                            break;
                         }
 
-                        if !read {
-                            break;
+                        let (shard, _, _) = select_all(ready).await;
+                        let shard = shard?;
+                        let message = shards[shard].read().await?;
+                        let message = state.forward_from(shard, message)?;
+                        state.spill().await?;
+                        if let Some(message) = message {
+                            return Ok(message);
                         }
                     }
 
@@ -143,10 +153,20 @@ impl Binding {
             }
 
             Binding::Admin(backend) => Ok(backend.send(messages).await?),
-            Binding::MultiShard(servers, _state) => {
-                for server in servers.iter_mut() {
-                    server.send(messages).await?;
-                }
+            Binding::MultiShard(servers, state) => {
+                let outgoing = (0..servers.len())
+                    .map(|shard| state.outgoing(shard, messages))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                try_join_all(
+                    servers
+                        .iter_mut()
+                        .zip(outgoing.iter())
+                        .map(|(server, outgoing)| {
+                            server.send(outgoing.as_ref().unwrap_or(messages))
+                        }),
+                )
+                .await?;
 
                 Ok(())
             }
@@ -161,36 +181,43 @@ impl Binding {
     }
 
     /// Send copy messages to shards they are destined to go.
+    ///
+    /// Rows are split into one buffer per shard and written to all shards
+    /// concurrently. This returns once every shard has been flushed, so the
+    /// next batch waits for the slowest shard.
     pub(super) async fn send_copy(&mut self, rows: Vec<CopyRow>) -> Result<(), Error> {
         match self {
             Binding::MultiShard(servers, _state) => {
-                for row in rows {
-                    for (shard, server) in servers.iter_mut().enumerate() {
-                        match row.shard() {
-                            Shard::Direct(row_shard) => {
-                                if shard == *row_shard {
-                                    server
-                                        .send_one(&ProtocolMessage::from(row.message()))
-                                        .await?;
-                                }
-                            }
-
-                            Shard::All => {
-                                server
-                                    .send_one(&ProtocolMessage::from(row.message()))
-                                    .await?;
-                            }
+                let mut buffers = vec![vec![]; servers.len()];
 
-                            Shard::Multi(multi) => {
-                                if multi.contains(&shard) {
-                                    server
-                                        .send_one(&ProtocolMessage::from(row.message()))
-                                        .await?;
-                                }
-                            }
+                for row in rows {
+                    for (shard, buffer) in buffers.iter_mut().enumerate() {
+                        let send = match row.shard() {
+                            Shard::Direct(row_shard) => shard == *row_shard,
+                            Shard::All => true,
+                            Shard::Multi(multi) => multi.contains(&shard),
+                        };
+
+                        if send {
+                            buffer.push(ProtocolMessage::from(row.message()));
                         }
                     }
                 }
+
+                try_join_all(
+                    servers
+                        .iter_mut()
+                        .zip(buffers)
+                        .filter(|(_, buffer)| !buffer.is_empty())
+                        .map(|(server, buffer)| async move {
+                            for message in &buffer {
+                                server.send_one(message).await?;
+                            }
+                            server.flush().await
+                        }),
+                )
+                .await?;
+
                 Ok(())
             }
 
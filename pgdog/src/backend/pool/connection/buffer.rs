@@ -1,34 +1,175 @@
 //! Buffer messages to sort and aggregate them later.
+//!
+//! Rows are kept in memory, up to `cross_shard_work_mem` per query and
+//! `cross_shard_work_mem_total` for all queries. Past that, sorted results
+//! are written to temporary files in sorted runs and merged when read back.
+//! The files are only readable by us and are deleted as soon as they're created.
 
-use std::{cmp::Ordering, collections::VecDeque};
+use std::{
+    cmp::Ordering,
+    collections::{HashSet, VecDeque},
+    io::{BufWriter, ErrorKind, Seek, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
+use bytes::BytesMut;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, BufReader},
+    task::spawn_blocking,
+};
 
 use crate::{
-    frontend::router::parser::{Aggregate, OrderBy},
+    config::{config, Collation},
+    frontend::router::{
+        parser::{Aggregate, Distinct, DistinctColumn, Limit, OrderBy},
+        Route,
+    },
     net::{
-        messages::{DataRow, FromBytes, Message, Protocol, ToBytes, Vector},
+        messages::{DataRow, Datum, FromBytes, Message, Protocol, ToBytes, Vector},
         Decoder,
     },
 };
 
 use super::Aggregates;
 
+static BUFFERED: AtomicUsize = AtomicUsize::new(0);
+static SPILLS: AtomicUsize = AtomicUsize::new(0);
+static SPILLED: AtomicUsize = AtomicUsize::new(0);
+static EXCEEDED: AtomicUsize = AtomicUsize::new(0);
+
+/// Cross-shard buffer statistics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stats {
+    /// Bytes buffered in memory right now.
+    pub buffered: usize,
+    /// Sorted runs written to temporary files.
+    pub spills: usize,
+    /// Bytes written to temporary files.
+    pub spilled: usize,
+    /// Queries that ran out of memory and couldn't spill.
+    pub exceeded: usize,
+}
+
+/// Get cross-shard buffer statistics.
+pub fn stats() -> Stats {
+    Stats {
+        buffered: BUFFERED.load(AtomicOrdering::Relaxed),
+        spills: SPILLS.load(AtomicOrdering::Relaxed),
+        spilled: SPILLED.load(AtomicOrdering::Relaxed),
+        exceeded: EXCEEDED.load(AtomicOrdering::Relaxed),
+    }
+}
+
 /// Sort and aggregate rows received from multiple shards.
 #[derive(Default, Debug)]
 pub(super) struct Buffer {
     buffer: VecDeque<DataRow>,
     full: bool,
+    /// Bytes held in memory.
+    bytes: usize,
+    /// Sorted rows written to temporary files.
+    runs: Vec<Run>,
+    /// Merges the runs with rows still in memory.
+    sorter: Option<Sorter>,
+    /// LIMIT and OFFSET, applied while merging.
+    limit: Option<Limit>,
+    /// Over the memory limit, rows should be written to disk.
+    spill: bool,
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.release();
+    }
 }
 
 impl Buffer {
     /// Add message to buffer.
     pub(super) fn add(&mut self, message: Message) -> Result<(), super::Error> {
-        let dr = DataRow::from_bytes(message.to_bytes()?)?;
+        let bytes = message.to_bytes()?;
+        self.bytes += bytes.len();
+        BUFFERED.fetch_add(bytes.len(), AtomicOrdering::Relaxed);
+
+        let dr = DataRow::from_bytes(bytes)?;
 
         self.buffer.push_back(dr);
 
         Ok(())
     }
 
+    /// Keep the buffer within memory limits. Rows are written to disk
+    /// with [`Buffer::spill`], if allowed.
+    pub(super) fn work_mem(&mut self, route: &Route) -> Result<(), super::Error> {
+        let config = config();
+        let general = &config.config.general;
+
+        let limit = match (
+            general.cross_shard_work_mem,
+            general.cross_shard_work_mem_total,
+        ) {
+            (Some(limit), _) if self.bytes > limit => limit,
+            (_, Some(limit)) if BUFFERED.load(AtomicOrdering::Relaxed) > limit => limit,
+            _ => return Ok(()),
+        };
+
+        // Aggregates and DISTINCT need all rows at once.
+        if !general.cross_shard_spill || !route.aggregate().is_empty() || route.distinct().is_some()
+        {
+            EXCEEDED.fetch_add(1, AtomicOrdering::Relaxed);
+            return Err(super::Error::WorkMem(limit));
+        }
+
+        self.spill = true;
+
+        Ok(())
+    }
+
+    /// Write rows to disk if [`Buffer::work_mem`] ran out of memory.
+    pub(super) async fn spill(
+        &mut self,
+        columns: &[OrderBy],
+        decoder: &Decoder,
+    ) -> Result<(), super::Error> {
+        if !self.spill {
+            return Ok(());
+        }
+
+        let dir = config()
+            .config
+            .general
+            .temp_dir
+            .clone()
+            .unwrap_or_else(std::env::temp_dir);
+        self.write_run(columns, decoder, &dir).await
+    }
+
+    /// Sort rows in memory and write them to a temporary file.
+    async fn write_run(
+        &mut self,
+        columns: &[OrderBy],
+        decoder: &Decoder,
+        dir: &Path,
+    ) -> Result<(), super::Error> {
+        let sorter = Sorter::new(columns, decoder);
+        self.buffer
+            .make_contiguous()
+            .sort_by(|a, b| sorter.compare(a, b));
+        self.runs
+            .push(Run::write(std::mem::take(&mut self.buffer), dir.to_owned()).await?);
+        self.release();
+        self.spill = false;
+
+        Ok(())
+    }
+
+    /// Stop counting rows in memory against the limits.
+    fn release(&mut self) {
+        BUFFERED.fetch_sub(self.bytes, AtomicOrdering::Relaxed);
+        self.bytes = 0;
+    }
+
     /// Mark the buffer as full. It will start returning messages now.
     /// Caller is responsible for sorting the buffer if needed.
     pub(super) fn full(&mut self) {
@@ -38,83 +179,47 @@ impl Buffer {
     pub(super) fn reset(&mut self) {
         self.buffer.clear();
         self.full = false;
+        self.runs.clear();
+        self.sorter = None;
+        self.limit = None;
+        self.spill = false;
+        self.release();
     }
 
     /// Sort the buffer.
     pub(super) fn sort(&mut self, columns: &[OrderBy], decoder: &Decoder) {
-        // Calculate column indices once, since
-        // fetching indices by name is O(number of columns).
-        let mut cols = vec![];
-        for column in columns {
-            match column {
-                OrderBy::Asc(_) => cols.push(column.clone()),
-                OrderBy::AscColumn(name) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::Asc(index + 1));
-                    }
-                }
-                OrderBy::Desc(_) => cols.push(column.clone()),
-                OrderBy::DescColumn(name) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::Desc(index + 1));
-                    }
-                }
-                OrderBy::AscVectorL2(_, _) => cols.push(column.clone()),
-                OrderBy::AscVectorL2Column(name, vector) => {
-                    if let Some(index) = decoder.rd().field_index(name) {
-                        cols.push(OrderBy::AscVectorL2(index + 1, vector.clone()));
-                    }
-                }
-            };
-        }
-
-        // Sort rows.
-        let order_by = move |a: &DataRow, b: &DataRow| -> Ordering {
-            for col in cols.iter() {
-                let index = col.index();
-                let asc = col.asc();
-                let index = if let Some(index) = index {
-                    index
-                } else {
-                    continue;
-                };
-                let left = a.get_column(index, decoder);
-                let right = b.get_column(index, decoder);
-
-                let ordering = match (left, right) {
-                    (Ok(Some(left)), Ok(Some(right))) => {
-                        // Handle the special vector case.
-                        if let OrderBy::AscVectorL2(_, vector) = col {
-                            let left: Option<Vector> = left.value.try_into().ok();
-                            let right: Option<Vector> = right.value.try_into().ok();
-
-                            if let (Some(left), Some(right)) = (left, right) {
-                                let left = left.distance_l2(vector);
-                                let right = right.distance_l2(vector);
-
-                                left.partial_cmp(&right)
-                            } else {
-                                Some(Ordering::Equal)
-                            }
-                        } else if asc {
-                            left.value.partial_cmp(&right.value)
-                        } else {
-                            right.value.partial_cmp(&left.value)
-                        }
-                    }
-
-                    _ => Some(Ordering::Equal),
-                };
+        let sorter = Sorter::new(columns, decoder);
+        self.buffer
+            .make_contiguous()
+            .sort_by(|a, b| sorter.compare(a, b));
 
-                if ordering != Some(Ordering::Equal) {
-                    return ordering.unwrap_or(Ordering::Equal);
-                }
-            }
+        // Rows on disk are merged as they are read.
+        if !self.runs.is_empty() {
+            self.sorter = Some(sorter);
+        }
+    }
 
-            Ordering::Equal
+    /// Compare two values like Postgres would.
+    fn compare(left: &Datum, right: &Datum, order_by: &OrderBy, collation: Collation) -> Ordering {
+        let ordering = match (left, right) {
+            (Datum::Null, Datum::Null) => return Ordering::Equal,
+            // NULLS FIRST/LAST doesn't depend on the sort direction.
+            (Datum::Null, _) if order_by.nulls_first() => return Ordering::Less,
+            (Datum::Null, _) => return Ordering::Greater,
+            (_, Datum::Null) if order_by.nulls_first() => return Ordering::Greater,
+            (_, Datum::Null) => return Ordering::Less,
+            (Datum::Text(left), Datum::Text(right)) => order_by
+                .collation()
+                .unwrap_or(collation)
+                .compare(left, right),
+            _ => left.partial_cmp(right).unwrap_or(Ordering::Equal),
         };
 
-        self.buffer.make_contiguous().sort_by(order_by);
+        if order_by.asc() {
+            ordering
+        } else {
+            ordering.reverse()
+        }
     }
 
     /// Execute aggregate functions.
@@ -135,29 +240,151 @@ impl Buffer {
             self.buffer = buffer;
         } else {
             let aggregates = Aggregates::new(&buffer, decoder, aggregate);
-            let result = aggregates.aggregate()?;
+            // HAVING can remove all groups.
+            self.buffer = aggregates.aggregate()?;
+        }
 
-            if !result.is_empty() {
-                self.buffer = result;
-            } else {
-                self.buffer = buffer;
+        Ok(())
+    }
+
+    /// Remove duplicate rows, keeping the first one.
+    ///
+    /// Rows are compared using their decoded values, so the buffer
+    /// should be sorted first for `DISTINCT ON` to keep the right one.
+    pub(super) fn distinct(
+        &mut self,
+        distinct: Option<&Distinct>,
+        decoder: &Decoder,
+    ) -> Result<(), super::Error> {
+        let columns = match distinct {
+            None => return Ok(()),
+            Some(Distinct::Row) => (0..decoder.rd().fields.len()).collect::<Vec<_>>(),
+            Some(Distinct::On(columns)) => {
+                let mut indices = vec![];
+                for column in columns {
+                    let index = match column {
+                        DistinctColumn::Index(index) => Some(*index),
+                        DistinctColumn::Name(name) => decoder.rd().field_index(name),
+                    };
+                    // Can't tell rows apart without the column.
+                    let Some(index) = index else {
+                        return Ok(());
+                    };
+                    indices.push(index);
+                }
+                indices
+            }
+        };
+
+        let mut seen = HashSet::new();
+        let mut buffer = VecDeque::with_capacity(self.buffer.len());
+
+        for row in std::mem::take(&mut self.buffer) {
+            let mut values = Vec::with_capacity(columns.len());
+            for index in &columns {
+                values.push(row.get_column(*index, decoder)?.map(|column| column.value));
+            }
+
+            if seen.insert(values) {
+                buffer.push_back(row);
             }
         }
 
+        self.buffer = buffer;
+
         Ok(())
     }
 
+    /// Apply OFFSET and LIMIT to the merged rows.
+    pub(super) fn limit(&mut self, limit: Option<Limit>) {
+        let Some(limit) = limit else {
+            return;
+        };
+
+        // Applied while merging rows from disk.
+        if !self.runs.is_empty() {
+            self.limit = Some(limit);
+            return;
+        }
+
+        let offset = limit.offset.min(self.buffer.len());
+        self.buffer.drain(..offset);
+
+        if let Some(limit) = limit.limit {
+            self.buffer.truncate(limit);
+        }
+    }
+
     /// Take messages from buffer.
-    pub(super) fn take(&mut self) -> Option<Message> {
-        if self.full {
-            self.buffer.pop_front().and_then(|s| s.message().ok())
+    pub(super) async fn take(&mut self) -> Result<Option<Message>, super::Error> {
+        if !self.full {
+            return Ok(None);
+        }
+
+        let row = if self.runs.is_empty() {
+            self.buffer.pop_front()
         } else {
-            None
+            self.merge().await?
+        };
+
+        match row {
+            Some(row) => Ok(Some(row.message()?)),
+            None => {
+                // Rows from the next statement wait until it's complete too.
+                self.reset();
+                Ok(None)
+            }
+        }
+    }
+
+    /// Next row from the runs on disk and rows in memory, in sorted order.
+    async fn merge(&mut self) -> Result<Option<DataRow>, super::Error> {
+        loop {
+            if self.limit.is_some_and(|limit| limit.limit == Some(0)) {
+                return Ok(None);
+            }
+
+            let sorter = self.sorter.get_or_insert_with(Sorter::default);
+            let mut next: Option<(Option<usize>, &DataRow)> =
+                self.buffer.front().map(|row| (None, row));
+            for (index, run) in self.runs.iter().enumerate() {
+                if let Some(ref row) = run.next {
+                    if next.is_none_or(|(_, next)| sorter.compare(row, next) == Ordering::Less) {
+                        next = Some((Some(index), row));
+                    }
+                }
+            }
+
+            let row = match next {
+                None => return Ok(None),
+                Some((None, _)) => self.buffer.pop_front(),
+                Some((Some(index), _)) => self.runs[index].next().await?,
+            };
+
+            if let Some(ref mut limit) = self.limit {
+                if limit.offset > 0 {
+                    limit.offset -= 1;
+                    continue;
+                }
+                if let Some(ref mut remaining) = limit.limit {
+                    *remaining -= 1;
+                }
+            }
+
+            return Ok(row);
         }
     }
 
     pub(super) fn len(&self) -> usize {
-        self.buffer.len()
+        let len = self.buffer.len() + self.runs.iter().map(|run| run.rows).sum::<usize>();
+
+        match self.limit {
+            Some(limit) => {
+                let len = len.saturating_sub(limit.offset);
+                limit.limit.map(|limit| limit.min(len)).unwrap_or(len)
+            }
+            None => len,
+        }
     }
 
     #[allow(dead_code)]
@@ -166,16 +393,196 @@ impl Buffer {
     }
 }
 
+/// ORDER BY columns resolved to their positions in the row.
+#[derive(Debug, Default)]
+struct Sorter {
+    columns: Vec<OrderBy>,
+    collation: Collation,
+    decoder: Decoder,
+}
+
+impl Sorter {
+    fn new(columns: &[OrderBy], decoder: &Decoder) -> Self {
+        // Calculate column indices once, since
+        // fetching indices by name is O(number of columns).
+        let mut cols = vec![];
+        for column in columns {
+            match column {
+                OrderBy::Asc(_, _) => cols.push(column.clone()),
+                OrderBy::AscColumn(name, options) => {
+                    if let Some(index) = decoder.rd().field_index(name) {
+                        cols.push(OrderBy::Asc(index + 1, options.clone()));
+                    }
+                }
+                OrderBy::Desc(_, _) => cols.push(column.clone()),
+                OrderBy::DescColumn(name, options) => {
+                    if let Some(index) = decoder.rd().field_index(name) {
+                        cols.push(OrderBy::Desc(index + 1, options.clone()));
+                    }
+                }
+                OrderBy::AscVectorL2(_, _) => cols.push(column.clone()),
+                OrderBy::AscVectorL2Column(name, vector) => {
+                    if let Some(index) = decoder.rd().field_index(name) {
+                        cols.push(OrderBy::AscVectorL2(index + 1, vector.clone()));
+                    }
+                }
+            };
+        }
+
+        Self {
+            columns: cols,
+            collation: config().config.general.collation,
+            decoder: decoder.clone(),
+        }
+    }
+
+    fn compare(&self, a: &DataRow, b: &DataRow) -> Ordering {
+        for col in self.columns.iter() {
+            let index = col.index();
+            let index = if let Some(index) = index {
+                index
+            } else {
+                continue;
+            };
+            let left = a.get_column(index, &self.decoder);
+            let right = b.get_column(index, &self.decoder);
+
+            let ordering = match (left, right) {
+                (Ok(Some(left)), Ok(Some(right))) => {
+                    // Handle the special vector case.
+                    if let OrderBy::AscVectorL2(_, vector) = col {
+                        let left: Option<Vector> = left.value.try_into().ok();
+                        let right: Option<Vector> = right.value.try_into().ok();
+
+                        if let (Some(left), Some(right)) = (left, right) {
+                            let left = left.distance_l2(vector);
+                            let right = right.distance_l2(vector);
+
+                            left.partial_cmp(&right)
+                        } else {
+                            Some(Ordering::Equal)
+                        }
+                    } else if let Some(ordering) =
+                        self.compare_enum(index, &left.value, &right.value)
+                    {
+                        Some(if col.asc() {
+                            ordering
+                        } else {
+                            ordering.reverse()
+                        })
+                    } else {
+                        Some(Buffer::compare(
+                            &left.value,
+                            &right.value,
+                            col,
+                            self.collation,
+                        ))
+                    }
+                }
+
+                _ => Some(Ordering::Equal),
+            };
+
+            if ordering != Some(Ordering::Equal) {
+                return ordering.unwrap_or(Ordering::Equal);
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Enums are sorted in the order their labels were created in.
+    fn compare_enum(&self, index: usize, left: &Datum, right: &Datum) -> Option<Ordering> {
+        let (Datum::Text(left), Datum::Text(right)) = (left, right) else {
+            return None;
+        };
+        let left = self.decoder.enum_order(index, left)?;
+        let right = self.decoder.enum_order(index, right)?;
+
+        Some(left.cmp(&right))
+    }
+}
+
+/// Sorted rows written to a temporary file.
+#[derive(Debug)]
+struct Run {
+    reader: BufReader<File>,
+    /// Rows in the file.
+    rows: usize,
+    /// Next row to merge.
+    next: Option<DataRow>,
+}
+
+impl Run {
+    async fn write(rows: VecDeque<DataRow>, dir: PathBuf) -> Result<Self, super::Error> {
+        let len = rows.len();
+        let (file, bytes) = spawn_blocking(move || -> Result<_, super::Error> {
+            // Created with mode 0600 and deleted right away,
+            // so it's cleaned up even if we crash.
+            let mut writer = BufWriter::new(tempfile::tempfile_in(dir)?);
+            let mut bytes = 0;
+            for row in &rows {
+                let row = row.to_bytes()?;
+                bytes += row.len();
+                writer.write_all(&row)?;
+            }
+            let mut file = writer.into_inner().map_err(|err| err.into_error())?;
+            file.rewind()?;
+            Ok((file, bytes))
+        })
+        .await
+        .map_err(std::io::Error::other)??;
+
+        SPILLS.fetch_add(1, AtomicOrdering::Relaxed);
+        SPILLED.fetch_add(bytes, AtomicOrdering::Relaxed);
+
+        let mut run = Self {
+            reader: BufReader::new(File::from_std(file)),
+            rows: len,
+            next: None,
+        };
+        run.next().await?;
+
+        Ok(run)
+    }
+
+    /// Return the current row and read the one after it.
+    async fn next(&mut self) -> Result<Option<DataRow>, super::Error> {
+        let mut header = [0u8; 5];
+        let next = match self.reader.read_exact(&mut header).await {
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => None,
+            Err(err) => return Err(err.into()),
+            Ok(_) => {
+                let len = i32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+                let mut row = BytesMut::from(&header[..]);
+                row.resize(len as usize + 1, 0);
+                self.reader.read_exact(&mut row[5..]).await?;
+                Some(DataRow::from_bytes(row.freeze())?)
+            }
+        };
+
+        Ok(std::mem::replace(&mut self.next, next))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::net::{Field, Format, RowDescription};
+    use crate::frontend::router::parser::SortOptions;
+    use crate::net::{
+        messages::{Array, Decimal, FromDataType},
+        Field, Format, RowDescription,
+    };
+    use bytes::Bytes;
 
-    #[test]
-    fn test_sort_buffer() {
+    #[tokio::test]
+    async fn test_sort_buffer() {
         let mut buf = Buffer::default();
         let rd = RowDescription::new(&[Field::bigint("one"), Field::text("two")]);
-        let columns = [OrderBy::Asc(1), OrderBy::Desc(2)];
+        let columns = [
+            OrderBy::Asc(1, SortOptions::default()),
+            OrderBy::Desc(2, SortOptions::default()),
+        ];
 
         for i in 0..25_i64 {
             let mut dr = DataRow::new();
@@ -189,7 +596,7 @@ mod test {
         buf.full();
 
         let mut i = 1;
-        while let Some(message) = buf.take() {
+        while let Some(message) = buf.take().await.unwrap() {
             let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
             let one = dr.get::<i64>(0, Format::Text).unwrap();
             let two = dr.get::<String>(1, Format::Text).unwrap();
@@ -201,8 +608,166 @@ mod test {
         assert_eq!(i, 26);
     }
 
-    #[test]
-    fn test_aggregate_buffer() {
+    #[tokio::test]
+    async fn test_spill_buffer() {
+        let mut buf = Buffer::default();
+        let rd = RowDescription::new(&[Field::bigint("id")]);
+        let decoder = Decoder::from(&rd);
+        let columns = [OrderBy::Asc(1, SortOptions::default())];
+        let dir = std::env::temp_dir();
+
+        // Two runs on disk and some rows still in memory.
+        for run in [vec![5, 1, 9, 3], vec![4, 8, 2]] {
+            for id in run {
+                let mut dr = DataRow::new();
+                dr.add(id as i64);
+                buf.add(dr.message().unwrap()).unwrap();
+            }
+            buf.write_run(&columns, &decoder, &dir).await.unwrap();
+            assert_eq!(buf.bytes, 0);
+        }
+        for id in [7_i64, 6] {
+            let mut dr = DataRow::new();
+            dr.add(id);
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+
+        buf.sort(&columns, &decoder);
+        buf.limit(Some(Limit {
+            limit: Some(5),
+            offset: 2,
+        }));
+        buf.full();
+        assert_eq!(buf.len(), 5);
+
+        let mut ids = vec![];
+        while let Some(message) = buf.take().await.unwrap() {
+            let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
+            ids.push(dr.get::<i64>(0, Format::Text).unwrap());
+        }
+        assert_eq!(ids, vec![3, 4, 5, 6, 7]);
+        assert!(buf.runs.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sort_buffer_nulls_collation() {
+        let rd = RowDescription::new(&[Field::text("name")]);
+        let decoder = Decoder::from(&rd);
+        let sorted = |order_by: OrderBy| async {
+            let mut buf = Buffer::default();
+            for name in [Some("b"), None, Some("B"), Some("a"), Some("_c")] {
+                let mut dr = DataRow::new();
+                match name {
+                    Some(name) => dr.add(name),
+                    None => dr.add(Datum::Null),
+                };
+                buf.add(dr.message().unwrap()).unwrap();
+            }
+            buf.sort(&[order_by], &decoder);
+            buf.full();
+
+            let mut names = vec![];
+            while let Some(message) = buf.take().await.unwrap() {
+                let dr = DataRow::from_bytes(message.to_bytes().unwrap()).unwrap();
+                names.push(String::from_utf8_lossy(&dr.column(0).unwrap()).to_string());
+            }
+            names
+        };
+
+        assert_eq!(
+            sorted(OrderBy::Asc(1, SortOptions::default())).await,
+            ["B", "_c", "a", "b", ""]
+        );
+        assert_eq!(
+            sorted(OrderBy::Desc(1, SortOptions::default())).await,
+            ["", "b", "a", "_c", "B"]
+        );
+        assert_eq!(
+            sorted(OrderBy::AscColumn(
+                "name".into(),
+                SortOptions {
+                    nulls_first: Some(true),
+                    collation: Some(Collation::Locale),
+                }
+            ))
+            .await,
+            ["", "a", "b", "B", "_c"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limit_buffer() {
+        let mut buf = Buffer::default();
+        let rd = RowDescription::new(&[Field::bigint("id")]);
+
+        for i in 0..25_i64 {
+            let mut dr = DataRow::new();
+            dr.add(25 - i);
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+
+        buf.sort(
+            &[OrderBy::Asc(1, SortOptions::default())],
+            &Decoder::from(&rd),
+        );
+        buf.limit(Some(Limit {
+            limit: Some(10),
+            offset: 20,
+        }));
+        buf.full();
+
+        assert_eq!(buf.len(), 5);
+        let row = buf.take().await.unwrap().unwrap();
+        let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
+        assert_eq!(dr.get::<i64>(0, Format::Text).unwrap(), 21);
+
+        buf.limit(Some(Limit {
+            limit: None,
+            offset: 100,
+        }));
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_distinct_buffer() {
+        let rd = RowDescription::new(&[Field::bigint("id"), Field::text("email")]);
+        let decoder = Decoder::from(&rd);
+        let rows = [
+            (2_i64, "b@test.com"),
+            (1, "a@test.com"),
+            (2, "b@test.com"),
+            (3, "a@test.com"),
+        ];
+
+        let mut buf = Buffer::default();
+        for (id, email) in rows {
+            let mut dr = DataRow::new();
+            dr.add(id).add(email);
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+
+        buf.distinct(Some(&Distinct::Row), &decoder).unwrap();
+        assert_eq!(buf.len(), 3);
+
+        buf.sort(&[OrderBy::Asc(1, SortOptions::default())], &decoder);
+        buf.distinct(
+            Some(&Distinct::On(vec![DistinctColumn::Name("email".into())])),
+            &decoder,
+        )
+        .unwrap();
+        buf.full();
+
+        assert_eq!(buf.len(), 2);
+        for (id, email) in [(1, "a@test.com"), (2, "b@test.com")] {
+            let row = buf.take().await.unwrap().unwrap();
+            let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
+            assert_eq!(dr.get::<i64>(0, Format::Text).unwrap(), id);
+            assert_eq!(dr.get::<String>(1, Format::Text).unwrap(), email);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_buffer() {
         let mut buf = Buffer::default();
         let rd = RowDescription::new(&[Field::bigint("count")]);
         let agg = Aggregate::new_count(0);
@@ -217,14 +782,14 @@ mod test {
         buf.full();
 
         assert_eq!(buf.len(), 1);
-        let row = buf.take().unwrap();
+        let row = buf.take().await.unwrap().unwrap();
         let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
         let count = dr.get::<i64>(0, Format::Text).unwrap();
         assert_eq!(count, 15 * 6);
     }
 
-    #[test]
-    fn test_aggregate_buffer_group_by() {
+    #[tokio::test]
+    async fn test_aggregate_buffer_group_by() {
         let mut buf = Buffer::default();
         let rd = RowDescription::new(&[Field::bigint("count"), Field::text("email")]);
         let agg = Aggregate::new_count_group_by(0, &[1]);
@@ -244,10 +809,288 @@ mod test {
 
         assert_eq!(buf.len(), 2);
         for _ in &emails {
-            let row = buf.take().unwrap();
+            let row = buf.take().await.unwrap().unwrap();
             let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
             let count = dr.get::<i64>(0, Format::Text).unwrap();
             assert_eq!(count, 15 * 6);
         }
     }
+
+    #[tokio::test]
+    async fn test_aggregate_buffer_avg() {
+        let ast = pg_query::parse("SELECT avg(price), stddev_samp(price) FROM sharded").unwrap();
+        let Some(pg_query::NodeEnum::SelectStmt(ref stmt)) =
+            ast.protobuf.stmts[0].stmt.as_ref().unwrap().node
+        else {
+            panic!("not a select");
+        };
+        let mut agg = Aggregate::parse(stmt).unwrap();
+        agg.rewrite(&mut stmt.as_ref().clone()).unwrap();
+
+        let mut buf = Buffer::default();
+        let rd = RowDescription::new(&[
+            Field::numeric("avg"),
+            Field::numeric("stddev_samp"),
+            Field::numeric("sum"),
+            Field::bigint("count"),
+            Field::numeric("sum"),
+            Field::bigint("count"),
+            Field::numeric("sum"),
+        ]);
+
+        // Shard 1 has 1, 2, 3, shard 2 has 10.
+        for (avg, stddev, sum, count, sum_squares) in
+            [("2", "1", "6", 3_i64, "14"), ("10", "", "10", 1, "100")]
+        {
+            let mut dr = DataRow::new();
+            dr.add(avg)
+                .add(stddev)
+                .add(sum)
+                .add(count)
+                .add(sum)
+                .add(count)
+                .add(sum_squares);
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+
+        buf.aggregate(&agg, &Decoder::from(&rd)).unwrap();
+        buf.full();
+
+        assert_eq!(buf.len(), 1);
+        let row = buf.take().await.unwrap().unwrap();
+        let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
+        assert_eq!(dr.len(), 2);
+        // Same as Postgres.
+        let avg = dr.get::<String>(0, Format::Text).unwrap();
+        assert_eq!(avg, "4.0000000000000000");
+        let stddev = dr.get::<String>(1, Format::Text).unwrap();
+        assert_eq!(stddev, "4.0824829046386302");
+
+        // Without the sums and counts, there is nothing to compute it from.
+        let agg = Aggregate::parse(stmt).unwrap();
+        let mut buf = Buffer::default();
+        let mut dr = DataRow::new();
+        dr.add("2").add("1");
+        buf.add(dr.message().unwrap()).unwrap();
+        assert!(matches!(
+            buf.aggregate(&agg, &Decoder::from(&rd)),
+            Err(crate::backend::Error::AggregateNotRewritten("avg"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_buffer_sum() {
+        let ast = pg_query::parse("SELECT sum(id), avg(id) FROM sharded").unwrap();
+        let Some(pg_query::NodeEnum::SelectStmt(ref stmt)) =
+            ast.protobuf.stmts[0].stmt.as_ref().unwrap().node
+        else {
+            panic!("not a select");
+        };
+        let mut agg = Aggregate::parse(stmt).unwrap();
+        agg.rewrite(&mut stmt.as_ref().clone()).unwrap();
+
+        let mut buf = Buffer::default();
+        let rd = RowDescription::new(&[
+            Field::numeric("sum"),
+            Field::numeric("avg"),
+            Field::numeric("sum"),
+            Field::bigint("count"),
+        ]);
+
+        // Too large to add up as floats.
+        for _ in 0..2 {
+            let mut dr = DataRow::new();
+            dr.add("9007199254740993")
+                .add("9007199254740993.0000000000000000")
+                .add("9007199254740993")
+                .add(1_i64);
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+
+        buf.aggregate(&agg, &Decoder::from(&rd)).unwrap();
+        buf.full();
+
+        let row = buf.take().await.unwrap().unwrap();
+        let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            dr.get::<String>(0, Format::Text).unwrap(),
+            "18014398509481986"
+        );
+        assert_eq!(
+            dr.get::<String>(1, Format::Text).unwrap(),
+            "9007199254740993.0000"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_buffer_distinct() {
+        let ast =
+            pg_query::parse("SELECT count(DISTINCT email), array_agg(DISTINCT id) FROM sharded")
+                .unwrap();
+        let Some(pg_query::NodeEnum::SelectStmt(ref stmt)) =
+            ast.protobuf.stmts[0].stmt.as_ref().unwrap().node
+        else {
+            panic!("not a select");
+        };
+        let mut agg = Aggregate::parse(stmt).unwrap();
+        agg.rewrite(&mut stmt.as_ref().clone()).unwrap();
+
+        let mut buf = Buffer::default();
+        let rd = RowDescription::new(&[
+            Field::bigint("count"),
+            Field {
+                type_oid: 1016,
+                ..Field::text("array_agg")
+            },
+            Field {
+                type_oid: 1009,
+                ..Field::text("array_agg")
+            },
+        ]);
+
+        for (count, ids, emails) in [
+            (2_i64, "{2,10}", "{a@test.com,b@test.com}"),
+            (3, "{1,2,NULL}", "{b@test.com,c@test.com,NULL}"),
+        ] {
+            let mut dr = DataRow::new();
+            dr.add(count).add(ids).add(emails);
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+
+        buf.aggregate(&agg, &Decoder::from(&rd)).unwrap();
+        buf.full();
+
+        assert_eq!(buf.len(), 1);
+        let row = buf.take().await.unwrap().unwrap();
+        let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
+        assert_eq!(dr.len(), 2);
+        assert_eq!(dr.get::<i64>(0, Format::Text).unwrap(), 3);
+        assert_eq!(dr.get::<String>(1, Format::Text).unwrap(), "{1,2,10,NULL}");
+
+        // The distinct values are needed to count them.
+        let agg = Aggregate::parse(stmt).unwrap();
+        let mut buf = Buffer::default();
+        let mut dr = DataRow::new();
+        dr.add(2_i64).add("{2,10}");
+        buf.add(dr.message().unwrap()).unwrap();
+        assert!(matches!(
+            buf.aggregate(&agg, &Decoder::from(&rd)),
+            Err(crate::backend::Error::AggregateNotRewritten(
+                "count(DISTINCT)"
+            ))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_buffer_distinct_types() {
+        let ast =
+            pg_query::parse("SELECT count(DISTINCT price), array_agg(DISTINCT id) FROM sharded")
+                .unwrap();
+        let Some(pg_query::NodeEnum::SelectStmt(ref stmt)) =
+            ast.protobuf.stmts[0].stmt.as_ref().unwrap().node
+        else {
+            panic!("not a select");
+        };
+        let mut agg = Aggregate::parse(stmt).unwrap();
+        agg.rewrite(&mut stmt.as_ref().clone()).unwrap();
+
+        let mut buf = Buffer::default();
+        let rd = RowDescription::new(&[
+            Field::bigint("count"),
+            Field {
+                type_oid: 1007,
+                format: 1,
+                ..Field::text("array_agg")
+            },
+            Field {
+                type_oid: 1231,
+                format: 1,
+                ..Field::text("array_agg")
+            },
+        ]);
+
+        // 1.0 and 1 are the same NUMERIC.
+        for (count, ids, prices) in [
+            (2_i64, vec![2, 10], vec!["1.0", "2"]),
+            (2, vec![10, 1], vec!["1", "2.50"]),
+        ] {
+            let ids = Array {
+                element_oid: 23,
+                elements: ids
+                    .into_iter()
+                    .map(|id: i32| Some(Bytes::copy_from_slice(&id.to_be_bytes())))
+                    .collect(),
+            };
+            let prices = Array {
+                element_oid: 1700,
+                elements: prices
+                    .into_iter()
+                    .map(|price| {
+                        Some(
+                            Decimal::decode(price.as_bytes(), Format::Text)
+                                .unwrap()
+                                .encode(Format::Binary)
+                                .unwrap(),
+                        )
+                    })
+                    .collect(),
+            };
+            let mut dr = DataRow::new();
+            dr.add(count)
+                .add(ids.encode(Format::Binary).unwrap())
+                .add(prices.encode(Format::Binary).unwrap());
+            buf.add(dr.message().unwrap()).unwrap();
+        }
+
+        buf.aggregate(&agg, &Decoder::from(&rd)).unwrap();
+        buf.full();
+
+        let row = buf.take().await.unwrap().unwrap();
+        let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
+        assert_eq!(dr.len(), 2);
+        assert_eq!(dr.get::<i64>(0, Format::Text).unwrap(), 3);
+        let ids = Array::decode(&dr.column(1).unwrap(), 1007, Format::Binary).unwrap();
+        assert_eq!(
+            ids.elements,
+            [1_i32, 2, 10]
+                .into_iter()
+                .map(|id| Some(Bytes::copy_from_slice(&id.to_be_bytes())))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aggregate_buffer_having() {
+        let ast = pg_query::parse(
+            "SELECT email, count(*) FROM sharded GROUP BY email HAVING count(*) > 10",
+        )
+        .unwrap();
+        let Some(pg_query::NodeEnum::SelectStmt(ref stmt)) =
+            ast.protobuf.stmts[0].stmt.as_ref().unwrap().node
+        else {
+            panic!("not a select");
+        };
+        let mut agg = Aggregate::parse(stmt).unwrap();
+        agg.rewrite(&mut stmt.as_ref().clone()).unwrap();
+
+        let mut buf = Buffer::default();
+        let rd = RowDescription::new(&[Field::text("email"), Field::bigint("count")]);
+
+        for _ in 0..2 {
+            for (email, count) in [("a@test.com", 6_i64), ("b@test.com", 3)] {
+                let mut dr = DataRow::new();
+                dr.add(email).add(count);
+                buf.add(dr.message().unwrap()).unwrap();
+            }
+        }
+
+        buf.aggregate(&agg, &Decoder::from(&rd)).unwrap();
+        buf.full();
+
+        assert_eq!(buf.len(), 1);
+        let row = buf.take().await.unwrap().unwrap();
+        let dr = DataRow::from_bytes(row.to_bytes().unwrap()).unwrap();
+        assert_eq!(dr.get::<String>(0, Format::Text).unwrap(), "a@test.com");
+        assert_eq!(dr.get::<i64>(1, Format::Text).unwrap(), 12);
+    }
 }
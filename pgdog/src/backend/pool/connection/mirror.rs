@@ -1,11 +1,22 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rand::random;
 use tokio::select;
 use tokio::time::timeout;
-use tokio::{spawn, sync::mpsc::*};
+use tokio::{
+    spawn,
+    sync::mpsc::{error::TrySendError, *},
+};
 use tracing::{debug, error};
 
 use crate::backend::Cluster;
-use crate::config::config;
+use crate::config::{config, MirroringRule, MirroringStatement};
 use crate::frontend::client::timeouts::Timeouts;
+use crate::frontend::router::Route;
 use crate::frontend::{PreparedStatements, Router, RouterContext};
 use crate::net::Parameters;
 use crate::state::State;
@@ -17,6 +28,76 @@ use crate::{
 use super::Connection;
 use super::Error;
 
+static STATS: Lazy<Mutex<BTreeMap<MirrorKey, Arc<Mutex<MirrorStats>>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Source database, mirror database and user.
+type MirrorKey = (String, String, String);
+
+/// Mirror statistics, shared by all clients of the source database.
+#[derive(Debug, Clone, Default)]
+pub struct MirrorStats {
+    /// Database mirrored.
+    pub database: String,
+    /// Mirror database.
+    pub mirror: String,
+    /// User.
+    pub user: String,
+    /// Requests waiting to be replayed.
+    pub queue: usize,
+    /// Requests sent to the mirror.
+    pub sent: usize,
+    /// Requests dropped because a client's queue was full.
+    pub dropped: usize,
+    /// Requests replayed successfully.
+    pub replayed: usize,
+    /// Requests that failed to replay.
+    pub errors: usize,
+    /// How long the last request waited in the queue.
+    pub lag: Duration,
+    /// Longest time a request waited in the queue.
+    pub max_lag: Duration,
+}
+
+impl MirrorStats {
+    fn get(cluster: &Cluster) -> Arc<Mutex<MirrorStats>> {
+        let database = cluster.mirror_of().unwrap_or_default().to_owned();
+        let key = (
+            database,
+            cluster.name().to_owned(),
+            cluster.user().to_owned(),
+        );
+        STATS
+            .lock()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(Mutex::new(MirrorStats {
+                    database: key.0,
+                    mirror: key.1,
+                    user: key.2,
+                    ..Default::default()
+                }))
+            })
+            .clone()
+    }
+
+    fn received(&mut self, request: &MirrorRequest) {
+        let lag = request.request.created_at.elapsed();
+        self.queue = self.queue.saturating_sub(1);
+        self.lag = lag;
+        self.max_lag = self.max_lag.max(lag);
+    }
+}
+
+/// Statistics for all mirrors.
+pub fn stats() -> Vec<MirrorStats> {
+    STATS
+        .lock()
+        .values()
+        .map(|stats| stats.lock().clone())
+        .collect()
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct MirrorRequest {
     pub(super) request: Request,
@@ -44,7 +125,7 @@ pub(crate) struct Mirror {
 
 impl Mirror {
     pub(crate) fn spawn(cluster: &Cluster) -> Result<MirrorHandler, Error> {
-        let connection = Connection::new(cluster.user(), cluster.name(), false)?;
+        let connection = Connection::new(cluster.user(), cluster.name(), None, false)?;
 
         let mut mirror = Self {
             connection,
@@ -57,9 +138,21 @@ impl Mirror {
 
         let config = config();
 
-        let query_timeout = Timeouts::from_config(&config.config.general);
+        let query_timeout = Timeouts::from_config(&config.config.general, None);
         let (tx, mut rx) = channel(config.config.general.mirror_queue);
-        let handler = MirrorHandler { tx };
+        let rules = config
+            .config
+            .mirroring
+            .iter()
+            .filter(|rule| rule.database == cluster.name())
+            .cloned()
+            .collect();
+        let stats = MirrorStats::get(cluster);
+        let handler = MirrorHandler {
+            tx,
+            rules,
+            stats: stats.clone(),
+        };
 
         spawn(async move {
             loop {
@@ -67,15 +160,18 @@ impl Mirror {
                 select! {
                     req = rx.recv() => {
                         if let Some(req) = req {
+                            stats.lock().received(&req);
                             // TODO: timeout these.
                             if let Err(err) = mirror.handle(&req).await {
                                 if !matches!(err, Error::Pool(PoolError::Offline | PoolError::AllReplicasDown | PoolError::Banned)) {
                                     error!("mirror error: {}", err);
                                 }
 
+                                stats.lock().errors += 1;
                                 mirror.connection.force_close();
                                 mirror.state = State::Idle;
                             } else {
+                                stats.lock().replayed += 1;
                                 mirror.state = State::Active;
                             }
                         } else {
@@ -88,10 +184,12 @@ impl Mirror {
                         match message {
                             Err(_) => {
                                 error!("mirror query timeout");
+                                stats.lock().errors += 1;
                                 mirror.connection.force_close();
                             }
                             Ok(Err(err)) => {
                                 error!("mirror error: {}", err);
+                                stats.lock().errors += 1;
                                 mirror.connection.disconnect();
                             }
                             Ok(_) => (),
@@ -142,4 +240,130 @@ impl Mirror {
 #[derive(Debug)]
 pub(crate) struct MirrorHandler {
     pub(super) tx: Sender<MirrorRequest>,
+    rules: Vec<MirroringRule>,
+    stats: Arc<Mutex<MirrorStats>>,
+}
+
+impl MirrorHandler {
+    /// Send the request to the mirror if it matches the mirroring rules.
+    pub(super) fn send(&self, user: &str, buffer: &Buffer, route: &Route) {
+        if self.rules.is_empty() || Self::matches(&self.rules, user, buffer, route) {
+            match self.tx.try_send(MirrorRequest::new(buffer)) {
+                Ok(()) => {
+                    let mut stats = self.stats.lock();
+                    stats.sent += 1;
+                    stats.queue += 1;
+                }
+                Err(TrySendError::Full(_)) => self.stats.lock().dropped += 1,
+                Err(TrySendError::Closed(_)) => (),
+            }
+        }
+    }
+
+    fn matches(rules: &[MirroringRule], user: &str, buffer: &Buffer, route: &Route) -> bool {
+        // Only fingerprint the query if some rules need it.
+        let mut fingerprint = None;
+
+        for rule in rules {
+            if let Some(ref rule_user) = rule.user {
+                if rule_user != user {
+                    continue;
+                }
+            }
+
+            let statement = match rule.statement {
+                MirroringStatement::All => true,
+                MirroringStatement::Read => route.is_read(),
+                MirroringStatement::Write => route.is_write(),
+            };
+
+            if !statement {
+                continue;
+            }
+
+            if !rule.fingerprints.is_empty() {
+                if fingerprint.is_none() {
+                    fingerprint = Some(
+                        buffer
+                            .query()
+                            .ok()
+                            .flatten()
+                            .and_then(|query| pg_query::fingerprint(query.query()).ok())
+                            .map(|fingerprint| fingerprint.hex),
+                    );
+                }
+
+                match fingerprint {
+                    Some(Some(ref fingerprint)) if rule.fingerprints.contains(fingerprint) => (),
+                    _ => continue,
+                }
+            }
+
+            if rule.percentage >= 100.0 || random::<f64>() * 100.0 < rule.percentage {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::messages::Query;
+
+    use super::*;
+
+    #[test]
+    fn test_mirroring_rules() {
+        let buffer = Buffer::from(vec![Query::new("SELECT 1").into()]);
+        let read = Route::read(Some(0));
+        let write = Route::write(Some(0));
+
+        let rules = vec![MirroringRule {
+            database: "mirror".into(),
+            statement: MirroringStatement::Write,
+            ..Default::default()
+        }];
+        assert!(MirrorHandler::matches(&rules, "pgdog", &buffer, &write));
+        assert!(!MirrorHandler::matches(&rules, "pgdog", &buffer, &read));
+
+        let rules = vec![MirroringRule {
+            database: "mirror".into(),
+            user: Some("alice".into()),
+            ..Default::default()
+        }];
+        assert!(MirrorHandler::matches(&rules, "alice", &buffer, &read));
+        assert!(!MirrorHandler::matches(&rules, "pgdog", &buffer, &read));
+
+        let fingerprint = pg_query::fingerprint("SELECT 1").unwrap().hex;
+        let rules = vec![MirroringRule {
+            database: "mirror".into(),
+            fingerprints: vec![fingerprint],
+            ..Default::default()
+        }];
+        assert!(MirrorHandler::matches(&rules, "pgdog", &buffer, &read));
+        let other = Buffer::from(vec![Query::new("SELECT * FROM users").into()]);
+        assert!(!MirrorHandler::matches(&rules, "pgdog", &other, &read));
+
+        let rules = vec![MirroringRule {
+            database: "mirror".into(),
+            percentage: 0.0,
+            ..Default::default()
+        }];
+        assert!(!MirrorHandler::matches(&rules, "pgdog", &buffer, &read));
+    }
+
+    #[test]
+    fn test_mirror_stats() {
+        let buffer = Buffer::from(vec![Query::new("SELECT 1").into()]);
+        let mut stats = MirrorStats {
+            queue: 2,
+            ..Default::default()
+        };
+        let request = MirrorRequest::new(&buffer);
+        stats.received(&request);
+        assert_eq!(stats.queue, 1);
+        assert!(stats.max_lag >= stats.lag);
+    }
 }
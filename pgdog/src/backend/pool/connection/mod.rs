@@ -1,22 +1,22 @@
 //! Server connection requested by a frontend.
 
-use mirror::{MirrorHandler, MirrorRequest};
+use mirror::MirrorHandler;
 use tokio::time::sleep;
 use tracing::debug;
 
 use crate::{
     admin::backend::Backend,
     backend::{
-        databases::databases,
+        databases::{add_application, databases},
         reload_notify,
         replication::{Buffer, ReplicationConfig},
     },
-    config::PoolerMode,
+    config::{config, PoolerMode},
     frontend::{
         router::{parser::Shard, CopyRow, Route},
         Router,
     },
-    net::{Bind, Message, ParameterStatus, Parameters},
+    net::{Bind, ErrorResponse, Message, ParameterStatus, Parameters},
     state::State,
 };
 
@@ -25,33 +25,42 @@ use super::{
     Address, Cluster, Request, ShardingSchema,
 };
 
-use std::{mem::replace, time::Duration};
+use std::{mem::replace, net::SocketAddr, time::Duration};
 
 pub mod aggregate;
 pub mod binding;
 pub mod buffer;
 pub mod mirror;
 pub mod multi_shard;
+mod replicated;
 
 use aggregate::Aggregates;
 use binding::Binding;
 use mirror::Mirror;
 use multi_shard::MultiShard;
+use replicated::Replicated;
 
 /// Wrapper around a server connection.
 #[derive(Default, Debug)]
 pub struct Connection {
     user: String,
     database: String,
+    application_name: Option<String>,
     binding: Binding,
     cluster: Option<Cluster>,
     mirrors: Vec<MirrorHandler>,
     locked: bool,
+    replicated: Option<Replicated>,
 }
 
 impl Connection {
     /// Create new server connection handler.
-    pub(crate) fn new(user: &str, database: &str, admin: bool) -> Result<Self, Error> {
+    pub(crate) fn new(
+        user: &str,
+        database: &str,
+        application_name: Option<&str>,
+        admin: bool,
+    ) -> Result<Self, Error> {
         let mut conn = Self {
             binding: if admin {
                 Binding::Admin(Backend::new())
@@ -61,8 +70,10 @@ impl Connection {
             cluster: None,
             user: user.to_owned(),
             database: database.to_owned(),
+            application_name: application_name.map(|a| a.to_owned()),
             mirrors: vec![],
             locked: false,
+            replicated: None,
         };
 
         if !admin {
@@ -72,6 +83,13 @@ impl Connection {
         Ok(conn)
     }
 
+    /// Client connected to the admin database, for the audit log.
+    pub(crate) fn admin_client(&mut self, addr: SocketAddr) {
+        if let Binding::Admin(ref mut backend) = self.binding {
+            backend.client(&self.user, addr);
+        }
+    }
+
     /// Check if the connection is available.
     pub(crate) fn connected(&self) -> bool {
         self.binding.connected()
@@ -119,20 +137,25 @@ impl Connection {
     ) -> Result<(), Error> {
         self.binding = Binding::Replication(
             None,
-            Buffer::new(shard, replication_config, sharding_schema),
+            Buffer::new(&self.database, shard, replication_config, sharding_schema),
         );
         Ok(())
     }
 
-    /// Send traffic to mirrors.
-    pub(crate) fn mirror(&self, buffer: &crate::frontend::Buffer) {
+    /// Send traffic to mirrors, if it matches their mirroring rules.
+    pub(crate) fn mirror(&self, buffer: &crate::frontend::Buffer, route: &Route) {
         for mirror in &self.mirrors {
-            let _ = mirror.tx.try_send(MirrorRequest::new(buffer));
+            mirror.send(&self.user, buffer, route);
         }
     }
 
     /// Try to get a connection for the given route.
     async fn try_conn(&mut self, request: &Request, route: &Route) -> Result<(), Error> {
+        let request = &Request {
+            sync_replica: route.sync_replica(),
+            ..*request
+        };
+
         if let Shard::Direct(shard) = route.shard() {
             let mut server = if route.is_read() {
                 self.cluster()?.replica(*shard, request).await?
@@ -162,6 +185,8 @@ impl Connection {
                 _ => (),
             };
         } else {
+            // Check out connections in shard order, so clients waiting
+            // for each other's connections can't deadlock.
             let mut shards = vec![];
             for (i, shard) in self.cluster()?.shards().iter().enumerate() {
                 if let Shard::Multi(numbers) = route.shard() {
@@ -183,7 +208,13 @@ impl Connection {
             }
             let num_shards = shards.len();
 
-            self.binding = Binding::MultiShard(shards, MultiShard::new(num_shards, route));
+            let mut state = MultiShard::new(num_shards, route);
+            // Only needed to sort and aggregate rows.
+            if route.should_buffer() {
+                state.oids(self.cluster()?.oids());
+            }
+
+            self.binding = Binding::MultiShard(shards, state);
         }
 
         Ok(())
@@ -208,6 +239,23 @@ impl Connection {
                     }
                 }
                 self.disconnect();
+
+                // Report the same version for all shards, unless configured otherwise.
+                let cluster = self.cluster()?;
+                let server_version = match config().config.general.server_version.clone() {
+                    Some(version) => Some(version),
+                    None if cluster.shards().len() > 1 => cluster.server_version(),
+                    None => None,
+                };
+
+                if let Some(server_version) = server_version {
+                    for param in params.iter_mut() {
+                        if param.name == "server_version" {
+                            param.value = server_version.clone();
+                        }
+                    }
+                }
+
                 Ok(params)
             }
         }
@@ -215,6 +263,7 @@ impl Connection {
 
     /// Disconnect from a server.
     pub(crate) fn disconnect(&mut self) {
+        self.replicated = None;
         self.binding.disconnect();
     }
 
@@ -249,6 +298,10 @@ impl Connection {
         router: &mut Router,
         streaming: bool,
     ) -> Result<(), Error> {
+        if router.route().replicated() {
+            self.begin_replicated().await?;
+        }
+
         if messages.copy() && !streaming {
             let rows = router
                 .copy_data(messages)
@@ -264,15 +317,53 @@ impl Connection {
             self.send(messages).await?;
         }
 
+        if messages.copy_done() {
+            router.copy_done();
+        }
+
         Ok(())
     }
 
+    /// Start a transaction on all shards for a write to a replicated table,
+    /// unless the client started one already.
+    async fn begin_replicated(&mut self) -> Result<(), Error> {
+        if let Binding::MultiShard(ref mut servers, _) = self.binding {
+            if servers.len() > 1 && !servers.iter().any(|server| server.in_transaction()) {
+                let two_phase = config().config.general.two_phase_commit;
+                self.replicated = Some(Replicated::begin(servers, two_phase).await?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// A write to a replicated table is waiting to be committed.
+    pub(crate) fn replicated(&self) -> bool {
+        self.replicated.is_some()
+    }
+
+    /// Commit the write to a replicated table on all shards, or roll it back.
+    /// Returns the error to send to the client if it wasn't committed.
+    pub(crate) async fn finish_replicated(&mut self) -> Result<Option<ErrorResponse>, Error> {
+        match (self.replicated.take(), &mut self.binding) {
+            (Some(replicated), Binding::MultiShard(ref mut servers, _)) => {
+                replicated.finish(servers).await
+            }
+            _ => Ok(None),
+        }
+    }
+
     /// Fetch the cluster from the global database store.
     pub(crate) fn reload(&mut self) -> Result<(), Error> {
         match self.binding {
             Binding::Server(_) | Binding::MultiShard(_, _) | Binding::Replication(_, _) => {
+                // Use the application's own pool, if it has one.
+                let application_name = self
+                    .application_name
+                    .as_deref()
+                    .filter(|app| add_application(&self.user, &self.database, app));
                 let databases = databases();
-                let user = (self.user.as_str(), self.database.as_str());
+                let user = (self.user.as_str(), self.database.as_str(), application_name);
                 let cluster = databases.cluster(user)?;
 
                 self.cluster = Some(cluster);
@@ -338,6 +429,29 @@ impl Connection {
         })
     }
 
+    /// Count the query as a read or write on connected servers.
+    pub(crate) fn routed(&mut self, read: bool) {
+        match self.binding {
+            Binding::Server(Some(ref mut server)) => server.stats_mut().routed(read, false),
+            Binding::MultiShard(ref mut servers, _) => {
+                let cross_shard = servers.len() > 1;
+                for server in servers {
+                    server.stats_mut().routed(read, cross_shard);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Process IDs of connected servers.
+    pub(crate) fn pids(&self) -> Vec<i32> {
+        match self.binding {
+            Binding::Server(Some(ref server)) => vec![server.id().pid],
+            Binding::MultiShard(ref servers, _) => servers.iter().map(|s| s.id().pid).collect(),
+            _ => vec![],
+        }
+    }
+
     /// Get a connected server, if any. If multi-shard, get the first one.
     #[inline]
     fn server(&mut self) -> Result<&mut Guard, Error> {
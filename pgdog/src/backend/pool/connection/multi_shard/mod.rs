@@ -1,13 +1,23 @@
 //! Multi-shard connection state.
 
+use std::collections::{HashSet, VecDeque};
+
 use context::Context;
 
 use crate::{
-    frontend::{router::Route, PreparedStatements},
+    backend::{
+        pool::Oids,
+        replication::{publisher::start_replication, Publisher},
+        ProtocolMessage,
+    },
+    frontend::{
+        router::{parser::Limit, Route},
+        PreparedStatements,
+    },
     net::{
         messages::{
-            command_complete::CommandComplete, FromBytes, Message, Protocol, RowDescription,
-            ToBytes,
+            command_complete::CommandComplete, replication::ReplicationMeta, CopyData, FromBytes,
+            Message, Protocol, Query, RowDescription, ToBytes,
         },
         Decoder,
     },
@@ -22,10 +32,15 @@ mod test;
 #[derive(Default, Debug)]
 struct Counters {
     rows: usize,
+    /// Rows received while forwarding them without buffering.
+    streamed: usize,
     ready_for_query: usize,
     command_complete_count: usize,
     empty_query_response: usize,
     copy_in: usize,
+    copy_both: usize,
+    copy_out: usize,
+    copy_done: usize,
     parse_complete: usize,
     parameter_description: usize,
     no_data: usize,
@@ -35,6 +50,25 @@ struct Counters {
     command_complete: Option<Message>,
 }
 
+/// Binary COPY header: signature, flags and header extension length.
+const BINARY_HEADER: usize = 19;
+/// Binary COPY trailer: a tuple with -1 columns.
+const BINARY_TRAILER: [u8; 2] = [0xff, 0xff];
+
+/// `COPY ... TO STDOUT` from all shards, sent to the client
+/// as one stream.
+#[derive(Default, Debug)]
+struct CopyOut {
+    /// Binary format.
+    binary: bool,
+    /// Shards that started sending rows.
+    started: HashSet<usize>,
+    /// Rows received before all shards sent CopyOutResponse.
+    pending: VecDeque<Message>,
+    /// CopyDone, sent after the binary trailer.
+    done: Option<Message>,
+}
+
 /// Multi-shard state.
 #[derive(Default, Debug)]
 pub(super) struct MultiShard {
@@ -45,6 +79,11 @@ pub(super) struct MultiShard {
 
     /// Counters
     counters: Counters,
+    copy_out: CopyOut,
+    /// Logical replication streams merged into one.
+    publisher: Option<Publisher>,
+    /// Client sent IDENTIFY_SYSTEM, which returns one row.
+    identify_system: bool,
 
     /// Sorting/aggregate buffer.
     buffer: Buffer,
@@ -62,8 +101,16 @@ impl MultiShard {
         }
     }
 
+    /// Decode custom data types loaded from the cluster's databases.
+    pub(super) fn oids(&mut self, oids: Oids) {
+        self.decoder.oids(oids);
+    }
+
     pub(super) fn reset(&mut self) {
         self.counters = Counters::default();
+        self.copy_out = CopyOut::default();
+        self.publisher = None;
+        self.identify_system = false;
         self.buffer.reset();
         // Don't reset:
         //  1. Route to keep routing decision
@@ -71,6 +118,110 @@ impl MultiShard {
         //  3. Decoder
     }
 
+    /// Same as [`MultiShard::forward`], for a message received from the given shard.
+    pub(super) fn forward_from(
+        &mut self,
+        shard: usize,
+        message: Message,
+    ) -> Result<Option<Message>, super::Error> {
+        match message.code() {
+            'W' => {
+                self.counters.copy_both += 1;
+                if self.shards > 1 && self.publisher.is_none() {
+                    self.publisher = Some(Publisher::new(self.shards));
+                }
+                if self.counters.copy_both.is_multiple_of(self.shards) {
+                    return Ok(Some(message));
+                }
+                Ok(None)
+            }
+            // The first shard speaks for all of them.
+            'D' if self.identify_system && shard > 0 => Ok(None),
+            'd' if self.publisher.is_some() => {
+                let data = CopyData::from_bytes(message.to_bytes()?)?;
+                if let Some(ref mut publisher) = self.publisher {
+                    publisher.handle(shard, data)?;
+                }
+                Ok(None)
+            }
+            'H' | 'd' | 'c' => self.copy_out(shard, message),
+            _ => self.forward(message),
+        }
+    }
+
+    /// Merge `COPY ... TO STDOUT` output into one stream with
+    /// a single header, CopyOutResponse and CopyDone.
+    fn copy_out(
+        &mut self,
+        shard: usize,
+        message: Message,
+    ) -> Result<Option<Message>, super::Error> {
+        match message.code() {
+            'H' => {
+                self.counters.copy_out += 1;
+                // Overall format: 0 is text, 1 is binary.
+                self.copy_out.binary = message.to_bytes()?.get(5) == Some(&1);
+                if self.counters.copy_out.is_multiple_of(self.shards) {
+                    return Ok(Some(message));
+                }
+                Ok(None)
+            }
+
+            'd' => {
+                let copy_data = CopyData::from_bytes(message.to_bytes()?)?;
+                let mut data = copy_data.data();
+                let first_row = self.copy_out.started.insert(shard);
+                let first_shard = self.copy_out.started.len() == 1;
+                let mut header: &[u8] = &[];
+
+                if self.copy_out.binary {
+                    if first_row && data.len() >= BINARY_HEADER {
+                        if first_shard {
+                            header = &data[..BINARY_HEADER];
+                        }
+                        data = &data[BINARY_HEADER..];
+                    }
+                    // Sent once, after all shards are done.
+                    if data == BINARY_TRAILER {
+                        data = &[];
+                    }
+                } else if first_row && !first_shard && self.route.copy_headers() {
+                    data = &[];
+                }
+
+                let message = if data.is_empty() && header.is_empty() {
+                    return Ok(None);
+                } else if header.is_empty() && data.len() == copy_data.data().len() {
+                    message
+                } else {
+                    CopyData::new(&[header, data].concat()).message()?
+                };
+
+                if self.counters.copy_out < self.shards {
+                    // Client hasn't received CopyOutResponse yet.
+                    self.copy_out.pending.push_back(message);
+                    Ok(None)
+                } else {
+                    Ok(Some(message))
+                }
+            }
+
+            'c' => {
+                self.counters.copy_done += 1;
+                if self.counters.copy_done.is_multiple_of(self.shards) {
+                    if self.copy_out.binary {
+                        self.copy_out.done = Some(message);
+                        return Ok(Some(CopyData::new(&BINARY_TRAILER).message()?));
+                    }
+                    return Ok(Some(message));
+                }
+                Ok(None)
+            }
+
+            _ => self.forward(message),
+        }
+    }
+
     /// Check if the message should be sent to the client, skipped,
     /// or modified.
     pub(super) fn forward(&mut self, message: Message) -> Result<Option<Message>, super::Error> {
@@ -105,10 +256,14 @@ impl MultiShard {
                     self.buffer
                         .aggregate(self.route.aggregate(), &self.decoder)?;
                     self.buffer.sort(self.route.order_by(), &self.decoder);
+                    self.buffer.distinct(self.route.distinct(), &self.decoder)?;
+                    self.buffer.limit(self.remaining_limit());
 
                     if has_rows {
                         let rows = if self.route.should_buffer() {
                             self.buffer.len()
+                        } else if let Some(limit) = self.route.limit() {
+                            limit.sent(self.counters.streamed) + self.buffer.len()
                         } else {
                             self.counters.rows
                         };
@@ -116,6 +271,12 @@ impl MultiShard {
                     } else {
                         forward = Some(cc.message()?);
                     }
+
+                    // The next statement, e.g. in a multi-statement query,
+                    // has its own row description and row count.
+                    self.counters.rows = 0;
+                    self.counters.streamed = 0;
+                    self.counters.row_description = 0;
                 }
             }
 
@@ -126,11 +287,25 @@ impl MultiShard {
                 if self.counters.row_description == 1 {
                     let rd = RowDescription::from_bytes(message.to_bytes()?)?;
                     self.decoder.row_description(&rd);
+                    // Rows are decoded to be sorted and aggregated.
+                    if self.route.should_buffer() {
+                        if let Some(oid) = self.decoder.mismatched() {
+                            return Err(super::Error::OidMismatch(oid));
+                        }
+                    }
                 }
                 if self.counters.row_description == self.shards {
                     // Only send it to the client once all shards sent it,
                     // so we don't get early requests from clients.
-                    forward = Some(message);
+                    let hidden = self.route.aggregate().hidden();
+                    if hidden > 0 {
+                        // Don't show columns we added to compute aggregates.
+                        let fields = &self.decoder.rd().fields;
+                        let visible = fields.len().saturating_sub(hidden);
+                        forward = Some(RowDescription::new(&fields[..visible]).message()?);
+                    } else {
+                        forward = Some(message);
+                    }
                 }
             }
 
@@ -143,9 +318,14 @@ impl MultiShard {
 
             'D' => {
                 if !self.route.should_buffer() && self.counters.row_description % self.shards == 0 {
-                    forward = Some(message);
+                    let row = self.counters.streamed;
+                    self.counters.streamed += 1;
+                    if self.route.limit().is_none_or(|limit| limit.includes(row)) {
+                        forward = Some(message);
+                    }
                 } else {
                     self.buffer.add(message)?;
+                    self.buffer.work_mem(&self.route)?;
                 }
             }
 
@@ -198,12 +378,89 @@ impl MultiShard {
         Ok(forward)
     }
 
+    /// LIMIT and OFFSET left to apply to buffered rows, after
+    /// the ones that were forwarded without buffering.
+    fn remaining_limit(&self) -> Option<Limit> {
+        let limit = self.route.limit()?;
+        if self.route.should_buffer() {
+            return Some(limit);
+        }
+
+        let streamed = self.counters.streamed;
+        Some(Limit {
+            limit: limit
+                .limit
+                .map(|count| count.saturating_sub(limit.sent(streamed))),
+            offset: limit.offset.saturating_sub(streamed),
+        })
+    }
+
+    /// Messages to send to the shard instead of the ones the client sent, if they
+    /// need to be changed for logical replication from all shards.
+    pub(super) fn outgoing(
+        &mut self,
+        shard: usize,
+        messages: &crate::frontend::Buffer,
+    ) -> Result<Option<crate::frontend::Buffer>, super::Error> {
+        let mut changed = false;
+        let mut outgoing = vec![];
+
+        for message in messages.iter() {
+            match message {
+                ProtocolMessage::Query(query) if self.shards > 1 => {
+                    if query.query().trim().eq_ignore_ascii_case("IDENTIFY_SYSTEM") {
+                        self.identify_system = true;
+                    }
+                    if let Some(query) = start_replication(query.query()) {
+                        outgoing.push(ProtocolMessage::Query(Query::new(query)));
+                        changed = true;
+                        continue;
+                    }
+                }
+
+                ProtocolMessage::CopyData(data) => {
+                    if let (Some(publisher), Some(ReplicationMeta::StatusUpdate(update))) =
+                        (self.publisher.as_mut(), data.replication_meta())
+                    {
+                        let update = publisher.status_update(shard, &update);
+                        outgoing.push(ProtocolMessage::CopyData(CopyData::bytes(
+                            update.to_bytes()?,
+                        )));
+                        changed = true;
+                        continue;
+                    }
+                }
+
+                _ => (),
+            }
+
+            outgoing.push(message.clone());
+        }
+
+        Ok(changed.then(|| outgoing.into()))
+    }
+
+    /// Write buffered rows to disk, if they don't fit in memory.
+    pub(super) async fn spill(&mut self) -> Result<(), super::Error> {
+        self.buffer
+            .spill(self.route.order_by(), &self.decoder)
+            .await
+    }
+
     /// Multi-shard state is ready to send messages.
-    pub(super) fn message(&mut self) -> Option<Message> {
-        if let Some(data_row) = self.buffer.take() {
-            Some(data_row)
+    pub(super) async fn message(&mut self) -> Result<Option<Message>, super::Error> {
+        if let Some(message) = self.publisher.as_mut().and_then(|p| p.message()) {
+            return Ok(Some(message));
+        }
+
+        if let Some(data_row) = self.buffer.take().await? {
+            Ok(Some(data_row))
+        } else if self.counters.copy_out >= self.shards && !self.copy_out.pending.is_empty() {
+            Ok(self.copy_out.pending.pop_front())
+        } else if let Some(copy_done) = self.copy_out.done.take() {
+            Ok(Some(copy_done))
         } else {
-            self.counters.command_complete.take()
+            Ok(self.counters.command_complete.take())
         }
     }
 
@@ -1,9 +1,14 @@
-use crate::net::{DataRow, Field};
+use bytes::BufMut;
+
+use crate::{
+    frontend::router::parser::Shard,
+    net::{messages::CopyDone, DataRow, Field},
+};
 
 use super::*;
 
-#[test]
-fn test_rd_before_dr() {
+#[tokio::test]
+async fn test_rd_before_dr() {
     let mut multi_shard = MultiShard::new(3, &Route::read(None));
     let rd = RowDescription::new(&[Field::bigint("id")]);
     let mut dr = DataRow::new();
@@ -21,7 +26,7 @@ fn test_rd_before_dr() {
 
     let result = multi_shard.forward(rd.message().unwrap()).unwrap();
     assert_eq!(result, Some(rd.message().unwrap()));
-    let result = multi_shard.message();
+    let result = multi_shard.message().await.unwrap();
     // Waiting for command complete
     assert!(result.is_none());
 
@@ -38,14 +43,14 @@ fn test_rd_before_dr() {
     }
 
     for _ in 0..2 {
-        let result = multi_shard.message();
+        let result = multi_shard.message().await.unwrap();
         assert_eq!(
             result.map(|m| m.backend()),
             Some(dr.message().unwrap().backend())
         );
     }
 
-    let result = multi_shard.message().map(|m| m.backend());
+    let result = multi_shard.message().await.unwrap().map(|m| m.backend());
     assert_eq!(
         result,
         Some(
@@ -57,5 +62,263 @@ fn test_rd_before_dr() {
     );
 
     // Buffer is empty.
-    assert!(multi_shard.message().is_none());
+    assert!(multi_shard.message().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_returning() {
+    let mut multi_shard = MultiShard::new(2, &Route::write(Shard::All));
+    let rd = RowDescription::new(&[Field::bigint("id")]);
+    let row = |id: i64| {
+        let mut dr = DataRow::new();
+        dr.add(id);
+        dr.message().unwrap()
+    };
+    let cc = |tag: &str| CommandComplete::from_str(tag).message().unwrap();
+
+    // First shard finishes before the second one starts.
+    let messages = vec![
+        rd.message().unwrap(),
+        row(1),
+        row(2),
+        cc("INSERT 0 2"),
+        rd.message().unwrap(),
+        row(3),
+        cc("INSERT 0 1"),
+        // Second statement in the same query.
+        rd.message().unwrap(),
+        row(4),
+        cc("UPDATE 1"),
+        rd.message().unwrap(),
+        row(5),
+        cc("UPDATE 1"),
+    ];
+
+    let mut received = vec![];
+    for message in messages {
+        while let Some(message) = multi_shard.message().await.unwrap() {
+            received.push(message);
+        }
+        if let Some(message) = multi_shard.forward(message).unwrap() {
+            received.push(message);
+        }
+    }
+    while let Some(message) = multi_shard.message().await.unwrap() {
+        received.push(message);
+    }
+
+    let codes = received.iter().map(|m| m.code()).collect::<String>();
+    assert_eq!(codes, "TDDDCTDDC");
+
+    let tags = received
+        .iter()
+        .filter(|m| m.code() == 'C')
+        .map(|m| {
+            CommandComplete::from_bytes(m.to_bytes().unwrap())
+                .unwrap()
+                .command()
+                .to_owned()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(tags, vec!["INSERT 0 3", "UPDATE 2"]);
+}
+
+async fn receive_copy(
+    multi_shard: &mut MultiShard,
+    messages: Vec<(usize, Message)>,
+) -> Vec<Message> {
+    let mut received = vec![];
+    for (shard, message) in messages {
+        while let Some(message) = multi_shard.message().await.unwrap() {
+            received.push(message);
+        }
+        if let Some(message) = multi_shard.forward_from(shard, message).unwrap() {
+            received.push(message);
+        }
+    }
+    while let Some(message) = multi_shard.message().await.unwrap() {
+        received.push(message);
+    }
+    received
+}
+
+#[tokio::test]
+async fn test_copy_out() {
+    let copy_out = |format: u8| {
+        let mut payload = bytes::BytesMut::new();
+        payload.put_u8(b'H');
+        payload.put_i32(4 + 1 + 2 + 2);
+        payload.put_u8(format);
+        payload.put_i16(1);
+        payload.put_i16(format as i16);
+        Message::new(payload.freeze())
+    };
+    let data = |data: &[u8]| CopyData::new(data).message().unwrap();
+    let done = || CopyDone.message().unwrap();
+
+    // CSV with headers.
+    let route = Route::write(Shard::All).set_copy_headers(true);
+    let mut multi_shard = MultiShard::new(2, &route);
+    let received = receive_copy(
+        &mut multi_shard,
+        vec![
+            (0, copy_out(0)),
+            (0, data(b"id,email\n")),
+            (0, data(b"1,a@test.com\n")),
+            (0, done()),
+            (1, copy_out(0)),
+            (1, data(b"id,email\n")),
+            (1, data(b"2,b@test.com\n")),
+            (1, done()),
+        ],
+    )
+    .await;
+    let codes = received.iter().map(|m| m.code()).collect::<String>();
+    assert_eq!(codes, "Hdddc");
+    let rows = received
+        .iter()
+        .filter(|m| m.code() == 'd')
+        .map(|m| {
+            CopyData::from_bytes(m.to_bytes().unwrap())
+                .unwrap()
+                .data()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rows,
+        vec![
+            b"id,email\n".to_vec(),
+            b"1,a@test.com\n".to_vec(),
+            b"2,b@test.com\n".to_vec()
+        ]
+    );
+
+    // Binary: one header and one trailer.
+    let header = [
+        b"PGCOPY\n\xff\r\n\0".as_slice(),
+        &[0, 0, 0, 0],
+        &[0, 0, 0, 0],
+    ]
+    .concat();
+    let tuple = |id: i64| {
+        [
+            &1i16.to_be_bytes()[..],
+            &8i32.to_be_bytes(),
+            &id.to_be_bytes(),
+        ]
+        .concat()
+    };
+    let mut multi_shard = MultiShard::new(2, &Route::write(Shard::All));
+    let received = receive_copy(
+        &mut multi_shard,
+        vec![
+            (0, copy_out(1)),
+            (1, copy_out(1)),
+            (1, data(&[header.as_slice(), &tuple(1)].concat())),
+            (0, data(&[header.as_slice(), &tuple(2)].concat())),
+            (1, data(&[0xff, 0xff])),
+            (1, done()),
+            (0, data(&[0xff, 0xff])),
+            (0, done()),
+        ],
+    )
+    .await;
+    let stream = received
+        .iter()
+        .filter(|m| m.code() == 'd')
+        .flat_map(|m| {
+            CopyData::from_bytes(m.to_bytes().unwrap())
+                .unwrap()
+                .data()
+                .to_vec()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        stream,
+        [header.as_slice(), &tuple(1), &tuple(2), &[0xff, 0xff]].concat()
+    );
+    assert_eq!(received.last().unwrap().code(), 'c');
+}
+
+#[tokio::test]
+async fn test_identify_system() {
+    let mut multi_shard = MultiShard::new(2, &Route::write(Shard::All));
+    let buffer: crate::frontend::Buffer =
+        vec![ProtocolMessage::Query(Query::new("IDENTIFY_SYSTEM"))].into();
+    assert!(multi_shard.outgoing(0, &buffer).unwrap().is_none());
+
+    let rd = RowDescription::new(&[Field::text("systemid")]);
+    let mut dr = DataRow::new();
+    dr.add("7361285617593937213");
+    let cc = CommandComplete::from_str("IDENTIFY_SYSTEM");
+
+    let mut received = vec![];
+    for shard in 0..2 {
+        for message in [
+            rd.message().unwrap(),
+            dr.message().unwrap(),
+            cc.message().unwrap(),
+        ] {
+            if let Some(message) = multi_shard.forward_from(shard, message).unwrap() {
+                received.push(message.code());
+            }
+        }
+    }
+    while let Some(message) = multi_shard.message().await.unwrap() {
+        received.push(message.code());
+    }
+
+    assert_eq!(received.iter().filter(|code| **code == 'D').count(), 1);
+}
+
+#[tokio::test]
+async fn test_limit_streamed() {
+    let route = Route::read(None).set_limit(Some(Limit {
+        limit: Some(2),
+        offset: 1,
+    }));
+    let mut multi_shard = MultiShard::new(2, &route);
+    let rd = RowDescription::new(&[Field::bigint("id")]);
+    let row = |id: i64| {
+        let mut dr = DataRow::new();
+        dr.add(id);
+        dr.message().unwrap()
+    };
+
+    // The first shard's row arrives before the second shard's RowDescription.
+    assert!(multi_shard
+        .forward(rd.message().unwrap())
+        .unwrap()
+        .is_none());
+    assert!(multi_shard.forward(row(1)).unwrap().is_none());
+    assert!(multi_shard
+        .forward(rd.message().unwrap())
+        .unwrap()
+        .is_some());
+
+    // Skipped by OFFSET, then forwarded.
+    let mut forwarded = vec![];
+    for id in 2..6 {
+        forwarded.push(multi_shard.forward(row(id)).unwrap().is_some());
+    }
+    assert_eq!(forwarded, [false, true, true, false]);
+
+    for _ in 0..2 {
+        assert!(multi_shard
+            .forward(CommandComplete::from_str("SELECT 3").message().unwrap())
+            .unwrap()
+            .is_none());
+    }
+
+    // The buffered row is over the LIMIT too.
+    let cc = multi_shard.message().await.unwrap().unwrap();
+    assert_eq!(
+        CommandComplete::from_bytes(cc.to_bytes().unwrap())
+            .unwrap()
+            .rows()
+            .unwrap(),
+        Some(2)
+    );
+    assert!(multi_shard.message().await.unwrap().is_none());
 }
@@ -0,0 +1,269 @@
+//! Writes to replicated tables, sent to all shards in one transaction.
+
+use std::{
+    ops::DerefMut,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing::error;
+
+use crate::{
+    backend::{Error, Server},
+    net::ErrorResponse,
+    state::State,
+};
+
+/// Transaction started on all shards for a write to a replicated table.
+#[derive(Debug)]
+pub(super) struct Replicated {
+    /// Commit with `PREPARE TRANSACTION` and `COMMIT PREPARED`.
+    two_phase: bool,
+}
+
+impl Replicated {
+    /// Start a transaction on all shards.
+    pub(super) async fn begin(
+        servers: &mut [impl DerefMut<Target = Server>],
+        two_phase: bool,
+    ) -> Result<Self, Error> {
+        for server in servers.iter_mut() {
+            server.execute_checked("BEGIN").await?;
+        }
+
+        Ok(Self { two_phase })
+    }
+
+    /// Commit the transaction on all shards, or roll it back if it failed on any of them.
+    ///
+    /// Returns the error to send to the client if it wasn't committed.
+    pub(super) async fn finish(
+        self,
+        servers: &mut [impl DerefMut<Target = Server>],
+    ) -> Result<Option<ErrorResponse>, Error> {
+        // The client got the error already.
+        if servers
+            .iter()
+            .any(|server| server.stats().state == State::TransactionError)
+        {
+            for server in servers.iter_mut() {
+                server.execute("ROLLBACK").await?;
+            }
+            return Ok(None);
+        }
+
+        if self.two_phase {
+            Self::two_phase_commit(servers).await
+        } else {
+            Self::commit(servers).await
+        }
+    }
+
+    /// Commit on one shard after the other. If a commit fails, shards before it
+    /// keep the write and shards after it are rolled back.
+    async fn commit(
+        servers: &mut [impl DerefMut<Target = Server>],
+    ) -> Result<Option<ErrorResponse>, Error> {
+        for shard in 0..servers.len() {
+            let mut error = match servers[shard].execute_checked("COMMIT").await {
+                Ok(_) => continue,
+                Err(Error::ExecutionError(error)) => error,
+                Err(err) => return Err(err),
+            };
+
+            for server in servers[shard + 1..].iter_mut() {
+                server.execute("ROLLBACK").await?;
+            }
+
+            if shard > 0 {
+                error.detail = Some(format!(
+                    "committed on shards 0 to {}, rolled back on the others",
+                    shard - 1
+                ));
+            }
+
+            return Ok(Some(*error));
+        }
+
+        Ok(None)
+    }
+
+    /// Prepare the transaction on all shards before committing it,
+    /// so it's committed on all of them or none.
+    async fn two_phase_commit(
+        servers: &mut [impl DerefMut<Target = Server>],
+    ) -> Result<Option<ErrorResponse>, Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        let gids = servers
+            .iter()
+            .map(|server| format!("pgdog_{}_{}", server.id().pid, now))
+            .collect::<Vec<_>>();
+
+        for shard in 0..servers.len() {
+            let query = format!("PREPARE TRANSACTION '{}'", gids[shard]);
+            // A transaction that failed to prepare is rolled back.
+            let error = match servers[shard].execute_checked(query).await {
+                Ok(_) => continue,
+                Err(Error::ExecutionError(error)) => error,
+                Err(err) => return Err(err),
+            };
+
+            for server in servers[shard + 1..].iter_mut() {
+                server.execute("ROLLBACK").await?;
+            }
+
+            for (server, gid) in servers[..shard].iter_mut().zip(&gids) {
+                server
+                    .execute_checked(format!("ROLLBACK PREPARED '{}'", gid))
+                    .await?;
+            }
+
+            return Ok(Some(*error));
+        }
+
+        let mut result = Ok(None);
+
+        for (shard, (server, gid)) in servers.iter_mut().zip(&gids).enumerate() {
+            if let Err(err) = server
+                .execute_checked(format!("COMMIT PREPARED '{}'", gid))
+                .await
+            {
+                // Postgres keeps the transaction until it's committed by hand.
+                error!(
+                    "prepared transaction \"{}\" on shard {} wasn't committed: {} [{}]",
+                    gid,
+                    shard,
+                    err,
+                    server.addr()
+                );
+
+                if result.as_ref().is_ok_and(|error| error.is_none()) {
+                    result = match err {
+                        Error::ExecutionError(mut error) => {
+                            error.detail = Some(format!(
+                                "prepared transaction \"{}\" on shard {} wasn't committed",
+                                gid, shard
+                            ));
+                            Ok(Some(*error))
+                        }
+                        err => Err(err),
+                    };
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{backend::server::test::test_server, net::DataRow};
+
+    use super::*;
+
+    async fn ids(server: &mut Server, table: &str) -> Vec<i64> {
+        server
+            .fetch_all::<DataRow>(format!("SELECT id FROM {} ORDER BY id", table))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|row| row.get_int(0, true).unwrap())
+            .collect()
+    }
+
+    /// Connections with a table each, standing in for shards.
+    async fn shards(name: &str) -> (Vec<Box<Server>>, Vec<String>) {
+        let mut servers = vec![];
+        let mut tables = vec![];
+        for shard in 0..2 {
+            let table = format!("{}_{}", name, shard);
+            let mut server = test_server().await;
+            server
+                .execute_checked(format!("DROP TABLE IF EXISTS {}", table))
+                .await
+                .unwrap();
+            server
+                .execute_checked(format!(
+                    "CREATE TABLE {} (
+                        id BIGINT PRIMARY KEY,
+                        value BIGINT UNIQUE DEFERRABLE INITIALLY DEFERRED
+                    )",
+                    table
+                ))
+                .await
+                .unwrap();
+            servers.push(Box::new(server));
+            tables.push(table);
+        }
+        (servers, tables)
+    }
+
+    async fn insert(servers: &mut [Box<Server>], tables: &[String], values: &str) {
+        for (server, table) in servers.iter_mut().zip(tables) {
+            let _ = server
+                .execute(format!("INSERT INTO {} VALUES {}", table, values))
+                .await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replicated_write() {
+        let (mut servers, tables) = shards("test_replicated_write").await;
+
+        let replicated = Replicated::begin(&mut servers, false).await.unwrap();
+        insert(&mut servers, &tables, "(1, 1)").await;
+        assert!(replicated.finish(&mut servers).await.unwrap().is_none());
+
+        for (server, table) in servers.iter_mut().zip(&tables) {
+            assert!(!server.in_transaction());
+            assert_eq!(ids(server, table).await, vec![1]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replicated_write_partial_failure() {
+        let (mut servers, tables) = shards("test_replicated_partial").await;
+        servers[1]
+            .execute_checked(format!("INSERT INTO {} VALUES (2, 2)", tables[1]))
+            .await
+            .unwrap();
+
+        // Fails on the second shard only.
+        let replicated = Replicated::begin(&mut servers, false).await.unwrap();
+        insert(&mut servers, &tables, "(2, 2)").await;
+        assert!(replicated.finish(&mut servers).await.unwrap().is_none());
+
+        assert!(ids(&mut servers[0], &tables[0]).await.is_empty());
+        assert_eq!(ids(&mut servers[1], &tables[1]).await, vec![2]);
+
+        // Fails when committing on the second shard.
+        let replicated = Replicated::begin(&mut servers, false).await.unwrap();
+        insert(&mut servers, &tables, "(3, 2)").await;
+        let error = replicated.finish(&mut servers).await.unwrap().unwrap();
+        assert_eq!(error.code, "23505");
+        assert_eq!(
+            error.detail.as_deref(),
+            Some("committed on shards 0 to 0, rolled back on the others")
+        );
+        assert_eq!(ids(&mut servers[0], &tables[0]).await, vec![3]);
+        assert_eq!(ids(&mut servers[1], &tables[1]).await, vec![2]);
+
+        // With two-phase commit, it's committed on no shard.
+        servers[0]
+            .execute_checked(format!("DELETE FROM {}", tables[0]))
+            .await
+            .unwrap();
+        let replicated = Replicated::begin(&mut servers, true).await.unwrap();
+        insert(&mut servers, &tables, "(3, 2)").await;
+        assert!(replicated.finish(&mut servers).await.unwrap().is_some());
+
+        for server in servers.iter() {
+            assert!(!server.in_transaction());
+        }
+        assert!(ids(&mut servers[0], &tables[0]).await.is_empty());
+        assert_eq!(ids(&mut servers[1], &tables[1]).await, vec![2]);
+    }
+}
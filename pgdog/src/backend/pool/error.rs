@@ -6,6 +6,9 @@ pub enum Error {
     #[error("checkout timeout")]
     CheckoutTimeout,
 
+    #[error("connect timeout")]
+    ConnectTimeout,
+
     #[error("replica checkout timeout")]
     ReplicaCheckoutTimeout,
 
@@ -45,6 +48,12 @@ pub enum Error {
     #[error("all replicas down")]
     AllReplicasDown,
 
+    #[error("no synchronous replica")]
+    NoSyncReplica,
+
+    #[error("all replicas are lagging behind the primary")]
+    AllReplicasLagging,
+
     #[error("router error")]
     Router,
 }
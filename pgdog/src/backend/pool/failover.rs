@@ -0,0 +1,305 @@
+//! Primary failover.
+//!
+//! The pool monitor reports primaries that keep failing healthchecks
+//! or refuse writes because they're read-only. We then ask the primary and its replicas
+//! if they are in recovery and, if one of the replicas was promoted,
+//! send writes to it instead, without reloading the config.
+//!
+//! The old primary is kept as a replica: it stays banned while it's down
+//! and serves reads again once it rejoins as a standby.
+//!
+//! Failovers are applied again when the config is reloaded or rolled back,
+//! until the config itself has the promoted replica as the primary.
+//! They are forgotten on restart.
+//!
+//! A node that lost its own connection to the primary shouldn't fail over
+//! while everyone else can still reach it. If configured, other PgDog nodes,
+//! found with service discovery, and an HTTP witness have to agree
+//! the primary is down first.
+
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::{spawn, time::timeout};
+use tracing::{debug, info, warn};
+
+use crate::backend::{databases, Server};
+use crate::config::config;
+use crate::events::{self, emit};
+use crate::net::discovery::Listener;
+
+use super::{witness, Address, Pool};
+
+/// Failovers kept for `SHOW FAILOVERS`.
+const MAX_EVENTS: usize = 100;
+
+/// Peers that didn't send anything for this long can't vote.
+/// They broadcast every second.
+const PEER_TIMEOUT: Duration = Duration::from_secs(5);
+
+static EVENTS: Lazy<Mutex<VecDeque<Event>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+static RESOLVING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+static UNREACHABLE: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Why we looked for a new primary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    /// The primary failed too many healthchecks in a row.
+    Unreachable,
+    /// The primary refused writes.
+    ReadOnly,
+}
+
+impl std::fmt::Display for Reason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unreachable => write!(f, "unreachable"),
+            Self::ReadOnly => write!(f, "read only"),
+        }
+    }
+}
+
+/// Primary failover, successful or not.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// When the failover happened.
+    pub created_at: SystemTime,
+    /// Old primary.
+    pub primary: String,
+    /// Why it was replaced.
+    pub reason: Reason,
+    /// Replica now used as the primary, if we found one.
+    pub promoted: Option<String>,
+    /// Number of databases sending writes to the new primary.
+    pub databases: usize,
+}
+
+/// Failovers, oldest first.
+pub fn events() -> Vec<Event> {
+    EVENTS.lock().iter().cloned().collect()
+}
+
+fn record(event: Event) {
+    let mut events = EVENTS.lock();
+    if events.len() >= MAX_EVENTS {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+/// Servers this node can't reach, shared with peers.
+pub fn unreachable() -> Vec<String> {
+    let mut servers: Vec<String> = UNREACHABLE.lock().iter().cloned().collect();
+    servers.sort();
+    servers
+}
+
+/// The server passed a healthcheck.
+pub(super) fn reachable(pool: &Pool) {
+    let mut unreachable = UNREACHABLE.lock();
+    if !unreachable.is_empty() {
+        unreachable.remove(&pool.addr().addr());
+    }
+}
+
+/// The pool monitor thinks the primary is gone.
+///
+/// Topology is re-resolved in the background; only one failover
+/// runs for the same server at a time.
+pub(super) fn detected(pool: &Pool, reason: Reason) {
+    let addr = pool.addr().addr();
+    if reason == Reason::Unreachable {
+        UNREACHABLE.lock().insert(addr.clone());
+    }
+    if !RESOLVING.lock().insert(addr.clone()) {
+        return;
+    }
+
+    let pool = pool.clone();
+    spawn(async move {
+        failover(&pool, reason).await;
+        RESOLVING.lock().remove(&addr);
+    });
+}
+
+async fn failover(pool: &Pool, reason: Reason) {
+    // Replicas of this server, if it's a primary in any database.
+    let mut candidates: Vec<Pool> = vec![];
+    for cluster in databases::databases().all().values() {
+        for shard in cluster.shards() {
+            let primary = shard
+                .primary
+                .as_ref()
+                .is_some_and(|primary| primary.addr().same_server(pool.addr()));
+            if !primary {
+                continue;
+            }
+            for replica in shard.replicas.pools() {
+                if !candidates
+                    .iter()
+                    .any(|candidate| candidate.addr().same_server(replica.addr()))
+                {
+                    candidates.push(replica.clone());
+                }
+            }
+        }
+    }
+
+    if candidates.is_empty() {
+        return;
+    }
+
+    if in_recovery(pool).await == Some(false) {
+        debug!(
+            "primary is {} but not in recovery [{}]",
+            reason,
+            pool.addr()
+        );
+        return;
+    }
+
+    // A read-only primary told us itself; only outages need a second opinion.
+    if reason == Reason::Unreachable && !confirmed(pool).await {
+        return;
+    }
+
+    warn!(
+        "primary is {}, looking for a new one [{}]",
+        reason,
+        pool.addr()
+    );
+
+    let mut promoted = None;
+    for candidate in &candidates {
+        if in_recovery(candidate).await == Some(false) {
+            promoted = Some(candidate.addr().clone());
+            break;
+        }
+    }
+
+    let databases = if let Some(ref promoted) = promoted {
+        let databases = databases::failover(pool.addr(), promoted);
+        info!(
+            "failover to {}:{} in {} databases [{}]",
+            promoted.host,
+            promoted.port,
+            databases,
+            pool.addr()
+        );
+        databases
+    } else {
+        warn!("no replica was promoted, failover failed [{}]", pool.addr());
+        0
+    };
+
+    emit(events::Event::Failover {
+        primary: pool.addr().addr(),
+        promoted: promoted.as_ref().map(Address::addr),
+        reason: reason.to_string(),
+    });
+    record(Event {
+        created_at: SystemTime::now(),
+        primary: pool.addr().addr(),
+        reason,
+        promoted: promoted.as_ref().map(Address::addr),
+        databases,
+    });
+}
+
+/// Peers and the witness, if configured, can't reach the primary either.
+async fn confirmed(pool: &Pool) -> bool {
+    let general = config().config.general.clone();
+    let addr = pool.addr();
+
+    if general.failover_quorum {
+        let (down, peers) = Listener::get().votes(&addr.addr(), PEER_TIMEOUT);
+        if peers == 0 {
+            warn!(
+                "primary is unreachable, but no peers were found to confirm it, not failing over [{}]",
+                addr
+            );
+            return false;
+        }
+        if !quorum(down, peers) {
+            warn!(
+                "primary is unreachable, but only {} of {} peers agree, not failing over [{}]",
+                down, peers, addr
+            );
+            return false;
+        }
+    }
+
+    if let Some(ref url) = general.failover_witness {
+        let duration = pool.lock().config.healthcheck_timeout;
+        if !witness::confirmed(url, addr, duration).await {
+            warn!(
+                "primary is unreachable, but the witness doesn't agree, not failing over [{}]",
+                addr
+            );
+            return false;
+        }
+    }
+
+    true
+}
+
+/// This node and most of its peers agree.
+///
+/// Peers that stopped talking to us are counted but can't vote, so a node
+/// cut off from the rest of the network never has a majority. A node that
+/// hasn't found any peers can't tell, so it doesn't fail over alone.
+fn quorum(down: usize, peers: usize) -> bool {
+    peers > 0 && (down + 1) * 2 > peers + 1
+}
+
+/// Ask the server if it's a replica, using a new connection.
+///
+/// Returns `None` if the server can't be reached.
+async fn in_recovery(pool: &Pool) -> Option<bool> {
+    let (connect_timeout, healthcheck_timeout) = {
+        let guard = pool.lock();
+        (
+            guard.config.connect_timeout,
+            guard.config.healthcheck_timeout,
+        )
+    };
+
+    let mut server = timeout(
+        connect_timeout,
+        Server::connect(pool.addr(), pool.server_options()),
+    )
+    .await
+    .ok()?
+    .ok()?;
+
+    let rows: Vec<String> = timeout(
+        healthcheck_timeout,
+        server.fetch_all("SELECT pg_is_in_recovery()::text"),
+    )
+    .await
+    .ok()?
+    .ok()?;
+
+    rows.first().map(|recovery| recovery == "true")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quorum() {
+        // No other nodes.
+        assert!(!quorum(0, 0));
+        assert!(!quorum(0, 1));
+        assert!(quorum(1, 1));
+        // One of two peers agrees.
+        assert!(quorum(1, 2));
+        // Cut off from both peers.
+        assert!(!quorum(0, 2));
+        assert!(!quorum(1, 3));
+        assert!(quorum(2, 3));
+    }
+}
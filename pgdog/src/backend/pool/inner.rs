@@ -2,13 +2,18 @@
 
 use std::cmp::max;
 use std::collections::VecDeque;
+use std::time::Duration;
 
 use crate::backend::{stats::Counts as BackendCounts, Server};
 use crate::net::messages::BackendKeyData;
+use crate::net::Parameters;
 
 use tokio::time::Instant;
 
-use super::{Ban, Config, Error, Mapping, Oids, Pool, Request, Stats, Taken, Waiter};
+use super::{
+    Ban, Config, Error, Mapping, Oids, Pool, ReplicationLag, Request, Stats, SyncState, Taken,
+    Waiter,
+};
 
 /// Pool internals protected by a mutex.
 #[derive(Default)]
@@ -28,6 +33,9 @@ pub(super) struct Inner {
     pub(super) online: bool,
     /// Pool is paused.
     pub(super) paused: bool,
+    /// Pool is draining: it's not serving new clients
+    /// and closes connections as they are checked in.
+    pub(super) draining: bool,
     /// Track out of sync terminations.
     pub(super) out_of_sync: usize,
     /// How many times servers had to be re-synced
@@ -37,10 +45,22 @@ pub(super) struct Inner {
     pub(super) force_close: usize,
     /// Track connections closed with errors.
     pub(super) errors: usize,
+    /// Reads sent to this primary because all replicas were down.
+    pub(super) replica_fallbacks: usize,
+    /// Connections that returned read-only errors since the last healthcheck.
+    pub(super) read_only_errors: usize,
     /// Stats
     pub(super) stats: Stats,
     /// OIDs.
     pub(super) oids: Option<Oids>,
+    /// Parameters reported by the server on connect.
+    pub(super) params: Option<Parameters>,
+    /// Replication lag, if measured.
+    pub(super) replication_lag: Option<ReplicationLag>,
+    /// Sync state reported by the primary, if this is a replica.
+    pub(super) sync_state: Option<SyncState>,
+    /// Result of the last healthcheck and when it ran.
+    pub(super) healthcheck: Option<(bool, Instant)>,
     /// The pool has been changed and connections should be returned
     /// to the new pool.
     moved: Option<Pool>,
@@ -51,6 +71,7 @@ impl std::fmt::Debug for Inner {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Inner")
             .field("paused", &self.paused)
+            .field("draining", &self.draining)
             .field("taken", &self.taken.len())
             .field("conns", &self.conns.len())
             .field("waiting", &self.waiting.len())
@@ -70,12 +91,19 @@ impl Inner {
             ban: None,
             online: false,
             paused: false,
+            draining: false,
             force_close: 0,
             out_of_sync: 0,
             re_synced: 0,
             errors: 0,
+            replica_fallbacks: 0,
+            read_only_errors: 0,
             stats: Stats::default(),
             oids: None,
+            params: None,
+            replication_lag: None,
+            sync_state: None,
+            healthcheck: None,
             moved: None,
             id,
         }
@@ -127,16 +155,34 @@ impl Inner {
         self.config.max
     }
 
+    /// Maximum number of connections in the pool, including
+    /// the reserve if a client has been waiting longer than the reserve timeout.
+    #[inline]
+    pub(super) fn limit(&self, now: Instant) -> usize {
+        let reserve = self.config.reserve > 0
+            && self.waiting.front().is_some_and(|waiter| {
+                now.saturating_duration_since(waiter.request.created_at)
+                    >= self.config.reserve_timeout
+            });
+
+        if reserve {
+            self.max() + self.config.reserve
+        } else {
+            self.max()
+        }
+    }
+
     /// The pool should create more connections now.
     #[inline]
     pub(super) fn should_create(&self) -> bool {
         let below_min = self.total() < self.min();
         let below_max = self.total() < self.max();
+        let below_limit = self.total() < self.limit(Instant::now());
         let maintain_min = below_min && below_max;
-        let client_needs = below_max && !self.waiting.is_empty() && self.conns.is_empty();
+        let client_needs = below_limit && !self.waiting.is_empty() && self.conns.is_empty();
         let maintenance_on = self.online && !self.paused;
 
-        !self.banned() && (client_needs || maintenance_on && maintain_min)
+        !self.banned() && !self.draining && (client_needs || maintenance_on && maintain_min)
     }
 
     /// Check if the pool ban should be removed.
@@ -233,8 +279,10 @@ impl Inner {
                     server: id,
                     client: waiter.request.id,
                 });
+                let wait_time = now.duration_since(waiter.request.created_at);
                 self.stats.counts.server_assignment_count += 1;
-                self.stats.counts.wait_time += now.duration_since(waiter.request.created_at);
+                self.stats.counts.wait_time += wait_time;
+                self.stats.histograms.wait.observe(wait_time);
             }
         } else {
             self.conns.push(conn);
@@ -252,6 +300,17 @@ impl Inner {
         self.conns.clear();
     }
 
+    /// Close the oldest idle connection, if any.
+    #[inline]
+    pub(super) fn close_one_idle(&mut self) -> bool {
+        if self.conns.is_empty() {
+            false
+        } else {
+            self.conns.remove(0);
+            true
+        }
+    }
+
     /// Take all idle connections and tell active ones to
     /// be returned to a different pool instance.
     #[inline]
@@ -293,6 +352,11 @@ impl Inner {
 
         // Update stats
         self.stats.counts = self.stats.counts + stats;
+        self.stats.histograms = self.stats.histograms + stats;
+
+        if server.take_read_only() {
+            self.read_only_errors += 1;
+        }
 
         // Ban the pool from serving more clients.
         if server.error() {
@@ -301,8 +365,8 @@ impl Inner {
             return result;
         }
 
-        // Pool is offline or paused, connection should be closed.
-        if !self.online || self.paused {
+        // Pool is offline, paused or draining, connection should be closed.
+        if !self.online || self.paused || self.draining {
             result.replenish = false;
             return result;
         }
@@ -323,6 +387,13 @@ impl Inner {
             server.reset_re_synced();
         }
 
+        // Close connections created from the reserve
+        // once nobody is waiting for them.
+        if self.waiting.is_empty() && self.total() >= self.max() {
+            result.replenish = false;
+            return result;
+        }
+
         // Finally, if the server is ok,
         // place the connection back into the idle list.
         if server.can_check_in() {
@@ -362,6 +433,7 @@ impl Inner {
                 created_at: now,
                 reason,
                 ban_timeout: self.config.ban_timeout(),
+                timed: false,
             };
             self.ban = Some(ban);
 
@@ -380,6 +452,23 @@ impl Inner {
         }
     }
 
+    /// Ban the pool until it's unbanned manually or,
+    /// if set, the duration expires.
+    pub(super) fn ban_manually(&mut self, now: Instant, duration: Option<Duration>) {
+        self.ban = Some(Ban {
+            created_at: now,
+            reason: Error::ManualBan,
+            ban_timeout: duration.unwrap_or(self.config.ban_timeout()),
+            timed: duration.is_some(),
+        });
+        self.close_waiters(Error::Banned);
+    }
+
+    /// Remove the pool ban, even if it was banned manually.
+    pub(super) fn unban_manually(&mut self) -> bool {
+        self.ban.take().is_some()
+    }
+
     /// Remove the pool ban unless it' been manually banned.
     #[inline(always)]
     pub fn maybe_unban(&mut self) -> bool {
@@ -444,6 +533,13 @@ mod test {
         assert!(banned);
         assert!(!inner.maybe_unban());
         assert!(inner.banned());
+        assert!(inner.unban_manually());
+        assert!(!inner.banned());
+        inner.ban_manually(Instant::now(), Some(Duration::from_secs(5)));
+        assert!(!inner.maybe_unban());
+        assert!(inner.check_ban(Instant::now() + Duration::from_secs(6)));
+        inner.ban_manually(Instant::now(), None);
+        assert!(!inner.check_ban(Instant::now() + Duration::from_secs(301)));
         let banned = inner.maybe_ban(Instant::now(), Error::ServerError);
         assert!(banned);
 
@@ -465,6 +561,14 @@ mod test {
         );
         assert_eq!(inner.total(), 0); // pool paused;
         inner.paused = false;
+        inner.draining = true;
+        inner.maybe_check_in(
+            Box::new(Server::default()),
+            Instant::now(),
+            BackendCounts::default(),
+        );
+        assert_eq!(inner.total(), 0); // pool draining;
+        inner.draining = false;
         assert!(
             !inner
                 .maybe_check_in(
@@ -560,4 +664,45 @@ mod test {
         // Not checked in because of max age.
         assert_eq!(inner.total(), 0);
     }
+
+    #[test]
+    fn test_reserve() {
+        let mut inner = Inner {
+            online: true,
+            ..Default::default()
+        };
+        inner.config.max = 1;
+        inner.config.reserve = 1;
+        inner.config.reserve_timeout = Duration::from_millis(100);
+        inner.taken.take(&Mapping::default());
+
+        let now = Instant::now();
+        inner.waiting.push_back(Waiter {
+            request: Request {
+                id: BackendKeyData::new(),
+                created_at: now,
+                sync_replica: false,
+            },
+            tx: channel().0,
+        });
+
+        // Client hasn't waited long enough.
+        assert_eq!(inner.limit(now), 1);
+        assert_eq!(inner.limit(now + Duration::from_millis(100)), 2);
+
+        inner.waiting.front_mut().unwrap().request.created_at = now - Duration::from_millis(200);
+        assert!(inner.should_create());
+
+        // Reserve connection is closed on check in.
+        inner.waiting.clear();
+        let server = Box::new(Server::default());
+        inner.taken.take(&Mapping {
+            client: BackendKeyData::new(),
+            server: *server.id(),
+        });
+        assert_eq!(inner.total(), 2);
+        let result = inner.maybe_check_in(server, Instant::now(), BackendCounts::default());
+        assert!(!result.replenish);
+        assert_eq!(inner.total(), 1);
+    }
 }
@@ -0,0 +1,116 @@
+//! Replication lag, polled by the idle healthcheck loop.
+//!
+//! Replicas report how long ago they replayed the last transaction
+//! and how far into the WAL they got. The primary reports its own WAL position,
+//! so we can tell how many bytes each replica is behind.
+
+use std::time::Duration;
+
+use crate::backend::{Error, Server};
+use crate::net::messages::{DataRow, Format};
+
+use super::Config;
+
+/// Replicas that replayed everything they received aren't behind,
+/// even if the primary hasn't written anything in a while.
+const QUERY: &str = "SELECT \
+    pg_is_in_recovery()::text, \
+    (CASE WHEN pg_is_in_recovery() THEN pg_last_wal_replay_lsn() \
+        ELSE pg_current_wal_lsn() END - '0/0'::pg_lsn)::bigint, \
+    CASE WHEN NOT pg_is_in_recovery() \
+        OR pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0 \
+        ELSE COALESCE((EXTRACT(EPOCH FROM now() - pg_last_xact_replay_timestamp()) * 1000)::bigint, 0) \
+    END";
+
+/// Replication lag of a database.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplicationLag {
+    /// The database is a replica.
+    pub replica: bool,
+    /// WAL position replayed by a replica, or written by the primary.
+    pub lsn: u64,
+    /// Time since the replica replayed the last transaction, if it's behind.
+    pub lag: Duration,
+}
+
+impl From<DataRow> for ReplicationLag {
+    fn from(value: DataRow) -> Self {
+        let replica = value.get::<String>(0, Format::Text).unwrap_or_default() == "true";
+        let lsn = value.get::<i64>(1, Format::Text).unwrap_or_default();
+        let lag = value.get::<i64>(2, Format::Text).unwrap_or_default();
+
+        Self {
+            replica,
+            lsn: lsn.max(0) as u64,
+            lag: Duration::from_millis(lag.max(0) as u64),
+        }
+    }
+}
+
+impl ReplicationLag {
+    pub(super) async fn load(server: &mut Server) -> Result<Self, Error> {
+        let lag: Vec<ReplicationLag> = server.fetch_all(QUERY).await?;
+        Ok(lag.into_iter().next().unwrap_or_default())
+    }
+
+    /// Bytes behind the primary, if we know where the primary is.
+    pub fn bytes(&self, primary_lsn: Option<u64>) -> Option<u64> {
+        if self.replica {
+            primary_lsn.map(|lsn| lsn.saturating_sub(self.lsn))
+        } else {
+            Some(0)
+        }
+    }
+
+    /// The replica is too far behind to serve reads.
+    pub fn exceeds(&self, config: &Config, primary_lsn: Option<u64>) -> bool {
+        if !self.replica {
+            return false;
+        }
+
+        let time = config.max_replica_lag.is_some_and(|max| self.lag > max);
+        let bytes = config
+            .max_replica_lag_bytes
+            .is_some_and(|max| self.bytes(primary_lsn).is_some_and(|bytes| bytes > max));
+
+        time || bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exceeds() {
+        let config = Config {
+            max_replica_lag: Some(Duration::from_secs(1)),
+            max_replica_lag_bytes: Some(1024),
+            ..Default::default()
+        };
+        let lag = ReplicationLag {
+            replica: true,
+            lsn: 10_000,
+            lag: Duration::from_millis(500),
+        };
+
+        assert!(!lag.exceeds(&config, None));
+        assert!(!lag.exceeds(&config, Some(10_500)));
+        assert!(lag.exceeds(&config, Some(20_000)));
+        assert_eq!(lag.bytes(Some(20_000)), Some(10_000));
+
+        let behind = ReplicationLag {
+            lag: Duration::from_secs(5),
+            ..lag
+        };
+        assert!(behind.exceeds(&config, None));
+        assert!(!behind.exceeds(&Config::default(), Some(20_000)));
+
+        let primary = ReplicationLag {
+            replica: false,
+            ..behind
+        };
+        assert!(!primary.exceeds(&config, None));
+        assert_eq!(primary.bytes(None), Some(0));
+    }
+}
@@ -8,9 +8,11 @@ pub mod comms;
 pub mod config;
 pub mod connection;
 pub mod error;
+pub mod failover;
 pub mod guard;
 pub mod healthcheck;
 pub mod inner;
+pub mod lag;
 pub mod mapping;
 pub mod monitor;
 pub mod oids;
@@ -20,8 +22,10 @@ pub mod request;
 pub mod shard;
 pub mod state;
 pub mod stats;
+pub mod sync_state;
 pub mod taken;
 pub mod waiting;
+pub mod witness;
 
 pub use address::Address;
 pub use cluster::{Cluster, ClusterConfig, ClusterShardConfig, PoolConfig, ShardingSchema};
@@ -30,6 +34,7 @@ pub use connection::Connection;
 pub use error::Error;
 pub use guard::Guard;
 pub use healthcheck::Healtcheck;
+pub use lag::ReplicationLag;
 use monitor::Monitor;
 pub use oids::Oids;
 pub use pool_impl::Pool;
@@ -38,6 +43,7 @@ pub use request::Request;
 pub use shard::Shard;
 pub use state::State;
 pub use stats::Stats;
+pub use sync_state::SyncState;
 
 use ban::Ban;
 use comms::Comms;
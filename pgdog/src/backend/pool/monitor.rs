@@ -6,6 +6,8 @@
 //!
 //! * the maintenance loop which runs ~3 times per second,
 //! * the healthcheck loop which runs every `idle_healthcheck_interval`
+//!   and measures replication lag and sync state of replicas on the same connection,
+//!   and looks for a new primary if this one is down, if `failover` is on
 //! * the new connection loop which runs every time a client asks
 //!   for a new connection to be created
 //!
@@ -34,14 +36,19 @@
 
 use std::time::Duration;
 
-use super::{Error, Guard, Healtcheck, Oids, Pool, Request};
-use crate::backend::Server;
+use super::{
+    failover::{self, Reason},
+    sync_state::{self, Standby},
+    Error, Guard, Healtcheck, Oids, Pool, ReplicationLag, Request,
+};
+use crate::backend::{databases, Server};
+use crate::events::{emit, Event};
 
 use tokio::time::{interval, sleep, timeout, Instant};
 use tokio::{select, task::spawn};
 use tracing::info;
 
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
 
 static MAINTENANCE: Duration = Duration::from_millis(333);
 
@@ -89,6 +96,14 @@ impl Monitor {
                 sleep(delay).await;
                 Self::healthchecks(pool).await
             });
+
+            // Load custom data types used for decoding rows.
+            let pool = self.pool.clone();
+            spawn(async move {
+                if let Err(err) = Self::fetch_oids(&pool).await {
+                    debug!("failed to load type oids: {} [{}]", err, pool.addr());
+                }
+            });
         }
 
         loop {
@@ -121,7 +136,9 @@ impl Monitor {
                         break;
                     }
 
-                    if should_create {
+                    // Clients wait for a connection to be returned
+                    // to one of the pools sharing the limit.
+                    if should_create && !databases::application_limit_reached(&self.pool) {
                         let ok = self.replenish(connect_timeout).await;
                         if !ok {
                             self.pool.ban(Error::ServerError);
@@ -148,6 +165,9 @@ impl Monitor {
 
         debug!("healthchecks running [{}]", pool.addr());
 
+        // Healthchecks failed in a row.
+        let mut failures = 0;
+
         loop {
             let mut unbanned = false;
             select! {
@@ -160,8 +180,8 @@ impl Monitor {
                             break;
                         }
 
-                        // Pool is paused, skip healtcheck.
-                        if guard.paused {
+                        // Pool is paused or draining, skip healtcheck.
+                        if guard.paused || guard.draining {
                             continue;
                         }
 
@@ -169,8 +189,19 @@ impl Monitor {
 
                     // If the server is okay, remove the ban if it had one.
                     if let Ok(true) = Self::healthcheck(&pool).await {
-                        unbanned = pool.lock().maybe_unban();
+                        failures = 0;
+                        {
+                            let mut guard = pool.lock();
+                            guard.healthcheck = Some((true, Instant::now()));
+                            unbanned = guard.maybe_unban();
+                        }
+                        failover::reachable(&pool);
+                    } else {
+                        failures += 1;
+                        pool.lock().healthcheck = Some((false, Instant::now()));
                     }
+
+                    Self::failover(&pool, failures);
                 }
 
 
@@ -179,12 +210,42 @@ impl Monitor {
 
             if unbanned {
                 info!("pool unbanned due to healtcheck [{}]", pool.addr());
+                emit(Event::PoolUnbanned {
+                    pool: pool.addr().to_string(),
+                });
             }
         }
 
         debug!("healthchecks stopped [{}]", pool.addr());
     }
 
+    /// Look for a new primary if this one is down or refusing writes.
+    ///
+    /// Replicas are checked too, but they aren't anyone's primary,
+    /// so nothing happens.
+    fn failover(pool: &Pool, failures: usize) {
+        let (threshold, read_only) = {
+            let mut guard = pool.lock();
+            let read_only = std::mem::take(&mut guard.read_only_errors) > 0;
+            // Read-only users get these errors on every write.
+            (
+                guard.config.failover_threshold,
+                read_only && !guard.config.read_only,
+            )
+        };
+
+        let Some(threshold) = threshold else {
+            return;
+        };
+
+        // Keep trying every few healthchecks until a replica is promoted.
+        if failures > 0 && failures.is_multiple_of(threshold) {
+            failover::detected(pool, Reason::Unreachable);
+        } else if read_only {
+            failover::detected(pool, Reason::ReadOnly);
+        }
+    }
+
     /// Perform maintenance on the pool periodically.
     async fn maintenance(pool: Pool) {
         let mut tick = interval(MAINTENANCE);
@@ -242,6 +303,9 @@ impl Monitor {
                 let server = Box::new(conn);
 
                 let mut guard = self.pool.lock();
+                if guard.params.is_none() {
+                    guard.params = Some(server.params().clone());
+                }
                 guard.put(server, Instant::now());
             }
 
@@ -257,20 +321,70 @@ impl Monitor {
         ok
     }
 
-    #[allow(dead_code)]
-    async fn fetch_oids(pool: &Pool) -> Result<(), Error> {
-        if pool.lock().oids.is_none() {
-            let oids = Oids::load(&mut pool.get(&Request::default()).await?)
-                .await
-                .ok();
-            if let Some(oids) = oids {
-                pool.lock().oids = Some(oids);
+    /// Load custom data types from the database, using a new connection
+    /// that's closed once done, so pools without clients can stay empty.
+    async fn fetch_oids(pool: &Pool) -> Result<(), crate::backend::Error> {
+        let connect_timeout = {
+            let guard = pool.lock();
+            if guard.oids.is_some() {
+                return Ok(());
             }
-        }
+            guard.config.connect_timeout
+        };
+
+        let mut server = timeout(
+            connect_timeout,
+            Server::connect(pool.addr(), pool.server_options()),
+        )
+        .await
+        .map_err(|_| Error::ConnectTimeout)??;
+        let oids = Oids::load(&mut server).await?;
+        pool.lock().oids = Some(oids);
 
         Ok(())
     }
 
+    /// Measure replication lag, using the healthcheck connection.
+    /// Primaries also report which of their replicas are sync.
+    async fn replication_lag(pool: &Pool, server: &mut Server) {
+        if !pool.lock().config.replication_lag {
+            return;
+        }
+
+        let lag = ReplicationLag::load(server).await;
+        let standbys = match lag {
+            Ok(ref lag) if !lag.replica => Some(Standby::load(server).await),
+            _ => None,
+        };
+
+        match lag {
+            Ok(lag) => {
+                let mut guard = pool.lock();
+                let exceeded = lag.exceeds(&guard.config, None);
+                if exceeded
+                    && !guard
+                        .replication_lag
+                        .is_some_and(|prev| prev.exceeds(&guard.config, None))
+                {
+                    warn!(
+                        "replica is {:.3}s behind, not sending reads to it [{}]",
+                        lag.lag.as_secs_f64(),
+                        pool.addr()
+                    );
+                }
+                guard.replication_lag = Some(lag);
+            }
+
+            Err(err) => error!("replication lag error: {} [{}]", err, pool.addr()),
+        }
+
+        match standbys {
+            Some(Ok(standbys)) => sync_state::update(pool, &standbys),
+            Some(Err(err)) => error!("sync state error: {} [{}]", err, pool.addr()),
+            None => (),
+        }
+    }
+
     /// Perform a periodic healthcheck on the pool.
     async fn healthcheck(pool: &Pool) -> Result<bool, Error> {
         let (conn, healthcheck_timeout, connect_timeout) = {
@@ -287,17 +401,16 @@ impl Monitor {
 
         // Have an idle connection, use that for the healthcheck.
         if let Some(conn) = conn {
-            Healtcheck::mandatory(
-                &mut Guard::new(pool.clone(), conn, Instant::now()),
-                pool,
-                healthcheck_timeout,
-            )
-            .healthcheck()
-            .await?;
+            let mut conn = Guard::new(pool.clone(), conn, Instant::now());
+            Healtcheck::mandatory(&mut conn, pool, healthcheck_timeout)
+                .healthcheck()
+                .await?;
+            Self::replication_lag(pool, &mut conn).await;
 
             Ok(true)
         } else {
-            // Create a new one and close it. once done.
+            // Create a new one and close it once done,
+            // so pools without clients can stay empty.
             info!("creating new healthcheck connection [{}]", pool.addr());
             match timeout(
                 connect_timeout,
@@ -308,7 +421,10 @@ impl Monitor {
                 Ok(Ok(mut server)) => {
                     Healtcheck::mandatory(&mut server, pool, healthcheck_timeout)
                         .healthcheck()
-                        .await?
+                        .await?;
+                    Self::replication_lag(pool, &mut server).await;
+
+                    return Ok(true);
                 }
                 Ok(Err(err)) => {
                     error!("healthcheck error: {} [{}]", err, pool.addr());
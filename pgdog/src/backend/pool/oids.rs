@@ -1,44 +1,164 @@
 //! OIDs used by Postgres for user-created data types.
+//!
+//! Types created by users and extensions (enums, domains, pgvector) don't have
+//! stable OIDs, so we load them from each database at startup. Each cluster
+//! decodes rows for sorting and aggregation with the types of its own databases.
 
-use crate::backend::Error;
-use crate::net::messages::{DataRow, Format};
+use std::collections::{HashMap, HashSet};
 
-use super::Guard;
+use crate::backend::{Error, Server};
+use crate::net::messages::{DataRow, DataType, Format};
 
-#[derive(Debug, Clone, Default, Copy)]
+#[derive(Debug, Clone, Default)]
 pub struct Oids {
     vector: Option<i32>,
+    types: HashMap<i32, DataType>,
+    /// Enum labels, in the order Postgres sorts them.
+    enums: HashMap<i32, HashMap<String, usize>>,
+    /// OIDs used for different types by databases of the same cluster.
+    mismatched: HashSet<i32>,
 }
 
 struct PgType {
     oid: i32,
     typname: String,
+    typtype: String,
+    typbasetype: i32,
 }
 
 impl From<DataRow> for PgType {
     fn from(value: DataRow) -> Self {
         let oid = value.get::<i32>(0, Format::Text).unwrap_or_default();
-        let typname = value.get::<String>(0, Format::Text).unwrap_or_default();
+        let typname = value.get::<String>(1, Format::Text).unwrap_or_default();
+        let typtype = value.get::<String>(2, Format::Text).unwrap_or_default();
+        let typbasetype = value.get::<i32>(3, Format::Text).unwrap_or_default();
 
-        Self { oid, typname }
+        Self {
+            oid,
+            typname,
+            typtype,
+            typbasetype,
+        }
+    }
+}
+
+struct PgEnum {
+    enumtypid: i32,
+    enumlabel: String,
+}
+
+impl From<DataRow> for PgEnum {
+    fn from(value: DataRow) -> Self {
+        let enumtypid = value.get::<i32>(0, Format::Text).unwrap_or_default();
+        let enumlabel = value.get::<String>(1, Format::Text).unwrap_or_default();
+
+        Self {
+            enumtypid,
+            enumlabel,
+        }
     }
 }
 
 impl Oids {
-    pub(super) async fn load(server: &mut Guard) -> Result<Self, Error> {
+    pub(super) async fn load(server: &mut Server) -> Result<Self, Error> {
         let types: Vec<PgType> = server
-            .fetch_all("SELECT oid, typname FROM pg_type WHERE typname IN ('vector')")
+            .fetch_all(
+                "SELECT oid::integer, typname::text, typtype::text, typbasetype::integer \
+                FROM pg_type WHERE typtype IN ('e', 'd') OR typname = 'vector'",
+            )
             .await?;
+        let enums: Vec<PgEnum> = server
+            .fetch_all(
+                "SELECT enumtypid::integer, enumlabel::text \
+                FROM pg_enum ORDER BY enumtypid, enumsortorder",
+            )
+            .await?;
+
+        Ok(Self::from_types(types, enums))
+    }
 
+    fn from_types(types: Vec<PgType>, enums: Vec<PgEnum>) -> Self {
         let mut oids = Oids::default();
+        let mut domains = vec![];
+
+        for label in enums {
+            let labels = oids.enums.entry(label.enumtypid).or_default();
+            labels.insert(label.enumlabel, labels.len());
+        }
 
         for ty in types {
             if ty.typname == "vector" {
                 oids.vector = Some(ty.oid);
+                oids.types.insert(ty.oid, DataType::Vector);
+            } else if ty.typtype == "e" {
+                // Enums are sent as their labels.
+                oids.types.insert(ty.oid, DataType::Text);
+            } else if ty.typtype == "d" {
+                domains.push(ty);
+            }
+        }
+
+        // Domains are encoded like their base type,
+        // which could be another custom type.
+        for domain in domains {
+            let data_type = oids
+                .types
+                .get(&domain.typbasetype)
+                .copied()
+                .unwrap_or(DataType::from_oid(domain.typbasetype));
+            oids.types.insert(domain.oid, data_type);
+            if let Some(labels) = oids.enums.get(&domain.typbasetype).cloned() {
+                oids.enums.insert(domain.oid, labels);
             }
         }
 
-        Ok(oids)
+        oids
+    }
+
+    /// Add types loaded from another database of the same cluster.
+    ///
+    /// OIDs are assigned by each database, so the same OID can be a different
+    /// type on another shard. Rows with those types can't be decoded.
+    pub fn merge(&mut self, other: &Oids) {
+        self.vector = self.vector.or(other.vector);
+        self.mismatched.extend(&other.mismatched);
+
+        for (oid, ty) in &other.types {
+            match self.types.get(oid) {
+                Some(existing) if existing != ty => {
+                    self.mismatched.insert(*oid);
+                }
+                _ => {
+                    self.types.insert(*oid, *ty);
+                }
+            }
+        }
+
+        for (oid, labels) in &other.enums {
+            match self.enums.get(oid) {
+                Some(existing) if existing != labels => {
+                    self.mismatched.insert(*oid);
+                }
+                _ => {
+                    self.enums.insert(*oid, labels.clone());
+                }
+            }
+        }
+    }
+
+    /// The OID is used for different types by databases of the same cluster.
+    pub fn mismatched(&self, oid: i32) -> bool {
+        self.mismatched.contains(&oid)
+    }
+
+    /// Look up a custom data type.
+    pub fn data_type(&self, oid: i32) -> Option<DataType> {
+        self.types.get(&oid).copied()
+    }
+
+    /// Position of an enum label in the order Postgres sorts them.
+    pub fn enum_order(&self, oid: i32, label: &str) -> Option<usize> {
+        self.enums.get(&oid)?.get(label).copied()
     }
 
     /// Get pgvector oid, if installed.
@@ -46,3 +166,77 @@ impl Oids {
         self.vector
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn ty(oid: i32, typname: &str, typtype: &str, typbasetype: i32) -> PgType {
+        PgType {
+            oid,
+            typname: typname.into(),
+            typtype: typtype.into(),
+            typbasetype,
+        }
+    }
+
+    fn label(enumtypid: i32, enumlabel: &str) -> PgEnum {
+        PgEnum {
+            enumtypid,
+            enumlabel: enumlabel.into(),
+        }
+    }
+
+    #[test]
+    fn test_oids() {
+        let oids = Oids::from_types(
+            vec![
+                ty(90001, "vector", "b", 0),
+                ty(90002, "mood", "e", 0),
+                ty(90003, "embedding", "d", 90001),
+                ty(90004, "positive", "d", 20),
+                ty(90005, "feeling", "d", 90002),
+            ],
+            vec![
+                label(90002, "sad"),
+                label(90002, "ok"),
+                label(90002, "happy"),
+            ],
+        );
+
+        assert_eq!(oids.vector(), Some(90001));
+        assert_eq!(oids.data_type(90001), Some(DataType::Vector));
+        assert_eq!(oids.data_type(90002), Some(DataType::Text));
+        assert_eq!(oids.data_type(90003), Some(DataType::Vector));
+        assert_eq!(oids.data_type(90004), Some(DataType::Bigint));
+        assert_eq!(oids.data_type(90006), None);
+
+        // Sorted by enumsortorder, not by label.
+        assert_eq!(oids.enum_order(90002, "sad"), Some(0));
+        assert_eq!(oids.enum_order(90002, "happy"), Some(2));
+        assert_eq!(oids.enum_order(90005, "ok"), Some(1));
+        assert_eq!(oids.enum_order(90002, "angry"), None);
+
+        // Another database of the same cluster.
+        let mut cluster = Oids::default();
+        cluster.merge(&oids);
+        assert_eq!(cluster.vector(), Some(90001));
+        assert_eq!(cluster.enum_order(90002, "ok"), Some(1));
+        assert!(!cluster.mismatched(90002));
+
+        // Same OIDs used for other types, or enums with other labels.
+        let shard = Oids::from_types(
+            vec![
+                ty(90001, "vector", "b", 0),
+                ty(90002, "mood", "e", 0),
+                ty(90004, "positive", "d", 23),
+            ],
+            vec![label(90002, "sad"), label(90002, "happy")],
+        );
+        cluster.merge(&shard);
+        assert!(!cluster.mismatched(90001));
+        assert!(cluster.mismatched(90002));
+        assert!(!cluster.mismatched(90003));
+        assert!(cluster.mismatched(90004));
+    }
+}
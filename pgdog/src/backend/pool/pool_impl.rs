@@ -7,17 +7,19 @@ use std::time::Duration;
 use once_cell::sync::Lazy;
 use parking_lot::{lock_api::MutexGuard, Mutex, RawMutex};
 use tokio::time::Instant;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::backend::{Server, ServerOptions};
+use crate::backend::{databases::User, Server, ServerOptions};
 use crate::config::PoolerMode;
+use crate::events::{emit, Event};
 use crate::net::messages::BackendKeyData;
-use crate::net::Parameter;
+use crate::net::{Parameter, Parameters};
+use crate::stats::errors::{record, ErrorEvent};
 
 use super::inner::CheckInResult;
 use super::{
-    Address, Comms, Config, Error, Guard, Healtcheck, Inner, Monitor, Oids, PoolConfig, Request,
-    State, Waiting,
+    Address, Comms, Config, Error, Guard, Healtcheck, Inner, Monitor, Oids, PoolConfig,
+    ReplicationLag, Request, State, SyncState, Waiting,
 };
 
 static ID_COUNTER: Lazy<Arc<AtomicU64>> = Lazy::new(|| Arc::new(AtomicU64::new(0)));
@@ -37,6 +39,7 @@ pub(crate) struct InnerSync {
     pub(super) inner: Mutex<Inner>,
     pub(super) id: u64,
     pub(super) config: Config,
+    pub(super) owner: Option<User>,
 }
 
 impl std::fmt::Debug for Pool {
@@ -58,6 +61,7 @@ impl Pool {
                 inner: Mutex::new(Inner::new(config.config, id)),
                 id,
                 config: config.config,
+                owner: config.owner.clone(),
             }),
         }
     }
@@ -67,7 +71,7 @@ impl Pool {
     pub fn new_test() -> Self {
         let config = PoolConfig {
             address: Address::new_test(),
-            config: Config::default(),
+            ..Default::default()
         };
 
         Self::new(&config)
@@ -113,8 +117,10 @@ impl Pool {
             // Try this only once. If the pool still
             // has an error after a checkout attempt,
             // return error.
-            if unban && guard.banned() {
-                guard.maybe_unban();
+            if unban && guard.banned() && guard.maybe_unban() {
+                emit(Event::PoolUnbanned {
+                    pool: self.addr().to_string(),
+                });
             }
 
             if guard.banned() {
@@ -126,9 +132,10 @@ impl Pool {
             if conn.is_some() {
                 guard.stats.counts.wait_time += elapsed;
                 guard.stats.counts.server_assignment_count += 1;
+                guard.stats.histograms.wait.observe(elapsed);
             }
 
-            (conn, granted_at, guard.paused)
+            (conn, granted_at, guard.paused || guard.draining)
         };
 
         if paused {
@@ -194,6 +201,7 @@ impl Pool {
         Pool::new(&PoolConfig {
             address: self.addr().clone(),
             config: *self.lock().config(),
+            owner: self.owner().cloned(),
         })
     }
 
@@ -220,6 +228,14 @@ impl Pool {
                 Error::ServerError,
                 self.addr()
             );
+            record(ErrorEvent::pool(
+                self.addr(),
+                format!("pool banned on check in: {}", Error::ServerError),
+            ));
+            emit(Event::PoolBanned {
+                pool: self.addr().to_string(),
+                reason: Error::ServerError.to_string(),
+            });
         }
 
         // Notify maintenance that we need a new connection because
@@ -237,7 +253,8 @@ impl Pool {
     /// Send a cancellation request if the client is connected to a server.
     pub async fn cancel(&self, id: &BackendKeyData) -> Result<(), super::super::Error> {
         if let Some(server) = self.peer(id) {
-            Server::cancel(self.addr(), &server).await?;
+            let connect_timeout = self.lock().config.connect_timeout;
+            Server::cancel(self.addr(), &server, connect_timeout).await?;
         }
 
         Ok(())
@@ -261,6 +278,14 @@ impl Pool {
 
         if banned {
             error!("pool banned explicitly: {} [{}]", reason, self.addr());
+            record(ErrorEvent::pool(
+                self.addr(),
+                format!("pool banned: {}", reason),
+            ));
+            emit(Event::PoolBanned {
+                pool: self.addr().to_string(),
+                reason: reason.to_string(),
+            });
         }
     }
 
@@ -269,9 +294,38 @@ impl Pool {
         let unbanned = self.lock().maybe_unban();
         if unbanned {
             info!("pool unbanned manually [{}]", self.addr());
+            emit(Event::PoolUnbanned {
+                pool: self.addr().to_string(),
+            });
         }
     }
 
+    /// Take the pool out of rotation until it's unbanned manually or,
+    /// if set, the duration expires.
+    pub fn ban_manually(&self, duration: Option<Duration>) {
+        self.lock().ban_manually(Instant::now(), duration);
+        warn!("pool banned manually [{}]", self.addr());
+        record(ErrorEvent::pool(self.addr(), "pool banned manually"));
+        emit(Event::PoolBanned {
+            pool: self.addr().to_string(),
+            reason: "banned manually".into(),
+        });
+    }
+
+    /// Remove any ban, including manual ones.
+    ///
+    /// Returns false if the pool wasn't banned.
+    pub fn unban_manually(&self) -> bool {
+        let unbanned = self.lock().unban_manually();
+        if unbanned {
+            info!("pool unbanned manually [{}]", self.addr());
+            emit(Event::PoolUnbanned {
+                pool: self.addr().to_string(),
+            });
+        }
+        unbanned
+    }
+
     /// Connection pool unique identifier.
     pub(crate) fn id(&self) -> u64 {
         self.inner.id
@@ -292,16 +346,26 @@ impl Pool {
 
             from_guard.online = false;
             let (idle, taken) = from_guard.move_conns_to(destination);
+            to_guard.set_taken(taken);
+            // Close idle connections over the new pool size. Checked out ones
+            // are closed when they're checked in.
             for server in idle {
-                to_guard.put(server, now);
+                if to_guard.total() < to_guard.max() {
+                    to_guard.put(server, now);
+                }
             }
-            to_guard.set_taken(taken);
         }
 
         destination.launch();
         self.shutdown();
     }
 
+    /// Continue counting statistics in the other pool.
+    pub(crate) fn copy_stats_to(&self, destination: &Pool) {
+        let stats = self.lock().stats;
+        destination.lock().stats = stats;
+    }
+
     /// The two pools refer to the same database.
     pub(crate) fn can_move_conns_to(&self, destination: &Pool) -> bool {
         self.addr() == destination.addr()
@@ -326,6 +390,33 @@ impl Pool {
         self.comms().ready.notify_waiters();
     }
 
+    /// Stop serving new clients and close server connections
+    /// once their transactions are finished.
+    pub fn drain(&self) {
+        let mut guard = self.lock();
+
+        guard.draining = true;
+        guard.dump_idle();
+    }
+
+    /// Close one idle connection, to make room for another pool.
+    ///
+    /// Returns false if there were none.
+    pub(crate) fn close_idle_conn(&self) -> bool {
+        self.lock().close_one_idle()
+    }
+
+    /// Start serving clients again after a drain.
+    pub fn undrain(&self) {
+        self.lock().draining = false;
+        self.comms().ready.notify_waiters();
+    }
+
+    /// Pool is draining.
+    pub fn draining(&self) -> bool {
+        self.lock().draining
+    }
+
     /// Shutdown the pool.
     pub fn shutdown(&self) {
         let mut guard = self.lock();
@@ -355,8 +446,13 @@ impl Pool {
         &self.inner.addr
     }
 
+    /// User and database of the cluster this pool belongs to.
+    pub(crate) fn owner(&self) -> Option<&User> {
+        self.inner.owner.as_ref()
+    }
+
     /// Get startup parameters for new server connections.
-    pub(super) fn server_options(&self) -> ServerOptions {
+    pub(crate) fn server_options(&self) -> ServerOptions {
         let mut params = vec![
             Parameter {
                 name: "application_name".into(),
@@ -407,6 +503,34 @@ impl Pool {
 
     /// Fetch OIDs for user-defined data types.
     pub fn oids(&self) -> Option<Oids> {
-        self.lock().oids
+        self.lock().oids.clone()
+    }
+
+    /// Parameters reported by the server, if connected at least once.
+    pub fn server_params(&self) -> Option<Parameters> {
+        self.lock().params.clone()
+    }
+
+    /// Replication lag measured by the last healthcheck, if any.
+    pub fn replication_lag(&self) -> Option<ReplicationLag> {
+        self.lock().replication_lag
+    }
+
+    /// Sync state reported by the primary, if this is a replica.
+    pub fn sync_state(&self) -> Option<SyncState> {
+        self.lock().sync_state
+    }
+
+    /// Replica found by replica discovery, not in the config.
+    pub fn discovered(&self) -> bool {
+        self.lock().config.discovered
+    }
+
+    /// The replica is too far behind the primary to serve reads.
+    pub fn lagging(&self, primary_lsn: Option<u64>) -> bool {
+        let guard = self.lock();
+        guard
+            .replication_lag
+            .is_some_and(|lag| lag.exceeds(&guard.config, primary_lsn))
     }
 }
@@ -15,7 +15,7 @@ use tracing::error;
 use crate::config::LoadBalancingStrategy;
 use crate::net::messages::BackendKeyData;
 
-use super::{Error, Guard, Pool, PoolConfig, Request};
+use super::{Config, Error, Guard, Pool, PoolConfig, Request};
 
 /// Replicas pools.
 #[derive(Clone, Default, Debug)]
@@ -38,7 +38,19 @@ impl Replicas {
             .map(|c| c.config.checkout_timeout())
             .sum::<Duration>();
         Self {
-            pools: addrs.iter().map(Pool::new).collect(),
+            pools: addrs
+                .iter()
+                .map(|addr| {
+                    Pool::new(&PoolConfig {
+                        address: addr.address.clone(),
+                        config: Config {
+                            replication_lag: true,
+                            ..addr.config
+                        },
+                        owner: addr.owner.clone(),
+                    })
+                })
+                .collect(),
             checkout_timeout,
             round_robin: Arc::new(AtomicUsize::new(0)),
             lb_strategy,
@@ -46,8 +58,21 @@ impl Replicas {
     }
 
     /// Get a live connection from the pool.
-    pub async fn get(&self, request: &Request, primary: &Option<Pool>) -> Result<Guard, Error> {
-        match timeout(self.checkout_timeout, self.get_internal(request, primary)).await {
+    ///
+    /// Replicas too far behind the primary, at `primary_lsn`, are skipped
+    /// unless all of them are.
+    pub async fn get(
+        &self,
+        request: &Request,
+        primary: &Option<Pool>,
+        primary_lsn: Option<u64>,
+    ) -> Result<Guard, Error> {
+        match timeout(
+            self.checkout_timeout,
+            self.get_internal(request, primary, primary_lsn),
+        )
+        .await
+        {
             Ok(Ok(conn)) => Ok(conn),
             Ok(Err(err)) => Err(err),
             Err(_) => Err(Error::ReplicaCheckoutTimeout),
@@ -107,10 +132,23 @@ impl Replicas {
         &self.pools
     }
 
+    /// At least one replica is a synchronous standby.
+    pub fn has_sync(&self) -> bool {
+        self.pools
+            .iter()
+            .any(|pool| pool.sync_state().is_some_and(|state| state.sync()))
+    }
+
+    /// All replicas are too far behind the primary.
+    pub fn lagging(&self, primary_lsn: Option<u64>) -> bool {
+        !self.pools.is_empty() && self.pools.iter().all(|pool| pool.lagging(primary_lsn))
+    }
+
     async fn get_internal(
         &self,
         request: &Request,
         primary: &Option<Pool>,
+        primary_lsn: Option<u64>,
     ) -> Result<Guard, Error> {
         let mut unbanned = false;
         loop {
@@ -120,6 +158,32 @@ impl Replicas {
                 candidates.push(primary);
             }
 
+            // Reads that must see the latest commits go to sync replicas,
+            // or the primary, if it takes reads, never to async replicas.
+            if request.sync_replica {
+                let sync = |pool: &Pool| {
+                    pool.sync_state().is_some_and(|state| state.sync())
+                        || primary
+                            .as_ref()
+                            .is_some_and(|primary| primary.addr() == pool.addr())
+                };
+                candidates.retain(|pool| sync(pool));
+                if candidates.is_empty() {
+                    return Err(Error::NoSyncReplica);
+                }
+            }
+
+            // Send clients to other databases while a pool is draining.
+            // If they are all draining, clients will wait for them.
+            if candidates.iter().any(|pool| !pool.draining()) {
+                candidates.retain(|pool| !pool.draining());
+            }
+
+            // Don't read stale data from replicas that fell behind.
+            if candidates.iter().any(|pool| !pool.lagging(primary_lsn)) {
+                candidates.retain(|pool| !pool.lagging(primary_lsn));
+            }
+
             use LoadBalancingStrategy::*;
 
             match self.lb_strategy {
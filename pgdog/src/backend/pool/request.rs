@@ -7,6 +7,8 @@ use crate::net::messages::BackendKeyData;
 pub struct Request {
     pub id: BackendKeyData,
     pub created_at: Instant,
+    /// Prefer replicas the primary waits for before confirming commits.
+    pub sync_replica: bool,
 }
 
 impl Request {
@@ -14,6 +16,7 @@ impl Request {
         Self {
             id,
             created_at: Instant::now(),
+            sync_replica: false,
         }
     }
 }
@@ -1,11 +1,13 @@
 //! A shard is a collection of replicas and a primary.
 
+use tracing::warn;
+
 use crate::{
     config::{LoadBalancingStrategy, ReadWriteSplit, Role},
     net::messages::BackendKeyData,
 };
 
-use super::{Error, Guard, Pool, PoolConfig, Replicas, Request};
+use super::{Address, Config, Error, Guard, Pool, PoolConfig, Replicas, Request};
 
 /// Primary and replicas.
 #[derive(Clone, Default, Debug)]
@@ -13,6 +15,8 @@ pub struct Shard {
     pub(super) primary: Option<Pool>,
     pub(super) replicas: Replicas,
     pub(super) rw_split: ReadWriteSplit,
+    /// Send reads to the primary if all replicas are down.
+    pub(super) replica_fallback: bool,
 }
 
 impl Shard {
@@ -22,14 +26,25 @@ impl Shard {
         replicas: &[PoolConfig],
         lb_strategy: LoadBalancingStrategy,
         rw_split: ReadWriteSplit,
+        replica_fallback: bool,
     ) -> Self {
-        let primary = primary.as_ref().map(Pool::new);
+        let primary = primary.as_ref().map(|primary| {
+            Pool::new(&PoolConfig {
+                address: primary.address.clone(),
+                config: Config {
+                    replication_lag: !replicas.is_empty(),
+                    ..primary.config
+                },
+                owner: primary.owner.clone(),
+            })
+        });
         let replicas = Replicas::new(replicas, lb_strategy);
 
         Self {
             primary,
             replicas,
             rw_split,
+            replica_fallback,
         }
     }
 
@@ -53,12 +68,45 @@ impl Shard {
         } else {
             use ReadWriteSplit::*;
 
-            let primary = match self.rw_split {
+            // Reads that must see the latest commits can't go to async replicas.
+            if request.sync_replica && !self.replicas.has_sync() {
+                return self
+                    .primary
+                    .as_ref()
+                    .ok_or(Error::NoSyncReplica)?
+                    .get(request)
+                    .await;
+            }
+
+            let primary_lsn = self.primary_lsn();
+
+            // All replicas fell behind, read from the primary instead,
+            // unless it's not supposed to serve reads.
+            if self.replicas.lagging(primary_lsn) {
+                match (self.rw_split, &self.primary) {
+                    (IncludePrimary, Some(primary)) => return primary.get(request).await,
+                    (ExcludePrimary, _) => return Err(Error::AllReplicasLagging),
+                    _ => (),
+                }
+            }
+
+            let candidate = match self.rw_split {
                 IncludePrimary => &self.primary,
                 ExcludePrimary => &None,
             };
 
-            self.replicas.get(request, primary).await
+            match self.replicas.get(request, candidate, primary_lsn).await {
+                Err(Error::AllReplicasDown) if self.replica_fallback => {
+                    let primary = self.primary.as_ref().ok_or(Error::AllReplicasDown)?;
+                    warn!(
+                        "all replicas are down, sending read to primary [{}]",
+                        primary.addr()
+                    );
+                    primary.lock().replica_fallbacks += 1;
+                    primary.get(request).await
+                }
+                result => result,
+            }
         }
     }
 
@@ -95,9 +143,106 @@ impl Shard {
             primary: self.primary.as_ref().map(|primary| primary.duplicate()),
             replicas: self.replicas.duplicate(),
             rw_split: self.rw_split,
+            replica_fallback: self.replica_fallback,
         }
     }
 
+    /// Make the promoted replica the primary. The old primary becomes a replica.
+    ///
+    /// Returns false if this shard doesn't have that primary and replica.
+    pub(crate) fn failover(&mut self, primary: &Address, promoted: &Address) -> bool {
+        if !self
+            .primary
+            .as_ref()
+            .is_some_and(|pool| pool.addr().same_server(primary))
+        {
+            return false;
+        }
+
+        let Some(position) = self
+            .replicas
+            .pools
+            .iter()
+            .position(|pool| pool.addr().same_server(promoted))
+        else {
+            return false;
+        };
+
+        let promoted = self.replicas.pools.remove(position);
+        if let Some(old) = self.primary.replace(promoted) {
+            self.replicas.pools.push(old);
+        }
+
+        true
+    }
+
+    /// Add replicas found streaming from the primary on these hosts, listening
+    /// on `port`, and remove discovered replicas that are gone. Replicas from the config
+    /// are never removed.
+    ///
+    /// Returns the pools added and removed.
+    pub(crate) fn discover(
+        &mut self,
+        primary: &Address,
+        hosts: &[String],
+        port: u16,
+    ) -> (Vec<Pool>, Vec<Pool>) {
+        let Some(template) = self
+            .primary
+            .as_ref()
+            .filter(|pool| pool.addr().same_server(primary))
+            .cloned()
+        else {
+            return (vec![], vec![]);
+        };
+
+        let config = Config {
+            discovered: true,
+            replication_lag: true,
+            ..*template.lock().config()
+        };
+
+        let (removed, mut pools): (Vec<_>, Vec<_>) =
+            self.replicas.pools.drain(..).partition(|pool| {
+                pool.lock().config.discovered && !hosts.contains(&pool.addr().host)
+            });
+
+        let mut added = vec![];
+        for host in hosts {
+            let address = Address {
+                host: host.clone(),
+                port,
+                ..template.addr().clone()
+            };
+
+            if address.same_server(template.addr())
+                || pools.iter().any(|pool| pool.addr().same_server(&address))
+            {
+                continue;
+            }
+
+            let pool = Pool::new(&PoolConfig {
+                address,
+                config,
+                owner: template.owner().cloned(),
+            });
+            pools.push(pool.clone());
+            added.push(pool);
+        }
+
+        if !pools.is_empty() {
+            template.lock().config.replication_lag = true;
+        }
+
+        self.replicas.checkout_timeout = pools
+            .iter()
+            .map(|pool| pool.lock().config.checkout_timeout())
+            .sum();
+        self.replicas.pools = pools;
+
+        (added, removed)
+    }
+
     /// Cancel a query if one is running.
     pub async fn cancel(&self, id: &BackendKeyData) -> Result<(), super::super::Error> {
         if let Some(ref primary) = self.primary {
@@ -116,6 +261,14 @@ impl Shard {
             .collect()
     }
 
+    /// WAL position of the primary, measured by its healthcheck.
+    pub fn primary_lsn(&self) -> Option<u64> {
+        self.primary
+            .as_ref()
+            .and_then(|primary| primary.replication_lag())
+            .map(|lag| lag.lsn)
+    }
+
     pub fn pools_with_roles(&self) -> Vec<(Role, Pool)> {
         let mut pools = vec![];
         if let Some(primary) = self.primary.clone() {
@@ -146,7 +299,9 @@ impl Shard {
 mod test {
     use std::collections::BTreeSet;
 
-    use crate::backend::pool::{Address, Config};
+    use std::time::Duration;
+
+    use crate::backend::pool::{Address, Config, ReplicationLag, SyncState};
 
     use super::*;
 
@@ -156,12 +311,12 @@ mod test {
 
         let primary = &Some(PoolConfig {
             address: Address::new_test(),
-            config: Config::default(),
+            ..Default::default()
         });
 
         let replicas = &[PoolConfig {
             address: Address::new_test(),
-            config: Config::default(),
+            ..Default::default()
         }];
 
         let shard = Shard::new(
@@ -169,6 +324,7 @@ mod test {
             replicas,
             LoadBalancingStrategy::Random,
             ReadWriteSplit::ExcludePrimary,
+            false,
         );
         shard.launch();
 
@@ -182,18 +338,57 @@ mod test {
         shard.shutdown();
     }
 
+    #[tokio::test]
+    async fn test_exclude_primary_lagging() {
+        crate::logger();
+
+        let primary = &Some(PoolConfig {
+            address: Address::new_test(),
+            ..Default::default()
+        });
+
+        let replicas = &[PoolConfig {
+            address: Address::new_test(),
+            config: Config {
+                max_replica_lag: Some(Duration::from_secs(1)),
+                ..Default::default()
+            },
+            ..Default::default()
+        }];
+
+        let shard = Shard::new(
+            primary,
+            replicas,
+            LoadBalancingStrategy::Random,
+            ReadWriteSplit::ExcludePrimary,
+            false,
+        );
+        shard.launch();
+
+        shard.replicas.pools[0].lock().replication_lag = Some(ReplicationLag {
+            replica: true,
+            lsn: 0,
+            lag: Duration::from_secs(10),
+        });
+
+        let err = shard.replica(&Request::default()).await.unwrap_err();
+        assert!(matches!(err, Error::AllReplicasLagging));
+
+        shard.shutdown();
+    }
+
     #[tokio::test]
     async fn test_include_primary() {
         crate::logger();
 
         let primary = &Some(PoolConfig {
             address: Address::new_test(),
-            config: Config::default(),
+            ..Default::default()
         });
 
         let replicas = &[PoolConfig {
             address: Address::new_test(),
-            config: Config::default(),
+            ..Default::default()
         }];
 
         let shard = Shard::new(
@@ -201,6 +396,7 @@ mod test {
             replicas,
             LoadBalancingStrategy::Random,
             ReadWriteSplit::IncludePrimary,
+            false,
         );
         shard.launch();
         let mut ids = BTreeSet::new();
@@ -214,4 +410,168 @@ mod test {
 
         assert_eq!(ids.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_sync_replica() {
+        crate::logger();
+
+        let shard = Shard::new(
+            &Some(PoolConfig {
+                address: Address::new_test(),
+                ..Default::default()
+            }),
+            &[PoolConfig {
+                address: Address::new_test(),
+                ..Default::default()
+            }],
+            LoadBalancingStrategy::Random,
+            ReadWriteSplit::ExcludePrimary,
+            false,
+        );
+        shard.launch();
+
+        let request = Request {
+            sync_replica: true,
+            ..Default::default()
+        };
+        let primary_id = shard.primary.as_ref().unwrap().id();
+        let replica = &shard.replicas.pools[0];
+
+        // No sync replica, read from the primary.
+        let conn = shard.replica(&request).await.unwrap();
+        assert_eq!(conn.pool.id(), primary_id);
+        drop(conn);
+
+        replica.lock().sync_state = Some(SyncState::Sync);
+        let conn = shard.replica(&request).await.unwrap();
+        assert_eq!(conn.pool.id(), replica.id());
+        drop(conn);
+
+        replica.lock().sync_state = Some(SyncState::Async);
+        let conn = shard.replica(&request).await.unwrap();
+        assert_eq!(conn.pool.id(), primary_id);
+        drop(conn);
+
+        shard.shutdown();
+    }
+
+    #[test]
+    fn test_failover() {
+        let address = |port| Address {
+            port,
+            ..Address::new_test()
+        };
+
+        let mut shard = Shard::new(
+            &Some(PoolConfig {
+                address: address(5432),
+                ..Default::default()
+            }),
+            &[5433, 5434].map(|port| PoolConfig {
+                address: address(port),
+                ..Default::default()
+            }),
+            LoadBalancingStrategy::Random,
+            ReadWriteSplit::ExcludePrimary,
+            false,
+        );
+
+        // Not this shard's primary.
+        assert!(!shard.failover(&address(5433), &address(5434)));
+        assert!(shard.failover(&address(5432), &address(5434)));
+
+        assert_eq!(shard.primary.as_ref().unwrap().addr().port, 5434);
+        let replicas = shard
+            .replicas
+            .pools()
+            .iter()
+            .map(|pool| pool.addr().port)
+            .collect::<Vec<_>>();
+        assert_eq!(replicas, vec![5433, 5432]);
+    }
+
+    #[test]
+    fn test_discover() {
+        let primary = Address::new_test();
+        let configured = Address {
+            host: "10.0.0.1".into(),
+            ..Address::new_test()
+        };
+
+        let mut shard = Shard::new(
+            &Some(PoolConfig {
+                address: primary.clone(),
+                ..Default::default()
+            }),
+            &[PoolConfig {
+                address: configured,
+                ..Default::default()
+            }],
+            LoadBalancingStrategy::Random,
+            ReadWriteSplit::ExcludePrimary,
+            false,
+        );
+        let hosts = |shard: &Shard| {
+            shard
+                .replicas
+                .pools()
+                .iter()
+                .map(|pool| pool.addr().host.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let (added, removed) = shard.discover(
+            &primary,
+            &["10.0.0.1".into(), "10.0.0.2".into()],
+            primary.port,
+        );
+        assert_eq!(added.len(), 1);
+        assert!(removed.is_empty());
+        assert_eq!(hosts(&shard), vec!["10.0.0.1", "10.0.0.2"]);
+        assert_eq!(added[0].addr().port, primary.port);
+
+        // Discovered replica disconnected, the configured one stays.
+        let (added, removed) = shard.discover(&primary, &[], primary.port);
+        assert!(added.is_empty());
+        assert_eq!(removed[0].addr().host, "10.0.0.2");
+        assert_eq!(hosts(&shard), vec!["10.0.0.1"]);
+    }
+
+    #[tokio::test]
+    async fn test_replica_fallback_to_primary() {
+        crate::logger();
+
+        let primary = &Some(PoolConfig {
+            address: Address::new_test(),
+            ..Default::default()
+        });
+
+        let replicas = &[PoolConfig {
+            address: Address::new_test(),
+            ..Default::default()
+        }];
+
+        for fallback in [false, true] {
+            let shard = Shard::new(
+                primary,
+                replicas,
+                LoadBalancingStrategy::Random,
+                ReadWriteSplit::ExcludePrimary,
+                fallback,
+            );
+            // Replica is offline.
+            let primary = shard.primary.as_ref().unwrap();
+            primary.launch();
+
+            let conn = shard.replica(&Request::default()).await;
+            if fallback {
+                assert_eq!(conn.unwrap().pool.id(), primary.id());
+                assert_eq!(primary.state().replica_fallbacks, 1);
+            } else {
+                assert!(matches!(conn, Err(Error::AllReplicasDown)));
+            }
+
+            shard.shutdown();
+        }
+    }
 }
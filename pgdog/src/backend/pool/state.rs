@@ -22,6 +22,8 @@ pub struct State {
     pub config: Config,
     /// The pool is paused.
     pub paused: bool,
+    /// The pool is draining.
+    pub draining: bool,
     /// Number of clients waiting for a connection.
     pub waiting: usize,
     /// Pool ban.
@@ -34,12 +36,16 @@ pub struct State {
     pub out_of_sync: usize,
     /// Re-synced servers.
     pub re_synced: usize,
+    /// Reads sent to the primary because all replicas were down.
+    pub replica_fallbacks: usize,
     /// Statistics
     pub stats: Stats,
     /// Max wait.
     pub maxwait: Duration,
     /// Pool mode
     pub pooler_mode: PoolerMode,
+    /// Result of the last healthcheck and how long ago it ran.
+    pub healthcheck: Option<(bool, Duration)>,
 }
 
 impl State {
@@ -55,12 +61,14 @@ impl State {
             empty: guard.idle() == 0,
             config: guard.config,
             paused: guard.paused,
+            draining: guard.draining,
             waiting: guard.waiting.len(),
             ban: guard.ban,
             banned: guard.ban.is_some(),
             errors: guard.errors,
             out_of_sync: guard.out_of_sync,
             re_synced: guard.re_synced,
+            replica_fallbacks: guard.replica_fallbacks,
             stats: guard.stats,
             maxwait: guard
                 .waiting
@@ -69,6 +77,9 @@ impl State {
                 .map(|req| now.duration_since(req.request.created_at))
                 .unwrap_or(Duration::ZERO),
             pooler_mode: guard.config().pooler_mode,
+            healthcheck: guard
+                .healthcheck
+                .map(|(ok, at)| (ok, now.saturating_duration_since(at))),
         }
     }
 }
@@ -1,11 +1,12 @@
 //! Pool stats.
 
 use crate::backend::stats::Counts as BackendCounts;
+use crate::stats::Histogram;
 
 use std::{
     iter::Sum,
     ops::{Add, Div, Sub},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 #[derive(Debug, Clone, Default, Copy)]
@@ -22,6 +23,10 @@ pub struct Counts {
     pub bind_count: usize,
     pub rollbacks: usize,
     pub healthchecks: usize,
+    pub errors: usize,
+    pub reads: usize,
+    pub writes: usize,
+    pub cross_shard: usize,
 }
 
 impl Sub for Counts {
@@ -43,6 +48,10 @@ impl Sub for Counts {
             bind_count: self.parse_count.saturating_sub(rhs.bind_count),
             rollbacks: self.rollbacks.saturating_sub(rhs.rollbacks),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
+            errors: self.errors.saturating_sub(rhs.errors),
+            reads: self.reads.saturating_sub(rhs.reads),
+            writes: self.writes.saturating_sub(rhs.writes),
+            cross_shard: self.cross_shard.saturating_sub(rhs.cross_shard),
         }
     }
 }
@@ -64,6 +73,10 @@ impl Div<usize> for Counts {
             bind_count: self.parse_count.saturating_div(rhs),
             rollbacks: self.rollbacks.saturating_div(rhs),
             healthchecks: self.healthchecks.saturating_div(rhs),
+            errors: self.errors.saturating_div(rhs),
+            reads: self.reads.saturating_div(rhs),
+            writes: self.writes.saturating_div(rhs),
+            cross_shard: self.cross_shard.saturating_div(rhs),
         }
     }
 }
@@ -85,6 +98,10 @@ impl Add<BackendCounts> for Counts {
             bind_count: self.bind_count + rhs.bind,
             rollbacks: self.rollbacks + rhs.rollbacks,
             healthchecks: self.healthchecks + rhs.healthchecks,
+            errors: self.errors + rhs.errors,
+            reads: self.reads + rhs.reads,
+            writes: self.writes + rhs.writes,
+            cross_shard: self.cross_shard + rhs.cross_shard,
         }
     }
 }
@@ -119,17 +136,60 @@ impl Add for Counts {
             bind_count: self.parse_count.saturating_add(rhs.bind_count),
             rollbacks: self.rollbacks.saturating_add(rhs.rollbacks),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
+            errors: self.errors.saturating_add(rhs.errors),
+            reads: self.reads.saturating_add(rhs.reads),
+            writes: self.writes.saturating_add(rhs.writes),
+            cross_shard: self.cross_shard.saturating_add(rhs.cross_shard),
         }
     }
 }
 
+/// Latency histograms.
 #[derive(Debug, Clone, Default, Copy)]
+pub struct Histograms {
+    /// Time clients waited for a connection.
+    pub wait: Histogram,
+    /// Query execution time.
+    pub query: Histogram,
+    /// Transaction execution time.
+    pub xact: Histogram,
+}
+
+impl Add<BackendCounts> for Histograms {
+    type Output = Histograms;
+
+    fn add(self, rhs: BackendCounts) -> Self::Output {
+        Histograms {
+            wait: self.wait,
+            query: self.query + rhs.query_histogram,
+            xact: self.xact + rhs.transaction_histogram,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Stats {
     // Total counts.
     pub counts: Counts,
     last_counts: Counts,
     // Average counts.
     pub averages: Counts,
+    // Latency histograms.
+    pub histograms: Histograms,
+    /// When counting started. Kept on reload if the pool connects to the same database.
+    pub stats_reset: SystemTime,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            counts: Counts::default(),
+            last_counts: Counts::default(),
+            averages: Counts::default(),
+            histograms: Histograms::default(),
+            stats_reset: SystemTime::now(),
+        }
+    }
 }
 
 impl Stats {
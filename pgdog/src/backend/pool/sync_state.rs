@@ -0,0 +1,154 @@
+//! Synchronous replication state, polled by the idle healthcheck loop.
+//!
+//! Replicas don't know if the primary waits for them to confirm commits,
+//! so primaries are asked instead, using `pg_stat_replication`. Standbys are
+//! matched to our replicas by host, like in replica discovery, or by
+//! `application_name`, which standbys usually set to their own name.
+
+use crate::backend::{databases::databases, Error, Server};
+use crate::net::messages::{DataRow, Format};
+
+use super::{Address, Pool};
+
+const QUERY: &str = "SELECT \
+    COALESCE(host(client_addr), ''), \
+    application_name, \
+    sync_state \
+    FROM pg_stat_replication \
+    WHERE state = 'streaming'";
+
+/// Sync state of a standby, from `pg_stat_replication.sync_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncState {
+    /// The primary doesn't wait for this standby.
+    #[default]
+    Async,
+    /// Becomes sync if one of the sync standbys fails.
+    Potential,
+    /// The primary waits for this standby before confirming commits.
+    Sync,
+    /// One of the standbys in a quorum; the primary waits for some of them.
+    Quorum,
+}
+
+impl SyncState {
+    /// Every commit confirmed by the primary was received by this standby.
+    ///
+    /// Quorum standbys don't count: each of them could be the one left behind.
+    pub fn sync(&self) -> bool {
+        *self == Self::Sync
+    }
+}
+
+impl From<&str> for SyncState {
+    fn from(value: &str) -> Self {
+        match value {
+            "potential" => Self::Potential,
+            "sync" => Self::Sync,
+            "quorum" => Self::Quorum,
+            _ => Self::Async,
+        }
+    }
+}
+
+impl std::fmt::Display for SyncState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Async => write!(f, "async"),
+            Self::Potential => write!(f, "potential"),
+            Self::Sync => write!(f, "sync"),
+            Self::Quorum => write!(f, "quorum"),
+        }
+    }
+}
+
+/// Standby streaming from a primary.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Standby {
+    /// Client address of the standby.
+    pub host: String,
+    /// Name the standby connected with.
+    pub application_name: String,
+    /// Sync state.
+    pub sync_state: SyncState,
+}
+
+impl From<DataRow> for Standby {
+    fn from(value: DataRow) -> Self {
+        Self {
+            host: value.get::<String>(0, Format::Text).unwrap_or_default(),
+            application_name: value.get::<String>(1, Format::Text).unwrap_or_default(),
+            sync_state: value
+                .get::<String>(2, Format::Text)
+                .unwrap_or_default()
+                .as_str()
+                .into(),
+        }
+    }
+}
+
+impl Standby {
+    pub(super) async fn load(server: &mut Server) -> Result<Vec<Self>, Error> {
+        server.fetch_all(QUERY).await
+    }
+
+    /// This standby is the replica at this address.
+    pub fn matches(&self, addr: &Address) -> bool {
+        !addr.host.is_empty() && (self.host == addr.host || self.application_name == addr.host)
+    }
+}
+
+/// Update sync state of all replicas of this primary.
+///
+/// Replicas that aren't streaming from it anymore are async.
+pub(super) fn update(primary: &Pool, standbys: &[Standby]) {
+    for cluster in databases().all().values() {
+        for shard in cluster.shards() {
+            let same = shard
+                .primary
+                .as_ref()
+                .is_some_and(|pool| pool.addr().same_server(primary.addr()));
+            if !same {
+                continue;
+            }
+
+            for replica in shard.replicas.pools() {
+                let sync_state = standbys
+                    .iter()
+                    .find(|standby| standby.matches(replica.addr()))
+                    .map(|standby| standby.sync_state)
+                    .unwrap_or_default();
+                replica.lock().sync_state = Some(sync_state);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_sync_state() {
+        assert_eq!(SyncState::from("sync"), SyncState::Sync);
+        assert_eq!(SyncState::from("quorum"), SyncState::Quorum);
+        assert_eq!(SyncState::from("async"), SyncState::Async);
+        assert_eq!(SyncState::from(""), SyncState::Async);
+        assert!(SyncState::Sync.sync());
+        assert!(!SyncState::Quorum.sync());
+        assert_eq!(SyncState::Potential.to_string(), "potential");
+
+        let standby = Standby {
+            host: "10.0.0.2".into(),
+            application_name: "replica-1".into(),
+            sync_state: SyncState::Sync,
+        };
+        let addr = |host: &str| Address {
+            host: host.into(),
+            ..Default::default()
+        };
+        assert!(standby.matches(&addr("10.0.0.2")));
+        assert!(standby.matches(&addr("replica-1")));
+        assert!(!standby.matches(&addr("10.0.0.3")));
+    }
+}
@@ -32,8 +32,10 @@ pub fn pool() -> Pool {
             database_name: "pgdog".into(),
             user: "pgdog".into(),
             password: "pgdog".into(),
+            ..Default::default()
         },
         config,
+        ..Default::default()
     });
     pool.launch();
     pool
@@ -280,6 +282,29 @@ async fn test_incomplete_request_recovery() {
     }
 }
 
+#[tokio::test]
+async fn test_cancel() {
+    crate::logger();
+
+    let pool = pool();
+    let request = Request::default();
+    let mut conn = pool.get(&request).await.unwrap();
+
+    let cancel = pool.clone();
+    spawn(async move {
+        sleep(Duration::from_millis(100)).await;
+        cancel.cancel(&request.id).await.unwrap();
+    });
+
+    let start = Instant::now();
+    let err = conn.execute("SELECT pg_sleep(10)").await.unwrap_err();
+    assert!(start.elapsed() < Duration::from_secs(5));
+    match err {
+        crate::backend::Error::ExecutionError(err) => assert_eq!(err.code, "57014"),
+        err => panic!("expected query_canceled, got {:?}", err),
+    }
+}
+
 #[tokio::test]
 async fn test_force_close() {
     let pool = pool();
@@ -334,3 +359,45 @@ async fn test_query_stats() {
     );
     assert_eq!(after.stats.counts.healthchecks, 1)
 }
+
+#[tokio::test]
+async fn test_move_conns_to_smaller_pool() {
+    let pool = |max| {
+        Pool::new(&PoolConfig {
+            address: Address {
+                host: "127.0.0.1".into(),
+                port: 5432,
+                database_name: "pgdog".into(),
+                user: "pgdog".into(),
+                password: "pgdog".into(),
+                ..Default::default()
+            },
+            config: Config {
+                max,
+                min: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    };
+
+    let old = pool(3);
+    old.launch();
+    let mut conns = vec![];
+    for _ in 0..3 {
+        conns.push(old.get(&Request::default()).await.unwrap());
+    }
+    conns.truncate(2);
+    assert_eq!(old.lock().idle(), 1);
+
+    let new = pool(1);
+    old.move_conns_to(&new);
+
+    // The idle connection is closed, checked out ones stay.
+    assert_eq!(new.lock().idle(), 0);
+    assert_eq!(new.lock().total(), 2);
+
+    // They're over the new pool size, so one is closed when they're returned.
+    drop(conns);
+    assert_eq!(new.lock().total(), 1);
+}
@@ -12,12 +12,14 @@ fn replicas() -> Replicas {
             user: "pgdog".into(),
             password: "pgdog".into(),
             database_name: "pgdog".into(),
+            ..Default::default()
         },
         config: Config {
             max: 1,
             checkout_timeout: Duration::from_millis(1000),
             ..Default::default()
         },
+        ..Default::default()
     };
     let mut two = one.clone();
     two.address.host = "localhost".into();
@@ -40,7 +42,10 @@ async fn test_replicas() {
             tasks.push(spawn(async move {
                 assert!(replicas.pools[pool].banned());
                 assert!(!replicas.pools[other].banned());
-                let conn = replicas.get(&Request::default(), &None).await.unwrap();
+                let conn = replicas
+                    .get(&Request::default(), &None, None)
+                    .await
+                    .unwrap();
                 assert_eq!(conn.addr(), replicas.pools[other].addr());
                 assert!(replicas.pools[pool].banned());
                 assert!(!replicas.pools[other].banned());
@@ -59,6 +64,9 @@ async fn test_replicas() {
 
     // All replicas banned, unban everyone.
     assert!(replicas.pools.iter().all(|pool| pool.banned()));
-    replicas.get(&Request::default(), &None).await.unwrap();
+    replicas
+        .get(&Request::default(), &None, None)
+        .await
+        .unwrap();
     assert!(replicas.pools.iter().all(|pool| !pool.banned()));
 }
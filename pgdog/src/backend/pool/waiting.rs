@@ -1,4 +1,6 @@
 use crate::backend::Server;
+use crate::events::{emit, Event};
+use crate::stats::errors::{record, ErrorEvent};
 
 use super::{Error, Guard, Pool, Request};
 use tokio::{
@@ -44,8 +46,15 @@ impl Waiting {
 
             Err(_err) => {
                 let mut guard = self.pool.lock();
-                if !guard.banned() {
-                    guard.maybe_ban(now, Error::CheckoutTimeout);
+                if !guard.banned() && guard.maybe_ban(now, Error::CheckoutTimeout) {
+                    record(ErrorEvent::pool(
+                        self.pool.addr(),
+                        format!("pool banned: {}", Error::CheckoutTimeout),
+                    ));
+                    emit(Event::PoolBanned {
+                        pool: self.pool.addr().to_string(),
+                        reason: Error::CheckoutTimeout.to_string(),
+                    });
                 }
                 guard.remove_waiter(&self.request.id);
                 Err(Error::CheckoutTimeout)
@@ -0,0 +1,112 @@
+//! External witness confirming primary outages.
+//!
+//! Before failing over, PgDog can ask an HTTP endpoint running elsewhere
+//! in the network if it can't reach the primary either:
+//!
+//! ```text
+//! GET <failover_witness>?host=<host>&port=<port>
+//! ```
+//!
+//! Any 2xx response confirms the outage. Everything else, including
+//! no response at all, means the primary could be fine and only
+//! this node lost its connection to it.
+
+use std::time::Duration;
+
+use http_body_util::Empty;
+use hyper::{body::Bytes, client::conn::http1, header::HOST, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use thiserror::Error;
+use tokio::{net::TcpStream, spawn, time::timeout};
+use tracing::warn;
+use url::Url;
+
+use super::Address;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Url(#[from] url::ParseError),
+
+    #[error("witness url has no host")]
+    NoHost,
+
+    #[error("only http witnesses are supported")]
+    Scheme,
+
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Hyper(#[from] hyper::Error),
+
+    #[error("{0}")]
+    Http(#[from] hyper::http::Error),
+}
+
+/// The witness can't reach the server either.
+pub(super) async fn confirmed(witness: &str, addr: &Address, duration: Duration) -> bool {
+    match timeout(duration, status(witness, addr)).await {
+        Ok(Ok(status)) => status.is_success(),
+        Ok(Err(err)) => {
+            warn!("failover witness error: {} [{}]", err, addr);
+            false
+        }
+        Err(_) => {
+            warn!("failover witness timeout [{}]", addr);
+            false
+        }
+    }
+}
+
+async fn status(witness: &str, addr: &Address) -> Result<StatusCode, Error> {
+    let url = witness_url(witness, addr)?;
+    let host = url.host_str().ok_or(Error::NoHost)?.to_owned();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let stream = TcpStream::connect((host.as_str(), port)).await?;
+    let (mut sender, conn) = http1::handshake(TokioIo::new(stream)).await?;
+    spawn(async move {
+        let _ = conn.await;
+    });
+
+    let request = Request::builder()
+        .uri(&url[url::Position::BeforePath..])
+        .header(HOST, host)
+        .body(Empty::<Bytes>::new())?;
+    let response = sender.send_request(request).await?;
+
+    Ok(response.status())
+}
+
+fn witness_url(witness: &str, addr: &Address) -> Result<Url, Error> {
+    let mut url = Url::parse(witness)?;
+    if url.scheme() != "http" {
+        return Err(Error::Scheme);
+    }
+
+    url.query_pairs_mut()
+        .append_pair("host", &addr.host)
+        .append_pair("port", &addr.port.to_string());
+
+    Ok(url)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_url() {
+        let addr = Address::new_test();
+        let url = witness_url("http://witness:8080/outage?cluster=prod", &addr).unwrap();
+        assert_eq!(
+            &url[url::Position::BeforePath..],
+            "/outage?cluster=prod&host=127.0.0.1&port=5432"
+        );
+        assert!(matches!(
+            witness_url("https://witness/outage", &addr),
+            Err(Error::Scheme)
+        ));
+    }
+}
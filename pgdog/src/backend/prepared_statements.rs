@@ -36,10 +36,14 @@ pub struct PreparedStatements {
     global_cache: Arc<Mutex<GlobalCache>>,
     local_cache: HashSet<String>,
     state: ProtocolState,
-    // Prepared statements being prepared now on the connection.
-    parses: VecDeque<String>,
-    // Describes being executed now on the connection.
-    describes: VecDeque<String>,
+    // Prepared statements being prepared now on the connection,
+    // with the number of Syncs sent ahead of them.
+    parses: VecDeque<(usize, String)>,
+    // Describes being executed now on the connection,
+    // with the number of Syncs sent ahead of them.
+    describes: VecDeque<(usize, String)>,
+    // Syncs sent to the server that it didn't answer yet.
+    syncs: usize,
 }
 
 impl Default for PreparedStatements {
@@ -57,6 +61,7 @@ impl PreparedStatements {
             state: ProtocolState::default(),
             parses: VecDeque::new(),
             describes: VecDeque::new(),
+            syncs: 0,
         }
     }
 
@@ -102,7 +107,8 @@ impl PreparedStatements {
                         }
                     }
 
-                    self.describes.push_back(describe.statement().to_string());
+                    self.describes
+                        .push_back((self.syncs, describe.statement().to_string()));
                 } else {
                     self.state.add(ExecutionCode::DescriptionOrNothing);
                 }
@@ -114,10 +120,12 @@ impl PreparedStatements {
 
             ProtocolMessage::Sync(_) => {
                 self.state.add('Z');
+                self.syncs += 1;
             }
 
             ProtocolMessage::Query(_) => {
                 self.state.add('Z');
+                self.syncs += 1;
             }
 
             ProtocolMessage::Parse(parse) => {
@@ -128,7 +136,8 @@ impl PreparedStatements {
                     } else {
                         self.prepared(parse.name());
                         self.state.add('1');
-                        self.parses.push_back(parse.name().to_string());
+                        self.parses
+                            .push_back((self.syncs, parse.name().to_string()));
                     }
                 } else {
                     self.state.add('1');
@@ -163,23 +172,24 @@ impl PreparedStatements {
     /// Should we forward the message to the client.
     pub fn forward(&mut self, message: &Message) -> Result<bool, Error> {
         let code = message.code();
+        let in_sync = self.state.in_sync();
         let action = self.state.action(code)?;
 
         // Cleanup prepared statements state.
         match code {
-            'E' => {
-                let parse = self.parses.pop_front();
-                let describe = self.describes.pop_front();
-                if let Some(parse) = parse {
-                    self.remove(&parse);
-                }
-                if let Some(describe) = describe {
-                    self.remove(&describe);
-                }
+            'E' => self.skipped(),
+
+            // Statements sent after the error, but before the client
+            // got it, were skipped too.
+            'Z' if !in_sync => {
+                self.skipped();
+                self.synced();
             }
 
+            'Z' => self.synced(),
+
             'T' => {
-                if let Some(describe) = self.describes.pop_front() {
+                if let Some((_, describe)) = self.describes.pop_front() {
                     self.add_row_description(
                         &describe,
                         &RowDescription::from_bytes(message.to_bytes()?)?,
@@ -220,6 +230,37 @@ impl PreparedStatements {
         }
     }
 
+    /// Statements pipelined after the one that failed are skipped
+    /// by the server, up to the next Sync, so they aren't prepared either.
+    /// Statements sent after that Sync are executed normally.
+    fn skipped(&mut self) {
+        while let Some(parse) = Self::pop_unsynced(&mut self.parses) {
+            self.remove(&parse);
+        }
+        if let Some(describe) = Self::pop_unsynced(&mut self.describes) {
+            self.remove(&describe);
+        }
+        while Self::pop_unsynced(&mut self.describes).is_some() {}
+    }
+
+    /// Server answered a Sync, so statements sent after it
+    /// have one less Sync ahead of them.
+    fn synced(&mut self) {
+        self.syncs = self.syncs.saturating_sub(1);
+        for (syncs, _) in self.parses.iter_mut().chain(self.describes.iter_mut()) {
+            *syncs = syncs.saturating_sub(1);
+        }
+    }
+
+    /// Remove the next statement, if it was sent before the next Sync.
+    fn pop_unsynced(queue: &mut VecDeque<(usize, String)>) -> Option<String> {
+        if queue.front()?.0 == 0 {
+            queue.pop_front().map(|(_, name)| name)
+        } else {
+            None
+        }
+    }
+
     /// Extended protocol is in sync.
     pub(crate) fn done(&self) -> bool {
         self.state.done() && self.parses.is_empty() && self.describes.is_empty()
@@ -302,3 +343,54 @@ impl PreparedStatements {
         self.len() == 0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::net::{Describe, ErrorResponse, ReadyForQuery, Sync};
+
+    #[test]
+    fn test_error_in_first_sync() {
+        let mut statements = PreparedStatements::new();
+
+        // Two Sync groups in one pipeline. The first one fails,
+        // the second one is executed by the server.
+        let pipeline: Vec<ProtocolMessage> = vec![
+            Parse::named("__pgdog_sync_1", "SELECT 1").into(),
+            Sync::new().into(),
+            Parse::named("__pgdog_sync_2", "SELECT 2").into(),
+            Describe::new_statement("__pgdog_sync_2").into(),
+            Sync::new().into(),
+        ];
+        for message in &pipeline {
+            assert!(matches!(
+                statements.handle(message).unwrap(),
+                HandleResult::Forward
+            ));
+        }
+
+        let error = ErrorResponse::syntax("syntax error").message().unwrap();
+        assert!(statements.forward(&error).unwrap());
+        assert!(!statements.contains("__pgdog_sync_1"));
+        assert!(statements.contains("__pgdog_sync_2"));
+
+        let rfq = ReadyForQuery::idle().message().unwrap();
+        assert!(statements.forward(&rfq).unwrap());
+        assert!(statements.contains("__pgdog_sync_2"));
+
+        let responses = [
+            ParseComplete.message().unwrap(),
+            Message::new(Bytes::from_static(b"t\0\0\0\x06\0\0")),
+            Message::new(Bytes::from_static(b"n\0\0\0\x04")),
+            rfq,
+        ];
+        for response in &responses {
+            assert!(statements.forward(response).unwrap());
+        }
+
+        assert!(statements.contains("__pgdog_sync_2"));
+        assert!(statements.done());
+    }
+}
@@ -49,6 +49,7 @@ impl From<char> for ExecutionCode {
 pub enum ExecutionItem {
     Code(ExecutionCode),
     Ignore(ExecutionCode),
+    Simulated(ExecutionCode),
 }
 
 #[derive(Debug, Clone, Default)]
@@ -93,26 +94,51 @@ impl ProtocolState {
     /// e.g. closed a prepared statement, when we actually did not.
     pub(crate) fn add_simulated(&mut self, message: Message) {
         self.queue
-            .push_back(ExecutionItem::Code(message.code().into()));
+            .push_back(ExecutionItem::Simulated(message.code().into()));
         self.simulated.push_back(message);
     }
 
+    /// A simulated message can be returned now.
+    pub fn has_simulated(&self) -> bool {
+        matches!(self.queue.front(), Some(ExecutionItem::Simulated(_)))
+    }
+
     /// Get a simulated message from the execution queue.
     ///
     /// Returns a message only if it should be returned at the current state
     /// of the extended pipeline.
     pub fn get_simulated(&mut self) -> Option<Message> {
-        let code = self.queue.front();
-        let message = self.simulated.front();
-        if let Some(ExecutionItem::Code(code)) = code {
-            if let Some(message) = message {
-                if code == &ExecutionCode::from(message.code()) {
-                    let _ = self.queue.pop_front();
-                    return self.simulated.pop_front();
+        if self.has_simulated() {
+            let _ = self.queue.pop_front();
+            return self.simulated.pop_front();
+        }
+        None
+    }
+
+    /// The server skips messages until Sync after an error. Remove what
+    /// we expected it to send for them, up to the ReadyForQuery for Sync,
+    /// and return the statements we thought it would prepare.
+    fn skip_to_sync(&mut self, include_sync: bool) -> VecDeque<String> {
+        let mut names = VecDeque::new();
+        while let Some(item) = self.queue.front() {
+            match item {
+                ExecutionItem::Code(ExecutionCode::ReadyForQuery) => {
+                    if include_sync {
+                        self.queue.pop_front();
+                    }
+                    break;
+                }
+                ExecutionItem::Ignore(_) => {
+                    names.extend(self.names.pop_front());
+                }
+                ExecutionItem::Simulated(_) => {
+                    self.simulated.pop_front();
                 }
+                ExecutionItem::Code(_) => (),
             }
+            self.queue.pop_front();
         }
-        None
+        names
     }
 
     /// Should we ignore the message we just received
@@ -122,22 +148,27 @@ impl ProtocolState {
         match code {
             ExecutionCode::Untracked => return Ok(Action::Forward),
             ExecutionCode::Error => {
-                // Remove everything from the execution queue.
+                // Remove everything from the execution queue until Sync.
                 // The connection is out of sync until client re-syncs it.
                 if self.extended {
                     self.out_of_sync = true;
                 }
-                let last = self.queue.pop_back();
-                self.queue.clear();
-                if let Some(ExecutionItem::Code(ExecutionCode::ReadyForQuery)) = last {
-                    self.queue
-                        .push_back(ExecutionItem::Code(ExecutionCode::ReadyForQuery));
+                let names = self.skip_to_sync(false);
+                if !names.is_empty() {
+                    return Ok(Action::ForwardAndRemove(names));
                 }
                 return Ok(Action::Forward);
             }
 
-            ExecutionCode::ReadyForQuery => {
+            // Messages pipelined after the error were skipped,
+            // including the ones sent after it happened.
+            ExecutionCode::ReadyForQuery if self.out_of_sync => {
                 self.out_of_sync = false;
+                let names = self.skip_to_sync(true);
+                if !names.is_empty() {
+                    return Ok(Action::ForwardAndRemove(names));
+                }
+                return Ok(Action::Forward);
             }
             _ => (),
         };
@@ -156,6 +187,9 @@ impl ProtocolState {
                 Ok(Action::Forward)
             }
 
+            // Simulated messages are returned before reading from the server.
+            ExecutionItem::Simulated(_) => Err(Error::ProtocolOutOfSync),
+
             // Used for preparing statements that the client expects to be there.
             ExecutionItem::Ignore(in_queue) => {
                 self.names.pop_front().ok_or(Error::ProtocolOutOfSync)?;
@@ -187,7 +221,6 @@ impl ProtocolState {
         self.is_empty() && !self.out_of_sync
     }
 
-    #[cfg(test)]
     pub(crate) fn in_sync(&self) -> bool {
         !self.out_of_sync
     }
@@ -196,6 +229,7 @@ impl ProtocolState {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::net::messages::ParseComplete;
 
     #[test]
     fn test_state() {
@@ -203,4 +237,51 @@ mod test {
         state.add_ignore('1', "test");
         assert_eq!(state.action('1').unwrap(), Action::Ignore);
     }
+
+    #[test]
+    fn test_pipeline_error() {
+        // Parse, Bind, Execute of two statements, then Sync. The second
+        // statement is prepared already, so its ParseComplete is simulated,
+        // and its Bind needs a Parse we inject and hide from the client.
+        let mut state = ProtocolState::default();
+        state.add('1');
+        state.add('2');
+        state.add('C');
+        state.add_simulated(ParseComplete.message().unwrap());
+        state.add_ignore('1', "__pgdog_2");
+        state.add('2');
+        state.add('C');
+        state.add('Z');
+
+        assert_eq!(state.action('1').unwrap(), Action::Forward);
+        assert_eq!(
+            state.action('E').unwrap(),
+            Action::ForwardAndRemove(VecDeque::from(["__pgdog_2".to_owned()]))
+        );
+        assert!(!state.has_simulated());
+        assert!(state.get_simulated().is_none());
+        assert!(!state.in_sync());
+        assert_eq!(state.action('Z').unwrap(), Action::Forward);
+        assert!(state.done());
+
+        // Error before the client sent Sync. The rest of the pipeline
+        // is skipped too, up to Sync.
+        state.add('1');
+        state.add('2');
+        state.add('C');
+        assert_eq!(state.action('E').unwrap(), Action::Forward);
+        assert!(state.is_empty());
+
+        state.add('1');
+        state.add('2');
+        state.add('C');
+        state.add('Z');
+        state.add('1');
+        state.add('Z');
+        assert_eq!(state.action('Z').unwrap(), Action::Forward);
+        assert_eq!(state.len(), 2);
+        assert_eq!(state.action('1').unwrap(), Action::Forward);
+        assert_eq!(state.action('Z').unwrap(), Action::Forward);
+        assert!(state.done());
+    }
 }
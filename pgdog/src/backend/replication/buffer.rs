@@ -3,8 +3,9 @@ use fnv::FnvHashSet as HashSet;
 use std::collections::VecDeque;
 
 use crate::backend::ShardingSchema;
+use crate::config::ShardedTable;
 use crate::frontend::router::parser::Shard;
-use crate::frontend::router::sharding::shard_str;
+use crate::frontend::router::sharding::ContextBuilder;
 use crate::net::messages::FromBytes;
 use crate::net::messages::Protocol;
 use crate::net::messages::ToBytes;
@@ -13,10 +14,7 @@ use crate::net::messages::{
     CopyData, Message,
 };
 
-use super::{Error, ReplicationConfig};
-
-/// We are putting vectors on a single shard only.
-static CENTROID_PROBES: usize = 1;
+use super::{progress::Stream, Error, ReplicationConfig};
 
 #[derive(Debug)]
 pub struct Buffer {
@@ -29,16 +27,19 @@ pub struct Buffer {
     oid: Option<i32>,
     buffer: VecDeque<Message>,
     sharding_schema: ShardingSchema,
+    stream: Stream,
 }
 
 impl Buffer {
     /// New replication buffer.
     pub fn new(
+        database: &str,
         shard: Shard,
         cluster: &ReplicationConfig,
         sharding_schema: &ShardingSchema,
     ) -> Self {
         Self {
+            stream: Stream::new(database, shard.to_string()),
             begin: None,
             message: None,
             relations: HashMap::default(),
@@ -64,6 +65,7 @@ impl Buffer {
         };
 
         if let Some(xlog_data) = data.xlog_data() {
+            self.stream.received(xlog_data.starting_point);
             if let Some(payload) = xlog_data.payload() {
                 match &payload {
                     XLogPayload::Begin(_) => {
@@ -71,6 +73,7 @@ impl Buffer {
                     }
                     XLogPayload::Commit(_) => {
                         self.message = Some(xlog_data);
+                        self.stream.committed();
                         return self.flush();
                     }
                     XLogPayload::Relation(relation) => {
@@ -79,14 +82,17 @@ impl Buffer {
                     }
                     XLogPayload::Update(update) => {
                         let (table, columns) = self.sharding_key(update.oid)?;
-                        let column = self
+                        let key = self
                             .replication_config
-                            .sharded_column(table, &columns)
-                            .and_then(|column| update.column(column.position))
-                            .and_then(|column| column.as_str());
-                        if let Some(column) = column {
-                            let shard =
-                                shard_str(column, &self.sharding_schema, &vec![], CENTROID_PROBES);
+                            .sharded_table(table, &columns)
+                            .and_then(|(table, position)| {
+                                update
+                                    .column(position)
+                                    .and_then(|column| column.as_str())
+                                    .map(|key| (table, key))
+                            });
+                        if let Some((table, key)) = key {
+                            let shard = self.shard(table, key)?;
                             if self.shard == shard {
                                 self.message = Some(xlog_data);
                                 return self.flush();
@@ -98,14 +104,17 @@ impl Buffer {
                     }
                     XLogPayload::Insert(insert) => {
                         let (table, columns) = self.sharding_key(insert.oid)?;
-                        let column = self
+                        let key = self
                             .replication_config
-                            .sharded_column(table, &columns)
-                            .and_then(|column| insert.column(column.position))
-                            .and_then(|column| column.as_str());
-                        if let Some(column) = column {
-                            let shard =
-                                shard_str(column, &self.sharding_schema, &vec![], CENTROID_PROBES);
+                            .sharded_table(table, &columns)
+                            .and_then(|(table, position)| {
+                                insert
+                                    .column(position)
+                                    .and_then(|column| column.as_str())
+                                    .map(|key| (table, key))
+                            });
+                        if let Some((table, key)) = key {
+                            let shard = self.shard(table, key)?;
                             if self.shard == shard {
                                 self.message = Some(xlog_data);
                                 return self.flush();
@@ -132,7 +141,9 @@ impl Buffer {
 
     /// Retrieve one message from the buffer, if any is stored.
     pub fn message(&mut self) -> Option<Message> {
-        self.buffer.pop_front()
+        let message = self.buffer.pop_front();
+        self.stream.buffered(self.buffer.len());
+        message
     }
 
     /// Flush partial transaction to buffer. Client will receive
@@ -163,6 +174,15 @@ impl Buffer {
         Ok(())
     }
 
+    /// Shard for the sharding key, same as the query router would pick.
+    fn shard(&self, table: &ShardedTable, key: &str) -> Result<Shard, Error> {
+        Ok(ContextBuilder::new(table)
+            .data(key)
+            .shards(self.sharding_schema.shards)
+            .build()?
+            .apply()?)
+    }
+
     fn sharding_key(&self, oid: i32) -> Result<(&str, Vec<&str>), Error> {
         let relation = self.relations.get(&oid).ok_or(Error::NoRelationMessage)?;
         let columns = relation.columns();
@@ -171,3 +191,133 @@ impl Buffer {
         Ok((name, columns))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bytes::{BufMut, Bytes, BytesMut};
+
+    use crate::backend::replication::ShardedTables;
+    use crate::config::ShardingFunction;
+    use crate::net::messages::replication::{logical::relation::Column, Commit};
+
+    use super::*;
+
+    fn xlog(payload: Bytes) -> Message {
+        XLogData {
+            starting_point: 0,
+            current_end: 0,
+            system_clock: 0,
+            bytes: payload,
+        }
+        .to_message()
+        .unwrap()
+    }
+
+    fn begin() -> Message {
+        let mut payload = BytesMut::new();
+        payload.put_u8(b'B');
+        payload.put_i64(0);
+        payload.put_i64(0);
+        payload.put_i32(1);
+        xlog(payload.freeze())
+    }
+
+    fn relation() -> Message {
+        let relation = Relation {
+            oid: 1,
+            namespace: "public".into(),
+            name: "sharded".into(),
+            replica_identity: b'd' as i8,
+            columns: vec![Column {
+                flag: 1,
+                name: "id".into(),
+                oid: 20,
+                type_modifier: -1,
+            }],
+        };
+        XLogData::relation(0, &relation)
+            .unwrap()
+            .to_message()
+            .unwrap()
+    }
+
+    fn insert(id: &str) -> Message {
+        let mut payload = BytesMut::new();
+        payload.put_u8(b'I');
+        payload.put_i32(1);
+        payload.put_u8(b'N');
+        payload.put_i16(1);
+        payload.put_u8(b't');
+        payload.put_i32(id.len() as i32);
+        payload.put(id.as_bytes());
+        xlog(payload.freeze())
+    }
+
+    fn commit() -> Message {
+        let commit = Commit {
+            flags: 0,
+            commit_lsn: 0,
+            end_lsn: 0,
+            commit_timestamp: 0,
+        };
+        xlog(commit.to_bytes().unwrap())
+    }
+
+    #[test]
+    fn test_consistent_hash() {
+        let table = ShardedTable {
+            database: "pgdog".into(),
+            name: Some("sharded".into()),
+            column: "id".into(),
+            sharding_function: ShardingFunction::ConsistentHash,
+            ..Default::default()
+        };
+        let tables = ShardedTables::from(&[table.clone()][..]);
+        let config = ReplicationConfig {
+            shards: 4,
+            sharded_tables: tables.clone(),
+        };
+        let schema = ShardingSchema { shards: 4, tables };
+
+        let modulo = ShardedTable {
+            sharding_function: ShardingFunction::default(),
+            ..table.clone()
+        };
+        let shard = |table: &ShardedTable, id: &str| {
+            ContextBuilder::new(table)
+                .data(id)
+                .shards(4)
+                .build()
+                .unwrap()
+                .apply()
+                .unwrap()
+        };
+        let mut moved = 0;
+
+        for id in 0..25 {
+            let id = id.to_string();
+            let expected = shard(&table, &id);
+            if expected != shard(&modulo, &id) {
+                moved += 1;
+            }
+
+            for shard in 0..4 {
+                let shard = Shard::Direct(shard);
+                let mut buffer = Buffer::new("pgdog", shard.clone(), &config, &schema);
+                for message in [begin(), relation(), insert(&id), commit()] {
+                    buffer.handle(message).unwrap();
+                }
+
+                let mut inserted = false;
+                while let Some(message) = buffer.message() {
+                    let data = CopyData::from_bytes(message.to_bytes().unwrap()).unwrap();
+                    inserted |= data.xlog_data().unwrap().bytes[0] == b'I';
+                }
+                assert_eq!(inserted, shard == expected, "id = {}", id);
+            }
+        }
+
+        // Some keys are on different shards than with the default hash.
+        assert!(moved > 0);
+    }
+}
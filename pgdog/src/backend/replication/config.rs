@@ -1,4 +1,5 @@
 use super::{ShardedColumn, ShardedTables};
+use crate::config::ShardedTable;
 
 /// Logical replication configuration.
 #[derive(Debug, Clone)]
@@ -15,6 +16,11 @@ impl ReplicationConfig {
         self.sharded_tables.sharded_column(table, columns)
     }
 
+    /// Get the sharded table and the position of its sharded column in a row.
+    pub fn sharded_table(&self, table: &str, columns: &[&str]) -> Option<(&ShardedTable, usize)> {
+        self.sharded_tables.sharded_table(table, columns)
+    }
+
     /// Total number of shards.
     pub fn shards(&self) -> usize {
         self.shards
@@ -10,4 +10,19 @@ pub enum Error {
 
     #[error("no message to forward")]
     NoMessage,
+
+    #[error("invalid replication slot name: \"{0}\"")]
+    SlotName(String),
+
+    #[error("{0}")]
+    Sharding(#[from] crate::frontend::router::sharding::Error),
+
+    #[error("database \"{0}\" is already being resharded")]
+    ReshardRunning(String),
+
+    #[error("shard {0} sent too many changes while another shard was sending a transaction")]
+    PublisherQueueFull(usize),
+
+    #[error("reshard: {0}")]
+    Reshard(String),
 }
@@ -1,9 +1,14 @@
 pub mod buffer;
 pub mod config;
 pub mod error;
+pub mod progress;
+pub mod publisher;
+pub mod reshard;
 pub mod sharded_tables;
+pub mod slots;
 
 pub use buffer::Buffer;
 pub use config::ReplicationConfig;
 pub use error::Error;
+pub use publisher::Publisher;
 pub use sharded_tables::{ShardedColumn, ShardedTables};
@@ -0,0 +1,122 @@
+//! Position of sharded logical replication streams, shown in `SHOW REPLICATION`.
+//!
+//! Each replication client gets its own buffer, filtering changes that don't
+//! belong to its shard. The buffer registers itself here and updates
+//! its position as changes arrive.
+
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use fnv::FnvHashMap as HashMap;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+static STREAMS: Lazy<Mutex<HashMap<usize, Arc<Progress>>>> =
+    Lazy::new(|| Mutex::new(HashMap::default()));
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Progress of a replication stream.
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// Database the client is replicating from.
+    pub database: String,
+    /// Shard the changes are filtered for.
+    pub shard: String,
+    lsn: AtomicI64,
+    buffered: AtomicUsize,
+    transactions: AtomicUsize,
+}
+
+impl Progress {
+    /// WAL position of the last change received.
+    pub fn lsn(&self) -> i64 {
+        self.lsn.load(Ordering::Relaxed)
+    }
+
+    /// Messages waiting to be sent to the client.
+    pub fn buffered(&self) -> usize {
+        self.buffered.load(Ordering::Relaxed)
+    }
+
+    /// Transactions sent to the client.
+    pub fn transactions(&self) -> usize {
+        self.transactions.load(Ordering::Relaxed)
+    }
+}
+
+/// Registered stream, removed when dropped.
+#[derive(Debug)]
+pub struct Stream {
+    id: usize,
+    progress: Arc<Progress>,
+}
+
+impl Stream {
+    /// Register a new stream.
+    pub fn new(database: &str, shard: String) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let progress = Arc::new(Progress {
+            database: database.to_owned(),
+            shard,
+            ..Default::default()
+        });
+        STREAMS.lock().insert(id, progress.clone());
+
+        Self { id, progress }
+    }
+
+    pub(super) fn received(&self, lsn: i64) {
+        self.progress.lsn.fetch_max(lsn, Ordering::Relaxed);
+    }
+
+    pub(super) fn buffered(&self, buffered: usize) {
+        self.progress.buffered.store(buffered, Ordering::Relaxed);
+    }
+
+    pub(super) fn committed(&self) {
+        self.progress.transactions.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        STREAMS.lock().remove(&self.id);
+    }
+}
+
+/// Replication streams currently open.
+pub fn streams() -> Vec<Arc<Progress>> {
+    STREAMS.lock().values().cloned().collect()
+}
+
+/// Format a WAL position like Postgres, e.g. `16/B374D848`.
+pub fn format_lsn(lsn: i64) -> String {
+    format!("{:X}/{:X}", (lsn >> 32) as u32, lsn as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_streams() {
+        let stream = Stream::new("test_streams", "1".into());
+        stream.received(0x16_B374_D848);
+        stream.received(0x10);
+        stream.committed();
+        stream.buffered(3);
+
+        let progress = streams()
+            .into_iter()
+            .find(|progress| progress.database == "test_streams")
+            .unwrap();
+        assert_eq!(format_lsn(progress.lsn()), "16/B374D848");
+        assert_eq!(progress.transactions(), 1);
+        assert_eq!(progress.buffered(), 3);
+
+        drop(stream);
+        assert!(!streams()
+            .iter()
+            .any(|progress| progress.database == "test_streams"));
+    }
+}
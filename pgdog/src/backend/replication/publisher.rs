@@ -0,0 +1,322 @@
+//! Merge logical replication streams from all shards into one,
+//! so a single consumer, e.g. Debezium, can follow a sharded database.
+//!
+//! Transactions from different shards are never interleaved: once a shard
+//! sends Begin, messages from other shards wait until it sends Commit.
+//! Transactions streamed while in progress are sent in blocks, so
+//! Stream Start and Stream Stop are boundaries too.
+//!
+//! Each shard has its own WAL positions, so the merged stream uses its own.
+//! Positions confirmed by the consumer are translated back to each shard,
+//! so their replication slots advance only past what the consumer has seen.
+
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::net::messages::{
+    replication::{Commit, KeepAlive, ReplicationMeta, StatusUpdate, StreamCommit, XLogData},
+    CopyData, FromBytes, Message, ToBytes,
+};
+
+use super::Error;
+
+/// Messages queued for a shard while another shard is sending a transaction.
+/// The consumer reconnects and resumes from the last confirmed position if
+/// a transaction is too large to hold the other shards back.
+const MAX_QUEUED: usize = 100_000;
+
+static START_REPLICATION: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^(\s*START_REPLICATION\s+SLOT\s+\S+\s+LOGICAL\s+)[0-9A-F]+/[0-9A-F]+").unwrap()
+});
+
+/// Start replication on a shard.
+///
+/// The consumer only knows positions in the merged stream, which mean nothing
+/// to the shards. `0/0` resumes from the position last confirmed on the slot.
+pub fn start_replication(query: &str) -> Option<String> {
+    START_REPLICATION
+        .is_match(query)
+        .then(|| START_REPLICATION.replace(query, "${1}0/0").to_string())
+}
+
+#[derive(Debug)]
+pub struct Publisher {
+    /// Shard sending a transaction.
+    active: Option<usize>,
+    /// Shard checked first once the active transaction commits,
+    /// so a busy shard doesn't hold up the others.
+    next: usize,
+    /// Messages waiting for the active transaction to commit.
+    queues: Vec<VecDeque<XLogData>>,
+    /// Messages ready for the consumer.
+    ready: VecDeque<Message>,
+    /// Position in the merged stream.
+    lsn: i64,
+    /// Commits sent to the consumer: merged position, shard and shard position.
+    commits: VecDeque<(i64, usize, i64)>,
+    /// Position confirmed by the consumer, for each shard.
+    confirmed: Vec<i64>,
+}
+
+impl Publisher {
+    /// Merge streams from this many shards.
+    pub fn new(shards: usize) -> Self {
+        // Positions keep increasing across reconnects, so consumers
+        // don't skip changes they think they've already seen.
+        let lsn = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_micros() as i64)
+            .unwrap_or_default();
+
+        Self {
+            active: None,
+            next: 0,
+            queues: vec![VecDeque::new(); shards],
+            ready: VecDeque::new(),
+            lsn,
+            commits: VecDeque::new(),
+            confirmed: vec![0; shards],
+        }
+    }
+
+    /// Handle CopyData sent by a shard.
+    pub fn handle(&mut self, shard: usize, data: CopyData) -> Result<(), Error> {
+        if let Some(xlog_data) = data.xlog_data() {
+            if self.queues[shard].len() >= MAX_QUEUED {
+                return Err(Error::PublisherQueueFull(shard));
+            }
+            self.queues[shard].push_back(xlog_data);
+            self.drain()
+        } else if let Some(ReplicationMeta::KeepAlive(keep_alive)) = data.replication_meta() {
+            // If a reply is requested, the consumer's status update
+            // is sent to all shards.
+            let keep_alive = KeepAlive {
+                wal_end: self.lsn,
+                ..keep_alive
+            };
+            self.ready.push_back(keep_alive.to_message()?.stream(true));
+            Ok(())
+        } else {
+            self.ready
+                .push_back(Message::new(data.to_bytes()?).stream(true));
+            Ok(())
+        }
+    }
+
+    /// Message for the consumer, if any.
+    pub fn message(&mut self) -> Option<Message> {
+        self.ready.pop_front()
+    }
+
+    /// Send queued messages, one transaction at a time.
+    fn drain(&mut self) -> Result<(), Error> {
+        let shards = self.queues.len();
+
+        loop {
+            let shard = match self.active {
+                Some(shard) => shard,
+                None => match (0..shards)
+                    .map(|shard| (self.next + shard) % shards)
+                    .find(|shard| !self.queues[*shard].is_empty())
+                {
+                    Some(shard) => shard,
+                    None => return Ok(()),
+                },
+            };
+
+            let Some(mut xlog_data) = self.queues[shard].pop_front() else {
+                // Waiting for the rest of the transaction.
+                return Ok(());
+            };
+
+            let start = self.lsn;
+            self.lsn += 1;
+
+            match xlog_data.bytes.first().map(|code| *code as char) {
+                // Begin and Stream Start.
+                Some('B') | Some('S') => self.active = Some(shard),
+                // Stream Stop: the rest of the transaction comes later.
+                Some('E') => self.done(shard),
+                Some('C') => {
+                    let mut commit = Commit::from_bytes(xlog_data.bytes.clone())?;
+                    self.commits.push_back((self.lsn, shard, commit.end_lsn));
+                    commit.commit_lsn = start;
+                    commit.end_lsn = self.lsn;
+                    xlog_data.bytes = commit.to_bytes()?;
+                    self.done(shard);
+                }
+                // Stream Commit is sent outside of a Stream Start/Stop block.
+                Some('c') => {
+                    let mut commit = StreamCommit::from_bytes(xlog_data.bytes.clone())?;
+                    self.commits.push_back((self.lsn, shard, commit.end_lsn));
+                    commit.commit_lsn = start;
+                    commit.end_lsn = self.lsn;
+                    xlog_data.bytes = commit.to_bytes()?;
+                }
+                _ => (),
+            }
+
+            xlog_data.starting_point = start;
+            xlog_data.current_end = self.lsn;
+            self.ready.push_back(xlog_data.to_message()?.stream(true));
+        }
+    }
+
+    /// Shard finished sending a transaction, or a block of one.
+    fn done(&mut self, shard: usize) {
+        self.active = None;
+        self.next = (shard + 1) % self.queues.len();
+    }
+
+    /// Translate the consumer's status update into one for the shard.
+    pub fn status_update(&mut self, shard: usize, update: &StatusUpdate) -> StatusUpdate {
+        while let Some((lsn, committed_shard, committed)) = self.commits.front().copied() {
+            if lsn > update.last_flushed {
+                break;
+            }
+            self.confirmed[committed_shard] = committed;
+            self.commits.pop_front();
+        }
+
+        let confirmed = self.confirmed.get(shard).copied().unwrap_or_default();
+
+        StatusUpdate {
+            last_written: confirmed,
+            last_flushed: confirmed,
+            last_applied: confirmed,
+            ..update.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::{BufMut, BytesMut};
+
+    use crate::net::messages::replication::Commit;
+    use crate::net::messages::{FromBytes, Protocol};
+
+    use super::*;
+
+    fn xlog(payload: &[u8], lsn: i64) -> CopyData {
+        let xlog_data = XLogData {
+            starting_point: lsn,
+            current_end: lsn,
+            system_clock: 0,
+            bytes: payload.to_vec().into(),
+        };
+        CopyData::bytes(xlog_data.to_bytes().unwrap())
+    }
+
+    fn begin() -> Vec<u8> {
+        let mut payload = BytesMut::new();
+        payload.put_u8(b'B');
+        payload.put_i64(0);
+        payload.put_i64(0);
+        payload.put_i32(1);
+        payload.to_vec()
+    }
+
+    fn commit(end_lsn: i64) -> Vec<u8> {
+        Commit {
+            flags: 0,
+            commit_lsn: end_lsn,
+            end_lsn,
+            commit_timestamp: 0,
+        }
+        .to_bytes()
+        .unwrap()
+        .to_vec()
+    }
+
+    fn payloads(publisher: &mut Publisher) -> Vec<char> {
+        let mut codes = vec![];
+        while let Some(message) = publisher.message() {
+            let data = CopyData::from_bytes(message.to_bytes().unwrap()).unwrap();
+            let xlog_data = data.xlog_data().unwrap();
+            codes.push(xlog_data.bytes[0] as char);
+        }
+        codes
+    }
+
+    #[test]
+    fn test_start_replication() {
+        assert_eq!(
+            start_replication(
+                "START_REPLICATION SLOT debezium LOGICAL 16/B374D848 (proto_version '1')"
+            )
+            .unwrap(),
+            "START_REPLICATION SLOT debezium LOGICAL 0/0 (proto_version '1')"
+        );
+        assert!(start_replication("IDENTIFY_SYSTEM").is_none());
+    }
+
+    #[test]
+    fn test_publisher() {
+        let mut publisher = Publisher::new(2);
+
+        // Shard 1 starts a transaction while shard 0 is in the middle of one.
+        publisher.handle(0, xlog(&begin(), 100)).unwrap();
+        publisher.handle(1, xlog(&begin(), 500)).unwrap();
+        publisher.handle(0, xlog(b"I", 100)).unwrap();
+        publisher.handle(1, xlog(b"I", 500)).unwrap();
+        publisher.handle(1, xlog(&commit(510), 510)).unwrap();
+        assert_eq!(payloads(&mut publisher), vec!['B', 'I']);
+
+        publisher.handle(0, xlog(&commit(110), 110)).unwrap();
+        assert_eq!(payloads(&mut publisher), vec!['C', 'B', 'I', 'C']);
+
+        // Consumer confirmed the first transaction only.
+        let first = publisher.commits[0].0;
+        let update = StatusUpdate {
+            last_written: first,
+            last_flushed: first,
+            last_applied: first,
+            system_clock: 0,
+            reply: 0,
+        };
+        assert_eq!(publisher.status_update(0, &update).last_flushed, 110);
+        assert_eq!(publisher.status_update(1, &update).last_flushed, 0);
+        assert_eq!(update.code(), 'r');
+    }
+
+    #[test]
+    fn test_publisher_stream() {
+        let mut publisher = Publisher::new(2);
+
+        // Shard 0 streams a block of an in-progress transaction,
+        // shard 1 sends a transaction after the block ends.
+        publisher.handle(0, xlog(b"S", 100)).unwrap();
+        publisher.handle(1, xlog(&begin(), 500)).unwrap();
+        publisher.handle(0, xlog(b"I", 100)).unwrap();
+        publisher.handle(1, xlog(&commit(510), 510)).unwrap();
+        assert_eq!(payloads(&mut publisher), vec!['S', 'I']);
+
+        publisher.handle(0, xlog(b"E", 100)).unwrap();
+        assert_eq!(payloads(&mut publisher), vec!['E', 'B', 'C']);
+
+        let stream_commit = StreamCommit {
+            xid: 1,
+            flags: 0,
+            commit_lsn: 120,
+            end_lsn: 120,
+            commit_timestamp: 0,
+        };
+        publisher
+            .handle(0, xlog(&stream_commit.to_bytes().unwrap(), 120))
+            .unwrap();
+        assert_eq!(payloads(&mut publisher), vec!['c']);
+        assert_eq!(publisher.commits.back().unwrap().2, 120);
+    }
+
+    #[test]
+    fn test_publisher_queue_full() {
+        let mut publisher = Publisher::new(2);
+        publisher.handle(0, xlog(&begin(), 100)).unwrap();
+        let full = (0..=MAX_QUEUED).try_for_each(|_| publisher.handle(1, xlog(b"I", 500)));
+        assert!(matches!(full, Err(Error::PublisherQueueFull(1))));
+    }
+}
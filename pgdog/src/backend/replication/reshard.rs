@@ -0,0 +1,1734 @@
+//! Online shard split.
+//!
+//! Rows are copied from the source database into the destination, a database
+//! configured with more shards, re-hashing every row with the destination's
+//! sharding config. Changes made in the meantime are streamed from a logical
+//! replication slot on each source shard and applied to the destination until it
+//! catches up. Clients are then paused, the last changes are applied and
+//! the source database is switched over to the destination's shards.
+//!
+//! The destination must already have the schema and the same users as the source.
+//! Transactions that change rows on more than one destination shard are applied
+//! with two-phase commit, so the destination needs `max_prepared_transactions` > 0.
+//! Sequences are set to their highest value on the source shards before switching.
+//! Tables that aren't sharded can be truncated on the source, sharded ones can't.
+//!
+//! The switch only happens in memory. Reloading the config is refused until
+//! the source database in it uses the destination's shards, so clients don't
+//! go back to the source shards.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use pg_query::NodeEnum;
+use tokio::{
+    spawn,
+    time::{sleep, timeout},
+};
+use tracing::{error, info, warn};
+
+use crate::backend::{
+    databases::{self, databases},
+    pool::{Guard, Request},
+    protocol::ProtocolMessage,
+    Cluster, Pool, Server, ServerOptions, ShardingSchema,
+};
+use crate::config::{config, Config, Role, ShardedTable};
+use crate::frontend::router::{
+    parser::{Column as KeyColumn, CopyParser, Shard, Table as KeyTable},
+    sharding::{ContextBuilder, Tables},
+};
+use crate::net::messages::{
+    replication::{
+        logical::{
+            string::escape,
+            tuple_data::{Column, Identifier},
+        },
+        xlog_data::XLogPayload,
+        Relation, ReplicationMeta, StatusUpdate, Truncate, TupleData,
+    },
+    CopyData, CopyDone, DataRow, ErrorResponse, Format, FromBytes, Message, Protocol, Query,
+    ToBytes,
+};
+use crate::net::Parameter;
+
+use super::{slots, Error};
+
+/// Publication created on the source shards.
+const PUBLICATION: &str = "pgdog_reshard";
+
+/// Tables in the publication and their columns, generated columns excluded.
+const TABLES: &str = "SELECT quote_ident(n.nspname) || '.' || quote_ident(c.relname), \
+    string_agg(quote_ident(a.attname), ', ' ORDER BY a.attnum) \
+    FROM pg_publication_tables p \
+    JOIN pg_namespace n ON n.nspname = p.schemaname \
+    JOIN pg_class c ON c.relnamespace = n.oid AND c.relname = p.tablename \
+    JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped AND a.attgenerated = '' \
+    WHERE p.pubname = 'pgdog_reshard' \
+    GROUP BY 1 ORDER BY 1";
+
+/// How often progress is confirmed to the source shards.
+const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Clients are paused once all shards are this close to the source, in bytes of WAL.
+const MAX_LAG: u64 = 1024 * 1024;
+
+/// How long to wait for running transactions once clients are paused.
+const PAUSE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait for the last changes once clients are paused.
+const CATCH_UP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Clients are paused at most this many times before giving up.
+const CUTOVER_ATTEMPTS: u32 = 5;
+
+/// Sequences and their values.
+const SEQUENCES: &str = "SELECT quote_ident(schemaname) || '.' || quote_ident(sequencename), \
+    last_value FROM pg_sequences WHERE last_value IS NOT NULL";
+
+/// Rows parsed at once during the initial copy.
+const COPY_BATCH: usize = 1000;
+
+/// Statements of a transaction kept in memory before they're sent to the destination, in bytes.
+const PENDING_LIMIT: usize = 1024 * 1024;
+
+/// Prepared transactions are committed or rolled back this many times before giving up.
+const PREPARED_ATTEMPTS: u32 = 3;
+
+/// Postgres epoch, used for replication timestamps.
+const POSTGRES_EPOCH: u64 = 946_684_800;
+
+static RESHARDS: Lazy<Mutex<BTreeMap<String, Reshard>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Where the shard split is at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    /// Creating publications.
+    Setup,
+    /// Copying rows.
+    Copying,
+    /// Applying changes made since the copy started.
+    CatchingUp,
+    /// Clients are paused while the last changes are applied.
+    CuttingOver,
+    /// The source database uses the destination shards.
+    Done,
+    /// Stopped by an error.
+    Failed,
+    /// Stopped with `RESHARD CANCEL`.
+    Cancelled,
+}
+
+impl Phase {
+    /// The split is still running.
+    pub fn running(&self) -> bool {
+        !matches!(self, Self::Done | Self::Failed | Self::Cancelled)
+    }
+}
+
+impl std::fmt::Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Setup => write!(f, "setup"),
+            Self::Copying => write!(f, "copying"),
+            Self::CatchingUp => write!(f, "catching up"),
+            Self::CuttingOver => write!(f, "cutting over"),
+            Self::Done => write!(f, "done"),
+            Self::Failed => write!(f, "failed"),
+            Self::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// Progress of one source shard.
+#[derive(Debug, Clone, Default)]
+pub struct ShardProgress {
+    /// Rows copied.
+    pub rows: usize,
+    /// Streaming changes, the copy is finished.
+    pub streaming: bool,
+    /// Last position applied to the destination.
+    pub applied: i64,
+    /// Last position written on the source.
+    pub wal_end: i64,
+    /// Transactions left prepared on the destination, to be committed or rolled back by hand.
+    pub prepared: Vec<String>,
+}
+
+impl ShardProgress {
+    /// Bytes of WAL not applied to the destination yet.
+    pub fn lag(&self) -> u64 {
+        (self.wal_end - self.applied).max(0) as u64
+    }
+}
+
+/// Shard split.
+#[derive(Debug, Clone)]
+pub struct Reshard {
+    /// Database being split.
+    pub source: String,
+    /// Database with the new shards.
+    pub destination: String,
+    /// When the split started.
+    pub started_at: SystemTime,
+    /// Where it's at.
+    pub phase: Phase,
+    /// Progress of each source shard.
+    pub shards: Vec<ShardProgress>,
+    /// Why it failed.
+    pub error: Option<String>,
+    /// Destination servers the source database was switched to.
+    servers: Vec<ServerConfig>,
+    stop: Arc<AtomicBool>,
+}
+
+/// Shard, role, host, port and database name of a server in the config.
+type ServerConfig = (usize, Role, String, u16, String);
+
+fn servers(config: &Config, database: &str) -> Vec<ServerConfig> {
+    let mut servers = config
+        .databases
+        .iter()
+        .filter(|db| db.name == database)
+        .map(|db| {
+            (
+                db.shard,
+                db.role,
+                db.host.clone(),
+                db.port,
+                db.database_name.clone().unwrap_or(db.name.clone()),
+            )
+        })
+        .collect::<Vec<_>>();
+    servers.sort();
+    servers
+}
+
+/// Check that the config has the shards resharded databases were switched to.
+/// Reloading it otherwise would switch clients back to the old shards.
+pub fn check(config: &Config) -> Result<(), Error> {
+    for reshard in RESHARDS.lock().values() {
+        if reshard.phase != Phase::Done {
+            continue;
+        }
+        let servers = servers(config, &reshard.source);
+        // Removing the database is fine.
+        if !servers.is_empty() && servers != reshard.servers {
+            return Err(Error::Reshard(format!(
+                "database \"{}\" was resharded, change its config to the shards of \"{}\" first",
+                reshard.source, reshard.destination
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Shard splits, running and finished.
+pub fn reshards() -> Vec<Reshard> {
+    RESHARDS.lock().values().cloned().collect()
+}
+
+fn update(source: &str, f: impl FnOnce(&mut Reshard)) {
+    if let Some(reshard) = RESHARDS.lock().get_mut(source) {
+        f(reshard);
+    }
+}
+
+fn phase(source: &str, phase: Phase) {
+    update(source, |reshard| reshard.phase = phase);
+}
+
+fn progress(source: &str) -> Vec<ShardProgress> {
+    RESHARDS
+        .lock()
+        .get(source)
+        .map(|reshard| reshard.shards.clone())
+        .unwrap_or_default()
+}
+
+/// Start splitting the source database into the destination's shards.
+pub fn start(source: &str, destination: &str) -> Result<(), Error> {
+    let clusters = super::slots::clusters();
+    let find = |name: &str| {
+        clusters
+            .iter()
+            .find(|cluster| cluster.name() == name)
+            .cloned()
+            .ok_or_else(|| Error::Reshard(format!("database \"{}\" doesn't exist", name)))
+    };
+    let source_cluster = find(source)?;
+    let destination_cluster = find(destination)?;
+
+    if source == destination {
+        return Err(Error::Reshard(
+            "source and destination must be different databases".into(),
+        ));
+    }
+
+    if destination_cluster.sharded_tables().is_empty() {
+        return Err(Error::Reshard(format!(
+            "database \"{}\" has no sharded tables",
+            destination
+        )));
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let mut reshards = RESHARDS.lock();
+        // Replication slots and publications use the same names.
+        if let Some(running) = reshards.values().find(|reshard| reshard.phase.running()) {
+            return Err(Error::ReshardRunning(running.source.clone()));
+        }
+        reshards.insert(
+            source.to_owned(),
+            Reshard {
+                source: source.to_owned(),
+                destination: destination.to_owned(),
+                started_at: SystemTime::now(),
+                phase: Phase::Setup,
+                shards: vec![ShardProgress::default(); source_cluster.shards().len()],
+                error: None,
+                servers: vec![],
+                stop: stop.clone(),
+            },
+        );
+    }
+
+    info!(
+        "resharding \"{}\" from {} into {} shards [{}]",
+        source,
+        source_cluster.shards().len(),
+        destination_cluster.shards().len(),
+        destination
+    );
+
+    spawn(async move {
+        run(source_cluster, destination_cluster, stop).await;
+    });
+
+    Ok(())
+}
+
+/// Stop the split, leaving the source database as it is.
+pub fn cancel(source: &str) -> Result<(), Error> {
+    match RESHARDS.lock().get(source) {
+        Some(reshard) if reshard.phase.running() => {
+            reshard.stop.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        _ => Err(Error::Reshard(format!(
+            "database \"{}\" is not being resharded",
+            source
+        ))),
+    }
+}
+
+async fn run(source: Cluster, destination: Cluster, stop: Arc<AtomicBool>) {
+    let name = source.name().to_owned();
+    let result = reshard(&source, &destination, &stop).await;
+
+    if let Err(err) = cleanup(&source).await {
+        warn!(
+            "error dropping publication \"{}\": {} [{}]",
+            PUBLICATION, err, name
+        );
+    }
+
+    match result {
+        Ok(false) => {
+            info!("resharding cancelled [{}]", name);
+            phase(&name, Phase::Cancelled);
+        }
+        Ok(true) => {
+            info!("resharding finished [{}]", name);
+            phase(&name, Phase::Done);
+        }
+        Err(err) => {
+            error!("resharding failed: {} [{}]", err, name);
+            update(&name, |reshard| {
+                reshard.phase = Phase::Failed;
+                reshard.error = Some(err.to_string());
+            });
+        }
+    }
+}
+
+/// Returns true if the source database was switched, false if cancelled.
+async fn reshard(
+    source: &Cluster,
+    destination: &Cluster,
+    stop: &Arc<AtomicBool>,
+) -> Result<bool, crate::backend::Error> {
+    let name = source.name();
+    let primaries = primaries(source)?;
+
+    for pool in &primaries {
+        let mut server = connect(pool, false).await?;
+        server
+            .execute_checked(format!(
+                "DROP PUBLICATION IF EXISTS {0}; CREATE PUBLICATION {0} FOR ALL TABLES",
+                PUBLICATION
+            ))
+            .await?;
+    }
+
+    phase(name, Phase::Copying);
+
+    let mut workers = primaries
+        .iter()
+        .enumerate()
+        .map(|(shard, pool)| {
+            let worker = Worker::new(name, shard, pool.clone(), destination.clone(), stop.clone());
+            spawn(worker.run())
+        })
+        .collect::<Vec<_>>();
+
+    let mut result = Ok(false);
+    let mut attempts = 0;
+    let mut next_attempt = Instant::now();
+
+    while !stop.load(Ordering::Relaxed) {
+        // Workers only return if they fail or are stopped.
+        if let Some(position) = workers.iter().position(|worker| worker.is_finished()) {
+            let worker = workers.remove(position);
+            result = match worker.await {
+                Ok(Ok(())) => Ok(false),
+                Ok(Err(err)) => Err(err),
+                Err(_) => Err(Error::Reshard("worker panicked".into()).into()),
+            };
+            break;
+        }
+
+        let progress = progress(name);
+        if progress.iter().all(|shard| shard.streaming) {
+            phase(name, Phase::CatchingUp);
+
+            if progress.iter().all(|shard| shard.lag() <= MAX_LAG) && Instant::now() >= next_attempt
+            {
+                phase(name, Phase::CuttingOver);
+                match cutover(source, destination, &primaries).await {
+                    Ok(false) => (),
+                    switched => {
+                        result = switched;
+                        break;
+                    }
+                }
+                phase(name, Phase::CatchingUp);
+
+                // Don't keep pausing clients.
+                attempts += 1;
+                if attempts >= CUTOVER_ATTEMPTS {
+                    result = Err(Error::Reshard(format!(
+                        "couldn't switch shards after {} attempts",
+                        attempts
+                    ))
+                    .into());
+                    break;
+                }
+                next_attempt = Instant::now() + STATUS_INTERVAL * 2_u32.pow(attempts);
+            }
+        }
+
+        sleep(STATUS_INTERVAL).await;
+    }
+
+    // Stop streaming before the publications are dropped.
+    stop.store(true, Ordering::Relaxed);
+    for worker in workers {
+        let _ = worker.await;
+    }
+
+    result
+}
+
+/// Pause clients, apply the last changes and switch the source database
+/// to the destination shards.
+///
+/// Returns false if clients were resumed without switching,
+/// so we can try again.
+async fn cutover(
+    source: &Cluster,
+    destination: &Cluster,
+    primaries: &[Pool],
+) -> Result<bool, crate::backend::Error> {
+    let pools = databases()
+        .all()
+        .iter()
+        .filter(|(user, _)| user.database == source.name())
+        .flat_map(|(_, cluster)| cluster.shards().iter().flat_map(|shard| shard.pools()))
+        .collect::<Vec<_>>();
+
+    for pool in &pools {
+        pool.pause();
+    }
+
+    let result = switch(source, destination, primaries, &pools).await;
+
+    // The source pools are shut down if we switched.
+    if !matches!(result, Ok(true)) {
+        for pool in &pools {
+            pool.resume();
+        }
+    }
+
+    result
+}
+
+async fn switch(
+    source: &Cluster,
+    destination: &Cluster,
+    primaries: &[Pool],
+    pools: &[Pool],
+) -> Result<bool, crate::backend::Error> {
+    let idle = timeout(PAUSE_TIMEOUT, async {
+        while pools.iter().any(|pool| pool.state().checked_out > 0) {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .is_ok();
+
+    if !idle {
+        warn!(
+            "clients still running transactions after {}ms, resuming [{}]",
+            PAUSE_TIMEOUT.as_millis(),
+            source.name()
+        );
+        return Ok(false);
+    }
+
+    // Nothing is writing to the source anymore.
+    let mut targets = vec![];
+    for pool in primaries {
+        let mut server = connect(pool, false).await?;
+        let rows: Vec<String> = server
+            .fetch_all("SELECT pg_current_wal_lsn()::text")
+            .await?;
+        let target = rows
+            .first()
+            .and_then(|row| lsn(row))
+            .ok_or_else(|| Error::Reshard("invalid WAL position".into()))?;
+        targets.push(target);
+    }
+
+    let caught_up = timeout(CATCH_UP_TIMEOUT, async {
+        loop {
+            let progress = progress(source.name());
+            if progress
+                .iter()
+                .zip(&targets)
+                .all(|(shard, target)| shard.applied >= *target)
+            {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .is_ok();
+
+    if !caught_up {
+        warn!(
+            "destination didn't catch up in {}ms, resuming [{}]",
+            CATCH_UP_TIMEOUT.as_millis(),
+            source.name()
+        );
+        return Ok(false);
+    }
+
+    sync_sequences(primaries, destination).await?;
+
+    let changed = databases::reshard(source.name(), destination.name());
+    if changed > 0 {
+        let servers = servers(&config().config, destination.name());
+        update(source.name(), |reshard| reshard.servers = servers);
+    }
+    info!(
+        "switched {} users to {} shards [{}]",
+        changed,
+        destination.shards().len(),
+        source.name()
+    );
+
+    Ok(changed > 0)
+}
+
+/// Set sequences on the destination shards to their highest value on the source,
+/// so new rows don't reuse keys.
+async fn sync_sequences(
+    primaries: &[Pool],
+    destination: &Cluster,
+) -> Result<(), crate::backend::Error> {
+    let mut sequences = BTreeMap::new();
+    for pool in primaries {
+        let mut server = connect(pool, false).await?;
+        let rows: Vec<DataRow> = server.fetch_all(SEQUENCES).await?;
+        for row in rows {
+            let name = row.get::<String>(0, Format::Text).unwrap_or_default();
+            let value = row.get::<i64>(1, Format::Text).unwrap_or_default();
+            let max = sequences.entry(name).or_insert(value);
+            *max = value.max(*max);
+        }
+    }
+
+    if sequences.is_empty() {
+        return Ok(());
+    }
+
+    let query = sequences
+        .iter()
+        .map(|(name, value)| {
+            format!(
+                "SELECT setval(to_regclass('{}'), {}) WHERE to_regclass('{}') IS NOT NULL",
+                escape(name, '\''),
+                value,
+                escape(name, '\'')
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    for shard in 0..destination.shards().len() {
+        let mut server = destination.primary(shard, &Request::default()).await?;
+        server.execute_checked(query.as_str()).await?;
+    }
+
+    info!(
+        "synced {} sequences [{}]",
+        sequences.len(),
+        destination.name()
+    );
+
+    Ok(())
+}
+
+async fn cleanup(source: &Cluster) -> Result<(), crate::backend::Error> {
+    for pool in primaries(source)? {
+        let mut server = connect(&pool, false).await?;
+        server
+            .execute_checked(format!("DROP PUBLICATION IF EXISTS {}", PUBLICATION))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Primary of each shard.
+fn primaries(cluster: &Cluster) -> Result<Vec<Pool>, crate::backend::Error> {
+    cluster
+        .shards()
+        .iter()
+        .map(|shard| {
+            shard
+                .pools_with_roles()
+                .into_iter()
+                .find(|(role, _)| *role == Role::Primary)
+                .map(|(_, pool)| pool)
+                .ok_or_else(|| {
+                    crate::backend::Error::from(Error::Reshard("shard has no primary".into()))
+                })
+        })
+        .collect()
+}
+
+/// Connect to the server directly, so paused pools don't get in the way.
+async fn connect(pool: &Pool, replication: bool) -> Result<Server, crate::backend::Error> {
+    let mut options: ServerOptions = pool.server_options();
+    if replication {
+        options.params.push(Parameter {
+            name: "replication".into(),
+            value: "database".into(),
+        });
+    }
+    Server::connect(pool.addr(), options).await
+}
+
+fn error_response(message: &Message) -> crate::backend::Error {
+    match message.to_bytes().and_then(ErrorResponse::from_bytes) {
+        Ok(error) => crate::backend::Error::ExecutionError(Box::new(error)),
+        Err(err) => err.into(),
+    }
+}
+
+/// Parse a WAL position, e.g. `16/B374D848`.
+fn lsn(text: &str) -> Option<i64> {
+    let (high, low) = text.trim().split_once('/')?;
+    let high = u32::from_str_radix(high, 16).ok()? as i64;
+    let low = u32::from_str_radix(low, 16).ok()? as i64;
+    Some((high << 32) | low)
+}
+
+/// Quote a column value. Unchanged TOAST values aren't sent by the source.
+fn literal(column: &Column) -> Result<Option<String>, Error> {
+    match column.identifier {
+        Identifier::Null => Ok(Some("NULL".into())),
+        Identifier::Toasted => Ok(None),
+        Identifier::Format(Format::Text) => column
+            .as_str()
+            .map(|text| Some(format!("'{}'", escape(text, '\''))))
+            .ok_or(Error::Net(crate::net::Error::NotTextEncoding)),
+        Identifier::Format(Format::Binary) => Err(Error::Net(crate::net::Error::NotTextEncoding)),
+    }
+}
+
+/// Table changed on the source.
+#[derive(Debug)]
+struct Table {
+    relation: Relation,
+    /// Position of the sharding key and how it's sharded, if the table is sharded.
+    key: Option<(usize, ShardedTable)>,
+}
+
+impl Table {
+    fn new(relation: Relation, schema: &ShardingSchema) -> Self {
+        let key = {
+            let columns = relation
+                .columns
+                .iter()
+                .map(|column| KeyColumn {
+                    name: column.name.as_str(),
+                })
+                .collect::<Vec<_>>();
+            let table = KeyTable {
+                name: relation.name(),
+                schema: Some(relation.namespace.as_str()),
+            };
+            Tables::new(schema)
+                .key(table, &columns)
+                .map(|key| (key.position, key.table.clone()))
+        };
+
+        Self { relation, key }
+    }
+
+    /// Destination shard for the row. NULL keys go to all shards, like in COPY.
+    fn shard(&self, tuple: &TupleData, shards: usize) -> Result<Shard, Error> {
+        let Some((position, ref table)) = self.key else {
+            return Ok(Shard::All);
+        };
+
+        match tuple.columns.get(position) {
+            Some(column) if matches!(column.identifier, Identifier::Format(Format::Text)) => {
+                let value = column
+                    .as_str()
+                    .ok_or(Error::Net(crate::net::Error::NotTextEncoding))?;
+                Ok(ContextBuilder::new(table)
+                    .data(value)
+                    .shards(shards)
+                    .build()?
+                    .apply()?)
+            }
+            _ => Ok(Shard::All),
+        }
+    }
+
+    fn insert(&self, tuple: &TupleData) -> Result<String, Error> {
+        let mut columns = vec![];
+        let mut values = vec![];
+        for (column, value) in self.relation.columns.iter().zip(&tuple.columns) {
+            let value = literal(value)?.ok_or_else(|| {
+                Error::Reshard(format!(
+                    "missing TOAST value for \"{}\" in {}",
+                    column.name,
+                    self.relation.name()
+                ))
+            })?;
+            columns.push(column.to_sql()?);
+            values.push(value);
+        }
+
+        Ok(format!(
+            "INSERT INTO {} ({}) VALUES ({})",
+            self.relation.to_sql()?,
+            columns.join(", "),
+            values.join(", ")
+        ))
+    }
+
+    /// Match the row using its replica identity.
+    fn filter(&self, identity: &TupleData) -> Result<String, Error> {
+        let mut filter = vec![];
+        for (column, value) in self.relation.columns.iter().zip(&identity.columns) {
+            if column.flag & 1 == 0 {
+                continue;
+            }
+            match literal(value)? {
+                Some(value) if value == "NULL" => {
+                    filter.push(format!("{} IS NULL", column.to_sql()?))
+                }
+                Some(value) => filter.push(format!("{} = {}", column.to_sql()?, value)),
+                None => (),
+            }
+        }
+
+        if filter.is_empty() {
+            return Err(Error::Reshard(format!(
+                "{} has no replica identity",
+                self.relation.name()
+            )));
+        }
+
+        Ok(filter.join(" AND "))
+    }
+
+    fn update(&self, identity: &TupleData, new: &TupleData) -> Result<String, Error> {
+        let mut set = vec![];
+        for (column, value) in self.relation.columns.iter().zip(&new.columns) {
+            if let Some(value) = literal(value)? {
+                set.push(format!("{} = {}", column.to_sql()?, value));
+            }
+        }
+
+        Ok(format!(
+            "UPDATE {} SET {} WHERE {}",
+            self.relation.to_sql()?,
+            set.join(", "),
+            self.filter(identity)?
+        ))
+    }
+
+    fn delete(&self, identity: &TupleData) -> Result<String, Error> {
+        Ok(format!(
+            "DELETE FROM {} WHERE {}",
+            self.relation.to_sql()?,
+            self.filter(identity)?
+        ))
+    }
+
+    /// Statements applying the change to the destination, and their shards.
+    fn changes(&self, payload: &XLogPayload, shards: usize) -> Result<Vec<(Shard, String)>, Error> {
+        Ok(match payload {
+            XLogPayload::Insert(insert) => vec![(
+                self.shard(&insert.tuple_data, shards)?,
+                self.insert(&insert.tuple_data)?,
+            )],
+
+            XLogPayload::Update(update) => {
+                let identity = update.key.as_ref().or(update.old.as_ref());
+                let new = self.shard(&update.new, shards)?;
+                let old = match identity {
+                    Some(identity) => self.shard(identity, shards)?,
+                    None => new.clone(),
+                };
+
+                match (old, identity) {
+                    // The sharding key changed, so the row moves to another shard.
+                    (Shard::Direct(old), Some(identity)) if new != Shard::Direct(old) => vec![
+                        (Shard::Direct(old), self.delete(identity)?),
+                        (new, self.insert(&update.new)?),
+                    ],
+                    _ => vec![(
+                        new,
+                        self.update(identity.unwrap_or(&update.new), &update.new)?,
+                    )],
+                }
+            }
+
+            XLogPayload::Delete(delete) => {
+                let identity = delete.key.as_ref().or(delete.old.as_ref()).ok_or_else(|| {
+                    Error::Reshard(format!("{} has no replica identity", self.relation.name()))
+                })?;
+                vec![(self.shard(identity, shards)?, self.delete(identity)?)]
+            }
+
+            _ => vec![],
+        })
+    }
+}
+
+/// Copies and streams rows from one source shard.
+struct Worker {
+    source: String,
+    shard: usize,
+    pool: Pool,
+    destination: Cluster,
+    schema: ShardingSchema,
+    stop: Arc<AtomicBool>,
+    tables: HashMap<i32, Table>,
+    /// Statements of the current transaction not sent yet, for each destination shard.
+    pending: Vec<Vec<String>>,
+    /// Size of the statements not sent yet, in bytes.
+    pending_bytes: usize,
+    /// Destination shards the current transaction was started on.
+    open: Vec<Option<Guard>>,
+    in_transaction: bool,
+    applied: i64,
+    wal_end: i64,
+}
+
+impl Worker {
+    fn new(
+        source: &str,
+        shard: usize,
+        pool: Pool,
+        destination: Cluster,
+        stop: Arc<AtomicBool>,
+    ) -> Self {
+        let schema = destination.sharding_schema();
+        Self {
+            source: source.to_owned(),
+            shard,
+            pool,
+            pending: vec![vec![]; schema.shards],
+            pending_bytes: 0,
+            open: (0..schema.shards).map(|_| None).collect(),
+            destination,
+            schema,
+            stop,
+            tables: HashMap::new(),
+            in_transaction: false,
+            applied: 0,
+            wal_end: 0,
+        }
+    }
+
+    fn record(&self, f: impl FnOnce(&mut ShardProgress)) {
+        update(&self.source, |reshard| {
+            if let Some(progress) = reshard.shards.get_mut(self.shard) {
+                f(progress);
+            }
+        });
+    }
+
+    async fn run(mut self) -> Result<(), crate::backend::Error> {
+        let slot = format!("{}_{}", PUBLICATION, self.shard);
+        let mut replication = connect(&self.pool, true).await?;
+
+        // The slot is dropped when we disconnect, so nothing is left behind.
+        let (start, snapshot) = slots::create_temporary_slot(&mut replication, &slot).await?;
+
+        self.applied = lsn(&start).ok_or_else(|| Error::Reshard("invalid WAL position".into()))?;
+        self.wal_end = self.applied;
+
+        self.copy(&snapshot).await?;
+        self.stream(&mut replication, &slot, &start).await
+    }
+
+    /// Copy all tables as of the moment the slot was created.
+    async fn copy(&mut self, snapshot: &str) -> Result<(), crate::backend::Error> {
+        let mut source = connect(&self.pool, false).await?;
+        source
+            .execute_checked("BEGIN ISOLATION LEVEL REPEATABLE READ")
+            .await?;
+        source
+            .execute_checked(format!("SET TRANSACTION SNAPSHOT '{}'", snapshot))
+            .await?;
+
+        let tables: Vec<DataRow> = source.fetch_all(TABLES).await?;
+        for table in tables {
+            let name = table.get::<String>(0, Format::Text).unwrap_or_default();
+            let columns = table.get::<String>(1, Format::Text).unwrap_or_default();
+            self.copy_table(&mut source, &name, &columns).await?;
+        }
+
+        source.execute_checked("COMMIT").await?;
+
+        Ok(())
+    }
+
+    async fn copy_table(
+        &mut self,
+        source: &mut Server,
+        table: &str,
+        columns: &str,
+    ) -> Result<(), crate::backend::Error> {
+        let copy_in = format!("COPY {} ({}) FROM STDIN WITH (FORMAT csv)", table, columns);
+        let ast = pg_query::parse(&copy_in).map_err(|e| Error::Reshard(e.to_string()))?;
+        let stmt = match ast
+            .protobuf
+            .stmts
+            .first()
+            .and_then(|stmt| stmt.stmt.as_ref())
+            .and_then(|stmt| stmt.node.as_ref())
+        {
+            Some(NodeEnum::CopyStmt(stmt)) => stmt.clone(),
+            _ => return Err(Error::Reshard(format!("can't copy {}", table)).into()),
+        };
+
+        let mut parser = CopyParser::new(&stmt, &self.destination)
+            .map_err(|e| crate::backend::Error::Router(e.to_string()))?
+            .ok_or_else(|| Error::Reshard(format!("can't copy {}", table)))?;
+
+        // Tables that aren't sharded have the same rows on all source shards.
+        if !parser.sharded() && self.shard > 0 {
+            return Ok(());
+        }
+
+        let mut destinations = vec![];
+        for shard in 0..self.schema.shards {
+            let mut server = self.destination.primary(shard, &Request::default()).await?;
+            server
+                .send_one(&ProtocolMessage::Query(Query::new(&copy_in)))
+                .await?;
+            server.flush().await?;
+            loop {
+                let message = server.read().await?;
+                match message.code() {
+                    'G' => break,
+                    'E' => return Err(error_response(&message)),
+                    _ => (),
+                }
+            }
+            destinations.push(server);
+        }
+
+        source
+            .send_one(&ProtocolMessage::Query(Query::new(format!(
+                "COPY {} ({}) TO STDOUT WITH (FORMAT csv)",
+                table, columns
+            ))))
+            .await?;
+        source.flush().await?;
+
+        let mut batch = vec![];
+        let mut rows = 0;
+        loop {
+            let message = source.read().await?;
+            let done = match message.code() {
+                'd' => {
+                    batch.push(CopyData::from_bytes(message.to_bytes()?)?);
+                    false
+                }
+                'E' => return Err(error_response(&message)),
+                'Z' => true,
+                _ => false,
+            };
+
+            if batch.len() >= COPY_BATCH || (done && !batch.is_empty()) {
+                rows += batch.len();
+                for row in parser
+                    .shard(std::mem::take(&mut batch))
+                    .map_err(|e| crate::backend::Error::Router(e.to_string()))?
+                {
+                    let shards = match row.shard() {
+                        Shard::Direct(shard) => vec![*shard],
+                        Shard::Multi(shards) => shards.clone(),
+                        Shard::All => (0..destinations.len()).collect(),
+                    };
+                    let message = ProtocolMessage::CopyData(row.message());
+                    for shard in shards {
+                        if let Some(server) = destinations.get_mut(shard) {
+                            server.send_one(&message).await?;
+                        }
+                    }
+                }
+                for server in destinations.iter_mut() {
+                    server.flush().await?;
+                }
+                self.record(|progress| progress.rows = rows);
+            }
+
+            if done {
+                break;
+            }
+        }
+
+        for server in destinations.iter_mut() {
+            server
+                .send_one(&ProtocolMessage::CopyDone(CopyDone))
+                .await?;
+            server.flush().await?;
+        }
+
+        for server in destinations.iter_mut() {
+            let mut error = None;
+            loop {
+                let message = server.read().await?;
+                match message.code() {
+                    'E' => error = Some(error_response(&message)),
+                    'Z' => break,
+                    _ => (),
+                }
+            }
+            if let Some(error) = error {
+                return Err(error);
+            }
+        }
+
+        info!("copied {} rows of {} [{}]", rows, table, self.pool.addr());
+
+        Ok(())
+    }
+
+    /// Apply changes made since the slot was created, until stopped.
+    async fn stream(
+        &mut self,
+        server: &mut Server,
+        slot: &str,
+        start: &str,
+    ) -> Result<(), crate::backend::Error> {
+        server
+            .send_one(&ProtocolMessage::Query(Query::new(format!(
+                "START_REPLICATION SLOT {} LOGICAL {} (proto_version '1', publication_names '{}')",
+                slot, start, PUBLICATION
+            ))))
+            .await?;
+        server.flush().await?;
+
+        loop {
+            let message = server.read().await?;
+            match message.code() {
+                'W' => break,
+                'E' => return Err(error_response(&message)),
+                _ => (),
+            }
+        }
+
+        self.record(|progress| progress.streaming = true);
+
+        let mut status = Instant::now();
+        while !self.stop.load(Ordering::Relaxed) {
+            // Reading isn't cancel-safe, waiting for data is.
+            if let Ok(ready) = timeout(STATUS_INTERVAL, server.ready()).await {
+                ready?;
+                let message = server.read().await?;
+                match message.code() {
+                    'd' => {
+                        self.handle(CopyData::from_bytes(message.to_bytes()?)?)
+                            .await?
+                    }
+                    'E' => return Err(error_response(&message)),
+                    'c' => return Err(Error::Reshard("replication stopped".into()).into()),
+                    _ => (),
+                }
+            }
+
+            if status.elapsed() >= STATUS_INTERVAL {
+                self.status_update(server).await?;
+                status = Instant::now();
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, data: CopyData) -> Result<(), crate::backend::Error> {
+        if let Some(xlog_data) = data.xlog_data() {
+            self.wal_end = self.wal_end.max(xlog_data.current_end);
+
+            match xlog_data.payload() {
+                Some(XLogPayload::Relation(relation)) => {
+                    self.tables
+                        .insert(relation.oid, Table::new(relation, &self.schema));
+                }
+
+                Some(XLogPayload::Begin(_)) => self.in_transaction = true,
+
+                Some(XLogPayload::Commit(commit)) => {
+                    self.apply(commit.end_lsn).await?;
+                    self.in_transaction = false;
+                    self.applied = self.applied.max(commit.end_lsn);
+                }
+
+                Some(XLogPayload::Truncate(truncate)) => self.truncate(&truncate)?,
+
+                Some(payload) => self.change(&payload)?,
+
+                None => (),
+            }
+
+            if self.pending_bytes >= PENDING_LIMIT {
+                self.flush().await?;
+            }
+        } else if let Some(ReplicationMeta::KeepAlive(keep_alive)) = data.replication_meta() {
+            self.wal_end = self.wal_end.max(keep_alive.wal_end);
+            // Nothing left to apply.
+            if !self.in_transaction {
+                self.applied = self.applied.max(keep_alive.wal_end);
+            }
+        }
+
+        let (applied, wal_end) = (self.applied, self.wal_end);
+        self.record(|progress| {
+            progress.applied = applied;
+            progress.wal_end = wal_end;
+        });
+
+        Ok(())
+    }
+
+    /// Queue the row change for the destination shards.
+    fn change(&mut self, payload: &XLogPayload) -> Result<(), Error> {
+        let oid = match payload {
+            XLogPayload::Insert(insert) => insert.oid,
+            XLogPayload::Update(update) => update.oid,
+            XLogPayload::Delete(delete) => delete.oid,
+            _ => return Ok(()),
+        };
+        let table = self.tables.get(&oid).ok_or(Error::NoRelationMessage)?;
+        let sharded = table.key.is_some();
+
+        for (shard, statement) in table.changes(payload, self.schema.shards)? {
+            let shards = match shard {
+                Shard::Direct(shard) => vec![shard],
+                Shard::Multi(shards) => shards,
+                // Tables that aren't sharded have the same rows on all source shards.
+                Shard::All if !sharded && self.shard > 0 => vec![],
+                Shard::All => (0..self.schema.shards).collect(),
+            };
+            for shard in shards {
+                self.queue(shard, &statement);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queue a TRUNCATE for all destination shards.
+    ///
+    /// Rows of sharded tables from this source shard are mixed with rows
+    /// from the others on the destination, so they can't be truncated.
+    fn truncate(&mut self, truncate: &Truncate) -> Result<(), Error> {
+        let mut tables = vec![];
+        for oid in &truncate.oids {
+            let table = self.tables.get(oid).ok_or(Error::NoRelationMessage)?;
+            if table.key.is_some() {
+                return Err(Error::Reshard(format!(
+                    "{} was truncated on the source, and sharded tables can't be truncated on the destination",
+                    table.relation.name()
+                )));
+            }
+            tables.push(table.relation.to_sql()?);
+        }
+
+        // Tables that aren't sharded have the same rows on all source shards.
+        if self.shard > 0 || tables.is_empty() {
+            return Ok(());
+        }
+
+        let mut statement = format!("TRUNCATE {}", tables.join(", "));
+        if truncate.restart_identity() {
+            statement.push_str(" RESTART IDENTITY");
+        }
+        if truncate.cascade() {
+            statement.push_str(" CASCADE");
+        }
+        for shard in 0..self.schema.shards {
+            self.queue(shard, &statement);
+        }
+
+        Ok(())
+    }
+
+    fn queue(&mut self, shard: usize, statement: &str) {
+        if let Some(pending) = self.pending.get_mut(shard) {
+            pending.push(statement.to_owned());
+            self.pending_bytes += statement.len();
+        }
+    }
+
+    /// Send queued statements to the destination shards, starting the transaction
+    /// on the ones that don't have it yet, so large transactions aren't kept in memory.
+    async fn flush(&mut self) -> Result<(), crate::backend::Error> {
+        for (shard, statements) in self.pending.iter_mut().enumerate() {
+            if statements.is_empty() {
+                continue;
+            }
+
+            let (server, query) = match self.open[shard] {
+                Some(ref mut server) => (server, statements.join("; ")),
+                None => {
+                    let server = self.destination.primary(shard, &Request::default()).await?;
+                    (
+                        self.open[shard].insert(server),
+                        format!("BEGIN; {}", statements.join("; ")),
+                    )
+                }
+            };
+            server.execute_checked(query).await?;
+            statements.clear();
+        }
+
+        self.pending_bytes = 0;
+
+        Ok(())
+    }
+
+    /// Commit the transaction on the destination shards it changed.
+    ///
+    /// Transactions changing more than one shard are prepared on all of them first,
+    /// so they're committed on all shards or none.
+    async fn apply(&mut self, lsn: i64) -> Result<(), crate::backend::Error> {
+        self.flush().await?;
+
+        let mut servers = self
+            .open
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(shard, server)| Some((shard, server.take()?)))
+            .collect::<Vec<_>>();
+
+        if let [(_, ref mut server)] = servers[..] {
+            server.execute_checked("COMMIT").await?;
+        } else if !servers.is_empty() {
+            let source = self.shard;
+            let gid = |shard: usize| format!("pgdog_reshard_{}_{:X}_{}", source, lsn, shard);
+            let mut prepared = vec![];
+            let mut error = None;
+
+            for (shard, mut server) in servers {
+                if error.is_some() {
+                    // Leave the connection usable.
+                    let _ = server.execute("ROLLBACK").await;
+                    continue;
+                }
+                // A transaction that fails to prepare is rolled back.
+                match server
+                    .execute_checked(format!("PREPARE TRANSACTION '{}'", gid(shard)))
+                    .await
+                {
+                    Ok(_) => prepared.push((shard, server)),
+                    Err(err) => error = Some(err),
+                }
+            }
+
+            let end = if error.is_some() {
+                "ROLLBACK PREPARED"
+            } else {
+                "COMMIT PREPARED"
+            };
+            self.end_prepared(prepared, end, gid).await?;
+
+            if let Some(error) = error {
+                return Err(error);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Commit or roll back transactions prepared on the destination shards.
+    ///
+    /// Postgres keeps them until then, so failures are retried with a new connection.
+    /// The ones that still fail are recorded, to be finished by hand.
+    async fn end_prepared(
+        &self,
+        prepared: Vec<(usize, Guard)>,
+        end: &str,
+        gid: impl Fn(usize) -> String,
+    ) -> Result<(), crate::backend::Error> {
+        let mut left = vec![];
+
+        for (shard, server) in prepared {
+            let query = format!("{} '{}'", end, gid(shard));
+            let mut server = Some(server);
+            let mut attempt = 0;
+            let mut lost = false;
+
+            let result = loop {
+                attempt += 1;
+                // Connect directly, the pool could be banned after losing the connection.
+                let result = match server.take() {
+                    Some(mut server) => server.execute_checked(query.as_str()).await,
+                    None => match self.connect(shard).await {
+                        Ok(mut server) => server.execute_checked(query.as_str()).await,
+                        Err(err) => Err(err),
+                    },
+                };
+
+                match result {
+                    Ok(_) => break Ok(()),
+                    // Finished by the attempt that lost its connection.
+                    Err(crate::backend::Error::ExecutionError(error))
+                        if lost && error.code == "42704" =>
+                    {
+                        break Ok(())
+                    }
+                    Err(err) if attempt >= PREPARED_ATTEMPTS => break Err(err),
+                    Err(err) => {
+                        warn!("{} failed on shard {}, retrying: {}", query, shard, err);
+                        lost = !matches!(err, crate::backend::Error::ExecutionError(_));
+                        sleep(STATUS_INTERVAL / 10 * attempt).await;
+                    }
+                }
+            };
+
+            if let Err(err) = result {
+                error!(
+                    "prepared transaction \"{}\" left on destination shard {}: {}",
+                    gid(shard),
+                    shard,
+                    err
+                );
+                left.push(gid(shard));
+            }
+        }
+
+        if left.is_empty() {
+            return Ok(());
+        }
+
+        self.record(|progress| progress.prepared.extend(left.iter().cloned()));
+
+        Err(Error::Reshard(format!(
+            "prepared transactions {} were left on the destination",
+            left.join(", ")
+        ))
+        .into())
+    }
+
+    /// Connect to the primary of a destination shard.
+    async fn connect(&self, shard: usize) -> Result<Server, crate::backend::Error> {
+        let pool = primaries(&self.destination)?
+            .into_iter()
+            .nth(shard)
+            .ok_or_else(|| Error::Reshard(format!("destination has no shard {}", shard)))?;
+        connect(&pool, false).await
+    }
+
+    /// Confirm applied changes, so the source can recycle its WAL.
+    async fn status_update(&self, server: &mut Server) -> Result<(), crate::backend::Error> {
+        let system_clock = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_micros() as i64 - (POSTGRES_EPOCH * 1_000_000) as i64)
+            .unwrap_or_default();
+        let update = StatusUpdate {
+            last_written: self.applied,
+            last_flushed: self.applied,
+            last_applied: self.applied,
+            system_clock,
+            reply: 0,
+        };
+
+        server
+            .send_one(&ProtocolMessage::CopyData(CopyData::bytes(
+                update.to_bytes()?,
+            )))
+            .await?;
+        server.flush().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bytes::Bytes;
+
+    use crate::backend::replication::ShardedTables;
+    use crate::backend::server::test::test_server;
+    use crate::config::{DataType, Database};
+    use crate::net::messages::replication::logical::relation::Column as RelationColumn;
+    use crate::net::messages::replication::{Delete, Insert, Update};
+
+    use super::*;
+
+    fn relation() -> Relation {
+        Relation {
+            oid: 1,
+            namespace: "public".into(),
+            name: "users".into(),
+            replica_identity: b'd' as i8,
+            columns: ["id", "email"]
+                .into_iter()
+                .enumerate()
+                .map(|(i, name)| RelationColumn {
+                    flag: (i == 0) as i8,
+                    name: name.into(),
+                    oid: 0,
+                    type_modifier: -1,
+                })
+                .collect(),
+        }
+    }
+
+    fn tuple(values: &[Option<&str>]) -> TupleData {
+        TupleData {
+            columns: values
+                .iter()
+                .map(|value| match value {
+                    Some(value) => Column {
+                        identifier: Identifier::Format(Format::Text),
+                        len: value.len() as i32,
+                        data: Bytes::copy_from_slice(value.as_bytes()),
+                    },
+                    None => Column {
+                        identifier: Identifier::Null,
+                        len: 0,
+                        data: Bytes::new(),
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    fn schema() -> ShardingSchema {
+        ShardingSchema {
+            shards: 4,
+            tables: ShardedTables::new(
+                vec![ShardedTable {
+                    name: Some("users".into()),
+                    column: "id".into(),
+                    data_type: DataType::Bigint,
+                    ..Default::default()
+                }],
+                vec![],
+                false,
+            ),
+        }
+    }
+
+    #[test]
+    fn test_lsn() {
+        assert_eq!(lsn("16/B374D848"), Some(0x16_B374_D848));
+        assert_eq!(lsn("0/0"), Some(0));
+        assert_eq!(lsn("nope"), None);
+    }
+
+    #[test]
+    fn test_changes() {
+        let table = Table::new(relation(), &schema());
+        assert_eq!(table.key.as_ref().map(|key| key.0), Some(0));
+
+        let insert = XLogPayload::Insert(Insert {
+            xid: None,
+            oid: 1,
+            tuple_data: tuple(&[Some("1"), Some("o'brien@example.com")]),
+        });
+        let changes = table.changes(&insert, 4).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert!(matches!(changes[0].0, Shard::Direct(_)));
+        assert_eq!(
+            changes[0].1,
+            r#"INSERT INTO "public"."users" ("id", "email") VALUES ('1', 'o''brien@example.com')"#
+        );
+
+        let delete = XLogPayload::Delete(Delete {
+            oid: 1,
+            key: Some(tuple(&[Some("1"), None])),
+            old: None,
+        });
+        let changes = table.changes(&delete, 4).unwrap();
+        assert_eq!(
+            changes[0].1,
+            r#"DELETE FROM "public"."users" WHERE "id" = '1'"#
+        );
+
+        // Moving the row to another shard is a delete and an insert.
+        let moved = (2..100)
+            .map(|id| id.to_string())
+            .find(|id| {
+                table.shard(&tuple(&[Some(id.as_str()), None]), 4).unwrap()
+                    != table.shard(&tuple(&[Some("1"), None]), 4).unwrap()
+            })
+            .unwrap();
+        let update = XLogPayload::Update(Update {
+            oid: 1,
+            key: Some(tuple(&[Some("1"), None])),
+            old: None,
+            new: tuple(&[Some(moved.as_str()), Some("a@example.com")]),
+        });
+        let changes = table.changes(&update, 4).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].1.starts_with("DELETE"));
+        assert!(changes[1].1.starts_with("INSERT"));
+
+        let update = XLogPayload::Update(Update {
+            oid: 1,
+            key: None,
+            old: None,
+            new: tuple(&[Some("1"), Some("b@example.com")]),
+        });
+        let changes = table.changes(&update, 4).unwrap();
+        assert_eq!(
+            changes[0].1,
+            r#"UPDATE "public"."users" SET "id" = '1', "email" = 'b@example.com' WHERE "id" = '1'"#
+        );
+    }
+
+    fn worker(source: &str, shard: usize, destination: &Cluster) -> Worker {
+        RESHARDS.lock().insert(
+            source.into(),
+            Reshard {
+                source: source.into(),
+                destination: "destination".into(),
+                started_at: SystemTime::now(),
+                phase: Phase::CatchingUp,
+                shards: vec![ShardProgress::default(); 2],
+                error: None,
+                servers: vec![],
+                stop: Arc::new(AtomicBool::new(false)),
+            },
+        );
+
+        let mut worker = Worker::new(
+            source,
+            shard,
+            Pool::new_test(),
+            destination.clone(),
+            Arc::new(AtomicBool::new(false)),
+        );
+        worker.schema = schema();
+        worker.pending = vec![vec![]; 4];
+        worker.open = (0..4).map(|_| None).collect();
+        worker
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut worker = worker("test_truncate", 0, &Cluster::new_test());
+        let mut countries = relation();
+        countries.oid = 2;
+        countries.name = "countries".into();
+        worker
+            .tables
+            .insert(1, Table::new(relation(), &worker.schema));
+        worker
+            .tables
+            .insert(2, Table::new(countries, &worker.schema));
+
+        let truncate = |oids: Vec<i32>| Truncate {
+            num_relations: oids.len() as i32,
+            options: 2,
+            oids,
+        };
+
+        // Sent to all destination shards.
+        worker.truncate(&truncate(vec![2])).unwrap();
+        for pending in &worker.pending {
+            assert_eq!(
+                pending,
+                &vec![r#"TRUNCATE "public"."countries" RESTART IDENTITY"#.to_string()]
+            );
+        }
+
+        // Rows of the sharded table are on all destination shards.
+        assert!(worker.truncate(&truncate(vec![2, 1])).is_err());
+
+        RESHARDS.lock().remove("test_truncate");
+    }
+
+    async fn rows(server: &mut Server) -> usize {
+        server
+            .fetch_all::<DataRow>("SELECT id FROM test_end_prepared")
+            .await
+            .unwrap()
+            .len()
+    }
+
+    #[tokio::test]
+    async fn test_end_prepared() {
+        let destination = Cluster::new_test();
+        destination.launch();
+        let worker = worker("test_end_prepared", 0, &destination);
+        let gid = |shard: usize| format!("test_end_prepared_{}", shard);
+
+        let mut admin = test_server().await;
+        for shard in 0..2 {
+            let _ = admin
+                .execute(format!("ROLLBACK PREPARED '{}'", gid(shard)))
+                .await;
+        }
+        admin
+            .execute_checked(
+                "DROP TABLE IF EXISTS test_end_prepared; CREATE TABLE test_end_prepared (id BIGINT)",
+            )
+            .await
+            .unwrap();
+
+        let prepare = |shard: usize| {
+            let destination = destination.clone();
+            async move {
+                let mut server = destination
+                    .primary(shard, &Request::default())
+                    .await
+                    .unwrap();
+                server
+                    .execute_checked(format!(
+                        "BEGIN; INSERT INTO test_end_prepared VALUES ({}); PREPARE TRANSACTION '{}'",
+                        shard,
+                        gid(shard)
+                    ))
+                    .await
+                    .unwrap();
+                (shard, server)
+            }
+        };
+
+        // The connection to the second shard is lost while committing, so it's retried.
+        let prepared = vec![prepare(0).await, prepare(1).await];
+        admin
+            .execute_checked(format!(
+                "SELECT pg_terminate_backend({})",
+                prepared[1].1.id().pid
+            ))
+            .await
+            .unwrap();
+        worker
+            .end_prepared(prepared, "COMMIT PREPARED", gid)
+            .await
+            .unwrap();
+        assert_eq!(rows(&mut admin).await, 2);
+
+        // Commit fails on the second shard: the first one is committed
+        // and the second one is recorded.
+        let missing = destination.primary(1, &Request::default()).await.unwrap();
+        let prepared = vec![prepare(0).await, (1, missing)];
+        assert!(worker
+            .end_prepared(prepared, "COMMIT PREPARED", gid)
+            .await
+            .is_err());
+        assert_eq!(rows(&mut admin).await, 3);
+        assert_eq!(progress("test_end_prepared")[0].prepared, vec![gid(1)]);
+
+        RESHARDS.lock().remove("test_end_prepared");
+        admin
+            .execute_checked("DROP TABLE test_end_prepared")
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_check() {
+        let database = |name: &str, shard: usize| Database {
+            name: name.into(),
+            host: "127.0.0.1".into(),
+            port: 5432,
+            shard,
+            database_name: Some(format!("shard_{}", shard)),
+            ..Default::default()
+        };
+        let mut config = Config {
+            databases: vec![
+                database("test_check_source", 0),
+                database("test_check_destination", 0),
+                database("test_check_destination", 1),
+            ],
+            ..Default::default()
+        };
+
+        RESHARDS.lock().insert(
+            "test_check_source".into(),
+            Reshard {
+                source: "test_check_source".into(),
+                destination: "test_check_destination".into(),
+                started_at: SystemTime::now(),
+                phase: Phase::Done,
+                shards: vec![],
+                error: None,
+                servers: servers(&config, "test_check_destination"),
+                stop: Arc::new(AtomicBool::new(false)),
+            },
+        );
+
+        // Still has the old shards.
+        assert!(check(&config).is_err());
+
+        config.databases = vec![
+            database("test_check_source", 0),
+            database("test_check_source", 1),
+        ];
+        assert!(check(&config).is_ok());
+
+        config.databases.clear();
+        assert!(check(&config).is_ok());
+
+        RESHARDS.lock().remove("test_check_source");
+    }
+}
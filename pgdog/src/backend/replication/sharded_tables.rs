@@ -44,6 +44,18 @@ impl ShardedTables {
 
     /// Find out which column (if any) is sharded in the given table.
     pub fn sharded_column(&self, table: &str, columns: &[&str]) -> Option<ShardedColumn> {
+        self.sharded_table(table, columns)
+            .map(|(sharded_table, position)| ShardedColumn {
+                data_type: sharded_table.data_type,
+                position,
+                centroids: sharded_table.centroids.clone(),
+                centroid_probes: sharded_table.centroid_probes,
+            })
+    }
+
+    /// Find the sharded table configuration for the given table,
+    /// and the position of its sharded column.
+    pub fn sharded_table(&self, table: &str, columns: &[&str]) -> Option<(&ShardedTable, usize)> {
         let with_names = self
             .tables()
             .iter()
@@ -52,29 +64,20 @@ impl ShardedTables {
         let without_names = self.tables().iter().filter(|t| t.name.is_none());
 
         let get_column = |sharded_table: &ShardedTable, columns: &[&str]| {
-            if let Some(position) = columns.iter().position(|c| *c == sharded_table.column) {
-                Some(ShardedColumn {
-                    data_type: sharded_table.data_type,
-                    position,
-                    centroids: sharded_table.centroids.clone(),
-                    centroid_probes: sharded_table.centroid_probes,
-                })
-            } else {
-                None
-            }
+            columns.iter().position(|c| *c == sharded_table.column)
         };
 
         for sharded_table in with_names {
             if Some(table) == sharded_table.name.as_deref() {
-                if let Some(column) = get_column(sharded_table, columns) {
-                    return Some(column);
+                if let Some(position) = get_column(sharded_table, columns) {
+                    return Some((sharded_table, position));
                 }
             }
         }
 
         for sharded_table in without_names {
-            if let Some(column) = get_column(sharded_table, columns) {
-                return Some(column);
+            if let Some(position) = get_column(sharded_table, columns) {
+                return Some((sharded_table, position));
             }
         }
 
@@ -0,0 +1,304 @@
+//! Replication slots on all shards.
+//!
+//! Shards keep WAL until the consumer of a slot confirms it received it.
+//! A slot left behind, e.g. by an abandoned shard split, keeps WAL forever,
+//! so slots are created and dropped on all shards at once and we warn when
+//! one of them retains too much.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::{spawn, time::sleep};
+use tracing::{debug, error, info, warn};
+
+use crate::backend::{databases::databases, pool::Request, Cluster, Server};
+use crate::config::Config;
+use crate::net::messages::{DataRow, Format};
+
+use super::Error;
+
+const QUERY: &str = "SELECT slot_name::text, \
+    COALESCE(plugin::text, ''), \
+    slot_type::text, \
+    COALESCE(database::text, ''), \
+    active::text, \
+    COALESCE((pg_current_wal_lsn() - restart_lsn)::bigint, 0) \
+    FROM pg_replication_slots \
+    ORDER BY slot_name";
+
+/// How often retained WAL is checked.
+const INTERVAL: Duration = Duration::from_secs(30);
+
+/// Incremented every time the config is loaded,
+/// stopping the monitor started for the previous one.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Replication slot.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Slot {
+    /// Slot name.
+    pub name: String,
+    /// Output plugin, for logical slots.
+    pub plugin: String,
+    /// Logical or physical.
+    pub slot_type: String,
+    /// Database, for logical slots.
+    pub database: String,
+    /// A consumer is connected.
+    pub active: bool,
+    /// Bytes of WAL kept on the server for this slot.
+    pub retained_wal: u64,
+}
+
+impl From<DataRow> for Slot {
+    fn from(value: DataRow) -> Self {
+        Self {
+            name: value.get::<String>(0, Format::Text).unwrap_or_default(),
+            plugin: value.get::<String>(1, Format::Text).unwrap_or_default(),
+            slot_type: value.get::<String>(2, Format::Text).unwrap_or_default(),
+            database: value.get::<String>(3, Format::Text).unwrap_or_default(),
+            active: value.get::<String>(4, Format::Text).unwrap_or_default() == "true",
+            retained_wal: value.get::<i64>(5, Format::Text).unwrap_or_default().max(0) as u64,
+        }
+    }
+}
+
+/// Slot on one of the shards.
+#[derive(Debug, Clone)]
+pub struct ShardSlot {
+    /// Shard number.
+    pub shard: usize,
+    /// Shard primary.
+    pub addr: String,
+    /// Slot.
+    pub slot: Slot,
+}
+
+/// Postgres only allows lowercase letters, numbers and underscores.
+fn check_name(name: &str) -> Result<(), Error> {
+    if !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        Ok(())
+    } else {
+        Err(Error::SlotName(name.to_owned()))
+    }
+}
+
+/// Slots on the primaries of all shards.
+pub async fn slots(cluster: &Cluster) -> Result<Vec<ShardSlot>, crate::backend::Error> {
+    let mut slots = vec![];
+
+    for (shard, _) in cluster.shards().iter().enumerate() {
+        let mut server = cluster.primary(shard, &Request::default()).await?;
+        let addr = server.addr().addr();
+        let rows: Vec<Slot> = server.fetch_all(QUERY).await?;
+        slots.extend(rows.into_iter().map(|slot| ShardSlot {
+            shard,
+            addr: addr.clone(),
+            slot,
+        }));
+    }
+
+    Ok(slots)
+}
+
+/// Create a logical slot using `pgoutput` on all shards.
+///
+/// If it can't be created on one of them, it's dropped from the shards
+/// it was already created on, so it doesn't hold back their WAL.
+pub async fn create_slot(cluster: &Cluster, name: &str) -> Result<(), crate::backend::Error> {
+    check_name(name)?;
+
+    for (shard, _) in cluster.shards().iter().enumerate() {
+        if let Err(err) = create_shard_slot(cluster, shard, name).await {
+            for created in 0..shard {
+                if let Err(err) = drop_shard_slot(cluster, created, name).await {
+                    error!(
+                        "error dropping replication slot \"{}\" from shard {}: {} [{}]",
+                        name,
+                        created,
+                        err,
+                        cluster.name()
+                    );
+                }
+            }
+            return Err(err);
+        }
+    }
+
+    info!(
+        "created replication slot \"{}\" on {} shards [{}]",
+        name,
+        cluster.shards().len(),
+        cluster.name()
+    );
+
+    Ok(())
+}
+
+/// Create a temporary logical slot using `pgoutput` on a replication connection,
+/// exporting a snapshot of the data at the slot's start.
+///
+/// Returns the start LSN and the snapshot name. The slot is dropped when the connection closes.
+pub async fn create_temporary_slot(
+    server: &mut Server,
+    name: &str,
+) -> Result<(String, String), crate::backend::Error> {
+    check_name(name)?;
+
+    let rows: Vec<DataRow> = server
+        .fetch_all(format!(
+            "CREATE_REPLICATION_SLOT {} TEMPORARY LOGICAL pgoutput EXPORT_SNAPSHOT",
+            name
+        ))
+        .await?;
+    let row = rows
+        .first()
+        .ok_or_else(|| Error::Reshard("replication slot not created".into()))?;
+
+    Ok((
+        row.get::<String>(1, Format::Text).unwrap_or_default(),
+        row.get::<String>(2, Format::Text).unwrap_or_default(),
+    ))
+}
+
+/// Drop the slot from all shards that have it.
+pub async fn drop_slot(cluster: &Cluster, name: &str) -> Result<(), crate::backend::Error> {
+    check_name(name)?;
+
+    for (shard, _) in cluster.shards().iter().enumerate() {
+        drop_shard_slot(cluster, shard, name).await?;
+    }
+
+    info!("dropped replication slot \"{}\" [{}]", name, cluster.name());
+
+    Ok(())
+}
+
+async fn create_shard_slot(
+    cluster: &Cluster,
+    shard: usize,
+    name: &str,
+) -> Result<(), crate::backend::Error> {
+    let mut server = cluster.primary(shard, &Request::default()).await?;
+    server
+        .execute_checked(format!(
+            "SELECT pg_create_logical_replication_slot('{}', 'pgoutput')",
+            name
+        ))
+        .await?;
+
+    Ok(())
+}
+
+async fn drop_shard_slot(
+    cluster: &Cluster,
+    shard: usize,
+    name: &str,
+) -> Result<(), crate::backend::Error> {
+    let mut server = cluster.primary(shard, &Request::default()).await?;
+    server
+        .execute_checked(format!(
+            "SELECT pg_drop_replication_slot(slot_name) FROM pg_replication_slots WHERE slot_name = '{}'",
+            name
+        ))
+        .await?;
+
+    Ok(())
+}
+
+/// One cluster for each database, since slots don't depend on the user.
+pub fn clusters() -> Vec<Cluster> {
+    let mut clusters: Vec<Cluster> = vec![];
+    for cluster in databases().all().values() {
+        if !clusters.iter().any(|other| other.name() == cluster.name()) {
+            clusters.push(cluster.clone());
+        }
+    }
+    clusters.sort_by(|a, b| a.name().cmp(b.name()));
+    clusters
+}
+
+/// Start warning about slots retaining too much WAL, if enabled in the config.
+pub fn launch(config: &Config) {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+
+    let max = config.general.replication_slot_max_retained_wal;
+    if max == 0 {
+        return;
+    }
+
+    spawn(async move {
+        debug!("replication slot monitor started");
+
+        // Slots we already warned about.
+        let mut exceeded = HashSet::new();
+
+        while GENERATION.load(Ordering::Relaxed) == generation {
+            for cluster in clusters() {
+                let slots = match slots(&cluster).await {
+                    Ok(slots) => slots,
+                    Err(err) => {
+                        error!(
+                            "error loading replication slots: {} [{}]",
+                            err,
+                            cluster.name()
+                        );
+                        continue;
+                    }
+                };
+
+                for ShardSlot { addr, slot, .. } in slots {
+                    let key = (addr.clone(), slot.name.clone());
+                    if slot.retained_wal > max {
+                        if exceeded.insert(key) {
+                            warn!(
+                                "replication slot \"{}\" is retaining {} bytes of WAL, active: {} [{}]",
+                                slot.name, slot.retained_wal, slot.active, addr
+                            );
+                        }
+                    } else {
+                        exceeded.remove(&key);
+                    }
+                }
+            }
+
+            sleep(INTERVAL).await;
+        }
+
+        debug!("replication slot monitor stopped");
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_check_name() {
+        assert!(check_name("pgdog_split_1").is_ok());
+        assert!(check_name("").is_err());
+        assert!(check_name("slot'; DROP TABLE users; --").is_err());
+        assert!(check_name("Slot").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_slot_cleanup() {
+        let cluster = Cluster::new_test();
+        cluster.launch();
+
+        // Both shards are the same database, so creating
+        // the slot on the second one fails.
+        let name = "pgdog_test_create_slot_cleanup";
+        assert!(create_slot(&cluster, name).await.is_err());
+
+        let slots = slots(&cluster).await.unwrap();
+        assert!(slots.iter().all(|slot| slot.slot.name != name));
+
+        cluster.shutdown();
+    }
+}
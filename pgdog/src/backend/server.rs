@@ -7,7 +7,7 @@ use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
     spawn,
-    time::Instant,
+    time::{timeout, Instant},
 };
 use tracing::{debug, error, info, trace, warn};
 
@@ -27,11 +27,11 @@ use crate::{
     },
 };
 use crate::{
-    config::PoolerMode,
+    config::{PoolerMode, ServerTlsMode},
     net::{
-        messages::{DataRow, NoticeResponse},
+        messages::{DataRow, NegotiateProtocolVersion, NoticeResponse},
         parameter::Parameters,
-        tls::connector,
+        tls::server_connector,
         CommandComplete, Stream,
     },
 };
@@ -51,6 +51,7 @@ pub struct Server {
     dirty: bool,
     streaming: bool,
     schema_changed: bool,
+    read_only: bool,
     sync_prepared: bool,
     in_transaction: bool,
     re_synced: bool,
@@ -67,24 +68,28 @@ impl Server {
 
         let mut stream = Stream::plain(stream);
 
-        // Request TLS.
-        stream.write_all(&Startup::tls().to_bytes()?).await?;
-        stream.flush().await?;
+        if addr.tls_mode != ServerTlsMode::Disable {
+            // Request TLS.
+            stream.write_all(&Startup::tls().to_bytes()?).await?;
+            stream.flush().await?;
 
-        let mut ssl = BytesMut::new();
-        ssl.put_u8(stream.read_u8().await?);
-        let ssl = SslReply::from_bytes(ssl.freeze())?;
+            let mut ssl = BytesMut::new();
+            ssl.put_u8(stream.read_u8().await?);
+            let ssl = SslReply::from_bytes(ssl.freeze())?;
 
-        if ssl == SslReply::Yes {
-            let connector = connector()?;
-            let plain = stream.take()?;
+            if ssl == SslReply::Yes {
+                let connector = server_connector(addr.tls_mode, addr.tls_ca.as_ref())?;
+                let plain = stream.take()?;
 
-            let server_name = ServerName::try_from(addr.host.clone())?;
+                let server_name = ServerName::try_from(addr.host.clone())?;
 
-            let cipher =
-                tokio_rustls::TlsStream::Client(connector.connect(server_name, plain).await?);
+                let cipher =
+                    tokio_rustls::TlsStream::Client(connector.connect(server_name, plain).await?);
 
-            stream = Stream::tls(cipher);
+                stream = Stream::tls(cipher);
+            } else if addr.tls_mode.required() {
+                return Err(Error::TlsRequired);
+            }
         }
 
         stream
@@ -134,6 +139,15 @@ impl Server {
                         }
                     }
                 }
+                // NegotiateProtocolVersion (B), we asked for 3.0 without
+                // protocol options, so there's nothing to change.
+                'v' => {
+                    let negotiate = NegotiateProtocolVersion::from_bytes(message.payload())?;
+                    warn!(
+                        "server negotiated protocol version 3.{} [{}]",
+                        negotiate.minor, addr
+                    );
+                }
 
                 code => return Err(Error::UnexpectedMessage(code)),
             }
@@ -190,6 +204,7 @@ impl Server {
             dirty: false,
             streaming: false,
             schema_changed: false,
+            read_only: false,
             sync_prepared: false,
             in_transaction: false,
             re_synced: false,
@@ -199,7 +214,11 @@ impl Server {
     }
 
     /// Request query cancellation for the given backend server identifier.
-    pub async fn cancel(addr: &Address, id: &BackendKeyData) -> Result<(), Error> {
+    pub async fn cancel(
+        addr: &Address,
+        id: &BackendKeyData,
+        connect_timeout: Duration,
+    ) -> Result<(), Error> {
         let mut stream = TcpStream::connect(addr.addr()).await?;
         stream
             .write_all(
@@ -212,6 +231,10 @@ impl Server {
             .await?;
         stream.flush().await?;
 
+        // Postgres closes the connection once it processed the request.
+        // Wait for that, so we know the cancellation was delivered.
+        let _ = timeout(connect_timeout, stream.read(&mut [0u8; 1])).await;
+
         Ok(())
     }
 
@@ -268,6 +291,20 @@ impl Server {
         }
     }
 
+    /// Wait for a message to be available.
+    ///
+    /// Unlike [`Server::read`], this is cancel-safe, so it can be used
+    /// to read from whichever server has data first.
+    pub async fn ready(&mut self) -> Result<(), Error> {
+        if self.prepared_statements.state().has_simulated() {
+            return Ok(());
+        }
+
+        self.stream.as_mut().unwrap().ready().await?;
+
+        Ok(())
+    }
+
     /// Read a single message from the server.
     pub async fn read(&mut self) -> Result<Message, Error> {
         let message = loop {
@@ -338,6 +375,8 @@ impl Server {
             'E' => {
                 let error = ErrorResponse::from_bytes(message.to_bytes()?)?;
                 self.schema_changed = error.code == "0A000";
+                // cannot execute ... in a read-only transaction
+                self.read_only |= error.code == "25006";
                 self.stats.error()
             }
             'W' => {
@@ -450,6 +489,12 @@ impl Server {
         self.schema_changed
     }
 
+    /// The server refused a write because it's read-only,
+    /// e.g. it was demoted to a replica. Cleared once checked.
+    pub fn take_read_only(&mut self) -> bool {
+        std::mem::take(&mut self.read_only)
+    }
+
     /// Prepared statements changed outside of our pipeline,
     /// need to resync from `pg_prepared_statements` view.
     pub fn sync_prepared(&self) -> bool {
@@ -499,19 +544,24 @@ impl Server {
         self.send(&queries.into()).await?;
 
         let mut zs = 0;
+        let mut error = None;
         while zs < expected {
             let message = self.read().await?;
             if message.code() == 'Z' {
                 zs += 1;
             }
 
-            if message.code() == 'E' {
-                let err = ErrorResponse::from_bytes(message.to_bytes()?)?;
-                return Err(Error::ExecutionError(Box::new(err)));
+            // Read until ReadyForQuery, so the connection stays in sync.
+            if message.code() == 'E' && error.is_none() {
+                error = Some(ErrorResponse::from_bytes(message.to_bytes()?)?);
             }
             messages.push(message);
         }
 
+        if let Some(error) = error {
+            return Err(Error::ExecutionError(Box::new(error)));
+        }
+
         Ok(messages)
     }
 
@@ -771,6 +821,7 @@ pub mod test {
                 dirty: false,
                 streaming: false,
                 schema_changed: false,
+                read_only: false,
                 sync_prepared: false,
                 in_transaction: false,
                 re_synced: false,
@@ -796,6 +847,7 @@ pub mod test {
             user: "pgdog".into(),
             password: "pgdog".into(),
             database_name: "pgdog".into(),
+            ..Default::default()
         };
 
         Server::connect(&address, ServerOptions::default())
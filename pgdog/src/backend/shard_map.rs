@@ -0,0 +1,275 @@
+//! Sharding key to shard mapping stored in a Postgres table.
+//!
+//! The table is read periodically and whenever a notification is sent
+//! on the configured channel, so keys can be moved between shards
+//! without reloading the config, e.g.:
+//!
+//! ```sql
+//! UPDATE pgdog.shard_map SET shard = 2 WHERE sharding_key = '1234';
+//! NOTIFY pgdog_shard_map;
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use arc_swap::ArcSwap;
+use once_cell::sync::Lazy;
+use tokio::{select, spawn, sync::Notify, time::sleep};
+use tracing::{debug, error, info};
+
+use crate::config::{Config, DataType};
+use crate::frontend::router::parser::Cache;
+use crate::net::messages::{DataRow, Format};
+
+use super::{databases::databases, pool::Request, Cluster, Error, Server, ServerOptions};
+
+/// Default time between reloads of the mapping table.
+const TTL: Duration = Duration::from_secs(60);
+
+/// Incremented every time the config is loaded,
+/// stopping the loaders started for the previous one.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Wakes up loaders waiting for the next reload, so they stop right away.
+static STOP: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Mapping loaded from a table, shared by all clusters using the same config.
+#[derive(Clone, Default)]
+pub struct ShardMap {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    mapping: ArcSwap<HashMap<String, usize>>,
+}
+
+impl ShardMap {
+    /// Shard for the sharding key, in its canonical text form.
+    pub fn get(&self, key: &str) -> Option<usize> {
+        self.inner.mapping.load().get(key).copied()
+    }
+
+    /// Number of mapped keys.
+    pub fn len(&self) -> usize {
+        self.inner.mapping.load().len()
+    }
+
+    /// No keys are mapped.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replace the mapping.
+    pub fn store(&self, mapping: HashMap<String, usize>) {
+        self.inner.mapping.store(Arc::new(mapping));
+        Cache::invalidate_routes();
+    }
+}
+
+impl Debug for ShardMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShardMap")
+            .field("keys", &self.len())
+            .finish()
+    }
+}
+
+impl PartialEq for ShardMap {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+            || *self.inner.mapping.load() == *other.inner.mapping.load()
+    }
+}
+
+/// Row in the mapping table.
+struct Row {
+    sharding_key: Option<String>,
+    shard: Option<i64>,
+}
+
+impl From<DataRow> for Row {
+    fn from(value: DataRow) -> Self {
+        Self {
+            sharding_key: value.get::<String>(0, Format::Text),
+            shard: value.get::<i64>(1, Format::Text),
+        }
+    }
+}
+
+/// Start loading mappings for all sharded tables that have a mapping table.
+///
+/// Loaders started by the previous call stop, closing their connections.
+pub fn launch(config: &Config) {
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    STOP.notify_waiters();
+
+    for table in &config.sharded_tables {
+        let Some(ref mapping_table) = table.mapping_table else {
+            continue;
+        };
+
+        let loader = Loader {
+            database: table
+                .mapping_database
+                .clone()
+                .unwrap_or(table.database.clone()),
+            query: format!(
+                "SELECT sharding_key::text, shard::bigint FROM {}",
+                mapping_table
+            ),
+            channel: table.mapping_channel.clone(),
+            ttl: table.mapping_ttl.map(Duration::from_millis).unwrap_or(TTL),
+            data_type: table.data_type,
+            shard_map: Arc::downgrade(&table.shard_map.inner),
+            generation,
+        };
+
+        spawn(async move {
+            loader.run().await;
+        });
+    }
+}
+
+struct Loader {
+    database: String,
+    query: String,
+    channel: Option<String>,
+    ttl: Duration,
+    data_type: DataType,
+    shard_map: Weak<Inner>,
+    generation: usize,
+}
+
+impl Loader {
+    async fn run(self) {
+        let mut listener: Option<Server> = None;
+
+        loop {
+            if GENERATION.load(Ordering::Relaxed) != self.generation {
+                debug!("mapping table loader stopped [{}]", self.database);
+                break;
+            }
+
+            let Some(inner) = self.shard_map.upgrade() else {
+                break;
+            };
+
+            match self.load().await {
+                Ok(mapping) => {
+                    debug!(
+                        "loaded {} sharding keys from mapping table [{}]",
+                        mapping.len(),
+                        self.database
+                    );
+                    inner.mapping.store(Arc::new(mapping));
+                    Cache::invalidate_routes();
+                }
+                Err(err) => error!("error loading mapping table [{}]: {}", self.database, err),
+            }
+
+            drop(inner);
+
+            if let Some(ref channel) = self.channel {
+                if listener.is_none() {
+                    match self.listen(channel).await {
+                        Ok(server) => {
+                            info!(
+                                "listening for mapping changes on \"{}\" [{}]",
+                                channel,
+                                server.addr()
+                            );
+                            listener = Some(server);
+                        }
+                        Err(err) => error!("error listening on \"{}\": {}", channel, err),
+                    }
+                }
+
+                if let Some(ref mut server) = listener {
+                    // Any message from the server is a notification,
+                    // or it closed the connection.
+                    let closed = select! {
+                        message = server.read() => message.is_err(),
+                        _ = sleep(self.ttl) => false,
+                        _ = STOP.notified() => false,
+                    };
+
+                    if closed {
+                        listener = None;
+                    }
+
+                    continue;
+                }
+            }
+
+            select! {
+                _ = sleep(self.ttl) => (),
+                _ = STOP.notified() => (),
+            }
+        }
+    }
+
+    fn cluster(&self) -> Result<Cluster, Error> {
+        databases()
+            .all()
+            .values()
+            .find(|cluster| cluster.name() == self.database)
+            .cloned()
+            .ok_or(Error::NoCluster)
+    }
+
+    async fn load(&self) -> Result<HashMap<String, usize>, Error> {
+        let cluster = self.cluster()?;
+        let mut server = cluster.primary(0, &Request::default()).await?;
+        let rows: Vec<Row> = server.fetch_all(self.query.as_str()).await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let key = self.data_type.normalize(&row.sharding_key?)?;
+                let shard = usize::try_from(row.shard?).ok()?;
+                Some((key, shard))
+            })
+            .collect())
+    }
+
+    async fn listen(&self, channel: &str) -> Result<Server, Error> {
+        let cluster = self.cluster()?;
+        let addr = cluster
+            .primary(0, &Request::default())
+            .await?
+            .addr()
+            .clone();
+
+        let mut server = Server::connect(&addr, ServerOptions::default()).await?;
+        server
+            .execute_checked(format!("LISTEN \"{}\"", channel))
+            .await?;
+
+        Ok(server)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_shard_map() {
+        let shard_map = ShardMap::default();
+        let clone = shard_map.clone();
+        assert!(shard_map.is_empty());
+
+        clone.store(HashMap::from([("1234".into(), 2)]));
+        assert_eq!(shard_map.get("1234"), Some(2));
+        assert_eq!(shard_map.get("5678"), None);
+        assert_ne!(shard_map, ShardMap::default());
+
+        let other = ShardMap::default();
+        other.store(HashMap::from([("1234".into(), 2)]));
+        assert_eq!(shard_map, other);
+    }
+}
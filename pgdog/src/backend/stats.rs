@@ -13,6 +13,7 @@ use tokio::time::Instant;
 use crate::{
     net::{messages::BackendKeyData, Parameters},
     state::State,
+    stats::Histogram,
 };
 
 use super::pool::Address;
@@ -62,6 +63,11 @@ pub struct Counts {
     pub parse: usize,
     pub bind: usize,
     pub healthchecks: usize,
+    pub query_histogram: Histogram,
+    pub transaction_histogram: Histogram,
+    pub reads: usize,
+    pub writes: usize,
+    pub cross_shard: usize,
 }
 
 impl Add for Counts {
@@ -83,6 +89,11 @@ impl Add for Counts {
             parse: self.parse.saturating_add(rhs.parse),
             bind: self.bind.saturating_add(rhs.bind),
             healthchecks: self.healthchecks.saturating_add(rhs.healthchecks),
+            query_histogram: self.query_histogram + rhs.query_histogram,
+            transaction_histogram: self.transaction_histogram + rhs.transaction_histogram,
+            reads: self.reads.saturating_add(rhs.reads),
+            writes: self.writes.saturating_add(rhs.writes),
+            cross_shard: self.cross_shard.saturating_add(rhs.cross_shard),
         }
     }
 }
@@ -141,6 +152,8 @@ impl Stats {
             let duration = now.duration_since(transaction_timer);
             self.total.transaction_time += duration;
             self.last_checkout.transaction_time += duration;
+            self.total.transaction_histogram.observe(duration);
+            self.last_checkout.transaction_histogram.observe(duration);
         }
         self.update();
     }
@@ -187,6 +200,22 @@ impl Stats {
         self.last_checkout.errors += 1;
     }
 
+    /// Query was routed to this server, as a read or a write,
+    /// and maybe to other shards as well.
+    pub fn routed(&mut self, read: bool, cross_shard: bool) {
+        if read {
+            self.total.reads += 1;
+            self.last_checkout.reads += 1;
+        } else {
+            self.total.writes += 1;
+            self.last_checkout.writes += 1;
+        }
+        if cross_shard {
+            self.total.cross_shard += 1;
+            self.last_checkout.cross_shard += 1;
+        }
+    }
+
     /// A query has been completed.
     pub fn query(&mut self, now: Instant) {
         self.total.queries += 1;
@@ -195,6 +224,8 @@ impl Stats {
             let duration = now.duration_since(query_timer);
             self.total.query_time += duration;
             self.last_checkout.query_time += duration;
+            self.total.query_histogram.observe(duration);
+            self.last_checkout.query_histogram.observe(duration);
         }
     }
 
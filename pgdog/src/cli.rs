@@ -1,7 +1,19 @@
 use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
+use serde_json::Value;
 use std::fs::read_to_string;
+use std::io::Write;
+use tokio::runtime::Builder;
+
+use crate::admin::api::EFFECTIVE;
+use crate::config::{
+    self, pgbouncer, reload,
+    validate::{reachable, validate, Severity},
+    ConfigAndUsers,
+};
+use crate::frontend::router::sharding::reshard::{Plan, Scheme};
+use crate::net::http;
 
 /// pgDog is a PostgreSQL pooler, proxy, load balancer and
 /// query router.
@@ -47,6 +59,53 @@ pub enum Commands {
     },
 
     Schema,
+
+    /// Simulate changing the number of shards or the sharding column.
+    PlanReshard {
+        /// Database to reshard.
+        #[arg(long)]
+        database: String,
+        /// Proposed number of shards.
+        #[arg(long)]
+        shards: usize,
+        /// Proposed sharding column. Default: the current one.
+        #[arg(long)]
+        column: Option<String>,
+        /// File with one sharding key per line, optionally followed by its row count, e.g. "1234,50".
+        #[arg(long)]
+        keys: Option<PathBuf>,
+        /// File with queries separated by semicolons, e.g. from the query log.
+        #[arg(long)]
+        queries: Option<PathBuf>,
+    },
+
+    /// Check the configuration files and exit with an error if they have problems.
+    CheckConfig {
+        /// Connect to every server to check it's reachable.
+        #[arg(long)]
+        connect: bool,
+        /// Admin API of a running PgDog, e.g. "http://127.0.0.1:8080",
+        /// to show what would change if it was reloaded with these files.
+        #[arg(long)]
+        admin_url: Option<String>,
+        /// Token for the admin API. Default: `http_token` in the configuration.
+        #[arg(long)]
+        admin_token: Option<String>,
+    },
+
+    /// Convert PgBouncer configuration into pgdog.toml and users.toml,
+    /// written to the --config and --users paths.
+    MigrateConfig {
+        /// Path to pgbouncer.ini.
+        #[arg(long)]
+        from: PathBuf,
+        /// Path to userlist.txt.
+        #[arg(long)]
+        userlist: Option<PathBuf>,
+        /// Overwrite pgdog.toml and users.toml if they exist.
+        #[arg(long)]
+        force: bool,
+    },
 }
 
 /// Fingerprint some queries.
@@ -77,3 +136,200 @@ fingerprint = "{}" #[{}]"#,
 
     Ok(())
 }
+
+/// Report what would happen if the database was resharded.
+pub fn plan_reshard(
+    config: &ConfigAndUsers,
+    database: &str,
+    shards: usize,
+    column: Option<String>,
+    keys: Option<PathBuf>,
+    queries: Option<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let current_shards = config
+        .config
+        .databases()
+        .get(database)
+        .map(|shards| shards.len())
+        .ok_or(format!(r#"database "{}" is not configured"#, database))?;
+    let sharded_tables = config
+        .config
+        .sharded_tables()
+        .remove(database)
+        .unwrap_or_default();
+    let table = sharded_tables
+        .iter()
+        .find(|table| table.primary)
+        .or(sharded_tables.first())
+        .cloned()
+        .ok_or(format!(r#"database "{}" has no sharded tables"#, database))?;
+
+    if shards == 0 {
+        return Err("number of shards must be greater than 0".into());
+    }
+
+    let mut proposed = table.clone();
+    if let Some(column) = column {
+        proposed.column = column;
+    }
+
+    let mut plan = Plan::new(
+        Scheme::new(table, current_shards),
+        Scheme::new(proposed, shards),
+    );
+
+    if let Some(keys) = keys {
+        for line in read_to_string(keys)?.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, rows) = match line.rsplit_once(',') {
+                Some((key, rows)) => (key.trim(), rows.trim().parse()?),
+                None => (line, 1),
+            };
+            plan.key(key, rows);
+        }
+    }
+
+    if let Some(queries) = queries {
+        plan.queries(&read_to_string(queries)?)?;
+    }
+
+    print!("{}", plan);
+
+    Ok(())
+}
+
+/// Check the configuration and show how it differs from the running one.
+/// Returns false if there are errors.
+pub fn check_config(
+    config_path: &PathBuf,
+    users_path: &PathBuf,
+    connect: bool,
+    admin_url: Option<String>,
+    admin_token: Option<String>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let config = match config::load(config_path, users_path) {
+        Ok(config) => config,
+        Err(err) => {
+            println!("error: {}", err);
+            return Ok(false);
+        }
+    };
+
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+
+    let mut problems = validate(&config);
+    if connect {
+        problems.extend(runtime.block_on(reachable(&config)));
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    let mut ok = !problems
+        .iter()
+        .any(|problem| problem.severity == Severity::Error);
+
+    if let Some(admin_url) = admin_url {
+        let token = admin_token
+            .or_else(|| config.config.admin.http_token.clone())
+            .unwrap_or_default();
+        let url = format!("{}{}", admin_url.trim_end_matches('/'), EFFECTIVE);
+
+        match runtime.block_on(http::get(&url, &token)) {
+            Ok(body) => {
+                let running: Value = serde_json::from_slice(&body)?;
+                let mut new = reload::to_json(&config);
+                reload::mask(&mut new);
+
+                let changes = reload::changes(&running, &new);
+                if changes.is_empty() {
+                    println!("no changes compared to {}", admin_url);
+                } else {
+                    println!("changes compared to {}:", admin_url);
+                }
+                for change in changes {
+                    println!(
+                        "  {}: {} -> {}{}",
+                        change.path,
+                        change.old.as_deref().unwrap_or("<none>"),
+                        change.new.as_deref().unwrap_or("<none>"),
+                        if change.restart {
+                            " (restart required)"
+                        } else {
+                            ""
+                        }
+                    );
+                }
+            }
+            Err(err) => {
+                println!("error: can't get the configuration from {}: {}", url, err);
+                ok = false;
+            }
+        }
+    }
+
+    if ok {
+        println!("configuration is valid");
+    }
+
+    Ok(ok)
+}
+
+/// Convert pgbouncer.ini and userlist.txt into pgdog.toml and users.toml.
+pub fn migrate_config(
+    from: &PathBuf,
+    userlist: Option<&PathBuf>,
+    config_path: &PathBuf,
+    users_path: &PathBuf,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !force {
+        for path in [config_path, users_path] {
+            if path.exists() {
+                return Err(format!(
+                    r#""{}" already exists, use --force to overwrite it"#,
+                    path.display()
+                )
+                .into());
+            }
+        }
+    }
+
+    let ini = read_to_string(from)?;
+    let userlist = userlist.map(read_to_string).transpose()?;
+    let migration = pgbouncer::convert(&ini, userlist.as_deref())?;
+    let (config, users) = migration.to_toml()?;
+
+    for warning in &migration.warnings {
+        println!("warning: {}", warning);
+    }
+
+    std::fs::write(config_path, config)?;
+    write_private(users_path, &users)?;
+    println!(
+        "wrote {} and {}, check them with \"pgdog check-config\"",
+        config_path.display(),
+        users_path.display()
+    );
+
+    Ok(())
+}
+
+/// Write a file only its owner can read, e.g. users.toml with passwords in it.
+fn write_private(path: &PathBuf, contents: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // The mode is only used for new files.
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    options.open(path)?.write_all(contents.as_bytes())
+}
@@ -0,0 +1,158 @@
+//! Environment variables in configuration files.
+//!
+//! `${VAR}` in a string value is replaced with the value of `VAR` after the file
+//! is parsed, and `${VAR:-fallback}` with `fallback` if `VAR` isn't set or is empty.
+//! Using a variable that isn't set and has no fallback is an error. Write `$${`
+//! for a literal `${`. Comments and keys are left as they are, and so are
+//! numbers and booleans, so only settings that are strings can use variables, e.g.:
+//!
+//! ```toml
+//! [[databases]]
+//! name = "prod"
+//! host = "${DATABASE_HOST:-127.0.0.1}"
+//! ```
+
+use std::env::var;
+
+use serde_json::Value;
+
+use super::Error;
+
+/// Replace environment variables in string values.
+pub fn substitute(value: &mut Value) -> Result<(), Error> {
+    substitute_with(value, &|name| var(name).ok(), &mut vec![])
+}
+
+fn substitute_with(
+    value: &mut Value,
+    lookup: &impl Fn(&str) -> Option<String>,
+    path: &mut Vec<String>,
+) -> Result<(), Error> {
+    match value {
+        Value::String(string) if string.contains('$') => {
+            *string = substitute_str(string, lookup)
+                .map_err(|message| Error::EnvVar(message, path.join(".")))?;
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                path.push(i.to_string());
+                substitute_with(value, lookup, path)?;
+                path.pop();
+            }
+        }
+        Value::Object(values) => {
+            for (key, value) in values.iter_mut() {
+                path.push(key.clone());
+                substitute_with(value, lookup, path)?;
+                path.pop();
+            }
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+fn substitute_str(
+    source: &str,
+    lookup: &impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    let mut result = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(start) = rest.find('$') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+        } else if let Some(variable) = rest.strip_prefix("${") {
+            let end = variable.find('}').ok_or("missing \"}\"")?;
+            let (name, fallback) = match variable[..end].split_once(":-") {
+                Some((name, fallback)) => (name, Some(fallback)),
+                None => (&variable[..end], None),
+            };
+
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("invalid variable name \"{}\"", name));
+            }
+
+            let value = match (lookup(name).filter(|value| !value.is_empty()), fallback) {
+                (Some(value), _) => value,
+                (None, Some(fallback)) => fallback.to_owned(),
+                (None, None) => {
+                    return Err(format!("environment variable \"{}\" is not set", name))
+                }
+            };
+
+            result.push_str(&value);
+            rest = &variable[end + 1..];
+        } else {
+            result.push('$');
+            rest = &rest[1..];
+        }
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lookup(name: &str) -> Option<String> {
+        match name {
+            "HOST" => Some("10.0.0.1".into()),
+            "EMPTY" => Some("".into()),
+            _ => None,
+        }
+    }
+
+    fn substitute(source: &str) -> Result<Value, Error> {
+        let mut value: Value = toml::from_str(source).unwrap();
+        substitute_with(&mut value, &lookup, &mut vec![])?;
+        Ok(value)
+    }
+
+    #[test]
+    fn test_substitute() {
+        let source = r#"host = "${HOST}" # "${NOT_SET}"
+port = 5432
+user = "${EMPTY:-pgdog}"
+password = "pa$$word"
+# host = "${NOT_SET}"
+literal = "$${HOST}"
+"${NOT_SET}" = 1
+
+[[databases]]
+host = "${HOST}:${PORT:-5432}"
+"#;
+        let value = substitute(source).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "host": "10.0.0.1",
+                "port": 5432,
+                "user": "pgdog",
+                "password": "pa$$word",
+                "literal": "${HOST}",
+                "${NOT_SET}": 1,
+                "databases": [{"host": "10.0.0.1:5432"}],
+            })
+        );
+    }
+
+    #[test]
+    fn test_substitute_errors() {
+        let err = substitute("a = 1\n[[databases]]\nhost = \"${NOT_SET}\"").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "environment variable \"NOT_SET\" is not set, in \"databases.0.host\""
+        );
+        assert!(substitute("host = \"${HOST\"").is_err());
+        assert!(substitute("host = \"${}\"").is_err());
+        assert!(substitute("host = \"${A B}\"").is_err());
+    }
+}
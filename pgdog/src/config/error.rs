@@ -11,6 +11,9 @@ pub enum Error {
     #[error("{0}")]
     Deser(#[from] toml::de::Error),
 
+    #[error("{0}")]
+    Ser(#[from] toml::ser::Error),
+
     #[error("{0}, line {1}")]
     MissingField(String, usize),
 
@@ -20,6 +23,33 @@ pub enum Error {
     #[error("{0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("{0}")]
+    Yaml(#[from] serde_yaml::Error),
+
+    #[error("{0}, in \"{1}\"")]
+    EnvVar(String, String),
+
+    #[error("{0}, line {1}")]
+    Syntax(String, usize),
+
+    #[error("{0}")]
+    Duplicate(String),
+
+    #[error("{0}")]
+    Template(String),
+
+    #[error("{0}")]
+    Remote(String),
+
+    #[error("can't read password file \"{0}\": {1}")]
+    PasswordFile(String, std::io::Error),
+
+    #[error("{0}")]
+    History(String),
+
+    #[error("sharding function \"plugin:{0}\" needs the \"{0}\" plugin in [[plugins]]")]
+    UnknownPlugin(String),
+
     #[error("incomplete startup")]
     IncompleteStartup,
 }
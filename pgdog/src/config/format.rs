@@ -0,0 +1,126 @@
+//! Configuration file formats.
+//!
+//! Files are TOML, unless they end with `.yaml`/`.yml` or `.json`.
+//! All of them are deserialized into the same structs, so settings have the
+//! same names and defaults everywhere.
+
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+
+use super::{env, Error};
+
+/// Configuration file format, detected by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Format of the file.
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "yaml" | "yml" => Self::Yaml,
+            "json" => Self::Json,
+            _ => Self::Toml,
+        }
+    }
+
+    /// Deserialize file contents.
+    ///
+    /// Environment variables in string values are substituted, see [`env`].
+    pub fn parse<T: DeserializeOwned>(&self, source: &str) -> Result<T, Error> {
+        // Parse into the struct directly when we can, errors have line numbers.
+        if source.contains("${") {
+            let mut value: serde_json::Value = self.deserialize(source)?;
+            env::substitute(&mut value)?;
+            return Ok(serde_json::from_value(value)?);
+        }
+
+        self.deserialize(source)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, source: &str) -> Result<T, Error> {
+        match self {
+            Self::Toml => toml::from_str(source).map_err(|err| Error::config(source, err)),
+            Self::Json => Ok(serde_json::from_str(source)?),
+            Self::Yaml => Ok(serde_yaml::from_str(source)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Config, Role, Users};
+
+    #[test]
+    fn test_formats() {
+        assert_eq!(Format::from_path(Path::new("pgdog.toml")), Format::Toml);
+        assert_eq!(Format::from_path(Path::new("/etc/pgdog.YML")), Format::Yaml);
+        assert_eq!(Format::from_path(Path::new("users.json")), Format::Json);
+        assert_eq!(Format::from_path(Path::new("pgdog")), Format::Toml);
+
+        let toml = r#"
+[general]
+port = 6433
+default_pool_size = 5
+
+[[databases]]
+name = "prod"
+host = "127.0.0.1"
+role = "replica"
+"#;
+        let yaml = r#"
+general:
+  port: 6433
+  default_pool_size: 5
+databases:
+  - name: prod
+    host: 127.0.0.1
+    role: replica
+"#;
+        let json = r#"{
+    "general": {"port": 6433, "default_pool_size": 5},
+    "databases": [{"name": "prod", "host": "127.0.0.1", "role": "replica"}]
+}"#;
+
+        for (format, source) in [
+            (Format::Toml, toml),
+            (Format::Yaml, yaml),
+            (Format::Json, json),
+        ] {
+            let config: Config = format.parse(source).unwrap();
+            assert_eq!(config.general.port, 6433);
+            assert_eq!(config.general.default_pool_size, 5);
+            assert_eq!(config.databases.len(), 1);
+            assert_eq!(config.databases[0].host, "127.0.0.1");
+            assert_eq!(config.databases[0].role, Role::Replica);
+        }
+
+        let users: Users = Format::Yaml
+            .parse("users:\n  - name: alice\n    database: prod\n    password: secret\n")
+            .unwrap();
+        assert_eq!(users.users[0].password(), "secret");
+
+        assert!(Format::Yaml
+            .parse::<Config>("genral:\n  port: 6433")
+            .is_err());
+
+        // Anchors and block scalars.
+        let users: Users = Format::Yaml
+            .parse(
+                "users:\n  - name: alice\n    database: prod\n    password: &password |-\n      secret\n  - name: bob\n    database: prod\n    password: *password\n",
+            )
+            .unwrap();
+        assert_eq!(users.users[1].password(), "secret");
+    }
+}
@@ -0,0 +1,157 @@
+//! Configurations applied since PgDog started, shown in `SHOW CONFIG HISTORY`.
+//!
+//! Every configuration that's applied, on startup, reload or `SET`, is kept
+//! in memory, up to `config_history` of them. `ROLLBACK CONFIG` applies the
+//! one before the current configuration again, without reading the config files,
+//! so a bad reload can be undone even if the files were already changed.
+//! Password files are read again, and state that isn't configuration, like
+//! failovers and resharded databases, isn't rolled back.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::{reload::to_json, ConfigAndUsers, Error};
+
+static HISTORY: Lazy<Mutex<History>> = Lazy::new(|| Mutex::new(History::default()));
+
+/// Configuration that was applied.
+#[derive(Debug, Clone)]
+pub struct Version {
+    /// Increases by one with every configuration applied.
+    pub version: usize,
+    /// MD5 of the configuration and users.
+    pub hash: String,
+    pub applied_at: SystemTime,
+    pub config: Arc<ConfigAndUsers>,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    versions: VecDeque<Version>,
+    next: usize,
+}
+
+impl History {
+    fn record(&mut self, config: &ConfigAndUsers) {
+        let hash = hash(config);
+
+        // Reloading files that haven't changed isn't a new version.
+        if self.versions.back().map(|version| &version.hash) == Some(&hash) {
+            return;
+        }
+
+        self.next += 1;
+        self.versions.push_back(Version {
+            version: self.next,
+            hash,
+            applied_at: SystemTime::now(),
+            config: Arc::new(config.clone()),
+        });
+
+        let keep = config.config.general.config_history.max(1);
+        while self.versions.len() > keep {
+            self.versions.pop_front();
+        }
+    }
+
+    fn amend(&mut self, config: &ConfigAndUsers) {
+        match self.versions.back_mut() {
+            Some(version) => {
+                version.hash = hash(config);
+                version.config = Arc::new(config.clone());
+            }
+            None => self.record(config),
+        }
+    }
+
+    fn rollback(&mut self, version: Option<usize>) -> Result<Version, Error> {
+        let current = self.versions.back().map(|version| version.version);
+        let target = match version {
+            Some(version) => self
+                .versions
+                .iter()
+                .find(|v| v.version == version && Some(version) != current),
+            None => self.versions.iter().rev().nth(1),
+        }
+        .cloned()
+        .ok_or_else(|| match version {
+            Some(version) => Error::History(format!("version {} isn't in the history", version)),
+            None => Error::History("there is no previous version".into()),
+        })?;
+
+        self.versions.retain(|v| v.version <= target.version);
+        Ok(target)
+    }
+}
+
+fn hash(config: &ConfigAndUsers) -> String {
+    format!("{:x}", md5::compute(to_json(config).to_string()))
+}
+
+/// Remember the configuration that was just applied.
+pub fn record(config: &ConfigAndUsers) {
+    HISTORY.lock().record(config);
+}
+
+/// Update the current version, e.g. with command line overrides on startup.
+pub fn amend(config: &ConfigAndUsers) {
+    HISTORY.lock().amend(config);
+}
+
+/// Configurations applied, oldest first.
+pub fn versions() -> Vec<Version> {
+    HISTORY.lock().versions.iter().cloned().collect()
+}
+
+/// Forget versions newer than `version`, or the current one, and return the
+/// configuration to apply instead.
+pub fn rollback(version: Option<usize>) -> Result<Version, Error> {
+    HISTORY.lock().rollback(version)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let mut history = History::default();
+        let mut config = ConfigAndUsers::default();
+        config.config.general.config_history = 3;
+
+        assert!(history.rollback(None).is_err());
+
+        for pool_size in [10, 10, 20, 30, 40] {
+            config.config.general.default_pool_size = pool_size;
+            history.record(&config);
+        }
+
+        let versions = history
+            .versions
+            .iter()
+            .map(|version| version.version)
+            .collect::<Vec<_>>();
+        assert_eq!(versions, vec![2, 3, 4]);
+
+        let target = history.rollback(None).unwrap();
+        assert_eq!(target.version, 3);
+        assert_eq!(target.config.config.general.default_pool_size, 30);
+        assert_eq!(history.versions.len(), 2);
+
+        assert!(history.rollback(Some(3)).is_err());
+        assert!(history.rollback(Some(1)).is_err());
+        assert_eq!(history.rollback(Some(2)).unwrap().version, 2);
+
+        config.config.general.default_pool_size = 50;
+        history.amend(&config);
+        assert_eq!(history.versions.len(), 1);
+        assert_eq!(
+            history.versions[0].config.config.general.default_pool_size,
+            50
+        );
+    }
+}
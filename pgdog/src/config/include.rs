@@ -0,0 +1,272 @@
+//! Split configuration across files with `include`.
+//!
+//! Both `pgdog.toml` and `users.toml` can list other files to load, relative
+//! to their own directory:
+//!
+//! ```toml
+//! include = ["conf.d/*.toml"]
+//! ```
+//!
+//! Included files can be in any format (see [`Format`]), e.g. YAML files
+//! included from `pgdog.toml`. `*` and `?` are supported in the file name, not in directories. Files matching
+//! a pattern are loaded in alphabetical order, after the main file, and their lists
+//! (e.g. `[[databases]]` or `[[users]]`) are added to it. Included files can't
+//! change other settings or include more files. The same database, user or sharded
+//! table defined twice, in any of the files, is an error.
+
+use std::collections::HashSet;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tracing::info;
+
+use crate::util::wildcard;
+
+use super::{
+    Config, Database, Error, Format, ManualQuery, MirroringRule, OmnishardedTables, RoutingRule,
+    ShardedTable, Templates, User, Users,
+};
+
+/// Lists that can be set in a file included by `pgdog.toml`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigInclude {
+    #[serde(default)]
+    databases: Vec<Database>,
+    #[serde(default)]
+    sharded_tables: Vec<ShardedTable>,
+    #[serde(default)]
+    manual_queries: Vec<ManualQuery>,
+    #[serde(default)]
+    omnisharded_tables: Vec<OmnishardedTables>,
+    #[serde(default)]
+    replicated_tables: Vec<OmnishardedTables>,
+    #[serde(default)]
+    mirroring: Vec<MirroringRule>,
+    #[serde(default)]
+    routing_rules: Vec<RoutingRule>,
+}
+
+/// Lists that can be set in a file included by `users.toml`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct UsersInclude {
+    #[serde(default)]
+    users: Vec<User>,
+}
+
+/// Add files included by `pgdog.toml`.
+pub fn config(config: &mut Config, path: &Path) -> Result<(), Error> {
+    let templates = Templates::new(config);
+    for file in files(path, &config.include)? {
+        let include: ConfigInclude = parse(&file, &templates)?;
+        config.databases.extend(include.databases);
+        config.sharded_tables.extend(include.sharded_tables);
+        config.manual_queries.extend(include.manual_queries);
+        config.omnisharded_tables.extend(include.omnisharded_tables);
+        config.replicated_tables.extend(include.replicated_tables);
+        config.mirroring.extend(include.mirroring);
+        config.routing_rules.extend(include.routing_rules);
+    }
+
+    let mut databases = Duplicates::new("database");
+    for database in &config.databases {
+        databases.check(format!(
+            "\"{}\" (shard {}, {}, {}:{})",
+            database.name, database.shard, database.role, database.host, database.port
+        ))?;
+    }
+
+    let mut tables = Duplicates::new("sharded table");
+    for table in &config.sharded_tables {
+        tables.check(format!(
+            "\"{}.{}\" (database \"{}\")",
+            table.name.as_deref().unwrap_or("*"),
+            table.column,
+            table.database
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Add files included by `users.toml`.
+pub fn users(users: &mut Users, path: &Path) -> Result<(), Error> {
+    for file in files(path, &users.include)? {
+        let include: UsersInclude = parse(&file, &Templates::default())?;
+        users.users.extend(include.users);
+    }
+
+    let mut duplicates = Duplicates::new("user");
+    for user in &users.users {
+        duplicates.check(format!(
+            "\"{}\" (database \"{}\")",
+            user.name, user.database
+        ))?;
+    }
+
+    Ok(())
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(file: &Path, templates: &Templates) -> Result<T, Error> {
+    let source = read_to_string(file)?;
+    let include = templates
+        .parse(Format::from_path(file), &source)
+        .map_err(|err| match err {
+            Error::MissingField(message, line) => {
+                Error::MissingField(format!("{}: {}", file.display(), message), line)
+            }
+            Error::Syntax(message, line) => {
+                Error::Syntax(format!("{}: {}", file.display(), message), line)
+            }
+            err => err,
+        })?;
+    info!("loaded \"{}\"", file.display());
+    Ok(include)
+}
+
+/// Same entry defined more than once.
+struct Duplicates {
+    kind: &'static str,
+    seen: HashSet<String>,
+}
+
+impl Duplicates {
+    fn new(kind: &'static str) -> Self {
+        Self {
+            kind,
+            seen: HashSet::new(),
+        }
+    }
+
+    fn check(&mut self, key: String) -> Result<(), Error> {
+        if self.seen.contains(&key) {
+            return Err(Error::Duplicate(format!(
+                "{} {} is defined more than once",
+                self.kind, key
+            )));
+        }
+        self.seen.insert(key);
+        Ok(())
+    }
+}
+
+/// Files currently matching the patterns, skipping patterns that can't be read.
+pub fn included(path: &Path, patterns: &[String]) -> Vec<PathBuf> {
+    patterns
+        .iter()
+        .flat_map(|pattern| files(path, std::slice::from_ref(pattern)).unwrap_or_default())
+        .collect()
+}
+
+/// Directories the patterns look for files in.
+pub fn dirs(path: &Path, patterns: &[String]) -> Vec<PathBuf> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    patterns
+        .iter()
+        .filter_map(|pattern| base.join(pattern).parent().map(Path::to_path_buf))
+        .collect()
+}
+
+/// Files matching the patterns, relative to the directory of `path`.
+fn files(path: &Path, patterns: &[String]) -> Result<Vec<PathBuf>, Error> {
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut files: Vec<PathBuf> = vec![];
+
+    for pattern in patterns {
+        let pattern = base.join(pattern);
+        let name = pattern
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let mut matches = if name.contains(['*', '?']) {
+            let dir = pattern.parent().unwrap_or(Path::new(""));
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            let mut matches = vec![];
+            for entry in read_dir(dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file()
+                    && wildcard(&name, &entry.file_name().to_string_lossy(), '*', '?')
+                {
+                    matches.push(dir.join(entry.file_name()));
+                }
+            }
+            matches.sort();
+            matches
+        } else {
+            vec![pattern]
+        };
+
+        matches.retain(|file| !files.contains(file));
+        files.extend(matches);
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    use super::*;
+
+    #[test]
+    fn test_matches_pattern() {
+        let matches_pattern = |pattern, name| wildcard(pattern, name, '*', '?');
+        assert!(matches_pattern("*.toml", "tenant_1.toml"));
+        assert!(matches_pattern("tenant_?.toml", "tenant_1.toml"));
+        assert!(matches_pattern("*", "users.toml"));
+        assert!(matches_pattern("a*b*c", "aXbYbc"));
+        assert!(!matches_pattern("*.toml", "tenant_1.toml.bak"));
+        assert!(!matches_pattern("tenant_?.toml", "tenant_10.toml"));
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("pgdog_include_{}", std::process::id()));
+        create_dir_all(dir.join("conf.d")).unwrap();
+
+        write(
+            dir.join("conf.d/b.toml"),
+            "[[databases]]\nname = \"b\"\nhost = \"127.0.0.1\"\n",
+        )
+        .unwrap();
+        write(
+            dir.join("conf.d/a.toml"),
+            "[[databases]]\nname = \"a\"\nhost = \"127.0.0.1\"\n",
+        )
+        .unwrap();
+        write(dir.join("conf.d/README"), "not included").unwrap();
+
+        let path = dir.join("pgdog.toml");
+        let mut config: Config = toml::from_str(
+            "include = [\"conf.d/*.toml\"]\n[[databases]]\nname = \"main\"\nhost = \"127.0.0.1\"\n",
+        )
+        .unwrap();
+        super::config(&mut config, &path).unwrap();
+        let names = config
+            .databases
+            .iter()
+            .map(|database| database.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["main", "a", "b"]);
+
+        // Same database again.
+        config.include = vec!["conf.d/a.toml".into()];
+        let err = super::config(&mut config, &path).unwrap_err();
+        assert!(matches!(err, Error::Duplicate(_)));
+
+        // Only lists can be included.
+        write(dir.join("conf.d/c.toml"), "[general]\nport = 6433\n").unwrap();
+        config.include = vec!["conf.d/c.toml".into()];
+        assert!(super::config(&mut config, &path).is_err());
+
+        remove_dir_all(&dir).unwrap();
+    }
+}
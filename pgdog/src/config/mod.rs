@@ -1,26 +1,42 @@
 //! Configuration.
 
 pub mod convert;
+pub mod env;
 pub mod error;
+pub mod format;
+pub mod history;
+pub mod include;
 pub mod overrides;
+pub mod pgbouncer;
+pub mod reload;
+pub mod source;
+pub mod template;
 pub mod url;
+pub mod validate;
+pub mod watch;
 
 use error::Error;
+pub use format::Format;
 pub use overrides::Overrides;
+pub use template::{Template, Templates};
 
-use std::collections::HashSet;
-use std::fs::read_to_string;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::net::Ipv4Addr;
 use std::sync::Arc;
 use std::time::Duration;
-use std::{collections::HashMap, path::PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use arc_swap::ArcSwap;
 use once_cell::sync::Lazy;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 use tracing::warn;
 
+use crate::backend::shard_map::ShardMap;
 use crate::net::messages::Vector;
 use crate::util::{human_duration_optional, random_string};
 
@@ -36,19 +52,46 @@ pub fn config() -> Arc<ConfigAndUsers> {
 
 /// Load the configuration file from disk.
 pub fn load(config: &PathBuf, users: &PathBuf) -> Result<ConfigAndUsers, Error> {
-    let config = ConfigAndUsers::load(config, users)?;
-    set(config)
+    let config = set(ConfigAndUsers::load(config, users)?)?;
+    history::record(&config);
+    Ok(config)
 }
 
 pub fn set(mut config: ConfigAndUsers) -> Result<ConfigAndUsers, Error> {
-    config.config.check();
     for table in config.config.sharded_tables.iter_mut() {
+        if let ShardingFunction::Plugin(ref name) = table.sharding_function {
+            if !config
+                .config
+                .plugins
+                .iter()
+                .any(|plugin| &plugin.name == name)
+            {
+                return Err(Error::UnknownPlugin(name.clone()));
+            }
+        }
         table.load_centroids()?;
+        table.load_mapping()?;
     }
+    config.config.check();
     CONFIG.store(Arc::new(config.clone()));
     Ok(config)
 }
 
+/// Settings in sections of pgdog.toml, e.g. `general.port`.
+fn settings(config: &serde_json::Value) -> BTreeSet<String> {
+    let mut settings = BTreeSet::new();
+    if let Some(sections) = config.as_object() {
+        for (section, value) in sections {
+            if let Some(value) = value.as_object() {
+                for setting in value.keys() {
+                    settings.insert(format!("{}.{}", section, setting));
+                }
+            }
+        }
+    }
+    settings
+}
+
 /// Load configuration from a list of database URLs.
 pub fn from_urls(urls: &[String]) -> Result<ConfigAndUsers, Error> {
     let config = ConfigAndUsers::from_urls(urls)?;
@@ -67,16 +110,20 @@ pub fn overrides(overrides: Overrides) {
 
     if let Some(default_pool_size) = default_pool_size {
         config.config.general.default_pool_size = default_pool_size;
+        config.overridden.insert("general.default_pool_size".into());
     }
 
     if let Some(min_pool_size) = min_pool_size {
         config.config.general.min_pool_size = min_pool_size;
+        config.overridden.insert("general.min_pool_size".into());
     }
 
     if let Some(true) = session_mode {
         config.config.general.pooler_mode = PoolerMode::Session;
+        config.overridden.insert("general.pooler_mode".into());
     }
 
+    history::amend(&config);
     CONFIG.store(Arc::new(config));
 }
 
@@ -91,16 +138,32 @@ pub struct ConfigAndUsers {
     pub config_path: PathBuf,
     /// Path to users.toml.
     pub users_path: PathBuf,
+    /// Settings set in pgdog.toml, e.g. `general.port`.
+    pub explicit: BTreeSet<String>,
+    /// Settings changed after loading, with command line options or `SET`.
+    pub overridden: BTreeSet<String>,
 }
 
 impl ConfigAndUsers {
     /// Load configuration from disk or use defaults.
     pub fn load(config_path: &PathBuf, users_path: &PathBuf) -> Result<Self, Error> {
-        let config: Config = if let Ok(config) = read_to_string(config_path) {
-            let config = match toml::from_str(&config) {
-                Ok(config) => config,
-                Err(err) => return Err(Error::config(&config, err)),
-            };
+        Self::load_with(config_path, users_path, source::read)
+    }
+
+    /// Load configuration, reading files and remote configuration with `read`.
+    pub(crate) fn load_with(
+        config_path: &PathBuf,
+        users_path: &PathBuf,
+        read: impl Fn(&Path) -> std::io::Result<String>,
+    ) -> Result<Self, Error> {
+        let mut explicit = BTreeSet::new();
+        let config: Config = if let Ok(config) = read(config_path) {
+            let format = Format::from_path(config_path);
+            explicit = settings(&format.parse::<serde_json::Value>(&config)?);
+            let templates: Templates = format.parse(&config)?;
+            let mut config: Config = templates.parse(format, &config)?;
+            include::config(&mut config, config_path)?;
+            config.load_password_files()?;
             info!("loaded \"{}\"", config_path.display());
             config
         } else {
@@ -122,8 +185,10 @@ impl ConfigAndUsers {
             info!("multi-tenant protection enabled");
         }
 
-        let users: Users = if let Ok(users) = read_to_string(users_path) {
-            let mut users: Users = toml::from_str(&users)?;
+        let users: Users = if let Ok(users) = read(users_path) {
+            let mut users: Users = Format::from_path(users_path).parse(&users)?;
+            include::users(&mut users, users_path)?;
+            users.load_password_files()?;
             users.check(&config);
             info!("loaded \"{}\"", users_path.display());
             users
@@ -140,6 +205,8 @@ impl ConfigAndUsers {
             users,
             config_path: config_path.to_owned(),
             users_path: users_path.to_owned(),
+            explicit,
+            overridden: BTreeSet::new(),
         })
     }
 
@@ -153,6 +220,9 @@ impl ConfigAndUsers {
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    /// Other files with databases, sharded tables, etc.
+    #[serde(default)]
+    pub include: Vec<String>,
     /// General configuration.
     #[serde(default)]
     pub general: General,
@@ -164,6 +234,12 @@ pub struct Config {
     pub tcp: Tcp,
     /// Multi-tenant
     pub multi_tenant: Option<MultiTenant>,
+    /// Settings inherited by databases that don't set them.
+    #[serde(default)]
+    pub database_defaults: Template,
+    /// Named database settings, used by databases with `template = "name"`.
+    #[serde(default)]
+    pub database_templates: BTreeMap<String, Template>,
     /// Servers.
     #[serde(default)]
     pub databases: Vec<Database>,
@@ -177,6 +253,32 @@ pub struct Config {
     pub manual_queries: Vec<ManualQuery>,
     #[serde(default)]
     pub omnisharded_tables: Vec<OmnishardedTables>,
+    /// Reference tables, identical on every shard. Reads go to one shard,
+    /// writes to all of them in one transaction, and joins with sharded tables
+    /// work on any shard.
+    #[serde(default)]
+    pub replicated_tables: Vec<OmnishardedTables>,
+    /// Client driver compatibility shims.
+    #[serde(default)]
+    pub compatibility: Compatibility,
+    /// Mirroring rules.
+    #[serde(default)]
+    pub mirroring: Vec<MirroringRule>,
+    /// Statement audit log.
+    #[serde(default)]
+    pub audit: Audit,
+    /// Routing rules, checked in order.
+    #[serde(default)]
+    pub routing_rules: Vec<RoutingRule>,
+    /// Read/write classification of function calls.
+    #[serde(default)]
+    pub functions: Functions,
+    /// OpenTelemetry tracing.
+    #[serde(default)]
+    pub telemetry: Telemetry,
+    /// Lifecycle events sent to external automation.
+    #[serde(default)]
+    pub events: Events,
 }
 
 impl Config {
@@ -215,7 +317,11 @@ impl Config {
     pub fn omnisharded_tables(&self) -> HashMap<String, Vec<String>> {
         let mut tables = HashMap::new();
 
-        for table in &self.omnisharded_tables {
+        for table in self
+            .omnisharded_tables
+            .iter()
+            .chain(self.replicated_tables.iter())
+        {
             let entry = tables
                 .entry(table.database.clone())
                 .or_insert_with(Vec::new);
@@ -232,12 +338,31 @@ impl Config {
         let mut queries = HashMap::new();
 
         for query in &self.manual_queries {
-            queries.insert(query.fingerprint.clone(), query.clone());
+            if !query.fingerprint.is_empty() {
+                queries.insert(query.fingerprint.clone(), query.clone());
+            }
         }
 
         queries
     }
 
+    /// Manual queries matched by a regular expression.
+    pub fn manual_patterns(&self) -> Vec<(Regex, ManualQuery)> {
+        self.manual_queries
+            .iter()
+            .filter_map(|query| {
+                let pattern = query.pattern.as_ref()?;
+                match Regex::new(pattern) {
+                    Ok(regex) => Some((regex, query.clone())),
+                    Err(err) => {
+                        warn!(r#"manual query pattern "{}" is invalid: {}"#, pattern, err);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
     pub fn check(&self) {
         // Check databases.
         let mut duplicate_primaries = HashSet::new();
@@ -256,6 +381,37 @@ impl Config {
                 );
             }
         }
+
+        // Check sharding key mappings point to existing shards.
+        let databases = self.databases();
+        for table in &self.sharded_tables {
+            let shards = databases
+                .get(&table.database)
+                .map(|shards| shards.len())
+                .unwrap_or_default();
+            for (value, shard) in &table.mapping {
+                if *shard >= shards {
+                    warn!(
+                        "sharding key \"{}\" is mapped to shard {}, but database \"{}\" has {} shards",
+                        value, shard, table.database, shards
+                    );
+                }
+            }
+        }
+    }
+
+    /// Read passwords of the admin user and databases with `password_file`.
+    pub fn load_password_files(&mut self) -> Result<(), Error> {
+        for database in &mut self.databases {
+            load_password_file(&mut database.password, &database.password_file)?;
+        }
+
+        let mut password = None;
+        load_password_file(&mut password, &self.admin.password_file)?;
+        if let Some(password) = password {
+            self.admin.password = password;
+        }
+        Ok(())
     }
 
     /// Multi-tenanncy is enabled.
@@ -282,6 +438,12 @@ pub struct General {
     /// Minimum number of connections to maintain in the pool.
     #[serde(default = "General::min_pool_size")]
     pub min_pool_size: usize,
+    /// Additional connections allowed above the pool size during traffic spikes.
+    #[serde(default)]
+    pub reserve_pool_size: usize,
+    /// How long a client has to wait before the reserve pool is used.
+    #[serde(default = "General::reserve_pool_timeout")]
+    pub reserve_pool_timeout: u64,
     /// Pooler mode, e.g. transaction.
     #[serde(default)]
     pub pooler_mode: PoolerMode,
@@ -321,9 +483,36 @@ pub struct General {
     /// Broadcast port.
     #[serde(default = "General::broadcast_port")]
     pub broadcast_port: u16,
-    /// Load queries to file (warning: slow, don't use in production).
+    /// Log queries to this file.
     #[serde(default)]
     pub query_log: Option<PathBuf>,
+    /// Fraction of queries written to the query log, from 0.0 to 1.0.
+    #[serde(default = "General::query_log_sample_rate")]
+    pub query_log_sample_rate: f64,
+    /// Log queries without their parameters.
+    #[serde(default)]
+    pub query_log_normalize: bool,
+    /// Rotate the query log when it's larger than this many bytes. 0 disables it.
+    #[serde(default = "General::query_log_max_size")]
+    pub query_log_max_size: u64,
+    /// Rotate the query log when it's older than this, in milliseconds. 0 disables it.
+    #[serde(default)]
+    pub query_log_rotate_interval: u64,
+    /// Rotated query logs kept.
+    #[serde(default = "General::query_log_max_files")]
+    pub query_log_max_files: usize,
+    /// Fetch configuration from a remote source this often, in milliseconds.
+    #[serde(default = "General::config_poll_interval")]
+    pub config_poll_interval: u64,
+    /// Reload configuration automatically when its files change.
+    #[serde(default)]
+    pub watch_config: bool,
+    /// Wait this long after configuration files stop changing before reloading, in milliseconds.
+    #[serde(default = "General::config_watch_interval")]
+    pub config_watch_interval: u64,
+    /// Number of applied configurations kept for `ROLLBACK CONFIG`.
+    #[serde(default = "General::config_history")]
+    pub config_history: usize,
     /// Enable OpenMetrics server on this port.
     pub openmetrics_port: Option<u16>,
     /// Prepared statatements support.
@@ -351,6 +540,115 @@ pub struct General {
     pub mirror_queue: usize,
     #[serde(default)]
     pub auth_type: AuthType,
+    /// Answer queries to the virtual `pgdog` schema on regular connections. Off by default,
+    /// since it hides tables in a real `pgdog` schema. Clients only see their own user and database.
+    #[serde(default = "General::metadata_schema")]
+    pub metadata_schema: bool,
+    /// Warn when a client buffers this many bytes before sending Sync.
+    #[serde(default = "General::request_buffer_warning")]
+    pub request_buffer_warning: usize,
+    /// Disconnect clients buffering more than this many bytes before Sync. 0 means no limit.
+    #[serde(default)]
+    pub request_buffer_limit: usize,
+    /// Report this `server_version` to clients instead of the one from the database.
+    #[serde(default)]
+    pub server_version: Option<String>,
+    /// Disconnect clients idle outside of a transaction for this long. 0 means never.
+    #[serde(default)]
+    pub client_idle_timeout: u64,
+    /// Give each `application_name` its own connection pool for the same user and database.
+    /// Together, the pools open at most `pool_size` connections to each server.
+    #[serde(default)]
+    pub pool_by_application_name: bool,
+    /// Maximum number of `application_name` pools for each user and database.
+    #[serde(default = "General::max_application_pools")]
+    pub max_application_pools: usize,
+    /// Stop sending reads to replicas replaying transactions this many milliseconds late. 0 means no limit.
+    #[serde(default)]
+    pub max_replica_lag: u64,
+    /// Stop sending reads to replicas this many bytes of WAL behind the primary. 0 means no limit.
+    #[serde(default)]
+    pub max_replica_lag_bytes: u64,
+    /// Cache this many routing decisions for prepared statements and their parameters. 0 disables the cache.
+    #[serde(default = "General::route_cache_size")]
+    pub route_cache_size: usize,
+    /// Send reads to the primary when all replicas are down.
+    #[serde(default)]
+    pub replica_fallback_to_primary: bool,
+    /// Memory, in bytes, used to remove duplicates for cross-shard `COUNT(DISTINCT)` and `array_agg(DISTINCT)`. 0 means no limit.
+    #[serde(default = "General::distinct_memory_limit")]
+    pub distinct_memory_limit: usize,
+    /// Database collation, used to sort text when merging rows from multiple shards.
+    /// Byte order by default, which matches the `C` collation exactly.
+    #[serde(default)]
+    pub collation: Collation,
+    /// What to do with queries that return wrong results when sent to multiple shards.
+    #[serde(default)]
+    pub cross_shard_strictness: CrossShardStrictness,
+    /// Commit writes to replicated tables with two-phase commit, so they're
+    /// committed on all shards or none. Requires `max_prepared_transactions` on the shards.
+    #[serde(default)]
+    pub two_phase_commit: bool,
+    /// Memory used by one query to sort and aggregate rows from multiple shards, in bytes.
+    #[serde(default)]
+    pub cross_shard_work_mem: Option<usize>,
+    /// Memory used by all queries to sort and aggregate rows from multiple shards, in bytes.
+    #[serde(default)]
+    pub cross_shard_work_mem_total: Option<usize>,
+    /// Write sorted rows to temporary files when out of memory, instead of returning an error.
+    /// Queries with aggregates or DISTINCT can't be spilled.
+    #[serde(default)]
+    pub cross_shard_spill: bool,
+    /// Directory for temporary files. Default: the system's temporary directory.
+    #[serde(default)]
+    pub temp_dir: Option<PathBuf>,
+    /// Skip rows that can't be sharded during COPY and append them to this file,
+    /// with their row number and error, instead of aborting the whole COPY.
+    #[serde(default)]
+    pub copy_reject_file: Option<PathBuf>,
+    /// Look for a promoted replica when a primary goes down or starts refusing writes,
+    /// and send writes to it without a config reload.
+    #[serde(default)]
+    pub failover: bool,
+    /// Healthchecks a primary has to fail in a row before it's considered down.
+    #[serde(default = "General::failover_threshold")]
+    pub failover_threshold: usize,
+    /// Fail over only if most PgDog nodes found with service discovery
+    /// can't reach the primary either. Requires `broadcast_address`. A node that
+    /// hasn't found any peers never fails over; turn this off for a single node.
+    #[serde(default)]
+    pub failover_quorum: bool,
+    /// HTTP endpoint that has to confirm the primary is down before failing over,
+    /// e.g. `http://witness:8080/outage`.
+    #[serde(default)]
+    pub failover_witness: Option<String>,
+    /// Add replicas streaming from the primaries, found in `pg_stat_replication`,
+    /// and remove them once they disconnect. Replicas in the config are matched
+    /// by the addresses their hosts resolve to, so they aren't added twice.
+    #[serde(default)]
+    pub replica_discovery: bool,
+    /// Port discovered replicas listen on. `pg_stat_replication` only has their address.
+    #[serde(default = "Database::port")]
+    pub replica_discovery_port: u16,
+    /// How often to look for new replicas, in milliseconds.
+    #[serde(default = "General::replica_discovery_interval")]
+    pub replica_discovery_interval: u64,
+    /// Warn when a replication slot on any shard retains this many bytes of WAL. 0 disables the check.
+    #[serde(default)]
+    pub replication_slot_max_retained_wal: u64,
+    /// Aggregate execution statistics by query fingerprint, shown in `SHOW QUERY STATS`.
+    #[serde(default)]
+    pub query_stats: bool,
+    /// Maximum number of fingerprints tracked. Queries not seen before are ignored once it's reached.
+    #[serde(default = "General::query_stats_max")]
+    pub query_stats_max: usize,
+    /// Export statistics of this many queries, with the highest total time, to OpenMetrics.
+    /// 0 disables the export.
+    #[serde(default)]
+    pub openmetrics_query_stats: usize,
+    /// When `/readyz` and `/healthz` on the OpenMetrics port report PgDog as ready.
+    #[serde(default)]
+    pub readiness: Readiness,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
@@ -372,6 +670,19 @@ impl PreparedStatements {
     }
 }
 
+/// Readiness criteria of the health endpoints.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Readiness {
+    /// Listening for client connections.
+    #[default]
+    Listener,
+    /// Listening and all primaries are up.
+    AllPrimaries,
+    /// Listening and all pools, including replicas, are up.
+    AllPools,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum PassthoughAuth {
@@ -404,6 +715,74 @@ impl AuthType {
     }
 }
 
+/// Handling of window functions, correlated subqueries and `ORDER BY random()`
+/// in queries sent to multiple shards.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossShardStrictness {
+    /// Send them to all shards anyway.
+    #[default]
+    Permissive,
+    /// Return an error to the client.
+    Strict,
+    /// Send them to one shard, returning only its rows.
+    SingleShard,
+}
+
+/// How text is sorted.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Collation {
+    /// Byte order, like the `C` and `POSIX` collations.
+    #[default]
+    C,
+    /// Close to glibc locales like `en_US.UTF-8`: letters and digits first,
+    /// ignoring case and punctuation, then lowercase before uppercase.
+    /// Only an approximation, so it has to be turned on.
+    Locale,
+}
+
+impl Collation {
+    /// Collation from its name in `COLLATE`.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "C" | "POSIX" | "ucs_basic" | "pg_c_utf8" => Self::C,
+            _ => Self::Locale,
+        }
+    }
+
+    /// Compare two strings.
+    pub fn compare(&self, left: &str, right: &str) -> std::cmp::Ordering {
+        match self {
+            Self::C => left.cmp(right),
+            Self::Locale => {
+                let letters = |s: &str| {
+                    s.chars()
+                        .filter(|c| c.is_alphanumeric())
+                        .flat_map(char::to_lowercase)
+                        .collect::<String>()
+                };
+                let swap_case = |s: &str| {
+                    s.chars()
+                        .map(|c| {
+                            if c.is_uppercase() {
+                                c.to_lowercase().next().unwrap_or(c)
+                            } else {
+                                c.to_uppercase().next().unwrap_or(c)
+                            }
+                        })
+                        .collect::<String>()
+                };
+
+                letters(left)
+                    .cmp(&letters(right))
+                    .then_with(|| left.to_lowercase().cmp(&right.to_lowercase()))
+                    .then_with(|| swap_case(left).cmp(&swap_case(right)))
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum ReadWriteStrategy {
@@ -420,6 +799,8 @@ impl Default for General {
             workers: Self::workers(),
             default_pool_size: Self::default_pool_size(),
             min_pool_size: Self::min_pool_size(),
+            reserve_pool_size: 0,
+            reserve_pool_timeout: Self::reserve_pool_timeout(),
             pooler_mode: PoolerMode::default(),
             healthcheck_interval: Self::healthcheck_interval(),
             idle_healthcheck_interval: Self::idle_healthcheck_interval(),
@@ -435,6 +816,15 @@ impl Default for General {
             broadcast_address: None,
             broadcast_port: Self::broadcast_port(),
             query_log: None,
+            query_log_sample_rate: Self::query_log_sample_rate(),
+            query_log_normalize: false,
+            query_log_max_size: Self::query_log_max_size(),
+            query_log_rotate_interval: 0,
+            query_log_max_files: Self::query_log_max_files(),
+            config_poll_interval: Self::config_poll_interval(),
+            watch_config: false,
+            config_watch_interval: Self::config_watch_interval(),
+            config_history: Self::config_history(),
             openmetrics_port: None,
             prepared_statements: PreparedStatements::default(),
             passthrough_auth: PassthoughAuth::default(),
@@ -445,6 +835,38 @@ impl Default for General {
             idle_timeout: Self::idle_timeout(),
             mirror_queue: Self::mirror_queue(),
             auth_type: AuthType::default(),
+            metadata_schema: Self::metadata_schema(),
+            request_buffer_warning: Self::request_buffer_warning(),
+            request_buffer_limit: 0,
+            server_version: None,
+            client_idle_timeout: 0,
+            pool_by_application_name: false,
+            max_application_pools: Self::max_application_pools(),
+            max_replica_lag: 0,
+            max_replica_lag_bytes: 0,
+            route_cache_size: Self::route_cache_size(),
+            replica_fallback_to_primary: false,
+            distinct_memory_limit: Self::distinct_memory_limit(),
+            collation: Collation::default(),
+            cross_shard_strictness: CrossShardStrictness::default(),
+            two_phase_commit: false,
+            cross_shard_work_mem: None,
+            cross_shard_work_mem_total: None,
+            cross_shard_spill: false,
+            temp_dir: None,
+            copy_reject_file: None,
+            failover: false,
+            failover_threshold: Self::failover_threshold(),
+            failover_quorum: false,
+            failover_witness: None,
+            replica_discovery: false,
+            replica_discovery_port: Database::port(),
+            replica_discovery_interval: Self::replica_discovery_interval(),
+            replication_slot_max_retained_wal: 0,
+            query_stats: false,
+            query_stats_max: Self::query_stats_max(),
+            openmetrics_query_stats: 0,
+            readiness: Readiness::default(),
         }
     }
 }
@@ -470,6 +892,10 @@ impl General {
         1
     }
 
+    fn reserve_pool_timeout() -> u64 {
+        1_000
+    }
+
     fn healthcheck_interval() -> u64 {
         30_000
     }
@@ -502,6 +928,13 @@ impl General {
         Duration::from_millis(self.query_timeout)
     }
 
+    pub(crate) fn client_idle_timeout(&self) -> Duration {
+        match self.client_idle_timeout {
+            0 => Duration::MAX,
+            timeout => Duration::from_millis(timeout),
+        }
+    }
+
     fn load_balancing_strategy() -> LoadBalancingStrategy {
         LoadBalancingStrategy::Random
     }
@@ -526,6 +959,62 @@ impl General {
         128
     }
 
+    fn query_stats_max() -> usize {
+        1_000
+    }
+
+    fn query_log_sample_rate() -> f64 {
+        1.0
+    }
+
+    fn query_log_max_size() -> u64 {
+        100 * 1024 * 1024
+    }
+
+    fn query_log_max_files() -> usize {
+        5
+    }
+
+    fn config_poll_interval() -> u64 {
+        Duration::from_secs(10).as_millis() as u64
+    }
+
+    fn config_watch_interval() -> u64 {
+        Duration::from_secs(1).as_millis() as u64
+    }
+
+    fn config_history() -> usize {
+        10
+    }
+
+    fn route_cache_size() -> usize {
+        10_000
+    }
+
+    fn distinct_memory_limit() -> usize {
+        64 * 1024 * 1024
+    }
+
+    fn metadata_schema() -> bool {
+        false
+    }
+
+    fn request_buffer_warning() -> usize {
+        1024 * 1024 // 1 MiB
+    }
+
+    fn max_application_pools() -> usize {
+        16
+    }
+
+    fn failover_threshold() -> usize {
+        3
+    }
+
+    fn replica_discovery_interval() -> u64 {
+        30_000
+    }
+
     /// Get shutdown timeout as a duration.
     pub fn shutdown_timeout(&self) -> Duration {
         Duration::from_millis(self.shutdown_timeout)
@@ -548,8 +1037,83 @@ impl General {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct Stats {}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Stats {
+    /// Upper bounds of latency histogram buckets exported to OpenMetrics, in milliseconds.
+    /// Changing them requires a restart.
+    #[serde(default = "Stats::histogram_buckets")]
+    pub histogram_buckets: Vec<f64>,
+    /// Push metrics to this StatsD server, e.g. `127.0.0.1:8125`. Disabled if not set.
+    pub statsd: Option<String>,
+    /// StatsD dialect.
+    #[serde(default)]
+    pub statsd_format: StatsdFormat,
+    /// Prefix of all StatsD metric names.
+    #[serde(default = "Stats::statsd_prefix")]
+    pub statsd_prefix: String,
+    /// How often metrics are pushed, in milliseconds.
+    #[serde(default = "Stats::statsd_interval")]
+    pub statsd_interval: u64,
+    /// Insert stats snapshots into tables on this database. Disabled if not set.
+    pub export_database: Option<String>,
+    /// User stats are inserted as. Defaults to the first user of `export_database` in users.toml.
+    pub export_user: Option<String>,
+    /// Schema of the stats tables, created if it doesn't exist.
+    #[serde(default = "Stats::export_schema")]
+    pub export_schema: String,
+    /// How often stats snapshots are inserted, in milliseconds.
+    #[serde(default = "Stats::export_interval")]
+    pub export_interval: u64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            histogram_buckets: Self::histogram_buckets(),
+            statsd: None,
+            statsd_format: StatsdFormat::default(),
+            statsd_prefix: Self::statsd_prefix(),
+            statsd_interval: Self::statsd_interval(),
+            export_database: None,
+            export_user: None,
+            export_schema: Self::export_schema(),
+            export_interval: Self::export_interval(),
+        }
+    }
+}
+
+impl Stats {
+    fn histogram_buckets() -> Vec<f64> {
+        crate::stats::histogram::DEFAULT_BUCKETS.to_vec()
+    }
+
+    fn statsd_prefix() -> String {
+        "pgdog".into()
+    }
+
+    fn statsd_interval() -> u64 {
+        10_000
+    }
+
+    fn export_schema() -> String {
+        "pgdog".into()
+    }
+
+    fn export_interval() -> u64 {
+        60_000
+    }
+}
+
+/// StatsD protocol dialect.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsdFormat {
+    /// Plain StatsD, labels are added to metric names.
+    #[default]
+    Statsd,
+    /// DogStatsD, labels are sent as tags.
+    Dogstatsd,
+}
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy, Eq, Ord, PartialOrd)]
 #[serde(rename_all = "snake_case")]
@@ -568,6 +1132,37 @@ impl std::fmt::Display for PoolerMode {
     }
 }
 
+/// TLS used for connections to a database.
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Ord, PartialOrd,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ServerTlsMode {
+    /// Don't use TLS.
+    Disable,
+    /// Use TLS if the server supports it, without verifying its certificate.
+    #[default]
+    Prefer,
+    /// Require TLS, without verifying the server certificate.
+    Require,
+    /// Require TLS and verify the server certificate is signed by a trusted CA.
+    VerifyCa,
+    /// Same as `verify_ca`, and check the certificate matches the host name.
+    VerifyFull,
+}
+
+impl ServerTlsMode {
+    /// Connection must be encrypted.
+    pub fn required(&self) -> bool {
+        !matches!(self, Self::Disable | Self::Prefer)
+    }
+
+    /// Server certificate must be verified.
+    pub fn verify(&self) -> bool {
+        matches!(self, Self::VerifyCa | Self::VerifyFull)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum LoadBalancingStrategy {
@@ -608,6 +1203,8 @@ pub struct Database {
     pub user: Option<String>,
     /// Use this password to login, overriding the userlist.
     pub password: Option<String>,
+    /// Read `password` from this file.
+    pub password_file: Option<PathBuf>,
     // Maximum number of connections to this database from this pooler.
     // #[serde(default = "Database::max_connections")]
     // pub max_connections: usize,
@@ -615,16 +1212,27 @@ pub struct Database {
     pub pool_size: Option<usize>,
     /// Minimum pool size for this database pools, overriding `min_pool_size`.
     pub min_pool_size: Option<usize>,
+    /// Reserve pool size for this database pools, overriding `reserve_pool_size`.
+    pub reserve_pool_size: Option<usize>,
     /// Pooler mode.
     pub pooler_mode: Option<PoolerMode>,
     /// Statement timeout.
     pub statement_timeout: Option<u64>,
     /// Idle timeout.
     pub idle_timeout: Option<u64>,
+    /// Checkout timeout for this database pools, overriding `checkout_timeout`.
+    pub checkout_timeout: Option<u64>,
     /// Mirror of another database.
     pub mirror_of: Option<String>,
     /// Read-only mode.
     pub read_only: Option<bool>,
+    /// TLS mode used for connections to this database.
+    #[serde(default)]
+    pub server_tls_mode: ServerTlsMode,
+    /// CA certificate(s) used to verify this database's certificate, instead of the system ones.
+    pub server_tls_ca: Option<PathBuf>,
+    /// Inherit settings from this entry in `database_templates`.
+    pub template: Option<String>,
 }
 
 impl Database {
@@ -667,6 +1275,9 @@ pub struct Plugin {
 /// Users and passwords.
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Users {
+    /// Other files with users.
+    #[serde(default)]
+    pub include: Vec<String>,
     /// Users and passwords.
     #[serde(default)]
     pub users: Vec<User>,
@@ -685,6 +1296,21 @@ impl Users {
         users
     }
 
+    /// Find user by name and database.
+    pub fn find(&self, user: &str, database: &str) -> Option<&User> {
+        self.users
+            .iter()
+            .find(|u| u.name == user && u.database == database)
+    }
+
+    /// Read passwords of users with `password_file`.
+    pub fn load_password_files(&mut self) -> Result<(), Error> {
+        for user in &mut self.users {
+            load_password_file(&mut user.password, &user.password_file)?;
+        }
+        Ok(())
+    }
+
     pub fn check(&mut self, config: &Config) {
         for user in &mut self.users {
             if user.password().is_empty() {
@@ -717,6 +1343,10 @@ pub struct User {
     pub database: String,
     /// User's password.
     pub password: Option<String>,
+    /// Read `password` from this file.
+    pub password_file: Option<PathBuf>,
+    /// Password accepted in addition to `password`, while rotating credentials.
+    pub next_password: Option<String>,
     /// Pool size for this user pool, overriding `default_pool_size`.
     pub pool_size: Option<usize>,
     /// Minimum pool size for this user pool, overriding `min_pool_size`.
@@ -738,6 +1368,12 @@ pub struct User {
     pub idle_timeout: Option<u64>,
     /// Read-only mode.
     pub read_only: Option<bool>,
+    /// Checkout timeout, overriding `checkout_timeout`.
+    pub checkout_timeout: Option<u64>,
+    /// Client idle timeout, overriding `client_idle_timeout`.
+    pub client_idle_timeout: Option<u64>,
+    /// Pool by `application_name`, overriding `pool_by_application_name`.
+    pub pool_by_application_name: Option<bool>,
 }
 
 impl User {
@@ -763,6 +1399,17 @@ pub struct Admin {
     /// Admin user's password.
     #[serde(default = "Admin::password")]
     pub password: String,
+    /// Read `password` from this file.
+    pub password_file: Option<PathBuf>,
+    /// Serve admin commands over HTTP on this port. Requires `http_token`.
+    pub http_port: Option<u16>,
+    /// Address the HTTP API listens on.
+    #[serde(default = "Admin::http_host")]
+    pub http_host: String,
+    /// Bearer token required by the HTTP API.
+    pub http_token: Option<String>,
+    /// Append every admin command and its result to this file.
+    pub audit_log: Option<PathBuf>,
 }
 
 impl Default for Admin {
@@ -771,6 +1418,11 @@ impl Default for Admin {
             name: Self::name(),
             user: Self::user(),
             password: admin_password(),
+            password_file: None,
+            http_port: None,
+            http_host: Self::http_host(),
+            http_token: None,
+            audit_log: None,
         }
     }
 }
@@ -788,6 +1440,10 @@ impl Admin {
         admin_password()
     }
 
+    fn http_host() -> String {
+        "127.0.0.1".into()
+    }
+
     /// The password has been randomly generated.
     pub fn random(&self) -> bool {
         let prefix = "_pgdog_";
@@ -800,6 +1456,16 @@ fn admin_password() -> String {
     format!("_pgdog_{}", pw)
 }
 
+/// Read the password from `path`, if it's set, e.g. a mounted Kubernetes secret.
+fn load_password_file(password: &mut Option<String>, path: &Option<PathBuf>) -> Result<(), Error> {
+    if let Some(path) = path {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| Error::PasswordFile(path.display().to_string(), err))?;
+        *password = Some(contents.trim_end_matches(['\r', '\n']).to_owned());
+    }
+    Ok(())
+}
+
 /// Sharded table.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 #[serde(deny_unknown_fields)]
@@ -815,6 +1481,16 @@ pub struct ShardedTable {
     /// This table is the primary sharding anchor (e.g. "users").
     #[serde(default)]
     pub primary: bool,
+    /// Add the sharding key to INSERTs that don't have it, generated by
+    /// `pgdog.next_id` on the shard the row is sent to. Only `bigint` columns
+    /// sharded with `pg_hash` are supported.
+    #[serde(default)]
+    pub generate_key: bool,
+    /// Regular expression that extracts the sharding key from the column value
+    /// in COPY, e.g. `^(\d+):` for keys prefixed with the tenant ID. The first
+    /// capture group is used, or the whole match if there isn't one.
+    #[serde(default)]
+    pub key_pattern: Option<String>,
     /// Centroids for vector sharding.
     #[serde(default)]
     pub centroids: Vec<Vector>,
@@ -823,9 +1499,39 @@ pub struct ShardedTable {
     /// Data type of the column.
     #[serde(default)]
     pub data_type: DataType,
+    /// Function assigning sharding keys to shards.
+    #[serde(default)]
+    pub sharding_function: ShardingFunction,
+    /// Points on the hash ring per shard, used by `consistent_hash`. Default: 256.
+    #[serde(default)]
+    pub virtual_nodes: usize,
     /// How many centroids to probe.
     #[serde(default)]
     pub centroid_probes: usize,
+    /// Explicit shard for some sharding key values, e.g. tenants
+    /// or countries pinned to a shard. Checked before hashing. Queries with a key
+    /// mapped to a shard that doesn't exist return an error.
+    #[serde(default)]
+    pub mapping: HashMap<String, usize>,
+    /// Load more of the mapping from a JSON file, e.g. `{"US": 0, "DE": 1}`.
+    #[serde(default)]
+    pub mapping_path: Option<PathBuf>,
+    /// Load more of the mapping from this table, with columns `sharding_key` and `shard`.
+    /// It takes precedence over `mapping` and `mapping_path`.
+    #[serde(default)]
+    pub mapping_table: Option<String>,
+    /// Database with the mapping table. Defaults to shard 0 of this table's database.
+    #[serde(default)]
+    pub mapping_database: Option<String>,
+    /// Reload the mapping table this often, in milliseconds. Default: 60 seconds.
+    #[serde(default)]
+    pub mapping_ttl: Option<u64>,
+    /// Reload the mapping table when a notification is sent on this channel.
+    #[serde(default)]
+    pub mapping_channel: Option<String>,
+    /// Mapping loaded from `mapping_table`.
+    #[serde(skip)]
+    pub shard_map: ShardMap,
 }
 
 impl ShardedTable {
@@ -856,6 +1562,36 @@ impl ShardedTable {
 
         Ok(())
     }
+
+    /// Load the value to shard mapping from file, if provided,
+    /// and normalize the values for the column's data type.
+    pub fn load_mapping(&mut self) -> Result<(), Error> {
+        if let Some(mapping_path) = &self.mapping_path {
+            if let Ok(f) = std::fs::read_to_string(mapping_path) {
+                let mapping: HashMap<String, usize> = serde_json::from_str(&f)?;
+                info!("loaded {} sharding key mappings", mapping.len());
+                self.mapping.extend(mapping);
+            } else {
+                warn!("mapping at path \"{}\" not found", mapping_path.display());
+            }
+        }
+
+        let mut mapping = HashMap::new();
+        for (value, shard) in std::mem::take(&mut self.mapping) {
+            match self.data_type.normalize(&value) {
+                Some(normalized) => {
+                    mapping.insert(normalized, shard);
+                }
+                None => warn!(
+                    "sharding key \"{}\" in mapping for column \"{}\" isn't a valid {:?}",
+                    value, self.column, self.data_type
+                ),
+            }
+        }
+        self.mapping = mapping;
+
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default, Copy)]
@@ -865,6 +1601,63 @@ pub enum DataType {
     Bigint,
     Uuid,
     Vector,
+    Varchar,
+}
+
+/// Function assigning sharding keys to shards.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum ShardingFunction {
+    /// Postgres `PARTITION BY HASH` function, modulo the number of shards.
+    #[default]
+    PgHash,
+    /// Postgres hash function placed on a hash ring with virtual nodes,
+    /// so adding a shard only moves about 1/N of the keys.
+    ConsistentHash,
+    /// `pgdog_shard_key` function exported by a plugin, e.g. `plugin:my_plugin`.
+    /// The plugin has to be in `[[plugins]]`.
+    Plugin(String),
+}
+
+impl TryFrom<String> for ShardingFunction {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "pg_hash" => Ok(Self::PgHash),
+            "consistent_hash" => Ok(Self::ConsistentHash),
+            _ => match value.strip_prefix("plugin:") {
+                Some(plugin) if !plugin.is_empty() => Ok(Self::Plugin(plugin.to_owned())),
+                _ => Err(format!("unknown sharding function \"{}\"", value)),
+            },
+        }
+    }
+}
+
+impl From<ShardingFunction> for String {
+    fn from(value: ShardingFunction) -> Self {
+        match value {
+            ShardingFunction::PgHash => "pg_hash".into(),
+            ShardingFunction::ConsistentHash => "consistent_hash".into(),
+            ShardingFunction::Plugin(plugin) => format!("plugin:{}", plugin),
+        }
+    }
+}
+
+impl DataType {
+    /// Sharding key in its canonical text form, if it's valid for this data type.
+    pub fn normalize(&self, value: &str) -> Option<String> {
+        match self {
+            Self::Bigint => value.trim().parse::<i64>().ok().map(|v| v.to_string()),
+            Self::Uuid => value
+                .trim()
+                .parse::<uuid::Uuid>()
+                .ok()
+                .map(|v| v.to_string()),
+            Self::Varchar => Some(value.to_owned()),
+            Self::Vector => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
@@ -875,9 +1668,117 @@ pub struct OmnishardedTables {
 }
 
 /// Queries with manual routing rules.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+///
+/// Queries are matched by fingerprint or, if `pattern` is set,
+/// by a regular expression. Without a target, queries that would go
+/// to all shards are sent to one of them instead.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(deny_unknown_fields)]
 pub struct ManualQuery {
+    /// Query fingerprint.
+    #[serde(default)]
     pub fingerprint: String,
+    /// Regular expression matching the query text.
+    pub pattern: Option<String>,
+    /// Send the query to this shard.
+    pub shard: Option<usize>,
+    /// Send the query to the primary or the replicas.
+    #[serde(default)]
+    pub role: RoutingRole,
+    /// Reject the query with an error.
+    #[serde(default)]
+    pub block: bool,
+}
+
+impl ManualQuery {
+    /// The query has a routing target.
+    pub fn has_target(&self) -> bool {
+        self.shard.is_some() || self.role != RoutingRole::Auto || self.block
+    }
+}
+
+/// Mirror only some of the traffic.
+///
+/// If a mirror database has any rules, a request is sent to it
+/// only if it matches at least one of them.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct MirroringRule {
+    /// Name of the mirror database.
+    pub database: String,
+    /// Kind of statements to mirror.
+    #[serde(default)]
+    pub statement: MirroringStatement,
+    /// Mirror only queries with these fingerprints.
+    #[serde(default)]
+    pub fingerprints: Vec<String>,
+    /// Mirror only queries sent by this user.
+    pub user: Option<String>,
+    /// Percentage of matching requests to mirror, e.g. 1.0 for 1%.
+    #[serde(default = "MirroringRule::percentage")]
+    pub percentage: f64,
+}
+
+impl Default for MirroringRule {
+    fn default() -> Self {
+        Self {
+            database: String::new(),
+            statement: MirroringStatement::default(),
+            fingerprints: vec![],
+            user: None,
+            percentage: Self::percentage(),
+        }
+    }
+}
+
+impl MirroringRule {
+    fn percentage() -> f64 {
+        100.0
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MirroringStatement {
+    #[default]
+    All,
+    Read,
+    Write,
+}
+
+/// Send matching traffic to the primary, the replicas or a specific shard,
+/// regardless of what the query parser decided.
+///
+/// Criteria that aren't set match everything. The first matching rule is used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct RoutingRule {
+    /// Match queries sent by this user.
+    pub user: Option<String>,
+    /// Match queries sent to this database.
+    pub database: Option<String>,
+    /// Match queries sent by clients with this `application_name`.
+    pub application_name: Option<String>,
+    /// Match queries with these fingerprints.
+    #[serde(default)]
+    pub fingerprints: Vec<String>,
+    /// Send queries to the primary or the replicas.
+    #[serde(default)]
+    pub role: RoutingRole,
+    /// Send queries to this shard.
+    pub shard: Option<usize>,
+}
+
+/// Database role targeted by a routing rule.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingRole {
+    /// Let the query parser decide.
+    #[default]
+    Auto,
+    Primary,
+    /// Reads only, writes still go to the primary.
+    Replica,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
@@ -947,6 +1848,205 @@ impl Tcp {
     }
 }
 
+/// Statement audit log, in a pgAudit-compatible layout.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Audit {
+    /// Append audit records to this file. Auditing is disabled if not set.
+    pub path: Option<PathBuf>,
+    /// Record format.
+    #[serde(default)]
+    pub format: AuditFormat,
+    /// Statement classes to audit. All classes are audited if empty.
+    #[serde(default)]
+    pub classes: Vec<AuditClass>,
+    /// Append DDL, role and privilege changes and TRUNCATE, with the shards
+    /// they were sent to, to this file. Independent of `path`.
+    pub ddl_path: Option<PathBuf>,
+}
+
+/// Lifecycle events, e.g. pool bans and failovers, sent to a webhook or a Unix socket.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Events {
+    /// POST events to this URL, as a JSON array.
+    pub webhook: Option<String>,
+    /// Write events to this Unix socket, one JSON object per line.
+    pub socket: Option<PathBuf>,
+    /// Events to send. All events are sent if empty.
+    #[serde(default)]
+    pub kinds: Vec<EventKind>,
+    /// Events kept in memory while the receiver is unavailable.
+    #[serde(default = "Events::queue_size")]
+    pub queue_size: usize,
+    /// Wait before retrying delivery, in milliseconds. Doubles after every failure.
+    #[serde(default = "Events::retry_backoff")]
+    pub retry_backoff: u64,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            webhook: None,
+            socket: None,
+            kinds: vec![],
+            queue_size: Self::queue_size(),
+            retry_backoff: Self::retry_backoff(),
+        }
+    }
+}
+
+impl Events {
+    fn queue_size() -> usize {
+        10_000
+    }
+
+    fn retry_backoff() -> u64 {
+        100
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    ClientConnected,
+    ClientDisconnected,
+    PoolBanned,
+    PoolUnbanned,
+    Failover,
+    Reload,
+}
+
+/// OpenTelemetry tracing of client sessions, transactions and queries.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Telemetry {
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`.
+    /// Tracing is disabled if not set.
+    pub endpoint: Option<String>,
+    /// Fraction of client sessions traced, between 0 and 1. Queries with a `traceparent`
+    /// comment follow the application's sampling decision instead.
+    #[serde(default = "Telemetry::sample_rate")]
+    pub sample_rate: f64,
+    /// Service name reported to the collector.
+    #[serde(default = "Telemetry::service_name")]
+    pub service_name: String,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            sample_rate: Self::sample_rate(),
+            service_name: Self::service_name(),
+        }
+    }
+}
+
+impl Telemetry {
+    fn sample_rate() -> f64 {
+        1.0
+    }
+
+    fn service_name() -> String {
+        "pgdog".into()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// Statement classes, same as pgAudit's `pgaudit.log`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditClass {
+    Read,
+    Write,
+    Function,
+    Role,
+    Ddl,
+    Misc,
+    MiscSet,
+}
+
+impl std::fmt::Display for AuditClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read => write!(f, "READ"),
+            Self::Write => write!(f, "WRITE"),
+            Self::Function => write!(f, "FUNCTION"),
+            Self::Role => write!(f, "ROLE"),
+            Self::Ddl => write!(f, "DDL"),
+            Self::Misc => write!(f, "MISC"),
+            Self::MiscSet => write!(f, "MISC_SET"),
+        }
+    }
+}
+
+/// Workarounds for known client driver behaviors.
+///
+/// Driver shims are enabled by default and only apply in transaction mode.
+/// `pg_restore` changes how restores are routed, so it's opt-in.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Compatibility {
+    /// Answer `SET extra_float_digits` to a positive value (sent by JDBC) without going to the server.
+    #[serde(default = "Compatibility::enabled")]
+    pub extra_float_digits: bool,
+    /// Answer `DISCARD ALL` and `DEALLOCATE ALL` (sent by Npgsql and pgx) without going to the server.
+    #[serde(default = "Compatibility::enabled")]
+    pub discard_all: bool,
+    /// Send queries that only read system catalogs (e.g. Npgsql type loading) to one shard.
+    #[serde(default = "Compatibility::enabled")]
+    pub catalog_queries: bool,
+    /// Let `pg_restore` restore into all shards of a sharded database, instead of
+    /// requiring `pgdog.shard`. The session keeps its connections to all shards,
+    /// rows in `COPY` and `INSERT` are split by sharding key and everything else,
+    /// e.g. DDL, `SET` and `setval()`, is sent to all shards.
+    #[serde(default)]
+    pub pg_restore: bool,
+}
+
+impl Default for Compatibility {
+    fn default() -> Self {
+        Self {
+            extra_float_digits: Self::enabled(),
+            discard_all: Self::enabled(),
+            catalog_queries: Self::enabled(),
+            pg_restore: false,
+        }
+    }
+}
+
+impl Compatibility {
+    fn enabled() -> bool {
+        true
+    }
+}
+
+/// Read/write classification of functions called by `SELECT` statements.
+///
+/// `nextval`, `setval` and advisory locks are always sent to the primary.
+/// Names can be schema-qualified, e.g. `app.refresh_cache`.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub struct Functions {
+    /// Functions that write.
+    #[serde(default)]
+    pub writes: Vec<String>,
+    /// Functions that only read, used with `conservative`.
+    #[serde(default)]
+    pub reads: Vec<String>,
+    /// Send queries calling functions not known to only read to the primary.
+    #[serde(default)]
+    pub conservative: bool,
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct MultiTenant {
@@ -959,6 +2059,22 @@ pub mod test {
 
     use super::*;
 
+    /// Restores the configuration when dropped, so tests changing it
+    /// don't affect tests running after them.
+    pub struct ConfigGuard(Arc<ConfigAndUsers>);
+
+    impl Default for ConfigGuard {
+        fn default() -> Self {
+            Self(config())
+        }
+    }
+
+    impl Drop for ConfigGuard {
+        fn drop(&mut self) {
+            let _ = set((*self.0).clone());
+        }
+    }
+
     pub fn load_test() {
         let mut config = ConfigAndUsers::default();
         config.config.databases = vec![Database {
@@ -1052,4 +2168,131 @@ column = "tenant_id"
         assert_eq!(config.tcp.retries().unwrap(), 5);
         assert_eq!(config.multi_tenant.unwrap().column, "tenant_id");
     }
+
+    #[test]
+    fn test_checkout_timeout() {
+        use crate::backend::pool::Config as PoolConfig;
+
+        let general = General::default();
+        let mut database = Database {
+            checkout_timeout: Some(1_000),
+            ..Default::default()
+        };
+        let mut user = User::default();
+
+        let config = PoolConfig::new(&general, &database, &user);
+        assert_eq!(config.checkout_timeout(), Duration::from_millis(1_000));
+
+        user.checkout_timeout = Some(100);
+        let config = PoolConfig::new(&general, &database, &user);
+        assert_eq!(config.checkout_timeout(), Duration::from_millis(100));
+
+        database.checkout_timeout = None;
+        user.checkout_timeout = None;
+        let config = PoolConfig::new(&general, &database, &user);
+        assert_eq!(
+            config.checkout_timeout(),
+            Duration::from_millis(general.checkout_timeout)
+        );
+    }
+
+    #[test]
+    fn test_manual_queries() {
+        let source = r#"
+[[databases]]
+name = "pgdog"
+host = "127.0.0.1"
+
+[[manual_queries]]
+fingerprint = "0a1b2c"
+
+[[manual_queries]]
+pattern = "(?i)^select .* from reports"
+role = "replica"
+shard = 1
+
+[[manual_queries]]
+pattern = "pg_sleep"
+block = true
+
+[[manual_queries]]
+pattern = "(unclosed"
+"#;
+        let config: Config = toml::from_str(source).unwrap();
+
+        let queries = config.manual_queries();
+        assert_eq!(queries.len(), 1);
+        assert!(!queries["0a1b2c"].has_target());
+
+        let patterns = config.manual_patterns();
+        assert_eq!(patterns.len(), 2);
+        let (regex, query) = &patterns[0];
+        assert!(regex.is_match("SELECT id FROM reports"));
+        assert_eq!(query.role, RoutingRole::Replica);
+        assert_eq!(query.shard, Some(1));
+        assert!(patterns[1].1.block);
+    }
+
+    #[test]
+    fn test_unknown_plugin() {
+        let _guard = ConfigGuard::default();
+
+        let mut config = ConfigAndUsers::default();
+        config.config.sharded_tables = vec![ShardedTable {
+            database: "pgdog".into(),
+            column: "id".into(),
+            sharding_function: ShardingFunction::Plugin("murmur3".into()),
+            ..Default::default()
+        }];
+        assert!(matches!(
+            set(config.clone()),
+            Err(Error::UnknownPlugin(name)) if name == "murmur3"
+        ));
+
+        config.config.plugins = vec![Plugin {
+            name: "murmur3".into(),
+        }];
+        assert!(set(config).is_ok());
+    }
+
+    #[test]
+    fn test_password_files() {
+        let dir = std::env::temp_dir().join(format!("pgdog_password_file_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("admin"), "admin-secret\n").unwrap();
+        std::fs::write(dir.join("user"), "user-secret").unwrap();
+
+        let source = format!(
+            r#"
+[admin]
+password_file = "{0}/admin"
+
+[[databases]]
+name = "prod"
+host = "127.0.0.1"
+password = "replaced"
+password_file = "{0}/user"
+"#,
+            dir.display()
+        );
+        let mut config: Config = toml::from_str(&source).unwrap();
+        config.load_password_files().unwrap();
+        assert_eq!(config.admin.password, "admin-secret");
+        assert!(!config.admin.random());
+        assert_eq!(config.databases[0].password.as_deref(), Some("user-secret"));
+
+        let mut users = Users {
+            users: vec![User {
+                password_file: Some(dir.join("missing")),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(matches!(
+            users.load_password_files(),
+            Err(Error::PasswordFile(_, _))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,622 @@
+//! Convert PgBouncer configuration, used by `pgdog migrate-config`.
+//!
+//! `[databases]` become `[[databases]]` and the `userlist.txt` users get a
+//! `[[users]]` entry for every database. Pool modes, pool sizes, timeouts
+//! (seconds in PgBouncer, milliseconds in PgDog), TLS and auth settings are
+//! converted. Anything PgDog doesn't have, like `auth_query` or the `*`
+//! fallback database, is reported as a warning and left out.
+
+use serde_json::{json, Map, Value};
+
+use super::{Config, Error, Users};
+
+/// PgBouncer settings without an equivalent that don't change how clients
+/// are served, skipped without a warning.
+const IGNORED: &[&str] = &[
+    "logfile",
+    "pidfile",
+    "syslog",
+    "syslog_ident",
+    "syslog_facility",
+    "log_connections",
+    "log_disconnections",
+    "log_pooler_errors",
+    "log_stats",
+    "stats_period",
+    "verbose",
+    "unix_socket_dir",
+    "unix_socket_mode",
+    "unix_socket_group",
+    "user",
+    "auth_file",
+    "max_client_conn",
+    "ignore_startup_parameters",
+    "server_reset_query",
+    "server_reset_query_always",
+    "server_round_robin",
+    "so_reuseport",
+    "application_name_add_host",
+];
+
+/// Converted configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Migration {
+    /// pgdog.toml.
+    pub config: Value,
+    /// users.toml.
+    pub users: Value,
+    /// Settings that couldn't be converted.
+    pub warnings: Vec<String>,
+}
+
+impl Migration {
+    /// pgdog.toml and users.toml contents.
+    pub fn to_toml(&self) -> Result<(String, String), Error> {
+        // Make sure PgDog can read what we're writing.
+        serde_json::from_value::<Config>(self.config.clone())?;
+        serde_json::from_value::<Users>(self.users.clone())?;
+
+        Ok((
+            toml::to_string(&self.config)?,
+            toml::to_string(&self.users)?,
+        ))
+    }
+}
+
+/// INI file, as sections of settings, in order.
+type Ini = Vec<(String, Vec<(String, String, usize)>)>;
+
+fn ini(source: &str) -> Result<Ini, Error> {
+    let mut sections: Ini = vec![];
+
+    for (number, line) in source.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(section) = line.strip_prefix('[') {
+            let section = section
+                .strip_suffix(']')
+                .ok_or_else(|| Error::Syntax("section isn't closed".into(), number))?;
+            sections.push((section.trim().to_lowercase(), vec![]));
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| Error::Syntax("expected \"key = value\"".into(), number))?;
+        let (_, settings) = sections
+            .last_mut()
+            .ok_or_else(|| Error::Syntax("setting outside of a section".into(), number))?;
+        settings.push((key.trim().to_owned(), value.trim().to_owned(), number));
+    }
+
+    Ok(sections)
+}
+
+/// `host=10.0.0.1 dbname='my db'`, from `[databases]` and `[users]`.
+fn connection_string(source: &str, line: usize) -> Result<Vec<(String, String)>, Error> {
+    let mut settings = vec![];
+    let mut chars = source.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let key = std::iter::from_fn(|| chars.next_if(|c| *c != '=' && !c.is_whitespace()))
+            .collect::<String>();
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next() != Some('=') {
+            return Err(Error::Syntax(
+                format!("expected \"=\" after \"{}\"", key),
+                line,
+            ));
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'\'').is_some() {
+            loop {
+                match chars.next() {
+                    Some('\'') if chars.next_if_eq(&'\'').is_some() => value.push('\''),
+                    Some('\'') => break,
+                    Some(c) => value.push(c),
+                    None => return Err(Error::Syntax("quote isn't closed".into(), line)),
+                }
+            }
+        } else {
+            value.extend(std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())));
+        }
+
+        settings.push((key.to_lowercase(), value));
+    }
+
+    Ok(settings)
+}
+
+/// `"user" "password"` lines of `userlist.txt`.
+fn userlist(source: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut users = vec![];
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = vec![];
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c != '"' {
+                continue;
+            }
+            let mut field = String::new();
+            loop {
+                match chars.next() {
+                    // Quotes are escaped by doubling them.
+                    Some('"') if chars.as_str().starts_with('"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => {
+                        return Err(Error::Syntax("quote isn't closed".into(), number + 1));
+                    }
+                }
+            }
+            fields.push(field);
+        }
+
+        match &fields[..] {
+            [user, password, ..] => users.push((user.clone(), password.clone())),
+            _ => {
+                return Err(Error::Syntax(
+                    "expected \"user\" \"password\"".into(),
+                    number + 1,
+                ))
+            }
+        }
+    }
+
+    Ok(users)
+}
+
+/// Seconds to milliseconds.
+fn millis(value: &str, line: usize) -> Result<u64, Error> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| *seconds >= 0.0)
+        .map(|seconds| (seconds * 1000.0).round() as u64)
+        .ok_or_else(|| Error::Syntax(format!("\"{}\" isn't a number of seconds", value), line))
+}
+
+fn number(value: &str, line: usize) -> Result<u64, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::Syntax(format!("\"{}\" isn't a number", value), line))
+}
+
+/// PgBouncer `pool_mode`. Statement pooling is closest to transaction pooling.
+fn pool_mode(value: &str, warnings: &mut Vec<String>) -> Value {
+    match value {
+        "session" => json!("session"),
+        "statement" => {
+            warnings.push(
+                "pool_mode \"statement\" isn't supported, using \"transaction\" instead".into(),
+            );
+            json!("transaction")
+        }
+        _ => json!("transaction"),
+    }
+}
+
+fn tls_mode(value: &str) -> Option<Value> {
+    let mode = match value {
+        "disable" => "disable",
+        "allow" | "prefer" => "prefer",
+        "require" => "require",
+        "verify-ca" => "verify_ca",
+        "verify-full" => "verify_full",
+        _ => return None,
+    };
+    Some(json!(mode))
+}
+
+/// Hashed passwords can't be used to connect to PostgreSQL.
+fn hashed(password: &str) -> bool {
+    (password.len() == 35 && password.starts_with("md5")) || password.starts_with("SCRAM-SHA-256$")
+}
+
+/// Convert `pgbouncer.ini` and, optionally, `userlist.txt`.
+pub fn convert(ini_source: &str, userlist_source: Option<&str>) -> Result<Migration, Error> {
+    let sections = ini(ini_source)?;
+    let mut warnings = vec![];
+    let mut general = Map::new();
+    let mut admin = Map::new();
+    let mut databases: Vec<Map<String, Value>> = vec![];
+    let mut user_settings: Vec<(String, Map<String, Value>)> = vec![];
+    let mut server_tls = Map::new();
+    let mut admin_user = None;
+
+    for (section, settings) in &sections {
+        match section.as_str() {
+            "pgbouncer" => {
+                for (key, value, line) in settings {
+                    let line = *line;
+                    match key.as_str() {
+                        "listen_addr" => {
+                            let host = value.split(',').next().unwrap_or_default().trim();
+                            general.insert(
+                                "host".into(),
+                                json!(if host == "*" { "0.0.0.0" } else { host }),
+                            );
+                        }
+                        "listen_port" => {
+                            general.insert("port".into(), json!(number(value, line)?));
+                        }
+                        "pool_mode" => {
+                            general.insert("pooler_mode".into(), pool_mode(value, &mut warnings));
+                        }
+                        "default_pool_size" | "min_pool_size" | "reserve_pool_size" => {
+                            general.insert(key.clone(), json!(number(value, line)?));
+                        }
+                        "reserve_pool_timeout" => {
+                            general.insert(key.clone(), json!(millis(value, line)?));
+                        }
+                        "server_connect_timeout" => {
+                            general.insert("connect_timeout".into(), json!(millis(value, line)?));
+                        }
+                        "server_idle_timeout" => {
+                            general.insert("idle_timeout".into(), json!(millis(value, line)?));
+                        }
+                        "server_check_delay" => {
+                            general.insert(
+                                "idle_healthcheck_interval".into(),
+                                json!(millis(value, line)?),
+                            );
+                        }
+                        // 0 disables these in PgBouncer, which is PgDog's default.
+                        "query_timeout" | "client_idle_timeout" | "query_wait_timeout" => {
+                            let timeout = millis(value, line)?;
+                            if timeout > 0 {
+                                let key = match key.as_str() {
+                                    "query_wait_timeout" => "checkout_timeout",
+                                    key => key,
+                                };
+                                general.insert(key.into(), json!(timeout));
+                            }
+                        }
+                        "auth_type" => match value.as_str() {
+                            "md5" | "plain" => {
+                                general.insert("auth_type".into(), json!("md5"));
+                            }
+                            "scram-sha-256" => {
+                                general.insert("auth_type".into(), json!("scram"));
+                            }
+                            "trust" | "any" => {
+                                general.insert("auth_type".into(), json!("trust"));
+                            }
+                            other => warnings.push(format!(
+                                "auth_type \"{}\" isn't supported, using \"scram\"",
+                                other
+                            )),
+                        },
+                        "client_tls_cert_file" => {
+                            general.insert("tls_certificate".into(), json!(value));
+                        }
+                        "client_tls_key_file" => {
+                            general.insert("tls_private_key".into(), json!(value));
+                        }
+                        "server_tls_sslmode" => match tls_mode(value) {
+                            Some(mode) => {
+                                server_tls.insert("server_tls_mode".into(), mode);
+                            }
+                            None => warnings
+                                .push(format!("server_tls_sslmode \"{}\" isn't supported", value)),
+                        },
+                        "server_tls_ca_file" => {
+                            server_tls.insert("server_tls_ca".into(), json!(value));
+                        }
+                        "admin_users" => {
+                            admin_user = value
+                                .split(',')
+                                .map(str::trim)
+                                .find(|user| !user.is_empty())
+                                .map(str::to_owned);
+                            if let Some(ref user) = admin_user {
+                                admin.insert("user".into(), json!(user));
+                            }
+                        }
+                        key if IGNORED.contains(&key) => (),
+                        key => warnings.push(format!("\"{}\" isn't supported, skipped", key)),
+                    }
+                }
+            }
+
+            "databases" => {
+                for (name, value, line) in settings {
+                    if name == "*" {
+                        warnings.push(
+                            "the \"*\" fallback database isn't supported, add each database instead"
+                                .into(),
+                        );
+                        continue;
+                    }
+
+                    let mut database = Map::new();
+                    database.insert("name".into(), json!(name));
+                    let mut hosts = vec!["127.0.0.1".to_owned()];
+
+                    for (key, value) in connection_string(value, *line)? {
+                        match key.as_str() {
+                            "host" => {
+                                hosts = value
+                                    .split(',')
+                                    .map(|host| host.trim().to_owned())
+                                    .collect()
+                            }
+                            "port" => {
+                                database.insert("port".into(), json!(number(&value, *line)?));
+                            }
+                            "dbname" => {
+                                database.insert("database_name".into(), json!(value));
+                            }
+                            "user" | "password" => {
+                                database.insert(key, json!(value));
+                            }
+                            "pool_size" | "min_pool_size" => {
+                                database.insert(key, json!(number(&value, *line)?));
+                            }
+                            "reserve_pool" | "reserve_pool_size" => {
+                                database.insert(
+                                    "reserve_pool_size".into(),
+                                    json!(number(&value, *line)?),
+                                );
+                            }
+                            "pool_mode" => {
+                                database
+                                    .insert("pooler_mode".into(), pool_mode(&value, &mut warnings));
+                            }
+                            key => warnings.push(format!(
+                                "\"{}\" of database \"{}\" isn't supported, skipped",
+                                key, name
+                            )),
+                        }
+                    }
+
+                    // PgBouncer load balances between hosts, PgDog needs a primary.
+                    if hosts.len() > 1 {
+                        warnings.push(format!(
+                            "database \"{}\" has several hosts, the first one is the primary and the rest are replicas",
+                            name
+                        ));
+                    }
+                    for (i, host) in hosts.into_iter().enumerate() {
+                        let mut database = database.clone();
+                        database.insert("host".into(), json!(host));
+                        if i > 0 {
+                            database.insert("role".into(), json!("replica"));
+                        }
+                        databases.push(database);
+                    }
+                }
+            }
+
+            "users" => {
+                for (name, value, line) in settings {
+                    let mut user = Map::new();
+                    for (key, value) in connection_string(value, *line)? {
+                        match key.as_str() {
+                            "pool_mode" => {
+                                user.insert("pooler_mode".into(), pool_mode(&value, &mut warnings));
+                            }
+                            "pool_size" => {
+                                user.insert(key, json!(number(&value, *line)?));
+                            }
+                            key => warnings.push(format!(
+                                "\"{}\" of user \"{}\" isn't supported, skipped",
+                                key, name
+                            )),
+                        }
+                    }
+                    user_settings.push((name.clone(), user));
+                }
+            }
+
+            "peers" => warnings.push("[peers] isn't supported, skipped".into()),
+
+            section => warnings.push(format!("[{}] isn't a PgBouncer section, skipped", section)),
+        }
+    }
+
+    // [pgbouncer] can come after [databases].
+    for database in &mut databases {
+        database.extend(server_tls.clone());
+    }
+
+    let mut users = vec![];
+    let mut names = databases
+        .iter()
+        .filter_map(|database| database["name"].as_str())
+        .collect::<Vec<_>>();
+    names.dedup();
+
+    let userlist = userlist(userlist_source.unwrap_or_default())?;
+    if userlist_source.is_none() {
+        warnings.push("no userlist.txt given, add users to users.toml".into());
+    }
+    for (name, _) in &user_settings {
+        if !userlist.iter().any(|(user, _)| user == name) {
+            warnings.push(format!(
+                "user \"{}\" isn't in userlist.txt, its [users] settings are skipped",
+                name
+            ));
+        }
+    }
+
+    for (name, password) in userlist {
+        if Some(&name) == admin_user.as_ref() && !hashed(&password) {
+            admin.insert("password".into(), json!(password));
+        }
+
+        let password = if hashed(&password) {
+            warnings.push(format!(
+                "user \"{}\" has a hashed password, set it in users.toml",
+                name
+            ));
+            None
+        } else {
+            Some(password)
+        };
+
+        for database in &names {
+            let mut user = Map::new();
+            user.insert("name".into(), json!(name));
+            user.insert("database".into(), json!(database));
+            if let Some(ref password) = password {
+                user.insert("password".into(), json!(password));
+            }
+            if let Some((_, settings)) = user_settings.iter().find(|(user, _)| user == &name) {
+                user.extend(settings.clone());
+            }
+            users.push(Value::Object(user));
+        }
+    }
+
+    let mut config = Map::new();
+    if !general.is_empty() {
+        config.insert("general".into(), Value::Object(general));
+    }
+    if !admin.is_empty() {
+        config.insert("admin".into(), Value::Object(admin));
+    }
+    config.insert(
+        "databases".into(),
+        Value::Array(databases.into_iter().map(Value::Object).collect()),
+    );
+
+    Ok(Migration {
+        config: Value::Object(config),
+        users: json!({ "users": users }),
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{PoolerMode, Role};
+
+    #[test]
+    fn test_convert() {
+        let ini = r#"
+; PgBouncer configuration
+[databases]
+prod = host=10.0.0.1,10.0.0.2 port=5433 dbname='prod db' pool_size=30
+analytics = host=10.0.0.3 pool_mode=session connect_query='SELECT 1'
+* = host=10.0.0.4
+
+[users]
+etl = pool_mode=session
+
+[pgbouncer]
+listen_addr = *
+listen_port = 6432
+pool_mode = transaction
+default_pool_size = 20
+query_wait_timeout = 120
+query_timeout = 0
+server_idle_timeout = 600.5
+auth_type = md5
+auth_file = /etc/pgbouncer/userlist.txt
+server_tls_sslmode = verify-full
+admin_users = admin
+auth_query = SELECT usename, passwd FROM pg_shadow WHERE usename=$1
+"#;
+        let userlist = r#"
+"alice" "secret"
+"etl" "md5c1b8d8b5b7e4e0c7a6f2a5b8d1e3f4a7"
+"admin" "admin""pass"
+"#;
+
+        let migration = convert(ini, Some(userlist)).unwrap();
+        let (config, users) = migration.to_toml().unwrap();
+        let config: Config = toml::from_str(&config).unwrap();
+        let users: Users = toml::from_str(&users).unwrap();
+
+        assert_eq!(config.general.host, "0.0.0.0");
+        assert_eq!(config.general.port, 6432);
+        assert_eq!(config.general.default_pool_size, 20);
+        assert_eq!(config.general.checkout_timeout, 120_000);
+        assert_eq!(config.general.idle_timeout, 600_500);
+        assert!(config.general.auth_type.md5());
+        assert_eq!(config.admin.user, "admin");
+        assert_eq!(config.admin.password, "admin\"pass");
+
+        let databases = config
+            .databases
+            .iter()
+            .map(|database| {
+                (
+                    database.name.as_str(),
+                    database.host.as_str(),
+                    database.role,
+                    database.port,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            databases,
+            vec![
+                ("prod", "10.0.0.1", Role::Primary, 5433),
+                ("prod", "10.0.0.2", Role::Replica, 5433),
+                ("analytics", "10.0.0.3", Role::Primary, 5432),
+            ]
+        );
+        assert_eq!(
+            config.databases[0].database_name.as_deref(),
+            Some("prod db")
+        );
+        assert_eq!(config.databases[0].pool_size, Some(30));
+        assert_eq!(config.databases[2].pooler_mode, Some(PoolerMode::Session));
+
+        assert_eq!(users.users.len(), 6);
+        assert_eq!(users.users[0].name, "alice");
+        assert_eq!(users.users[0].password(), "secret");
+        assert_eq!(users.users[2].password, None);
+        assert_eq!(users.users[2].pooler_mode, Some(PoolerMode::Session));
+
+        assert_eq!(
+            migration.warnings,
+            vec![
+                "database \"prod\" has several hosts, the first one is the primary and the rest are replicas",
+                "\"connect_query\" of database \"analytics\" isn't supported, skipped",
+                "the \"*\" fallback database isn't supported, add each database instead",
+                "\"auth_query\" isn't supported, skipped",
+                "user \"etl\" has a hashed password, set it in users.toml",
+            ]
+        );
+
+        assert!(convert("[pgbouncer]\nlisten_port\n", None).is_err());
+
+        let migration = convert(
+            "[databases]\nprod = host=127.0.0.1\n[users]\netl = pool_mode=session\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            migration.warnings,
+            vec![
+                "no userlist.txt given, add users to users.toml",
+                "user \"etl\" isn't in userlist.txt, its [users] settings are skipped",
+            ]
+        );
+    }
+}
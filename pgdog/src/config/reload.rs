@@ -0,0 +1,361 @@
+//! What changed in the configuration on reload, shown in `SHOW RELOAD`.
+//!
+//! Most settings are applied by reloading: pools are re-created with the new
+//! settings and existing connections are moved into them. The ones listed in
+//! [`RESTART`] are only read on startup, so changing them is reported as
+//! requiring a restart.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::SystemTime;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use super::ConfigAndUsers;
+
+/// Settings that need a restart to take effect.
+pub const RESTART: &[&str] = &[
+    "general.host",
+    "general.port",
+    "general.workers",
+    "general.openmetrics_port",
+    "general.broadcast_address",
+    "general.broadcast_port",
+    "general.watch_config",
+    "admin.http_port",
+    "admin.http_host",
+    "plugins",
+    "stats.histogram_buckets",
+    "stats.statsd",
+    "stats.statsd_format",
+    "stats.statsd_prefix",
+    "stats.statsd_interval",
+    "stats.export_database",
+    "stats.export_user",
+    "stats.export_schema",
+    "stats.export_interval",
+];
+
+/// Longest value shown, e.g. for vector centroids.
+const MAX_VALUE: usize = 256;
+
+/// Shown instead of passwords and tokens.
+const MASK: &str = "********";
+
+static LAST: Lazy<Mutex<Option<Report>>> = Lazy::new(|| Mutex::new(None));
+
+/// Setting that changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Setting, e.g. `general.default_pool_size` or `users[alice@prod].pool_size`.
+    pub path: String,
+    /// Previous value, if it was set.
+    pub old: Option<String>,
+    /// New value, if it's set.
+    pub new: Option<String>,
+    /// Needs a restart to take effect.
+    pub restart: bool,
+}
+
+/// Result of a reload.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub reloaded_at: SystemTime,
+    pub changes: Vec<Change>,
+    /// Reload failed and nothing was changed.
+    pub error: Option<String>,
+}
+
+impl Report {
+    /// Compare the configuration before and after reload.
+    pub fn new(old: &ConfigAndUsers, new: &ConfigAndUsers) -> Self {
+        let (mut old, mut new) = (to_json(old), to_json(new));
+        mask(&mut old);
+        mask(&mut new);
+
+        Self {
+            reloaded_at: SystemTime::now(),
+            changes: changes(&old, &new),
+            error: None,
+        }
+    }
+
+    /// Reload failed.
+    pub fn failed(error: impl ToString) -> Self {
+        Self {
+            reloaded_at: SystemTime::now(),
+            changes: vec![],
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// Log the changes and remember them for `SHOW RELOAD`.
+    pub fn record(self) {
+        if let Some(ref error) = self.error {
+            warn!("configuration reload failed: {}", error);
+        } else if self.changes.is_empty() {
+            info!("configuration reloaded, nothing changed");
+        }
+
+        for change in &self.changes {
+            let old = change.old.as_deref().unwrap_or("<none>");
+            let new = change.new.as_deref().unwrap_or("<none>");
+            if change.restart {
+                warn!(
+                    "\"{}\" changed from {} to {}, restart required",
+                    change.path, old, new
+                );
+            } else {
+                info!("\"{}\" changed from {} to {}", change.path, old, new);
+            }
+        }
+
+        *LAST.lock() = Some(self);
+    }
+}
+
+/// Last reload, if there was one.
+pub fn last() -> Option<Report> {
+    LAST.lock().clone()
+}
+
+/// Configuration and users, as `{"config": ..., "users": ...}`.
+pub fn to_json(config: &ConfigAndUsers) -> Value {
+    json!({
+        "config": serde_json::to_value(&config.config).unwrap_or_default(),
+        "users": serde_json::to_value(&config.users).unwrap_or_default(),
+    })
+}
+
+/// Settings that are different, from [`to_json`] output.
+pub fn changes(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = vec![];
+    for part in ["config", "users"] {
+        diff(
+            "",
+            old.get(part).unwrap_or(&Value::Null),
+            new.get(part).unwrap_or(&Value::Null),
+            &mut changes,
+        );
+    }
+    changes
+}
+
+/// Replace passwords and tokens, so the configuration can be shown.
+pub fn mask(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if secret(key) && !value.is_null() {
+                    *value = Value::String(MASK.into());
+                } else {
+                    mask(value);
+                }
+            }
+        }
+        Value::Array(list) => list.iter_mut().for_each(mask),
+        _ => (),
+    }
+}
+
+fn secret(name: &str) -> bool {
+    (name.contains("password") || name.contains("token")) && !name.ends_with("_file")
+}
+
+fn diff(path: &str, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                diff(
+                    &join(path, key),
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    changes,
+                );
+            }
+        }
+
+        (Value::Array(old_list), Value::Array(new_list)) if old != new => {
+            match (keyed(path, old_list), keyed(path, new_list)) {
+                (Some(old), Some(new)) => {
+                    let keys = old.keys().chain(new.keys()).collect::<BTreeSet<_>>();
+                    for key in keys {
+                        diff(
+                            &format!("{}[{}]", path, key),
+                            old.get(key).copied().unwrap_or(&Value::Null),
+                            new.get(key).copied().unwrap_or(&Value::Null),
+                            changes,
+                        );
+                    }
+                }
+                _ => changes.push(change(path, old, new)),
+            }
+        }
+
+        _ if old != new => changes.push(change(path, old, new)),
+
+        _ => (),
+    }
+}
+
+/// Entries of lists that have a name, like databases and users,
+/// so they're compared by name and not position.
+fn keyed<'a>(path: &str, list: &'a [Value]) -> Option<BTreeMap<String, &'a Value>> {
+    let field = |value: &Value, name: &str| render(value.get(name)?);
+
+    list.iter()
+        .map(|value| {
+            let key = match path {
+                "databases" => format!(
+                    "{}/{}/{}/{}:{}",
+                    field(value, "name")?,
+                    field(value, "shard").unwrap_or_default(),
+                    field(value, "role").unwrap_or_default(),
+                    field(value, "host")?,
+                    field(value, "port").unwrap_or_default(),
+                ),
+                "users" => format!("{}@{}", field(value, "name")?, field(value, "database")?),
+                "sharded_tables" => format!(
+                    "{}.{}.{}",
+                    field(value, "database")?,
+                    field(value, "name").unwrap_or("*".into()),
+                    field(value, "column")?,
+                ),
+                "plugins" => field(value, "name")?,
+                _ => return None,
+            };
+            Some((key, value))
+        })
+        .collect()
+}
+
+fn change(path: &str, old: &Value, new: &Value) -> Change {
+    Change {
+        path: path.to_owned(),
+        old: render(old),
+        new: render(new),
+        restart: RESTART
+            .iter()
+            .any(|setting| path == *setting || path.starts_with(&format!("{}[", setting))),
+    }
+}
+
+fn render(value: &Value) -> Option<String> {
+    let value = match value {
+        Value::Null => return None,
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+
+    if value.len() > MAX_VALUE {
+        let end = (0..=MAX_VALUE)
+            .rev()
+            .find(|end| value.is_char_boundary(*end))
+            .unwrap_or(0);
+        Some(format!("{}...", &value[..end]))
+    } else {
+        Some(value)
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Database, User};
+
+    #[test]
+    fn test_report() {
+        let mut old = ConfigAndUsers::default();
+        old.config.databases.push(Database {
+            name: "prod".into(),
+            host: "127.0.0.1".into(),
+            ..Default::default()
+        });
+        old.users.users.push(User {
+            name: "alice".into(),
+            database: "prod".into(),
+            password: Some("hunter2".into()),
+            ..Default::default()
+        });
+
+        let mut new = old.clone();
+        new.config.general.default_pool_size += 5;
+        new.config.general.port += 1;
+        new.users.users[0].password = Some("hunter3".into());
+        new.users.users.push(User {
+            name: "bob".into(),
+            database: "prod".into(),
+            password: Some("hunter4".into()),
+            ..Default::default()
+        });
+        new.config.databases.insert(
+            0,
+            Database {
+                name: "staging".into(),
+                host: "127.0.0.1".into(),
+                ..Default::default()
+            },
+        );
+
+        let report = Report::new(&old, &new);
+        let change = |path: &str| {
+            report
+                .changes
+                .iter()
+                .find(|change| change.path == path)
+                .cloned()
+                .unwrap()
+        };
+
+        let pool_size = change("general.default_pool_size");
+        assert!(!pool_size.restart);
+        assert_eq!(
+            pool_size.new,
+            Some(new.config.general.default_pool_size.to_string())
+        );
+        assert!(change("general.port").restart);
+
+        // Passwords are masked before comparing, so they never show up.
+        assert!(!report
+            .changes
+            .iter()
+            .any(|change| change.path.starts_with("users[alice@prod]")));
+        let bob = change("users[bob@prod]");
+        assert!(bob.new.unwrap().contains("********"));
+        assert!(!report
+            .changes
+            .iter()
+            .filter_map(|change| change.new.as_ref())
+            .any(|value| value.contains("hunter")));
+
+        // Moving the prod database in the list doesn't change it.
+        assert!(!report
+            .changes
+            .iter()
+            .any(|change| change.path.starts_with("databases[prod")));
+        let staging = report
+            .changes
+            .iter()
+            .find(|change| change.path.starts_with("databases[staging"))
+            .unwrap();
+        assert!(staging.old.is_none());
+        assert!(staging.new.is_some());
+
+        assert!(Report::new(&old, &old).changes.is_empty());
+
+        let mut old = to_json(&old);
+        mask(&mut old);
+        assert_eq!(old["users"]["users"][0]["password"], MASK);
+    }
+}
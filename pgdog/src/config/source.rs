@@ -0,0 +1,299 @@
+//! Configuration fetched from a server instead of read from a file.
+//!
+//! `--config` and `--users` can be URLs:
+//!
+//! * `http://host:port/path/pgdog.toml`, fetched with GET,
+//! * `consul://host:8500/pgdog/pgdog.toml`, the `pgdog/pgdog.toml` key in Consul,
+//!   using the `CONSUL_HTTP_TOKEN` environment variable if it's set,
+//! * `etcd://host:2379/pgdog/pgdog.toml`, the `pgdog/pgdog.toml` key in etcd,
+//!   with its JSON API.
+//!
+//! `https://`, `consuls://` and `etcds://` use TLS, verifying the server
+//! certificate against the system CAs.
+//!
+//! The format is detected from the extension, like for files. Remote configuration
+//! is fetched on startup and then polled every `config_poll_interval`; etcd and
+//! Consul watches aren't used. When it changes, it's reloaded, same as on SIGHUP. If fetching fails, the last configuration
+//! fetched is kept. Included files aren't supported.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use hyper::{body::Bytes, header::HeaderName, Method};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::{runtime::Builder, spawn, time::sleep};
+use tracing::{error, info, warn};
+use url::Url;
+
+use super::{config, ConfigAndUsers, Error};
+use crate::backend::databases::reload;
+use crate::net::http;
+
+/// Last configuration fetched, by URL.
+static FETCHED: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Where configuration is fetched from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Http { url: String },
+    Consul { url: String },
+    Etcd { url: String, key: String },
+}
+
+impl Source {
+    /// Remote source, if the path is a URL.
+    pub fn new(path: &Path) -> Result<Option<Self>, Error> {
+        let Some(path) = path.to_str().filter(|path| path.contains("://")) else {
+            return Ok(None);
+        };
+
+        let url = Url::parse(path)?;
+        let host = url.host_str().unwrap_or("127.0.0.1");
+        let key = url.path().trim_start_matches('/');
+
+        let source = match url.scheme() {
+            "http" | "https" => Self::Http {
+                url: path.to_owned(),
+            },
+            scheme @ ("consul" | "consuls") => Self::Consul {
+                url: format!(
+                    "{}://{}:{}/v1/kv/{}?raw",
+                    http_scheme(scheme),
+                    host,
+                    url.port().unwrap_or(8500),
+                    key
+                ),
+            },
+            scheme @ ("etcd" | "etcds") => Self::Etcd {
+                url: format!(
+                    "{}://{}:{}/v3/kv/range",
+                    http_scheme(scheme),
+                    host,
+                    url.port().unwrap_or(2379)
+                ),
+                key: key.to_owned(),
+            },
+            scheme => {
+                return Err(Error::Remote(format!(
+                    "\"{}\" isn't supported, use http(s), consul(s) or etcd(s)",
+                    scheme
+                )))
+            }
+        };
+
+        Ok(Some(source))
+    }
+
+    /// Fetch the configuration.
+    pub async fn fetch(&self) -> Result<String, Error> {
+        let remote = |err: http::Error| Error::Remote(err.to_string());
+
+        match self {
+            Self::Http { url } => {
+                let body = http::request(Method::GET, url, &[], Bytes::new())
+                    .await
+                    .map_err(remote)?;
+                Ok(String::from_utf8_lossy(&body).to_string())
+            }
+
+            Self::Consul { url } => {
+                let token = std::env::var("CONSUL_HTTP_TOKEN").unwrap_or_default();
+                let header = HeaderName::from_static("x-consul-token");
+                let headers = if token.is_empty() {
+                    vec![]
+                } else {
+                    vec![(header, token.as_str())]
+                };
+                let body = http::request(Method::GET, url, &headers, Bytes::new())
+                    .await
+                    .map_err(remote)?;
+                Ok(String::from_utf8_lossy(&body).to_string())
+            }
+
+            Self::Etcd { url, key } => {
+                let request = json!({"key": BASE64_STANDARD.encode(key)});
+                let body = http::request(Method::POST, url, &[], request.to_string())
+                    .await
+                    .map_err(remote)?;
+                etcd_value(&body, key)
+            }
+        }
+    }
+}
+
+/// `https` for schemes ending with `s`, like `etcds`.
+fn http_scheme(scheme: &str) -> &'static str {
+    if scheme.ends_with('s') {
+        "https"
+    } else {
+        "http"
+    }
+}
+
+/// Value of the key in an etcd range response.
+fn etcd_value(body: &[u8], key: &str) -> Result<String, Error> {
+    let response: Value = serde_json::from_slice(body)?;
+    let value = response["kvs"][0]["value"]
+        .as_str()
+        .ok_or_else(|| Error::Remote(format!("etcd key \"{}\" doesn't exist", key)))?;
+    let value = BASE64_STANDARD
+        .decode(value)
+        .map_err(|err| Error::Remote(err.to_string()))?;
+
+    Ok(String::from_utf8_lossy(&value).to_string())
+}
+
+/// Read the file, or the configuration last fetched from the URL.
+pub fn read(path: &Path) -> std::io::Result<String> {
+    read_fetched(path, &FETCHED.lock())
+}
+
+/// Read the file, or the configuration fetched from the URL.
+fn read_fetched(path: &Path, fetched: &HashMap<String, String>) -> std::io::Result<String> {
+    if matches!(Source::new(path), Ok(Some(_))) {
+        fetched
+            .get(path.to_string_lossy().as_ref())
+            .cloned()
+            .ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    } else {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Load configuration the way a reload would, fetching remote
+/// configuration again. What's been fetched before isn't changed,
+/// so the next poll still notices changes and reloads.
+pub async fn load(config_path: &PathBuf, users_path: &PathBuf) -> Result<ConfigAndUsers, Error> {
+    let mut fetched = HashMap::new();
+    for (path, source) in sources(&[config_path, users_path])? {
+        let contents = source.fetch().await?;
+        fetched.insert(path, contents);
+    }
+
+    ConfigAndUsers::load_with(config_path, users_path, |path| read_fetched(path, &fetched))
+}
+
+/// Fetch remote configuration before loading it. Call before starting Tokio.
+pub fn fetch(paths: &[&PathBuf]) -> Result<(), Error> {
+    let sources = sources(paths)?;
+    if sources.is_empty() {
+        return Ok(());
+    }
+
+    let runtime = Builder::new_current_thread().enable_all().build()?;
+    for (path, source) in sources {
+        let contents = runtime.block_on(source.fetch())?;
+        info!("fetched \"{}\"", path);
+        FETCHED.lock().insert(path, contents);
+    }
+
+    Ok(())
+}
+
+/// Fetch remote configuration periodically, reloading it when it changes.
+pub fn watch() {
+    let current = config();
+    let sources = match sources(&[&current.config_path, &current.users_path]) {
+        Ok(sources) if !sources.is_empty() => sources,
+        _ => return,
+    };
+
+    spawn(async move {
+        loop {
+            let interval = config().config.general.config_poll_interval;
+            sleep(Duration::from_millis(interval)).await;
+
+            let mut changed = false;
+            for (path, source) in &sources {
+                match source.fetch().await {
+                    Ok(contents) => {
+                        let previous = FETCHED.lock().insert(path.clone(), contents.clone());
+                        changed |= previous.as_ref() != Some(&contents);
+                    }
+                    Err(err) => warn!("failed to fetch \"{}\": {}", path, err),
+                }
+            }
+
+            if changed {
+                info!("remote configuration changed, reloading");
+                if let Err(err) = reload() {
+                    error!("configuration reload error: {}", err);
+                }
+            }
+        }
+    });
+}
+
+fn sources(paths: &[&PathBuf]) -> Result<Vec<(String, Source)>, Error> {
+    let mut sources = vec![];
+    for path in paths {
+        if let Some(source) = Source::new(path)? {
+            sources.push((path.to_string_lossy().to_string(), source));
+        }
+    }
+    Ok(sources)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_source() {
+        assert_eq!(Source::new(Path::new("pgdog.toml")).unwrap(), None);
+        assert_eq!(
+            Source::new(Path::new("consul://consul:8500/pgdog/pgdog.toml")).unwrap(),
+            Some(Source::Consul {
+                url: "http://consul:8500/v1/kv/pgdog/pgdog.toml?raw".into()
+            })
+        );
+        assert_eq!(
+            Source::new(Path::new("etcd://etcd/pgdog/users.yaml")).unwrap(),
+            Some(Source::Etcd {
+                url: "http://etcd:2379/v3/kv/range".into(),
+                key: "pgdog/users.yaml".into(),
+            })
+        );
+        assert_eq!(
+            Source::new(Path::new("etcds://etcd:2380/pgdog/users.yaml")).unwrap(),
+            Some(Source::Etcd {
+                url: "https://etcd:2380/v3/kv/range".into(),
+                key: "pgdog/users.yaml".into(),
+            })
+        );
+        assert_eq!(
+            Source::new(Path::new("consuls://consul/pgdog.toml")).unwrap(),
+            Some(Source::Consul {
+                url: "https://consul:8500/v1/kv/pgdog.toml?raw".into()
+            })
+        );
+        assert!(Source::new(Path::new("zookeeper://zk/pgdog")).is_err());
+    }
+
+    #[test]
+    fn test_read_fetched() {
+        let fetched = HashMap::from([(
+            "http://127.0.0.1:8080/pgdog.toml".to_string(),
+            "[general]\n".to_string(),
+        )]);
+        assert_eq!(
+            read_fetched(Path::new("http://127.0.0.1:8080/pgdog.toml"), &fetched).unwrap(),
+            "[general]\n"
+        );
+        assert!(read_fetched(Path::new("http://127.0.0.1:8080/users.toml"), &fetched).is_err());
+    }
+
+    #[test]
+    fn test_etcd_value() {
+        let body = json!({"kvs": [{"key": "cGdkb2c=", "value": BASE64_STANDARD.encode("[general]\nport = 6433\n")}]});
+        assert_eq!(
+            etcd_value(body.to_string().as_bytes(), "pgdog").unwrap(),
+            "[general]\nport = 6433\n"
+        );
+        assert!(etcd_value(b"{\"count\": 0}", "pgdog").is_err());
+    }
+}
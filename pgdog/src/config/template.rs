@@ -0,0 +1,158 @@
+//! Database settings shared by many `[[databases]]` entries.
+//!
+//! ```toml
+//! [database_defaults]
+//! pool_size = 20
+//! server_tls_mode = "verify_full"
+//!
+//! [database_templates.replica]
+//! role = "replica"
+//! pool_size = 50
+//!
+//! [[databases]]
+//! name = "prod"
+//! host = "10.0.0.1"
+//!
+//! [[databases]]
+//! name = "prod"
+//! host = "10.0.0.2"
+//! template = "replica"
+//! ```
+//!
+//! Databases use the settings they set themselves first, then the ones from
+//! their template, then `database_defaults`. Databases in included files
+//! use the templates from `pgdog.toml`.
+
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+
+use super::{Config, Error, Format};
+
+/// Database settings, by name.
+pub type Template = Map<String, Value>;
+
+/// Database defaults and templates.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Templates {
+    #[serde(default)]
+    database_defaults: Template,
+    #[serde(default)]
+    database_templates: BTreeMap<String, Template>,
+}
+
+impl Templates {
+    /// Templates set in the configuration.
+    pub fn new(config: &Config) -> Self {
+        Self {
+            database_defaults: config.database_defaults.clone(),
+            database_templates: config.database_templates.clone(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.database_defaults.is_empty() && self.database_templates.is_empty()
+    }
+
+    /// Deserialize file contents, filling in database settings from templates.
+    pub fn parse<T: DeserializeOwned>(&self, format: Format, source: &str) -> Result<T, Error> {
+        // Without templates, errors have line numbers.
+        if self.is_empty() {
+            return format.parse(source);
+        }
+
+        let mut value: Value = format.parse(source)?;
+        self.apply(&mut value)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Fill in settings of every database in `databases`.
+    fn apply(&self, value: &mut Value) -> Result<(), Error> {
+        let Some(databases) = value.get_mut("databases").and_then(Value::as_array_mut) else {
+            return Ok(());
+        };
+
+        for database in databases.iter_mut().filter_map(Value::as_object_mut) {
+            if let Some(name) = database.get("template") {
+                let name = name
+                    .as_str()
+                    .ok_or_else(|| Error::Template("template must be a name".into()))?;
+                let template = self.database_templates.get(name).ok_or_else(|| {
+                    Error::Template(format!("database template \"{}\" doesn't exist", name))
+                })?;
+                inherit(database, template);
+            }
+
+            inherit(database, &self.database_defaults);
+        }
+
+        Ok(())
+    }
+}
+
+fn inherit(database: &mut Template, template: &Template) {
+    for (setting, value) in template {
+        if !database.contains_key(setting) {
+            database.insert(setting.clone(), value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::Role;
+
+    #[test]
+    fn test_templates() {
+        let source = r#"
+[database_defaults]
+pool_size = 20
+port = 6432
+
+[database_templates.replica]
+role = "replica"
+pool_size = 50
+
+[[databases]]
+name = "prod"
+host = "10.0.0.1"
+
+[[databases]]
+name = "prod"
+host = "10.0.0.2"
+template = "replica"
+
+[[databases]]
+name = "prod"
+host = "10.0.0.3"
+template = "replica"
+pool_size = 5
+port = 5432
+"#;
+        let templates: Templates = Format::Toml.parse(source).unwrap();
+        let config: Config = templates.parse(Format::Toml, source).unwrap();
+
+        let settings = config
+            .databases
+            .iter()
+            .map(|database| (database.role, database.pool_size, database.port))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            settings,
+            vec![
+                (Role::Primary, Some(20), 6432),
+                (Role::Replica, Some(50), 6432),
+                (Role::Replica, Some(5), 5432),
+            ]
+        );
+        assert_eq!(config.databases[1].template.as_deref(), Some("replica"));
+
+        let source = "[[databases]]\nname = \"prod\"\nhost = \"10.0.0.1\"\ntemplate = \"big\"\n";
+        let err = Templates::new(&config)
+            .parse::<Config>(Format::Toml, source)
+            .unwrap_err();
+        assert!(matches!(err, Error::Template(_)));
+    }
+}
@@ -70,7 +70,10 @@ impl ConfigAndUsers {
             .collect::<Vec<_>>();
 
         Ok(Self {
-            users: Users { users },
+            users: Users {
+                users,
+                ..Default::default()
+            },
             config: Config {
                 databases,
                 ..Default::default()
@@ -0,0 +1,253 @@
+//! Configuration checks run by `pgdog check-config`.
+//!
+//! Unlike [`Config::check`](super::Config::check), which only warns so PgDog
+//! can still start, these report everything that's likely a mistake.
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::time::Duration;
+
+use futures::future::join_all;
+use tokio::{net::TcpStream, time::timeout};
+
+use super::{ConfigAndUsers, Role};
+
+/// How bad a problem is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Works, but probably isn't intended.
+    Warning,
+    /// Won't work.
+    Error,
+}
+
+/// Problem found in the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Problem {
+    fn error(message: String) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(message: String) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "warning: {}", self.message),
+            Severity::Error => write!(f, "error: {}", self.message),
+        }
+    }
+}
+
+/// Check the configuration.
+pub fn validate(config: &ConfigAndUsers) -> Vec<Problem> {
+    let mut problems = vec![];
+    let databases = config.config.databases();
+
+    let mut names = databases.keys().collect::<Vec<_>>();
+    names.sort();
+    for name in names {
+        for (shard, servers) in databases[name].iter().enumerate() {
+            let primaries = servers
+                .iter()
+                .filter(|server| server.role == Role::Primary)
+                .count();
+
+            if servers.is_empty() {
+                problems.push(Problem::error(format!(
+                    "database \"{}\" has no servers for shard {}",
+                    name, shard
+                )));
+            } else if primaries > 1 {
+                problems.push(Problem::error(format!(
+                    "database \"{}\" has {} primaries for shard {}",
+                    name, primaries, shard
+                )));
+            } else if primaries == 0 {
+                problems.push(Problem::warning(format!(
+                    "database \"{}\" has no primary for shard {}, so it can't be written to",
+                    name, shard
+                )));
+            }
+
+            let mut addresses = HashSet::new();
+            for server in servers {
+                if !addresses.insert((server.host.as_str(), server.port)) {
+                    problems.push(Problem::error(format!(
+                        "database \"{}\" has {}:{} more than once for shard {}",
+                        name, server.host, server.port, shard
+                    )));
+                }
+            }
+        }
+    }
+
+    for table in &config.config.sharded_tables {
+        let name = table.name.as_deref().unwrap_or("*");
+        let Some(shards) = databases.get(&table.database).map(|shards| shards.len()) else {
+            problems.push(Problem::error(format!(
+                "sharded table \"{}\" is in database \"{}\", which isn't configured",
+                name, table.database
+            )));
+            continue;
+        };
+
+        let mut mapping = table.mapping.iter().collect::<Vec<_>>();
+        mapping.sort();
+        for (value, shard) in mapping {
+            if *shard >= shards {
+                problems.push(Problem::error(format!(
+                    "sharding key \"{}\" of table \"{}\" is mapped to shard {}, but database \"{}\" has {} shards",
+                    value, name, shard, table.database, shards
+                )));
+            }
+        }
+    }
+
+    for rule in &config.config.mirroring {
+        if !databases.contains_key(&rule.database) {
+            problems.push(Problem::error(format!(
+                "mirroring to database \"{}\", which isn't configured",
+                rule.database
+            )));
+        }
+    }
+
+    for user in &config.users.users {
+        if !databases.contains_key(&user.database) {
+            problems.push(Problem::error(format!(
+                "user \"{}\" is for database \"{}\", which isn't configured",
+                user.name, user.database
+            )));
+        }
+    }
+
+    if !config.config.general.passthrough_auth() {
+        let mut names = databases.keys().collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            if !config.users.users.iter().any(|user| &user.database == name) {
+                problems.push(Problem::warning(format!(
+                    "database \"{}\" has no users, so clients can't connect to it",
+                    name
+                )));
+            }
+        }
+    }
+
+    if config.config.admin.http_port.is_some() && config.config.admin.http_token.is_none() {
+        problems.push(Problem::error(
+            "admin \"http_port\" is set without \"http_token\", so the HTTP API won't start".into(),
+        ));
+    }
+
+    problems
+}
+
+/// Check that every server accepts connections.
+pub async fn reachable(config: &ConfigAndUsers) -> Vec<Problem> {
+    let connect_timeout = Duration::from_millis(config.config.general.connect_timeout);
+
+    let mut addresses = config
+        .config
+        .databases
+        .iter()
+        .map(|database| (database.host.clone(), database.port))
+        .collect::<Vec<_>>();
+    addresses.sort();
+    addresses.dedup();
+
+    join_all(addresses.into_iter().map(|(host, port)| async move {
+        match timeout(connect_timeout, TcpStream::connect((host.as_str(), port))).await {
+            Ok(Ok(_)) => None,
+            Ok(Err(err)) => Some(Problem::error(format!(
+                "can't connect to {}:{}: {}",
+                host, port, err
+            ))),
+            Err(_) => Some(Problem::error(format!(
+                "can't connect to {}:{}: timeout",
+                host, port
+            ))),
+        }
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Database, ShardedTable, User};
+
+    #[test]
+    fn test_validate() {
+        let mut config = ConfigAndUsers::default();
+        let database = |shard, role, host: &str| Database {
+            name: "prod".into(),
+            host: host.into(),
+            shard,
+            role,
+            ..Default::default()
+        };
+        config.config.databases = vec![
+            database(0, Role::Primary, "10.0.0.1"),
+            database(0, Role::Replica, "10.0.0.2"),
+            database(2, Role::Primary, "10.0.0.3"),
+            database(2, Role::Primary, "10.0.0.4"),
+        ];
+        config.config.sharded_tables.push(ShardedTable {
+            database: "staging".into(),
+            ..Default::default()
+        });
+        config.users.users.push(User {
+            name: "alice".into(),
+            database: "prod".into(),
+            ..Default::default()
+        });
+        config.users.users.push(User {
+            name: "bob".into(),
+            database: "dev".into(),
+            ..Default::default()
+        });
+
+        let problems = validate(&config)
+            .into_iter()
+            .map(|problem| problem.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            problems,
+            vec![
+                "error: database \"prod\" has no servers for shard 1",
+                "error: database \"prod\" has 2 primaries for shard 2",
+                "error: sharded table \"*\" is in database \"staging\", which isn't configured",
+                "error: user \"bob\" is for database \"dev\", which isn't configured",
+            ]
+        );
+
+        config.config.databases.truncate(2);
+        config.config.sharded_tables.clear();
+        config.users.users.truncate(1);
+        assert!(validate(&config).is_empty());
+
+        config.config.admin.http_port = Some(8080);
+        assert_eq!(validate(&config).len(), 1);
+        config.config.admin.http_token = Some("secret".into());
+        assert!(validate(&config).is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+//! Reload configuration when its files change on disk.
+//!
+//! With `watch_config = true`, pgdog.toml, users.toml, the files they include
+//! and every `password_file` are watched. Changes are debounced for
+//! `config_watch_interval`, so editors and Kubernetes ConfigMap updates,
+//! which replace files in several steps, are reloaded once. Reloading is the
+//! same as on SIGHUP.
+//!
+//! The directories of the files are watched, not the files themselves, so
+//! files replaced by a rename, or through the `..data` symlink Kubernetes
+//! swaps when a ConfigMap changes, are seen too. Files matching an `include`
+//! pattern are picked up when they're added.
+
+use std::collections::HashSet;
+use std::fs::metadata;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use tokio::{spawn, sync::mpsc::unbounded_channel};
+use tracing::{debug, error, info};
+
+use super::{config, include, source::Source, ConfigAndUsers};
+use crate::backend::databases::reload;
+
+/// When the file was last changed, and its size. `None` if it can't be read.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+fn stamps(files: &[PathBuf]) -> Vec<(PathBuf, Stamp)> {
+    files
+        .iter()
+        .map(|path| (path.clone(), stamp(path)))
+        .collect()
+}
+
+/// Local files the configuration is loaded from. Remote configuration is skipped.
+fn files(current: &ConfigAndUsers) -> Vec<PathBuf> {
+    let mut files = vec![];
+
+    for (path, patterns) in [
+        (&current.config_path, &current.config.include),
+        (&current.users_path, &current.users.include),
+    ] {
+        if matches!(Source::new(path), Ok(None)) {
+            files.push(path.clone());
+            files.extend(include::included(path, patterns));
+        }
+    }
+
+    files.extend(
+        current
+            .config
+            .databases
+            .iter()
+            .filter_map(|database| database.password_file.clone()),
+    );
+    files.extend(current.config.admin.password_file.clone());
+    files.extend(
+        current
+            .users
+            .users
+            .iter()
+            .filter_map(|user| user.password_file.clone()),
+    );
+
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// Directories to watch: the ones with the files, and the ones `include` patterns look in.
+fn dirs(current: &ConfigAndUsers, files: &[PathBuf]) -> HashSet<PathBuf> {
+    let mut dirs = files
+        .iter()
+        .filter_map(|file| file.parent().map(Path::to_path_buf))
+        .collect::<Vec<_>>();
+
+    for (path, patterns) in [
+        (&current.config_path, &current.config.include),
+        (&current.users_path, &current.users.include),
+    ] {
+        if matches!(Source::new(path), Ok(None)) {
+            dirs.extend(include::dirs(path, patterns));
+        }
+    }
+
+    dirs.into_iter()
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                dir
+            }
+        })
+        .collect()
+}
+
+/// Start watching directories that aren't watched yet.
+fn watch_dirs(
+    debouncer: &mut Debouncer<RecommendedWatcher>,
+    dirs: HashSet<PathBuf>,
+    watched: &mut HashSet<PathBuf>,
+) {
+    for dir in dirs {
+        if watched.contains(&dir) {
+            continue;
+        }
+
+        match debouncer.watcher().watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                debug!("watching \"{}\" for changes", dir.display());
+                watched.insert(dir);
+            }
+            Err(err) => error!("can't watch \"{}\": {}", dir.display(), err),
+        }
+    }
+}
+
+/// Watch configuration files, if `watch_config` is enabled.
+pub fn watch() {
+    let current = config();
+    if !current.config.general.watch_config {
+        return;
+    }
+
+    let watching = files(&current);
+    if watching.is_empty() {
+        return;
+    }
+
+    let (tx, mut rx) = unbounded_channel();
+    let interval = Duration::from_millis(current.config.general.config_watch_interval);
+    let debouncer = new_debouncer(interval, move |result: DebounceEventResult| match result {
+        Ok(_) => {
+            let _ = tx.send(());
+        }
+        Err(err) => error!("configuration watch error: {}", err),
+    });
+    let mut debouncer = match debouncer {
+        Ok(debouncer) => debouncer,
+        Err(err) => {
+            error!("can't watch configuration files: {}", err);
+            return;
+        }
+    };
+
+    let mut watched = HashSet::new();
+    watch_dirs(&mut debouncer, dirs(&current, &watching), &mut watched);
+
+    info!(
+        "watching {} for changes",
+        watching
+            .iter()
+            .map(|path| format!("\"{}\"", path.display()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    spawn(async move {
+        let mut loaded = stamps(&watching);
+
+        while rx.recv().await.is_some() {
+            // Something else in the directories changed.
+            if stamps(&files(&config())) == loaded {
+                continue;
+            }
+
+            info!("configuration files changed, reloading");
+            if let Err(err) = reload() {
+                error!("configuration reload error: {}", err);
+            }
+
+            // The files may have changed with the configuration.
+            let current = config();
+            let watching = files(&current);
+            watch_dirs(&mut debouncer, dirs(&current, &watching), &mut watched);
+            loaded = stamps(&watching);
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stamp() {
+        let path = std::env::temp_dir().join(format!("pgdog_watch_{}.toml", std::process::id()));
+        assert_eq!(stamp(&path), None);
+
+        std::fs::write(&path, "[general]\n").unwrap();
+        let before = stamp(&path).unwrap();
+        std::fs::write(&path, "[general]\nport = 6433\n").unwrap();
+        let after = stamp(&path).unwrap();
+        assert_ne!(before, after);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = std::env::temp_dir().join(format!("pgdog_watch_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("conf.d")).unwrap();
+        std::fs::write(dir.join("conf.d/a.toml"), "").unwrap();
+
+        let mut current = ConfigAndUsers {
+            config_path: dir.join("pgdog.toml"),
+            users_path: PathBuf::from("consul://consul/pgdog/users.toml"),
+            ..Default::default()
+        };
+        current.config.include = vec!["conf.d/*.toml".into()];
+        current.config.admin.password_file = Some(PathBuf::from("/run/secrets/admin"));
+
+        assert_eq!(
+            files(&current),
+            vec![
+                PathBuf::from("/run/secrets/admin"),
+                dir.join("conf.d/a.toml"),
+                dir.join("pgdog.toml"),
+            ]
+        );
+        assert_eq!(
+            dirs(&current, &files(&current)),
+            HashSet::from([
+                PathBuf::from("/run/secrets"),
+                dir.join("conf.d"),
+                dir.clone()
+            ])
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+//! Lifecycle events for external automation.
+//!
+//! Client connections, pool bans, failovers and config reloads are sent
+//! as JSON to a webhook, in batches, or to a Unix socket, one object per line.
+//! Events are queued in memory while the receiver is unavailable and delivery
+//! is retried with exponential backoff. If the queue is full, the oldest events are dropped.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Local;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::{
+    io::AsyncWriteExt,
+    net::UnixStream,
+    spawn,
+    sync::Notify,
+    time::{sleep, timeout},
+};
+use tracing::warn;
+
+use crate::config::{config, EventKind};
+use crate::net::http::{post, Error};
+
+/// Events sent in one webhook request.
+const BATCH: usize = 100;
+
+/// Longest wait between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Time allowed to deliver one batch.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+static QUEUE: Lazy<Mutex<VecDeque<String>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+static READY: Lazy<Notify> = Lazy::new(Notify::new);
+
+/// Something happened.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    ClientConnected {
+        client: SocketAddr,
+        user: String,
+        database: String,
+    },
+    ClientDisconnected {
+        client: SocketAddr,
+        user: String,
+        database: String,
+    },
+    PoolBanned {
+        pool: String,
+        reason: String,
+    },
+    PoolUnbanned {
+        pool: String,
+    },
+    Failover {
+        primary: String,
+        promoted: Option<String>,
+        reason: String,
+    },
+    Reload {
+        /// Reloaded database, or all of them.
+        database: Option<String>,
+    },
+}
+
+impl Event {
+    fn kind(&self) -> EventKind {
+        match self {
+            Self::ClientConnected { .. } => EventKind::ClientConnected,
+            Self::ClientDisconnected { .. } => EventKind::ClientDisconnected,
+            Self::PoolBanned { .. } => EventKind::PoolBanned,
+            Self::PoolUnbanned { .. } => EventKind::PoolUnbanned,
+            Self::Failover { .. } => EventKind::Failover,
+            Self::Reload { .. } => EventKind::Reload,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Queue the event, if a receiver is configured and wants it.
+pub fn emit(event: Event) {
+    let config = config();
+    let events = &config.config.events;
+
+    if events.webhook.is_none() && events.socket.is_none() {
+        return;
+    }
+    if !events.kinds.is_empty() && !events.kinds.contains(&event.kind()) {
+        return;
+    }
+
+    let record = Record {
+        timestamp: Local::now().format("%Y-%m-%d %H:%M:%S%.3f %Z").to_string(),
+        event: &event,
+    };
+    let Ok(line) = serde_json::to_string(&record) else {
+        return;
+    };
+
+    let mut queue = QUEUE.lock();
+    while queue.len() >= events.queue_size.max(1) {
+        queue.pop_front();
+    }
+    queue.push_back(line);
+    READY.notify_one();
+}
+
+/// Deliver events in the background.
+pub fn start() {
+    spawn(async move {
+        let mut backoff = None;
+
+        loop {
+            let batch = QUEUE.lock().iter().take(BATCH).cloned().collect::<Vec<_>>();
+            if batch.is_empty() {
+                READY.notified().await;
+                continue;
+            }
+
+            match timeout(TIMEOUT, deliver(&batch)).await {
+                Ok(Ok(())) => {
+                    let mut queue = QUEUE.lock();
+                    // Oldest events could've been dropped while we were sending.
+                    let delivered = queue
+                        .iter()
+                        .zip(batch.iter())
+                        .take_while(|(queued, sent)| queued == sent)
+                        .count();
+                    queue.drain(..delivered);
+                    backoff = None;
+                }
+
+                result => {
+                    let err = match result {
+                        Ok(Err(err)) => err,
+                        _ => Error::Timeout,
+                    };
+                    let wait = next_backoff(backoff);
+                    warn!(
+                        "event delivery error: {}, retrying in {}ms",
+                        err,
+                        wait.as_millis()
+                    );
+                    backoff = Some(wait);
+                    sleep(wait).await;
+                }
+            }
+        }
+    });
+}
+
+/// Double the wait after each failure, up to a limit.
+fn next_backoff(backoff: Option<Duration>) -> Duration {
+    match backoff {
+        None => Duration::from_millis(config().config.events.retry_backoff),
+        Some(backoff) => (backoff * 2).min(MAX_BACKOFF),
+    }
+}
+
+async fn deliver(batch: &[String]) -> Result<(), Error> {
+    let events = config().config.events.clone();
+
+    if let Some(ref webhook) = events.webhook {
+        let body = format!("[{}]", batch.join(","));
+        post(webhook, "application/json", body).await?;
+    }
+
+    if let Some(ref socket) = events.socket {
+        send(socket, batch).await?;
+    }
+
+    Ok(())
+}
+
+async fn send(socket: &Path, batch: &[String]) -> Result<(), Error> {
+    let mut stream = UnixStream::connect(socket).await?;
+    for line in batch {
+        stream.write_all(line.as_bytes()).await?;
+        stream.write_all(b"\n").await?;
+    }
+    stream.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let event = Event::PoolBanned {
+            pool: "127.0.0.1:5432".into(),
+            reason: "checkout timeout".into(),
+        };
+        let record = Record {
+            timestamp: "2025-01-01 00:00:00.000 +00:00".into(),
+            event: &event,
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"timestamp":"2025-01-01 00:00:00.000 +00:00","event":"pool_banned","pool":"127.0.0.1:5432","reason":"checkout timeout"}"#
+        );
+        assert_eq!(event.kind(), EventKind::PoolBanned);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let first = next_backoff(None);
+        assert_eq!(first, Duration::from_millis(100));
+        assert_eq!(next_backoff(Some(first)), Duration::from_millis(200));
+        assert_eq!(next_backoff(Some(Duration::from_secs(20))), MAX_BACKOFF);
+    }
+}
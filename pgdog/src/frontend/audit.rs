@@ -0,0 +1,719 @@
+//! Statement audit log in a pgAudit-compatible layout.
+//!
+//! Managed Postgres doesn't always allow installing pgAudit,
+//! so we record the same information (class, command, object, statement)
+//! for every statement going through the proxy.
+//!
+//! DDL, role and privilege changes and TRUNCATE can also be written
+//! to a separate log, with the shards they were sent to.
+//!
+//! Records are written by a background task, buffered and flushed in batches.
+//! If it falls behind by more than [`QUEUE`] lines, new ones are dropped,
+//! and how many is logged, so clients never wait on disk.
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use once_cell::sync::OnceCell;
+use pg_query::{protobuf::RawStmt, NodeEnum, NodeRef};
+use serde::Serialize;
+use tokio::{
+    fs::{remove_file, rename, File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+    spawn,
+    sync::mpsc::{channel, Sender},
+};
+use tracing::{error, warn};
+
+use super::{
+    router::parser::{Cache, Shard},
+    Buffer, Error,
+};
+use crate::config::{config, AuditClass, AuditFormat};
+use crate::net::Parameters;
+
+/// Lines waiting to be written. More are dropped.
+const QUEUE: usize = 10_000;
+
+/// Lines written at once, before flushing.
+const BATCH: usize = 1024;
+
+static WRITER: OnceCell<Sender<Line>> = OnceCell::new();
+
+/// Lines dropped because the writer fell behind, since it last said so.
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// Audit record, one per statement.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Record {
+    pub timestamp: String,
+    pub user: String,
+    pub database: String,
+    pub client: String,
+    pub audit_type: &'static str,
+    pub statement_id: usize,
+    pub substatement_id: usize,
+    pub class: String,
+    pub command: String,
+    pub object_type: String,
+    pub object_name: String,
+    pub statement: String,
+    pub parameter: &'static str,
+    /// Shards the statement was sent to, only in the DDL log.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shards: Option<String>,
+}
+
+impl Record {
+    /// Format record as a CSV line, same column order as pgAudit.
+    pub fn csv(&self) -> String {
+        let mut line = [
+            &self.timestamp,
+            &self.user,
+            &self.database,
+            &self.client,
+            self.audit_type,
+        ]
+        .iter()
+        .map(|field| quote(field))
+        .collect::<Vec<_>>();
+        line.push(self.statement_id.to_string());
+        line.push(self.substatement_id.to_string());
+        line.extend(
+            [
+                &self.class,
+                &self.command,
+                &self.object_type,
+                &self.object_name,
+                &self.statement,
+                self.parameter,
+            ]
+            .iter()
+            .map(|field| quote(field)),
+        );
+        if let Some(ref shards) = self.shards {
+            line.push(quote(shards));
+        }
+
+        line.join(",")
+    }
+}
+
+/// Audit log of a client.
+#[derive(Debug, Default)]
+pub struct Auditor {
+    statement_id: usize,
+}
+
+impl Auditor {
+    /// Record the query sent by the client, if auditing is enabled.
+    pub fn record(
+        &mut self,
+        buffer: &Buffer,
+        params: &Parameters,
+        addr: &SocketAddr,
+    ) -> Result<(), Error> {
+        let config = config();
+        let audit = &config.config.audit;
+
+        let Some(ref path) = audit.path else {
+            return Ok(());
+        };
+        let Some(query) = buffer.query()? else {
+            return Ok(());
+        };
+
+        self.statement_id += 1;
+
+        let user = params.get_default("user", "postgres");
+        let database = params.get_default("database", user);
+
+        for (class, record) in records(query.query(), self.statement_id) {
+            if !audit.classes.is_empty() && !audit.classes.contains(&class) {
+                continue;
+            }
+
+            let record = Record {
+                user: user.to_owned(),
+                database: database.to_owned(),
+                client: addr.to_string(),
+                ..record
+            };
+
+            write(path, audit.format, &record);
+        }
+
+        Ok(())
+    }
+
+    /// Record schema, role and privilege changes in the DDL log, once we know
+    /// which shards they're going to.
+    pub fn privileged(
+        &self,
+        buffer: &Buffer,
+        params: &Parameters,
+        addr: &SocketAddr,
+        shard: &Shard,
+        shards: usize,
+    ) -> Result<(), Error> {
+        let config = config();
+        let audit = &config.config.audit;
+
+        let Some(ref path) = audit.ddl_path else {
+            return Ok(());
+        };
+        let Some(query) = buffer.query()? else {
+            return Ok(());
+        };
+
+        let user = params.get_default("user", "postgres");
+        let database = params.get_default("database", user);
+
+        for (class, record) in records(query.query(), self.statement_id) {
+            if !privileged(class, &record.command) {
+                continue;
+            }
+
+            let record = Record {
+                user: user.to_owned(),
+                database: database.to_owned(),
+                client: addr.to_string(),
+                shards: Some(shard_list(shard, shards)),
+                ..record
+            };
+
+            write(path, audit.format, &record);
+        }
+
+        Ok(())
+    }
+}
+
+fn write(path: &Path, format: AuditFormat, record: &Record) {
+    let line = match format {
+        AuditFormat::Csv => record.csv(),
+        AuditFormat::Json => match serde_json::to_string(record) {
+            Ok(line) => line,
+            Err(err) => {
+                error!("audit record serialization error: {}", err);
+                return;
+            }
+        },
+    };
+
+    append(path.to_path_buf(), line + "\n", None);
+}
+
+/// Statement changes the schema, roles or privileges, or truncates tables.
+fn privileged(class: AuditClass, command: &str) -> bool {
+    matches!(class, AuditClass::Ddl | AuditClass::Role) || command.starts_with("TRUNCATE")
+}
+
+/// Shards the statement is sent to, e.g. `0,1,2`.
+fn shard_list(shard: &Shard, shards: usize) -> String {
+    let list = match shard {
+        Shard::Direct(shard) => vec![*shard],
+        Shard::Multi(list) => list.clone(),
+        Shard::All => (0..shards).collect(),
+    };
+
+    list.iter()
+        .map(|shard| shard.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Split the query into audit records, without client information.
+fn records(query: &str, statement_id: usize) -> Vec<(AuditClass, Record)> {
+    // Statements that don't parse are rejected by the server anyway.
+    let Ok(ast) = Cache::get().parse(query) else {
+        return vec![];
+    };
+
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S%.3f %Z").to_string();
+
+    ast.protobuf
+        .stmts
+        .iter()
+        .enumerate()
+        .map(|(i, stmt)| {
+            let node = stmt.stmt.as_ref().and_then(|stmt| stmt.node.as_ref());
+            let statement = statement(query, stmt);
+            let class = class(node);
+            let tables = node.map(tables).unwrap_or_default();
+            let record = Record {
+                timestamp: timestamp.clone(),
+                user: String::new(),
+                database: String::new(),
+                client: String::new(),
+                audit_type: "SESSION",
+                statement_id,
+                substatement_id: i + 1,
+                class: class.to_string(),
+                command: command(node, statement),
+                object_type: if tables.is_empty() {
+                    String::new()
+                } else {
+                    "TABLE".into()
+                },
+                object_name: tables.join(","),
+                statement: statement.to_owned(),
+                parameter: "<not logged>",
+                shards: None,
+            };
+            (class, record)
+        })
+        .collect()
+}
+
+/// Tables referenced by the statement, in order of appearance.
+fn tables(node: &NodeEnum) -> Vec<String> {
+    let mut tables: Vec<String> = vec![];
+    for (node, _, _, _) in node.nodes() {
+        if let NodeRef::RangeVar(range_var) = node {
+            let table = if range_var.schemaname.is_empty() {
+                range_var.relname.clone()
+            } else {
+                format!("{}.{}", range_var.schemaname, range_var.relname)
+            };
+            if !tables.contains(&table) {
+                tables.push(table);
+            }
+        }
+    }
+    tables
+}
+
+/// Text of one statement in a multi-statement query.
+fn statement<'a>(query: &'a str, stmt: &RawStmt) -> &'a str {
+    let start = (stmt.stmt_location.max(0) as usize).min(query.len());
+    let end = if stmt.stmt_len > 0 {
+        (start + stmt.stmt_len as usize).min(query.len())
+    } else {
+        query.len()
+    };
+
+    query.get(start..end).unwrap_or(query).trim()
+}
+
+/// pgAudit class of the statement.
+fn class(node: Option<&NodeEnum>) -> AuditClass {
+    match node {
+        Some(NodeEnum::SelectStmt(stmt)) if stmt.into_clause.is_none() => AuditClass::Read,
+        Some(NodeEnum::CopyStmt(stmt)) if !stmt.is_from => AuditClass::Read,
+        Some(
+            NodeEnum::InsertStmt(_)
+            | NodeEnum::UpdateStmt(_)
+            | NodeEnum::DeleteStmt(_)
+            | NodeEnum::MergeStmt(_)
+            | NodeEnum::TruncateStmt(_)
+            | NodeEnum::CopyStmt(_),
+        ) => AuditClass::Write,
+        Some(NodeEnum::DoStmt(_) | NodeEnum::CallStmt(_)) => AuditClass::Function,
+        Some(
+            NodeEnum::GrantStmt(_)
+            | NodeEnum::GrantRoleStmt(_)
+            | NodeEnum::CreateRoleStmt(_)
+            | NodeEnum::AlterRoleStmt(_)
+            | NodeEnum::AlterRoleSetStmt(_)
+            | NodeEnum::DropRoleStmt(_),
+        ) => AuditClass::Role,
+        Some(NodeEnum::VariableSetStmt(_)) => AuditClass::MiscSet,
+        Some(
+            NodeEnum::DiscardStmt(_)
+            | NodeEnum::FetchStmt(_)
+            | NodeEnum::CheckPointStmt(_)
+            | NodeEnum::VacuumStmt(_)
+            | NodeEnum::TransactionStmt(_)
+            | NodeEnum::VariableShowStmt(_)
+            | NodeEnum::ExplainStmt(_)
+            | NodeEnum::PrepareStmt(_)
+            | NodeEnum::ExecuteStmt(_)
+            | NodeEnum::DeallocateStmt(_)
+            | NodeEnum::ListenStmt(_)
+            | NodeEnum::UnlistenStmt(_)
+            | NodeEnum::NotifyStmt(_)
+            | NodeEnum::LockStmt(_)
+            | NodeEnum::DeclareCursorStmt(_)
+            | NodeEnum::ClosePortalStmt(_),
+        ) => AuditClass::Misc,
+        _ => AuditClass::Ddl,
+    }
+}
+
+/// Command tag, e.g. `SELECT` or `CREATE TABLE`.
+fn command(node: Option<&NodeEnum>, statement: &str) -> String {
+    match node {
+        Some(NodeEnum::SelectStmt(_)) => return "SELECT".into(),
+        Some(NodeEnum::InsertStmt(_)) => return "INSERT".into(),
+        Some(NodeEnum::UpdateStmt(_)) => return "UPDATE".into(),
+        Some(NodeEnum::DeleteStmt(_)) => return "DELETE".into(),
+        Some(NodeEnum::MergeStmt(_)) => return "MERGE".into(),
+        Some(NodeEnum::TruncateStmt(_)) => return "TRUNCATE TABLE".into(),
+        _ => (),
+    }
+
+    // Skip modifiers, e.g. CREATE OR REPLACE FUNCTION is CREATE FUNCTION.
+    let mut words = statement
+        .split_whitespace()
+        .map(|word| word.trim_end_matches(';').to_uppercase());
+    let Some(first) = words.next() else {
+        return String::new();
+    };
+
+    if !matches!(first.as_str(), "CREATE" | "ALTER" | "DROP" | "COMMENT") {
+        return first;
+    }
+
+    let mut tag = vec![first];
+    for word in words {
+        match word.as_str() {
+            "OR" | "REPLACE" | "UNIQUE" | "TEMP" | "TEMPORARY" | "UNLOGGED" | "GLOBAL"
+            | "LOCAL" | "TRUSTED" | "PROCEDURAL" => continue,
+            "MATERIALIZED" | "FOREIGN" | "EVENT" | "TEXT" | "ACCESS" | "DEFAULT" | "USER"
+                if tag.len() == 1 =>
+            {
+                tag.push(word)
+            }
+            _ => {
+                tag.push(word);
+                break;
+            }
+        }
+    }
+
+    tag.join(" ")
+}
+
+/// Quote a CSV field if needed.
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Line to append to a log file.
+#[derive(Debug)]
+struct Line {
+    path: PathBuf,
+    line: String,
+    rotation: Option<Rotation>,
+}
+
+/// When to rotate a log file: `query.log` is renamed to `query.log.1`,
+/// `query.log.1` to `query.log.2`, and so on, up to `max_files`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Rotation {
+    /// Rotate when the file is larger than this many bytes. 0 disables it.
+    pub max_size: u64,
+    /// Rotate when the file was opened longer ago than this. Zero disables it.
+    pub interval: Duration,
+    /// Rotated files kept.
+    pub max_files: usize,
+}
+
+/// Log file written by the background task.
+struct LogFile {
+    path: PathBuf,
+    file: BufWriter<File>,
+    size: u64,
+    opened_at: Instant,
+    rotation: Option<Rotation>,
+}
+
+impl LogFile {
+    async fn open(path: &Path, rotation: Option<Rotation>) -> std::io::Result<Self> {
+        let file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .await?;
+        let size = file.metadata().await?.len();
+
+        Ok(Self {
+            path: path.to_owned(),
+            file: BufWriter::new(file),
+            size,
+            opened_at: Instant::now(),
+            rotation,
+        })
+    }
+
+    async fn write(&mut self, line: &str) -> std::io::Result<()> {
+        self.file.write_all(line.as_bytes()).await?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let Some(rotation) = self.rotation else {
+            return false;
+        };
+
+        (rotation.max_size > 0 && self.size >= rotation.max_size)
+            || (!rotation.interval.is_zero() && self.opened_at.elapsed() >= rotation.interval)
+    }
+
+    /// Move the file out of the way and start a new one.
+    async fn rotate(mut self) -> std::io::Result<Self> {
+        self.file.flush().await?;
+        drop(self.file);
+
+        let max_files = self.rotation.unwrap_or_default().max_files;
+        if max_files == 0 {
+            remove_file(&self.path).await?;
+        } else {
+            let _ = remove_file(rotated(&self.path, max_files)).await;
+            for n in (1..max_files).rev() {
+                let _ = rename(rotated(&self.path, n), rotated(&self.path, n + 1)).await;
+            }
+            rename(&self.path, rotated(&self.path, 1)).await?;
+        }
+
+        Self::open(&self.path, self.rotation).await
+    }
+}
+
+/// Path of a rotated file, e.g. `query.log.1`.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(format!(".{}", n));
+    path.into()
+}
+
+/// Append a line to the file, rotating it if needed. Shared by the statement,
+/// admin and query logs, so only one task writes to disk.
+pub(crate) fn append(path: PathBuf, line: String, rotation: Option<Rotation>) {
+    let line = Line {
+        path,
+        line,
+        rotation,
+    };
+    if writer().try_send(line).is_err() {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Write lines to their files, flushing once they're all written.
+async fn write_lines(files: &mut HashMap<PathBuf, LogFile>, lines: Vec<Line>) {
+    for Line {
+        path,
+        line,
+        rotation,
+    } in lines
+    {
+        // Paths can change on config reload.
+        if !files.contains_key(&path) {
+            match LogFile::open(&path, rotation).await {
+                Ok(file) => {
+                    files.insert(path.clone(), file);
+                }
+                Err(err) => {
+                    error!("failed to open log \"{}\": {}", path.display(), err);
+                    continue;
+                }
+            }
+        }
+
+        if let Some(file) = files.get_mut(&path) {
+            file.rotation = rotation;
+            if let Err(err) = file.write(&line).await {
+                error!("failed to write log \"{}\": {}", path.display(), err);
+                files.remove(&path);
+            }
+        }
+    }
+
+    let paths = files.keys().cloned().collect::<Vec<_>>();
+    for path in paths {
+        let Some(mut file) = files.remove(&path) else {
+            continue;
+        };
+
+        if let Err(err) = file.file.flush().await {
+            error!("failed to write log \"{}\": {}", path.display(), err);
+            continue;
+        }
+
+        if file.needs_rotation() {
+            match file.rotate().await {
+                Ok(file) => {
+                    files.insert(path, file);
+                }
+                Err(err) => error!("failed to rotate log \"{}\": {}", path.display(), err),
+            }
+        } else {
+            files.insert(path, file);
+        }
+    }
+}
+
+/// Background task writing lines to log files.
+fn writer() -> &'static Sender<Line> {
+    WRITER.get_or_init(|| {
+        let (tx, mut rx) = channel::<Line>(QUEUE);
+
+        spawn(async move {
+            let mut files: HashMap<PathBuf, LogFile> = HashMap::new();
+            let mut lines = vec![];
+
+            while rx.recv_many(&mut lines, BATCH).await > 0 {
+                write_lines(&mut files, std::mem::take(&mut lines)).await;
+
+                let dropped = DROPPED.swap(0, Ordering::Relaxed);
+                if dropped > 0 {
+                    warn!("log writer fell behind, dropped {} lines", dropped);
+                }
+            }
+        });
+
+        tx
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_records() {
+        let records = records(
+            "SELECT * FROM users JOIN orders ON true; INSERT INTO logs (id) VALUES (1); CREATE UNIQUE INDEX ON users (email); SET work_mem TO '1GB'; GRANT SELECT ON users TO bob",
+            7,
+        )
+        .into_iter()
+        .map(|(_, record)| record)
+        .collect::<Vec<_>>();
+
+        let summary = records
+            .iter()
+            .map(|r| (r.class.as_str(), r.command.as_str(), r.substatement_id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("READ", "SELECT", 1),
+                ("WRITE", "INSERT", 2),
+                ("DDL", "CREATE INDEX", 3),
+                ("MISC_SET", "SET", 4),
+                ("ROLE", "GRANT", 5),
+            ]
+        );
+        assert_eq!(records[1].statement, "INSERT INTO logs (id) VALUES (1)");
+        assert!(records.iter().all(|r| r.statement_id == 7));
+
+        let record = Record {
+            user: "alice".into(),
+            database: "prod".into(),
+            client: "127.0.0.1:1234".into(),
+            ..records[0].clone()
+        };
+        assert!(record.csv().ends_with(
+            ",alice,prod,127.0.0.1:1234,SESSION,7,1,READ,SELECT,TABLE,\"users,orders\",SELECT * FROM users JOIN orders ON true,<not logged>"
+        ));
+
+        assert_eq!(
+            command(None, "CREATE OR REPLACE FUNCTION f() ..."),
+            "CREATE FUNCTION"
+        );
+        assert_eq!(
+            command(None, "create materialized view v as select 1"),
+            "CREATE MATERIALIZED VIEW"
+        );
+        assert_eq!(command(None, "VACUUM;"), "VACUUM");
+    }
+
+    #[test]
+    fn test_privileged() {
+        let privileged = records(
+            "SELECT 1; CREATE TABLE t (id BIGINT); GRANT SELECT ON t TO bob; REVOKE ALL ON t FROM bob; TRUNCATE t; DELETE FROM t",
+            1,
+        )
+        .into_iter()
+        .filter(|(class, record)| privileged(*class, &record.command))
+        .map(|(_, record)| record.command)
+        .collect::<Vec<_>>();
+        assert_eq!(
+            privileged,
+            vec!["CREATE TABLE", "GRANT", "REVOKE", "TRUNCATE TABLE"]
+        );
+
+        assert_eq!(shard_list(&Shard::All, 3), "0,1,2");
+        assert_eq!(shard_list(&Shard::Multi(vec![0, 2]), 3), "0,2");
+        assert_eq!(shard_list(&Shard::Direct(1), 3), "1");
+
+        let record = Record {
+            shards: Some("0,1".into()),
+            ..records("DROP TABLE t", 1).remove(0).1
+        };
+        assert!(record.csv().ends_with(",DROP TABLE t,<not logged>,\"0,1\""));
+    }
+
+    #[test]
+    fn test_rotated() {
+        assert_eq!(
+            rotated(Path::new("/var/log/query.log"), 2),
+            PathBuf::from("/var/log/query.log.2")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("pgdog_query_log_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let path = dir.join("query.log");
+
+        let rotation = Rotation {
+            max_size: 10,
+            max_files: 2,
+            ..Default::default()
+        };
+
+        let mut file = LogFile::open(&path, Some(rotation)).await.unwrap();
+        for _ in 0..3 {
+            file.write("SELECT 1;\n").await.unwrap();
+            assert!(file.needs_rotation());
+            file = file.rotate().await.unwrap();
+        }
+        assert_eq!(file.size, 0);
+        assert!(rotated(&path, 1).exists());
+        assert!(rotated(&path, 2).exists());
+        assert!(!rotated(&path, 3).exists());
+
+        file.rotation = None;
+        file.write("SELECT 1;\n").await.unwrap();
+        assert!(!file.needs_rotation());
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_lines() {
+        let dir = std::env::temp_dir().join(format!("pgdog_write_lines_{}", std::process::id()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let (a, b) = (dir.join("a.log"), dir.join("b.log"));
+
+        // Lines for different files in the same batch are all written.
+        let line = |path: &PathBuf, line: &str| Line {
+            path: path.clone(),
+            line: line.into(),
+            rotation: None,
+        };
+        let mut files = HashMap::new();
+        write_lines(
+            &mut files,
+            vec![line(&a, "1\n"), line(&b, "2\n"), line(&a, "3\n")],
+        )
+        .await;
+
+        assert_eq!(tokio::fs::read_to_string(&a).await.unwrap(), "1\n3\n");
+        assert_eq!(tokio::fs::read_to_string(&b).await.unwrap(), "2\n");
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
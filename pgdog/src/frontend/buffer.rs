@@ -104,6 +104,54 @@ impl Buffer {
         Ok(None)
     }
 
+    /// Number of statements executed by this buffer
+    /// with the extended protocol.
+    pub fn executes(&self) -> usize {
+        self.buffer
+            .iter()
+            .filter(|message| matches!(message, ProtocolMessage::Execute(_)))
+            .count()
+    }
+
+    /// Statements executed by this buffer, with their parameters.
+    ///
+    /// Clients in pipeline mode send several statements before a Sync,
+    /// each with its own Bind. Statements are found by name in this buffer
+    /// or among prepared statements parsed earlier.
+    pub fn statements(&self) -> Result<Vec<(BufferedQuery, Option<&Bind>)>, Error> {
+        let mut statements = vec![];
+        let mut parses: Vec<&Parse> = vec![];
+
+        for message in &self.buffer {
+            match message {
+                ProtocolMessage::Query(query) => {
+                    statements.push((BufferedQuery::Query(query.clone()), None));
+                }
+                ProtocolMessage::Parse(parse) => parses.push(parse),
+                ProtocolMessage::Bind(bind) => {
+                    let parse = parses
+                        .iter()
+                        .rev()
+                        .find(|parse| parse.name() == bind.statement())
+                        .map(|parse| (*parse).clone())
+                        .or_else(|| {
+                            if bind.anonymous() {
+                                None
+                            } else {
+                                PreparedStatements::global().lock().parse(bind.statement())
+                            }
+                        });
+                    if let Some(parse) = parse {
+                        statements.push((BufferedQuery::Prepared(parse), Some(bind)));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(statements)
+    }
+
     /// If this buffer contains bound parameters, retrieve them.
     pub fn parameters(&self) -> Result<Option<&Bind>, Error> {
         for message in &self.buffer {
@@ -142,6 +190,11 @@ impl Buffer {
             .unwrap_or(false)
     }
 
+    /// The buffer ends the COPY with CopyDone or CopyFail.
+    pub fn copy_done(&self) -> bool {
+        self.buffer.iter().any(|m| matches!(m.code(), 'c' | 'f'))
+    }
+
     /// The client is expecting a reply now.
     pub fn flush(&self) -> bool {
         self.buffer.last().map(|m| m.code() == 'H').unwrap_or(false)
@@ -155,6 +208,18 @@ impl Buffer {
             .any(|m| ['E', 'Q', 'B'].contains(&m.code()))
     }
 
+    /// Replace integer parameters in Bind messages.
+    pub fn rewrite_parameters(&mut self, parameters: &[(usize, i64)]) -> Result<(), Error> {
+        for message in self.buffer.iter_mut() {
+            if let ProtocolMessage::Bind(bind) = message {
+                for (index, value) in parameters {
+                    bind.set_integer(*index, *value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Rewrite query in buffer.
     pub fn rewrite(&mut self, query: &str) -> Result<(), Error> {
         if self.buffer.iter().any(|c| c.code() != 'Q') {
@@ -164,6 +229,52 @@ impl Buffer {
         self.buffer.push(Query::new(query).into());
         Ok(())
     }
+
+    /// Rewrite the prepared statement executed by this buffer.
+    ///
+    /// The rewritten query is prepared under its own name, so the original
+    /// statement can still be executed on one shard. Each result column
+    /// can have its own format, so the `columns` added by the rewrite are
+    /// returned in text.
+    pub fn rewrite_prepared(&mut self, query: &str, columns: usize) -> Result<(), Error> {
+        let Some(BufferedQuery::Prepared(original)) = self.query()? else {
+            return Ok(());
+        };
+        let parse = original.with_query(query);
+
+        // Statements we don't manage keep their names.
+        let name = {
+            let global = PreparedStatements::global();
+            let mut global = global.lock();
+            if global.parse(original.name()).is_some() {
+                global.insert(&parse).1
+            } else {
+                original.name().to_owned()
+            }
+        };
+
+        for message in self.buffer.iter_mut() {
+            match message {
+                ProtocolMessage::Parse(ref mut statement)
+                    if statement.name() == original.name() =>
+                {
+                    *statement = parse.rename(&name);
+                }
+                ProtocolMessage::Bind(ref mut bind) if bind.statement() == original.name() => {
+                    bind.add_results(columns);
+                    *bind = bind.clone().rename(&name);
+                }
+                ProtocolMessage::Describe(ref mut describe)
+                    if describe.is_statement() && describe.statement() == original.name() =>
+                {
+                    *describe = describe.clone().rename(&name);
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl From<Buffer> for Vec<ProtocolMessage> {
@@ -222,3 +333,68 @@ impl Deref for BufferedQuery {
         self.query()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::net::messages::{Describe, Execute, Format, Sync};
+
+    #[test]
+    fn test_rewrite_prepared() {
+        let query = "SELECT avg(id) FROM test_rewrite_prepared";
+        let rewritten = "SELECT avg(id), sum(id), count(id) FROM test_rewrite_prepared";
+        let (_, name) = PreparedStatements::global()
+            .lock()
+            .insert(&Parse::named("test", query));
+
+        let mut buffer = Buffer::from(vec![
+            Parse::named(&name, query).into(),
+            Bind::test_results(&name, &[1]).into(),
+            Describe::new_statement(&name).into(),
+            Execute::new().into(),
+            Sync.into(),
+        ]);
+        buffer.rewrite_prepared(rewritten, 2).unwrap();
+
+        let ProtocolMessage::Parse(parse) = &buffer[0] else {
+            panic!("not a parse");
+        };
+        assert_eq!(parse.query(), rewritten);
+        assert_ne!(parse.name(), name);
+        assert_eq!(
+            PreparedStatements::global()
+                .lock()
+                .parse(parse.name())
+                .unwrap()
+                .query(),
+            rewritten
+        );
+
+        // One format is used for all columns.
+        let ProtocolMessage::Bind(bind) = &buffer[1] else {
+            panic!("not a bind");
+        };
+        assert_eq!(bind.statement(), parse.name());
+        assert_eq!(bind.result_formats(), vec![Format::Binary]);
+
+        let ProtocolMessage::Describe(describe) = &buffer[2] else {
+            panic!("not a describe");
+        };
+        assert_eq!(describe.statement(), parse.name());
+
+        // Added columns are returned in text.
+        let mut buffer = Buffer::from(vec![
+            Bind::test_results(&name, &[1, 0]).into(),
+            Execute::new().into(),
+            Sync.into(),
+        ]);
+        buffer.rewrite_prepared(rewritten, 2).unwrap();
+        let ProtocolMessage::Bind(bind) = &buffer[0] else {
+            panic!("not a bind");
+        };
+        assert_eq!(
+            bind.result_formats(),
+            vec![Format::Binary, Format::Text, Format::Text, Format::Text]
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! pg_dump and pg_restore sessions.
+//!
+//! Dumps and restores run long transactions and rely on session state,
+//! so they get a server connection on one shard for the whole session
+//! and aren't subject to query or idle timeouts.
+//!
+//! With `compatibility.pg_restore`, restores that don't pick a shard
+//! keep connections to all shards instead, see [`Dump::restore`].
+
+use crate::net::Parameters;
+
+/// Programs detected by their `application_name`.
+const PROGRAMS: [&str; 3] = ["pg_dump", "pg_dumpall", "pg_restore"];
+
+/// A pg_dump or pg_restore session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dump {
+    program: String,
+    shard: Option<usize>,
+}
+
+impl Dump {
+    /// Detect a dump or restore from startup parameters.
+    pub fn new(params: &Parameters) -> Option<Self> {
+        let program = params.get_default("application_name", "");
+        if !PROGRAMS.contains(&program) {
+            return None;
+        }
+
+        let shard = params.get_default("pgdog.shard", "").parse().ok();
+
+        Some(Self {
+            program: program.to_owned(),
+            shard,
+        })
+    }
+
+    /// Shard to dump from or restore into. Sharded databases
+    /// require the client to pick one with `pgdog.shard`.
+    pub fn shard(&self, shards: usize) -> Option<usize> {
+        match self.shard {
+            Some(shard) if shard < shards => Some(shard),
+            Some(_) => None,
+            None if shards == 1 => Some(0),
+            None => None,
+        }
+    }
+
+    /// Restore into all shards of a sharded database.
+    ///
+    /// Only `pg_restore` writes, so only it can be split between shards.
+    pub fn restore(&self, shards: usize, enabled: bool) -> bool {
+        enabled && self.program == "pg_restore" && self.shard.is_none() && shards > 1
+    }
+
+    /// Program name, e.g. "pg_dump".
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dump() {
+        let mut params = Parameters::default();
+        params.insert("application_name", "psql");
+        assert!(Dump::new(&params).is_none());
+
+        params.insert("application_name", "pg_dump");
+        let dump = Dump::new(&params).unwrap();
+        assert_eq!(dump.program(), "pg_dump");
+        assert_eq!(dump.shard(1), Some(0));
+        assert_eq!(dump.shard(2), None);
+
+        params.insert("pgdog.shard", "1");
+        let dump = Dump::new(&params).unwrap();
+        assert_eq!(dump.shard(2), Some(1));
+        assert_eq!(dump.shard(1), None);
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut params = Parameters::default();
+        params.insert("application_name", "pg_restore");
+        let dump = Dump::new(&params).unwrap();
+        assert!(dump.restore(2, true));
+        assert!(!dump.restore(2, false));
+        assert!(!dump.restore(1, true));
+
+        params.insert("pgdog.shard", "0");
+        assert!(!Dump::new(&params).unwrap().restore(2, true));
+
+        let mut params = Parameters::default();
+        params.insert("application_name", "pg_dump");
+        assert!(!Dump::new(&params).unwrap().restore(2, true));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::ops::{Deref, DerefMut};
 
 use crate::{
@@ -6,8 +7,12 @@ use crate::{
         Error as BackendError,
     },
     frontend::{
-        buffer::BufferedQuery, router::Error as RouterError, Buffer, Command, Comms,
-        PreparedStatements, Router, RouterContext, Stats,
+        buffer::BufferedQuery,
+        router::{
+            parser::{Shard, TempTable},
+            Error as RouterError,
+        },
+        Buffer, Command, Comms, PreparedStatements, Router, RouterContext, Stats,
     },
     net::Parameters,
     state::State,
@@ -33,14 +38,23 @@ pub(super) struct Inner {
     pub(super) start_transaction: Option<BufferedQuery>,
     /// Client-wide comms.
     pub(super) comms: Comms,
+    /// Keep the server connection for the whole session.
+    pub(super) pinned: bool,
+    /// The client is holding a session lock, e.g. an advisory lock.
+    pub(super) session_locked: bool,
+    /// Temporary tables created by the client. We keep the server
+    /// connection until they are dropped.
+    pub(super) temp_tables: HashSet<String>,
 }
 
 impl Inner {
     pub fn new(client: &Client) -> Result<Self, Error> {
         let user = client.params.get_required("user")?;
         let database = client.params.get_default("database", user);
+        let application_name = client.params.get_default("application_name", "");
 
-        let mut backend = Connection::new(user, database, client.admin)?;
+        let mut backend = Connection::new(user, database, Some(application_name), client.admin)?;
+        backend.admin_client(client.addr);
         let mut router = Router::new();
 
         // Configure replication mode.
@@ -55,6 +69,24 @@ impl Inner {
                 router.replication_mode();
                 debug!("logical replication sharding [{}]", client.addr);
             }
+        } else if matches!(
+            client
+                .params
+                .get_default("replication", "false")
+                .to_lowercase()
+                .as_str(),
+            "true" | "on" | "yes" | "1" | "database"
+        ) {
+            // Replication commands are sent to all shards
+            // and their streams are merged into one.
+            router.replication_mode();
+            debug!("logical replication from all shards [{}]", client.addr);
+        }
+
+        match client.dump {
+            Some(Shard::Direct(shard)) => router.pin_shard(shard),
+            Some(_) => router.restore(),
+            None => (),
         }
 
         Ok(Self {
@@ -63,6 +95,9 @@ impl Inner {
             stats: Stats::new(),
             start_transaction: None,
             comms: client.comms.clone(),
+            pinned: client.dump.is_some(),
+            session_locked: false,
+            temp_tables: HashSet::new(),
         })
     }
 
@@ -93,6 +128,42 @@ impl Inner {
             buffer.rewrite(query)?;
         }
 
+        if let Some(Command::Query(route)) = command {
+            // Fetch the extra columns needed to merge results across shards.
+            if let Some(query) = route.rewrite() {
+                if let Some(BufferedQuery::Prepared(_)) = buffer.query()? {
+                    buffer.rewrite_prepared(query, route.aggregate().hidden())?;
+                } else {
+                    buffer.rewrite(query)?;
+                }
+            }
+            if !route.parameters().is_empty() {
+                buffer.rewrite_parameters(route.parameters())?;
+            }
+
+            if let Some(temp_table) = route.temp_table() {
+                match temp_table {
+                    TempTable::Create(name) => {
+                        self.temp_tables.insert(name.clone());
+                    }
+                    TempTable::Drop(names) => {
+                        for name in names {
+                            self.temp_tables.remove(name);
+                        }
+                    }
+                    TempTable::Discard => self.temp_tables.clear(),
+                }
+            }
+
+            // Already connected, so lock (or unlock) the connection now.
+            if self.backend.connected() {
+                self.session_locked |= route.lock_session();
+                let lock = self.session_locked || self.pinned || !self.temp_tables.is_empty();
+                self.stats.locked(lock);
+                self.backend.lock(lock);
+            }
+        }
+
         Ok(command)
     }
 
@@ -139,13 +210,15 @@ impl Inner {
 
         if result.is_ok() {
             self.stats.connected();
-            self.stats.locked(route.lock_session());
+            self.session_locked = route.lock_session();
+            let lock = self.session_locked || self.pinned || !self.temp_tables.is_empty();
+            self.stats.locked(lock);
             // This connection will be locked to this client
             // until they disconnect.
             //
             // Used in case the client runs an advisory lock
             // or another leaky transaction mode abostraction.
-            self.backend.lock(route.lock_session());
+            self.backend.lock(lock);
 
             if let Ok(addr) = self.backend.addr() {
                 debug!(
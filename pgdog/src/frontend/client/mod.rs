@@ -2,24 +2,26 @@
 
 use std::collections::VecDeque;
 use std::net::SocketAddr;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use bytes::BytesMut;
 use timeouts::Timeouts;
-use tokio::time::timeout;
+use tokio::time::{sleep, timeout};
 use tokio::{select, spawn};
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
-use super::{Buffer, Command, Comms, Error, PreparedStatements};
-use crate::auth::{md5, scram::Server};
+use super::{connected_client::RunningQuery, Buffer, Command, Comms, Error, PreparedStatements};
+use crate::auth::{md5, scram::Server, Credential};
 use crate::backend::{
     databases,
     pool::{Connection, Request},
     ProtocolMessage,
 };
 use crate::config::{self, AuthType};
+use crate::events::{emit, Event};
+use crate::frontend::audit::Auditor;
 use crate::frontend::buffer::BufferedQuery;
-#[cfg(debug_assertions)]
+use crate::frontend::router::parser::{Explain, Metadata, Shard};
 use crate::frontend::QueryLogger;
 use crate::net::messages::{
     Authentication, BackendKeyData, CommandComplete, ErrorResponse, FromBytes, Message, Password,
@@ -27,11 +29,18 @@ use crate::net::messages::{
 };
 use crate::net::{parameter::Parameters, Stream};
 use crate::net::{DataRow, EmptyQueryResponse, Field, NoticeResponse, RowDescription};
+use crate::stats::clients;
+use crate::stats::errors::{connection_error, record, ErrorEvent};
+use crate::stats::query_stats::Execution;
+use crate::stats::wait_events::{self, WaitEvents};
+use crate::telemetry::Session;
 
 pub mod counter;
+pub mod dump;
 pub mod inner;
 pub mod timeouts;
 
+use dump::Dump;
 use inner::{Inner, InnerBorrow};
 
 /// Frontend client.
@@ -47,12 +56,25 @@ pub struct Client {
     streaming: bool,
     shutdown: bool,
     shard: Option<usize>,
+    /// Shard(s) of a pg_dump or pg_restore session.
+    dump: Option<Shard>,
     prepared_statements: PreparedStatements,
     in_transaction: bool,
     timeouts: Timeouts,
+    /// Statement timeout from a query comment was set on the server
+    /// and needs to be restored once the request is done.
+    reset_statement_timeout: bool,
     request_buffer: Buffer,
     stream_buffer: BytesMut,
     message_buffer: VecDeque<ProtocolMessage>,
+    buffer_time: Duration,
+    buffer_oversized: bool,
+    /// We replied with an error to a pipeline that isn't over yet,
+    /// ignore messages until the client sends a Sync.
+    skip_to_sync: bool,
+    auditor: Auditor,
+    execution: Option<Execution>,
+    telemetry: Session,
 }
 
 impl Client {
@@ -62,6 +84,7 @@ impl Client {
         params: Parameters,
         addr: SocketAddr,
         mut comms: Comms,
+        connected_at: Instant,
     ) -> Result<(), Error> {
         let user = params.get_default("user", "postgres");
         let database = params.get_default("database", user);
@@ -96,7 +119,8 @@ impl Client {
         }
 
         // Get server parameters and send them to the client.
-        let mut conn = match Connection::new(user, database, admin) {
+        let application_name = params.get_default("application_name", "");
+        let mut conn = match Connection::new(user, database, Some(application_name), admin) {
             Ok(conn) => conn,
             Err(_) => {
                 stream.fatal(ErrorResponse::auth(user, database)).await?;
@@ -104,14 +128,15 @@ impl Client {
             }
         };
 
-        let password = if admin {
-            admin_password
+        let (password, next_password) = if admin {
+            (admin_password.as_str(), None)
         } else {
-            conn.cluster()?.password()
+            let cluster = conn.cluster()?;
+            (cluster.password(), cluster.next_password())
         };
 
         let auth_type = &config.config.general.auth_type;
-        let auth_ok = match (auth_type, stream.is_tls()) {
+        let credential = match (auth_type, stream.is_tls()) {
             // TODO: SCRAM doesn't work with TLS currently because of
             // lack of support for channel binding in our scram library.
             // Defaulting to MD5.
@@ -120,36 +145,95 @@ impl Client {
                 stream.send_flush(&md5.challenge()).await?;
                 let password = Password::from_bytes(stream.read().await?.to_bytes()?)?;
                 if let Password::PasswordMessage { response } = password {
-                    md5.check(&response)
+                    if md5.check(&response) {
+                        Some(Credential::Current)
+                    } else if next_password
+                        .is_some_and(|next| md5.with_password(next).check(&response))
+                    {
+                        Some(Credential::Next)
+                    } else {
+                        None
+                    }
                 } else {
-                    false
+                    None
                 }
             }
 
             (AuthType::Scram, false) => {
                 stream.send_flush(&Authentication::scram()).await?;
 
-                let scram = Server::new(password);
-                let res = scram.handle(&mut stream).await;
-                matches!(res, Ok(true))
+                let mut scram = Server::new(password);
+                if let Some(next_password) = next_password {
+                    scram = scram.next_password(next_password);
+                }
+                scram.handle(&mut stream).await.ok().flatten()
             }
 
-            (AuthType::Trust, _) => true,
+            (AuthType::Trust, _) => Some(Credential::Current),
         };
 
-        if !auth_ok {
+        let Some(credential) = credential else {
             stream.fatal(ErrorResponse::auth(user, database)).await?;
             return Ok(());
-        } else {
-            stream.send(&Authentication::Ok).await?;
+        };
+        stream.send(&Authentication::Ok).await?;
+
+        if credential == Credential::Next {
+            info!(
+                "user \"{}\" authenticated with next password [{}]",
+                user, addr
+            );
         }
 
         // Check if the pooler is shutting down.
-        if comms.offline() && !admin {
+        if !comms.accepting() && !admin {
             stream.fatal(ErrorResponse::shutting_down()).await?;
             return Ok(());
         }
 
+        // Database was disabled by an admin, e.g. during a cutover.
+        if !admin && databases::disabled(database) {
+            stream
+                .fatal(ErrorResponse::database_disabled(database))
+                .await?;
+            return Ok(());
+        }
+
+        // Pin pg_dump and pg_restore to one shard for the whole session,
+        // or restore into all of them in compatibility mode.
+        let dump = match Dump::new(&params) {
+            Some(dump) if !admin => {
+                let shards = conn.cluster()?.shards().len();
+                match dump.shard(shards) {
+                    Some(shard) => {
+                        info!(
+                            "{} session pinned to shard {} [{}]",
+                            dump.program(),
+                            shard,
+                            addr
+                        );
+                        Some(Shard::Direct(shard))
+                    }
+                    None if dump.restore(shards, config.config.compatibility.pg_restore) => {
+                        info!(
+                            "{} session restoring into all {} shards [{}]",
+                            dump.program(),
+                            shards,
+                            addr
+                        );
+                        Some(Shard::All)
+                    }
+                    None => {
+                        stream
+                            .fatal(ErrorResponse::dump_shard(dump.program(), shards))
+                            .await?;
+                        return Ok(());
+                    }
+                }
+            }
+            _ => None,
+        };
+
         let server_params = match conn.parameters(&Request::new(id)).await {
             Ok(params) => params,
             Err(err) => {
@@ -169,7 +253,13 @@ impl Client {
 
         stream.send(&id).await?;
         stream.send_flush(&ReadyForQuery::idle()).await?;
-        comms.connect(&id, addr, &params);
+        comms.connect(&id, addr, &params, credential);
+        clients::logged_in(user, database, connected_at.elapsed());
+        emit(Event::ClientConnected {
+            client: addr,
+            user: user.to_owned(),
+            database: database.to_owned(),
+        });
         let shard = params.shard();
 
         info!(
@@ -184,6 +274,18 @@ impl Client {
 
         let mut prepared_statements = PreparedStatements::new();
         prepared_statements.enabled = config.prepared_statements();
+        // Dumps and restores can take a while.
+        let timeouts = if dump.is_some() {
+            Timeouts::default()
+        } else {
+            Timeouts::from_config(&config.config.general, config.users.find(user, database))
+        };
+
+        let telemetry = if admin {
+            Session::default()
+        } else {
+            Session::new(user, database, addr)
+        };
 
         let mut client = Self {
             addr,
@@ -193,14 +295,22 @@ impl Client {
             admin,
             streaming: false,
             shard,
+            dump,
             params: params.clone(),
             connect_params: params,
             prepared_statements: PreparedStatements::new(),
             in_transaction: false,
-            timeouts: Timeouts::from_config(&config.config.general),
+            timeouts,
             request_buffer: Buffer::new(),
             stream_buffer: BytesMut::new(),
             message_buffer: VecDeque::new(),
+            buffer_time: Duration::ZERO,
+            buffer_oversized: false,
+            skip_to_sync: false,
+            auditor: Auditor::default(),
+            execution: None,
+            reset_statement_timeout: false,
+            telemetry,
             shutdown: false,
         };
 
@@ -237,11 +347,19 @@ impl Client {
             params: connect_params,
             admin: false,
             shard: None,
+            dump: None,
             in_transaction: false,
-            timeouts: Timeouts::from_config(&config().config.general),
+            timeouts: Timeouts::from_config(&config().config.general, None),
             request_buffer: Buffer::new(),
             stream_buffer: BytesMut::new(),
             message_buffer: VecDeque::new(),
+            buffer_time: Duration::ZERO,
+            buffer_oversized: false,
+            skip_to_sync: false,
+            auditor: Auditor::default(),
+            execution: None,
+            reset_statement_timeout: false,
+            telemetry: Session::default(),
             shutdown: false,
         }
     }
@@ -262,13 +380,26 @@ impl Client {
         }
     }
 
+    /// Add time this client spent in each state to its pool.
+    fn wait_events(&self, events: WaitEvents) {
+        if self.admin {
+            return;
+        }
+        let user = self.params.get_default("user", "postgres");
+        wait_events::record(user, self.params.get_default("database", user), events);
+    }
+
     /// Run the client.
     async fn run(&mut self) -> Result<(), Error> {
         let mut inner = Inner::new(self)?;
         let shutdown = self.comms.shutting_down();
+        let kill = self.comms.killed();
 
         loop {
             let query_timeout = self.timeouts.query_timeout(&inner.stats.state);
+            let client_idle_timeout = self
+                .timeouts
+                .client_idle_timeout(&inner.stats.state, self.in_transaction);
 
             select! {
                 _ = shutdown.notified() => {
@@ -277,6 +408,18 @@ impl Client {
                     }
                 }
 
+                _ = kill.notified() => {
+                    info!("client terminated by admin [{}]", self.addr);
+                    self.stream.fatal(ErrorResponse::terminated()).await?;
+                    break;
+                }
+
+                _ = sleep(client_idle_timeout) => {
+                    info!("client idle timeout [{}]", self.addr);
+                    self.stream.fatal(ErrorResponse::client_idle_timeout()).await?;
+                    break;
+                }
+
                 // Async messages.
                 message = timeout(query_timeout, inner.backend.read()) => {
                     let message = message??;
@@ -312,6 +455,8 @@ impl Client {
             }
         }
 
+        self.wait_events(inner.stats.take_wait_events());
+
         if inner.comms.offline() && !self.admin {
             self.stream
                 .send_flush(&ErrorResponse::shutting_down())
@@ -324,6 +469,11 @@ impl Client {
     /// Handle client messages.
     async fn client_messages(&mut self, mut inner: InnerBorrow<'_>) -> Result<bool, Error> {
         inner.stats.received(self.request_buffer.len());
+        inner.stats.buffered(
+            self.buffer_time,
+            self.request_buffer.len(),
+            self.buffer_oversized,
+        );
 
         #[cfg(debug_assertions)]
         if let Some(query) = self.request_buffer.query()? {
@@ -333,8 +483,8 @@ impl Client {
                 self.addr,
                 self.in_transaction
             );
-            QueryLogger::new(&self.request_buffer).log().await?;
         }
+        QueryLogger::new(&self.request_buffer).log()?;
 
         let connected = inner.connected();
 
@@ -350,11 +500,20 @@ impl Client {
                     self.stream
                         .send_flush(&ReadyForQuery::in_transaction(self.in_transaction))
                         .await?;
+                } else if err.cross_shard() {
+                    self.error(ErrorResponse::feature_not_supported(
+                        err.to_string().as_str(),
+                    ))
+                    .await?;
                 } else {
                     error!("{:?} [{}]", err, self.addr);
-                    self.stream
-                        .error(ErrorResponse::syntax(err.to_string().as_str()))
-                        .await?;
+                    let error = ErrorResponse::syntax(err.to_string().as_str());
+                    record(ErrorEvent::new(
+                        self.params.get_default("database", ""),
+                        &error,
+                        self.addr,
+                    ));
+                    self.error(error).await?;
                 }
                 inner.done(self.in_transaction);
                 return Ok(false);
@@ -363,6 +522,53 @@ impl Client {
 
         self.streaming = matches!(command, Some(Command::StartReplication));
 
+        // Statement timeout from a query comment, if any.
+        self.timeouts.statement_timeout = match command {
+            Some(Command::Query(route)) => route.timeout(),
+            _ => None,
+        };
+
+        self.auditor
+            .record(&self.request_buffer, &self.params, &self.addr)?;
+
+        // Driver statements we answer without going to the server.
+        if let Some(Command::Intercept(intercept)) = command {
+            let intercept = *intercept;
+            if intercept.resets_params() {
+                self.params = self.connect_params.clone();
+                inner.comms.update_params(&self.params);
+            }
+            if let Some((name, value)) = intercept.param() {
+                self.params.insert(name, value);
+                inner.comms.update_params(&self.params);
+            }
+            if intercept.deallocates() {
+                self.prepared_statements.clear();
+            }
+            self.stream
+                .send_many(&[
+                    CommandComplete::from_str(intercept.tag()).message()?,
+                    ReadyForQuery::in_transaction(self.in_transaction).message()?,
+                ])
+                .await?;
+            inner.done(self.in_transaction);
+            return Ok(false);
+        }
+
+        // Routing decision for EXPLAIN (PGDOG).
+        if let Some(Command::Explain(explain)) = command {
+            let explain = explain.clone();
+            self.explain(&explain, inner).await?;
+            return Ok(false);
+        }
+
+        // Queries to the virtual pgdog schema.
+        if let Some(Command::Metadata(metadata)) = command {
+            let metadata = *metadata;
+            self.metadata(metadata, inner).await?;
+            return Ok(false);
+        }
+
         if !connected {
             // Simulate transaction starting
             // until client sends an actual query.
@@ -432,7 +638,13 @@ impl Client {
                 Err(err) => {
                     if err.no_server() {
                         error!("connection pool is down [{}]", self.addr);
-                        self.stream.error(ErrorResponse::connection()).await?;
+                        let error = ErrorResponse::connection();
+                        record(ErrorEvent::new(
+                            self.params.get_default("database", ""),
+                            &error,
+                            self.addr,
+                        ));
+                        self.error(error).await?;
                         return Ok(false);
                     } else {
                         return Err(err.into());
@@ -449,6 +661,15 @@ impl Client {
             if let Some(query) = inner.start_transaction.take() {
                 inner.backend.execute(&query).await?;
             }
+
+            // Statement timeout from a query comment.
+            if let Some(timeout) = self.timeouts.statement_timeout {
+                inner
+                    .backend
+                    .execute(&format!("SET statement_timeout TO {}", timeout.as_millis()))
+                    .await?;
+                self.reset_statement_timeout = true;
+            }
         }
 
         for msg in self.request_buffer.iter() {
@@ -457,14 +678,47 @@ impl Client {
             }
         }
 
+        // Show the statement in SHOW QUERIES until the server is done with it.
+        if let Ok(Some(query)) = self.request_buffer.query() {
+            let read = inner.router.route().is_read();
+            inner.backend.routed(read);
+            let route = inner.router.route();
+            let shards = inner
+                .backend
+                .cluster()
+                .map(|c| c.shards().len())
+                .unwrap_or(1);
+            self.auditor.privileged(
+                &self.request_buffer,
+                &self.params,
+                &self.addr,
+                route.shard(),
+                shards,
+            )?;
+            let backend_pids = inner.backend.pids();
+            self.telemetry
+                .query(query.query(), &route.shard().to_string(), &backend_pids);
+            inner.comms.query(Some(RunningQuery {
+                query: query.query().to_owned(),
+                started_at: Instant::now(),
+                shard: route.shard().to_string(),
+                read: route.is_read(),
+                backend_pids,
+            }));
+            self.execution = Execution::start(query.query(), route.shard().to_string());
+        }
+
         inner
             .handle_buffer(&self.request_buffer, self.streaming)
             .await?;
+        inner.stats.request_sent();
 
         inner.stats.memory_used(self.stream_buffer.capacity());
 
         // Send traffic to mirrors, if any.
-        inner.backend.mirror(&self.request_buffer);
+        inner
+            .backend
+            .mirror(&self.request_buffer, &inner.router.route());
 
         Ok(false)
     }
@@ -476,8 +730,18 @@ impl Client {
         message: Message,
     ) -> Result<bool, Error> {
         let code = message.code();
-        let message = message.backend();
+        let mut message = message.backend();
         let has_more_messages = inner.backend.has_more_messages();
+        inner.stats.server_responded();
+
+        // Commit writes to replicated tables on all shards
+        // before telling the client they're done.
+        if code == 'Z' && !has_more_messages && inner.backend.replicated() {
+            if let Some(error) = inner.backend.finish_replicated().await? {
+                self.stream.send(&error).await?;
+            }
+            message = ReadyForQuery::idle().message()?.backend();
+        }
 
         // Messages that we need to send to the client immediately.
         // ReadyForQuery (B) | CopyInResponse (B) | ErrorResponse(B) | NoticeResponse(B)
@@ -488,12 +752,57 @@ impl Client {
         // ReadyForQuery (B)
         if code == 'Z' {
             inner.stats.query();
+            inner.comms.query(None);
+            if let Some(execution) = self.execution.take() {
+                execution.finish();
+            }
             self.in_transaction = message.in_transaction();
+            self.telemetry.done(self.in_transaction);
             inner.stats.idle(self.in_transaction);
         }
 
         inner.stats.sent(message.len());
 
+        // CommandComplete (B)
+        if code == 'C' {
+            if let Some(ref mut execution) = self.execution {
+                match CommandComplete::from_bytes(message.to_bytes()?).and_then(|cc| cc.rows()) {
+                    Ok(rows) => execution.rows(rows.unwrap_or(0)),
+                    Err(err) => warn!("query stats: {} [{}]", err, self.addr),
+                }
+            }
+        }
+
+        // ErrorResponse (B)
+        if code == 'E' {
+            let error = ErrorResponse::from_bytes(message.to_bytes()?)?;
+            if connection_error(&error) {
+                let pool = inner
+                    .backend
+                    .addr()
+                    .ok()
+                    .and_then(|addr| addr.first().map(|addr| addr.to_string()))
+                    .unwrap_or_default();
+                record(ErrorEvent::new(pool, &error, self.addr));
+            }
+        }
+
+        // Restore the client's statement timeout after a statement
+        // that set it from a query comment. If the transaction failed,
+        // wait for the client to roll it back first.
+        if code == 'Z'
+            && !has_more_messages
+            && self.reset_statement_timeout
+            && !message.transaction_error()
+        {
+            let query = match self.params.get("statement_timeout") {
+                Some(value) => format!("SET statement_timeout TO {}", value),
+                None => "RESET statement_timeout".to_string(),
+            };
+            inner.backend.execute(&query).await?;
+            self.reset_statement_timeout = false;
+        }
+
         // Release the connection back into the pool
         // before flushing data to client.
         // Flushing can take a minute and we don't want to block
@@ -523,11 +832,17 @@ impl Client {
 
         trace!("[{}] <- {:#?}", self.addr, message);
 
+        let sent_at = Instant::now();
         if flush {
             self.stream.send_flush(&message).await?;
         } else {
             self.stream.send(&message).await?;
         }
+        inner.stats.client_sent(sent_at.elapsed());
+
+        if code == 'Z' {
+            self.wait_events(inner.stats.take_wait_events());
+        }
 
         // Pooler is offline or the client requested to disconnect and the transaction is done.
         if inner.backend.done() && (inner.comms.offline() || self.shutdown) && !self.admin {
@@ -546,11 +861,21 @@ impl Client {
 
         // Only start timer once we receive the first message.
         let mut timer = None;
+        let mut size = 0;
+        self.buffer_oversized = false;
 
         // Check config once per request.
         let config = config::config();
         self.prepared_statements.enabled = config.prepared_statements();
-        self.timeouts = Timeouts::from_config(&config.config.general);
+        let user = self.connect_params.get_default("user", "postgres");
+        let database = self.connect_params.get_default("database", user);
+        // Dumps and restores keep running without timeouts.
+        if self.dump.is_none() {
+            self.timeouts =
+                Timeouts::from_config(&config.config.general, config.users.find(user, database));
+        }
+        let warning = config.config.general.request_buffer_warning;
+        let limit = config.config.general.request_buffer_limit;
 
         while !self.request_buffer.full() {
             let message = match self.stream.read_buf(&mut self.stream_buffer).await {
@@ -560,10 +885,35 @@ impl Client {
                 }
             };
 
+            // After an error, the rest of the pipeline is ignored
+            // until Sync, which gets the ReadyForQuery.
+            if self.skip_to_sync && message.code() != 'X' {
+                if message.code() == 'S' {
+                    self.skip_to_sync = false;
+                    self.stream
+                        .send_flush(&ReadyForQuery::in_transaction(self.in_transaction))
+                        .await?;
+                }
+                continue;
+            }
+
             if timer.is_none() {
                 timer = Some(Instant::now());
             }
 
+            // Detect clients sending large batches without a Sync.
+            size += message.len();
+            if !self.buffer_oversized && warning > 0 && size > warning {
+                self.buffer_oversized = true;
+                warn!(
+                    "client buffered {} bytes without sync [{}]",
+                    size, self.addr
+                );
+            }
+            if limit > 0 && size > limit {
+                return Err(Error::RequestTooLarge(limit));
+            }
+
             // Terminate (B & F).
             if message.code() == 'X' {
                 self.shutdown = true;
@@ -579,15 +929,33 @@ impl Client {
             }
         }
 
+        self.buffer_time = timer.map(|timer| timer.elapsed()).unwrap_or_default();
+
         trace!(
             "request buffered [{:.4}ms]\n{:#?}",
-            timer.unwrap().elapsed().as_secs_f64() * 1000.0,
+            self.buffer_time.as_secs_f64() * 1000.0,
             self.request_buffer,
         );
 
         Ok(BufferEvent::HaveRequest)
     }
 
+    /// Reply to the request with an error.
+    ///
+    /// If the client pipelined extended protocol messages and only asked
+    /// for a Flush, it expects ReadyForQuery after its Sync, like PostgreSQL
+    /// does it.
+    async fn error(&mut self, error: ErrorResponse) -> Result<(), Error> {
+        if self.request_buffer.flush() {
+            self.stream.send_flush(&error).await?;
+            self.skip_to_sync = true;
+        } else {
+            self.stream.error(error).await?;
+        }
+
+        Ok(())
+    }
+
     /// Tell the client we started a transaction.
     async fn start_transaction(&mut self) -> Result<(), Error> {
         self.stream
@@ -622,6 +990,93 @@ impl Client {
         Ok(())
     }
 
+    /// Answer a query to the virtual pgdog schema.
+    async fn metadata(
+        &mut self,
+        metadata: Metadata,
+        mut inner: InnerBorrow<'_>,
+    ) -> Result<(), Error> {
+        let mut messages = if let Some(command) = metadata.admin_command() {
+            let messages = crate::admin::parser::Parser::parse(command)?
+                .execute()
+                .await?;
+            // Servers are listed with the user and database they connect with.
+            let addrs = if metadata == Metadata::Servers {
+                inner
+                    .backend
+                    .cluster()?
+                    .shards()
+                    .iter()
+                    .flat_map(|shard| shard.pools_with_roles())
+                    .map(|(_, pool)| pool.addr().clone())
+                    .collect()
+            } else {
+                vec![]
+            };
+            let visible = if addrs.is_empty() {
+                let user = self.connect_params.get_default("user", "postgres");
+                vec![(user, self.connect_params.get_default("database", user))]
+            } else {
+                addrs
+                    .iter()
+                    .map(|addr| (addr.user.as_str(), addr.database_name.as_str()))
+                    .collect()
+            };
+            Metadata::filter(messages, &visible)?
+        } else {
+            let rd = RowDescription::new(&[
+                Field::bigint("shard"),
+                Field::text("role"),
+                Field::text("host"),
+                Field::bigint("port"),
+                Field::text("database_name"),
+            ]);
+            let mut messages = vec![rd.message()?];
+            for (number, shard) in inner.backend.cluster()?.shards().iter().enumerate() {
+                for (role, pool) in shard.pools_with_roles() {
+                    let addr = pool.addr();
+                    let mut dr = DataRow::new();
+                    dr.add(number as i64)
+                        .add(role.to_string())
+                        .add(addr.host.as_str())
+                        .add(addr.port as i64)
+                        .add(addr.database_name.as_str());
+                    messages.push(dr.message()?);
+                }
+            }
+            messages
+        };
+
+        let rows = messages.len().saturating_sub(1);
+        messages.push(CommandComplete::from_str(&format!("SELECT {}", rows)).message()?);
+        messages.push(ReadyForQuery::in_transaction(self.in_transaction).message()?);
+        self.stream.send_many(&messages).await?;
+        inner.done(self.in_transaction);
+        debug!("metadata: {:?}", metadata);
+
+        Ok(())
+    }
+
+    async fn explain(
+        &mut self,
+        explain: &Explain,
+        mut inner: InnerBorrow<'_>,
+    ) -> Result<(), Error> {
+        let mut messages =
+            vec![RowDescription::new(&[Field::text("name"), Field::text("value")]).message()?];
+        for (name, value) in explain.rows() {
+            let mut dr = DataRow::new();
+            dr.add(*name).add(value.as_str());
+            messages.push(dr.message()?);
+        }
+        messages.push(CommandComplete::from_str("EXPLAIN").message()?);
+        messages.push(ReadyForQuery::in_transaction(self.in_transaction).message()?);
+        self.stream.send_many(&messages).await?;
+        inner.done(self.in_transaction);
+
+        Ok(())
+    }
+
     /// Handle SET command.
     async fn set(&mut self, mut inner: InnerBorrow<'_>) -> Result<(), Error> {
         self.stream.send(&CommandComplete::new("SET")).await?;
@@ -638,6 +1093,13 @@ impl Client {
 impl Drop for Client {
     fn drop(&mut self) {
         self.comms.disconnect();
+
+        let user = self.connect_params.get_default("user", "postgres");
+        emit(Event::ClientDisconnected {
+            client: self.addr,
+            user: user.to_owned(),
+            database: self.connect_params.get_default("database", user).to_owned(),
+        });
     }
 }
 
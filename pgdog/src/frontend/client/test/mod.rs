@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt, BufStream},
     net::{TcpListener, TcpStream},
@@ -8,15 +10,16 @@ use bytes::{Buf, BufMut, BytesMut};
 use crate::{
     backend::databases::databases,
     config::{
-        test::{load_test, load_test_replicas},
+        test::{load_test, load_test_replicas, ConfigGuard},
         Role,
     },
     frontend::{
-        client::{BufferEvent, Inner},
+        client::{timeouts::Timeouts, BufferEvent, Inner},
+        router::parser::Shard,
         Client, Command,
     },
     net::{
-        bind::Parameter, Bind, CommandComplete, DataRow, Describe, Execute, Field, Format,
+        bind::Parameter, Bind, CommandComplete, DataRow, Describe, Execute, Field, Flush, Format,
         FromBytes, Parse, Protocol, Query, ReadyForQuery, RowDescription, Sync, Terminate, ToBytes,
     },
     state::State,
@@ -430,3 +433,200 @@ async fn test_lock_session() {
 
     inner.disconnect();
 }
+
+#[tokio::test]
+async fn test_temp_table_pinning() {
+    let (mut conn, mut client, mut inner) = new_client!(false);
+
+    for (query, pinned) in [
+        (
+            "CREATE TEMP TABLE test_temp_table_pinning (id BIGINT)",
+            true,
+        ),
+        ("INSERT INTO test_temp_table_pinning VALUES (1)", true),
+        ("DROP TABLE test_temp_table_pinning", false),
+    ] {
+        conn.write_all(&buffer!({ Query::new(query) }))
+            .await
+            .unwrap();
+
+        client.buffer().await.unwrap();
+        client.client_messages(inner.get()).await.unwrap();
+
+        for c in ['C', 'Z'] {
+            let msg = inner.backend.read().await.unwrap();
+            assert_eq!(msg.code(), c);
+            client.server_message(inner.get(), msg).await.unwrap();
+        }
+
+        assert_eq!(inner.backend.connected(), pinned, "{}", query);
+        assert_eq!(inner.temp_tables.is_empty(), !pinned);
+    }
+}
+
+#[tokio::test]
+async fn test_request_buffer_limit() {
+    let (mut conn, mut client, _) = new_client!(false);
+    let _guard = ConfigGuard::default();
+
+    let mut config = (*crate::config::config()).clone();
+    config.config.general.request_buffer_warning = 32;
+    crate::config::set(config.clone()).unwrap();
+
+    conn.write_all(&buffer!(
+        { Parse::named("test", "SELECT $1 AS a_rather_long_column_name") },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+
+    let event = client.buffer().await.unwrap();
+    assert_eq!(event, BufferEvent::HaveRequest);
+    assert!(client.buffer_oversized);
+
+    config.config.general.request_buffer_limit = 32;
+    crate::config::set(config).unwrap();
+
+    conn.write_all(&buffer!(
+        { Parse::named("test", "SELECT $1 AS a_rather_long_column_name") },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+
+    let err = client.buffer().await.unwrap_err();
+    assert!(matches!(err, crate::frontend::Error::RequestTooLarge(32)));
+}
+
+#[tokio::test]
+async fn test_dump_timeouts() {
+    let (mut conn, mut client, _) = new_client!(false);
+    let _guard = ConfigGuard::default();
+
+    let mut config = (*crate::config::config()).clone();
+    config.config.general.query_timeout = 1000;
+    config.config.general.client_idle_timeout = 1000;
+    crate::config::set(config).unwrap();
+
+    client.dump = Some(Shard::Direct(0));
+    client.timeouts = Timeouts::default();
+
+    for _ in 0..2 {
+        conn.write_all(&buffer!({ Query::new("SELECT 1") }))
+            .await
+            .unwrap();
+
+        let event = client.buffer().await.unwrap();
+        assert_eq!(event, BufferEvent::HaveRequest);
+        assert_eq!(client.timeouts.query_timeout, Duration::MAX);
+        assert_eq!(client.timeouts.client_idle_timeout, Duration::MAX);
+    }
+}
+
+#[tokio::test]
+async fn test_pipeline_routing() {
+    let (mut conn, mut client, mut inner) = new_client!(true);
+
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous("SELECT 1") },
+        { Bind::test_statement("") },
+        { Execute::new() },
+        { Parse::new_anonymous("CREATE TABLE IF NOT EXISTS test_pipeline_routing (id BIGINT)") },
+        { Bind::test_statement("") },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+
+    client.buffer().await.unwrap();
+    let command = inner
+        .command(
+            &mut client.request_buffer,
+            &mut client.prepared_statements,
+            &client.params,
+        )
+        .unwrap();
+
+    // The write is in the same pipeline as the read, so both go to the primary.
+    match command {
+        Some(Command::Query(route)) => assert!(route.is_write()),
+        command => panic!("unexpected command: {:?}", command),
+    }
+}
+
+#[tokio::test]
+async fn test_pipeline_error() {
+    let (mut conn, mut client, _) = new_client!(false);
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous("SELECT bad syntax") },
+        { Bind::test_statement("") },
+        { Execute::new() },
+        { Flush }
+    ))
+    .await
+    .unwrap();
+
+    let _ = read!(conn, ['E']);
+
+    // Skipped until Sync.
+    conn.write_all(&buffer!(
+        { Parse::new_anonymous("SELECT 1") },
+        { Bind::test_statement("") },
+        { Execute::new() },
+        { Sync }
+    ))
+    .await
+    .unwrap();
+
+    let _ = read!(conn, ['Z']);
+
+    conn.write_all(&buffer!({ Query::new("SELECT 1") }, { Terminate }))
+        .await
+        .unwrap();
+
+    let _ = read!(conn, ['T', 'D', 'C', 'Z']);
+
+    handle.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_comment_statement_timeout() {
+    let (mut conn, mut client, _) = new_client!(true);
+
+    let handle = tokio::spawn(async move {
+        client.run().await.unwrap();
+    });
+
+    conn.write_all(&buffer!({ Query::new("BEGIN") }))
+        .await
+        .unwrap();
+    let _ = read!(conn, ['C', 'Z']);
+
+    for (query, timeout) in [
+        (
+            "/* pgdog_timeout: 1234 */ SELECT current_setting('statement_timeout')",
+            "1234ms",
+        ),
+        ("SELECT current_setting('statement_timeout')", "0"),
+    ] {
+        conn.write_all(&buffer!({ Query::new(query) }))
+            .await
+            .unwrap();
+        let msgs = read!(conn, ['T', 'D', 'C', 'Z']);
+        let row = DataRow::from_bytes(msgs[1].clone().freeze()).unwrap();
+        assert_eq!(row.get_text(0).unwrap(), timeout, "{}", query);
+    }
+
+    conn.write_all(&buffer!({ Query::new("COMMIT") }, { Terminate }))
+        .await
+        .unwrap();
+    let _ = read!(conn, ['C', 'Z']);
+
+    handle.await.unwrap();
+}
@@ -1,24 +1,40 @@
 use std::time::Duration;
 
-use crate::{config::General, state::State};
+use crate::{
+    config::{General, User},
+    state::State,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct Timeouts {
     pub(super) query_timeout: Duration,
+    pub(super) client_idle_timeout: Duration,
+    /// Query timeout requested with a `pgdog_timeout` comment.
+    pub(super) statement_timeout: Option<Duration>,
 }
 
 impl Default for Timeouts {
     fn default() -> Self {
         Self {
             query_timeout: Duration::MAX,
+            client_idle_timeout: Duration::MAX,
+            statement_timeout: None,
         }
     }
 }
 
 impl Timeouts {
-    pub(crate) fn from_config(general: &General) -> Self {
+    pub(crate) fn from_config(general: &General, user: Option<&User>) -> Self {
+        let client_idle_timeout = match user.and_then(|user| user.client_idle_timeout) {
+            Some(0) => Duration::MAX,
+            Some(timeout) => Duration::from_millis(timeout),
+            None => general.client_idle_timeout(),
+        };
+
         Self {
             query_timeout: general.query_timeout(),
+            client_idle_timeout,
+            statement_timeout: None,
         }
     }
 
@@ -26,8 +42,68 @@ impl Timeouts {
     #[inline]
     pub(crate) fn query_timeout(&self, state: &State) -> Duration {
         match state {
-            State::Active => self.query_timeout,
+            State::Active => self.statement_timeout.unwrap_or(self.query_timeout),
+            _ => Duration::MAX,
+        }
+    }
+
+    /// Get idle timeout. Clients inside a transaction are not idle.
+    #[inline]
+    pub(crate) fn client_idle_timeout(&self, state: &State, in_transaction: bool) -> Duration {
+        match state {
+            State::Idle if !in_transaction => self.client_idle_timeout,
             _ => Duration::MAX,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_client_idle_timeout() {
+        let general = General {
+            client_idle_timeout: 60_000,
+            ..Default::default()
+        };
+        let timeouts = Timeouts::from_config(&general, None);
+        assert_eq!(
+            timeouts.client_idle_timeout(&State::Idle, false),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            timeouts.client_idle_timeout(&State::Idle, true),
+            Duration::MAX
+        );
+        assert_eq!(
+            timeouts.client_idle_timeout(&State::Active, false),
+            Duration::MAX
+        );
+
+        let user = User {
+            client_idle_timeout: Some(0),
+            ..Default::default()
+        };
+        let timeouts = Timeouts::from_config(&general, Some(&user));
+        assert_eq!(
+            timeouts.client_idle_timeout(&State::Idle, false),
+            Duration::MAX
+        );
+    }
+
+    #[test]
+    fn test_statement_timeout() {
+        let general = General {
+            query_timeout: 30_000,
+            ..Default::default()
+        };
+        let mut timeouts = Timeouts::from_config(&general, None);
+        timeouts.statement_timeout = Some(Duration::from_secs(5));
+        assert_eq!(
+            timeouts.query_timeout(&State::Active),
+            Duration::from_secs(5)
+        );
+        assert_eq!(timeouts.query_timeout(&State::Idle), Duration::MAX);
+    }
+}
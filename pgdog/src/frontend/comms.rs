@@ -5,6 +5,7 @@ use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
+use std::time::Instant;
 
 use fnv::FnvHashMap as HashMap;
 use once_cell::sync::Lazy;
@@ -12,10 +13,11 @@ use parking_lot::Mutex;
 use tokio::sync::Notify;
 use tokio_util::task::TaskTracker;
 
+use crate::auth::Credential;
 use crate::net::messages::BackendKeyData;
 use crate::net::Parameters;
 
-use super::{ConnectedClient, Stats};
+use super::{connected_client::RunningQuery, ConnectedClient, Stats};
 
 static COMMS: Lazy<Comms> = Lazy::new(Comms::new);
 
@@ -24,10 +26,35 @@ pub fn comms() -> Comms {
     COMMS.clone()
 }
 
+/// How to shut down, from most to least patient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ShutdownMode {
+    /// Wait for clients to disconnect on their own.
+    Smart,
+    /// Wait for clients to finish their transactions, up to `shutdown_timeout`.
+    Fast,
+    /// Disconnect everyone right away.
+    Immediate,
+}
+
+impl std::fmt::Display for ShutdownMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Smart => write!(f, "smart"),
+            Self::Fast => write!(f, "fast"),
+            Self::Immediate => write!(f, "immediate"),
+        }
+    }
+}
+
 /// Sync primitives shared between all clients.
 struct Global {
     shutdown: Arc<Notify>,
     offline: AtomicBool,
+    /// Shutdown requested, and when.
+    shutdown_mode: Mutex<Option<(ShutdownMode, Instant)>>,
+    /// Tells the listener a shutdown was requested.
+    shutdown_requested: Arc<Notify>,
     // This uses the FNV hasher, which is safe,
     // because BackendKeyData is randomly generated by us,
     // not by the client.
@@ -55,6 +82,8 @@ impl Comms {
             global: Arc::new(Global {
                 shutdown: Arc::new(Notify::new()),
                 offline: AtomicBool::new(false),
+                shutdown_mode: Mutex::new(None),
+                shutdown_requested: Arc::new(Notify::new()),
                 clients: Mutex::new(HashMap::default()),
                 tracker: TaskTracker::new(),
             }),
@@ -67,6 +96,11 @@ impl Comms {
         self.global.clients.lock().clone()
     }
 
+    /// Get a connected client by its identifier.
+    pub fn client(&self, id: &BackendKeyData) -> Option<ConnectedClient> {
+        self.global.clients.lock().get(id).cloned()
+    }
+
     /// Number of connected clients.
     pub fn clients_len(&self) -> usize {
         self.global.clients.lock().len()
@@ -87,15 +121,42 @@ impl Comms {
     }
 
     /// New client connected.
-    pub fn connect(&mut self, id: &BackendKeyData, addr: SocketAddr, params: &Parameters) -> Self {
+    pub fn connect(
+        &mut self,
+        id: &BackendKeyData,
+        addr: SocketAddr,
+        params: &Parameters,
+        credential: Credential,
+    ) -> Self {
         self.global
             .clients
             .lock()
-            .insert(*id, ConnectedClient::new(addr, params));
+            .insert(*id, ConnectedClient::new(addr, params, credential));
         self.id = Some(*id);
         self.clone()
     }
 
+    /// Terminate the client with this process ID.
+    ///
+    /// Returns false if it's not connected.
+    pub fn kill(&self, pid: i32) -> bool {
+        let guard = self.global.clients.lock();
+        match guard.iter().find(|(id, _)| id.pid == pid) {
+            Some((_, client)) => {
+                client.kill.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Notified when this client is killed.
+    pub fn killed(&self) -> Arc<Notify> {
+        self.id
+            .and_then(|id| self.global.clients.lock().get(&id).map(|c| c.kill.clone()))
+            .unwrap_or_default()
+    }
+
     /// Update client parameters.
     pub fn update_params(&self, params: &Parameters) {
         if let Some(id) = self.id {
@@ -106,6 +167,16 @@ impl Comms {
         }
     }
 
+    /// Statement the client started or finished executing.
+    pub fn query(&self, query: Option<RunningQuery>) {
+        if let Some(id) = self.id {
+            let mut guard = self.global.clients.lock();
+            if let Some(entry) = guard.get_mut(&id) {
+                entry.query = query;
+            }
+        }
+    }
+
     /// Client disconnected.
     pub fn disconnect(&mut self) {
         if let Some(id) = self.id.take() {
@@ -123,6 +194,35 @@ impl Comms {
         }
     }
 
+    /// Ask the listener to shut down, e.g. with `SHUTDOWN`.
+    ///
+    /// A shutdown in progress can be made faster, but not slower.
+    pub fn request_shutdown(&self, mode: ShutdownMode) {
+        let mut guard = self.global.shutdown_mode.lock();
+        let started_at = match *guard {
+            Some((current, _)) if current >= mode => return,
+            Some((_, started_at)) => started_at,
+            None => Instant::now(),
+        };
+        *guard = Some((mode, started_at));
+        self.global.shutdown_requested.notify_one();
+    }
+
+    /// Shutdown mode requested, and when the shutdown started.
+    pub fn shutdown_mode(&self) -> Option<(ShutdownMode, Instant)> {
+        *self.global.shutdown_mode.lock()
+    }
+
+    /// Notified when a shutdown is requested.
+    pub fn shutdown_requested(&self) -> Arc<Notify> {
+        self.global.shutdown_requested.clone()
+    }
+
+    /// New clients are allowed to connect.
+    pub fn accepting(&self) -> bool {
+        !self.offline() && self.global.shutdown_mode.lock().is_none()
+    }
+
     /// Notify clients pgDog is shutting down.
     pub fn shutdown(&self) {
         self.global.offline.store(true, Ordering::Relaxed);
@@ -1,6 +1,11 @@
 use chrono::{DateTime, Local};
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
 
+use tokio::sync::Notify;
+
+use crate::auth::Credential;
 use crate::net::Parameters;
 
 use super::Stats;
@@ -16,16 +21,40 @@ pub struct ConnectedClient {
     pub connected_at: DateTime<Local>,
     /// Client connection parameters.
     pub paramters: Parameters,
+    /// Password the client authenticated with.
+    pub credential: Credential,
+    /// Terminate the client, e.g. with `KILL`.
+    pub kill: Arc<Notify>,
+    /// Statement the client is running, if any.
+    pub query: Option<RunningQuery>,
+}
+
+/// Statement sent by a client that didn't finish yet.
+#[derive(Clone, Debug)]
+pub struct RunningQuery {
+    /// Query text, as sent by the client.
+    pub query: String,
+    /// When the client sent it.
+    pub started_at: Instant,
+    /// Shard(s) it was sent to.
+    pub shard: String,
+    /// Sent to a replica.
+    pub read: bool,
+    /// Process IDs of the servers executing it.
+    pub backend_pids: Vec<i32>,
 }
 
 impl ConnectedClient {
     /// New connected client.
-    pub fn new(addr: SocketAddr, params: &Parameters) -> Self {
+    pub fn new(addr: SocketAddr, params: &Parameters, credential: Credential) -> Self {
         Self {
             stats: Stats::new(),
             addr,
             connected_at: Local::now(),
             paramters: params.clone(),
+            credential,
+            kill: Arc::new(Notify::new()),
+            query: None,
         }
     }
 }
@@ -45,6 +45,12 @@ pub enum Error {
 
     #[error("join error")]
     Join(#[from] tokio::task::JoinError),
+
+    #[error("{0}")]
+    Admin(#[from] crate::admin::Error),
+
+    #[error("request exceeds {0} bytes before sync")]
+    RequestTooLarge(usize),
 }
 
 impl Error {
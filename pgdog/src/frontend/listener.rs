@@ -2,6 +2,7 @@
 
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Instant;
 
 use crate::backend::databases::{databases, reload, shutdown};
 use crate::config::config;
@@ -10,6 +11,7 @@ use crate::net::messages::{hello::SslReply, Startup};
 use crate::net::tls::acceptor;
 use crate::net::{tweak, Stream};
 use crate::sighup::Sighup;
+use crate::stats::health;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::signal::ctrl_c;
 use tokio::sync::Notify;
@@ -19,7 +21,7 @@ use tokio::{select, spawn};
 use tracing::{error, info, warn};
 
 use super::{
-    comms::{comms, Comms},
+    comms::{comms, Comms, ShutdownMode},
     Client, Error,
 };
 
@@ -43,8 +45,9 @@ impl Listener {
     pub async fn listen(&mut self) -> Result<(), Error> {
         info!("🐕 PgDog listening on {}", self.addr);
         let listener = TcpListener::bind(&self.addr).await?;
+        health::listening(true);
         let comms = comms();
-        let shutdown_signal = comms.shutting_down();
+        let shutdown_requested = comms.shutdown_requested();
         let mut sighup = Sighup::new()?;
 
         loop {
@@ -53,7 +56,7 @@ impl Listener {
             select! {
                 connection = listener.accept() => {
                    let (stream, addr) = connection?;
-                   let offline = comms.offline();
+                   let offline = !comms.accepting();
 
                    let client_comms = comms.clone();
                    let future = async move {
@@ -72,12 +75,14 @@ impl Listener {
                    }
                 }
 
-                _ = shutdown_signal.notified() => {
-                    self.start_shutdown();
+                _ = shutdown_requested.notified() => {
+                    if let Some((mode, _)) = comms.shutdown_mode() {
+                        self.start_shutdown(mode);
+                    }
                 }
 
                 _ = ctrl_c() => {
-                    self.start_shutdown();
+                    comms.request_shutdown(ShutdownMode::Fast);
                 }
 
                 _ = sighup.listen() => {
@@ -100,14 +105,53 @@ impl Listener {
         self.shutdown.notify_one();
     }
 
-    fn start_shutdown(&self) {
-        shutdown();
-        comms().shutdown();
-
+    fn start_shutdown(&self, mode: ShutdownMode) {
+        info!("starting {} shutdown", mode);
         let listener = self.clone();
-        spawn(async move {
-            listener.execute_shutdown().await;
-        });
+
+        match mode {
+            ShutdownMode::Smart => {
+                spawn(async move {
+                    listener.smart_shutdown().await;
+                });
+            }
+
+            ShutdownMode::Fast => {
+                shutdown();
+                comms().shutdown();
+                spawn(async move {
+                    listener.execute_shutdown().await;
+                });
+            }
+
+            ShutdownMode::Immediate => {
+                shutdown();
+                comms().shutdown();
+                warn!(
+                    "terminating {} client connections immediately",
+                    comms().tracker().len()
+                );
+                // The listener isn't waiting for it yet, so keep the permit.
+                self.shutdown.notify_one();
+            }
+        }
+    }
+
+    /// Wait for clients to disconnect on their own; new ones can't connect.
+    async fn smart_shutdown(&self) {
+        let comms = comms();
+
+        info!(
+            "waiting for {} clients to disconnect",
+            comms.tracker().len()
+        );
+
+        comms.tracker().close();
+        comms.tracker().wait().await;
+
+        shutdown();
+        comms.shutdown();
+        self.shutdown.notify_one();
     }
 
     async fn execute_shutdown(&self) {
@@ -134,6 +178,7 @@ impl Listener {
     }
 
     async fn handle_client(stream: TcpStream, addr: SocketAddr, comms: Comms) -> Result<(), Error> {
+        let connected_at = Instant::now();
         tweak(&stream)?;
 
         let mut stream = Stream::plain(stream);
@@ -142,9 +187,14 @@ impl Listener {
         loop {
             let startup = Startup::from_stream(&mut stream).await?;
 
+            // Newer protocol versions and options aren't an error.
+            if let Some(negotiate) = startup.negotiate() {
+                stream.send_flush(&negotiate).await?;
+            }
+
             match startup {
                 Startup::Ssl => {
-                    if let Some(tls) = tls {
+                    if let Some(ref tls) = tls {
                         stream.send_flush(&SslReply::Yes).await?;
                         let plain = stream.take()?;
                         let cipher = tls.accept(plain).await?;
@@ -154,14 +204,16 @@ impl Listener {
                     }
                 }
 
-                Startup::Startup { params } => {
-                    Client::spawn(stream, params, addr, comms).await?;
+                Startup::Startup { params, .. } => {
+                    Client::spawn(stream, params, addr, comms, connected_at).await?;
                     break;
                 }
 
                 Startup::Cancel { pid, secret } => {
                     let id = BackendKeyData { pid, secret };
-                    let _ = databases().cancel(&id).await;
+                    if let Err(err) = databases().cancel(&id).await {
+                        error!("cancel request failed: {} [{}]", err, addr);
+                    }
                     break;
                 }
             }
@@ -1,5 +1,6 @@
 //! pgDog frontend manages connections to clients.
 
+pub mod audit;
 pub mod buffer;
 pub mod client;
 pub mod comms;
@@ -7,7 +8,6 @@ pub mod connected_client;
 pub mod error;
 pub mod listener;
 pub mod prepared_statements;
-#[cfg(debug_assertions)]
 pub mod query_logger;
 pub mod router;
 pub mod stats;
@@ -18,7 +18,6 @@ pub use comms::Comms;
 pub use connected_client::ConnectedClient;
 pub use error::Error;
 pub use prepared_statements::{PreparedStatements, Rewrite};
-#[cfg(debug_assertions)]
 pub use query_logger::QueryLogger;
 pub use router::{Command, Router};
 pub use router::{RouterContext, SearchPath};
@@ -79,6 +79,11 @@ impl PreparedStatements {
         self.local.len()
     }
 
+    /// Forget all statements prepared by the client.
+    pub fn clear(&mut self) {
+        self.local.clear();
+    }
+
     /// Is the local cache empty?
     pub fn is_empty(&self) -> bool {
         self.len() == 0
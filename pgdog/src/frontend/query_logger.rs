@@ -1,12 +1,21 @@
 //! Log queries to a file.
 //!
-//! DO NOT USE IN PRODUCTION. This is very slow.
-//!
-use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+//! Queries are written by the same background task as the audit logs, so
+//! clients never wait on disk. Set `query_log_sample_rate` to log only some
+//! of them and `query_log_normalize` to remove parameters. The file is
+//! rotated when it gets too large or old: `query.log` is renamed to
+//! `query.log.1`, `query.log.1` to `query.log.2`, and so on, up to
+//! `query_log_max_files`.
+use std::time::Duration;
+
+use rand::Rng;
 
 use crate::config::config;
 
-use super::{Buffer, Error};
+use super::{
+    audit::{append, Rotation},
+    Buffer, Error,
+};
 
 /// Log queries.
 pub struct QueryLogger<'a> {
@@ -20,21 +29,37 @@ impl<'a> QueryLogger<'a> {
     }
 
     /// Log queries
-    pub async fn log(&self) -> Result<(), Error> {
-        let path = &config().config.general.query_log;
-
-        if let Some(path) = path {
-            if let Some(query) = self.buffer.query()? {
-                let mut file = OpenOptions::new()
-                    .append(true)
-                    .create(true)
-                    .open(path)
-                    .await?;
-                let line = format!("{}\n", query.trim());
-                file.write_all(line.as_bytes()).await?;
-            }
+    pub fn log(&self) -> Result<(), Error> {
+        let config = config();
+        let general = &config.config.general;
+
+        let Some(ref path) = general.query_log else {
+            return Ok(());
+        };
+        let Some(query) = self.buffer.query()? else {
+            return Ok(());
+        };
+
+        if general.query_log_sample_rate < 1.0
+            && rand::thread_rng().gen::<f64>() >= general.query_log_sample_rate
+        {
+            return Ok(());
         }
 
+        let query = query.trim();
+        let query = if general.query_log_normalize {
+            pg_query::normalize(query).unwrap_or_else(|_| query.to_owned())
+        } else {
+            query.to_owned()
+        };
+
+        let rotation = Rotation {
+            max_size: general.query_log_max_size,
+            interval: Duration::from_millis(general.query_log_rotate_interval),
+            max_files: general.query_log_max_files,
+        };
+        append(path.clone(), format!("{}\n", query), Some(rotation));
+
         Ok(())
     }
 }
@@ -17,6 +17,8 @@ pub struct RouterContext<'a> {
     pub cluster: &'a Cluster,
     /// Client parameters, e.g. search_path.
     pub params: &'a Parameters,
+    /// Every statement in the buffer, if the client pipelined more than one.
+    pub pipeline: Vec<(BufferedQuery, Option<&'a Bind>)>,
 }
 
 impl<'a> RouterContext<'a> {
@@ -28,10 +30,16 @@ impl<'a> RouterContext<'a> {
     ) -> Result<Self, Error> {
         let query = buffer.query()?;
         let bind = buffer.parameters()?;
+        let pipeline = if buffer.executes() > 1 {
+            buffer.statements()?
+        } else {
+            vec![]
+        };
 
         Ok(Self {
             query,
             bind,
+            pipeline,
             params,
             prepared_statements: stmt,
             cluster,
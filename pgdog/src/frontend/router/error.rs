@@ -31,4 +31,14 @@ impl Error {
     pub fn empty_query(&self) -> bool {
         matches!(self, Self::Parser(super::parser::Error::EmptyQuery))
     }
+
+    /// The query can't be executed across shards.
+    pub fn cross_shard(&self) -> bool {
+        matches!(
+            self,
+            Self::Parser(
+                super::parser::Error::CrossShard(_) | super::parser::Error::PipelineCrossShard
+            )
+        )
+    }
 }
@@ -42,6 +42,16 @@ impl Router {
         self.query_parser.replication_mode();
     }
 
+    /// Send all queries to the primary of this shard.
+    pub fn pin_shard(&mut self, shard: usize) {
+        self.query_parser.pin_shard(shard);
+    }
+
+    /// Route statements sent by pg_restore into all shards.
+    pub fn restore(&mut self) {
+        self.query_parser.restore();
+    }
+
     /// Route a query to a shard.
     ///
     /// If the router can't determine the route for the query to take,
@@ -57,6 +67,11 @@ impl Router {
         Ok(self.query_parser.copy_data(buffer.copy_data()?)?)
     }
 
+    /// The client finished sending COPY data.
+    pub fn copy_done(&mut self) {
+        self.query_parser.copy_done()
+    }
+
     /// Get current route.
     pub fn route(&self) -> Route {
         self.query_parser.route()
@@ -1,13 +1,18 @@
 use pg_query::protobuf::Integer;
-use pg_query::protobuf::{self, a_const::Val, SelectStmt};
+use pg_query::protobuf::{
+    self, a_const::Val, AConst, AExpr, AExprKind, FuncCall, LimitOption, Node, ResTarget,
+    SelectStmt, SetOperation, TypeCast, TypeName,
+};
 use pg_query::NodeEnum;
 
-use super::Error;
+use super::{Distinct, Error, Having, Unsupported};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AggregateTarget {
     column: usize,
     function: AggregateFunction,
+    helpers: Option<Helpers>,
+    distinct: Option<usize>,
 }
 
 impl AggregateTarget {
@@ -18,6 +23,28 @@ impl AggregateTarget {
     pub fn column(&self) -> usize {
         self.column
     }
+
+    /// Columns added to the query to compute this aggregate across shards.
+    pub fn helpers(&self) -> Option<&Helpers> {
+        self.helpers.as_ref()
+    }
+
+    /// Column with each shard's distinct values, as an array.
+    pub fn distinct(&self) -> Option<usize> {
+        self.distinct
+    }
+}
+
+/// Columns fetched from each shard to compute aggregates
+/// that can't be merged directly, e.g. `AVG(x)` is `SUM(x) / COUNT(x)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Helpers {
+    /// `SUM(x)`.
+    pub sum: usize,
+    /// `COUNT(x)`.
+    pub count: usize,
+    /// `SUM(x * x)`, for variance and standard deviation.
+    pub sum_squares: Option<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -27,77 +54,381 @@ pub enum AggregateFunction {
     Min,
     Avg,
     Sum,
+    VarSamp,
+    VarPop,
+    StddevSamp,
+    StddevPop,
+    CountDistinct,
+    ArrayAggDistinct,
+}
+
+impl AggregateFunction {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "count" => Self::Count,
+            "max" => Self::Max,
+            "min" => Self::Min,
+            "avg" => Self::Avg,
+            "sum" => Self::Sum,
+            "variance" | "var_samp" => Self::VarSamp,
+            "var_pop" => Self::VarPop,
+            "stddev" | "stddev_samp" => Self::StddevSamp,
+            "stddev_pop" => Self::StddevPop,
+            _ => return None,
+        })
+    }
+
+    /// Function name, as written in SQL.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Count => "count",
+            Self::Max => "max",
+            Self::Min => "min",
+            Self::Avg => "avg",
+            Self::Sum => "sum",
+            Self::VarSamp => "var_samp",
+            Self::VarPop => "var_pop",
+            Self::StddevSamp => "stddev_samp",
+            Self::StddevPop => "stddev_pop",
+            Self::CountDistinct => "count_distinct",
+            Self::ArrayAggDistinct => "array_agg_distinct",
+        }
+    }
+
+    /// Computed from the sum and count (and sum of squares) of each shard.
+    pub fn decomposed(&self) -> bool {
+        matches!(
+            self,
+            Self::Avg | Self::VarSamp | Self::VarPop | Self::StddevSamp | Self::StddevPop
+        )
+    }
+
+    fn sum_squares(&self) -> bool {
+        self.decomposed() && *self != Self::Avg
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Aggregate {
     targets: Vec<AggregateTarget>,
     group_by: Vec<usize>,
+    hidden: usize,
+    having: Option<Having>,
 }
 
 impl Aggregate {
     /// Figure out what aggregates are present and which ones PgDog supports.
     pub fn parse(stmt: &SelectStmt) -> Result<Self, Error> {
-        let mut targets = vec![];
+        // Only deparse the result columns if we need to
+        // match GROUP BY expressions to them.
+        let expressions = if stmt.group_clause.iter().all(Self::positional) {
+            vec![]
+        } else {
+            Self::expressions(&stmt.target_list)
+        };
+
         let group_by = stmt
             .group_clause
             .iter()
-            .filter_map(|node| {
-                node.node.as_ref().map(|node| match node {
-                    NodeEnum::AConst(aconst) => aconst.val.as_ref().map(|val| match val {
-                        Val::Ival(Integer { ival }) => Some(*ival as usize - 1), // We use 0-indexed arrays, Postgres uses 1-indexed.
-                        _ => None,
-                    }),
-                    _ => None,
-                })
-            })
-            .flatten()
-            .flatten()
+            .filter_map(|node| Self::group_by_column(&stmt.target_list, &expressions, node))
             .collect::<Vec<_>>();
 
-        for (idx, node) in stmt.target_list.iter().enumerate() {
-            if let Some(NodeEnum::ResTarget(ref res)) = &node.node {
-                if let Some(node) = &res.val {
-                    if let Some(NodeEnum::FuncCall(func)) = &node.node {
-                        if let Some(name) = func.funcname.first() {
-                            if let Some(NodeEnum::String(protobuf::String { sval })) = &name.node {
-                                match sval.as_str() {
-                                    "count" => {
-                                        targets.push(AggregateTarget {
-                                            column: idx,
-                                            function: AggregateFunction::Count,
-                                        });
-                                    }
-
-                                    "max" => {
-                                        targets.push(AggregateTarget {
-                                            column: idx,
-                                            function: AggregateFunction::Max,
-                                        });
-                                    }
-
-                                    "min" => {
-                                        targets.push(AggregateTarget {
-                                            column: idx,
-                                            function: AggregateFunction::Min,
-                                        });
-                                    }
-
-                                    "sum" => targets.push(AggregateTarget {
-                                        column: idx,
-                                        function: AggregateFunction::Max,
-                                    }),
-
-                                    _ => {}
-                                }
-                            }
-                        }
-                    }
+        let targets = stmt
+            .target_list
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, node)| Self::target(idx, Self::func_call(node)?))
+            .collect();
+
+        Ok(Self {
+            targets,
+            group_by,
+            hidden: 0,
+            having: None,
+        })
+    }
+
+    fn target(column: usize, func: &FuncCall) -> Option<AggregateTarget> {
+        // Window functions aren't aggregates.
+        if func.over.is_some() {
+            return None;
+        }
+
+        let Some(NodeEnum::String(protobuf::String { sval })) = &func.funcname.first()?.node else {
+            return None;
+        };
+
+        let function = match (AggregateFunction::from_name(sval), func.agg_distinct) {
+            (Some(AggregateFunction::Count), true) => AggregateFunction::CountDistinct,
+            (None, true) if sval == "array_agg" => AggregateFunction::ArrayAggDistinct,
+            (function, _) => function?,
+        };
+
+        Some(AggregateTarget {
+            column,
+            // Each shard returns its distinct values already.
+            distinct: if function == AggregateFunction::ArrayAggDistinct {
+                Some(column)
+            } else {
+                None
+            },
+            function,
+            helpers: None,
+        })
+    }
+
+    /// Add the columns needed to compute AVG, VARIANCE, STDDEV and COUNT(DISTINCT)
+    /// across shards to the end of the target list, along with GROUP BY expressions
+    /// and aggregates used in HAVING that aren't in it already.
+    ///
+    /// HAVING is removed from the query, so it can be applied after merging
+    /// groups from all shards.
+    ///
+    /// Returns true if the statement was changed. Added columns are removed
+    /// from the results before they are sent to the client.
+    pub fn rewrite(&mut self, stmt: &mut SelectStmt) -> Result<bool, Unsupported> {
+        let columns = stmt.target_list.len();
+        let mut expressions = Self::expressions(&stmt.target_list);
+
+        // Groups are matched using all GROUP BY columns.
+        for node in &stmt.group_clause {
+            if Self::positional(node)
+                || matches!(node.node, Some(NodeEnum::GroupingSet(_)))
+                || Self::group_by_column(&stmt.target_list, &expressions, node).is_some()
+            {
+                continue;
+            }
+
+            self.group_by.push(stmt.target_list.len());
+            expressions.push(Self::expression(node));
+            stmt.target_list.push(Self::res_target(node.clone()));
+        }
+
+        if let Some(clause) = stmt.having_clause.take() {
+            let (targets, target_list) = (&mut self.targets, &mut stmt.target_list);
+            self.having = Having::new(&clause, &mut |node| {
+                if let Some(column) = Self::column(&expressions, node) {
+                    return Some(column);
                 }
+
+                // Fetch aggregates that aren't in the result as well.
+                let Some(NodeEnum::FuncCall(ref func)) = node.node else {
+                    return None;
+                };
+                let column = target_list.len();
+                targets.push(Self::target(column, func)?);
+                expressions.push(Self::expression(node));
+                target_list.push(Self::res_target(node.clone()));
+
+                Some(column)
+            });
+
+            // Let Postgres handle what we can't.
+            if self.having.is_none() {
+                stmt.having_clause = Some(clause);
+            }
+        }
+
+        for target in self.targets.iter_mut() {
+            if !target.function.decomposed() && target.function != AggregateFunction::CountDistinct
+            {
+                continue;
+            }
+
+            let Some(func) = stmt
+                .target_list
+                .get(target.column)
+                .and_then(Self::func_call)
+            else {
+                continue;
+            };
+
+            if func.args.len() != 1 {
+                continue;
+            }
+
+            // Fetch the distinct values from each shard and count them
+            // after removing duplicates.
+            if target.function == AggregateFunction::CountDistinct {
+                let array_agg = FuncCall {
+                    funcname: vec![Self::string("array_agg")],
+                    ..func.clone()
+                };
+                target.distinct = Some(stmt.target_list.len());
+                stmt.target_list
+                    .push(Self::res_target(Self::func(array_agg)));
+                continue;
+            }
+
+            // DISTINCT values can repeat between shards.
+            if func.agg_distinct {
+                return Err(Unsupported::DistinctAggregate);
+            }
+
+            let sum = FuncCall {
+                funcname: vec![Self::string("sum")],
+                ..func.clone()
+            };
+            let count = FuncCall {
+                funcname: vec![Self::string("count")],
+                ..func.clone()
+            };
+            let sum_squares = if target.function.sum_squares() {
+                let arg = Self::numeric(func.args[0].clone());
+                Some(FuncCall {
+                    funcname: vec![Self::string("sum")],
+                    args: vec![Node {
+                        node: Some(NodeEnum::AExpr(Box::new(AExpr {
+                            kind: AExprKind::AexprOp.into(),
+                            name: vec![Self::string("*")],
+                            lexpr: Some(Box::new(arg.clone())),
+                            rexpr: Some(Box::new(arg)),
+                            location: -1,
+                        }))),
+                    }],
+                    ..func.clone()
+                })
+            } else {
+                None
+            };
+
+            let next = stmt.target_list.len();
+            let helpers = Helpers {
+                sum: next,
+                count: next + 1,
+                sum_squares: sum_squares.as_ref().map(|_| next + 2),
+            };
+
+            for func in [Some(sum), Some(count), sum_squares].into_iter().flatten() {
+                stmt.target_list.push(Self::res_target(Self::func(func)));
             }
+
+            target.helpers = Some(helpers);
         }
 
-        Ok(Self { targets, group_by })
+        self.hidden = stmt.target_list.len() - columns;
+        let changed = self.hidden > 0 || self.having.is_some();
+
+        // Extra columns change the result of DISTINCT,
+        // so it's applied after merging instead.
+        if changed && !stmt.distinct_clause.is_empty() {
+            if Distinct::new(stmt).is_none() {
+                return Err(Unsupported::DistinctOnAggregate);
+            }
+            stmt.distinct_clause.clear();
+        }
+
+        Ok(changed)
+    }
+
+    fn func_call(node: &Node) -> Option<&FuncCall> {
+        if let Some(NodeEnum::ResTarget(ref res)) = node.node {
+            if let Some(ref val) = res.val {
+                if let Some(NodeEnum::FuncCall(ref func)) = val.node {
+                    return Some(func.as_ref());
+                }
+            }
+        }
+
+        None
+    }
+
+    /// GROUP BY 1, 2, etc.
+    fn positional(node: &Node) -> bool {
+        matches!(node.node, Some(NodeEnum::AConst(_)))
+    }
+
+    /// Result column used by GROUP BY, matched by position, expression or alias.
+    fn group_by_column(
+        target_list: &[Node],
+        expressions: &[Option<String>],
+        node: &Node,
+    ) -> Option<usize> {
+        match node.node.as_ref()? {
+            NodeEnum::AConst(AConst {
+                val: Some(Val::Ival(Integer { ival })),
+                ..
+            }) => usize::try_from(*ival).ok()?.checked_sub(1), // We use 0-indexed arrays, Postgres uses 1-indexed.
+            NodeEnum::AConst(_) | NodeEnum::GroupingSet(_) => None,
+            NodeEnum::ColumnRef(column) => Self::column(expressions, node).or_else(|| {
+                let Some(NodeEnum::String(ref name)) = column.fields.first()?.node else {
+                    return None;
+                };
+                target_list.iter().position(|target| {
+                    matches!(target.node, Some(NodeEnum::ResTarget(ref res)) if res.name == name.sval)
+                })
+            }),
+            _ => Self::column(expressions, node),
+        }
+    }
+
+    /// Result column with the same expression.
+    pub(crate) fn column(expressions: &[Option<String>], node: &Node) -> Option<usize> {
+        let expression = Self::expression(node)?;
+        expressions
+            .iter()
+            .position(|e| e.as_ref() == Some(&expression))
+    }
+
+    pub(crate) fn expressions(target_list: &[Node]) -> Vec<Option<String>> {
+        target_list
+            .iter()
+            .map(|node| match node.node {
+                Some(NodeEnum::ResTarget(ref res)) => res.val.as_deref().and_then(Self::expression),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Expression as SQL, so we can compare them without their positions in the query.
+    fn expression(node: &Node) -> Option<String> {
+        NodeEnum::SelectStmt(Box::new(SelectStmt {
+            target_list: vec![Self::res_target(node.clone())],
+            op: SetOperation::SetopNone.into(),
+            limit_option: LimitOption::Default.into(),
+            ..Default::default()
+        }))
+        .deparse()
+        .ok()
+    }
+
+    fn res_target(val: Node) -> Node {
+        Node {
+            node: Some(NodeEnum::ResTarget(Box::new(ResTarget {
+                val: Some(Box::new(val)),
+                location: -1,
+                ..Default::default()
+            }))),
+        }
+    }
+
+    fn func(func: FuncCall) -> Node {
+        Node {
+            node: Some(NodeEnum::FuncCall(Box::new(func))),
+        }
+    }
+
+    fn string(sval: &str) -> Node {
+        Node {
+            node: Some(NodeEnum::String(protobuf::String { sval: sval.into() })),
+        }
+    }
+
+    /// Sum of squares is computed in NUMERIC, so it's exact
+    /// for integers and NUMERIC, like in Postgres.
+    fn numeric(arg: Node) -> Node {
+        Node {
+            node: Some(NodeEnum::TypeCast(Box::new(TypeCast {
+                arg: Some(Box::new(arg)),
+                type_name: Some(TypeName {
+                    names: vec![Self::string("pg_catalog"), Self::string("numeric")],
+                    typemod: -1,
+                    location: -1,
+                    ..Default::default()
+                }),
+                location: -1,
+            }))),
+        }
     }
 
     pub fn targets(&self) -> &[AggregateTarget] {
@@ -108,13 +439,28 @@ impl Aggregate {
         &self.group_by
     }
 
+    /// HAVING applied to merged groups.
+    pub fn having(&self) -> Option<&Having> {
+        self.having.as_ref()
+    }
+
+    /// Number of columns added to the end of each row
+    /// by [`Aggregate::rewrite`].
+    pub fn hidden(&self) -> usize {
+        self.hidden
+    }
+
     pub fn new_count(column: usize) -> Self {
         Self {
             targets: vec![AggregateTarget {
                 function: AggregateFunction::Count,
                 column,
+                helpers: None,
+                distinct: None,
             }],
             group_by: vec![],
+            hidden: 0,
+            having: None,
         }
     }
 
@@ -123,16 +469,154 @@ impl Aggregate {
             targets: vec![AggregateTarget {
                 function: AggregateFunction::Count,
                 column,
+                helpers: None,
+                distinct: None,
             }],
             group_by: group_by.to_vec(),
+            hidden: 0,
+            having: None,
         }
     }
 
+    /// No aggregates and no groups to merge.
     pub fn is_empty(&self) -> bool {
-        self.len() == 0
+        self.len() == 0 && self.group_by.is_empty()
     }
 
     pub fn len(&self) -> usize {
         self.targets.len()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use super::*;
+
+    fn select(query: &str) -> SelectStmt {
+        let ast = parse(query).unwrap();
+        match ast.protobuf.stmts[0].stmt.as_ref().unwrap().node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => (**stmt).clone(),
+            _ => panic!("not a select"),
+        }
+    }
+
+    fn rewrite(aggregate: &mut Aggregate, stmt: &SelectStmt) -> Option<String> {
+        let mut stmt = stmt.clone();
+        if aggregate.rewrite(&mut stmt).unwrap() {
+            Some(NodeEnum::SelectStmt(Box::new(stmt)).deparse().unwrap())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_rewrite_avg() {
+        let stmt =
+            select("SELECT avg(price), stddev_pop(price), sum(price) FROM sharded GROUP BY 3");
+        let mut aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(aggregate.targets()[2].function(), &AggregateFunction::Sum);
+
+        let query = rewrite(&mut aggregate, &stmt).unwrap();
+        assert_eq!(select(&query).target_list.len(), 8);
+        assert_eq!(aggregate.hidden(), 5);
+        assert_eq!(
+            aggregate.targets()[0].helpers(),
+            Some(&Helpers {
+                sum: 3,
+                count: 4,
+                sum_squares: None
+            })
+        );
+        assert_eq!(
+            aggregate.targets()[1].helpers(),
+            Some(&Helpers {
+                sum: 5,
+                count: 6,
+                sum_squares: Some(7)
+            })
+        );
+        assert_eq!(aggregate.targets()[2].helpers(), None);
+
+        let stmt = select("SELECT avg(price) FROM sharded");
+        let mut aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(
+            rewrite(&mut aggregate, &stmt).unwrap(),
+            "SELECT avg(price), sum(price), count(price) FROM sharded"
+        );
+
+        let stmt = select("SELECT count(*), avg(DISTINCT price) FROM sharded");
+        let mut aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(
+            aggregate.rewrite(&mut stmt.clone()),
+            Err(Unsupported::DistinctAggregate)
+        );
+
+        // Window functions aren't merged.
+        let stmt = select("SELECT id, avg(price) OVER (PARTITION BY id) FROM sharded");
+        assert!(Aggregate::parse(&stmt).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rewrite_select_distinct() {
+        // Duplicates are removed after merging.
+        let stmt = select("SELECT DISTINCT kind, avg(price) FROM sharded GROUP BY kind");
+        let mut aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(
+            rewrite(&mut aggregate, &stmt).unwrap(),
+            "SELECT kind, avg(price), sum(price), count(price) FROM sharded GROUP BY kind"
+        );
+
+        let stmt =
+            select("SELECT DISTINCT ON (lower(kind)) kind, avg(price) FROM sharded GROUP BY kind");
+        let mut aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(
+            aggregate.rewrite(&mut stmt.clone()),
+            Err(Unsupported::DistinctOnAggregate)
+        );
+
+        // Nothing to fetch, so each shard removes duplicates too.
+        let stmt = select("SELECT DISTINCT kind, count(*) FROM sharded GROUP BY kind");
+        let mut aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(rewrite(&mut aggregate, &stmt), None);
+    }
+
+    #[test]
+    fn test_rewrite_count_distinct() {
+        let stmt = select("SELECT count(DISTINCT email), array_agg(DISTINCT id) FROM sharded");
+        let mut aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(
+            aggregate.targets()[0].function(),
+            &AggregateFunction::CountDistinct
+        );
+        assert_eq!(aggregate.targets()[1].distinct(), Some(1));
+
+        assert_eq!(
+            rewrite(&mut aggregate, &stmt).unwrap(),
+            "SELECT count(DISTINCT email), array_agg(DISTINCT id), array_agg(DISTINCT email) FROM sharded"
+        );
+        assert_eq!(aggregate.targets()[0].distinct(), Some(2));
+        assert_eq!(aggregate.hidden(), 1);
+    }
+
+    #[test]
+    fn test_rewrite_group_by_having() {
+        let stmt = select(
+            "SELECT email AS e, lower(name), count(*) FROM sharded \
+            GROUP BY e, lower(name), tenant_id HAVING count(*) > 1 AND max(id) < 100",
+        );
+        let mut aggregate = Aggregate::parse(&stmt).unwrap();
+        assert_eq!(aggregate.group_by(), &[0, 1]);
+
+        assert_eq!(
+            rewrite(&mut aggregate, &stmt).unwrap(),
+            "SELECT email AS e, lower(name), count(*), tenant_id, max(id) FROM sharded \
+            GROUP BY e, lower(name), tenant_id"
+        );
+        assert_eq!(aggregate.group_by(), &[0, 1, 3]);
+        assert_eq!(aggregate.hidden(), 2);
+        assert_eq!(aggregate.targets().len(), 2);
+        assert!(aggregate.having().is_some());
+    }
+}
@@ -1,16 +1,24 @@
-//! AST cache.
+//! AST and routing decision cache.
 //!
 //! Shared between all clients and databases.
 
 use once_cell::sync::Lazy;
 use pg_query::*;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
 
 use parking_lot::Mutex;
 use std::sync::Arc;
 
 use super::{Error, Route};
 use crate::frontend::buffer::BufferedQuery;
+use crate::net::messages::{Bind, Format};
+
+/// Don't cache routes for queries with parameters larger than this,
+/// they are unlikely to be repeated.
+const ROUTE_MAX_PARAMS_SIZE: usize = 1024;
 
 static CACHE: Lazy<Cache> = Lazy::new(Cache::default);
 
@@ -27,6 +35,12 @@ pub struct Stats {
     pub multi: usize,
     /// Size of the cache.
     pub size: usize,
+    /// Routing decisions found in the cache.
+    pub route_hits: usize,
+    /// Routing decisions not in the cache.
+    pub route_misses: usize,
+    /// Number of routing decisions in the cache.
+    pub route_size: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -63,10 +77,85 @@ struct Inner {
     stats: Stats,
 }
 
+/// Routing decision cache key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RouteKey {
+    database: String,
+    user: String,
+    query: String,
+    params: Vec<(Format, Vec<u8>)>,
+}
+
+impl RouteKey {
+    /// Key for a prepared statement executed with these parameters. Returns `None`
+    /// if the parameters are too large to be worth caching.
+    pub fn new(database: &str, user: &str, query: &str, bind: &Bind) -> Option<Self> {
+        let mut params = Vec::with_capacity(bind.params_len());
+        let mut size = 0;
+
+        for index in 0..bind.params_len() {
+            let param = bind.parameter(index).ok()??;
+            size += param.data().len();
+            if size > ROUTE_MAX_PARAMS_SIZE {
+                return None;
+            }
+            params.push((param.format(), param.data().to_vec()));
+        }
+
+        Some(Self {
+            database: database.to_owned(),
+            user: user.to_owned(),
+            query: query.to_owned(),
+            params,
+        })
+    }
+}
+
+/// Least recently used routing decisions.
+#[derive(Default, Debug)]
+struct Routes {
+    routes: HashMap<RouteKey, (Route, u64)>,
+    used: BTreeMap<u64, RouteKey>,
+    counter: u64,
+}
+
+impl Routes {
+    fn get(&mut self, key: &RouteKey) -> Option<Route> {
+        self.counter += 1;
+        let (route, used) = self.routes.get_mut(key)?;
+        let key = self.used.remove(used)?;
+        *used = self.counter;
+        self.used.insert(self.counter, key);
+
+        Some(route.clone())
+    }
+
+    fn insert(&mut self, key: RouteKey, route: Route, capacity: usize) {
+        self.counter += 1;
+        if let Some((_, used)) = self.routes.insert(key.clone(), (route, self.counter)) {
+            self.used.remove(&used);
+        }
+        self.used.insert(self.counter, key);
+
+        while self.routes.len() > capacity {
+            let Some((_, key)) = self.used.pop_first() else {
+                break;
+            };
+            self.routes.remove(&key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.routes.clear();
+        self.used.clear();
+    }
+}
+
 /// AST cache.
 #[derive(Default, Clone, Debug)]
 pub struct Cache {
     inner: Arc<Mutex<Inner>>,
+    routes: Arc<Mutex<Routes>>,
 }
 
 impl Cache {
@@ -105,6 +194,32 @@ impl Cache {
         CACHE.clone()
     }
 
+    /// Get a routing decision made for the same query and parameters.
+    pub fn route(&self, key: &RouteKey) -> Option<Route> {
+        let route = self.routes.lock().get(key);
+
+        let mut guard = self.inner.lock();
+        if route.is_some() {
+            guard.stats.route_hits += 1;
+        } else {
+            guard.stats.route_misses += 1;
+        }
+
+        route
+    }
+
+    /// Save a routing decision, evicting the least recently used ones
+    /// if the cache has more than `capacity` entries.
+    pub fn record_route(&self, key: RouteKey, route: &Route, capacity: usize) {
+        self.routes.lock().insert(key, route.clone(), capacity);
+    }
+
+    /// Remove all routing decisions, e.g. because the sharding
+    /// configuration has changed.
+    pub fn invalidate_routes() {
+        Self::get().routes.lock().clear();
+    }
+
     pub fn record_command(
         &self,
         query: &BufferedQuery,
@@ -172,6 +287,7 @@ impl Cache {
         let guard = cache.inner.lock();
         let mut stats = guard.stats;
         stats.size = guard.queries.len();
+        stats.route_size = cache.routes.lock().routes.len();
         stats
     }
 
@@ -188,6 +304,9 @@ impl Cache {
         guard.queries.shrink_to_fit();
         guard.stats.hits = 0;
         guard.stats.misses = 0;
+        guard.stats.route_hits = 0;
+        guard.stats.route_misses = 0;
+        cache.routes.lock().clear();
     }
 }
 
@@ -196,6 +315,7 @@ mod test {
     use tokio::spawn;
 
     use super::*;
+    use crate::frontend::router::parser::Shard;
     use std::time::{Duration, Instant};
 
     #[tokio::test(flavor = "multi_thread")]
@@ -279,6 +399,46 @@ mod test {
         assert!(faster > 10.0);
     }
 
+    #[test]
+    fn test_routes_lru() {
+        let bind = |id: &str| {
+            Bind::test_params(
+                "",
+                &[crate::net::messages::Parameter {
+                    len: id.len() as i32,
+                    data: id.as_bytes().to_vec(),
+                }],
+            )
+        };
+        let key = |id: &str| {
+            RouteKey::new(
+                "pgdog",
+                "pgdog",
+                "SELECT * FROM sharded WHERE id = $1",
+                &bind(id),
+            )
+            .unwrap()
+        };
+
+        let mut routes = Routes::default();
+        routes.insert(key("1"), Route::read(Some(0)), 2);
+        routes.insert(key("2"), Route::read(Some(1)), 2);
+        assert_eq!(routes.get(&key("1")).unwrap().shard(), &Shard::Direct(0));
+
+        // "2" is the least recently used.
+        routes.insert(key("3"), Route::read(Some(1)), 2);
+        assert!(routes.get(&key("2")).is_none());
+        assert!(routes.get(&key("1")).is_some());
+        assert!(routes.get(&key("3")).is_some());
+        assert_eq!(routes.routes.len(), routes.used.len());
+
+        routes.clear();
+        assert!(routes.get(&key("1")).is_none());
+
+        let large = "1".repeat(ROUTE_MAX_PARAMS_SIZE + 1);
+        assert!(RouteKey::new("pgdog", "pgdog", "SELECT $1", &bind(&large)).is_none());
+    }
+
     #[test]
     fn test_normalize() {
         let q = "SELECT * FROM users WHERE id = 1";
@@ -14,6 +14,9 @@ pub enum Command {
     PreparedStatement(Prepare),
     Rewrite(String),
     Shards(usize),
+    Intercept(Intercept),
+    Metadata(Metadata),
+    Explain(Box<Explain>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
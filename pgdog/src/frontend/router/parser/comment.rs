@@ -1,50 +1,205 @@
+use std::time::Duration;
+
 use once_cell::sync::Lazy;
 use pg_query::{protobuf::Token, scan};
 use regex::Regex;
 
 use crate::backend::ShardingSchema;
+use crate::config::Role;
 use crate::frontend::router::sharding::ContextBuilder;
 
-use super::super::parser::Shard;
+use super::super::parser::{Route, Shard};
 use super::Error;
 
 static SHARD: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_shard: *([0-9]+)"#).unwrap());
 static SHARDING_KEY: Lazy<Regex> =
-    Lazy::new(|| Regex::new(r#"pgdog_sharding_key: *([0-9a-zA-Z]+)"#).unwrap());
-
-/// Extract shard number from a comment.
-///
-/// Comment style uses the C-style comments (not SQL comments!)
-/// as to allow the comment to appear anywhere in the query.
-///
-/// See [`SHARD`] and [`SHARDING_KEY`] for the style of comment we expect.
-///
-pub fn shard(query: &str, schema: &ShardingSchema) -> Result<Shard, Error> {
-    let tokens = scan(query).map_err(Error::PgQuery)?;
-
-    for token in tokens.tokens.iter() {
-        if token.token == Token::CComment as i32 {
-            let comment = &query[token.start as usize..token.end as usize];
-            if let Some(cap) = SHARDING_KEY.captures(comment) {
-                if let Some(sharding_key) = cap.get(1) {
-                    let ctx = ContextBuilder::from_str(sharding_key.as_str())?
-                        .shards(schema.shards)
-                        .build()?;
-                    return Ok(ctx.apply()?);
-                }
+    Lazy::new(|| Regex::new(r#"pgdog_sharding_key: *(?:'([^']*)'|([0-9a-zA-Z_\-]+))"#).unwrap());
+static ROLE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_role: *(primary|replica)\b"#).unwrap());
+static TIMEOUT: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_timeout: *([0-9]+)"#).unwrap());
+static REPLICA: Lazy<Regex> = Lazy::new(|| Regex::new(r#"pgdog_replica: *(sync|any)\b"#).unwrap());
+
+/// Routing hints found in query comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// Shard from `pgdog_shard` or `pgdog_sharding_key`.
+    pub shard: Shard,
+    /// Role from `pgdog_role`.
+    pub role: Option<Role>,
+    /// Statement timeout from `pgdog_timeout`, in milliseconds.
+    pub timeout: Option<Duration>,
+    /// Read from sync replicas, from `pgdog_replica: sync`.
+    pub sync_replica: bool,
+}
+
+impl Default for Comment {
+    fn default() -> Self {
+        Self {
+            shard: Shard::All,
+            role: None,
+            timeout: None,
+            sync_replica: false,
+        }
+    }
+}
+
+impl Comment {
+    /// Extract routing hints from query comments.
+    ///
+    /// Comment style uses the C-style comments (not SQL comments!)
+    /// as to allow the comment to appear anywhere in the query, e.g.:
+    ///
+    /// ```sql
+    /// /* pgdog_sharding_key: 1234 pgdog_role: replica pgdog_timeout: 5000 */ SELECT * FROM users
+    /// ```
+    ///
+    /// See [`SHARD`], [`SHARDING_KEY`], [`ROLE`], [`TIMEOUT`] and [`REPLICA`]
+    /// for the style of comment we expect.
+    ///
+    pub fn parse(query: &str, schema: &ShardingSchema) -> Result<Self, Error> {
+        let mut comment = Self::default();
+
+        // Skip the tokenizer for queries without hints.
+        if !query.contains("pgdog_") {
+            return Ok(comment);
+        }
+
+        let tokens = scan(query).map_err(Error::PgQuery)?;
+
+        for token in tokens.tokens.iter() {
+            if token.token != Token::CComment as i32 {
+                continue;
             }
-            if let Some(cap) = SHARD.captures(comment) {
-                if let Some(shard) = cap.get(1) {
-                    return Ok(shard
-                        .as_str()
-                        .parse::<usize>()
-                        .ok()
-                        .map(Shard::Direct)
-                        .unwrap_or(Shard::All));
+
+            let text = &query[token.start as usize..token.end as usize];
+
+            if comment.shard.all() {
+                comment.shard = Self::shard(text, schema)?;
+            }
+
+            if let Some(role) = ROLE.captures(text).and_then(|cap| cap.get(1)) {
+                comment.role = Some(match role.as_str() {
+                    "replica" => Role::Replica,
+                    _ => Role::Primary,
+                });
+            }
+
+            if let Some(timeout) = TIMEOUT.captures(text).and_then(|cap| cap.get(1)) {
+                comment.timeout = timeout
+                    .as_str()
+                    .parse::<u64>()
+                    .ok()
+                    .map(Duration::from_millis);
+            }
+
+            if let Some(replica) = REPLICA.captures(text).and_then(|cap| cap.get(1)) {
+                comment.sync_replica = replica.as_str() == "sync";
+            }
+        }
+
+        Ok(comment)
+    }
+
+    /// Apply role, timeout and replica hints to the route.
+    ///
+    /// `pgdog_role: replica` can't send writes to a replica, so it's ignored for them.
+    pub fn apply(&self, mut route: Route) -> Route {
+        if let Some(Role::Primary) = self.role {
+            route.set_read_mut(false);
+        }
+
+        if self.timeout.is_some() {
+            route.set_timeout_mut(self.timeout);
+        }
+
+        if self.sync_replica {
+            route.set_sync_replica_mut(true);
+        }
+
+        route
+    }
+
+    fn shard(comment: &str, schema: &ShardingSchema) -> Result<Shard, Error> {
+        if let Some(cap) = SHARDING_KEY.captures(comment) {
+            if let Some(sharding_key) = cap.get(1).or(cap.get(2)) {
+                let sharding_key = sharding_key.as_str();
+                // Use the sharded table's data type and sharding function
+                // if there is only one, otherwise guess.
+                let ctx = match schema.tables.tables() {
+                    [table] => ContextBuilder::new(table).data(sharding_key),
+                    _ => ContextBuilder::from_str(sharding_key)?,
                 }
+                .shards(schema.shards)
+                .build()?;
+                return Ok(ctx.apply()?);
+            }
+        }
+
+        if let Some(cap) = SHARD.captures(comment) {
+            if let Some(shard) = cap.get(1) {
+                return Ok(shard
+                    .as_str()
+                    .parse::<usize>()
+                    .ok()
+                    .map(Shard::Direct)
+                    .unwrap_or(Shard::All));
             }
         }
+
+        Ok(Shard::All)
     }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backend::Cluster;
+
+    use super::*;
+
+    #[test]
+    fn test_comment() {
+        let schema = Cluster::new_test().sharding_schema();
+
+        let comment = Comment::parse(
+            "/* pgdog_role: replica pgdog_timeout: 5000 */ SELECT * FROM sharded",
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(comment.role, Some(Role::Replica));
+        assert_eq!(comment.timeout, Some(Duration::from_secs(5)));
+        assert!(comment.shard.all());
+        assert!(!comment.sync_replica);
 
-    Ok(Shard::All)
+        let comment = Comment::parse(
+            "/* pgdog_role: replica pgdog_replica: sync */ SELECT * FROM sharded",
+            &schema,
+        )
+        .unwrap();
+        assert!(comment.sync_replica);
+        let route = comment.apply(Route::read(Some(0)));
+        assert!(route.is_read());
+        assert!(route.sync_replica());
+
+        // Writes stay on the primary.
+        let route = comment.apply(Route::write(Some(0)));
+        assert!(route.is_write());
+
+        let comment = Comment::parse(
+            "SELECT * FROM sharded /* pgdog_sharding_key: '1234' */ /* pgdog_role: primary */",
+            &schema,
+        )
+        .unwrap();
+        assert_eq!(comment.role, Some(Role::Primary));
+        assert!(matches!(comment.shard, Shard::Direct(_)));
+
+        let route = comment.apply(Route::read(Some(0)));
+        assert!(route.is_write());
+        assert_eq!(route.timeout(), None);
+
+        let comment = Comment::parse("/* pgdog_shard: 1 */ SELECT 1", &schema).unwrap();
+        assert_eq!(comment.shard, Shard::Direct(1));
+
+        // Hints must be in C-style comments.
+        let comment = Comment::parse("SELECT 'pgdog_role: replica'", &schema).unwrap();
+        assert_eq!(comment, Comment::default());
+    }
 }
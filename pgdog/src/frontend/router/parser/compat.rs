@@ -0,0 +1,144 @@
+//! Compatibility shims for known client driver behaviors.
+//!
+//! Some drivers send statements on connect or on pool checkout
+//! that either do nothing useful or actively break transaction pooling.
+//! We answer those locally instead of sending them to a server.
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::config::Compatibility;
+
+/// JDBC sends `extra_float_digits = 3` on every connection. Since Postgres 12,
+/// any positive value prints floats the same way as the default of 1, so setting
+/// it on a shared server connection is skipped. Zero and negative values round
+/// floats, so they're sent to the server, and so are values out of range.
+static EXTRA_FLOAT_DIGITS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^\s*SET\s+(SESSION\s+)?extra_float_digits\s*(=|TO)\s*'?0*([1-3])'?\s*;?\s*$")
+        .unwrap()
+});
+
+/// Npgsql and pgx reset connections with these before returning them to
+/// their own pools. Server-side prepared statements are managed by PgDog,
+/// so dropping them from the server would break other clients.
+static DISCARD_ALL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^\s*DISCARD\s+ALL\s*;?\s*$").unwrap());
+static DEALLOCATE_ALL: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)^\s*DEALLOCATE\s+(PREPARE\s+)?ALL\s*;?\s*$").unwrap());
+
+/// Statement answered by PgDog without going to a server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intercept {
+    /// `SET extra_float_digits`.
+    ExtraFloatDigits(u8),
+    /// `DISCARD ALL`.
+    DiscardAll,
+    /// `DEALLOCATE ALL`.
+    DeallocateAll,
+}
+
+impl Intercept {
+    /// Check if the query should be answered locally.
+    pub fn new(query: &str, compatibility: &Compatibility) -> Option<Self> {
+        if let Some(captures) = compatibility
+            .extra_float_digits
+            .then(|| EXTRA_FLOAT_DIGITS.captures(query))
+            .flatten()
+        {
+            captures[3].parse().ok().map(Self::ExtraFloatDigits)
+        } else if compatibility.discard_all && DISCARD_ALL.is_match(query) {
+            Some(Self::DiscardAll)
+        } else if compatibility.discard_all && DEALLOCATE_ALL.is_match(query) {
+            Some(Self::DeallocateAll)
+        } else {
+            None
+        }
+    }
+
+    /// Tag returned to the client in CommandComplete.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::ExtraFloatDigits(_) => "SET",
+            Self::DiscardAll => "DISCARD ALL",
+            Self::DeallocateAll => "DEALLOCATE ALL",
+        }
+    }
+
+    /// Session parameter set by the statement.
+    pub fn param(&self) -> Option<(&'static str, String)> {
+        match self {
+            Self::ExtraFloatDigits(value) => Some(("extra_float_digits", value.to_string())),
+            _ => None,
+        }
+    }
+
+    /// Statement can't run inside a transaction block.
+    pub fn outside_transaction(&self) -> bool {
+        matches!(self, Self::DiscardAll)
+    }
+
+    /// Statement resets session parameters.
+    pub fn resets_params(&self) -> bool {
+        matches!(self, Self::DiscardAll)
+    }
+
+    /// Statement closes client prepared statements.
+    pub fn deallocates(&self) -> bool {
+        matches!(self, Self::DiscardAll | Self::DeallocateAll)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_intercept() {
+        let compat = Compatibility::default();
+        for (query, expected) in [
+            (
+                "SET extra_float_digits = 3",
+                Some(Intercept::ExtraFloatDigits(3)),
+            ),
+            (
+                "set extra_float_digits to '2';",
+                Some(Intercept::ExtraFloatDigits(2)),
+            ),
+            (
+                "SET SESSION extra_float_digits = 1",
+                Some(Intercept::ExtraFloatDigits(1)),
+            ),
+            ("SET extra_float_digits = 4", None),
+            ("SET extra_float_digits = -1", None),
+            ("SET extra_float_digits = 0", None),
+            ("DISCARD ALL", Some(Intercept::DiscardAll)),
+            ("discard all;", Some(Intercept::DiscardAll)),
+            ("DEALLOCATE ALL", Some(Intercept::DeallocateAll)),
+            ("DEALLOCATE PREPARE ALL", Some(Intercept::DeallocateAll)),
+            ("DEALLOCATE __pgx_1", None),
+            ("DISCARD TEMP", None),
+            ("SET statement_timeout TO 1", None),
+            ("SELECT 1", None),
+        ] {
+            assert_eq!(Intercept::new(query, &compat), expected, "{}", query);
+        }
+
+        let compat = Compatibility {
+            extra_float_digits: false,
+            discard_all: false,
+            ..Default::default()
+        };
+        assert!(Intercept::new("SET extra_float_digits = 3", &compat).is_none());
+        assert!(Intercept::new("DISCARD ALL", &compat).is_none());
+    }
+
+    #[test]
+    fn test_intercept_param() {
+        let compat = Compatibility::default();
+        let intercept = Intercept::new("SET extra_float_digits = 3", &compat).unwrap();
+        assert_eq!(
+            intercept.param(),
+            Some(("extra_float_digits", "3".to_string()))
+        );
+        assert!(Intercept::DiscardAll.param().is_none());
+    }
+}
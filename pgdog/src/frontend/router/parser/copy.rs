@@ -1,10 +1,18 @@
 //! Parse COPY statement.
 
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
 use pg_query::{protobuf::CopyStmt, NodeEnum};
+use regex::Regex;
 
 use crate::{
     backend::{Cluster, ShardingSchema},
-    config::ShardedTable,
+    config::{config, ShardedTable},
     frontend::router::{
         parser::Shard,
         sharding::{ContextBuilder, Tables},
@@ -13,7 +21,9 @@ use crate::{
     net::messages::{CopyData, ToBytes},
 };
 
-use super::{binary::Data, BinaryStream, Column, CsvStream, Error, Table};
+use super::{
+    binary::Data, csv::CsvOptions, progress::Tracker, BinaryStream, Column, CsvStream, Error, Table,
+};
 
 /// Copy information parsed from a COPY statement.
 #[derive(Debug, Clone)]
@@ -70,6 +80,17 @@ pub struct CopyParser {
     sharded_table: Option<ShardedTable>,
     /// The sharding column is in this position in each row.
     sharded_column: usize,
+    /// Extracts the sharding key from the column value.
+    key_pattern: Option<Regex>,
+    /// Rows and bytes received so far.
+    progress: Option<Arc<Tracker>>,
+    /// Rows that can't be sharded are written here instead of aborting the COPY.
+    reject_file: Option<RejectFile>,
+    /// Number of rows read, including the header. CSV rows can span lines.
+    row: usize,
+    /// `ON_ERROR ignore`: skip rows that can't be sharded, like Postgres
+    /// skips rows it can't parse.
+    skip_errors: bool,
 }
 
 impl Default for CopyParser {
@@ -83,6 +104,11 @@ impl Default for CopyParser {
             sharding_schema: ShardingSchema::default(),
             sharded_table: None,
             sharded_column: 0,
+            key_pattern: None,
+            progress: None,
+            reject_file: None,
+            row: 0,
+            skip_errors: false,
         }
     }
 }
@@ -92,10 +118,20 @@ impl CopyParser {
     pub fn new(stmt: &CopyStmt, cluster: &Cluster) -> Result<Option<Self>, Error> {
         let mut parser = Self {
             is_from: stmt.is_from,
+            reject_file: config()
+                .config
+                .general
+                .copy_reject_file
+                .clone()
+                .map(RejectFile::new),
             ..Default::default()
         };
 
         let mut format = CopyFormat::Text;
+        let mut quote = None;
+        let mut escape = None;
+        let mut null = None;
+        let mut force_not_null = vec![];
 
         if let Some(ref rel) = stmt.relation {
             let mut columns = vec![];
@@ -108,9 +144,22 @@ impl CopyParser {
 
             let table = Table::from(rel);
 
+            if stmt.is_from {
+                parser.progress = Some(Arc::new(Tracker::new(
+                    table.name,
+                    cluster.sharding_schema().shards,
+                )));
+            }
+
             if let Some(key) = Tables::new(&cluster.sharding_schema()).key(table, &columns) {
                 parser.sharded_table = Some(key.table.clone());
                 parser.sharded_column = key.position;
+                parser.key_pattern = key
+                    .table
+                    .key_pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()?;
             }
 
             parser.columns = columns.len();
@@ -147,8 +196,51 @@ impl CopyParser {
                             }
                         }
 
+                        // The statement is sent to the shards as-is, so options we don't
+                        // need for splitting rows, e.g. FREEZE, are passed through.
                         "header" => {
-                            parser.headers = true;
+                            parser.headers = Self::header_option(elem.arg.as_deref());
+                        }
+
+                        "on_error" => {
+                            parser.skip_errors = matches!(
+                                elem.arg.as_ref().and_then(|arg| arg.node.as_ref()),
+                                Some(NodeEnum::String(ref string)) if string.sval.eq_ignore_ascii_case("ignore")
+                            );
+                        }
+
+                        "quote" => quote = Self::char_option(elem.arg.as_deref()),
+
+                        "escape" => escape = Self::char_option(elem.arg.as_deref()),
+
+                        "null" => {
+                            if let Some(NodeEnum::String(ref string)) =
+                                elem.arg.as_ref().and_then(|arg| arg.node.as_ref())
+                            {
+                                null = Some(string.sval.clone());
+                            }
+                        }
+
+                        "force_not_null" => {
+                            match elem.arg.as_ref().and_then(|arg| arg.node.as_ref()) {
+                                // FORCE_NOT_NULL *
+                                Some(NodeEnum::AStar(_)) => {
+                                    force_not_null = (0..columns.len()).collect()
+                                }
+                                Some(NodeEnum::List(ref list)) => {
+                                    for item in &list.items {
+                                        if let Ok(name) = Column::from_string(item) {
+                                            if let Some(position) = columns
+                                                .iter()
+                                                .position(|column| column.name == name.name)
+                                            {
+                                                force_not_null.push(position);
+                                            }
+                                        }
+                                    }
+                                }
+                                _ => (),
+                            }
                         }
 
                         _ => (),
@@ -160,17 +252,63 @@ impl CopyParser {
         parser.stream = if format == CopyFormat::Binary {
             CopyStream::Binary(BinaryStream::default())
         } else {
-            CopyStream::Text(Box::new(CsvStream::new(
-                parser.delimiter(),
-                parser.headers,
-                format,
-            )))
+            let mut options = CsvOptions::new(parser.delimiter(), parser.headers, format);
+            if let Some(quote) = quote {
+                options.quote = quote;
+            }
+            // Escape is the same as quote unless specified.
+            options.escape = escape.unwrap_or(options.quote);
+            if let Some(null) = null {
+                options.null = null;
+            }
+            options.force_not_null = force_not_null;
+            CopyStream::Text(Box::new(CsvStream::with_options(options)))
         };
         parser.sharding_schema = cluster.sharding_schema();
 
         Ok(Some(parser))
     }
 
+    /// Rows are split between shards using a sharding key.
+    pub fn sharded(&self) -> bool {
+        self.sharded_table.is_some()
+    }
+
+    /// The client finished or aborted the COPY, so it's no longer shown as running.
+    pub fn finish(&mut self) {
+        self.progress = None;
+    }
+
+    /// `COPY ... TO STDOUT` output starts with a header.
+    pub fn copy_out_headers(&self) -> bool {
+        !self.is_from && self.headers
+    }
+
+    /// `HEADER`, `HEADER false`, `HEADER MATCH`, etc.
+    fn header_option(arg: Option<&pg_query::Node>) -> bool {
+        let Some(arg) = arg else {
+            return true;
+        };
+
+        match arg.node {
+            Some(NodeEnum::String(ref string)) => !matches!(
+                string.sval.to_lowercase().as_str(),
+                "false" | "off" | "0" | "no"
+            ),
+            Some(NodeEnum::Integer(ref integer)) => integer.ival != 0,
+            Some(NodeEnum::Boolean(ref boolean)) => boolean.boolval,
+            _ => true,
+        }
+    }
+
+    /// Single character option, e.g. `QUOTE '"'`.
+    fn char_option(arg: Option<&pg_query::Node>) -> Option<char> {
+        match arg?.node {
+            Some(NodeEnum::String(ref string)) => string.sval.chars().next(),
+            _ => None,
+        }
+    }
+
     #[inline]
     fn delimiter(&self) -> char {
         self.delimiter.unwrap_or('\t')
@@ -180,6 +318,9 @@ impl CopyParser {
     /// with shard numbers.
     pub fn shard(&mut self, data: Vec<CopyData>) -> Result<Vec<CopyRow>, Error> {
         let mut rows = vec![];
+        let bytes = data.iter().map(|row| row.data().len()).sum::<usize>();
+        let mut routed = vec![0; self.sharding_schema.shards];
+        let mut parsed = 0;
 
         for row in data {
             match &mut self.stream {
@@ -192,6 +333,7 @@ impl CopyParser {
                     if self.headers && self.is_from {
                         let headers = stream.headers()?;
                         if let Some(headers) = headers {
+                            self.row += 1;
                             rows.push(CopyRow::new(headers.to_string().as_bytes(), Shard::All));
                         }
                         self.headers = false;
@@ -200,22 +342,41 @@ impl CopyParser {
                     for record in stream.records() {
                         // Totally broken.
                         let record = record?;
+                        self.row += 1;
 
-                        let shard = if let Some(table) = &self.sharded_table {
-                            let key = record
-                                .get(self.sharded_column)
-                                .ok_or(Error::NoShardingColumn)?;
+                        // End-of-data marker, e.g. in plain pg_dump output.
+                        // It's not a row, so it can't be sharded.
+                        if record.len() == 1 && record.get(0) == Some("\\.") {
+                            continue;
+                        }
 
-                            let ctx = ContextBuilder::new(table)
-                                .data(key)
-                                .shards(self.sharding_schema.shards)
-                                .build()?;
+                        let shard = match self.sharded_table {
+                            // NULLs go to all shards, same as in binary COPY.
+                            Some(ref table) if !record.is_null(self.sharded_column) => record
+                                .get(self.sharded_column)
+                                .ok_or(Error::NoShardingColumn)
+                                .and_then(|key| {
+                                    Ok(ContextBuilder::new(table)
+                                        .data(Self::key(self.key_pattern.as_ref(), key)?)
+                                        .shards(self.sharding_schema.shards)
+                                        .build()?
+                                        .apply()?)
+                                }),
+                            _ => Ok(Shard::All),
+                        };
 
-                            ctx.apply()?
-                        } else {
-                            Shard::All
+                        let shard = match (shard, &mut self.reject_file) {
+                            (Ok(shard), _) => shard,
+                            (Err(err), Some(reject_file)) => {
+                                reject_file.write(self.row, &err, record.to_string().as_bytes())?;
+                                continue;
+                            }
+                            (Err(_), None) if self.skip_errors => continue,
+                            (Err(err), None) => return Err(err),
                         };
 
+                        parsed += 1;
+                        Self::routed(&mut routed, &shard);
                         rows.push(CopyRow::new(record.to_string().as_bytes(), shard));
                     }
                 }
@@ -229,37 +390,147 @@ impl CopyParser {
 
                     for tuple in stream.tuples() {
                         let tuple = tuple?;
+                        self.row += 1;
                         if tuple.end() {
                             let terminator = (-1_i16).to_be_bytes();
                             rows.push(CopyRow::new(&terminator, Shard::All));
                             break;
                         }
                         let shard = if let Some(table) = &self.sharded_table {
-                            let key = tuple
-                                .get(self.sharded_column)
-                                .ok_or(Error::NoShardingColumn)?;
-                            if let Data::Column(key) = key {
-                                let ctx = ContextBuilder::new(&table)
-                                    .data(&key[..])
-                                    .shards(self.sharding_schema.shards)
-                                    .build()?;
-
-                                ctx.apply()?
-                            } else {
-                                Shard::All
+                            match tuple.get(self.sharded_column) {
+                                Some(Data::Column(key)) => match self.key_pattern {
+                                    // Only works for text columns.
+                                    Some(ref pattern) => std::str::from_utf8(key)
+                                        .map_err(|_| Error::KeyPattern)
+                                        .and_then(|key| Self::key(Some(pattern), key))
+                                        .and_then(|key| {
+                                            Ok(ContextBuilder::new(table)
+                                                .data(key)
+                                                .shards(self.sharding_schema.shards)
+                                                .build()?
+                                                .apply()?)
+                                        }),
+                                    None => ContextBuilder::new(table)
+                                        .data(&key[..])
+                                        .shards(self.sharding_schema.shards)
+                                        .build()
+                                        .and_then(|ctx| ctx.apply())
+                                        .map_err(Error::from),
+                                },
+                                Some(_) => Ok(Shard::All),
+                                None => Err(Error::NoShardingColumn),
                             }
                         } else {
-                            Shard::All
+                            Ok(Shard::All)
+                        };
+
+                        let shard = match (shard, &mut self.reject_file) {
+                            (Ok(shard), _) => shard,
+                            (Err(err), Some(reject_file)) => {
+                                let hex = tuple
+                                    .to_bytes()?
+                                    .iter()
+                                    .map(|byte| format!("{:02x}", byte))
+                                    .collect::<String>();
+                                reject_file.write(
+                                    self.row,
+                                    &err,
+                                    format!("\\x{}\n", hex).as_bytes(),
+                                )?;
+                                continue;
+                            }
+                            (Err(err), None) => return Err(err),
                         };
 
+                        parsed += 1;
+                        Self::routed(&mut routed, &shard);
                         rows.push(CopyRow::new(&tuple.to_bytes()?, shard));
                     }
                 }
             }
         }
 
+        if let Some(ref mut reject_file) = self.reject_file {
+            reject_file.flush()?;
+        }
+
+        if let Some(ref progress) = self.progress {
+            progress.record(bytes, parsed, &routed);
+        }
+
         Ok(rows)
     }
+
+    /// Extract the sharding key from the column value.
+    fn key<'a>(pattern: Option<&Regex>, value: &'a str) -> Result<&'a str, Error> {
+        let Some(pattern) = pattern else {
+            return Ok(value);
+        };
+        let captures = pattern.captures(value).ok_or(Error::KeyPattern)?;
+
+        Ok(captures
+            .get(1)
+            .or(captures.get(0))
+            .map(|key| key.as_str())
+            .unwrap_or_default())
+    }
+
+    /// Count a row sent to the shard(s).
+    fn routed(routed: &mut [usize], shard: &Shard) {
+        for (number, count) in routed.iter_mut().enumerate() {
+            let sent = match shard {
+                Shard::Direct(direct) => *direct == number,
+                Shard::All => true,
+                Shard::Multi(multi) => multi.contains(&number),
+            };
+            if sent {
+                *count += 1;
+            }
+        }
+    }
+}
+
+/// File rows that can't be sharded are appended to, with their row number and error.
+/// It's opened on the first rejected row and kept open until the COPY is done.
+#[derive(Debug)]
+struct RejectFile {
+    path: PathBuf,
+    file: Option<BufWriter<File>>,
+}
+
+impl Clone for RejectFile {
+    fn clone(&self) -> Self {
+        Self::new(self.path.clone())
+    }
+}
+
+impl RejectFile {
+    fn new(path: PathBuf) -> Self {
+        Self { path, file: None }
+    }
+
+    fn write(&mut self, row: usize, err: &Error, data: &[u8]) -> Result<(), Error> {
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => self.file.insert(BufWriter::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            )),
+        };
+        write!(file, "{}\t{}\t", row, err)?;
+        file.write_all(data)?;
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if let Some(ref mut file) = self.file {
+            file.flush()?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -290,6 +561,11 @@ mod test {
         let sharded = copy.shard(vec![one, two]).unwrap();
         assert_eq!(sharded[0].message().data(), b"5\thello world\n");
         assert_eq!(sharded[1].message().data(), b"10\thowdy mate\n");
+
+        // Dropping the tracker removes the COPY from SHOW COPY.
+        assert!(copy.progress.is_some());
+        copy.finish();
+        assert!(copy.progress.is_none());
     }
 
     #[test]
@@ -331,6 +607,134 @@ mod test {
         assert_eq!(sharded[0].message().data(), b"\"1\",\"2\"\n");
     }
 
+    #[test]
+    fn test_copy_csv_options() {
+        let copy = "COPY sharded (id, value) FROM STDIN \
+            (FORMAT csv, DELIMITER ';', QUOTE '''', ESCAPE '\\', NULL 'null', FORCE_NOT_NULL (value))";
+        let stmt = parse(copy).unwrap();
+        let stmt = stmt.protobuf.stmts.first().unwrap();
+        let copy = match stmt.stmt.clone().unwrap().node.unwrap() {
+            NodeEnum::CopyStmt(copy) => copy,
+            _ => panic!("not a copy"),
+        };
+
+        let mut copy = CopyParser::new(&copy, &Cluster::default())
+            .unwrap()
+            .unwrap();
+
+        let one = CopyData::new(b"5;'hello; \\'world\\''\n");
+        let two = CopyData::new(b"null;null\n");
+        let three = CopyData::new(b"'null';'a\\\\b'\n");
+        let sharded = copy.shard(vec![one, two, three]).unwrap();
+        assert_eq!(sharded[0].message().data(), b"'5';'hello; \\'world\\''\n");
+        // Sharding key is NULL, value isn't because of FORCE_NOT_NULL.
+        assert_eq!(sharded[1].message().data(), b"null;'null'\n");
+        assert_eq!(sharded[1].shard(), &Shard::All);
+        assert_eq!(sharded[2].message().data(), b"'null';'a\\\\b'\n");
+    }
+
+    #[test]
+    fn test_copy_reject_file() {
+        let copy = "COPY sharded (id, value) FROM STDIN CSV";
+        let stmt = parse(copy).unwrap();
+        let stmt = stmt.protobuf.stmts.first().unwrap();
+        let stmt = match stmt.stmt.clone().unwrap().node.unwrap() {
+            NodeEnum::CopyStmt(copy) => copy,
+            _ => panic!("not a copy"),
+        };
+
+        let mut copy = CopyParser::new(&stmt, &Cluster::new_test())
+            .unwrap()
+            .unwrap();
+        let rows = || {
+            vec![
+                CopyData::new(b"1,one\n"),
+                CopyData::new(b"two,two\n"),
+                CopyData::new(b"3,three\n"),
+            ]
+        };
+        assert!(copy.shard(rows()).is_err());
+
+        let reject_file = std::env::temp_dir().join(format!("pgdog_reject_{}", std::process::id()));
+        let mut copy = CopyParser::new(&stmt, &Cluster::new_test())
+            .unwrap()
+            .unwrap();
+        copy.reject_file = Some(RejectFile::new(reject_file.clone()));
+        let sharded = copy.shard(rows()).unwrap();
+        assert_eq!(sharded.len(), 2);
+        let rejected = std::fs::read_to_string(&reject_file).unwrap();
+        assert!(rejected.starts_with("2\t"));
+        assert!(rejected.ends_with("\t\"two\",\"two\"\n"));
+
+        // Rows are numbered, not lines: a quoted value can have a line break.
+        copy.shard(vec![CopyData::new(b"\"four\",\"fo\nur\"\n")])
+            .unwrap();
+        let rejected = std::fs::read_to_string(&reject_file).unwrap();
+        std::fs::remove_file(&reject_file).unwrap();
+        assert!(rejected.lines().nth(1).unwrap().starts_with("4\t"));
+    }
+
+    #[test]
+    fn test_copy_options() {
+        let parser = |query: &str| {
+            let stmt = parse(query).unwrap();
+            let stmt = stmt.protobuf.stmts.first().unwrap();
+            let copy = match stmt.stmt.clone().unwrap().node.unwrap() {
+                NodeEnum::CopyStmt(copy) => copy,
+                _ => panic!("not a copy"),
+            };
+            CopyParser::new(&copy, &Cluster::new_test())
+                .unwrap()
+                .unwrap()
+        };
+
+        assert!(!parser("COPY sharded (id, value) FROM STDIN (FORMAT csv, HEADER false)").headers);
+        assert!(!parser("COPY sharded (id, value) FROM STDIN (FORMAT csv, HEADER 0)").headers);
+        assert!(parser("COPY sharded (id, value) FROM STDIN (FORMAT csv, HEADER MATCH)").headers);
+        assert!(
+            parser("COPY sharded (id, value) FROM STDIN (FORMAT csv, HEADER on, FREEZE)").headers
+        );
+
+        let mut copy = parser("COPY sharded (id, value) FROM STDIN (FORMAT csv, ON_ERROR ignore)");
+        assert!(copy.skip_errors);
+        let sharded = copy
+            .shard(vec![
+                CopyData::new(b"1,one\n"),
+                CopyData::new(b"two,two\n"),
+                CopyData::new(b"3,three\n"),
+            ])
+            .unwrap();
+        assert_eq!(sharded.len(), 2);
+    }
+
+    #[test]
+    fn test_copy_key_pattern() {
+        let pattern = Regex::new(r"^(\d+):").unwrap();
+        assert_eq!(CopyParser::key(Some(&pattern), "42:abc").unwrap(), "42");
+        assert_eq!(CopyParser::key(None, "42:abc").unwrap(), "42:abc");
+        assert!(CopyParser::key(Some(&pattern), "abc").is_err());
+
+        let pattern = Regex::new(r"^[0-9a-f]{8}").unwrap();
+        assert_eq!(
+            CopyParser::key(Some(&pattern), "01890a5d-ac96-774b-bcce-b302099a8057").unwrap(),
+            "01890a5d"
+        );
+
+        let stmt = parse("COPY sharded (id, value) FROM STDIN CSV").unwrap();
+        let stmt = match stmt.protobuf.stmts[0].stmt.clone().unwrap().node.unwrap() {
+            NodeEnum::CopyStmt(copy) => copy,
+            _ => panic!("not a copy"),
+        };
+        let mut copy = CopyParser::new(&stmt, &Cluster::new_test())
+            .unwrap()
+            .unwrap();
+        let expected = copy.shard(vec![CopyData::new(b"42,one\n")]).unwrap();
+
+        copy.key_pattern = Some(Regex::new(r"^(\d+):").unwrap());
+        let sharded = copy.shard(vec![CopyData::new(b"42:tenant,one\n")]).unwrap();
+        assert_eq!(sharded[0].shard(), expected[0].shard());
+    }
+
     #[test]
     fn test_copy_binary() {
         let copy = "COPY sharded (id, value) FROM STDIN (FORMAT 'binary')";
@@ -0,0 +1,222 @@
+//! Queries that return wrong results when sent to more than one shard.
+//!
+//! Each shard only sees its own rows, so anything that needs to see all of them
+//! at once can't be computed by merging results from each shard.
+
+use std::fmt::Display;
+
+use pg_query::{protobuf::SelectStmt, NodeEnum, NodeRef};
+
+use crate::backend::ShardingSchema;
+
+use super::Joins;
+
+/// SQL we can't run across shards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Unsupported {
+    /// `OVER (...)` without partitioning by the sharding key.
+    WindowFunction,
+    /// Subquery that refers to the outer query and reads a sharded table
+    /// that isn't joined on the sharding key.
+    CorrelatedSubquery,
+    /// `ORDER BY random()`.
+    RandomOrder,
+    /// `AVG(DISTINCT x)`, etc.: the same value can be on more than one shard.
+    DistinctAggregate,
+    /// `DISTINCT ON` expressions, which can't be applied after merging
+    /// aggregates computed from extra columns.
+    DistinctOnAggregate,
+    /// Aggregates computed from extra columns, or groups filtered and limited
+    /// after merging, in a request with other statements whose results
+    /// are merged the same way.
+    PipelinedAggregate,
+}
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WindowFunction => write!(f, "window functions"),
+            Self::CorrelatedSubquery => write!(f, "correlated subqueries"),
+            Self::RandomOrder => write!(f, "ORDER BY random()"),
+            Self::DistinctAggregate => write!(f, "AVG, VARIANCE and STDDEV of DISTINCT values"),
+            Self::DistinctOnAggregate => write!(
+                f,
+                "DISTINCT ON expressions with AVG, VARIANCE, STDDEV, COUNT(DISTINCT) or HAVING"
+            ),
+            Self::PipelinedAggregate => write!(
+                f,
+                "AVG, VARIANCE, STDDEV, COUNT(DISTINCT), HAVING or LIMIT with aggregates, \
+                with other statements in the same request"
+            ),
+        }
+    }
+}
+
+impl Unsupported {
+    /// Find the first construct in the statement that can't be sent to multiple shards.
+    pub fn check(node: &NodeEnum, schema: &ShardingSchema) -> Option<Self> {
+        let NodeEnum::SelectStmt(ref stmt) = node else {
+            return None;
+        };
+        let joins = Joins::new(stmt);
+
+        if Self::random_order(stmt) {
+            return Some(Self::RandomOrder);
+        }
+
+        for (node, _, _, _) in node.nodes() {
+            match node {
+                NodeRef::FuncCall(func) => {
+                    if let Some(ref over) = func.over {
+                        // Each partition is on one shard.
+                        let partitioned = over.partition_clause.iter().any(|node| {
+                            Self::column(node).is_some_and(|column| {
+                                joins.tables().any(|table| {
+                                    schema.tables.sharded_column(table, &[column]).is_some()
+                                })
+                            })
+                        });
+
+                        if !partitioned {
+                            return Some(Self::WindowFunction);
+                        }
+                    }
+                }
+
+                NodeRef::SubLink(link) => {
+                    if let Some(NodeEnum::SelectStmt(ref subquery)) =
+                        link.subselect.as_ref().and_then(|node| node.node.as_ref())
+                    {
+                        if Self::correlated(subquery, &joins, schema) {
+                            return Some(Self::CorrelatedSubquery);
+                        }
+                    }
+                }
+
+                _ => (),
+            }
+        }
+
+        None
+    }
+
+    fn random_order(stmt: &SelectStmt) -> bool {
+        stmt.sort_clause.iter().any(|node| match node.node {
+            Some(NodeEnum::SortBy(ref sort_by)) => matches!(
+                sort_by.node.as_ref().and_then(|node| node.node.as_ref()),
+                Some(NodeEnum::FuncCall(ref func)) if func.funcname.last().and_then(|name| match name.node {
+                    Some(NodeEnum::String(ref name)) => Some(name.sval.as_str()),
+                    _ => None,
+                }) == Some("random")
+            ),
+            _ => false,
+        })
+    }
+
+    /// The subquery refers to the outer query and reads from a sharded table
+    /// using something other than its sharding key.
+    fn correlated(subquery: &SelectStmt, outer: &Joins, schema: &ShardingSchema) -> bool {
+        let inner = Joins::new(subquery);
+        let outer_alias =
+            |alias: &str| inner.table(alias).is_none() && outer.table(alias).is_some();
+
+        let references_outer = NodeEnum::SelectStmt(Box::new(subquery.clone()))
+            .nodes()
+            .into_iter()
+            .any(|(node, _, _, _)| match node {
+                NodeRef::ColumnRef(column) if column.fields.len() > 1 => {
+                    let table = column.fields[column.fields.len() - 2].node.as_ref();
+                    matches!(table, Some(NodeEnum::String(table)) if outer_alias(&table.sval))
+                }
+                _ => false,
+            });
+
+        if !references_outer {
+            return false;
+        }
+
+        let correlated = inner.tables().any(|table| {
+            let Some(sharded) = schema.tables.table(table) else {
+                // Not sharded, so it's the same on all shards or
+                // we don't know how it's split up.
+                return false;
+            };
+
+            // Rows joined on the sharding key are on the same shard.
+            !inner
+                .equivalent(table, &sharded.column)
+                .iter()
+                .any(|column| {
+                    outer_alias(column.table)
+                        && outer.table(column.table).is_some_and(|outer_table| {
+                            schema
+                                .tables
+                                .sharded_column(outer_table, &[column.name])
+                                .is_some()
+                        })
+                })
+        });
+
+        correlated
+    }
+
+    fn column(node: &pg_query::protobuf::Node) -> Option<&str> {
+        match node.node {
+            Some(NodeEnum::ColumnRef(ref column)) => match column.fields.last()?.node {
+                Some(NodeEnum::String(ref name)) => Some(name.sval.as_str()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use crate::backend::Cluster;
+
+    use super::*;
+
+    fn check(query: &str) -> Option<Unsupported> {
+        let schema = Cluster::new_test().sharding_schema();
+        let ast = parse(query).unwrap();
+        let node = ast.protobuf.stmts[0].stmt.as_ref().unwrap().node.as_ref();
+        Unsupported::check(node.unwrap(), &schema)
+    }
+
+    #[test]
+    fn test_unsupported() {
+        assert_eq!(
+            check("SELECT id, row_number() OVER (ORDER BY created_at) FROM sharded"),
+            Some(Unsupported::WindowFunction)
+        );
+        assert_eq!(
+            check("SELECT id, sum(value) OVER (PARTITION BY id) FROM sharded"),
+            None
+        );
+        assert_eq!(
+            check("SELECT * FROM sharded ORDER BY random() LIMIT 1"),
+            Some(Unsupported::RandomOrder)
+        );
+        assert_eq!(
+            check(
+                "SELECT * FROM sharded s WHERE value > \
+                (SELECT avg(value) FROM sharded s2 WHERE s2.kind = s.kind)"
+            ),
+            Some(Unsupported::CorrelatedSubquery)
+        );
+        assert_eq!(
+            check(
+                "SELECT * FROM sharded s WHERE EXISTS \
+                (SELECT 1 FROM sharded s2 WHERE s2.id = s.id AND s2.value > 1)"
+            ),
+            None
+        );
+        assert_eq!(
+            check("SELECT * FROM sharded WHERE id IN (SELECT id FROM sharded_omni)"),
+            None
+        );
+    }
+}
@@ -13,6 +13,44 @@ static ENDS_BUFFER: usize = 2048; // Max of 2048 columns in a CSV.
                                   // Postgres supports a max of 1600 columns in a table,
                                   // so we are well within bounds.
 
+/// COPY options that change how rows are parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvOptions {
+    /// Column delimiter.
+    pub delimiter: char,
+    /// Quote character, CSV only.
+    pub quote: char,
+    /// Character before a quote inside a quoted value, CSV only.
+    pub escape: char,
+    /// String that represents NULL.
+    pub null: String,
+    /// First record are headers.
+    pub headers: bool,
+    /// Copy format.
+    pub format: CopyFormat,
+    /// Columns, by position, that are never NULL, even if they match the NULL string.
+    pub force_not_null: Vec<usize>,
+}
+
+impl CsvOptions {
+    /// Postgres defaults for the format.
+    pub fn new(delimiter: char, headers: bool, format: CopyFormat) -> Self {
+        Self {
+            delimiter,
+            quote: '"',
+            escape: '"',
+            null: if format == CopyFormat::Csv {
+                String::new()
+            } else {
+                "\\N".into()
+            },
+            headers,
+            format,
+            force_not_null: vec![],
+        }
+    }
+}
+
 /// CSV reader that can handle partial inputs.
 #[derive(Clone)]
 pub struct CsvStream {
@@ -26,23 +64,17 @@ pub struct CsvStream {
     reader: Reader,
     /// Number of bytes read so far.
     read: usize,
-    /// CSV deliminter.
-    delimiter: char,
-    /// First record are headers.
-    headers: bool,
+    /// Parsing options.
+    options: CsvOptions,
     /// Read headers.
     headers_record: Option<Record>,
-    /// Copy format
-    format: CopyFormat,
 }
 
 impl std::fmt::Debug for CsvStream {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("CsvStream")
             .field("read", &self.read)
-            .field("delimiter", &self.delimiter)
-            .field("headers", &self.headers)
-            .field("format", &self.format)
+            .field("options", &self.options)
             .finish()
     }
 }
@@ -50,26 +82,77 @@ impl std::fmt::Debug for CsvStream {
 impl CsvStream {
     /// Create new CSV stream reader.
     pub fn new(delimiter: char, headers: bool, format: CopyFormat) -> Self {
+        Self::with_options(CsvOptions::new(delimiter, headers, format))
+    }
+
+    /// Create new CSV stream reader using options from the COPY statement.
+    pub fn with_options(options: CsvOptions) -> Self {
         Self {
             buffer: Vec::new(),
             record: vec![0u8; RECORD_BUFFER],
             ends: vec![0usize; ENDS_BUFFER],
-            reader: Self::reader(delimiter),
+            reader: Self::reader(&options),
             read: 0,
-            delimiter,
-            headers,
+            options,
             headers_record: None,
-            format,
         }
     }
 
-    fn reader(delimiter: char) -> Reader {
+    fn reader(options: &CsvOptions) -> Reader {
+        let escape = (options.escape != options.quote).then_some(options.escape as u8);
+
         ReaderBuilder::new()
-            .delimiter(delimiter as u8)
-            .double_quote(true)
+            .delimiter(options.delimiter as u8)
+            // Text format doesn't have quoting, only backslash escapes,
+            // which we pass through untouched.
+            .quoting(options.format == CopyFormat::Csv)
+            .quote(options.quote as u8)
+            .double_quote(escape.is_none())
+            .escape(escape)
             .build()
     }
 
+    /// Find which fields in the raw record were quoted.
+    fn quoted(&self, raw: &[u8]) -> Vec<bool> {
+        let (quote, escape, delimiter) = (
+            self.options.quote as u8,
+            self.options.escape as u8,
+            self.options.delimiter as u8,
+        );
+        let mut quoted = vec![];
+        let mut pos = 0;
+
+        loop {
+            let is_quoted = self.options.format == CopyFormat::Csv && raw.get(pos) == Some(&quote);
+            quoted.push(is_quoted);
+
+            if is_quoted {
+                pos += 1;
+                while pos < raw.len() {
+                    let next = raw.get(pos + 1);
+                    if raw[pos] == escape && (next == Some(&quote) || next == Some(&escape)) {
+                        pos += 2;
+                    } else if raw[pos] == quote {
+                        pos += 1;
+                        break;
+                    } else {
+                        pos += 1;
+                    }
+                }
+            }
+
+            while pos < raw.len() && !matches!(raw[pos], b'\n' | b'\r') && raw[pos] != delimiter {
+                pos += 1;
+            }
+
+            if raw.get(pos) == Some(&delimiter) {
+                pos += 1;
+            } else {
+                return quoted;
+            }
+        }
+    }
+
     /// Write some data to the CSV stream.
     ///
     /// This data will be appended to the input buffer. To read records from
@@ -91,28 +174,29 @@ impl CsvStream {
             match result {
                 ReadRecordResult::OutputFull => {
                     self.record.resize(self.buffer.len() * 2 + 1, 0u8);
-                    self.reader = Self::reader(self.delimiter);
+                    self.reader = Self::reader(&self.options);
                 }
 
                 // Data incomplete.
                 ReadRecordResult::InputEmpty | ReadRecordResult::End => {
                     self.buffer = Vec::from(&self.buffer[self.read..]);
                     self.read = 0;
-                    self.reader = Self::reader(self.delimiter);
+                    self.reader = Self::reader(&self.options);
                     return Ok(None);
                 }
 
                 ReadRecordResult::Record => {
+                    let quoted = self.quoted(&self.buffer[self.read..self.read + read]);
                     let record = Record::new(
                         &self.record[..written],
                         &self.ends[..ends],
-                        self.delimiter,
-                        self.format,
+                        &quoted,
+                        &self.options,
                     );
                     self.read += read;
                     self.record.fill(0u8);
 
-                    if self.headers && self.headers_record.is_none() {
+                    if self.options.headers && self.headers_record.is_none() {
                         self.headers_record = Some(record);
                     } else {
                         return Ok(Some(record));
@@ -133,7 +217,7 @@ impl CsvStream {
 
     /// Get headers from the CSV, if any.
     pub fn headers(&mut self) -> Result<Option<&Record>, super::Error> {
-        if self.headers {
+        if self.options.headers {
             if let Some(ref headers) = self.headers_record {
                 return Ok(Some(headers));
             } else {
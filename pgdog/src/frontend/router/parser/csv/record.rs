@@ -1,4 +1,4 @@
-use super::super::CopyFormat;
+use super::{super::CopyFormat, CsvOptions};
 use std::{ops::Range, str::from_utf8};
 
 /// A complete CSV record.
@@ -8,8 +8,14 @@ pub struct Record {
     pub data: Vec<u8>,
     /// Field ranges.
     pub fields: Vec<Range<usize>>,
+    /// NULL fields.
+    pub nulls: Vec<bool>,
     /// Delimiter.
     pub delimiter: char,
+    /// Quote character.
+    pub quote: char,
+    /// Escape character.
+    pub escape: char,
     /// Format used.
     pub format: CopyFormat,
 }
@@ -19,6 +25,7 @@ impl std::fmt::Debug for Record {
         f.debug_struct("Record")
             .field("data", &from_utf8(&self.data))
             .field("fields", &self.fields)
+            .field("nulls", &self.nulls)
             .field("delimiter", &self.delimiter)
             .field("format", &self.format)
             .finish()
@@ -32,7 +39,17 @@ impl std::fmt::Display for Record {
             "{}",
             (0..self.len())
                 .map(|field| match self.format {
-                    CopyFormat::Csv => format!("\"{}\"", self.get(field).unwrap()),
+                    // NULLs are the only values that aren't quoted.
+                    CopyFormat::Csv if !self.is_null(field) => {
+                        let mut value = String::new();
+                        for c in self.get(field).unwrap().chars() {
+                            if c == self.quote || c == self.escape {
+                                value.push(self.escape);
+                            }
+                            value.push(c);
+                        }
+                        format!("{}{}{}", self.quote, value, self.quote)
+                    }
                     _ => self.get(field).unwrap().to_string(),
                 })
                 .collect::<Vec<String>>()
@@ -42,18 +59,28 @@ impl std::fmt::Display for Record {
 }
 
 impl Record {
-    pub(super) fn new(data: &[u8], ends: &[usize], delimiter: char, format: CopyFormat) -> Self {
+    pub(super) fn new(data: &[u8], ends: &[usize], quoted: &[bool], options: &CsvOptions) -> Self {
         let mut last = 0;
         let mut fields = vec![];
-        for e in ends {
+        let mut nulls = vec![];
+        for (position, e) in ends.iter().enumerate() {
+            // Only unquoted values can be NULL.
+            nulls.push(
+                !quoted.get(position).copied().unwrap_or_default()
+                    && &data[last..*e] == options.null.as_bytes()
+                    && !options.force_not_null.contains(&position),
+            );
             fields.push(last..*e);
             last = *e;
         }
         Self {
             data: data.to_vec(),
             fields,
-            delimiter,
-            format,
+            nulls,
+            delimiter: options.delimiter,
+            quote: options.quote,
+            escape: options.escape,
+            format: options.format,
         }
     }
 
@@ -67,6 +94,11 @@ impl Record {
         self.len() == 0
     }
 
+    /// The field is NULL.
+    pub fn is_null(&self, index: usize) -> bool {
+        self.nulls.get(index).copied().unwrap_or_default()
+    }
+
     pub fn get(&self, index: usize) -> Option<&str> {
         self.fields
             .get(index)
@@ -0,0 +1,94 @@
+//! SELECT DISTINCT.
+//!
+//! The same row can be returned by more than one shard,
+//! so duplicates are removed after merging the results.
+
+use pg_query::protobuf::{a_const::Val, SelectStmt};
+use pg_query::NodeEnum;
+
+/// Result column used to compare rows.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DistinctColumn {
+    /// Position in the result, starting at 0.
+    Index(usize),
+    /// Column name, resolved using the row description.
+    Name(String),
+}
+
+/// Columns that make a row distinct.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Distinct {
+    /// `SELECT DISTINCT`: all columns.
+    Row,
+    /// `SELECT DISTINCT ON (...)`: the first row of each set is kept.
+    On(Vec<DistinctColumn>),
+}
+
+impl Distinct {
+    /// Get the DISTINCT clause, if we know how to apply it.
+    pub fn new(stmt: &SelectStmt) -> Option<Self> {
+        let first = stmt.distinct_clause.first()?;
+
+        // Plain DISTINCT is a list with one empty node.
+        if first.node.is_none() {
+            return Some(Self::Row);
+        }
+
+        let columns = stmt
+            .distinct_clause
+            .iter()
+            .map(|node| match node.node.as_ref()? {
+                NodeEnum::AConst(aconst) => match aconst.val {
+                    Some(Val::Ival(ref integer)) if integer.ival > 0 => {
+                        Some(DistinctColumn::Index(integer.ival as usize - 1))
+                    }
+                    _ => None,
+                },
+                NodeEnum::ColumnRef(column_ref) => match column_ref.fields.last()?.node {
+                    Some(NodeEnum::String(ref name)) => {
+                        Some(DistinctColumn::Name(name.sval.clone()))
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self::On(columns))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use super::*;
+
+    fn distinct(query: &str) -> Option<Distinct> {
+        let ast = parse(query).unwrap();
+        match ast.protobuf.stmts[0].stmt.as_ref().unwrap().node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => Distinct::new(stmt),
+            _ => panic!("not a select"),
+        }
+    }
+
+    #[test]
+    fn test_distinct() {
+        assert_eq!(
+            distinct("SELECT DISTINCT email FROM sharded"),
+            Some(Distinct::Row)
+        );
+        assert_eq!(
+            distinct("SELECT DISTINCT ON (2, sharded.email) id, email FROM sharded"),
+            Some(Distinct::On(vec![
+                DistinctColumn::Index(1),
+                DistinctColumn::Name("email".into())
+            ]))
+        );
+        assert_eq!(
+            distinct("SELECT DISTINCT ON (lower(email)) id FROM sharded"),
+            None
+        );
+        assert_eq!(distinct("SELECT email FROM sharded"), None);
+    }
+}
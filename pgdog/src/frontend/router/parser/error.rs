@@ -48,6 +48,27 @@ pub enum Error {
     #[error("no multi tenant id")]
     MultiTenantId,
 
+    #[error("query blocked by manual_queries")]
+    Blocked,
+
+    #[error("feature not supported across shards: {0}")]
+    CrossShard(super::Unsupported),
+
+    #[error("pipelined statements go to different shards, send a Sync between them")]
+    PipelineCrossShard,
+
     #[error("{0}")]
     Sharder(#[from] sharding::Error),
+
+    #[error("can't restore INSERT into \"{0}\" without its sharding key")]
+    RestoreNoKey(String),
+
+    #[error("can't restore multi-row INSERT into sharded table \"{0}\", dump it with --rows-per-insert=1")]
+    RestoreMultiRow(String),
+
+    #[error("sharding key doesn't match key_pattern")]
+    KeyPattern,
+
+    #[error("{0}")]
+    Regex(#[from] regex::Error),
 }
@@ -0,0 +1,272 @@
+//! `EXPLAIN (PGDOG) <query>`: show how the query would be routed
+//! instead of running it.
+use once_cell::sync::Lazy;
+use pg_query::{parse, NodeEnum};
+use regex::Regex;
+
+use crate::{backend::ShardingSchema, frontend::router::sharding::Tables};
+
+use super::{Command, Distinct, DistinctColumn, Insert, Key, OrderBy, Table, Value, WhereClause};
+
+static EXPLAIN: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?is)^\s*EXPLAIN\s*\(\s*"?PGDOG"?\s*\)\s*(.+)$"#).unwrap());
+
+/// Routing decision for a query, returned to the client as rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explain {
+    query: String,
+    rows: Vec<(&'static str, String)>,
+}
+
+impl Explain {
+    /// Check if the client is asking us to explain a query.
+    pub fn new(query: &str) -> Option<Self> {
+        let captures = EXPLAIN.captures(query)?;
+
+        Some(Self {
+            query: captures.get(1)?.as_str().to_owned(),
+            rows: vec![],
+        })
+    }
+
+    /// Query being explained.
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Describe the command the router returned for the query.
+    pub fn describe(mut self, command: &Command, schema: &ShardingSchema) -> Self {
+        let route = match command {
+            Command::Query(route) => route,
+            command => {
+                self.rows.push(("command", Self::command(command).into()));
+                return self;
+            }
+        };
+
+        let aggregate = route.aggregate();
+        let columns = |columns: &mut dyn Iterator<Item = String>| -> String {
+            columns.collect::<Vec<_>>().join(", ")
+        };
+
+        self.rows = vec![
+            ("command", "query".into()),
+            ("shard", route.shard().to_string()),
+            (
+                "role",
+                if route.is_read() {
+                    "replica"
+                } else {
+                    "primary"
+                }
+                .into(),
+            ),
+            ("shard_keys", Self::keys(&self.query, schema).join(", ")),
+            (
+                "order_by",
+                columns(&mut route.order_by().iter().map(Self::order_by)),
+            ),
+            (
+                "aggregate",
+                columns(&mut aggregate.targets().iter().map(|target| {
+                    format!("{}({})", target.function().name(), target.column() + 1)
+                })),
+            ),
+            (
+                "group_by",
+                columns(&mut aggregate.group_by().iter().map(|c| (c + 1).to_string())),
+            ),
+            (
+                "distinct",
+                match route.distinct() {
+                    None => String::new(),
+                    Some(Distinct::Row) => "all columns".into(),
+                    Some(Distinct::On(on)) => columns(&mut on.iter().map(|column| match column {
+                        DistinctColumn::Index(index) => (index + 1).to_string(),
+                        DistinctColumn::Name(name) => name.clone(),
+                    })),
+                },
+            ),
+            (
+                "limit",
+                match route.limit() {
+                    Some(limit) => format!(
+                        "{} offset {}",
+                        limit
+                            .limit
+                            .map(|limit| limit.to_string())
+                            .unwrap_or("all".into()),
+                        limit.offset
+                    ),
+                    None => String::new(),
+                },
+            ),
+            ("rewrite", route.rewrite().unwrap_or_default().into()),
+            ("buffered", route.should_buffer().to_string()),
+        ];
+
+        self
+    }
+
+    /// Setting name and value pairs.
+    pub fn rows(&self) -> &[(&'static str, String)] {
+        &self.rows
+    }
+
+    fn command(command: &Command) -> &'static str {
+        match command {
+            Command::Query(_) => "query",
+            Command::Copy(_) => "copy",
+            Command::StartTransaction(_) => "begin",
+            Command::CommitTransaction => "commit",
+            Command::RollbackTransaction => "rollback",
+            Command::StartReplication | Command::ReplicationMeta => "replication",
+            Command::Set { .. } => "set",
+            Command::PreparedStatement(_) => "prepare",
+            Command::Rewrite(_) => "rewrite",
+            Command::Shards(_) => "show pgdog.shards",
+            Command::Intercept(_) => "intercept",
+            Command::Metadata(_) => "metadata",
+            Command::Explain(_) => "explain",
+        }
+    }
+
+    fn order_by(order_by: &OrderBy) -> String {
+        let direction = if order_by.asc() { "ASC" } else { "DESC" };
+        match order_by {
+            OrderBy::Asc(column, _) | OrderBy::Desc(column, _) => {
+                format!("{} {}", column, direction)
+            }
+            OrderBy::AscColumn(name, _) | OrderBy::DescColumn(name, _) => {
+                format!("{} {}", name, direction)
+            }
+            OrderBy::AscVectorL2(column, _) => format!("{} <->", column),
+            OrderBy::AscVectorL2Column(name, _) => format!("{} <->", name),
+        }
+    }
+
+    /// Sharding keys found in the query.
+    fn keys(query: &str, schema: &ShardingSchema) -> Vec<String> {
+        let Ok(ast) = parse(query) else {
+            return vec![];
+        };
+        let Some(node) = ast
+            .protobuf
+            .stmts
+            .first()
+            .and_then(|stmt| stmt.stmt.as_ref())
+            .and_then(|stmt| stmt.node.as_ref())
+        else {
+            return vec![];
+        };
+
+        let (table, where_clause) = match node {
+            NodeEnum::SelectStmt(stmt) => {
+                (Table::try_from(&stmt.from_clause).ok(), &stmt.where_clause)
+            }
+            NodeEnum::UpdateStmt(stmt) => {
+                (stmt.relation.as_ref().map(Table::from), &stmt.where_clause)
+            }
+            NodeEnum::DeleteStmt(stmt) => {
+                (stmt.relation.as_ref().map(Table::from), &stmt.where_clause)
+            }
+            NodeEnum::InsertStmt(stmt) => return Self::insert_keys(&Insert::new(stmt), schema),
+            _ => return vec![],
+        };
+
+        let Some(where_clause) = WhereClause::new(table.map(|table| table.name), where_clause)
+        else {
+            return vec![];
+        };
+
+        let mut keys = vec![];
+        for table in schema.tables().tables() {
+            for key in where_clause.keys(table.name.as_deref(), &table.column) {
+                let value = match key {
+                    Key::Constant(value) => value,
+                    Key::Parameter(param) => format!("${}", param + 1),
+                    Key::Null => "NULL".into(),
+                };
+                keys.push(format!("{} = {}", table.column, value));
+            }
+        }
+
+        keys
+    }
+
+    fn insert_keys(insert: &Insert<'_>, schema: &ShardingSchema) -> Vec<String> {
+        let tables = Tables::new(schema);
+        let columns = insert.columns();
+        let Some(key) = insert.table().and_then(|table| tables.key(table, &columns)) else {
+            return vec![];
+        };
+
+        insert
+            .tuples()
+            .iter()
+            .filter_map(|tuple| tuple.values.get(key.position))
+            .map(|value| {
+                let value = match value {
+                    Value::Integer(integer) => integer.to_string(),
+                    Value::String(string) => string.to_string(),
+                    Value::Placeholder(param) => format!("${}", param),
+                    Value::Null => "NULL".into(),
+                    value => format!("{:?}", value),
+                };
+                format!("{} = {}", key.table.column, value)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::backend::Cluster;
+
+    use super::super::{Aggregate, Route, Shard};
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(
+            Explain::new("explain (pgdog) SELECT 1").unwrap().query(),
+            "SELECT 1"
+        );
+        assert!(Explain::new("EXPLAIN SELECT 1").is_none());
+        assert!(Explain::new("EXPLAIN (ANALYZE) SELECT 1").is_none());
+
+        let schema = Cluster::new_test().sharding_schema();
+        let explain = Explain::new("EXPLAIN (PGDOG) SELECT * FROM sharded WHERE id = 5")
+            .unwrap()
+            .describe(
+                &Command::Query(Route::select(
+                    Shard::Direct(1),
+                    vec![],
+                    Aggregate::default(),
+                )),
+                &schema,
+            );
+        let row = |name: &str| {
+            explain
+                .rows()
+                .iter()
+                .find(|row| row.0 == name)
+                .map(|row| row.1.clone())
+                .unwrap()
+        };
+        assert_eq!(row("shard"), "1");
+        assert_eq!(row("role"), "replica");
+        assert_eq!(row("shard_keys"), "id = 5");
+        assert_eq!(row("buffered"), "false");
+
+        let explain = Explain::new("EXPLAIN (PGDOG) INSERT INTO sharded (id) VALUES ($1)")
+            .unwrap()
+            .describe(&Command::Query(Route::write(Shard::All)), &schema);
+        assert_eq!(explain.rows()[3], ("shard_keys", "id = $1".into()));
+
+        let explain = Explain::new("EXPLAIN (PGDOG) COMMIT")
+            .unwrap()
+            .describe(&Command::CommitTransaction, &schema);
+        assert_eq!(explain.rows(), &[("command", "commit".into())]);
+    }
+}
@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use once_cell::sync::Lazy;
 use pg_query::{protobuf, Node, NodeEnum};
 
+use crate::config::Functions;
+
 static WRITE_ONLY: Lazy<HashMap<&'static str, LockingBehavior>> = Lazy::new(|| {
     HashMap::from([
         ("pg_advisory_lock", LockingBehavior::Lock),
@@ -19,6 +21,77 @@ static WRITE_ONLY: Lazy<HashMap<&'static str, LockingBehavior>> = Lazy::new(|| {
     ])
 });
 
+/// Built-in functions that don't write, checked in conservative mode.
+static READ_ONLY: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    HashSet::from([
+        // Aggregates and window functions.
+        "count",
+        "sum",
+        "avg",
+        "min",
+        "max",
+        "array_agg",
+        "string_agg",
+        "json_agg",
+        "jsonb_agg",
+        "json_object_agg",
+        "jsonb_object_agg",
+        "bool_and",
+        "bool_or",
+        "row_number",
+        "rank",
+        "dense_rank",
+        "lag",
+        "lead",
+        // Date and time.
+        "now",
+        "clock_timestamp",
+        "statement_timestamp",
+        "transaction_timestamp",
+        "date_trunc",
+        "date_part",
+        "extract",
+        "to_char",
+        "to_date",
+        "to_timestamp",
+        "age",
+        // Strings, numbers and JSON.
+        "lower",
+        "upper",
+        "length",
+        "concat",
+        "concat_ws",
+        "substring",
+        "replace",
+        "trim",
+        "btrim",
+        "split_part",
+        "format",
+        "md5",
+        "abs",
+        "round",
+        "floor",
+        "ceil",
+        "random",
+        "gen_random_uuid",
+        "json_build_object",
+        "jsonb_build_object",
+        "json_build_array",
+        "jsonb_build_array",
+        "to_json",
+        "to_jsonb",
+        "unnest",
+        "generate_series",
+        // System information.
+        "version",
+        "current_database",
+        "current_schema",
+        "current_setting",
+        "pg_backend_pid",
+        "pg_is_in_recovery",
+    ])
+});
+
 #[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub enum LockingBehavior {
     Lock,
@@ -40,10 +113,19 @@ impl FunctionBehavior {
             ..Default::default()
         }
     }
+
+    /// Combine with the behavior of another function in the same query.
+    pub fn merge(&mut self, other: FunctionBehavior) {
+        self.writes |= other.writes;
+        if self.locking_behavior == LockingBehavior::None {
+            self.locking_behavior = other.locking_behavior;
+        }
+    }
 }
 
 pub struct Function<'a> {
     pub name: &'a str,
+    pub schema: Option<&'a str>,
 }
 
 impl<'a> Function<'a> {
@@ -51,6 +133,7 @@ impl<'a> Function<'a> {
         match node {
             Some(NodeEnum::String(protobuf::String { sval })) => Ok(Self {
                 name: sval.as_str(),
+                schema: None,
             }),
 
             _ => Err(()),
@@ -58,16 +141,35 @@ impl<'a> Function<'a> {
     }
 
     /// This function likely writes.
-    pub fn behavior(&self) -> FunctionBehavior {
-        if let Some(locks) = WRITE_ONLY.get(&self.name) {
+    pub fn behavior(&self, functions: &Functions) -> FunctionBehavior {
+        let builtin = matches!(self.schema, None | Some("pg_catalog"));
+
+        if let Some(locks) = WRITE_ONLY.get(&self.name).filter(|_| builtin) {
             FunctionBehavior {
                 writes: true,
                 locking_behavior: *locks,
             }
+        } else if self.matches(&functions.writes)
+            || (functions.conservative
+                && !(builtin && READ_ONLY.contains(self.name))
+                && !self.matches(&functions.reads))
+        {
+            FunctionBehavior::writes_only()
         } else {
             FunctionBehavior::default()
         }
     }
+
+    /// Function is in the list, by name or by schema-qualified name.
+    fn matches(&self, names: &[String]) -> bool {
+        names.iter().any(|name| match name.split_once('.') {
+            Some((schema, name)) => {
+                self.schema.is_some_and(|s| s.eq_ignore_ascii_case(schema))
+                    && self.name.eq_ignore_ascii_case(name)
+            }
+            None => self.name.eq_ignore_ascii_case(name),
+        })
+    }
 }
 
 impl<'a> TryFrom<&'a Node> for Function<'a> {
@@ -75,8 +177,14 @@ impl<'a> TryFrom<&'a Node> for Function<'a> {
     fn try_from(value: &'a Node) -> Result<Self, Self::Error> {
         match &value.node {
             Some(NodeEnum::FuncCall(func)) => {
-                if let Some(node) = func.funcname.last() {
-                    return Self::from_string(&node.node);
+                let mut names = func.funcname.iter().rev();
+                if let Some(node) = names.next() {
+                    let mut function = Self::from_string(&node.node)?;
+                    function.schema = names
+                        .next()
+                        .and_then(|node| Self::from_string(&node.node).ok())
+                        .map(|schema| schema.name);
+                    return Ok(function);
                 }
             }
 
@@ -122,4 +230,52 @@ mod test {
             _ => panic!("not a select"),
         }
     }
+
+    fn function(query: &str) -> FunctionBehavior {
+        let functions = Functions {
+            writes: vec!["refresh_cache".into(), "app.archive".into()],
+            reads: vec!["app.lookup".into()],
+            conservative: false,
+        };
+        behavior(query, &functions)
+    }
+
+    fn behavior(query: &str, functions: &Functions) -> FunctionBehavior {
+        let ast = parse(query).unwrap();
+        let root = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+
+        match root.node.as_ref() {
+            Some(NodeEnum::SelectStmt(stmt)) => {
+                Function::try_from(stmt.target_list.first().unwrap())
+                    .unwrap()
+                    .behavior(functions)
+            }
+
+            _ => panic!("not a select"),
+        }
+    }
+
+    #[test]
+    fn test_configured_functions() {
+        assert!(function("SELECT nextval('seq')").writes);
+        assert!(function("SELECT refresh_cache()").writes);
+        assert!(function("SELECT public.REFRESH_CACHE()").writes);
+        assert!(function("SELECT app.archive(1)").writes);
+        assert!(!function("SELECT other.archive(1)").writes);
+        assert!(!function("SELECT archive(1)").writes);
+        assert!(!function("SELECT my_function()").writes);
+
+        let conservative = Functions {
+            reads: vec!["app.lookup".into(), "search".into()],
+            conservative: true,
+            ..Default::default()
+        };
+        assert!(behavior("SELECT my_function()", &conservative).writes);
+        assert!(behavior("SELECT app.other()", &conservative).writes);
+        assert!(!behavior("SELECT app.lookup($1)", &conservative).writes);
+        assert!(!behavior("SELECT search('term')", &conservative).writes);
+        assert!(!behavior("SELECT count(*)", &conservative).writes);
+        assert!(!behavior("SELECT pg_catalog.now()", &conservative).writes);
+        assert!(behavior("SELECT app.now()", &conservative).writes);
+    }
 }
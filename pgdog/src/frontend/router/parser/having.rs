@@ -0,0 +1,309 @@
+//! HAVING clause applied after merging groups from all shards.
+//!
+//! Each shard only sees its own part of every group, so the predicate
+//! is removed from the query and evaluated on the merged rows instead.
+
+use std::cmp::Ordering;
+
+use pg_query::protobuf::{a_const::Val, AExprKind, BoolExprType, Node};
+use pg_query::NodeEnum;
+
+use crate::net::messages::{
+    Datum, Decimal, Format, FromDataType, Interval, Numeric, Timestamp, TimestampTz,
+};
+
+/// Comparison operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+}
+
+impl Op {
+    fn new(name: &str) -> Option<Self> {
+        Some(match name {
+            "=" => Self::Eq,
+            "<>" | "!=" => Self::NotEq,
+            "<" => Self::Lt,
+            "<=" => Self::LtEq,
+            ">" => Self::Gt,
+            ">=" => Self::GtEq,
+            _ => return None,
+        })
+    }
+
+    /// Same comparison with the operands swapped.
+    fn flip(self) -> Self {
+        match self {
+            Self::Lt => Self::Gt,
+            Self::LtEq => Self::GtEq,
+            Self::Gt => Self::Lt,
+            Self::GtEq => Self::LtEq,
+            op => op,
+        }
+    }
+}
+
+/// Constant the column is compared to, as written in the query.
+/// Like in Postgres, its type is the type of the column.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(String),
+    Text(String),
+}
+
+impl Value {
+    fn as_str(&self) -> &str {
+        match self {
+            Self::Number(value) | Self::Text(value) => value,
+        }
+    }
+
+    /// Compare the column value to this constant.
+    fn compare(&self, datum: &Datum) -> Option<Ordering> {
+        let value = self.as_str();
+        let bytes = value.as_bytes();
+
+        let integer = match datum {
+            Datum::Bigint(integer) => Some(*integer),
+            Datum::Integer(integer) => Some(*integer as i64),
+            Datum::SmallInt(integer) => Some(*integer as i64),
+            _ => None,
+        };
+        if let Some(integer) = integer {
+            // Integers are exact, unless compared to a fraction.
+            return match value.trim().parse::<i64>() {
+                Ok(value) => Some(integer.cmp(&value)),
+                Err(_) => {
+                    Decimal::from(integer).partial_cmp(&Decimal::decode(bytes, Format::Text).ok()?)
+                }
+            };
+        }
+
+        let constant = match datum {
+            Datum::Null => return None,
+            Datum::Numeric(_) => Datum::Numeric(Numeric::decode(bytes, Format::Text).ok()?),
+            Datum::Decimal(_) => Datum::Decimal(Decimal::decode(bytes, Format::Text).ok()?),
+            Datum::Text(_) => Datum::Text(value.to_owned()),
+            Datum::Timestamp(_) => Datum::Timestamp(Timestamp::decode(bytes, Format::Text).ok()?),
+            Datum::TimestampTz(_) => {
+                Datum::TimestampTz(TimestampTz::decode(bytes, Format::Text).ok()?)
+            }
+            Datum::Interval(_) => Datum::Interval(Interval::decode(bytes, Format::Text).ok()?),
+            Datum::Uuid(_) => Datum::Uuid(value.parse().ok()?),
+            _ => return None,
+        };
+
+        datum.partial_cmp(&constant)
+    }
+}
+
+/// HAVING predicate.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Having {
+    And(Vec<Having>),
+    Or(Vec<Having>),
+    Not(Box<Having>),
+    Compare { column: usize, op: Op, value: Value },
+}
+
+impl Having {
+    /// Build the predicate, if we know how to evaluate it.
+    ///
+    /// `column` finds (or adds) the result column for an expression compared to a constant.
+    pub fn new(node: &Node, column: &mut impl FnMut(&Node) -> Option<usize>) -> Option<Self> {
+        match node.node.as_ref()? {
+            NodeEnum::BoolExpr(expr) => {
+                let args = expr
+                    .args
+                    .iter()
+                    .map(|arg| Self::new(arg, column))
+                    .collect::<Option<Vec<_>>>()?;
+
+                match expr.boolop() {
+                    BoolExprType::AndExpr => Some(Self::And(args)),
+                    BoolExprType::OrExpr => Some(Self::Or(args)),
+                    BoolExprType::NotExpr => Some(Self::Not(Box::new(args.into_iter().next()?))),
+                    _ => None,
+                }
+            }
+
+            NodeEnum::AExpr(expr) if expr.kind() == AExprKind::AexprOp => {
+                let op = match expr.name.first()?.node.as_ref()? {
+                    NodeEnum::String(name) => Op::new(&name.sval)?,
+                    _ => return None,
+                };
+                let (left, right) = (expr.lexpr.as_deref()?, expr.rexpr.as_deref()?);
+
+                if let Some(value) = Self::value(right) {
+                    Some(Self::Compare {
+                        column: column(left)?,
+                        op,
+                        value,
+                    })
+                } else {
+                    Some(Self::Compare {
+                        column: column(right)?,
+                        op: op.flip(),
+                        value: Self::value(left)?,
+                    })
+                }
+            }
+
+            _ => None,
+        }
+    }
+
+    fn value(node: &Node) -> Option<Value> {
+        match node.node.as_ref()? {
+            NodeEnum::AConst(aconst) => match aconst.val.as_ref()? {
+                Val::Ival(ival) => Some(Value::Number(ival.ival.to_string())),
+                Val::Fval(fval) => Some(Value::Number(fval.fval.clone())),
+                Val::Sval(sval) => Some(Value::Text(sval.sval.clone())),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Check the merged row values. NULLs never match, like in Postgres.
+    pub fn matches(&self, values: &[Datum]) -> bool {
+        self.evaluate(values).unwrap_or(false)
+    }
+
+    fn evaluate(&self, values: &[Datum]) -> Option<bool> {
+        match self {
+            Self::And(args) => {
+                let mut result = Some(true);
+                for arg in args {
+                    match arg.evaluate(values) {
+                        Some(false) => return Some(false),
+                        None => result = None,
+                        Some(true) => (),
+                    }
+                }
+                result
+            }
+            Self::Or(args) => {
+                let mut result = Some(false);
+                for arg in args {
+                    match arg.evaluate(values) {
+                        Some(true) => return Some(true),
+                        None => result = None,
+                        Some(false) => (),
+                    }
+                }
+                result
+            }
+            Self::Not(arg) => arg.evaluate(values).map(|result| !result),
+            Self::Compare { column, op, value } => {
+                let ordering = value.compare(values.get(*column)?)?;
+
+                Some(match op {
+                    Op::Eq => ordering.is_eq(),
+                    Op::NotEq => ordering.is_ne(),
+                    Op::Lt => ordering.is_lt(),
+                    Op::LtEq => ordering.is_le(),
+                    Op::Gt => ordering.is_gt(),
+                    Op::GtEq => ordering.is_ge(),
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use super::*;
+
+    fn having(query: &str) -> Option<Having> {
+        let ast = parse(query).unwrap();
+        let Some(NodeEnum::SelectStmt(ref stmt)) = ast.protobuf.stmts[0].stmt.as_ref()?.node else {
+            return None;
+        };
+        // Columns are numbered in the order they appear.
+        let mut columns = 0;
+        Having::new(stmt.having_clause.as_deref()?, &mut |_| {
+            columns += 1;
+            Some(columns - 1)
+        })
+    }
+
+    #[test]
+    fn test_having() {
+        let predicate = having(
+            "SELECT 1 FROM t GROUP BY 1 HAVING count(*) > 5 AND (10 >= sum(x) OR NOT min(y) = 'a')",
+        )
+        .unwrap();
+        assert_eq!(
+            predicate,
+            Having::And(vec![
+                Having::Compare {
+                    column: 0,
+                    op: Op::Gt,
+                    value: Value::Number("5".into())
+                },
+                Having::Or(vec![
+                    Having::Compare {
+                        column: 1,
+                        op: Op::LtEq,
+                        value: Value::Number("10".into())
+                    },
+                    Having::Not(Box::new(Having::Compare {
+                        column: 2,
+                        op: Op::Eq,
+                        value: Value::Text("a".into())
+                    })),
+                ]),
+            ])
+        );
+
+        let row = |count, sum, min: &str| {
+            vec![
+                Datum::Bigint(count),
+                Datum::Bigint(sum),
+                Datum::Text(min.into()),
+            ]
+        };
+        assert!(predicate.matches(&row(6, 5, "a")));
+        assert!(predicate.matches(&row(6, 50, "b")));
+        assert!(!predicate.matches(&row(6, 50, "a")));
+        assert!(!predicate.matches(&row(5, 5, "b")));
+        assert!(!predicate.matches(&[Datum::Null, Datum::Bigint(1), Datum::Null]));
+
+        assert!(having("SELECT 1 FROM t GROUP BY 1 HAVING count(*) > sum(x)").is_none());
+    }
+
+    #[test]
+    fn test_having_types() {
+        let predicate = having("SELECT 1 FROM t GROUP BY 1 HAVING count(*) > 1").unwrap();
+        assert!(predicate.matches(&[Datum::Bigint(2)]));
+        assert!(!predicate.matches(&[Datum::Bigint(1)]));
+        assert!(predicate.matches(&[Datum::Integer(2)]));
+        assert!(predicate.matches(&[Datum::Numeric(Numeric::from(1.5))]));
+        assert!(!predicate.matches(&[Datum::Numeric(Numeric::from(0.5))]));
+
+        let predicate = having("SELECT 1 FROM t GROUP BY 1 HAVING sum(x) <= 2.5").unwrap();
+        assert!(predicate.matches(&[Datum::Bigint(2)]));
+        assert!(!predicate.matches(&[Datum::Bigint(3)]));
+        let decimal =
+            |value: &str| Datum::Decimal(Decimal::decode(value.as_bytes(), Format::Text).unwrap());
+        assert!(predicate.matches(&[decimal("2.50")]));
+        assert!(!predicate.matches(&[decimal("2.5000000000000000001")]));
+
+        let predicate =
+            having("SELECT 1 FROM t GROUP BY 1 HAVING max(created_at) > '2025-01-01 00:00:00'")
+                .unwrap();
+        let timestamp = |value: &str| {
+            Datum::Timestamp(Timestamp::decode(value.as_bytes(), Format::Text).unwrap())
+        };
+        assert!(predicate.matches(&[timestamp("2025-06-01 12:00:00")]));
+        assert!(!predicate.matches(&[timestamp("2024-06-01 12:00:00")]));
+    }
+}
@@ -3,6 +3,7 @@ use pg_query::{protobuf::*, NodeEnum};
 
 use crate::{
     backend::ShardingSchema,
+    config::{DataType, ShardingFunction},
     frontend::router::{
         round_robin,
         sharding::{ContextBuilder, Tables, Value as ShardingValue},
@@ -119,6 +120,90 @@ impl<'a> Insert<'a> {
 
         Ok(Shard::All)
     }
+
+    /// The table is sharded but the sharding key isn't in the statement,
+    /// so [`Insert::shard`] picks the shard with round robin.
+    pub fn round_robin(&self, schema: &ShardingSchema) -> bool {
+        let tables = Tables::new(schema);
+        self.table().is_some_and(|table| {
+            tables.key(table, &self.columns()).is_none() && tables.sharded(table).is_some()
+        })
+    }
+
+    /// Add the sharding key, if it's missing and the table is configured
+    /// to generate it. Values come from `pgdog.next_id`, which only returns
+    /// keys that belong to the shard it's called on.
+    pub fn generate_key(&self, schema: &ShardingSchema, shard: usize) -> Option<InsertStmt> {
+        let tables = Tables::new(schema);
+        let table = tables.sharded(self.table()?)?;
+
+        if !table.generate_key
+            || table.data_type != DataType::Bigint
+            || table.sharding_function != ShardingFunction::PgHash
+        {
+            return None;
+        }
+
+        // Without a column list, we don't know if the key is there.
+        let columns = self.columns();
+        if columns.len() != self.stmt.cols.len()
+            || columns.is_empty()
+            || columns.iter().any(|column| column.name == table.column)
+        {
+            return None;
+        }
+
+        let mut stmt = self.stmt.clone();
+        let select = match stmt.select_stmt.as_mut()?.node {
+            Some(NodeEnum::SelectStmt(ref mut select)) if !select.values_lists.is_empty() => select,
+            _ => return None,
+        };
+
+        for values in select.values_lists.iter_mut() {
+            match values.node {
+                Some(NodeEnum::List(ref mut list)) => {
+                    list.items.push(Self::next_id(schema.shards, shard))
+                }
+                _ => return None,
+            }
+        }
+
+        stmt.cols.push(Node {
+            node: Some(NodeEnum::ResTarget(Box::new(ResTarget {
+                name: table.column.clone(),
+                location: -1,
+                ..Default::default()
+            }))),
+        });
+
+        Some(stmt)
+    }
+
+    /// `pgdog.next_id(shards, shard)`
+    fn next_id(shards: usize, shard: usize) -> Node {
+        let string = |sval: &str| Node {
+            node: Some(NodeEnum::String(pg_query::protobuf::String {
+                sval: sval.to_owned(),
+            })),
+        };
+        let integer = |ival: usize| Node {
+            node: Some(NodeEnum::AConst(AConst {
+                val: Some(a_const::Val::Ival(Integer { ival: ival as i32 })),
+                location: -1,
+                ..Default::default()
+            })),
+        };
+
+        Node {
+            node: Some(NodeEnum::FuncCall(Box::new(FuncCall {
+                funcname: vec![string("pgdog"), string("next_id")],
+                args: vec![integer(shards), integer(shard)],
+                funcformat: CoercionForm::CoerceExplicitCall.into(),
+                location: -1,
+                ..Default::default()
+            }))),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -296,4 +381,44 @@ mod test {
             _ => panic!("not a select"),
         }
     }
+
+    #[test]
+    fn test_generate_key() {
+        let schema = ShardingSchema {
+            shards: 2,
+            tables: ShardedTables::new(
+                vec![ShardedTable {
+                    name: Some("sharded".into()),
+                    column: "id".into(),
+                    data_type: DataType::Bigint,
+                    generate_key: true,
+                    ..Default::default()
+                }],
+                vec![],
+                false,
+            ),
+        };
+
+        let generate = |query: &str| {
+            let query = parse(query).unwrap();
+            match query.protobuf.stmts[0].stmt.as_ref().unwrap().node {
+                Some(NodeEnum::InsertStmt(ref stmt)) => Insert::new(stmt)
+                    .generate_key(&schema, 1)
+                    .map(|stmt| NodeEnum::InsertStmt(Box::new(stmt)).deparse().unwrap()),
+                _ => panic!("not an insert"),
+            }
+        };
+
+        assert_eq!(
+            generate("INSERT INTO sharded (value) VALUES ('a'), ('b') RETURNING id").as_deref(),
+            Some(
+                "INSERT INTO sharded (value, id) VALUES ('a', pgdog.next_id(2, 1)), \
+                ('b', pgdog.next_id(2, 1)) RETURNING id"
+            )
+        );
+        assert!(generate("INSERT INTO sharded (id, value) VALUES (1, 'a')").is_none());
+        assert!(generate("INSERT INTO sharded VALUES (1, 'a')").is_none());
+        assert!(generate("INSERT INTO sharded (value) SELECT 'a'").is_none());
+        assert!(generate("INSERT INTO other (value) VALUES ('a')").is_none());
+    }
 }
@@ -0,0 +1,264 @@
+//! Equi-joins between tables in a `SELECT` statement.
+//!
+//! `SELECT * FROM users u JOIN orders o ON u.id = o.user_id WHERE o.user_id = $1`
+//! filters on `users.id` too, so if both tables are sharded on the joined columns,
+//! the query only needs one shard.
+
+use std::collections::{HashMap, VecDeque};
+
+use pg_query::{protobuf::*, Node, NodeEnum};
+
+use super::Table;
+
+/// Column qualified with the table name or alias used in the query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinColumn<'a> {
+    /// Table name or alias.
+    pub table: &'a str,
+    /// Column name.
+    pub name: &'a str,
+}
+
+/// Join graph of a `SELECT` statement.
+#[derive(Debug, Default)]
+pub struct Joins<'a> {
+    /// Table name for each table (or its alias) in the `FROM` clause.
+    tables: HashMap<&'a str, &'a str>,
+    /// Columns compared with `=`.
+    edges: Vec<(JoinColumn<'a>, JoinColumn<'a>)>,
+}
+
+impl<'a> Joins<'a> {
+    /// Find all tables and equi-joins in the `FROM` and `WHERE` clauses.
+    pub fn new(stmt: &'a SelectStmt) -> Self {
+        let mut joins = Self::default();
+
+        for node in &stmt.from_clause {
+            joins.from(node);
+        }
+
+        // FROM users, orders WHERE users.id = orders.user_id
+        if let Some(ref where_clause) = stmt.where_clause {
+            joins.quals(where_clause);
+        }
+
+        joins
+    }
+
+    /// Table referred to by the name or alias, if it's in the `FROM` clause.
+    pub fn table(&self, alias: &str) -> Option<&'a str> {
+        self.tables.get(alias).copied()
+    }
+
+    /// Names of all tables in the `FROM` clause.
+    pub fn tables(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.tables.values().copied()
+    }
+
+    /// Names the table is referred to by in the query, i.e. its own name or its alias.
+    pub fn aliases(&self, table: &str) -> Vec<&'a str> {
+        self.tables
+            .iter()
+            .filter(|(_, name)| **name == table)
+            .map(|(alias, _)| *alias)
+            .collect()
+    }
+
+    /// Columns equal to the table's column through one or more equi-joins.
+    pub fn equivalent(&self, table: &str, column: &str) -> Vec<JoinColumn<'a>> {
+        let mut found: Vec<JoinColumn<'a>> = vec![];
+        let mut queue = VecDeque::new();
+
+        for (left, right) in &self.edges {
+            for column_ref in [left, right] {
+                if self.tables.get(column_ref.table).copied() == Some(table)
+                    && column_ref.name == column
+                    && !found.contains(column_ref)
+                {
+                    found.push(*column_ref);
+                    queue.push_back(*column_ref);
+                }
+            }
+        }
+
+        while let Some(column_ref) = queue.pop_front() {
+            for (left, right) in &self.edges {
+                let other = if *left == column_ref {
+                    right
+                } else if *right == column_ref {
+                    left
+                } else {
+                    continue;
+                };
+
+                if !found.contains(other) {
+                    found.push(*other);
+                    queue.push_back(*other);
+                }
+            }
+        }
+
+        found
+    }
+
+    fn from(&mut self, node: &'a Node) -> Option<&'a str> {
+        match node.node {
+            Some(NodeEnum::RangeVar(ref range_var)) => {
+                let table = Table::from(range_var);
+                self.tables.insert(table.name, range_var.relname.as_str());
+                Some(table.name)
+            }
+
+            Some(NodeEnum::JoinExpr(ref join)) => {
+                let left = join.larg.as_deref().and_then(|node| self.from(node));
+                let right = join.rarg.as_deref().and_then(|node| self.from(node));
+
+                // JOIN orders USING (user_id)
+                if let (Some(left), Some(right)) = (left, right) {
+                    for column in &join.using_clause {
+                        if let Some(NodeEnum::String(ref name)) = column.node {
+                            self.edges.push((
+                                JoinColumn {
+                                    table: left,
+                                    name: name.sval.as_str(),
+                                },
+                                JoinColumn {
+                                    table: right,
+                                    name: name.sval.as_str(),
+                                },
+                            ));
+                        }
+                    }
+                }
+
+                if let Some(ref quals) = join.quals {
+                    self.quals(quals);
+                }
+
+                None
+            }
+
+            _ => None,
+        }
+    }
+
+    fn quals(&mut self, node: &'a Node) {
+        match node.node {
+            // Only AND guarantees both sides of the join are equal.
+            Some(NodeEnum::BoolExpr(ref expr)) if expr.boolop() == BoolExprType::AndExpr => {
+                for arg in &expr.args {
+                    self.quals(arg);
+                }
+            }
+
+            Some(NodeEnum::AExpr(ref expr)) if expr.kind() == AExprKind::AexprOp => {
+                let op = expr.name.first().and_then(|node| match node.node {
+                    Some(NodeEnum::String(ref op)) => Some(op.sval.as_str()),
+                    _ => None,
+                });
+
+                if op == Some("=") {
+                    if let (Some(left), Some(right)) = (
+                        expr.lexpr.as_deref().and_then(Self::column),
+                        expr.rexpr.as_deref().and_then(Self::column),
+                    ) {
+                        self.edges.push((left, right));
+                    }
+                }
+            }
+
+            _ => (),
+        }
+    }
+
+    /// Qualified column reference. Unqualified columns are ambiguous in a join.
+    fn column(node: &'a Node) -> Option<JoinColumn<'a>> {
+        match node.node {
+            Some(NodeEnum::ColumnRef(ref column)) => {
+                let mut fields = column.fields.iter().rev().map(|field| match field.node {
+                    Some(NodeEnum::String(ref string)) => Some(string.sval.as_str()),
+                    _ => None,
+                });
+                let name = fields.next()??;
+                let table = fields.next()??;
+
+                Some(JoinColumn { table, name })
+            }
+
+            Some(NodeEnum::TypeCast(ref cast)) => cast.arg.as_deref().and_then(Self::column),
+
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use super::*;
+
+    fn joins(query: &str, f: impl Fn(&Joins)) {
+        let ast = parse(query).unwrap();
+        let stmt = ast.protobuf.stmts.first().unwrap().stmt.as_ref().unwrap();
+
+        match stmt.node {
+            Some(NodeEnum::SelectStmt(ref stmt)) => f(&Joins::new(stmt)),
+            _ => panic!("not a select"),
+        }
+    }
+
+    #[test]
+    fn test_joins() {
+        joins(
+            "SELECT * FROM users u
+            JOIN orders o ON u.id = o.user_id
+            JOIN items i ON i.order_id = o.id AND i.user_id = o.user_id
+            WHERE o.user_id = $1",
+            |joins| {
+                assert_eq!(joins.aliases("users"), vec!["u"]);
+                let equivalent = joins.equivalent("users", "id");
+                assert_eq!(
+                    equivalent,
+                    vec![
+                        JoinColumn {
+                            table: "u",
+                            name: "id"
+                        },
+                        JoinColumn {
+                            table: "o",
+                            name: "user_id"
+                        },
+                        JoinColumn {
+                            table: "i",
+                            name: "user_id"
+                        },
+                    ]
+                );
+                assert!(joins.equivalent("items", "order_id").len() == 2);
+                assert!(joins.equivalent("orders", "total").is_empty());
+            },
+        );
+    }
+
+    #[test]
+    fn test_joins_using_and_where() {
+        joins("SELECT * FROM users JOIN orders USING (user_id)", |joins| {
+            assert_eq!(joins.equivalent("orders", "user_id").len(), 2);
+        });
+
+        joins(
+            "SELECT * FROM users, orders WHERE users.id = orders.user_id",
+            |joins| {
+                assert_eq!(joins.equivalent("orders", "user_id").len(), 2);
+            },
+        );
+
+        joins(
+            "SELECT * FROM users u LEFT JOIN orders o ON u.id = o.user_id OR o.user_id IS NULL",
+            |joins| {
+                assert!(joins.equivalent("users", "id").is_empty());
+            },
+        );
+    }
+}
@@ -0,0 +1,133 @@
+//! Virtual `pgdog` schema, available on regular connections.
+//!
+//! Only `SELECT * FROM pgdog.<table>` is supported. Clients only
+//! see rows for their own user and database; the admin database shows everything.
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::net::{DataRow, Error, FromBytes, Message, Protocol, RowDescription, ToBytes};
+
+static METADATA: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?i)^\s*SELECT\s+\*\s+FROM\s+"?pgdog"?\."?(\w+)"?\s*;?\s*$"#).unwrap()
+});
+
+/// Table in the `pgdog` schema.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metadata {
+    /// Shards of the database the client is connected to.
+    Shards,
+    Clients,
+    Pools,
+    Servers,
+    Stats,
+    Version,
+}
+
+impl Metadata {
+    /// Check if the query is reading from the `pgdog` schema.
+    pub fn new(query: &str) -> Option<Self> {
+        let captures = METADATA.captures(query)?;
+
+        match captures.get(1)?.as_str().to_lowercase().as_str() {
+            "shards" => Some(Self::Shards),
+            "clients" => Some(Self::Clients),
+            "pools" => Some(Self::Pools),
+            "servers" => Some(Self::Servers),
+            "stats" => Some(Self::Stats),
+            "version" => Some(Self::Version),
+            _ => None,
+        }
+    }
+
+    /// Admin command returning the same data, if any.
+    pub fn admin_command(&self) -> Option<&'static str> {
+        match self {
+            Self::Shards => None,
+            Self::Clients => Some("show clients"),
+            Self::Pools => Some("show pools"),
+            Self::Servers => Some("show servers"),
+            Self::Stats => Some("show stats"),
+            Self::Version => Some("show version"),
+        }
+    }
+
+    /// Remove rows that don't belong to one of the visible user and database pairs.
+    pub fn filter(messages: Vec<Message>, visible: &[(&str, &str)]) -> Result<Vec<Message>, Error> {
+        let Some(rd) = messages.first().filter(|message| message.code() == 'T') else {
+            return Ok(messages);
+        };
+        let rd = RowDescription::from_bytes(rd.to_bytes()?)?;
+        let (user, database) = (rd.field_index("user"), rd.field_index("database"));
+
+        let mut filtered = vec![];
+        for message in messages {
+            if message.code() == 'D' {
+                let row = DataRow::from_bytes(message.to_bytes()?)?;
+                let matches = |index: Option<usize>, value: &str| {
+                    index.is_none_or(|index| row.get_text(index).as_deref() == Some(value))
+                };
+                if !visible
+                    .iter()
+                    .any(|(u, d)| matches(user, u) && matches(database, d))
+                {
+                    continue;
+                }
+            }
+            filtered.push(message);
+        }
+
+        Ok(filtered)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::net::Field;
+
+    use super::*;
+
+    #[test]
+    fn test_metadata() {
+        assert_eq!(
+            Metadata::new("SELECT * FROM pgdog.shards"),
+            Some(Metadata::Shards)
+        );
+        assert_eq!(
+            Metadata::new(r#"select * from "pgdog"."clients";"#),
+            Some(Metadata::Clients)
+        );
+        assert_eq!(Metadata::new("SELECT * FROM pgdog.users"), None);
+        assert_eq!(Metadata::new("SELECT id FROM pgdog.shards"), None);
+        assert_eq!(Metadata::new("SELECT * FROM shards"), None);
+    }
+
+    #[test]
+    fn test_metadata_filter() {
+        let rd = RowDescription::new(&[
+            Field::text("user"),
+            Field::text("database"),
+            Field::bigint("id"),
+        ]);
+        let mut messages = vec![rd.message().unwrap()];
+        for (user, database, id) in [("alice", "a", 1), ("bob", "b", 2), ("alice", "b", 3)] {
+            let mut dr = DataRow::new();
+            dr.add(user).add(database).add(id as i64);
+            messages.push(dr.message().unwrap());
+        }
+
+        let filtered = Metadata::filter(messages, &[("alice", "a")]).unwrap();
+        assert_eq!(filtered.len(), 2);
+        let row = DataRow::from_bytes(filtered[1].to_bytes().unwrap()).unwrap();
+        assert_eq!(row.get_int(2, true), Some(1));
+
+        // Tables without user and database columns are returned as-is.
+        let rd = RowDescription::new(&[Field::text("version")]);
+        let mut dr = DataRow::new();
+        dr.add("PgDog");
+        let messages = vec![rd.message().unwrap(), dr.message().unwrap()];
+        assert_eq!(
+            Metadata::filter(messages, &[("alice", "a")]).unwrap().len(),
+            2
+        );
+    }
+}
@@ -6,40 +6,60 @@ pub mod cache;
 pub mod column;
 pub mod command;
 pub mod comment;
+pub mod compat;
 pub mod copy;
+pub mod cross_shard;
 pub mod csv;
+pub mod distinct;
 pub mod error;
+pub mod explain;
 pub mod function;
+pub mod having;
 pub mod insert;
+pub mod join;
 pub mod key;
+pub mod metadata;
 pub mod multi_tenant;
 pub mod order_by;
 pub mod prepare;
+pub mod progress;
 pub mod query;
 pub mod rewrite;
 pub mod route;
+pub mod routing_rules;
 pub mod table;
+pub mod temp_table;
 pub mod tuple;
 pub mod value;
 pub mod where_clause;
 
-pub use aggregate::{Aggregate, AggregateFunction, AggregateTarget};
+pub use aggregate::{Aggregate, AggregateFunction, AggregateTarget, Helpers};
 pub use binary::BinaryStream;
-pub use cache::Cache;
+pub use cache::{Cache, RouteKey};
 pub use column::Column;
 pub use command::Command;
+pub use comment::Comment;
+pub use compat::Intercept;
 pub use copy::{CopyFormat, CopyParser};
+pub use cross_shard::Unsupported;
 pub use csv::{CsvStream, Record};
+pub use distinct::{Distinct, DistinctColumn};
 pub use error::Error;
+pub use explain::Explain;
 pub use function::Function;
 pub use function::{FunctionBehavior, LockingBehavior};
+pub use having::Having;
 pub use insert::Insert;
+pub use join::{JoinColumn, Joins};
 pub use key::Key;
-pub use order_by::OrderBy;
+pub use metadata::Metadata;
+pub use order_by::{OrderBy, SortOptions};
 pub use prepare::Prepare;
 pub use query::QueryParser;
-pub use route::{Route, Shard};
+pub use route::{Limit, Route, Shard};
+pub use routing_rules::RoutingRules;
 pub use table::Table;
+pub use temp_table::TempTable;
 pub use tuple::Tuple;
 pub use value::Value;
 pub use where_clause::WhereClause;
@@ -2,14 +2,21 @@
 
 use std::fmt::Debug;
 
-use crate::net::messages::Vector;
+use crate::{config::Collation, net::messages::Vector};
+
+/// `NULLS FIRST`/`NULLS LAST` and `COLLATE`, if specified.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SortOptions {
+    pub nulls_first: Option<bool>,
+    pub collation: Option<Collation>,
+}
 
 #[derive(Clone, Debug)]
 pub enum OrderBy {
-    Asc(usize),
-    Desc(usize),
-    AscColumn(String),
-    DescColumn(String),
+    Asc(usize, SortOptions),
+    Desc(usize, SortOptions),
+    AscColumn(String, SortOptions),
+    DescColumn(String, SortOptions),
     AscVectorL2Column(String, Vector),
     AscVectorL2(usize, Vector),
 }
@@ -19,8 +26,8 @@ impl OrderBy {
     pub fn asc(&self) -> bool {
         matches!(
             self,
-            OrderBy::Asc(_)
-                | OrderBy::AscColumn(_)
+            OrderBy::Asc(_, _)
+                | OrderBy::AscColumn(_, _)
                 | OrderBy::AscVectorL2Column(_, _)
                 | OrderBy::AscVectorL2(_, _)
         )
@@ -29,8 +36,8 @@ impl OrderBy {
     /// Column index.
     pub fn index(&self) -> Option<usize> {
         match self {
-            OrderBy::Asc(column) => Some(*column - 1),
-            OrderBy::Desc(column) => Some(*column - 1),
+            OrderBy::Asc(column, _) => Some(*column - 1),
+            OrderBy::Desc(column, _) => Some(*column - 1),
             OrderBy::AscVectorL2(column, _) => Some(*column - 1),
             _ => None,
         }
@@ -39,13 +46,36 @@ impl OrderBy {
     /// Get column name.
     pub fn name(&self) -> Option<&str> {
         match self {
-            OrderBy::AscColumn(ref name) => Some(name.as_str()),
-            OrderBy::DescColumn(ref name) => Some(name.as_str()),
+            OrderBy::AscColumn(ref name, _) => Some(name.as_str()),
+            OrderBy::DescColumn(ref name, _) => Some(name.as_str()),
             OrderBy::AscVectorL2Column(ref name, _) => Some(name.as_str()),
             _ => None,
         }
     }
 
+    /// NULLS FIRST or LAST. NULLs are larger than other values by default,
+    /// like in Postgres.
+    pub fn nulls_first(&self) -> bool {
+        match self {
+            OrderBy::Asc(_, options)
+            | OrderBy::Desc(_, options)
+            | OrderBy::AscColumn(_, options)
+            | OrderBy::DescColumn(_, options) => options.nulls_first.unwrap_or(!self.asc()),
+            _ => false,
+        }
+    }
+
+    /// Collation from `COLLATE`, if any.
+    pub fn collation(&self) -> Option<Collation> {
+        match self {
+            OrderBy::Asc(_, options)
+            | OrderBy::Desc(_, options)
+            | OrderBy::AscColumn(_, options)
+            | OrderBy::DescColumn(_, options) => options.collation,
+            _ => None,
+        }
+    }
+
     /// ORDER BY clause contains a vector.
     pub fn vector(&self) -> Option<(&Vector, &String)> {
         match self {
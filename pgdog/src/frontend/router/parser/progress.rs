@@ -0,0 +1,150 @@
+//! Progress of COPY statements running through the router.
+//!
+//! Shared between all clients, so bulk loads can be watched
+//! with `SHOW COPY` and OpenMetrics.
+
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+static COPIES: Lazy<Mutex<Inner>> = Lazy::new(|| Mutex::new(Inner::default()));
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Default, Debug)]
+struct Inner {
+    copies: BTreeMap<usize, Progress>,
+    totals: Totals,
+}
+
+/// Counters across all COPY statements, including finished ones.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Totals {
+    /// COPY statements started.
+    pub copies: usize,
+    /// Rows parsed.
+    pub rows: usize,
+    /// Bytes received from clients.
+    pub bytes: usize,
+}
+
+/// Progress of one COPY.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    /// Unique identifier.
+    pub id: usize,
+    /// Target table.
+    pub table: String,
+    /// When the COPY started.
+    pub started: Instant,
+    /// Rows parsed.
+    pub rows: usize,
+    /// Rows sent to each shard.
+    pub shards: Vec<usize>,
+    /// Bytes received from the client.
+    pub bytes: usize,
+}
+
+impl Progress {
+    /// How long the COPY has been running.
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Rows parsed per second.
+    pub fn rate(&self) -> f64 {
+        let elapsed = self.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.rows as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Records progress of one COPY and removes it once dropped.
+#[derive(Debug)]
+pub struct Tracker {
+    id: usize,
+}
+
+impl Tracker {
+    /// Start tracking a COPY into the table.
+    pub fn new(table: &str, shards: usize) -> Self {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let mut guard = COPIES.lock();
+        guard.totals.copies += 1;
+        guard.copies.insert(
+            id,
+            Progress {
+                id,
+                table: table.to_owned(),
+                started: Instant::now(),
+                rows: 0,
+                shards: vec![0; shards],
+                bytes: 0,
+            },
+        );
+
+        Self { id }
+    }
+
+    /// Record bytes received and rows sent to each shard.
+    pub fn record(&self, bytes: usize, rows: usize, shards: &[usize]) {
+        let mut guard = COPIES.lock();
+        guard.totals.rows += rows;
+        guard.totals.bytes += bytes;
+
+        if let Some(progress) = guard.copies.get_mut(&self.id) {
+            progress.rows += rows;
+            progress.bytes += bytes;
+            for (total, routed) in progress.shards.iter_mut().zip(shards) {
+                *total += routed;
+            }
+        }
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        COPIES.lock().copies.remove(&self.id);
+    }
+}
+
+/// COPY statements currently running.
+pub fn copies() -> Vec<Progress> {
+    COPIES.lock().copies.values().cloned().collect()
+}
+
+/// Counters across all COPY statements.
+pub fn totals() -> Totals {
+    COPIES.lock().totals
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let tracker = Tracker::new("progress_test", 2);
+        tracker.record(100, 3, &[1, 2]);
+        tracker.record(50, 1, &[1, 0]);
+
+        let progress = copies()
+            .into_iter()
+            .find(|copy| copy.table == "progress_test")
+            .unwrap();
+        assert_eq!(progress.rows, 4);
+        assert_eq!(progress.bytes, 150);
+        assert_eq!(progress.shards, vec![2, 2]);
+        assert!(totals().rows >= 4);
+
+        drop(tracker);
+        assert!(copies().iter().all(|copy| copy.table != "progress_test"));
+    }
+}
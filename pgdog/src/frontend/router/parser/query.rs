@@ -3,20 +3,20 @@ use std::{collections::HashSet, sync::Arc};
 
 use crate::{
     backend::{databases::databases, Cluster, ShardingSchema},
-    config::{config, ReadWriteStrategy},
+    config::{config, Collation, CrossShardStrictness, Functions, PoolerMode, ReadWriteStrategy},
     frontend::{
         buffer::BufferedQuery,
         router::{
             context::RouterContext,
             parser::{rewrite::Rewrite, OrderBy, Shard},
             round_robin,
-            sharding::{Centroids, ContextBuilder, Value as ShardingValue},
+            sharding::{Centroids, ContextBuilder, Tables, Value as ShardingValue},
             CopyRow,
         },
         PreparedStatements,
     },
     net::{
-        messages::{Bind, CopyData, Vector},
+        messages::{Bind, CopyData, Query, Vector},
         parameter::ParameterValue,
         Parameters,
     },
@@ -34,6 +34,9 @@ use pg_query::{
 use regex::Regex;
 use tracing::{debug, trace};
 
+static TEMP_TABLE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\b(temp|temporary|drop|discard)\b").unwrap());
+
 static REPLICATION_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
         "(CREATE_REPLICATION_SLOT|IDENTIFY_SYSTEM|DROP_REPLICATION_SLOT|READ_REPLICATION_SLOT|ALTER_REPLICATION_SLOT|TIMELINE_HISTORY).*",
@@ -41,6 +44,43 @@ static REPLICATION_REGEX: Lazy<Regex> = Lazy::new(|| {
     .unwrap()
 });
 
+/// `pg_catalog` tables and views describing the schema, which
+/// drivers read on connect. They're the same on all shards.
+const CATALOGS: &[&str] = &[
+    "pg_aggregate",
+    "pg_am",
+    "pg_attrdef",
+    "pg_attribute",
+    "pg_cast",
+    "pg_class",
+    "pg_collation",
+    "pg_constraint",
+    "pg_database",
+    "pg_depend",
+    "pg_description",
+    "pg_enum",
+    "pg_extension",
+    "pg_index",
+    "pg_indexes",
+    "pg_inherits",
+    "pg_language",
+    "pg_matviews",
+    "pg_namespace",
+    "pg_opclass",
+    "pg_operator",
+    "pg_proc",
+    "pg_range",
+    "pg_roles",
+    "pg_sequence",
+    "pg_sequences",
+    "pg_settings",
+    "pg_tables",
+    "pg_trigger",
+    "pg_type",
+    "pg_user",
+    "pg_views",
+];
+
 #[derive(Debug)]
 pub struct QueryParser {
     command: Command,
@@ -48,6 +88,16 @@ pub struct QueryParser {
     routed: bool,
     in_transaction: bool,
     write_override: Option<bool>,
+    pinned_shard: Option<usize>,
+    restore: bool,
+    /// The client pipelined more than one statement.
+    pipelined: bool,
+    /// The client pipelined different statements. Results of all of them
+    /// are merged the same way, so they can't be rewritten.
+    mixed_pipeline: bool,
+    /// Last `EXPLAIN (PGDOG)`, kept apart so it doesn't replace
+    /// the transaction's routing decision.
+    explained: Option<Command>,
 }
 
 impl Default for QueryParser {
@@ -58,6 +108,11 @@ impl Default for QueryParser {
             routed: false,
             in_transaction: false,
             write_override: None,
+            pinned_shard: None,
+            restore: false,
+            pipelined: false,
+            mixed_pipeline: false,
+            explained: None,
         }
     }
 }
@@ -68,8 +123,47 @@ impl QueryParser {
         self.replication_mode = true;
     }
 
+    /// Send all queries to the primary of this shard, without parsing them.
+    pub fn pin_shard(&mut self, shard: usize) {
+        self.pinned_shard = Some(shard);
+    }
+
+    /// Route statements sent by pg_restore, see [`Self::restore_command`].
+    pub fn restore(&mut self) {
+        self.restore = true;
+    }
+
     pub fn parse(&mut self, context: RouterContext) -> Result<&Command, Error> {
+        self.pipelined = !context.pipeline.is_empty();
+        self.mixed_pipeline = context.pipeline.iter().any(|(statement, _)| {
+            context.query.as_ref().map(|query| query.query()) != Some(statement.query())
+        });
+
+        // Show the routing decision instead of running the query.
+        if let Some(explain) = context
+            .query
+            .as_ref()
+            .filter(|query| query.simple())
+            .and_then(|query| Explain::new(query))
+        {
+            let command = self.explain(explain, context)?;
+            return Ok(self.explained.insert(command));
+        }
+
+        if let Some(shard) = self.pinned_shard {
+            self.command = Command::Query(Route::write(Some(shard)));
+            return Ok(&self.command);
+        }
+
+        if self.restore {
+            if let Some(ref query) = context.query {
+                self.command = Self::restore_command(query, context.cluster, context.bind)?;
+            }
+            return Ok(&self.command);
+        }
+
         if let Some(ref query) = context.query {
+            let routed = self.routed;
             self.command = self.query(
                 query,
                 context.cluster,
@@ -78,6 +172,21 @@ impl QueryParser {
                 context.params,
             )?;
 
+            // Statements in a pipeline run on the same connection.
+            // The first one is routed already.
+            if !routed && !context.pipeline.is_empty() {
+                let pipeline = match context.pipeline.split_first() {
+                    Some(((first, _), rest)) if first.query() == query.query() => rest,
+                    _ => &context.pipeline[..],
+                };
+                self.pipeline(
+                    pipeline,
+                    context.cluster,
+                    context.prepared_statements,
+                    context.params,
+                )?;
+            }
+
             // If the cluster only has one shard, use direct-to-shard queries.
             if let Command::Query(ref mut query) = self.command {
                 if !matches!(query.shard(), Shard::Direct(_)) && context.cluster.shards().len() == 1
@@ -85,11 +194,132 @@ impl QueryParser {
                     query.set_shard_mut(0);
                 }
             }
+
+            // Routing rules override the parser's read/write and shard decisions.
+            if let Command::Query(ref mut route) = self.command {
+                let config = config();
+                let rules = RoutingRules::new(&config.config.routing_rules);
+                if let Some(rule) = rules.find(
+                    context.cluster.user(),
+                    context.cluster.name(),
+                    context.params.get_default("application_name", ""),
+                    query,
+                )? {
+                    debug!("routing rule matched: {:?}", rule);
+                    RoutingRules::apply(
+                        rule.role,
+                        rule.shard,
+                        route,
+                        context.cluster.shards().len(),
+                    );
+                }
+            }
         }
 
         Ok(&self.command)
     }
 
+    /// Route the query inside `EXPLAIN (PGDOG)` without changing
+    /// the client's transaction state.
+    fn explain(&mut self, explain: Explain, context: RouterContext) -> Result<Command, Error> {
+        let previous = self.command.clone();
+        let (routed, in_transaction, write_override) =
+            (self.routed, self.in_transaction, self.write_override);
+        let sharding_schema = context.cluster.sharding_schema();
+        let context = RouterContext {
+            query: Some(BufferedQuery::Query(Query::new(explain.query()))),
+            bind: None,
+            pipeline: vec![],
+            ..context
+        };
+
+        let command = self.parse(context).cloned();
+        self.command = previous;
+        self.routed = routed;
+        self.in_transaction = in_transaction;
+        self.write_override = write_override;
+
+        Ok(Command::Explain(Box::new(
+            explain.describe(&command?, &sharding_schema),
+        )))
+    }
+
+    /// Route a pipeline of statements sent before a Sync, which run
+    /// on the same connection, so that every one of them can run there.
+    ///
+    /// The pipeline goes to the primary if any statement writes. All statements
+    /// must go to the same shard, or to the same set of shards; statements
+    /// that can run on any shard follow the others. Otherwise, the client
+    /// needs to send a Sync between them.
+    fn pipeline(
+        &mut self,
+        pipeline: &[(BufferedQuery, Option<&Bind>)],
+        cluster: &Cluster,
+        prepared_statements: &mut PreparedStatements,
+        params: &Parameters,
+    ) -> Result<(), Error> {
+        let Command::Query(ref route) = self.command else {
+            return Ok(());
+        };
+        let mut route = route.clone();
+        let (routed, in_transaction, write_override) =
+            (self.routed, self.in_transaction, self.write_override);
+        let multi_shard = cluster.shards().len() > 1;
+        // Shard(s) of statements that can't go anywhere else.
+        let mut shard = (!route.round_robin()).then(|| route.shard().clone());
+        let mut round_robin = route.round_robin();
+
+        let mut result = Ok(());
+        for (query, bind) in pipeline {
+            self.routed = false;
+            let command = match self.query(query, cluster, *bind, prepared_statements, params) {
+                Ok(command) => command,
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            };
+            let Command::Query(statement) = command else {
+                continue;
+            };
+
+            if statement.lock_session() {
+                route = route.set_lock_session();
+            }
+
+            if statement.is_write() {
+                route.set_read_mut(false);
+            }
+
+            if statement.round_robin() {
+                round_robin = true;
+            } else {
+                match shard {
+                    Some(ref shard) if shard != statement.shard() && multi_shard => {
+                        result = Err(Error::PipelineCrossShard);
+                        break;
+                    }
+                    _ => shard = Some(statement.shard().clone()),
+                }
+            }
+        }
+
+        self.routed = routed;
+        self.in_transaction = in_transaction;
+        self.write_override = write_override;
+        result?;
+
+        match shard {
+            Some(Shard::Direct(shard)) => route.set_shard_mut(shard),
+            // Statements for one shard would return rows from all of them.
+            Some(_) if round_robin && multi_shard => return Err(Error::PipelineCrossShard),
+            _ => (),
+        }
+        self.command = Command::Query(route);
+
+        Ok(())
+    }
+
     /// Shard copy data.
     pub fn copy_data(&mut self, rows: Vec<CopyData>) -> Result<Vec<CopyRow>, Error> {
         match &mut self.command {
@@ -98,10 +328,18 @@ impl QueryParser {
         }
     }
 
+    /// The client sent CopyDone or CopyFail.
+    pub fn copy_done(&mut self) {
+        if let Command::Copy(copy) = &mut self.command {
+            copy.finish();
+        }
+    }
+
     /// Get the route currently determined by the parser.
     pub fn route(&self) -> Route {
         match self.command {
             Command::Query(ref route) => route.clone(),
+            Command::Copy(ref copy) => Route::write(None).set_copy_headers(copy.copy_out_headers()),
             _ => Route::write(None),
         }
     }
@@ -135,10 +373,30 @@ impl QueryParser {
             }
         }
 
+        let config = config();
+
+        // Answer known driver statements locally. Only simple protocol
+        // is supported since we don't emulate the extended protocol responses.
+        // Statements that fail inside a transaction block are left to the server.
+        if query.simple() && cluster.pooler_mode() == PoolerMode::Transaction {
+            if let Some(intercept) = Intercept::new(query, &config.config.compatibility) {
+                if !(intercept.outside_transaction() && self.in_transaction) {
+                    return Ok(Command::Intercept(intercept));
+                }
+            }
+        }
+
+        // Queries reading from the virtual pgdog schema.
+        if query.simple() && config.config.general.metadata_schema {
+            if let Some(metadata) = Metadata::new(query) {
+                return Ok(Command::Metadata(metadata));
+            }
+        }
+
         let shards = cluster.shards().len();
         let read_only = cluster.read_only();
         let write_only = cluster.write_only();
-        let full_prepared_statements = config().config.general.prepared_statements.full();
+        let full_prepared_statements = config.config.general.prepared_statements.full();
         let sharding_schema = cluster.sharding_schema();
         let dry_run = sharding_schema.tables.dry_run();
         let multi_tenant = cluster.multi_tenant();
@@ -162,13 +420,16 @@ impl QueryParser {
         //
         // We know what the routing decision is in this case and we don't
         // need to invoke the parser.
+        //
+        // Temporary tables are still tracked, so the client keeps its connection.
         if parser_disabled {
-            if read_only {
-                return Ok(Command::Query(Route::read(Some(0))));
-            }
-            if write_only {
-                return Ok(Command::Query(Route::write(Some(0))));
-            }
+            let mut route = if read_only {
+                Route::read(Some(0))
+            } else {
+                Route::write(Some(0))
+            };
+            route.set_temp_table_mut(Self::temp_table(query));
+            return Ok(Command::Query(route));
         }
 
         // We already decided where all queries for this
@@ -181,16 +442,32 @@ impl QueryParser {
             }
 
             if multi_tenant.is_none() {
-                return Ok(self.command.clone());
+                let mut command = self.command.clone();
+                if let Command::Query(ref mut route) = command {
+                    route.set_temp_table_mut(Self::temp_table(query));
+                    // Timeouts are per statement.
+                    route.set_timeout_mut(Comment::parse(query, &sharding_schema)?.timeout);
+                    let ast = match query {
+                        BufferedQuery::Prepared(query) => {
+                            Cache::get().parse(query.query()).map_err(Error::PgQuery)?
+                        }
+                        BufferedQuery::Query(query) => {
+                            Arc::new(parse(query.query()).map_err(Error::PgQuery)?)
+                        }
+                    };
+                    self.routed(route, query, &ast, bind, &sharding_schema, dry_run)?;
+                }
+                return Ok(command);
             }
         }
 
-        let mut shard = Shard::All;
+        let mut comment = Comment::default();
 
-        // Parse hardcoded shard from a query comment.
+        // Parse hardcoded shard, role and timeout from query comments.
         if !router_disabled && !self.routed {
-            shard = super::comment::shard(query, &sharding_schema)?;
+            comment = Comment::parse(query, &sharding_schema)?;
         }
+        let shard = comment.shard.clone();
 
         // Cluster is read only or write only, traffic split isn't needed,
         // and prepared statements support is limited to the extended protocol,
@@ -198,17 +475,43 @@ impl QueryParser {
         if !full_prepared_statements && multi_tenant.is_none() {
             if let Shard::Direct(_) = shard {
                 if cluster.read_only() {
-                    return Ok(Command::Query(Route::read(shard)));
+                    return Ok(Command::Query(comment.apply(Route::read(shard))));
                 }
 
                 if cluster.write_only() {
-                    return Ok(Command::Query(Route::write(shard)));
+                    return Ok(Command::Query(comment.apply(Route::write(shard))));
                 }
             }
         }
 
         let cache = Cache::get();
 
+        // Prepared statements executed with the same parameters
+        // are routed the same way, unless the routing decision depends on
+        // the transaction state. Pipelined statements are routed together,
+        // so their routes aren't cached either.
+        let route_cache_size = config.config.general.route_cache_size;
+        let route_key = match (query, bind) {
+            (BufferedQuery::Prepared(parse), Some(bind))
+                if route_cache_size > 0
+                    && !dry_run
+                    && !self.pipelined
+                    && multi_tenant.is_none()
+                    && self.write_override.is_none() =>
+            {
+                RouteKey::new(cluster.name(), cluster.user(), parse.query(), bind)
+            }
+            _ => None,
+        };
+
+        if let Some(ref key) = route_key {
+            if let Some(route) = cache.route(key) {
+                debug!("query router decision (cached): {:#?}", route);
+                self.routed = true;
+                return Ok(Command::Query(route));
+            }
+        }
+
         // Get the AST from cache or parse the statement live.
         let ast = match query {
             // Only prepared statements (or just extended) are cached.
@@ -240,7 +543,11 @@ impl QueryParser {
         }
 
         if self.routed {
-            return Ok(self.command.clone());
+            let mut command = self.command.clone();
+            if let Command::Query(ref mut route) = command {
+                self.routed(route, query, &ast, bind, &sharding_schema, dry_run)?;
+            }
+            return Ok(command);
         }
 
         //
@@ -261,20 +568,25 @@ impl QueryParser {
         let mut command = match root.node {
             // SELECT statements.
             Some(NodeEnum::SelectStmt(ref stmt)) => {
-                let mut writes = Self::select_writes(stmt)?;
+                let mut writes = Self::select_writes(stmt, &config.config.functions)?;
                 // Write overwrite because of conservative read/write split.
                 if let Some(true) = self.write_override {
                     writes.writes = true;
                 }
 
                 if matches!(shard, Shard::Direct(_)) {
-                    return Ok(Command::Query(Route::read(shard).set_write(writes)));
+                    return Ok(Command::Query(
+                        comment.apply(Route::read(shard).set_write(writes)),
+                    ));
                 }
                 // `SELECT NOW()`, `SELECT 1`, etc.
                 else if ast.tables().is_empty() {
                     return Ok(Command::Query(
-                        Route::read(Some(round_robin::next() % cluster.shards().len()))
-                            .set_write(writes),
+                        comment.apply(
+                            Route::read(None)
+                                .set_round_robin(round_robin::next() % cluster.shards().len())
+                                .set_write(writes),
+                        ),
                     ));
                 } else {
                     let command = Self::select(stmt, &sharding_schema, bind)?;
@@ -284,14 +596,37 @@ impl QueryParser {
                         // shard if the table(s) it's touching contain
                         // the same data on all shards.
                         if query.is_all_shards() {
-                            let tables = ast.tables();
-                            omni = tables
-                                .iter()
-                                .all(|t| sharding_schema.tables.omnishards().contains(t));
+                            // System catalogs are the same on all shards too, e.g.
+                            // Npgsql loading types on connect.
+                            let catalogs = config.config.compatibility.catalog_queries;
+                            omni = ast.tables().iter().all(|t| {
+                                sharding_schema.tables.omnishards().contains(t)
+                                    || (catalogs && Self::system_catalog(t))
+                            });
                         }
 
                         if omni {
-                            query.set_shard_mut(round_robin::next() % cluster.shards().len());
+                            query.set_round_robin_mut(round_robin::next() % cluster.shards().len());
+                        }
+
+                        if !matches!(query.shard(), Shard::Direct(_)) {
+                            if let Some(unsupported) = root
+                                .node
+                                .as_ref()
+                                .and_then(|node| Unsupported::check(node, &sharding_schema))
+                            {
+                                match config.config.general.cross_shard_strictness {
+                                    CrossShardStrictness::Permissive => (),
+                                    CrossShardStrictness::Strict => {
+                                        return Err(Error::CrossShard(unsupported))
+                                    }
+                                    CrossShardStrictness::SingleShard => {
+                                        query.set_round_robin_mut(
+                                            round_robin::next() % cluster.shards().len(),
+                                        );
+                                    }
+                                }
+                            }
                         }
 
                         Ok(Command::Query(query.set_write(writes)))
@@ -356,6 +691,13 @@ impl QueryParser {
 
         self.routed = true;
 
+        // Temporary tables pin the client to its server connection.
+        if let Command::Query(ref mut route) = command {
+            if let Some(ref node) = root.node {
+                route.set_temp_table_mut(TempTable::new(node));
+            }
+        }
+
         // Overwrite shard using shard we got from a comment, if any.
         if let Shard::Direct(shard) = shard {
             if let Command::Query(ref mut route) = command {
@@ -363,6 +705,11 @@ impl QueryParser {
             }
         }
 
+        // Role and timeout from comments.
+        if let Command::Query(route) = command {
+            command = Command::Query(comment.apply(route));
+        }
+
         // If we only have one shard, set it.
         //
         // If the query parser couldn't figure it out,
@@ -375,25 +722,38 @@ impl QueryParser {
             }
         }
 
-        // Last ditch attempt to route a query to a specific shard.
-        //
         // Looking through manual queries to see if we have any
-        // with the fingerprint.
+        // with the fingerprint or matching the query text.
         //
         if let Command::Query(ref mut route) = command {
-            if route.shard().all() {
-                let databases = databases();
-                // Only fingerprint the query if some manual queries are configured.
-                // Otherwise, we're wasting time parsing SQL.
-                if !databases.manual_queries().is_empty() {
-                    let fingerprint = fingerprint(query).map_err(Error::PgQuery)?;
-                    debug!("fingerprint: {}", fingerprint.hex);
-                    let manual_route = databases.manual_query(&fingerprint.hex).cloned();
-
-                    // TODO: check routing logic required by config.
-                    if manual_route.is_some() {
-                        route.set_shard_mut(round_robin::next() % cluster.shards().len());
-                    }
+            let databases = databases();
+            // Only fingerprint the query if some manual queries are configured.
+            // Otherwise, we're wasting time parsing SQL.
+            let mut manual_query = None;
+            if !databases.manual_queries().is_empty() {
+                let fingerprint = fingerprint(query).map_err(Error::PgQuery)?;
+                debug!("fingerprint: {}", fingerprint.hex);
+                manual_query = databases.manual_query(&fingerprint.hex).cloned();
+            }
+            if manual_query.is_none() && !databases.manual_patterns().is_empty() {
+                manual_query = databases.manual_pattern(query).cloned();
+            }
+
+            if let Some(manual_query) = manual_query {
+                if manual_query.block {
+                    return Err(Error::Blocked);
+                }
+
+                if manual_query.has_target() {
+                    RoutingRules::apply(
+                        manual_query.role,
+                        manual_query.shard,
+                        route,
+                        cluster.shards().len(),
+                    );
+                } else if route.shard().all() {
+                    // Last ditch attempt to route a query to a specific shard.
+                    route.set_round_robin_mut(round_robin::next() % cluster.shards().len());
                 }
             }
 
@@ -402,8 +762,37 @@ impl QueryParser {
             }
         }
 
+        if let (Command::Query(ref mut route), Some(NodeEnum::SelectStmt(ref stmt))) =
+            (&mut command, &root.node)
+        {
+            self.merge(route, stmt, query, ast.protobuf.stmts.len(), dry_run)?;
+        }
+
+        // Generate the sharding key for INSERTs that don't have it.
+        if let (Command::Query(ref mut route), Some(NodeEnum::InsertStmt(ref stmt))) =
+            (&mut command, &root.node)
+        {
+            if let Shard::Direct(shard) = route.shard() {
+                if query.simple() && !dry_run && ast.protobuf.stmts.len() == 1 {
+                    if let Some(stmt) = Insert::new(stmt).generate_key(&sharding_schema, *shard) {
+                        let rewrite = NodeEnum::InsertStmt(Box::new(stmt))
+                            .deparse()
+                            .map_err(Error::PgQuery)?;
+                        route.set_rewrite_mut(Some(rewrite));
+                    }
+                }
+            }
+        }
+
         debug!("query router decision: {:#?}", command);
 
+        // Round robin picks a different shard every time.
+        if let (Some(key), Command::Query(route)) = (route_key, &command) {
+            if !route.round_robin() {
+                cache.record_route(key, route, route_cache_size);
+            }
+        }
+
         if dry_run {
             let default_route = Route::write(None);
             cache.record_command(
@@ -419,6 +808,109 @@ impl QueryParser {
         }
     }
 
+    /// Rows of a SELECT sent to more than one shard are merged by PgDog.
+    ///
+    /// AVG, VARIANCE and STDDEV are computed from sums and counts returned by
+    /// each shard, which are fetched by rewriting the query. If it can't be
+    /// rewritten, the query isn't sent at all, instead of returning wrong results.
+    fn merge(
+        &self,
+        route: &mut Route,
+        stmt: &SelectStmt,
+        query: &BufferedQuery,
+        statements: usize,
+        dry_run: bool,
+    ) -> Result<(), Error> {
+        let multi_shard = !dry_run && !matches!(route.shard(), Shard::Direct(_));
+        // The rewritten statement replaces the client's, and results
+        // of all statements in the request are merged the same way.
+        let rewrite = multi_shard && statements == 1 && !self.mixed_pipeline;
+
+        // Groups are merged, filtered by HAVING and limited by PgDog,
+        // since each shard only has part of every group.
+        let grouped = multi_shard && !route.aggregate().is_empty();
+
+        if multi_shard && !rewrite {
+            let limited = grouped && route.limit().is_some();
+            if limited
+                || route
+                    .aggregate()
+                    .clone()
+                    .rewrite(&mut stmt.clone())
+                    .map_err(Error::CrossShard)?
+            {
+                return Err(Error::CrossShard(Unsupported::PipelinedAggregate));
+            }
+        }
+
+        if rewrite && query.simple() {
+            route.rewrite_select(stmt)?;
+        } else {
+            if rewrite {
+                route.rewrite_aggregate(stmt)?;
+            }
+            if let Some(limit) = route.limit().filter(|_| !grouped) {
+                let direct = matches!(route.shard(), Shard::Direct(_));
+                match Self::limit_parameters(stmt, limit) {
+                    Some(parameters) if !direct => route.set_parameters_mut(parameters),
+                    // Each shard applies the OFFSET itself.
+                    _ => route.set_limit_mut(Some(Limit { offset: 0, ..limit })),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Statements in a transaction go where the first one went,
+    /// but rows from more than one shard are merged the way
+    /// each statement needs.
+    fn routed(
+        &self,
+        route: &mut Route,
+        query: &BufferedQuery,
+        ast: &pg_query::ParseResult,
+        bind: Option<&Bind>,
+        sharding_schema: &ShardingSchema,
+        dry_run: bool,
+    ) -> Result<(), Error> {
+        route.set_merge_mut(Route::default());
+        if matches!(route.shard(), Shard::Direct(_)) {
+            return Ok(());
+        }
+
+        let stmt = ast
+            .protobuf
+            .stmts
+            .first()
+            .and_then(|stmt| stmt.stmt.as_ref())
+            .and_then(|stmt| stmt.node.as_ref());
+        if let Some(NodeEnum::SelectStmt(ref stmt)) = stmt {
+            if let Command::Query(select) = Self::select(stmt, sharding_schema, bind)? {
+                route.set_merge_mut(select);
+            }
+            self.merge(route, stmt, query, ast.protobuf.stmts.len(), dry_run)?;
+        }
+
+        Ok(())
+    }
+
+    /// Temporary tables created or dropped by a statement
+    /// in a transaction that's already routed.
+    fn temp_table(query: &BufferedQuery) -> Option<TempTable> {
+        if !TEMP_TABLE_REGEX.is_match(query) {
+            return None;
+        }
+
+        let ast = match query {
+            BufferedQuery::Prepared(query) => Cache::get().parse(query.query()).ok()?,
+            BufferedQuery::Query(query) => Arc::new(parse(query.query()).ok()?),
+        };
+        let node = ast.protobuf.stmts.first()?.stmt.as_ref()?.node.as_ref()?;
+
+        TempTable::new(node)
+    }
+
     fn show(
         &mut self,
         stmt: &VariableShowStmt,
@@ -543,13 +1035,27 @@ impl QueryParser {
     fn where_clause(
         sharding_schema: &ShardingSchema,
         where_clause: &WhereClause,
+        joins: Option<&Joins>,
         params: Option<&Bind>,
     ) -> Result<HashSet<Shard>, Error> {
         let mut shards = HashSet::new();
         // Complexity: O(number of sharded tables * number of columns in the query)
         for table in sharding_schema.tables().tables() {
             let table_name = table.name.as_deref();
-            let keys = where_clause.keys(table_name, &table.column);
+            let mut keys = where_clause.keys(table_name, &table.column);
+
+            // Table referred to by its alias, or filtered on
+            // through a column it's joined with.
+            if let (Some(joins), Some(name)) = (joins, table_name) {
+                for alias in joins.aliases(name) {
+                    if alias != name {
+                        keys.extend(where_clause.keys(Some(alias), &table.column));
+                    }
+                }
+                for column in joins.equivalent(name, &table.column) {
+                    keys.extend(where_clause.keys(Some(column.table), column.name));
+                }
+            }
             for key in keys {
                 match key {
                     Key::Constant(value) => {
@@ -608,18 +1114,93 @@ impl QueryParser {
         shard
     }
 
-    fn select_writes(stmt: &SelectStmt) -> Result<FunctionBehavior, Error> {
+    /// Table has the same data on all shards, so writes
+    /// have to go to all of them.
+    fn omnisharded(table: Option<Table>, sharding_schema: &ShardingSchema) -> bool {
+        table.is_some_and(|table| sharding_schema.tables.omnishards().contains(table.name))
+    }
+
+    /// Table is in the `pg_catalog` or `information_schema` schema, e.g.
+    /// `pg_catalog.pg_type` or `information_schema.columns`. `pg_catalog` is
+    /// searched first, so unqualified names of catalogs in [`CATALOGS`] are in it too.
+    fn system_catalog(table: &str) -> bool {
+        match table.split_once('.') {
+            Some((schema, _)) => schema == "pg_catalog" || schema == "information_schema",
+            None => CATALOGS.contains(&table),
+        }
+    }
+
+    /// Check if the `SELECT` has to go to the primary because it locks rows
+    /// (`FOR UPDATE`, `FOR SHARE`, etc.) or calls a function that writes,
+    /// e.g. `pg_advisory_lock`. CTEs, subqueries and set operations are checked too.
+    fn select_writes(stmt: &SelectStmt, functions: &Functions) -> Result<FunctionBehavior, Error> {
+        let mut behavior = FunctionBehavior::default();
+
         for target in &stmt.target_list {
             if let Ok(func) = Function::try_from(target) {
-                return Ok(func.behavior());
+                behavior.merge(func.behavior(functions));
             }
         }
 
-        Ok(if stmt.locking_clause.is_empty() {
-            FunctionBehavior::default()
-        } else {
-            FunctionBehavior::writes_only()
-        })
+        if !stmt.locking_clause.is_empty() {
+            behavior.merge(FunctionBehavior::writes_only());
+        }
+
+        // UNION, INTERSECT, EXCEPT.
+        for arm in [&stmt.larg, &stmt.rarg].into_iter().flatten() {
+            behavior.merge(Self::select_writes(arm, functions)?);
+        }
+
+        if let Some(ref with_clause) = stmt.with_clause {
+            for cte in &with_clause.ctes {
+                let Some(NodeEnum::CommonTableExpr(ref cte)) = cte.node else {
+                    continue;
+                };
+
+                match cte.ctequery.as_ref().and_then(|query| query.node.as_ref()) {
+                    Some(NodeEnum::SelectStmt(stmt)) => {
+                        behavior.merge(Self::select_writes(stmt, functions)?);
+                    }
+                    // WITH t AS (INSERT ... RETURNING *) SELECT * FROM t
+                    Some(NodeEnum::InsertStmt(_))
+                    | Some(NodeEnum::UpdateStmt(_))
+                    | Some(NodeEnum::DeleteStmt(_)) => {
+                        behavior.merge(FunctionBehavior::writes_only());
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        for from in &stmt.from_clause {
+            match from.node {
+                Some(NodeEnum::RangeSubselect(ref subselect)) => {
+                    if let Some(NodeEnum::SelectStmt(ref stmt)) = subselect
+                        .subquery
+                        .as_ref()
+                        .and_then(|query| query.node.as_ref())
+                    {
+                        behavior.merge(Self::select_writes(stmt, functions)?);
+                    }
+                }
+
+                // SELECT * FROM my_function()
+                Some(NodeEnum::RangeFunction(ref range_function)) => {
+                    for function in &range_function.functions {
+                        // Each function is a list of the call and its column definitions.
+                        if let Some(NodeEnum::List(ref list)) = function.node {
+                            if let Some(Ok(func)) = list.items.first().map(Function::try_from) {
+                                behavior.merge(func.behavior(functions));
+                            }
+                        }
+                    }
+                }
+
+                _ => (),
+            }
+        }
+
+        Ok(behavior)
     }
 
     fn select(
@@ -627,13 +1208,14 @@ impl QueryParser {
         sharding_schema: &ShardingSchema,
         params: Option<&Bind>,
     ) -> Result<Command, Error> {
-        let order_by = Self::select_sort(&stmt.sort_clause, params);
+        let order_by = Self::select_sort(stmt, params);
         let mut shards = HashSet::new();
         let the_table = Table::try_from(&stmt.from_clause).ok();
+        let joins = Joins::new(stmt);
         if let Some(where_clause) =
             WhereClause::new(the_table.as_ref().map(|t| t.name), &stmt.where_clause)
         {
-            shards = Self::where_clause(sharding_schema, &where_clause, params)?;
+            shards = Self::where_clause(sharding_schema, &where_clause, Some(&joins), params)?;
         }
 
         // Shard by vector in ORDER BY clause.
@@ -658,30 +1240,136 @@ impl QueryParser {
         let shard = Self::converge(shards);
 
         let aggregates = Aggregate::parse(stmt)?;
+        let limit = Self::select_limit(stmt, params);
+
+        Ok(Command::Query(
+            Route::select(shard, order_by, aggregates)
+                .set_distinct(Distinct::new(stmt))
+                .set_limit(limit),
+        ))
+    }
+
+    /// Parse the `LIMIT` and `OFFSET` clauses of a `SELECT` statement.
+    fn select_limit(stmt: &SelectStmt, params: Option<&Bind>) -> Option<Limit> {
+        if stmt.limit_count.is_none() && stmt.limit_offset.is_none() {
+            return None;
+        }
+
+        // FETCH FIRST ... WITH TIES returns more rows than the limit.
+        if stmt.limit_option() == LimitOption::WithTies {
+            return None;
+        }
 
-        Ok(Command::Query(Route::select(shard, order_by, aggregates)))
+        // None if it's not a constant or a parameter.
+        let value = |node: &Option<Box<Node>>| -> Option<Option<usize>> {
+            let Some(node) = node else {
+                return Some(None);
+            };
+            match node.node {
+                Some(NodeEnum::AConst(ref aconst)) if aconst.isnull => Some(None),
+                _ => match Value::try_from(&node.node).ok()? {
+                    Value::Integer(value) => usize::try_from(value).ok().map(Some),
+                    Value::Placeholder(p) => params?
+                        .parameter((p - 1) as usize)
+                        .ok()??
+                        .bigint()
+                        .and_then(|value| usize::try_from(value).ok())
+                        .map(Some),
+                    _ => None,
+                },
+            }
+        };
+
+        Some(Limit {
+            limit: value(&stmt.limit_count)?,
+            offset: value(&stmt.limit_offset)?.unwrap_or(0),
+        })
+    }
+
+    /// Bind parameters that make each shard return the first LIMIT + OFFSET rows,
+    /// so the OFFSET can be applied after merging them. `None` if LIMIT or OFFSET
+    /// is a constant, which can't be changed in a prepared statement.
+    fn limit_parameters(stmt: &SelectStmt, limit: Limit) -> Option<Vec<(usize, i64)>> {
+        if limit.offset == 0 {
+            return Some(vec![]);
+        }
+
+        let placeholder = |node: &Option<Box<Node>>| match Value::try_from(&node.as_ref()?.node) {
+            Ok(Value::Placeholder(p)) if p > 0 => Some(p as usize - 1),
+            _ => None,
+        };
+
+        let offset = placeholder(&stmt.limit_offset)?;
+        let mut parameters = vec![(offset, 0)];
+        if let Some(count) = limit.limit {
+            let count_param = placeholder(&stmt.limit_count).filter(|p| *p != offset)?;
+            parameters.push((count_param, (count + limit.offset) as i64));
+        }
+
+        Some(parameters)
     }
 
     /// Parse the `ORDER BY` clause of a `SELECT` statement.
-    fn select_sort(nodes: &[Node], params: Option<&Bind>) -> Vec<OrderBy> {
+    fn select_sort(stmt: &SelectStmt, params: Option<&Bind>) -> Vec<OrderBy> {
         let mut order_by = vec![];
-        for clause in nodes {
+        for clause in &stmt.sort_clause {
             if let Some(NodeEnum::SortBy(ref sort_by)) = clause.node {
                 let asc = matches!(sort_by.sortby_dir, 0..=2);
-                let Some(ref node) = sort_by.node else {
+                let mut options = SortOptions {
+                    nulls_first: match sort_by.sortby_nulls() {
+                        SortByNulls::SortbyNullsFirst => Some(true),
+                        SortByNulls::SortbyNullsLast => Some(false),
+                        _ => None,
+                    },
+                    collation: None,
+                };
+                let Some(ref expr) = sort_by.node else {
                     continue;
                 };
-                let Some(ref node) = node.node else {
+
+                // ORDER BY x COLLATE "C"
+                let expr = match expr.node {
+                    Some(NodeEnum::CollateClause(ref collate)) => {
+                        options.collation =
+                            collate.collname.last().and_then(|name| match name.node {
+                                Some(NodeEnum::String(ref name)) => {
+                                    Some(Collation::from_name(&name.sval))
+                                }
+                                _ => None,
+                            });
+                        let Some(ref arg) = collate.arg else {
+                            continue;
+                        };
+                        arg
+                    }
+                    _ => expr,
+                };
+
+                let Some(ref node) = expr.node else {
                     continue;
                 };
 
+                // Expressions are sorted using the result column
+                // with the same expression, e.g. ORDER BY lower(email).
+                let position = || {
+                    Aggregate::column(&Aggregate::expressions(&stmt.target_list), expr).map(
+                        |index| {
+                            if asc {
+                                OrderBy::Asc(index + 1, options.clone())
+                            } else {
+                                OrderBy::Desc(index + 1, options.clone())
+                            }
+                        },
+                    )
+                };
+
                 match node {
                     NodeEnum::AConst(aconst) => {
                         if let Some(Val::Ival(ref integer)) = aconst.val {
                             order_by.push(if asc {
-                                OrderBy::Asc(integer.ival as usize)
+                                OrderBy::Asc(integer.ival as usize, options.clone())
                             } else {
-                                OrderBy::Desc(integer.ival as usize)
+                                OrderBy::Desc(integer.ival as usize, options.clone())
                             });
                         }
                     }
@@ -694,9 +1382,9 @@ impl QueryParser {
                         };
                         if let Some(NodeEnum::String(ref string)) = field.node {
                             order_by.push(if asc {
-                                OrderBy::AscColumn(string.sval.clone())
+                                OrderBy::AscColumn(string.sval.clone(), options.clone())
                             } else {
-                                OrderBy::DescColumn(string.sval.clone())
+                                OrderBy::DescColumn(string.sval.clone(), options.clone())
                             });
                         }
                     }
@@ -742,14 +1430,14 @@ impl QueryParser {
                                                 }
                                             }
                                         }
-                                        _ => continue,
+                                        _ => order_by.extend(position()),
                                     }
                                 }
                             }
                         }
                     }
 
-                    _ => continue,
+                    _ => order_by.extend(position()),
                 }
             }
         }
@@ -757,6 +1445,70 @@ impl QueryParser {
         order_by
     }
 
+    /// Route a statement restored into all shards.
+    ///
+    /// Rows in `COPY` are split between shards like for any other client.
+    /// `INSERT` into sharded tables must have one row and its sharding key,
+    /// so it's never sent to all shards or a random one. Everything else,
+    /// e.g. DDL, `SET`, `set_config()` and `setval()`, is sent to all shards,
+    /// which keep the session's state since its connections are pinned.
+    fn restore_command(
+        query: &BufferedQuery,
+        cluster: &Cluster,
+        bind: Option<&Bind>,
+    ) -> Result<Command, Error> {
+        let ast = match query {
+            BufferedQuery::Prepared(query) => {
+                Cache::get().parse(query.query()).map_err(Error::PgQuery)?
+            }
+            BufferedQuery::Query(query) => Arc::new(parse(query.query()).map_err(Error::PgQuery)?),
+        };
+        let root = ast
+            .protobuf
+            .stmts
+            .first()
+            .and_then(|stmt| stmt.stmt.as_ref())
+            .and_then(|stmt| stmt.node.as_ref());
+
+        match root {
+            Some(NodeEnum::CopyStmt(stmt)) if stmt.is_from => Self::copy(stmt, cluster),
+            Some(NodeEnum::InsertStmt(stmt)) => {
+                Self::restore_insert(stmt, &cluster.sharding_schema(), bind)
+            }
+            _ => Ok(Command::Query(Route::write(Shard::All))),
+        }
+    }
+
+    fn restore_insert(
+        stmt: &InsertStmt,
+        sharding_schema: &ShardingSchema,
+        params: Option<&Bind>,
+    ) -> Result<Command, Error> {
+        let insert = Insert::new(stmt);
+        let table = insert.table();
+        if Self::omnisharded(table, sharding_schema) {
+            return Ok(Command::Query(Route::write(Shard::All).set_replicated()));
+        }
+        let name = || table.map(|table| table.name.to_owned()).unwrap_or_default();
+        if insert.round_robin(sharding_schema) {
+            return Err(Error::RestoreNoKey(name()));
+        }
+        match insert.shard(sharding_schema, params)? {
+            Shard::Direct(shard) => Ok(Command::Query(Route::write(Some(shard)))),
+            // Rows of a sharded table going to different shards, or a key we can't read.
+            _ if table
+                .is_some_and(|table| Tables::new(sharding_schema).sharded(table).is_some()) =>
+            {
+                if params.is_none() && insert.tuples().len() > 1 {
+                    Err(Error::RestoreMultiRow(name()))
+                } else {
+                    Err(Error::RestoreNoKey(name()))
+                }
+            }
+            shard => Ok(Command::Query(Route::write(shard))),
+        }
+    }
+
     fn copy(stmt: &CopyStmt, cluster: &Cluster) -> Result<Command, Error> {
         let parser = CopyParser::new(stmt, cluster)?;
         if let Some(parser) = parser {
@@ -772,8 +1524,16 @@ impl QueryParser {
         params: Option<&Bind>,
     ) -> Result<Command, Error> {
         let insert = Insert::new(stmt);
-        let shard = insert.shard(sharding_schema, params)?;
-        Ok(Command::Query(Route::write(shard)))
+        if Self::omnisharded(insert.table(), sharding_schema) {
+            return Ok(Command::Query(Route::write(Shard::All).set_replicated()));
+        }
+        let route = match insert.shard(sharding_schema, params)? {
+            Shard::Direct(shard) if insert.round_robin(sharding_schema) => {
+                Route::write(None).set_round_robin(shard)
+            }
+            shard => Route::write(shard),
+        };
+        Ok(Command::Query(route))
     }
 
     fn update(
@@ -782,11 +1542,14 @@ impl QueryParser {
         params: Option<&Bind>,
     ) -> Result<Command, Error> {
         let table = stmt.relation.as_ref().map(Table::from);
+        if Self::omnisharded(table, sharding_schema) {
+            return Ok(Command::Query(Route::write(Shard::All).set_replicated()));
+        }
 
         let where_clause = WhereClause::new(table.map(|t| t.name), &stmt.where_clause);
 
         if let Some(where_clause) = where_clause {
-            let shards = Self::where_clause(sharding_schema, &where_clause, params)?;
+            let shards = Self::where_clause(sharding_schema, &where_clause, None, params)?;
             return Ok(Command::Query(Route::write(Self::converge(shards))));
         }
 
@@ -799,10 +1562,13 @@ impl QueryParser {
         params: Option<&Bind>,
     ) -> Result<Command, Error> {
         let table = stmt.relation.as_ref().map(Table::from);
+        if Self::omnisharded(table, sharding_schema) {
+            return Ok(Command::Query(Route::write(Shard::All).set_replicated()));
+        }
         let where_clause = WhereClause::new(table.map(|t| t.name), &stmt.where_clause);
 
         if let Some(where_clause) = where_clause {
-            let shards = Self::where_clause(sharding_schema, &where_clause, params)?;
+            let shards = Self::where_clause(sharding_schema, &where_clause, None, params)?;
             return Ok(Command::Query(Route::write(Self::converge(shards))));
         }
 
@@ -813,9 +1579,10 @@ impl QueryParser {
 #[cfg(test)]
 mod test {
 
+    use crate::backend::ProtocolMessage;
     use crate::net::{
         messages::{parse::Parse, Parameter},
-        Format,
+        Execute, Format,
     };
 
     use super::{super::Shard, *};
@@ -980,6 +1747,23 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_order_by_options() {
+        let route = query!(
+            "SELECT id, lower(email) AS e FROM sharded \
+            ORDER BY lower(email) DESC NULLS LAST, email COLLATE \"C\", e, random()"
+        );
+        let order_by = route.order_by();
+        assert_eq!(order_by.len(), 3);
+        assert_eq!(order_by[0].index(), Some(1));
+        assert!(!order_by[0].asc());
+        assert!(!order_by[0].nulls_first());
+        assert_eq!(order_by[1].name(), Some("email"));
+        assert_eq!(order_by[1].collation(), Some(Collation::C));
+        assert!(!order_by[1].nulls_first());
+        assert_eq!(order_by[2].name(), Some("e"));
+    }
+
     #[test]
     fn test_parse_with_cast() {
         let route = parse!(
@@ -1008,6 +1792,441 @@ mod test {
         assert!(route.is_write());
     }
 
+    #[test]
+    fn test_temp_table() {
+        let route = query!("CREATE TEMP TABLE test_temp (id BIGINT)");
+        assert_eq!(
+            route.temp_table(),
+            Some(&TempTable::Create("test_temp".into()))
+        );
+
+        let route = query!("DROP TABLE test_temp");
+        assert_eq!(
+            route.temp_table(),
+            Some(&TempTable::Drop(vec!["test_temp".into()]))
+        );
+
+        assert!(query!("SELECT * FROM sharded").temp_table().is_none());
+    }
+
+    #[test]
+    fn test_aggregate_rewrite() {
+        let route = query!("SELECT avg(id) FROM sharded");
+        assert_eq!(
+            route.rewrite(),
+            Some("SELECT avg(id), sum(id), count(id) FROM sharded")
+        );
+        assert_eq!(route.aggregate().hidden(), 2);
+
+        // Direct-to-shard queries are computed by Postgres.
+        let route = query!("SELECT avg(id) FROM sharded WHERE id = 1");
+        assert!(route.rewrite().is_none());
+        assert!(query!("SELECT count(*) FROM sharded").rewrite().is_none());
+
+        // Prepared statements are rewritten too.
+        let route = parse!("SELECT avg(id) FROM sharded", Vec::<Vec<u8>>::new());
+        assert_eq!(
+            route.rewrite(),
+            Some("SELECT avg(id), sum(id), count(id) FROM sharded")
+        );
+        assert_eq!(route.aggregate().hidden(), 2);
+    }
+
+    #[test]
+    fn test_limit_rewrite() {
+        let route = query!("SELECT * FROM sharded ORDER BY id LIMIT 10 OFFSET 20");
+        assert_eq!(
+            route.rewrite(),
+            Some("SELECT * FROM sharded ORDER BY id LIMIT 30")
+        );
+        assert_eq!(
+            route.limit(),
+            Some(Limit {
+                limit: Some(10),
+                offset: 20
+            })
+        );
+        assert!(route.should_buffer());
+
+        let route = query!("SELECT * FROM sharded LIMIT 5");
+        assert!(route.rewrite().is_none());
+        assert_eq!(route.limit().unwrap().limit, Some(5));
+
+        // Each shard only has part of every group.
+        let route = query!("SELECT email, count(*) FROM sharded GROUP BY 1 LIMIT 5");
+        assert_eq!(
+            route.rewrite(),
+            Some("SELECT email, count(*) FROM sharded GROUP BY 1")
+        );
+
+        // Prepared statements too.
+        let route = parse!(
+            "SELECT email, count(*) FROM sharded GROUP BY 1 LIMIT $1 OFFSET $2",
+            ["10".as_bytes(), "20".as_bytes()]
+        );
+        assert!(route.rewrite().is_none());
+        assert_eq!(route.parameters(), &[(0, i64::from(i32::MAX)), (1, 0)]);
+        assert_eq!(
+            route.limit(),
+            Some(Limit {
+                limit: Some(10),
+                offset: 20
+            })
+        );
+
+        let route = parse!(
+            "SELECT email, count(*) FROM sharded GROUP BY 1 LIMIT 5",
+            Vec::<Vec<u8>>::new()
+        );
+        assert_eq!(
+            route.rewrite(),
+            Some("SELECT email, count(*) FROM sharded GROUP BY 1")
+        );
+        assert!(route.parameters().is_empty());
+        assert_eq!(route.limit().unwrap().limit, Some(5));
+
+        let route = query!("SELECT * FROM sharded ORDER BY id FETCH FIRST 5 ROWS WITH TIES");
+        assert!(route.limit().is_none());
+
+        // Prepared statements get LIMIT + OFFSET rows from each shard
+        // by changing the parameters.
+        let route = parse!(
+            "SELECT * FROM sharded ORDER BY id LIMIT $1 OFFSET $2",
+            ["10".as_bytes(), "20".as_bytes()]
+        );
+        assert!(route.rewrite().is_none());
+        assert_eq!(
+            route.limit(),
+            Some(Limit {
+                limit: Some(10),
+                offset: 20
+            })
+        );
+        assert_eq!(route.parameters(), &[(1, 0), (0, 30)]);
+
+        // Constants can't be changed, so shards apply the OFFSET.
+        let route = parse!(
+            "SELECT * FROM sharded ORDER BY id LIMIT 10 OFFSET $1",
+            ["20".as_bytes()]
+        );
+        assert!(route.parameters().is_empty());
+        assert_eq!(route.limit().unwrap().offset, 0);
+
+        // Without ORDER BY, rows are counted as they're forwarded.
+        let route = query!("SELECT * FROM sharded LIMIT 10");
+        assert!(!route.should_buffer());
+    }
+
+    #[test]
+    fn test_comment_hints() {
+        let route = query!("/* pgdog_role: primary pgdog_timeout: 1000 */ SELECT * FROM sharded");
+        assert!(route.is_write());
+        assert_eq!(route.timeout(), Some(std::time::Duration::from_secs(1)));
+
+        let route = parse!(
+            "/* pgdog_sharding_key: 1 pgdog_role: primary */ SELECT * FROM sharded WHERE id = $1",
+            ["1".as_bytes()]
+        );
+        assert!(route.is_write());
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        let route = query!("/* pgdog_role: replica */ SELECT * FROM sharded");
+        assert!(route.is_read());
+        assert_eq!(route.timeout(), None);
+
+        let route = query!("/* pgdog_role: replica */ INSERT INTO sharded (id) VALUES (1)");
+        assert!(route.is_write());
+    }
+
+    #[test]
+    fn test_route_cache() {
+        // The cache is global, so only look at keys other tests don't use
+        // and don't clear it.
+        let cluster = Cluster::new_test();
+        let key = |query: &str| {
+            RouteKey::new(
+                cluster.name(),
+                cluster.user(),
+                query,
+                &Bind::test_params(
+                    "",
+                    &[Parameter {
+                        len: 2,
+                        data: "11".as_bytes().to_vec(),
+                    }],
+                ),
+            )
+            .unwrap()
+        };
+
+        let query = "SELECT * FROM sharded WHERE id = $1 AND 'route_cache' IS NOT NULL";
+        let route = parse!(query, ["11".as_bytes()]);
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+        assert!(!route.round_robin());
+
+        let cached = parse!(query, ["11".as_bytes()]);
+        assert_eq!(cached.shard(), route.shard());
+
+        // Round robin picks a shard every time, so it's not cached.
+        let query = "INSERT INTO sharded (value) VALUES ($1) /* route_cache */";
+        let route = parse!(query, ["11".as_bytes()]);
+        assert!(route.round_robin());
+        assert!(Cache::get().route(&key(query)).is_none());
+    }
+
+    #[test]
+    fn test_route_cache_transaction() {
+        let cluster = Cluster::new_test();
+        let query = BufferedQuery::Prepared(Parse::named(
+            "",
+            "SELECT avg(id) FROM sharded WHERE 'route_cache_transaction' IS NOT NULL",
+        ));
+        let bind = Bind::test_params("", &[]);
+        let route = |in_transaction| {
+            let mut qp = QueryParser {
+                in_transaction,
+                ..Default::default()
+            };
+            let command = qp
+                .query(
+                    &query,
+                    &cluster,
+                    Some(&bind),
+                    &mut PreparedStatements::default(),
+                    &Parameters::default(),
+                )
+                .unwrap();
+            match command {
+                Command::Query(route) => route,
+                _ => panic!("not a query"),
+            }
+        };
+
+        // Rewritten the same way in a transaction and outside of it.
+        assert_eq!(route(true).aggregate().hidden(), 2);
+        assert_eq!(route(false).aggregate().hidden(), 2);
+        assert_eq!(route(false).aggregate().hidden(), 2);
+        assert_eq!(route(true).aggregate().hidden(), 2);
+    }
+
+    #[test]
+    fn test_aggregate_transaction() {
+        let cluster = Cluster::new_test();
+        let mut qp = QueryParser::default();
+        let mut route = |query: &str| {
+            let buffer = Buffer::from(vec![Query::new(query).into()]);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params).unwrap();
+            qp.parse(context).unwrap().clone()
+        };
+
+        assert!(matches!(route("BEGIN"), Command::StartTransaction(_)));
+        let Command::Query(first) = route("SELECT avg(id) FROM sharded") else {
+            panic!("not a query");
+        };
+        assert_eq!(
+            first.rewrite(),
+            Some("SELECT avg(id), sum(id), count(id) FROM sharded")
+        );
+
+        // Later statements go to the same shards, but their rows
+        // are merged the way they need.
+        let Command::Query(second) = route("SELECT id FROM sharded ORDER BY id") else {
+            panic!("not a query");
+        };
+        assert_eq!(second.shard(), first.shard());
+        assert!(second.rewrite().is_none());
+        assert!(second.aggregate().is_empty());
+        assert_eq!(second.order_by().len(), 1);
+
+        let Command::Query(third) = route("SELECT stddev(value) FROM sharded") else {
+            panic!("not a query");
+        };
+        assert_eq!(third.aggregate().hidden(), 3);
+    }
+
+    #[test]
+    fn test_count_distinct_rewrite() {
+        let cluster = Cluster::new_test();
+        let route = |qp: &mut QueryParser, messages: Vec<ProtocolMessage>| {
+            let buffer = Buffer::from(messages);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params).unwrap();
+            qp.parse(context).cloned()
+        };
+        let query = "SELECT count(DISTINCT email) FROM sharded";
+        let rewritten = "SELECT count(DISTINCT email), array_agg(DISTINCT email) FROM sharded";
+
+        // In a transaction.
+        let mut qp = QueryParser::default();
+        route(&mut qp, vec![Query::new("BEGIN").into()]).unwrap();
+        let Ok(Command::Query(first)) = route(&mut qp, vec![Query::new(query).into()]) else {
+            panic!("not a query");
+        };
+        assert_eq!(first.rewrite(), Some(rewritten));
+        let Ok(Command::Query(second)) = route(&mut qp, vec![Query::new(query).into()]) else {
+            panic!("not a query");
+        };
+        assert_eq!(second.rewrite(), Some(rewritten));
+
+        // Pipelined with itself, or with another statement.
+        let pipeline = |queries: &[&str]| {
+            let mut messages: Vec<ProtocolMessage> = vec![];
+            for query in queries {
+                messages.push(Parse::new_anonymous(query).into());
+                messages.push(Bind::test_statement("").into());
+                messages.push(Execute::new().into());
+            }
+            route(&mut QueryParser::default(), messages)
+        };
+        let Ok(Command::Query(route)) = pipeline(&[query, query]) else {
+            panic!("not a query");
+        };
+        assert_eq!(route.rewrite(), Some(rewritten));
+        assert!(matches!(
+            pipeline(&[query, "SELECT 1"]),
+            Err(Error::CrossShard(Unsupported::PipelinedAggregate))
+        ));
+    }
+
+    #[test]
+    fn test_group_by_transaction() {
+        let cluster = Cluster::new_test();
+        let mut qp = QueryParser::default();
+        let mut route = |messages: Vec<ProtocolMessage>| {
+            let buffer = Buffer::from(messages);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params).unwrap();
+            qp.parse(context).cloned()
+        };
+
+        route(vec![Query::new("BEGIN").into()]).unwrap();
+        for query in [
+            "SELECT * FROM sharded",
+            "SELECT email, count(*) FROM sharded GROUP BY email HAVING count(*) > 1 LIMIT 10 OFFSET 5",
+        ] {
+            let Ok(Command::Query(route)) = route(vec![Query::new(query).into()]) else {
+                panic!("not a query");
+            };
+            if route.aggregate().is_empty() {
+                continue;
+            }
+            // HAVING, LIMIT and OFFSET are applied after merging groups.
+            assert_eq!(
+                route.rewrite(),
+                Some("SELECT email, count(*) FROM sharded GROUP BY email")
+            );
+            assert!(route.aggregate().having().is_some());
+            assert_eq!(
+                route.limit(),
+                Some(Limit {
+                    limit: Some(10),
+                    offset: 5
+                })
+            );
+        }
+
+        // Pipelined with other statements, groups can't be merged.
+        let mut messages: Vec<ProtocolMessage> = vec![];
+        for query in [
+            "SELECT email, count(*) FROM sharded GROUP BY email LIMIT 10",
+            "SELECT 1",
+        ] {
+            messages.push(Parse::new_anonymous(query).into());
+            messages.push(Bind::test_statement("").into());
+            messages.push(Execute::new().into());
+        }
+        assert!(matches!(
+            QueryParser::default().parse(
+                RouterContext::new(
+                    &Buffer::from(messages),
+                    &cluster,
+                    &mut PreparedStatements::default(),
+                    &Parameters::default(),
+                )
+                .unwrap()
+            ),
+            Err(Error::CrossShard(Unsupported::PipelinedAggregate))
+        ));
+    }
+
+    #[test]
+    fn test_aggregate_not_rewritten() {
+        let cluster = Cluster::new_test();
+        let route = |messages: Vec<ProtocolMessage>| {
+            let buffer = Buffer::from(messages);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params).unwrap();
+            QueryParser::default().parse(context).cloned()
+        };
+        let pipeline = |queries: &[&str]| {
+            let mut messages: Vec<ProtocolMessage> = vec![];
+            for query in queries {
+                messages.push(Parse::new_anonymous(query).into());
+                messages.push(Bind::test_statement("").into());
+                messages.push(Execute::new().into());
+            }
+            route(messages)
+        };
+
+        assert!(matches!(
+            route(vec![
+                Query::new("SELECT avg(DISTINCT id) FROM sharded").into()
+            ]),
+            Err(Error::CrossShard(Unsupported::DistinctAggregate))
+        ));
+        assert!(matches!(
+            route(vec![
+                Query::new("SELECT avg(id) FROM sharded; SELECT 1").into()
+            ]),
+            Err(Error::CrossShard(Unsupported::PipelinedAggregate))
+        ));
+        assert!(matches!(
+            pipeline(&["SELECT avg(id) FROM sharded", "SELECT * FROM sharded"]),
+            Err(Error::CrossShard(Unsupported::PipelinedAggregate))
+        ));
+        assert!(matches!(
+            pipeline(&["SELECT * FROM sharded", "SELECT avg(id) FROM sharded"]),
+            Err(Error::CrossShard(Unsupported::PipelinedAggregate))
+        ));
+
+        // The same statement pipelined is rewritten for all of them.
+        let Ok(Command::Query(route)) =
+            pipeline(&["SELECT avg(id) FROM sharded", "SELECT avg(id) FROM sharded"])
+        else {
+            panic!("not a query");
+        };
+        assert_eq!(route.aggregate().hidden(), 2);
+    }
+
+    #[test]
+    fn test_select_join_sharding_key() {
+        let route = parse!(
+            "SELECT * FROM sharded s JOIN orders o ON s.id = o.sharded_id WHERE o.sharded_id = $1",
+            ["1".as_bytes()]
+        );
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        let route = parse!(
+            "SELECT * FROM orders o, sharded WHERE sharded.id = o.sharded_id AND o.sharded_id = $1",
+            ["1".as_bytes()]
+        );
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        let route = parse!("SELECT * FROM sharded s WHERE s.id = $1", ["1".as_bytes()]);
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        let route = parse!(
+            "SELECT * FROM sharded s JOIN orders o ON s.id = o.sharded_id WHERE o.total = $1",
+            ["1".as_bytes()]
+        );
+        assert!(matches!(route.shard(), Shard::All));
+    }
+
     #[test]
     fn test_omni() {
         let q = "SELECT sharded_omni.* FROM sharded_omni WHERE sharded_omni.id = $1";
@@ -1018,6 +2237,124 @@ mod test {
         assert!(!qp.in_transaction);
     }
 
+    #[test]
+    fn test_pipeline_cross_shard() {
+        let pipeline = |ids: &[usize], query: Option<&str>| {
+            let mut messages: Vec<ProtocolMessage> =
+                vec![Parse::named("pipeline", "SELECT * FROM sharded WHERE id = $1").into()];
+            for id in ids {
+                let id = id.to_string();
+                let param = Parameter {
+                    len: id.len() as i32,
+                    data: id.as_bytes().to_vec(),
+                };
+                messages.push(Bind::test_params("pipeline", &[param]).into());
+                messages.push(Execute::new().into());
+            }
+            if let Some(query) = query {
+                messages.push(Parse::new_anonymous(query).into());
+                messages.push(Bind::test_statement("").into());
+                messages.push(Execute::new().into());
+            }
+            QueryParser::default()
+                .parse(
+                    RouterContext::new(
+                        &Buffer::from(messages),
+                        &Cluster::new_test(),
+                        &mut PreparedStatements::default(),
+                        &Parameters::default(),
+                    )
+                    .unwrap(),
+                )
+                .map(|command| match command {
+                    Command::Query(route) => route.shard().clone(),
+                    command => panic!("unexpected command: {:?}", command),
+                })
+        };
+
+        let shard = |id| pipeline(&[id], None).unwrap();
+        let first = shard(1);
+        let same = (2..100).find(|id| shard(*id) == first).unwrap();
+        let other = (2..100).find(|id| shard(*id) != first).unwrap();
+
+        assert_eq!(pipeline(&[1, same], None).unwrap(), first);
+        assert!(matches!(
+            pipeline(&[1, other], None),
+            Err(Error::PipelineCrossShard)
+        ));
+
+        // Statements that can run anywhere follow the others.
+        assert_eq!(pipeline(&[1], Some("SELECT 1")).unwrap(), first);
+        assert!(matches!(
+            pipeline(&[1], Some("SELECT * FROM sharded")),
+            Err(Error::PipelineCrossShard)
+        ));
+    }
+
+    #[test]
+    fn test_omni_writes() {
+        for q in [
+            "INSERT INTO sharded_omni (id, value) VALUES (1, 'test')",
+            "UPDATE sharded_omni SET value = 'test' WHERE id = 1",
+            "DELETE FROM sharded_omni WHERE id = 1",
+        ] {
+            let route = query!(q);
+            assert!(route.is_write());
+            assert_eq!(route.shard(), &Shard::All);
+            assert!(route.replicated());
+        }
+
+        // Writes to sharded tables aren't wrapped in a transaction.
+        let route = query!("UPDATE sharded SET value = 'test'");
+        assert_eq!(route.shard(), &Shard::All);
+        assert!(!route.replicated());
+
+        // Joins with sharded tables are routed using the sharded table.
+        let route = query!(
+            "SELECT * FROM sharded JOIN sharded_omni ON sharded_omni.id = sharded.value WHERE sharded.id = 1"
+        );
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        let route = query!("SELECT * FROM sharded_omni JOIN pg_type ON true");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+    }
+
+    #[test]
+    fn test_insert_without_key() {
+        // Key generation isn't enabled for the test table.
+        let route = query!("INSERT INTO sharded (value) VALUES ('test')");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+        assert!(route.rewrite().is_none());
+    }
+
+    #[test]
+    fn test_cross_shard_permissive() {
+        // Sent to all shards by default.
+        let route = query!("SELECT id, row_number() OVER (ORDER BY value) FROM sharded");
+        assert_eq!(route.shard(), &Shard::All);
+
+        let route = query!("SELECT * FROM sharded WHERE id = 1 ORDER BY random()");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+    }
+
+    #[test]
+    fn test_catalog_queries() {
+        let route = query!("SELECT oid, typname FROM pg_type JOIN pg_catalog.pg_namespace ON pg_namespace.oid = typnamespace");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        let route = query!("SELECT * FROM information_schema.columns");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+
+        let route = query!("SELECT * FROM sharded JOIN pg_type ON true");
+        assert!(matches!(route.shard(), Shard::All));
+
+        // User tables can start with pg_ too.
+        let route = query!("SELECT * FROM pg_sharded");
+        assert!(matches!(route.shard(), Shard::All));
+        let route = query!("SELECT * FROM pg_catalog.pg_stat_activity");
+        assert!(matches!(route.shard(), Shard::Direct(_)));
+    }
+
     #[test]
     fn test_set() {
         let route = query!(r#"SET "pgdog.shard" TO 1"#);
@@ -1207,6 +2544,63 @@ mod test {
         assert!(!qp.in_transaction);
     }
 
+    #[test]
+    fn test_intercept() {
+        let (cmd, qp) = command!("DISCARD ALL");
+        assert!(matches!(cmd, Command::Intercept(Intercept::DiscardAll)));
+        assert!(!qp.routed);
+
+        let (cmd, _) = command!("SET extra_float_digits = 3");
+        assert!(matches!(
+            cmd,
+            Command::Intercept(Intercept::ExtraFloatDigits(3))
+        ));
+
+        // Postgres doesn't allow DISCARD ALL in a transaction block.
+        let buffer = Buffer::from(vec![Query::new("DISCARD ALL").into()]);
+        let cluster = Cluster::new_test();
+        let mut stmt = PreparedStatements::default();
+        let params = Parameters::default();
+        let mut qp = QueryParser {
+            in_transaction: true,
+            ..Default::default()
+        };
+        let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params).unwrap();
+        let cmd = qp.parse(context).unwrap().clone();
+        assert!(matches!(cmd, Command::Query(_)));
+    }
+
+    #[test]
+    fn test_metadata() {
+        // Disabled by default.
+        let (cmd, _) = command!("SELECT * FROM pgdog.shards");
+        assert!(matches!(cmd, Command::Query(_)));
+
+        let old = config();
+        let mut config = (*old).clone();
+        config.config.general.metadata_schema = true;
+        crate::config::set(config).unwrap();
+
+        let (cmd, qp) = command!("SELECT * FROM pgdog.shards");
+        assert!(matches!(cmd, Command::Metadata(Metadata::Shards)));
+        assert!(!qp.routed);
+
+        crate::config::set((*old).clone()).unwrap();
+    }
+
+    #[test]
+    fn test_explain_pgdog() {
+        let (cmd, qp) = command!("EXPLAIN (PGDOG) SELECT * FROM sharded ORDER BY id LIMIT 5");
+        let Command::Explain(explain) = cmd else {
+            panic!("not an explain");
+        };
+        assert_eq!(explain.rows()[1], ("shard", "all".into()));
+        assert_eq!(explain.rows()[4], ("order_by", "id ASC".into()));
+        assert_eq!(explain.rows()[10], ("buffered", "true".into()));
+        // Explaining doesn't route the transaction.
+        assert!(!qp.routed);
+    }
+
     #[test]
     fn test_write_functions() {
         let route = query!("SELECT pg_advisory_lock($1)");
@@ -1220,4 +2614,170 @@ mod test {
         assert!(route.is_write());
         assert!(!route.lock_session());
     }
+
+    #[test]
+    fn test_write_functions_from() {
+        let route = query!("SELECT * FROM nextval('seq')");
+        assert!(route.is_write());
+
+        let route = query!("SELECT * FROM generate_series(1, 10)");
+        assert!(route.is_read());
+    }
+
+    #[test]
+    fn test_write_functions_any_target() {
+        let route = query!("SELECT NOW(), pg_advisory_lock($1)");
+        assert!(route.is_write());
+        assert!(route.lock_session());
+    }
+
+    #[test]
+    fn test_select_for_share() {
+        for query in [
+            "SELECT * FROM sharded WHERE id = $1 FOR SHARE",
+            "SELECT * FROM sharded WHERE id = $1 FOR NO KEY UPDATE",
+            "SELECT * FROM sharded WHERE id = $1 FOR KEY SHARE SKIP LOCKED",
+        ] {
+            let route = query!(query);
+            assert!(route.is_write(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_select_for_update_cte() {
+        let route = query!(
+            "WITH locked AS (SELECT * FROM sharded WHERE id = $1 FOR UPDATE) SELECT * FROM locked"
+        );
+        assert!(route.is_write());
+
+        let route = query!(
+            "WITH a AS (SELECT 1), b AS (SELECT id FROM sharded FOR SHARE) SELECT * FROM a, b"
+        );
+        assert!(route.is_write());
+
+        let route =
+            query!("WITH t AS (DELETE FROM sharded WHERE id = $1 RETURNING *) SELECT * FROM t");
+        assert!(route.is_write());
+
+        let route = query!("WITH t AS (SELECT pg_advisory_lock($1)) SELECT * FROM t");
+        assert!(route.is_write());
+        assert!(route.lock_session());
+
+        let route = query!("WITH t AS (SELECT * FROM sharded WHERE id = $1) SELECT * FROM t");
+        assert!(route.is_read());
+    }
+
+    #[test]
+    fn test_select_for_update_subquery() {
+        let route = query!("SELECT * FROM (SELECT * FROM sharded FOR UPDATE) s");
+        assert!(route.is_write());
+
+        let route = query!("(SELECT id FROM sharded FOR UPDATE) UNION ALL SELECT id FROM sharded");
+        assert!(route.is_write());
+
+        let route = query!("SELECT * FROM (SELECT * FROM sharded) s");
+        assert!(route.is_read());
+    }
+
+    #[test]
+    fn test_pg_restore() {
+        let dump = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/pg_dump/sharded.sql"
+        ));
+        let cluster = Cluster::new_test();
+        let mut query_parser = QueryParser::default();
+        query_parser.restore();
+
+        // Split the dump like psql does: statements end with a semicolon
+        // and COPY data with the end-of-data marker.
+        let mut statements = vec![];
+        let mut statement = std::string::String::new();
+        let mut lines = dump.lines();
+        while let Some(line) = lines.next() {
+            if statement.is_empty() && (line.is_empty() || line.starts_with("--")) {
+                continue;
+            }
+            statement.push_str(line);
+            statement.push('\n');
+            if line.ends_with(';') {
+                let mut data = vec![];
+                if statement.starts_with("COPY") {
+                    for line in lines.by_ref() {
+                        data.push(CopyData::new(format!("{}\n", line).as_bytes()));
+                        if line == "\\." {
+                            break;
+                        }
+                    }
+                }
+                statements.push((std::mem::take(&mut statement), data));
+            }
+        }
+        assert_eq!(statements.len(), 25);
+
+        let mut rows = [0; 2];
+        let mut omni = 0;
+        for (statement, data) in statements {
+            let buffer = Buffer::from(vec![Query::new(&statement).into()]);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params).unwrap();
+
+            match query_parser.parse(context).unwrap().clone() {
+                Command::Copy(_) => {
+                    for row in query_parser.copy_data(data).unwrap() {
+                        assert_ne!(row.message().data(), b"\\.\n");
+                        match row.shard() {
+                            Shard::Direct(shard) => rows[*shard] += 1,
+                            _ => omni += 1,
+                        }
+                    }
+                }
+                Command::Query(route) => {
+                    assert!(data.is_empty());
+                    assert!(route.is_write(), "{}", statement);
+                    assert_eq!(route.shard(), &Shard::All, "{}", statement);
+                }
+                command => panic!("unexpected {:?}: {}", command, statement),
+            }
+        }
+
+        assert_eq!(rows.iter().sum::<usize>(), 10);
+        assert!(rows.iter().all(|rows| *rows > 0));
+        assert_eq!(omni, 2);
+    }
+
+    #[test]
+    fn test_pg_restore_insert() {
+        let cluster = Cluster::new_test();
+        let mut query_parser = QueryParser::default();
+        query_parser.restore();
+
+        let mut route = |query: &str| {
+            let buffer = Buffer::from(vec![Query::new(query).into()]);
+            let mut stmt = PreparedStatements::default();
+            let params = Parameters::default();
+            let context = RouterContext::new(&buffer, &cluster, &mut stmt, &params).unwrap();
+            query_parser.parse(context).cloned()
+        };
+
+        match route("INSERT INTO sharded (id, value) VALUES (1, 'test')").unwrap() {
+            Command::Query(route) => assert!(matches!(route.shard(), Shard::Direct(_))),
+            command => panic!("unexpected {:?}", command),
+        }
+
+        assert!(matches!(
+            route("INSERT INTO sharded (id, value) VALUES (1, 'test'), (2, 'test')"),
+            Err(Error::RestoreMultiRow(_))
+        ));
+        assert!(matches!(
+            route("INSERT INTO sharded (value) VALUES ('test')"),
+            Err(Error::RestoreNoKey(_))
+        ));
+
+        match route("INSERT INTO not_sharded (id) VALUES (1), (2)").unwrap() {
+            Command::Query(route) => assert_eq!(route.shard(), &Shard::All),
+            command => panic!("unexpected {:?}", command),
+        }
+    }
 }
@@ -1,6 +1,10 @@
 use std::fmt::Display;
+use std::time::Duration;
 
-use super::{Aggregate, FunctionBehavior, LockingBehavior, OrderBy};
+use pg_query::protobuf::{a_const::Val, AConst, Integer, Node, SelectStmt};
+use pg_query::NodeEnum;
+
+use super::{Aggregate, Distinct, Error, FunctionBehavior, LockingBehavior, OrderBy, TempTable};
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Ord, Eq, Hash, Default)]
 pub enum Shard {
@@ -44,12 +48,27 @@ impl From<Option<usize>> for Shard {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// LIMIT and OFFSET applied after merging rows from all shards.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
 pub struct Limit {
-    pub limit: usize,
+    /// `None` for `LIMIT ALL` or no LIMIT.
+    pub limit: Option<usize>,
     pub offset: usize,
 }
 
+impl Limit {
+    /// The row at this position, counting from 0, is returned.
+    pub fn includes(&self, row: usize) -> bool {
+        row >= self.offset && self.limit.is_none_or(|limit| row - self.offset < limit)
+    }
+
+    /// Rows returned out of this many.
+    pub fn sent(&self, rows: usize) -> usize {
+        let rows = rows.saturating_sub(self.offset);
+        self.limit.map_or(rows, |limit| rows.min(limit))
+    }
+}
+
 /// Path a query should take and any transformations
 /// that should be applied along the way.
 #[derive(Debug, Clone)]
@@ -58,8 +77,17 @@ pub struct Route {
     read: bool,
     order_by: Vec<OrderBy>,
     aggregate: Aggregate,
+    distinct: Option<Distinct>,
     limit: Option<Limit>,
     lock_session: bool,
+    timeout: Option<Duration>,
+    sync_replica: bool,
+    temp_table: Option<TempTable>,
+    rewrite: Option<String>,
+    parameters: Vec<(usize, i64)>,
+    copy_headers: bool,
+    round_robin: bool,
+    replicated: bool,
 }
 
 impl Display for Route {
@@ -80,8 +108,17 @@ impl Default for Route {
             order_by: vec![],
             read: false,
             aggregate: Aggregate::default(),
+            distinct: None,
             limit: None,
             lock_session: false,
+            timeout: None,
+            sync_replica: false,
+            temp_table: None,
+            rewrite: None,
+            parameters: vec![],
+            copy_headers: false,
+            round_robin: false,
+            replicated: false,
         }
     }
 }
@@ -147,6 +184,7 @@ impl Route {
 
     pub fn set_shard_mut(&mut self, shard: usize) {
         self.shard = Shard::Direct(shard);
+        self.round_robin = false;
     }
 
     pub fn set_shard(mut self, shard: usize) -> Self {
@@ -154,14 +192,82 @@ impl Route {
         self
     }
 
+    /// The query can go to any shard, this one was picked by round robin.
+    pub fn set_round_robin_mut(&mut self, shard: usize) {
+        self.set_shard_mut(shard);
+        self.round_robin = true;
+    }
+
+    pub fn set_round_robin(mut self, shard: usize) -> Self {
+        self.set_round_robin_mut(shard);
+        self
+    }
+
+    pub fn round_robin(&self) -> bool {
+        self.round_robin
+    }
+
+    /// Rows from all shards are merged before they are sent to the client.
+    /// LIMIT and OFFSET alone are applied while rows are forwarded.
     pub fn should_buffer(&self) -> bool {
-        !self.order_by().is_empty() || !self.aggregate().is_empty()
+        !self.order_by().is_empty() || !self.aggregate().is_empty() || self.distinct.is_some()
+    }
+
+    /// Bind parameters replaced before the query is sent, e.g. so each shard
+    /// returns the first LIMIT + OFFSET rows of a prepared statement.
+    pub fn parameters(&self) -> &[(usize, i64)] {
+        &self.parameters
+    }
+
+    pub fn set_parameters_mut(&mut self, parameters: Vec<(usize, i64)>) {
+        self.parameters = parameters;
+    }
+
+    /// Merge rows from all shards the same way as `route`, e.g. for another
+    /// statement in a transaction that's already routed.
+    pub fn set_merge_mut(&mut self, route: Route) {
+        self.order_by = route.order_by;
+        self.aggregate = route.aggregate;
+        self.distinct = route.distinct;
+        self.limit = route.limit;
+        self.parameters = route.parameters;
+        self.rewrite = route.rewrite;
+    }
+
+    /// Duplicate rows to remove after merging results from all shards.
+    pub fn distinct(&self) -> Option<&Distinct> {
+        self.distinct.as_ref()
+    }
+
+    /// Each shard sends a header with `COPY ... TO STDOUT`,
+    /// only the first one is forwarded to the client.
+    pub fn copy_headers(&self) -> bool {
+        self.copy_headers
+    }
+
+    pub fn set_copy_headers(mut self, copy_headers: bool) -> Self {
+        self.copy_headers = copy_headers;
+        self
+    }
+
+    pub fn set_distinct(mut self, distinct: Option<Distinct>) -> Self {
+        self.distinct = distinct;
+        self
     }
 
     pub fn limit(&self) -> Option<Limit> {
         self.limit
     }
 
+    pub fn set_limit(mut self, limit: Option<Limit>) -> Self {
+        self.set_limit_mut(limit);
+        self
+    }
+
+    pub fn set_limit_mut(&mut self, limit: Option<Limit>) {
+        self.limit = limit;
+    }
+
     pub fn set_read(mut self, read: bool) -> Self {
         self.set_read_mut(read);
         self
@@ -185,6 +291,16 @@ impl Route {
         self.lock_session = matches!(locking_behavior, LockingBehavior::Lock);
     }
 
+    /// Write to a replicated table, sent to all shards in one transaction.
+    pub fn replicated(&self) -> bool {
+        self.replicated
+    }
+
+    pub fn set_replicated(mut self) -> Self {
+        self.replicated = true;
+        self
+    }
+
     pub fn set_lock_session(mut self) -> Self {
         self.lock_session = true;
         self
@@ -193,4 +309,135 @@ impl Route {
     pub fn lock_session(&self) -> bool {
         self.lock_session
     }
+
+    /// Statement timeout requested by the client.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn set_timeout_mut(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Read from sync replicas, if there are any.
+    pub fn sync_replica(&self) -> bool {
+        self.sync_replica
+    }
+
+    pub fn set_sync_replica_mut(&mut self, sync_replica: bool) {
+        self.sync_replica = sync_replica;
+    }
+
+    /// Temporary tables created or dropped by the query.
+    pub fn temp_table(&self) -> Option<&TempTable> {
+        self.temp_table.as_ref()
+    }
+
+    pub fn set_temp_table_mut(&mut self, temp_table: Option<TempTable>) {
+        self.temp_table = temp_table;
+    }
+
+    /// Query sent to the shards instead of the one sent by the client.
+    pub fn rewrite(&self) -> Option<&str> {
+        self.rewrite.as_deref()
+    }
+
+    pub fn set_rewrite_mut(&mut self, rewrite: Option<String>) {
+        self.rewrite = rewrite;
+    }
+
+    /// Rewrite the query sent to the shards, so the results can be merged:
+    ///
+    /// 1. fetch the extra columns needed to compute aggregates,
+    /// 2. fetch the first `LIMIT + OFFSET` rows from each shard and
+    ///    apply the OFFSET after merging them.
+    ///
+    pub fn rewrite_select(&mut self, stmt: &SelectStmt) -> Result<(), Error> {
+        let mut stmt = stmt.clone();
+        let mut changed = self
+            .aggregate
+            .rewrite(&mut stmt)
+            .map_err(Error::CrossShard)?;
+
+        if let Some(limit) = self.limit {
+            if !self.aggregate.is_empty() {
+                // Each shard only has part of every group.
+                changed |= stmt.limit_count.take().is_some();
+                changed |= stmt.limit_offset.take().is_some();
+            } else if limit.offset > 0 {
+                stmt.limit_offset = None;
+                // Without a LIMIT if it doesn't fit.
+                stmt.limit_count = limit
+                    .limit
+                    .and_then(|count| i32::try_from(count + limit.offset).ok())
+                    .map(|count| Box::new(Self::integer(count)));
+                changed = true;
+            }
+        }
+
+        self.rewrite = if changed {
+            Some(
+                NodeEnum::SelectStmt(Box::new(stmt))
+                    .deparse()
+                    .map_err(Error::PgQuery)?,
+            )
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+
+    /// Fetch the extra columns needed to compute aggregates from a prepared statement.
+    ///
+    /// Each shard only has part of every group, so it returns all of them
+    /// and LIMIT and OFFSET are applied after merging. Constants are removed,
+    /// while parameters are changed to return all rows.
+    pub fn rewrite_aggregate(&mut self, stmt: &SelectStmt) -> Result<(), Error> {
+        let mut stmt = stmt.clone();
+        let mut changed = self
+            .aggregate
+            .rewrite(&mut stmt)
+            .map_err(Error::CrossShard)?;
+
+        if self.limit.is_some() && !self.aggregate.is_empty() {
+            for (node, all) in [
+                (&mut stmt.limit_count, i64::from(i32::MAX)),
+                (&mut stmt.limit_offset, 0),
+            ] {
+                match node.as_deref().and_then(|node| node.node.as_ref()) {
+                    Some(NodeEnum::ParamRef(param)) if param.number > 0 => {
+                        self.parameters.push((param.number as usize - 1, all));
+                    }
+                    Some(NodeEnum::AConst(_)) => {
+                        *node = None;
+                        changed = true;
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        self.rewrite = if changed {
+            Some(
+                NodeEnum::SelectStmt(Box::new(stmt))
+                    .deparse()
+                    .map_err(Error::PgQuery)?,
+            )
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+
+    fn integer(ival: i32) -> Node {
+        Node {
+            node: Some(NodeEnum::AConst(AConst {
+                val: Some(Val::Ival(Integer { ival })),
+                location: -1,
+                ..Default::default()
+            })),
+        }
+    }
 }
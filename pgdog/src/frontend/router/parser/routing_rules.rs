@@ -0,0 +1,155 @@
+//! Routing rules from `[[routing_rules]]`, applied on top
+//! of the query parser's decision.
+
+use pg_query::fingerprint;
+
+use crate::config::{RoutingRole, RoutingRule};
+
+use super::{Error, Route};
+
+/// Configured routing rules.
+#[derive(Debug)]
+pub struct RoutingRules<'a> {
+    rules: &'a [RoutingRule],
+}
+
+impl<'a> RoutingRules<'a> {
+    /// Create from config.
+    pub fn new(rules: &'a [RoutingRule]) -> Self {
+        Self { rules }
+    }
+
+    /// Find the first rule matching the client and the query.
+    pub fn find(
+        &self,
+        user: &str,
+        database: &str,
+        application_name: &str,
+        query: &str,
+    ) -> Result<Option<&'a RoutingRule>, Error> {
+        // Only fingerprint the query if some rules need it.
+        let mut hex = None;
+
+        for rule in self.rules {
+            let matches = |expected: &Option<String>, actual: &str| {
+                expected.as_deref().unwrap_or(actual) == actual
+            };
+
+            if !matches(&rule.user, user)
+                || !matches(&rule.database, database)
+                || !matches(&rule.application_name, application_name)
+            {
+                continue;
+            }
+
+            if !rule.fingerprints.is_empty() {
+                if hex.is_none() {
+                    hex = Some(fingerprint(query).map_err(Error::PgQuery)?.hex);
+                }
+
+                if !hex
+                    .as_ref()
+                    .is_some_and(|hex| rule.fingerprints.contains(hex))
+                {
+                    continue;
+                }
+            }
+
+            return Ok(Some(rule));
+        }
+
+        Ok(None)
+    }
+
+    /// Send the query to the role and shard, if they are set.
+    ///
+    /// Writes can't run on a replica, so they stay on the primary
+    /// regardless of the role.
+    pub fn apply(role: RoutingRole, shard: Option<usize>, route: &mut Route, shards: usize) {
+        match role {
+            RoutingRole::Auto | RoutingRole::Replica => (),
+            RoutingRole::Primary => route.set_read_mut(false),
+        }
+
+        if let Some(shard) = shard {
+            if shard < shards {
+                route.set_shard_mut(shard);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frontend::router::parser::Shard;
+
+    #[test]
+    fn test_routing_rules() {
+        let hex = fingerprint("SELECT * FROM reports").unwrap().hex;
+        let rules = vec![
+            RoutingRule {
+                application_name: Some("reports".into()),
+                fingerprints: vec![hex],
+                shard: Some(1),
+                ..Default::default()
+            },
+            RoutingRule {
+                user: Some("analytics".into()),
+                role: RoutingRole::Replica,
+                ..Default::default()
+            },
+            RoutingRule {
+                database: Some("billing".into()),
+                role: RoutingRole::Primary,
+                ..Default::default()
+            },
+        ];
+        let rules = RoutingRules::new(&rules);
+
+        let rule = rules
+            .find("pgdog", "pgdog", "reports", "SELECT * FROM reports")
+            .unwrap()
+            .unwrap();
+        let mut route = Route::read(None);
+        RoutingRules::apply(rule.role, rule.shard, &mut route, 2);
+        assert_eq!(route.shard(), &Shard::Direct(1));
+        assert!(route.is_read());
+
+        assert!(rules
+            .find("pgdog", "pgdog", "reports", "SELECT 1")
+            .unwrap()
+            .is_none());
+
+        let rule = rules
+            .find("analytics", "billing", "psql", "SELECT 1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(rule.role, RoutingRole::Replica);
+        let mut route = Route::read(Some(0));
+        RoutingRules::apply(rule.role, rule.shard, &mut route, 1);
+        assert!(route.is_read());
+
+        let rule = rules
+            .find(
+                "analytics",
+                "billing",
+                "psql",
+                "INSERT INTO users VALUES (1)",
+            )
+            .unwrap()
+            .unwrap();
+        let mut route = Route::write(Some(0));
+        RoutingRules::apply(rule.role, rule.shard, &mut route, 1);
+        assert!(route.is_write());
+
+        let rule = rules
+            .find("pgdog", "billing", "psql", "SELECT 1")
+            .unwrap()
+            .unwrap();
+        let mut route = Route::read(Some(0));
+        RoutingRules::apply(rule.role, rule.shard, &mut route, 1);
+        assert!(route.is_write());
+        assert_eq!(route.shard(), &Shard::Direct(0));
+    }
+}
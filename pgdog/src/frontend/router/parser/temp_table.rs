@@ -0,0 +1,123 @@
+//! Temporary tables.
+//!
+//! Temporary tables only exist on the server connection that created them,
+//! so the client has to keep that connection until it drops them.
+
+use pg_query::{
+    protobuf::{DiscardMode, ObjectType, OnCommitAction, RangeVar},
+    NodeEnum,
+};
+
+/// Temporary tables created or dropped by a statement.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TempTable {
+    /// `CREATE TEMP TABLE`.
+    Create(String),
+    /// `DROP TABLE`. Only the client knows which of these are temporary.
+    Drop(Vec<String>),
+    /// `DISCARD TEMP` or `DISCARD ALL`.
+    Discard,
+}
+
+impl TempTable {
+    /// Get the temporary tables the statement creates or drops, if any.
+    pub fn new(node: &NodeEnum) -> Option<Self> {
+        match node {
+            NodeEnum::CreateStmt(stmt) => {
+                // Dropped at the end of the transaction.
+                if stmt.oncommit() == OnCommitAction::OncommitDrop {
+                    return None;
+                }
+
+                Self::create(stmt.relation.as_ref()?)
+            }
+
+            NodeEnum::CreateTableAsStmt(stmt) => {
+                let into = stmt.into.as_ref()?;
+                if into.on_commit() == OnCommitAction::OncommitDrop {
+                    return None;
+                }
+
+                Self::create(into.rel.as_ref()?)
+            }
+
+            NodeEnum::DropStmt(stmt) if stmt.remove_type() == ObjectType::ObjectTable => {
+                let tables = stmt
+                    .objects
+                    .iter()
+                    .filter_map(|object| match object.node {
+                        // Temporary tables can be schema-qualified with pg_temp.
+                        Some(NodeEnum::List(ref list)) => {
+                            list.items.last().and_then(|item| match item.node {
+                                Some(NodeEnum::String(ref name)) => Some(name.sval.clone()),
+                                _ => None,
+                            })
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+
+                if tables.is_empty() {
+                    None
+                } else {
+                    Some(Self::Drop(tables))
+                }
+            }
+
+            NodeEnum::DiscardStmt(stmt)
+                if matches!(
+                    stmt.target(),
+                    DiscardMode::DiscardTemp | DiscardMode::DiscardAll
+                ) =>
+            {
+                Some(Self::Discard)
+            }
+
+            _ => None,
+        }
+    }
+
+    fn create(relation: &RangeVar) -> Option<Self> {
+        if relation.relpersistence == "t" {
+            Some(Self::Create(relation.relname.clone()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pg_query::parse;
+
+    use super::*;
+
+    fn temp_table(query: &str) -> Option<TempTable> {
+        let ast = parse(query).unwrap();
+        let node = ast.protobuf.stmts[0].stmt.as_ref().unwrap().node.as_ref();
+        TempTable::new(node.unwrap())
+    }
+
+    #[test]
+    fn test_temp_table() {
+        assert_eq!(
+            temp_table("CREATE TEMP TABLE test (id BIGINT)"),
+            Some(TempTable::Create("test".into()))
+        );
+        assert_eq!(
+            temp_table("CREATE TEMPORARY TABLE test AS SELECT 1"),
+            Some(TempTable::Create("test".into()))
+        );
+        assert_eq!(
+            temp_table("DROP TABLE pg_temp.test, other"),
+            Some(TempTable::Drop(vec!["test".into(), "other".into()]))
+        );
+        assert_eq!(temp_table("DISCARD TEMP"), Some(TempTable::Discard));
+        assert_eq!(temp_table("CREATE TABLE test (id BIGINT)"), None);
+        assert_eq!(
+            temp_table("CREATE TEMP TABLE test (id BIGINT) ON COMMIT DROP"),
+            None
+        );
+        assert_eq!(temp_table("DROP INDEX test"), None);
+    }
+}
@@ -0,0 +1,108 @@
+//! Consistent hashing with virtual nodes.
+//!
+//! Each shard owns many points on a hash ring and a key goes to the shard
+//! owning the first point at or after the key's hash. Adding a shard
+//! only moves the keys that land on its points, about 1/N of them.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::varchar;
+
+/// Virtual nodes per shard, if not configured.
+pub const VIRTUAL_NODES: usize = 256;
+
+/// Rings by number of shards and virtual nodes.
+type Rings = HashMap<(usize, usize), Arc<Ring>>;
+
+/// Rings are the same for the same number of shards and virtual nodes.
+static RINGS: Lazy<Mutex<Rings>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Hash ring.
+#[derive(Debug)]
+pub struct Ring {
+    points: Vec<(u64, usize)>,
+}
+
+impl Ring {
+    /// Get the ring for this many shards and virtual nodes per shard.
+    pub fn get(shards: usize, virtual_nodes: usize) -> Arc<Ring> {
+        let virtual_nodes = if virtual_nodes == 0 {
+            VIRTUAL_NODES
+        } else {
+            virtual_nodes
+        };
+
+        RINGS
+            .lock()
+            .entry((shards, virtual_nodes))
+            .or_insert_with(|| Arc::new(Self::new(shards, virtual_nodes)))
+            .clone()
+    }
+
+    fn new(shards: usize, virtual_nodes: usize) -> Self {
+        let mut points = (0..shards)
+            .flat_map(|shard| {
+                // Postgres folds the high half of a bigint into the low half
+                // before hashing it, so points are hashed as text instead.
+                (0..virtual_nodes).map(move |node| {
+                    let point = format!("{}-{}", shard, node);
+                    (varchar(point.as_bytes()), shard)
+                })
+            })
+            .collect::<Vec<_>>();
+        points.sort_unstable();
+
+        Self { points }
+    }
+
+    /// Shard owning the hash.
+    pub fn shard(&self, hash: u64) -> Option<usize> {
+        let index = self.points.partition_point(|(point, _)| *point < hash);
+        self.points
+            .get(index)
+            .or(self.points.first())
+            .map(|(_, shard)| *shard)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::bigint;
+    use super::*;
+
+    #[test]
+    fn test_ring() {
+        let ring = Ring::get(4, 0);
+        assert_eq!(ring.points.len(), 4 * VIRTUAL_NODES);
+        assert!(Arc::ptr_eq(&ring, &Ring::get(4, VIRTUAL_NODES)));
+        assert_eq!(ring.shard(u64::MAX), ring.shard(0));
+
+        let mut counts = [0; 4];
+        let bigger = Ring::get(5, 0);
+        let mut moved = 0;
+        for key in 0..10_000 {
+            let hash = bigint(key);
+            let shard = ring.shard(hash).unwrap();
+            counts[shard] += 1;
+
+            let new_shard = bigger.shard(hash).unwrap();
+            if new_shard != shard {
+                // Keys only move to the new shard.
+                assert_eq!(new_shard, 4);
+                moved += 1;
+            }
+        }
+
+        // About 1/5 of keys move to the new shard.
+        assert!(moved > 1_000 && moved < 3_000, "{}", moved);
+        for count in counts {
+            assert!(count > 1_500 && count < 3_500, "{:?}", counts);
+        }
+
+        assert_eq!(Ring::get(0, 0).shard(1234), None);
+    }
+}
@@ -1,4 +1,11 @@
+use std::collections::HashMap;
+use std::ffi::c_char;
+
+use tracing::warn;
+
+use crate::backend::shard_map::ShardMap;
 use crate::frontend::router::parser::Shard;
+use crate::plugin::plugin;
 
 use super::{Error, Operator, Value};
 
@@ -6,10 +13,16 @@ use super::{Error, Operator, Value};
 pub struct Context<'a> {
     pub(super) value: Value<'a>,
     pub(super) operator: Operator<'a>,
+    pub(super) mapping: Option<&'a HashMap<String, usize>>,
+    pub(super) shard_map: Option<&'a ShardMap>,
 }
 
 impl<'a> Context<'a> {
     pub fn apply(&self) -> Result<Shard, Error> {
+        if let Some(shard) = self.mapped()? {
+            return Ok(Shard::Direct(shard));
+        }
+
         match &self.operator {
             Operator::Shards(shards) => {
                 if let Some(hash) = self.value.hash()? {
@@ -26,8 +39,164 @@ impl<'a> Context<'a> {
                     return Ok(centroids.shard(&vector, *shards, *probes));
                 }
             }
+
+            Operator::ConsistentHash { ring, .. } => {
+                if let Some(hash) = self.value.hash()? {
+                    if let Some(shard) = ring.shard(hash) {
+                        return Ok(Shard::Direct(shard));
+                    }
+                }
+            }
+
+            Operator::Plugin {
+                shards,
+                plugin: name,
+            } => {
+                let (format, data) = self.value.raw();
+                let parameter = pgdog_plugin::Parameter {
+                    len: data.len() as i32,
+                    data: data.as_ptr() as *const c_char,
+                    format: i16::from(format) as i32,
+                };
+
+                match plugin(name).and_then(|plugin| plugin.shard_key(parameter, *shards)) {
+                    Some(shard) if shard >= 0 && (shard as usize) < *shards => {
+                        return Ok(Shard::Direct(shard as usize));
+                    }
+                    Some(_) => (),
+                    None => warn!(
+                        "plugin \"{}\" isn't loaded or doesn't export pgdog_shard_key, \
+                         sending query to all shards",
+                        name
+                    ),
+                }
+            }
         }
 
         Ok(Shard::All)
     }
+
+    /// Shard explicitly assigned to the value in the sharding key mapping,
+    /// or in the mapping table.
+    fn mapped(&self) -> Result<Option<usize>, Error> {
+        if self.mapping.is_none() && self.shard_map.is_none() {
+            return Ok(None);
+        }
+
+        let Some(value) = self.value.text()? else {
+            return Ok(None);
+        };
+
+        let shard = self
+            .shard_map
+            .and_then(|shard_map| shard_map.get(&value))
+            .or_else(|| {
+                self.mapping
+                    .and_then(|mapping| mapping.get(&value).copied())
+            });
+
+        match shard {
+            Some(shard) if shard >= self.operator.shards() => {
+                Err(Error::MappedShard(value, shard, self.operator.shards()))
+            }
+            shard => Ok(shard),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::{DataType, ShardedTable, ShardingFunction};
+
+    use super::super::ContextBuilder;
+    use super::*;
+
+    #[test]
+    fn test_mapping() {
+        let mut table = ShardedTable {
+            column: "country".into(),
+            data_type: DataType::Varchar,
+            mapping: HashMap::from([("US".into(), 3), ("DE".into(), 1), ("FR".into(), 10)]),
+            ..Default::default()
+        };
+        table.load_mapping().unwrap();
+
+        let shard = |value: &str| {
+            ContextBuilder::new(&table)
+                .data(value)
+                .shards(4)
+                .build()
+                .unwrap()
+                .apply()
+        };
+        assert_eq!(shard("US").unwrap(), Shard::Direct(3));
+        assert_eq!(shard("DE").unwrap(), Shard::Direct(1));
+        assert!(matches!(shard("CA").unwrap(), Shard::Direct(shard) if shard < 4));
+        // Mapped to a shard that doesn't exist.
+        assert!(matches!(
+            shard("FR"),
+            Err(Error::MappedShard(value, 10, 4)) if value == "FR"
+        ));
+
+        let mut table = ShardedTable {
+            column: "tenant_id".into(),
+            mapping: HashMap::from([("0042".into(), 1), ("not a number".into(), 0)]),
+            ..Default::default()
+        };
+        table.load_mapping().unwrap();
+        assert_eq!(table.mapping.len(), 1);
+
+        for shards in [2, 4, 8] {
+            let ctx = ContextBuilder::new(&table)
+                .data(42_i64)
+                .shards(shards)
+                .build()
+                .unwrap();
+            assert_eq!(ctx.apply().unwrap(), Shard::Direct(1));
+        }
+
+        let binary = 42_i64.to_be_bytes();
+        let ctx = ContextBuilder::new(&table)
+            .data(&binary[..])
+            .shards(2)
+            .build()
+            .unwrap();
+        assert_eq!(ctx.apply().unwrap(), Shard::Direct(1));
+
+        // Mapping table takes precedence.
+        table.mapping_table = Some("pgdog.shard_map".into());
+        table
+            .shard_map
+            .store(HashMap::from([("42".into(), 0), ("7".into(), 1)]));
+        for (key, shard) in [(42, 0), (7, 1)] {
+            let ctx = ContextBuilder::new(&table)
+                .data(key as i64)
+                .shards(2)
+                .build()
+                .unwrap();
+            assert_eq!(ctx.apply().unwrap(), Shard::Direct(shard));
+        }
+    }
+
+    #[test]
+    fn test_plugin_sharding_function() {
+        let function = ShardingFunction::try_from("plugin:murmur3".to_string()).unwrap();
+        assert_eq!(function, ShardingFunction::Plugin("murmur3".into()));
+        assert_eq!(String::from(function.clone()), "plugin:murmur3");
+        assert!(ShardingFunction::try_from("plugin:".to_string()).is_err());
+        assert!(ShardingFunction::try_from("murmur3".to_string()).is_err());
+
+        // Plugin isn't loaded.
+        let table = ShardedTable {
+            column: "id".into(),
+            sharding_function: function,
+            ..Default::default()
+        };
+        let ctx = ContextBuilder::new(&table)
+            .data("1234")
+            .shards(4)
+            .build()
+            .unwrap();
+        assert_eq!(ctx.apply().unwrap(), Shard::All);
+    }
 }
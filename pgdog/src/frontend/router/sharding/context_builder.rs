@@ -1,6 +1,9 @@
-use crate::config::{DataType, ShardedTable};
+use std::collections::HashMap;
 
-use super::{Centroids, Context, Data, Error, Operator, Value};
+use crate::backend::shard_map::ShardMap;
+use crate::config::{DataType, ShardedTable, ShardingFunction};
+
+use super::{Centroids, Context, Data, Error, Operator, Ring, Value};
 
 pub struct ContextBuilder<'a> {
     data_type: DataType,
@@ -8,6 +11,10 @@ pub struct ContextBuilder<'a> {
     operator: Option<Operator<'a>>,
     centroids: Option<Centroids<'a>>,
     probes: usize,
+    mapping: Option<&'a HashMap<String, usize>>,
+    shard_map: Option<&'a ShardMap>,
+    function: Option<&'a ShardingFunction>,
+    virtual_nodes: usize,
 }
 
 impl<'a> ContextBuilder<'a> {
@@ -22,6 +29,14 @@ impl<'a> ContextBuilder<'a> {
             probes: table.centroid_probes,
             operator: None,
             value: None,
+            mapping: if table.mapping.is_empty() {
+                None
+            } else {
+                Some(&table.mapping)
+            },
+            shard_map: table.mapping_table.as_ref().map(|_| &table.shard_map),
+            function: Some(&table.sharding_function),
+            virtual_nodes: table.virtual_nodes,
         }
     }
 
@@ -37,6 +52,10 @@ impl<'a> ContextBuilder<'a> {
                 probes: 0,
                 centroids: None,
                 operator: None,
+                mapping: None,
+                shard_map: None,
+                function: None,
+                virtual_nodes: 0,
             })
         } else if uuid.valid() {
             Ok(Self {
@@ -45,6 +64,10 @@ impl<'a> ContextBuilder<'a> {
                 probes: 0,
                 centroids: None,
                 operator: None,
+                mapping: None,
+                shard_map: None,
+                function: None,
+                virtual_nodes: 0,
             })
         } else {
             Err(Error::IncompleteContext)
@@ -58,6 +81,16 @@ impl<'a> ContextBuilder<'a> {
                 probes: self.probes,
                 centroids,
             });
+        } else if let Some(ShardingFunction::ConsistentHash) = self.function {
+            self.operator = Some(Operator::ConsistentHash {
+                shards,
+                ring: Ring::get(shards, self.virtual_nodes),
+            });
+        } else if let Some(ShardingFunction::Plugin(plugin)) = self.function {
+            self.operator = Some(Operator::Plugin {
+                shards,
+                plugin: plugin.as_str(),
+            });
         } else {
             self.operator = Some(Operator::Shards(shards))
         }
@@ -78,6 +111,11 @@ impl<'a> ContextBuilder<'a> {
         let operator = self.operator.take().ok_or(Error::IncompleteContext)?;
         let value = self.value.take().ok_or(Error::IncompleteContext)?;
 
-        Ok(Context { operator, value })
+        Ok(Context {
+            operator,
+            value,
+            mapping: self.mapping,
+            shard_map: self.shard_map,
+        })
     }
 }
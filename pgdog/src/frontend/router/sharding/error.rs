@@ -24,4 +24,7 @@ pub enum Error {
 
     #[error("wrong integer binary size")]
     IntegerSize,
+
+    #[error("sharding key \"{0}\" is mapped to shard {1}, but there are only {2} shards")]
+    MappedShard(String, usize, usize),
 }
@@ -1,21 +1,23 @@
 use uuid::Uuid;
 
 use crate::{
-    backend::ShardingSchema,
     config::{DataType, ShardedTable},
     net::messages::{Format, FromDataType, ParameterWithFormat, Vector},
 };
 
 // pub mod context;
+pub mod consistent;
 pub mod context;
 pub mod context_builder;
 pub mod error;
 pub mod ffi;
 pub mod operator;
+pub mod reshard;
 pub mod tables;
 pub mod value;
 pub mod vector;
 
+pub use consistent::Ring;
 pub use context::*;
 pub use context_builder::*;
 pub use error::Error;
@@ -41,24 +43,14 @@ pub fn uuid(uuid: Uuid) -> u64 {
     }
 }
 
-/// Shard a string value, parsing out a BIGINT, UUID, or vector.
-///
-/// TODO: This is really not great, we should pass in the type oid
-/// from RowDescription in here to avoid guessing.
-pub(crate) fn shard_str(
-    value: &str,
-    schema: &ShardingSchema,
-    centroids: &Vec<Vector>,
-    centroid_probes: usize,
-) -> Shard {
-    let data_type = if value.starts_with('[') && value.ends_with(']') {
-        DataType::Vector
-    } else if value.parse::<i64>().is_ok() {
-        DataType::Bigint
-    } else {
-        DataType::Uuid
-    };
-    shard_value(value, &data_type, schema.shards, centroids, centroid_probes)
+/// Hash `VARCHAR`/`TEXT`.
+pub fn varchar(bytes: &[u8]) -> u64 {
+    unsafe {
+        ffi::hash_combine64(
+            0,
+            ffi::hash_bytes_extended(bytes.as_ptr(), bytes.len() as i64),
+        )
+    }
 }
 
 /// Shard a value that's coming out of the query text directly.
@@ -86,6 +78,7 @@ pub(crate) fn shard_value(
             .ok()
             .map(|v| Centroids::from(centroids).shard(&v, shards, centroid_probes))
             .unwrap_or(Shard::All),
+        DataType::Varchar => Shard::Direct(varchar(value.as_bytes()) as usize % shards),
     }
 }
 
@@ -109,6 +102,7 @@ pub(crate) fn shard_binary(
             .ok()
             .map(|v| Centroids::from(centroids).shard(&v, shards, centroid_probes))
             .unwrap_or(Shard::All),
+        DataType::Varchar => Shard::Direct(varchar(bytes) as usize % shards),
     }
 }
 
@@ -1,4 +1,6 @@
-use super::Centroids;
+use std::sync::Arc;
+
+use super::{Centroids, Ring};
 
 #[derive(Debug)]
 pub enum Operator<'a> {
@@ -8,4 +10,24 @@ pub enum Operator<'a> {
         probes: usize,
         centroids: Centroids<'a>,
     },
+    ConsistentHash {
+        shards: usize,
+        ring: Arc<Ring>,
+    },
+    Plugin {
+        shards: usize,
+        plugin: &'a str,
+    },
+}
+
+impl Operator<'_> {
+    /// Number of shards.
+    pub fn shards(&self) -> usize {
+        match self {
+            Self::Shards(shards) => *shards,
+            Self::Centroids { shards, .. } => *shards,
+            Self::ConsistentHash { shards, .. } => *shards,
+            Self::Plugin { shards, .. } => *shards,
+        }
+    }
 }
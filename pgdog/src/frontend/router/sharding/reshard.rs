@@ -0,0 +1,386 @@
+//! Simulate changing the number of shards or the sharding key.
+//!
+//! A sample of sharding keys (and optionally their row counts) and queries
+//! is routed using both the current and the proposed sharding schemes, to
+//! see how much data would move and which queries would stop being direct-to-shard.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+use pg_query::NodeEnum;
+
+use crate::config::ShardedTable;
+use crate::frontend::router::parser::{Key, Shard, Table, WhereClause};
+
+use super::ContextBuilder;
+
+/// Sharding scheme.
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    table: ShardedTable,
+    shards: usize,
+}
+
+impl Scheme {
+    /// Shard the table into this many shards.
+    pub fn new(table: ShardedTable, shards: usize) -> Self {
+        Self { table, shards }
+    }
+
+    /// Shard for the key, if it's valid for the sharding column's data type.
+    fn shard(&self, key: &str) -> Option<usize> {
+        let context = ContextBuilder::new(&self.table)
+            .data(key)
+            .shards(self.shards)
+            .build()
+            .ok()?;
+
+        match context.apply() {
+            Ok(Shard::Direct(shard)) => Some(shard),
+            _ => None,
+        }
+    }
+
+    /// The query goes to one shard. A parameter in place of the sharding key
+    /// targets one shard too, we just don't know which one.
+    fn direct(&self, query: &str) -> bool {
+        let Ok(ast) = pg_query::parse(query) else {
+            return false;
+        };
+        let Some(stmt) = ast
+            .protobuf
+            .stmts
+            .first()
+            .and_then(|stmt| stmt.stmt.as_ref())
+            .and_then(|stmt| stmt.node.as_ref())
+        else {
+            return false;
+        };
+
+        let (relation, where_clause) = match stmt {
+            NodeEnum::SelectStmt(stmt) => (
+                stmt.from_clause.first().and_then(|node| match node.node {
+                    Some(NodeEnum::RangeVar(ref range_var)) => Some(range_var),
+                    _ => None,
+                }),
+                &stmt.where_clause,
+            ),
+            NodeEnum::UpdateStmt(stmt) => (stmt.relation.as_ref(), &stmt.where_clause),
+            NodeEnum::DeleteStmt(stmt) => (stmt.relation.as_ref(), &stmt.where_clause),
+            _ => return false,
+        };
+        let Some(relation) = relation else {
+            return false;
+        };
+
+        if let Some(ref name) = self.table.name {
+            if name != &relation.relname {
+                return false;
+            }
+        }
+
+        let table = Table::from(relation);
+        let Some(where_clause) = WhereClause::new(Some(table.name), where_clause) else {
+            return false;
+        };
+
+        let mut shards = BTreeSet::new();
+        let mut parameters = 0;
+        for key in where_clause.keys(Some(table.name), &self.table.column) {
+            match key {
+                Key::Constant(value) => match self.shard(&value) {
+                    Some(shard) => {
+                        shards.insert(shard);
+                    }
+                    None => return false,
+                },
+                Key::Parameter(_) => parameters += 1,
+                Key::Null => return false,
+            }
+        }
+
+        matches!((shards.len(), parameters), (1, 0) | (0, 1))
+    }
+}
+
+/// Query that would become cross-shard.
+#[derive(Debug, Clone)]
+struct CrossShard {
+    query: String,
+    count: usize,
+}
+
+/// Resharding plan.
+#[derive(Debug)]
+pub struct Plan {
+    current: Scheme,
+    proposed: Scheme,
+    keys: usize,
+    invalid: usize,
+    moved_keys: usize,
+    rows: u64,
+    moved_rows: u64,
+    before: Vec<u64>,
+    after: Vec<u64>,
+    queries: usize,
+    fingerprints: BTreeSet<String>,
+    cross_shard: HashMap<String, CrossShard>,
+}
+
+impl Plan {
+    /// Compare the current sharding scheme to the proposed one.
+    pub fn new(current: Scheme, proposed: Scheme) -> Self {
+        Self {
+            before: vec![0; current.shards],
+            after: vec![0; proposed.shards],
+            current,
+            proposed,
+            keys: 0,
+            invalid: 0,
+            moved_keys: 0,
+            rows: 0,
+            moved_rows: 0,
+            queries: 0,
+            fingerprints: BTreeSet::new(),
+            cross_shard: HashMap::new(),
+        }
+    }
+
+    /// Add a sharding key with the number of rows it has.
+    pub fn key(&mut self, key: &str, rows: u64) {
+        self.keys += 1;
+
+        let (Some(before), Some(after)) = (self.current.shard(key), self.proposed.shard(key))
+        else {
+            self.invalid += 1;
+            return;
+        };
+
+        self.rows += rows;
+        self.before[before] += rows;
+        self.after[after] += rows;
+
+        if before != after {
+            self.moved_keys += 1;
+            self.moved_rows += rows;
+        }
+    }
+
+    /// Add queries separated by semicolons.
+    pub fn queries(&mut self, queries: &str) -> Result<(), pg_query::Error> {
+        for query in pg_query::split_with_parser(queries)? {
+            self.query(query);
+        }
+
+        Ok(())
+    }
+
+    /// Add a query from the workload.
+    pub fn query(&mut self, query: &str) {
+        let Ok(fingerprint) = pg_query::fingerprint(query) else {
+            return;
+        };
+
+        self.queries += 1;
+        self.fingerprints.insert(fingerprint.hex.clone());
+
+        if self.current.direct(query) && !self.proposed.direct(query) {
+            self.cross_shard
+                .entry(fingerprint.hex)
+                .or_insert_with(|| CrossShard {
+                    query: query.trim().to_owned(),
+                    count: 0,
+                })
+                .count += 1;
+        }
+    }
+
+    /// Number of keys that would move to a different shard.
+    pub fn moved_keys(&self) -> usize {
+        self.moved_keys
+    }
+
+    /// Fingerprints of queries that would become cross-shard.
+    pub fn cross_shard(&self) -> Vec<&str> {
+        let mut fingerprints = self
+            .cross_shard
+            .keys()
+            .map(|fingerprint| fingerprint.as_str())
+            .collect::<Vec<_>>();
+        fingerprints.sort();
+        fingerprints
+    }
+
+    fn percent(part: u64, total: u64) -> f64 {
+        if total == 0 {
+            0.0
+        } else {
+            part as f64 / total as f64 * 100.0
+        }
+    }
+
+    /// Largest shard compared to a perfectly even split.
+    fn skew(rows: &[u64]) -> f64 {
+        let total = rows.iter().sum::<u64>();
+        let max = rows.iter().max().copied().unwrap_or_default();
+        if total == 0 {
+            0.0
+        } else {
+            max as f64 / (total as f64 / rows.len() as f64)
+        }
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "sharding \"{}\" into {} shards, was \"{}\" into {} shards",
+            self.proposed.table.column,
+            self.proposed.shards,
+            self.current.table.column,
+            self.current.shards
+        )?;
+
+        if self.keys > 0 {
+            writeln!(f)?;
+            writeln!(f, "keys: {} ({} invalid)", self.keys, self.invalid)?;
+            writeln!(
+                f,
+                "moved: {} keys ({:.2}%), {} rows ({:.2}%)",
+                self.moved_keys,
+                Self::percent(self.moved_keys as u64, (self.keys - self.invalid) as u64),
+                self.moved_rows,
+                Self::percent(self.moved_rows, self.rows),
+            )?;
+            writeln!(f)?;
+            writeln!(f, "{:<8}{:>14}{:>14}", "shard", "rows before", "rows after")?;
+            for shard in 0..self.before.len().max(self.after.len()) {
+                let rows = |rows: &[u64]| {
+                    rows.get(shard)
+                        .map(|rows| rows.to_string())
+                        .unwrap_or("-".into())
+                };
+                writeln!(
+                    f,
+                    "{:<8}{:>14}{:>14}",
+                    shard,
+                    rows(&self.before),
+                    rows(&self.after)
+                )?;
+            }
+            writeln!(
+                f,
+                "skew (largest shard / average): {:.2} before, {:.2} after",
+                Self::skew(&self.before),
+                Self::skew(&self.after),
+            )?;
+        }
+
+        if self.queries > 0 {
+            writeln!(f)?;
+            writeln!(
+                f,
+                "queries: {} ({} fingerprints), {} fingerprints would become cross-shard",
+                self.queries,
+                self.fingerprints.len(),
+                self.cross_shard.len()
+            )?;
+            for fingerprint in self.cross_shard() {
+                let cross_shard = &self.cross_shard[fingerprint];
+                writeln!(
+                    f,
+                    "  {} [{}] {}",
+                    fingerprint, cross_shard.count, cross_shard.query
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::ShardingFunction;
+
+    use super::*;
+
+    fn table(column: &str) -> ShardedTable {
+        ShardedTable {
+            database: "pgdog".into(),
+            name: Some("users".into()),
+            column: column.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut plan = Plan::new(Scheme::new(table("id"), 2), Scheme::new(table("id"), 4));
+        for key in 0..1000 {
+            plan.key(&key.to_string(), 10);
+        }
+        plan.key("not a number", 1);
+
+        assert_eq!(plan.keys, 1001);
+        assert_eq!(plan.invalid, 1);
+        assert_eq!(plan.rows, 10_000);
+        assert!(plan.moved_keys() > 0 && plan.moved_keys() < 1000);
+        assert_eq!(plan.moved_rows, plan.moved_keys() as u64 * 10);
+        assert_eq!(plan.after.iter().sum::<u64>(), 10_000);
+
+        let mut same = Plan::new(Scheme::new(table("id"), 2), Scheme::new(table("id"), 2));
+        same.key("1234", 1);
+        assert_eq!(same.moved_keys(), 0);
+    }
+
+    #[test]
+    fn test_plan_consistent_hash() {
+        let consistent = ShardedTable {
+            sharding_function: ShardingFunction::ConsistentHash,
+            ..table("id")
+        };
+        let mut modulo = Plan::new(Scheme::new(table("id"), 4), Scheme::new(table("id"), 5));
+        let mut ring = Plan::new(
+            Scheme::new(consistent.clone(), 4),
+            Scheme::new(consistent, 5),
+        );
+        for key in 0..1000 {
+            modulo.key(&key.to_string(), 1);
+            ring.key(&key.to_string(), 1);
+        }
+
+        assert!(ring.moved_keys() < 300);
+        assert!(modulo.moved_keys() > ring.moved_keys() * 2);
+    }
+
+    #[test]
+    fn test_cross_shard() {
+        let mut plan = Plan::new(
+            Scheme::new(table("id"), 2),
+            Scheme::new(table("tenant_id"), 2),
+        );
+        plan.queries(
+            "SELECT * FROM users WHERE id = 1;
+            SELECT * FROM users WHERE id = 2;
+            SELECT * FROM users WHERE id = 1 AND tenant_id = 5;
+            UPDATE users SET name = 'a;b' WHERE id = $1;
+            DELETE FROM users WHERE tenant_id = $1;
+            SELECT * FROM orders WHERE id = 1;",
+        )
+        .unwrap();
+
+        assert_eq!(plan.queries, 6);
+        let mut counts = plan
+            .cross_shard
+            .values()
+            .map(|cross_shard| cross_shard.count)
+            .collect::<Vec<_>>();
+        counts.sort();
+        assert_eq!(counts, vec![1, 2]);
+        assert!(plan
+            .to_string()
+            .contains("2 fingerprints would become cross-shard"));
+    }
+}
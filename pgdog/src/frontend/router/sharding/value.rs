@@ -1,8 +1,9 @@
+use std::borrow::Cow;
 use std::str::{from_utf8, FromStr};
 
 use uuid::Uuid;
 
-use super::{bigint, uuid, Error};
+use super::{bigint, uuid, varchar, Error};
 use crate::{
     config::DataType,
     net::{Format, FromDataType, ParameterWithFormat, Vector},
@@ -84,6 +85,10 @@ impl<'a> Value<'a> {
                 Data::Binary(data) => data.len() == 16,
                 Data::Integer(_) => false,
             },
+            DataType::Varchar => match self.data {
+                Data::Text(_) | Data::Integer(_) => true,
+                Data::Binary(data) => from_utf8(data).is_ok(),
+            },
 
             _ => false,
         }
@@ -93,12 +98,7 @@ impl<'a> Value<'a> {
         match self.data_type {
             DataType::Bigint => match self.data {
                 Data::Text(text) => Ok(Some(bigint(text.parse()?))),
-                Data::Binary(data) => Ok(Some(bigint(match data.len() {
-                    2 => i16::from_be_bytes(data.try_into()?) as i64,
-                    4 => i32::from_be_bytes(data.try_into()?) as i64,
-                    8 => i64::from_be_bytes(data.try_into()?) as i64,
-                    _ => return Err(Error::IntegerSize),
-                }))),
+                Data::Binary(data) => Ok(Some(bigint(Self::integer(data)?))),
                 Data::Integer(int) => Ok(Some(bigint(int))),
             },
 
@@ -108,7 +108,58 @@ impl<'a> Value<'a> {
                 Data::Integer(_) => Ok(None),
             },
 
+            DataType::Varchar => match self.data {
+                Data::Text(text) => Ok(Some(varchar(text.as_bytes()))),
+                Data::Binary(data) => Ok(Some(varchar(data))),
+                Data::Integer(int) => Ok(Some(varchar(int.to_string().as_bytes()))),
+            },
+
+            DataType::Vector => Ok(None),
+        }
+    }
+
+    /// Value as text, normalized for its data type,
+    /// e.g. to look it up in the sharding key mapping.
+    pub fn text(&self) -> Result<Option<String>, Error> {
+        match self.data_type {
+            DataType::Bigint => match self.data {
+                Data::Text(text) => Ok(Some(text.trim().parse::<i64>()?.to_string())),
+                Data::Binary(data) => Ok(Some(Self::integer(data)?.to_string())),
+                Data::Integer(int) => Ok(Some(int.to_string())),
+            },
+
+            DataType::Uuid => match self.data {
+                Data::Text(text) => Ok(Some(Uuid::from_str(text.trim())?.to_string())),
+                Data::Binary(data) => Ok(Some(Uuid::from_bytes(data.try_into()?).to_string())),
+                Data::Integer(_) => Ok(None),
+            },
+
+            DataType::Varchar => match self.data {
+                Data::Text(text) => Ok(Some(text.to_owned())),
+                Data::Binary(data) => Ok(Some(from_utf8(data)?.to_owned())),
+                Data::Integer(int) => Ok(Some(int.to_string())),
+            },
+
             DataType::Vector => Ok(None),
         }
     }
+
+    /// Value as sent by the client, with its format code.
+    pub fn raw(&self) -> (Format, Cow<'a, [u8]>) {
+        match self.data {
+            Data::Text(text) => (Format::Text, Cow::Borrowed(text.as_bytes())),
+            Data::Binary(data) => (Format::Binary, Cow::Borrowed(data)),
+            Data::Integer(int) => (Format::Text, Cow::Owned(int.to_string().into_bytes())),
+        }
+    }
+
+    /// Decode a binary-encoded integer of any size.
+    fn integer(data: &[u8]) -> Result<i64, Error> {
+        Ok(match data.len() {
+            2 => i16::from_be_bytes(data.try_into()?) as i64,
+            4 => i32::from_be_bytes(data.try_into()?) as i64,
+            8 => i64::from_be_bytes(data.try_into()?),
+            _ => return Err(Error::IntegerSize),
+        })
+    }
 }
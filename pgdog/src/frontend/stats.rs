@@ -3,7 +3,7 @@
 use std::time::{Duration, SystemTime};
 use tokio::time::Instant;
 
-use crate::state::State;
+use crate::{state::State, stats::wait_events::WaitEvents};
 
 /// Client statistics.
 #[derive(Copy, Clone, Debug)]
@@ -34,6 +34,20 @@ pub struct Stats {
     pub last_request: SystemTime,
     pub memory_used: usize,
     pub locked: bool,
+    /// Time it took the last request to reach Sync.
+    pub buffer_time: Duration,
+    /// Longest time a request took to reach Sync.
+    pub max_buffer_time: Duration,
+    /// Size of the last request.
+    pub buffer_size: usize,
+    /// Largest request.
+    pub max_buffer_size: usize,
+    /// Requests larger than the warning threshold.
+    pub oversized_requests: usize,
+    /// Time spent in each state, since they were last recorded.
+    pub wait_events: WaitEvents,
+    /// When the current wait event started.
+    event_timer: Instant,
 }
 
 impl Stats {
@@ -56,6 +70,13 @@ impl Stats {
             last_request: SystemTime::now(),
             memory_used: 0,
             locked: false,
+            buffer_time: Duration::from_secs(0),
+            max_buffer_time: Duration::from_secs(0),
+            buffer_size: 0,
+            max_buffer_size: 0,
+            oversized_requests: 0,
+            wait_events: WaitEvents::default(),
+            event_timer: now,
         }
     }
 
@@ -98,6 +119,34 @@ impl Stats {
         self.transaction_timer = now;
         self.query_timer = now;
         self.wait_time = now.duration_since(self.wait_timer);
+        self.wait_events.checkout += self.wait_time;
+    }
+
+    /// Request sent to the server, waiting for it to respond.
+    pub(super) fn request_sent(&mut self) {
+        self.event_timer = Instant::now();
+    }
+
+    /// Server sent a message.
+    pub(super) fn server_responded(&mut self) {
+        // Notifications are sent by the server whenever they want.
+        if matches!(self.state, State::Idle | State::IdleInTransaction) {
+            return;
+        }
+        let now = Instant::now();
+        self.wait_events.server += now.duration_since(self.event_timer);
+        self.event_timer = now;
+    }
+
+    /// Sent a server message to the client.
+    pub(super) fn client_sent(&mut self, duration: Duration) {
+        self.wait_events.client += duration;
+        self.event_timer = Instant::now();
+    }
+
+    /// Wait events since they were last recorded.
+    pub(super) fn take_wait_events(&mut self) -> WaitEvents {
+        std::mem::take(&mut self.wait_events)
     }
 
     pub(super) fn locked(&mut self, lock: bool) {
@@ -108,6 +157,17 @@ impl Stats {
         self.bytes_sent += bytes;
     }
 
+    /// Record how long a request took to buffer and how large it got.
+    pub(super) fn buffered(&mut self, time: Duration, size: usize, oversized: bool) {
+        self.buffer_time = time;
+        self.buffer_size = size;
+        self.max_buffer_time = self.max_buffer_time.max(time);
+        self.max_buffer_size = self.max_buffer_size.max(size);
+        if oversized {
+            self.oversized_requests += 1;
+        }
+    }
+
     pub(super) fn memory_used(&mut self, memory: usize) {
         self.memory_used = memory;
     }
@@ -122,6 +182,9 @@ impl Stats {
 
     pub(super) fn received(&mut self, bytes: usize) {
         self.bytes_received += bytes;
+        if self.state == State::IdleInTransaction {
+            self.wait_events.idle_in_transaction += self.event_timer.elapsed();
+        }
         // In session mode, we stay connected to the server
         // until client disconnects, so we need to reset timers every time
         // client is activated from idle state.
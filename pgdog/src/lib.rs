@@ -3,12 +3,14 @@ pub mod auth;
 pub mod backend;
 pub mod cli;
 pub mod config;
+pub mod events;
 pub mod frontend;
 pub mod net;
 pub mod plugin;
 pub mod sighup;
 pub mod state;
 pub mod stats;
+pub mod telemetry;
 #[cfg(feature = "tui")]
 pub mod tui;
 pub mod util;
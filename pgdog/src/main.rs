@@ -1,13 +1,16 @@
 //! pgDog, modern PostgreSQL proxy, pooler and query router.
 
 use clap::Parser;
+use pgdog::admin;
 use pgdog::backend::databases;
 use pgdog::cli::{self, Commands};
 use pgdog::config;
+use pgdog::events;
 use pgdog::frontend::listener::Listener;
 use pgdog::net;
 use pgdog::plugin;
 use pgdog::stats;
+use pgdog::telemetry;
 use tokio::runtime::Builder;
 use tracing::info;
 
@@ -35,6 +38,38 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         Some(Commands::Schema) => (),
 
+        Some(Commands::PlanReshard {
+            database,
+            shards,
+            column,
+            keys,
+            queries,
+        }) => {
+            config::source::fetch(&[&args.config, &args.users])?;
+            let config = config::load(&args.config, &args.users)?;
+            cli::plan_reshard(&config, &database, shards, column, keys, queries)?;
+            exit(0);
+        }
+
+        Some(Commands::CheckConfig {
+            connect,
+            admin_url,
+            admin_token,
+        }) => {
+            config::source::fetch(&[&args.config, &args.users])?;
+            let ok = cli::check_config(&args.config, &args.users, connect, admin_url, admin_token)?;
+            exit(if ok { 0 } else { 1 });
+        }
+
+        Some(Commands::MigrateConfig {
+            from,
+            userlist,
+            force,
+        }) => {
+            cli::migrate_config(&from, userlist.as_ref(), &args.config, &args.users, force)?;
+            exit(0);
+        }
+
         Some(Commands::Run {
             pool_size,
             min_pool_size,
@@ -55,6 +90,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let config = if let Some(database_urls) = args.database_url {
         config::from_urls(&database_urls)?
     } else {
+        config::source::fetch(&[&args.config, &args.users])?;
         config::load(&args.config, &args.users)?
     };
 
@@ -100,6 +136,18 @@ async fn pgdog() -> Result<(), Box<dyn std::error::Error>> {
         tokio::spawn(async move { stats::http_server::server(openmetrics_port).await });
     }
 
+    if let Some(http_port) = config::config().config.admin.http_port {
+        let host = config::config().config.admin.http_host.clone();
+        tokio::spawn(async move { admin::api::server(&host, http_port).await });
+    }
+
+    telemetry::exporter::start();
+    stats::statsd::start();
+    stats::export::start();
+    events::start();
+    config::source::watch();
+    config::watch::watch();
+
     let stats_logger = stats::StatsLogger::new();
 
     if general.dry_run {
@@ -1,6 +1,9 @@
+use std::sync::Arc;
+
+use crate::backend::pool::Oids;
 use crate::frontend::PreparedStatements;
 
-use super::{Bind, Format, RowDescription};
+use super::{Bind, DataType, Format, RowDescription};
 
 impl From<&Bind> for Decoder {
     fn from(value: &Bind) -> Self {
@@ -22,6 +25,7 @@ impl From<&RowDescription> for Decoder {
 pub struct Decoder {
     formats: Vec<Format>,
     rd: RowDescription,
+    oids: Arc<Oids>,
 }
 
 impl Decoder {
@@ -34,8 +38,9 @@ impl Decoder {
     pub fn bind(&mut self, bind: &Bind) {
         // Only override RowDescription formats if
         // Bind specifies formats.
-        if !bind.codes().is_empty() {
-            self.formats = bind.codes().to_vec();
+        let formats = bind.result_formats();
+        if !formats.is_empty() {
+            self.formats = formats;
         }
 
         if self.rd.is_empty() {
@@ -67,4 +72,34 @@ impl Decoder {
     pub fn rd(&self) -> &RowDescription {
         &self.rd
     }
+
+    /// Decode custom data types, e.g. enums or pgvector,
+    /// loaded from the databases.
+    pub fn oids(&mut self, oids: Oids) {
+        self.oids = Arc::new(oids);
+    }
+
+    /// Get data type of column at position.
+    pub fn data_type(&self, position: usize) -> DataType {
+        match self.rd.field(position).map(|field| field.data_type()) {
+            Some(DataType::Other(oid)) => self.oids.data_type(oid).unwrap_or(DataType::Other(oid)),
+            Some(data_type) => data_type,
+            None => DataType::Other(0),
+        }
+    }
+
+    /// A column with a data type that's different on some shards.
+    pub fn mismatched(&self) -> Option<i32> {
+        self.rd
+            .fields
+            .iter()
+            .map(|field| field.type_oid)
+            .find(|oid| self.oids.mismatched(*oid))
+    }
+
+    /// Sort position of an enum value in column at position.
+    pub fn enum_order(&self, position: usize, label: &str) -> Option<usize> {
+        let field = self.rd.field(position)?;
+        self.oids.enum_order(field.type_oid, label)
+    }
 }
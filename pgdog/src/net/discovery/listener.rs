@@ -25,12 +25,27 @@ pub struct Listener {
 
 #[derive(Debug, Clone)]
 pub struct State {
+    /// Node identifier.
+    pub node_id: u64,
     /// Number of connected clients.
     pub clients: u64,
+    /// Servers the node can't reach.
+    pub down: Vec<String>,
     /// When we received the last state update.
     pub last_message: SystemTime,
 }
 
+impl State {
+    fn new(node_id: u64, now: SystemTime) -> Self {
+        Self {
+            node_id,
+            clients: 0,
+            down: vec![],
+            last_message: now,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Inner {
     peers: HashMap<SocketAddr, State>,
@@ -59,6 +74,29 @@ impl Listener {
         self.inner.lock().peers.clone()
     }
 
+    /// Peers that can't reach the server, and all peers we know about,
+    /// not counting this node. Peers we haven't heard from in `timeout`
+    /// are counted but don't agree.
+    pub fn votes(&self, server: &str, timeout: Duration) -> (usize, usize) {
+        let now = SystemTime::now();
+        let guard = self.inner.lock();
+        let peers = guard
+            .peers
+            .values()
+            .filter(|state| state.node_id != self.id)
+            .collect::<Vec<_>>();
+        let down = peers
+            .iter()
+            .filter(|state| {
+                now.duration_since(state.last_message)
+                    .is_ok_and(|elapsed| elapsed <= timeout)
+                    && state.down.iter().any(|down| down == server)
+            })
+            .count();
+
+        (down, peers.len())
+    }
+
     /// Run the listener.
     pub fn run(&self, address: Ipv4Addr, port: u16) {
         let listener = self.clone();
@@ -89,13 +127,18 @@ impl Listener {
                     if let Some(message) = message {
                         debug!("{}: {:#?}", addr, message);
 
-                        if let Payload::Stats {
-                                clients
-                            } = message.payload {
-                            self.inner.lock().peers.insert(addr, State {
-                                clients,
-                                last_message: now,
-                            });
+                        let mut guard = self.inner.lock();
+                        let state = guard
+                            .peers
+                            .entry(addr)
+                            .or_insert_with(|| State::new(message.node_id, now));
+                        state.node_id = message.node_id;
+                        state.last_message = now;
+
+                        match message.payload {
+                            Payload::Stats { clients } => state.clients = clients,
+                            Payload::Health { down } => state.down = down,
+                            Payload::Healthcheck => (),
                         }
 
                     }
@@ -104,6 +147,8 @@ impl Listener {
                 _ = interval.tick() => {
                     let healthcheck = Message::stats(self.id).to_bytes()?;
                     socket.send_to(&healthcheck, format!("{}:{}", address, port)).await?;
+                    let health = Message::health(self.id).to_bytes()?;
+                    socket.send_to(&health, format!("{}:{}", address, port)).await?;
                     debug!("healtcheck");
                 }
             }
@@ -1,13 +1,20 @@
 use rmp_serde::{decode, encode, Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
 
+use crate::backend::pool::failover::unreachable;
 use crate::frontend::comms::comms;
 
 /// Message kind.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Payload {
     Healthcheck,
-    Stats { clients: u64 },
+    Stats {
+        clients: u64,
+    },
+    /// Servers failing healthchecks on this node.
+    Health {
+        down: Vec<String>,
+    },
 }
 
 /// Message sent via UDP.
@@ -47,4 +54,14 @@ impl Message {
             payload: Payload::Stats { clients },
         }
     }
+
+    /// Servers this node can't reach.
+    pub fn health(node_id: u64) -> Self {
+        Self {
+            node_id,
+            payload: Payload::Health {
+                down: unreachable(),
+            },
+        }
+    }
 }
@@ -1,6 +1,7 @@
 //! Frontend errors.
 
 use std::array::TryFromSliceError;
+use std::path::PathBuf;
 
 use thiserror::Error;
 use tokio_rustls::rustls;
@@ -37,6 +38,22 @@ pub enum Error {
     #[error("{0}")]
     Rustls(#[from] rustls::Error),
 
+    #[error("{0}")]
+    Verifier(#[from] rustls::client::VerifierBuilderError),
+
+    #[error("can't read TLS certificate \"{0}\" or its key")]
+    TlsReload(PathBuf),
+
+    #[error("can't load native certificates: {0}")]
+    NativeCerts(String),
+
+    #[error("TLS for database \"{database}\" (shard {shard}): {error}")]
+    ServerTls {
+        database: String,
+        shard: usize,
+        error: Box<Error>,
+    },
+
     #[error("\"{0}\" parameter is missing")]
     MissingParameter(String),
 
@@ -61,6 +78,9 @@ pub enum Error {
     #[error("not a float")]
     NotFloat(#[from] std::num::ParseFloatError),
 
+    #[error("not a numeric")]
+    NotNumeric(#[from] bigdecimal::ParseBigDecimalError),
+
     #[error("not a uuid")]
     NotUuid(#[from] uuid::Error),
 
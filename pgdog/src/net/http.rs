@@ -0,0 +1,147 @@
+//! Minimal HTTP client, for pushing data to webhooks and collectors
+//! and talking to the admin API.
+
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::Bytes,
+    client::conn::http1::{self, SendRequest},
+    header::{HeaderName, AUTHORIZATION, CONTENT_TYPE, HOST},
+    Method, Request, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use rustls_pki_types::ServerName;
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    spawn,
+};
+use url::Url;
+
+use crate::config::ServerTlsMode;
+
+use super::tls;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Url(#[from] url::ParseError),
+
+    #[error("url has no host")]
+    NoHost,
+
+    #[error("only http and https urls are supported")]
+    Scheme,
+
+    #[error("tls: {0}")]
+    Tls(String),
+
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("{0}")]
+    Hyper(#[from] hyper::Error),
+
+    #[error("{0}")]
+    Http(#[from] hyper::http::Error),
+
+    #[error("server returned {0}")]
+    Status(StatusCode),
+
+    #[error("timeout")]
+    Timeout,
+}
+
+/// POST the body, expecting a 2xx response.
+pub async fn post(url: &str, content_type: &str, body: impl Into<Bytes>) -> Result<(), Error> {
+    let (url, host, mut sender) = connect(url).await?;
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(&url[url::Position::BeforePath..])
+        .header(HOST, host)
+        .header(CONTENT_TYPE, content_type)
+        .body(Full::new(body.into()))?;
+    let response = sender.send_request(request).await?;
+
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(Error::Status(response.status()))
+    }
+}
+
+/// GET the url with a bearer token, returning the body of a 2xx response.
+pub async fn get(url: &str, token: &str) -> Result<Bytes, Error> {
+    request(
+        Method::GET,
+        url,
+        &[(AUTHORIZATION, &format!("Bearer {}", token))],
+        Bytes::new(),
+    )
+    .await
+}
+
+/// Send a request, returning the body of a 2xx response.
+pub async fn request(
+    method: Method,
+    url: &str,
+    headers: &[(HeaderName, &str)],
+    body: impl Into<Bytes>,
+) -> Result<Bytes, Error> {
+    let (url, host, mut sender) = connect(url).await?;
+
+    let mut request = Request::builder()
+        .method(method)
+        .uri(&url[url::Position::BeforePath..])
+        .header(HOST, host);
+    for (name, value) in headers {
+        request = request.header(name, *value);
+    }
+    let response = sender
+        .send_request(request.body(Full::new(body.into()))?)
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(Error::Status(response.status()));
+    }
+
+    Ok(response.into_body().collect().await?.to_bytes())
+}
+
+async fn connect(url: &str) -> Result<(Url, String, SendRequest<Full<Bytes>>), Error> {
+    let url = Url::parse(url)?;
+    let https = match url.scheme() {
+        "http" => false,
+        "https" => true,
+        _ => return Err(Error::Scheme),
+    };
+    let host = url.host_str().ok_or(Error::NoHost)?.to_owned();
+    let port = url.port_or_known_default().unwrap_or(80);
+
+    let stream = TcpStream::connect((host.as_str(), port)).await?;
+    let sender = if https {
+        // Verify the certificate against the system CAs.
+        let connector = tls::server_connector(ServerTlsMode::VerifyFull, None)
+            .map_err(|err| Error::Tls(err.to_string()))?;
+        let server_name =
+            ServerName::try_from(host.clone()).map_err(|err| Error::Tls(err.to_string()))?;
+        handshake(connector.connect(server_name, stream).await?).await?
+    } else {
+        handshake(stream).await?
+    };
+
+    Ok((url, host, sender))
+}
+
+async fn handshake<T>(stream: T) -> Result<SendRequest<Full<Bytes>>, Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, conn) = http1::handshake(TokioIo::new(stream)).await?;
+    spawn(async move {
+        let _ = conn.await;
+    });
+
+    Ok(sender)
+}
@@ -12,7 +12,7 @@ use std::fmt::Debug;
 use std::str::from_utf8;
 use std::str::from_utf8_unchecked;
 
-#[derive(PartialEq, Debug, Copy, Clone, PartialOrd, Ord, Eq)]
+#[derive(PartialEq, Debug, Copy, Clone, PartialOrd, Ord, Eq, Hash)]
 pub enum Format {
     Text,
     Binary,
@@ -160,6 +160,15 @@ impl Bind {
             .map(|parameter| ParameterWithFormat { parameter, format }))
     }
 
+    /// Bind parameters in text format to a prepared statement.
+    pub fn new_params(name: &str, params: &[Parameter]) -> Self {
+        Self {
+            statement: Bytes::from(name.to_string() + "\0"),
+            params: params.to_vec(),
+            ..Default::default()
+        }
+    }
+
     /// Rename this Bind message to a different prepared statement.
     pub fn rename(mut self, name: impl ToString) -> Self {
         self.statement = Bytes::from(name.to_string() + "\0");
@@ -182,6 +191,57 @@ impl Bind {
     pub fn codes(&self) -> &[Format] {
         &self.codes
     }
+
+    /// Formats of the result columns. One format is used for all columns.
+    pub fn result_formats(&self) -> Vec<Format> {
+        self.results
+            .iter()
+            .map(|code| match code {
+                1 => Format::Binary,
+                _ => Format::Text,
+            })
+            .collect()
+    }
+
+    /// Return more result columns, in text format if each column has its own format.
+    pub(crate) fn add_results(&mut self, columns: usize) {
+        if self.results.len() > 1 {
+            self.results.extend(std::iter::repeat_n(0, columns));
+            self.original = None;
+        }
+    }
+
+    /// Number of parameters.
+    pub(crate) fn params_len(&self) -> usize {
+        self.params.len()
+    }
+
+    /// Replace an integer parameter, keeping its format and size.
+    pub(crate) fn set_integer(&mut self, index: usize, value: i64) -> Result<(), Error> {
+        let format = self.parameter_format(index)?;
+        let Some(param) = self.params.get_mut(index) else {
+            return Ok(());
+        };
+
+        param.data = match format {
+            Format::Text => value.to_string().into_bytes(),
+            Format::Binary => match param.data.len() {
+                2 => i16::try_from(value)
+                    .unwrap_or(i16::MAX)
+                    .to_be_bytes()
+                    .to_vec(),
+                4 => i32::try_from(value)
+                    .unwrap_or(i32::MAX)
+                    .to_be_bytes()
+                    .to_vec(),
+                _ => value.to_be_bytes().to_vec(),
+            },
+        };
+        param.len = param.data.len() as i32;
+        self.original = None;
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -217,6 +277,14 @@ impl Bind {
             ..Default::default()
         }
     }
+
+    pub(crate) fn test_results(name: &str, results: &[i16]) -> Self {
+        Self {
+            statement: Bytes::from(name.to_string() + "\0"),
+            results: results.to_vec(),
+            ..Default::default()
+        }
+    }
 }
 
 impl FromBytes for Bind {
@@ -403,4 +471,29 @@ mod test {
             assert_eq!(msg.code(), c);
         }
     }
+
+    #[test]
+    fn test_set_integer() {
+        let bind = Bind::test_params_codes(
+            "",
+            &[
+                Parameter {
+                    len: 2,
+                    data: "10".as_bytes().to_vec(),
+                },
+                Parameter {
+                    len: 4,
+                    data: 20_i32.to_be_bytes().to_vec(),
+                },
+            ],
+            &[Format::Text, Format::Binary],
+        );
+        let mut bind = Bind::from_bytes(bind.to_bytes().unwrap()).unwrap();
+        bind.set_integer(0, 300).unwrap();
+        bind.set_integer(1, 0).unwrap();
+
+        let bind = Bind::from_bytes(bind.to_bytes().unwrap()).unwrap();
+        assert_eq!(bind.parameter(0).unwrap().unwrap().text(), Some("300"));
+        assert_eq!(bind.parameter(1).unwrap().unwrap().data(), &[0, 0, 0, 0]);
+    }
 }
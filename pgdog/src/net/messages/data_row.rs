@@ -200,7 +200,7 @@ impl DataRow {
             if let Some(data) = self.column(index) {
                 return Ok(Some(Column {
                     name: field.name.as_str(),
-                    value: Datum::new(&data, field.data_type(), decoder.format(index))?,
+                    value: Datum::new(&data, decoder.data_type(index), decoder.format(index))?,
                 }));
             }
         }
@@ -229,6 +229,11 @@ impl DataRow {
         self.columns.len()
     }
 
+    /// Keep only the first `len` columns.
+    pub fn truncate(&mut self, len: usize) {
+        self.columns.truncate(len);
+    }
+
     /// No columns.
     pub fn is_empty(&self) -> bool {
         self.len() == 0
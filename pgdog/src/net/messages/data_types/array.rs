@@ -0,0 +1,304 @@
+//! One-dimensional arrays, e.g. `{1,2,NULL,"a b"}`.
+
+use std::fmt::Display;
+
+use bytes::{Buf, BufMut, BytesMut};
+
+use super::*;
+
+/// Array elements, in text format.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TextArray {
+    pub elements: Vec<Option<String>>,
+}
+
+impl TextArray {
+    /// Parse a one-dimensional array.
+    pub fn decode(text: &str) -> Result<Self, Error> {
+        // Skip dimensions, e.g. `[0:2]={1,2,3}`.
+        let text = match text.find('=') {
+            Some(pos) if text.starts_with('[') => &text[pos + 1..],
+            _ => text,
+        };
+
+        let inner = text
+            .trim()
+            .strip_prefix('{')
+            .and_then(|text| text.strip_suffix('}'))
+            .ok_or(Error::UnexpectedPayload)?;
+
+        let mut elements = vec![];
+        let mut chars = inner.chars().peekable();
+
+        if inner.trim().is_empty() {
+            return Ok(Self { elements });
+        }
+
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+
+            let element = if chars.next_if_eq(&'"').is_some() {
+                let mut element = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => element.push(chars.next().ok_or(Error::UnexpectedPayload)?),
+                        Some(c) => element.push(c),
+                        None => return Err(Error::UnexpectedPayload),
+                    }
+                }
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                Some(element)
+            } else {
+                let mut element = String::new();
+                while let Some(c) = chars.next_if(|c| *c != ',') {
+                    match c {
+                        // Multi-dimensional arrays aren't supported.
+                        '{' | '}' => return Err(Error::UnexpectedPayload),
+                        '\\' => element.push(chars.next().ok_or(Error::UnexpectedPayload)?),
+                        c => element.push(c),
+                    }
+                }
+                let element = element.trim_end();
+                if element.eq_ignore_ascii_case("null") {
+                    None
+                } else {
+                    Some(element.to_owned())
+                }
+            };
+
+            elements.push(element);
+
+            match chars.next() {
+                Some(',') => continue,
+                None => break,
+                Some(_) => return Err(Error::UnexpectedPayload),
+            }
+        }
+
+        Ok(Self { elements })
+    }
+
+    fn quote(element: &str) -> bool {
+        element.is_empty()
+            || element.eq_ignore_ascii_case("null")
+            || element
+                .chars()
+                .any(|c| matches!(c, '{' | '}' | ',' | '"' | '\\') || c.is_whitespace())
+    }
+}
+
+impl Display for TextArray {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{")?;
+        for (i, element) in self.elements.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match element {
+                None => write!(f, "NULL")?,
+                Some(element) if Self::quote(element) => {
+                    write!(f, "\"")?;
+                    for c in element.chars() {
+                        if c == '"' || c == '\\' {
+                            write!(f, "\\")?;
+                        }
+                        write!(f, "{}", c)?;
+                    }
+                    write!(f, "\"")?;
+                }
+                Some(element) => write!(f, "{}", element)?,
+            }
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Array with elements in the same format as the array.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Array {
+    /// Element data type.
+    pub element_oid: i32,
+    /// Elements, `None` if NULL.
+    pub elements: Vec<Option<Bytes>>,
+}
+
+impl Array {
+    /// Element type of built-in array types.
+    pub fn element_oid(array_oid: i32) -> Option<i32> {
+        Some(match array_oid {
+            1000 => 16,
+            1005 => 21,
+            1007 => 23,
+            1016 => 20,
+            1009 => 25,
+            1015 => 1043,
+            1021 => 700,
+            1022 => 701,
+            1231 => 1700,
+            1115 => 1114,
+            1185 => 1184,
+            1187 => 1186,
+            2951 => 2950,
+            _ => return None,
+        })
+    }
+
+    /// Decode a one-dimensional array of type `array_oid`.
+    pub fn decode(bytes: &[u8], array_oid: i32, encoding: Format) -> Result<Self, Error> {
+        match encoding {
+            Format::Text => Ok(Self {
+                element_oid: Self::element_oid(array_oid).unwrap_or(0),
+                elements: TextArray::decode(&String::decode(bytes, Format::Text)?)?
+                    .elements
+                    .into_iter()
+                    .map(|element| element.map(Bytes::from))
+                    .collect(),
+            }),
+
+            // Dimensions, flags, element type, length and lower bound of each dimension,
+            // and the elements, each prefixed with its length, -1 if it's NULL.
+            Format::Binary => {
+                let mut bytes = bytes;
+                if bytes.len() < 12 {
+                    return Err(Error::WrongSizeBinary(bytes.len()));
+                }
+                let dimensions = bytes.get_i32();
+                let _flags = bytes.get_i32();
+                let element_oid = bytes.get_i32();
+
+                let len = match dimensions {
+                    0 => 0,
+                    1 if bytes.len() >= 8 => {
+                        let len = bytes.get_i32();
+                        let _lower_bound = bytes.get_i32();
+                        len
+                    }
+                    1 => return Err(Error::WrongSizeBinary(bytes.len())),
+                    // Multi-dimensional arrays aren't supported.
+                    _ => return Err(Error::UnexpectedPayload),
+                };
+
+                let mut elements = vec![];
+                for _ in 0..len {
+                    if bytes.len() < 4 {
+                        return Err(Error::WrongSizeBinary(bytes.len()));
+                    }
+                    let len = bytes.get_i32();
+                    if len < 0 {
+                        elements.push(None);
+                    } else if bytes.len() < len as usize {
+                        return Err(Error::WrongSizeBinary(bytes.len()));
+                    } else {
+                        elements.push(Some(Bytes::copy_from_slice(&bytes[..len as usize])));
+                        bytes.advance(len as usize);
+                    }
+                }
+
+                Ok(Self {
+                    element_oid,
+                    elements,
+                })
+            }
+        }
+    }
+
+    /// Encode the array. Elements must already be in this format.
+    pub fn encode(&self, encoding: Format) -> Result<Bytes, Error> {
+        match encoding {
+            Format::Text => {
+                let elements = self
+                    .elements
+                    .iter()
+                    .map(|element| {
+                        element
+                            .as_ref()
+                            .map(|element| String::decode(element, Format::Text))
+                            .transpose()
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                Ok(Bytes::from(TextArray { elements }.to_string()))
+            }
+
+            Format::Binary => {
+                let mut bytes = BytesMut::new();
+                bytes.put_i32(if self.elements.is_empty() { 0 } else { 1 });
+                bytes.put_i32(self.elements.iter().any(Option::is_none) as i32);
+                bytes.put_i32(self.element_oid);
+                if !self.elements.is_empty() {
+                    bytes.put_i32(self.elements.len() as i32);
+                    bytes.put_i32(1);
+                }
+                for element in &self.elements {
+                    match element {
+                        Some(element) => {
+                            bytes.put_i32(element.len() as i32);
+                            bytes.put_slice(element);
+                        }
+                        None => bytes.put_i32(-1),
+                    }
+                }
+                Ok(bytes.freeze())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text_array() {
+        let array = TextArray::decode(r#"{1, "a b",NULL,"NULL","x\"y",""}"#).unwrap();
+        assert_eq!(
+            array.elements,
+            vec![
+                Some("1".into()),
+                Some("a b".into()),
+                None,
+                Some("NULL".into()),
+                Some("x\"y".into()),
+                Some("".into()),
+            ]
+        );
+        assert_eq!(array.to_string(), r#"{1,"a b",NULL,"NULL","x\"y",""}"#);
+
+        assert_eq!(TextArray::decode("{}").unwrap(), TextArray::default());
+        assert_eq!(
+            TextArray::decode("[0:1]={3,4}").unwrap().elements,
+            vec![Some("3".into()), Some("4".into())]
+        );
+        assert!(TextArray::decode("{{1,2},{3,4}}").is_err());
+        assert!(TextArray::decode("1,2").is_err());
+    }
+
+    #[test]
+    fn test_array() {
+        let array = Array::decode(b"{1,NULL,\"\"}", 1009, Format::Text).unwrap();
+        assert_eq!(array.element_oid, 25);
+        assert_eq!(
+            array.elements,
+            vec![Some(Bytes::from("1")), None, Some(Bytes::new())]
+        );
+        assert_eq!(array.encode(Format::Text).unwrap(), "{1,NULL,\"\"}");
+
+        // SELECT '{1,NULL}'::int4[] in binary.
+        let binary = [
+            0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 23, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 1,
+            0xff, 0xff, 0xff, 0xff,
+        ];
+        let array = Array::decode(&binary, 1007, Format::Binary).unwrap();
+        assert_eq!(array.element_oid, 23);
+        assert_eq!(
+            array.elements,
+            vec![Some(Bytes::from_static(&[0, 0, 0, 1])), None]
+        );
+        assert_eq!(array.encode(Format::Binary).unwrap(), &binary[..]);
+
+        let empty =
+            Array::decode(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 23], 1007, Format::Binary).unwrap();
+        assert!(empty.elements.is_empty());
+        assert!(Array::decode(&[0, 0, 0, 1], 1007, Format::Binary).is_err());
+    }
+}
@@ -0,0 +1,446 @@
+//! NUMERIC, without losing precision.
+
+use std::{
+    fmt::Display,
+    iter::{repeat, repeat_n},
+    ops::{Add, Neg},
+    str::FromStr,
+};
+
+use bigdecimal::{
+    num_bigint::{BigInt, Sign},
+    BigDecimal, Context, RoundingMode, Signed, ToPrimitive, Zero,
+};
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::net::messages::data_row::Data;
+
+use super::*;
+
+const POSITIVE: u16 = 0x0000;
+const NEGATIVE: u16 = 0x4000;
+const NAN: u16 = 0xC000;
+const INFINITY: u16 = 0xD000;
+const NEGATIVE_INFINITY: u16 = 0xF000;
+
+/// Significant digits of a quotient, same as `NUMERIC_MIN_SIG_DIGITS` in Postgres.
+const MIN_SIG_DIGITS: i64 = 16;
+
+/// Largest scale Postgres displays.
+const MAX_DISPLAY_SCALE: i64 = 1000;
+
+/// NUMERIC value.
+///
+/// Unlike [`Numeric`], which is a float, it's exact and keeps the scale
+/// it was sent with, e.g. `1.50` stays `1.50`. Equal values are equal
+/// regardless of their scale. Variants are in the order Postgres sorts them.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Decimal {
+    NegativeInfinity,
+    Finite(BigDecimal),
+    Infinity,
+    NaN,
+}
+
+impl Decimal {
+    /// Digits after the decimal point.
+    fn scale(value: &BigDecimal) -> i64 {
+        value.fractional_digit_count().max(0)
+    }
+
+    /// Position and value of the first base 10000 digit, the way Postgres stores them.
+    fn weight(value: &BigDecimal) -> (i64, u32) {
+        if value.is_zero() {
+            return (0, 0);
+        }
+
+        let (mantissa, scale) = value.as_bigint_and_exponent();
+        let digits = mantissa.magnitude().to_string();
+        // Power of ten of the first digit.
+        let exponent = digits.len() as i64 - 1 - scale;
+        let weight = exponent.div_euclid(4);
+        let first = digits
+            .chars()
+            .chain(repeat('0'))
+            .take((exponent - weight * 4 + 1) as usize)
+            .collect::<String>()
+            .parse()
+            .unwrap_or(0);
+
+        (weight, first)
+    }
+
+    /// Scale of a quotient, same as `select_div_scale` in Postgres:
+    /// at least 16 significant digits, and no fewer digits after the
+    /// decimal point than either input.
+    fn div_scale(dividend: &BigDecimal, divisor: &BigDecimal) -> i64 {
+        let (weight1, first1) = Self::weight(dividend);
+        let (weight2, first2) = Self::weight(divisor);
+        let mut weight = weight1 - weight2;
+        if first1 <= first2 {
+            weight -= 1;
+        }
+
+        (MIN_SIG_DIGITS - weight * 4)
+            .max(Self::scale(dividend))
+            .max(Self::scale(divisor))
+            .clamp(0, MAX_DISPLAY_SCALE)
+    }
+
+    /// Divide like Postgres does, rounding half away from zero.
+    pub fn div(&self, divisor: &Self) -> Self {
+        use Decimal::*;
+
+        match (self, divisor) {
+            (NaN, _) | (_, NaN) => NaN,
+            (Finite(_), Infinity | NegativeInfinity) => Finite(BigDecimal::zero()),
+            (Infinity | NegativeInfinity, Infinity | NegativeInfinity) => NaN,
+            (Infinity, Finite(divisor)) if divisor.is_negative() => NegativeInfinity,
+            (NegativeInfinity, Finite(divisor)) if divisor.is_negative() => Infinity,
+            (Infinity | NegativeInfinity, Finite(_)) => self.clone(),
+            (Finite(_), Finite(divisor)) if divisor.is_zero() => NaN,
+            (Finite(dividend), Finite(divisor)) => {
+                let scale = Self::div_scale(dividend, divisor);
+                let (dividend, dividend_scale) = dividend.as_bigint_and_exponent();
+                let (divisor, divisor_scale) = divisor.as_bigint_and_exponent();
+
+                // dividend / divisor * 10^scale, in integers.
+                let shift = divisor_scale + scale - dividend_scale;
+                let power = BigInt::from(10).pow(shift.unsigned_abs() as u32);
+                let (dividend, divisor) = if shift >= 0 {
+                    (dividend * power, divisor)
+                } else {
+                    (dividend, divisor * power)
+                };
+
+                let mut quotient = &dividend / &divisor;
+                let remainder = &dividend % &divisor;
+                if remainder.abs() * 2 >= divisor.abs() {
+                    if (dividend.sign() == Sign::Minus) != (divisor.sign() == Sign::Minus) {
+                        quotient -= 1;
+                    } else {
+                        quotient += 1;
+                    }
+                }
+
+                Finite(BigDecimal::new(quotient, scale))
+            }
+        }
+    }
+
+    /// Square root, with the same number of digits after the decimal point.
+    pub fn sqrt(&self) -> Self {
+        match self {
+            Self::Finite(value) if value.is_negative() => Self::NaN,
+            Self::Finite(value) => {
+                let scale = Self::scale(value);
+                // Extra digits, so it's rounded once.
+                let precision = value.digits() + scale as u64 + 4;
+                let context = Context::default()
+                    .with_prec(precision)
+                    .unwrap_or_default()
+                    .with_rounding_mode(RoundingMode::Down);
+                match value.sqrt_with_context(&context) {
+                    Some(root) => Self::Finite(root.with_scale_round(scale, RoundingMode::HalfUp)),
+                    None => Self::NaN,
+                }
+            }
+            Self::NegativeInfinity => Self::NaN,
+            special => special.clone(),
+        }
+    }
+
+    /// Closest float.
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Self::NegativeInfinity => f64::NEG_INFINITY,
+            Self::Finite(value) => value.to_f64().unwrap_or(f64::NAN),
+            Self::Infinity => f64::INFINITY,
+            Self::NaN => f64::NAN,
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, Error> {
+        let text = text.trim();
+        Ok(if text.eq_ignore_ascii_case("nan") {
+            Self::NaN
+        } else if text.eq_ignore_ascii_case("infinity") {
+            Self::Infinity
+        } else if text.eq_ignore_ascii_case("-infinity") {
+            Self::NegativeInfinity
+        } else {
+            Self::Finite(BigDecimal::from_str(text)?)
+        })
+    }
+
+    /// Binary format: the number of base 10000 digits, the weight of the first one,
+    /// the sign, the number of decimal digits after the decimal point, and the digits.
+    fn decode_binary(mut bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < 8 {
+            return Err(Error::WrongSizeBinary(bytes.len()));
+        }
+
+        let ndigits = bytes.get_i16();
+        let weight = bytes.get_i16() as i64;
+        let sign = bytes.get_u16();
+        let scale = bytes.get_u16() as i64;
+
+        match sign {
+            NAN => return Ok(Self::NaN),
+            INFINITY => return Ok(Self::Infinity),
+            NEGATIVE_INFINITY => return Ok(Self::NegativeInfinity),
+            POSITIVE | NEGATIVE => (),
+            _ => return Err(Error::UnexpectedPayload),
+        }
+
+        if ndigits < 0 || bytes.len() != ndigits as usize * 2 {
+            return Err(Error::WrongSizeBinary(bytes.len()));
+        }
+
+        let mut mantissa = BigInt::zero();
+        for _ in 0..ndigits {
+            mantissa = mantissa * 10000 + bytes.get_i16();
+        }
+        if sign == NEGATIVE {
+            mantissa = -mantissa;
+        }
+
+        // The last digit is worth 10000^(weight - ndigits + 1).
+        let value = BigDecimal::new(mantissa, (ndigits as i64 - 1 - weight) * 4);
+
+        Ok(Self::Finite(value.with_scale(scale)))
+    }
+
+    fn encode_binary(&self) -> Bytes {
+        let (sign, weight, scale, digits) = match self {
+            Self::NaN => (NAN, 0, 0, vec![]),
+            Self::Infinity => (INFINITY, 0, 0, vec![]),
+            Self::NegativeInfinity => (NEGATIVE_INFINITY, 0, 0, vec![]),
+            Self::Finite(value) => {
+                let scale = Self::scale(value);
+                let (mantissa, _) = value.with_scale(scale).into_bigint_and_exponent();
+                let sign = if mantissa.is_negative() {
+                    NEGATIVE
+                } else {
+                    POSITIVE
+                };
+
+                // Whole base 10000 digits on both sides of the decimal point.
+                let fraction = (scale as usize).div_ceil(4) * 4;
+                let mut text = mantissa.magnitude().to_string();
+                text.extend(repeat_n('0', fraction - scale as usize));
+                let integer = text.len().saturating_sub(fraction).div_ceil(4) * 4;
+                let text = "0".repeat(integer + fraction - text.len()) + &text;
+
+                let mut digits = text
+                    .as_bytes()
+                    .chunks(4)
+                    .map(|chunk| {
+                        chunk
+                            .iter()
+                            .fold(0i16, |digit, c| digit * 10 + (c - b'0') as i16)
+                    })
+                    .collect::<Vec<_>>();
+                let mut weight = (integer / 4) as i64 - 1;
+
+                let leading = digits.iter().take_while(|digit| **digit == 0).count();
+                digits.drain(..leading);
+                weight -= leading as i64;
+                while digits.last() == Some(&0) {
+                    digits.pop();
+                }
+                if digits.is_empty() {
+                    weight = 0;
+                }
+
+                (sign, weight, scale, digits)
+            }
+        };
+
+        let mut bytes = BytesMut::with_capacity(8 + digits.len() * 2);
+        bytes.put_i16(digits.len() as i16);
+        bytes.put_i16(weight as i16);
+        bytes.put_u16(sign);
+        bytes.put_u16(scale as u16);
+        for digit in digits {
+            bytes.put_i16(digit);
+        }
+
+        bytes.freeze()
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NegativeInfinity => write!(f, "-Infinity"),
+            Self::Infinity => write!(f, "Infinity"),
+            Self::NaN => write!(f, "NaN"),
+            Self::Finite(value) => {
+                let scale = Self::scale(value);
+                let (mantissa, _) = value.with_scale(scale).into_bigint_and_exponent();
+                let digits = mantissa.magnitude().to_string();
+                let digits =
+                    "0".repeat((scale as usize + 1).saturating_sub(digits.len())) + &digits;
+                let (integer, fraction) = digits.split_at(digits.len() - scale as usize);
+
+                if mantissa.is_negative() {
+                    write!(f, "-")?;
+                }
+                write!(f, "{}", integer)?;
+                if !fraction.is_empty() {
+                    write!(f, ".{}", fraction)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        use Decimal::*;
+
+        match (self, rhs) {
+            (Finite(a), Finite(b)) => Finite(a + b),
+            (NaN, _) | (_, NaN) => NaN,
+            (Infinity, NegativeInfinity) | (NegativeInfinity, Infinity) => NaN,
+            (Infinity, _) | (_, Infinity) => Infinity,
+            _ => NegativeInfinity,
+        }
+    }
+}
+
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Self::NegativeInfinity => Self::Infinity,
+            Self::Finite(value) => Self::Finite(-value),
+            Self::Infinity => Self::NegativeInfinity,
+            Self::NaN => Self::NaN,
+        }
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Self::Finite(BigDecimal::from(value))
+    }
+}
+
+impl From<BigDecimal> for Decimal {
+    fn from(value: BigDecimal) -> Self {
+        Self::Finite(value)
+    }
+}
+
+impl FromDataType for Decimal {
+    fn decode(bytes: &[u8], encoding: Format) -> Result<Self, Error> {
+        match encoding {
+            Format::Text => Self::parse(&String::decode(bytes, encoding)?),
+            Format::Binary => Self::decode_binary(bytes),
+        }
+    }
+
+    fn encode(&self, encoding: Format) -> Result<Bytes, Error> {
+        match encoding {
+            Format::Text => Ok(Bytes::from(self.to_string())),
+            Format::Binary => Ok(self.encode_binary()),
+        }
+    }
+}
+
+impl ToDataRowColumn for Decimal {
+    fn to_data_row_column(&self) -> Data {
+        Bytes::from(self.to_string()).into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decimal(text: &str) -> Decimal {
+        Decimal::decode(text.as_bytes(), Format::Text).unwrap()
+    }
+
+    #[test]
+    fn test_decimal_text() {
+        for text in [
+            "0",
+            "0.00",
+            "-1.50",
+            "123456789012345678901234567890.123456789",
+            "0.0001",
+            "-0.5",
+            "NaN",
+            "Infinity",
+            "-Infinity",
+        ] {
+            assert_eq!(decimal(text).to_string(), text);
+        }
+
+        assert_eq!(decimal("1.0"), decimal("1"));
+        assert!(decimal("-Infinity") < decimal("-1"));
+        assert!(decimal("NaN") > decimal("Infinity"));
+        assert!(Decimal::decode(b"abc", Format::Text).is_err());
+    }
+
+    #[test]
+    fn test_decimal_binary() {
+        // SELECT 1234.5::numeric(10, 2), in binary.
+        let bytes = [0, 2, 0, 0, 0, 0, 0, 2, 0x04, 0xd2, 0x13, 0x88];
+        let value = Decimal::decode(&bytes, Format::Binary).unwrap();
+        assert_eq!(value.to_string(), "1234.50");
+        assert_eq!(&value.encode(Format::Binary).unwrap()[..], &bytes);
+
+        for text in [
+            "0",
+            "0.000",
+            "-1",
+            "10000",
+            "0.00012",
+            "-98765432109876543210.0123456789",
+            "NaN",
+            "-Infinity",
+        ] {
+            let value = decimal(text);
+            let binary = value.encode(Format::Binary).unwrap();
+            let decoded = Decimal::decode(&binary, Format::Binary).unwrap();
+            assert_eq!(decoded.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn test_decimal_div() {
+        // Same as Postgres.
+        for (dividend, divisor, quotient) in [
+            ("3", "2", "1.5000000000000000"),
+            ("30", "2", "15.0000000000000000"),
+            ("100000", "1", "100000.000000000000"),
+            ("2", "3", "0.66666666666666666667"),
+            ("-2", "3", "-0.66666666666666666667"),
+            ("10.125", "4", "2.5312500000000000"),
+            ("99999999999999999999999", "3", "33333333333333333333333"),
+            ("1", "0", "NaN"),
+        ] {
+            assert_eq!(
+                decimal(dividend).div(&decimal(divisor)).to_string(),
+                quotient
+            );
+        }
+
+        assert_eq!(decimal("2.0000").sqrt().to_string(), "1.4142");
+        assert_eq!(
+            decimal("Infinity").div(&decimal("-2")),
+            Decimal::NegativeInfinity
+        );
+        assert_eq!(decimal("1.5") + decimal("2.25"), decimal("3.75"));
+        assert_eq!(decimal("Infinity") + decimal("-Infinity"), Decimal::NaN);
+    }
+}
@@ -4,7 +4,9 @@ use super::{bind::Format, data_row::Data, Error, ToDataRowColumn};
 use ::uuid::Uuid;
 use bytes::Bytes;
 
+pub mod array;
 pub mod bigint;
+pub mod decimal;
 pub mod integer;
 pub mod interval;
 pub mod numeric;
@@ -14,6 +16,8 @@ pub mod timestamptz;
 pub mod uuid;
 pub mod vector;
 
+pub use array::{Array, TextArray};
+pub use decimal::Decimal;
 pub use interval::Interval;
 pub use numeric::Numeric;
 pub use timestamp::Timestamp;
@@ -43,8 +47,10 @@ pub enum Datum {
     TimestampTz(TimestampTz),
     /// UUID.
     Uuid(Uuid),
-    /// NUMERIC, REAL, DOUBLE PRECISION.
+    /// REAL, DOUBLE PRECISION.
     Numeric(Numeric),
+    /// NUMERIC.
+    Decimal(Decimal),
     /// Vector
     Vector(Vector),
     /// We don't know.
@@ -67,6 +73,7 @@ impl ToDataRowColumn for Datum {
             TimestampTz(tz) => tz.to_data_row_column(),
             Uuid(uuid) => uuid.to_data_row_column(),
             Numeric(num) => num.to_data_row_column(),
+            Decimal(decimal) => decimal.to_data_row_column(),
             Vector(vector) => vector.to_data_row_column(),
             Unknown(bytes) => bytes.clone().into(),
             Null => Data::null(),
@@ -86,6 +93,7 @@ impl Add for Datum {
             (SmallInt(a), SmallInt(b)) => SmallInt(a + b),
             (Interval(a), Interval(b)) => Interval(a + b),
             (Numeric(a), Numeric(b)) => Numeric(a + b),
+            (Decimal(a), Decimal(b)) => Decimal(a + b),
             (Datum::Null, b) => b,
             (a, Datum::Null) => a,
             _ => Datum::Null, // Might be good to raise an error.
@@ -104,7 +112,8 @@ impl Datum {
             DataType::Integer => Ok(Datum::Integer(i32::decode(bytes, encoding)?)),
             DataType::Text => Ok(Datum::Text(String::decode(bytes, encoding)?)),
             DataType::Interval => Ok(Datum::Interval(Interval::decode(bytes, encoding)?)),
-            DataType::Numeric | DataType::DoublePrecision | DataType::Real => {
+            DataType::Numeric => Ok(Datum::Decimal(Decimal::decode(bytes, encoding)?)),
+            DataType::DoublePrecision | DataType::Real => {
                 Ok(Datum::Numeric(Numeric::decode(bytes, encoding)?))
             }
             DataType::Uuid => Ok(Datum::Uuid(Uuid::decode(bytes, encoding)?)),
@@ -119,12 +128,50 @@ impl Datum {
         matches!(self, Datum::Null)
     }
 
+    /// Numeric value, if it's a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Datum::Bigint(value) => Some(*value as f64),
+            Datum::Integer(value) => Some(*value as f64),
+            Datum::SmallInt(value) => Some(*value as f64),
+            Datum::Numeric(value) => Some(**value),
+            Datum::Decimal(value) => Some(value.to_f64()),
+            _ => None,
+        }
+    }
+
+    /// Exact value, if it's an integer or a NUMERIC.
+    pub fn as_decimal(&self) -> Option<Decimal> {
+        match self {
+            Datum::Bigint(value) => Some(Decimal::from(*value)),
+            Datum::Integer(value) => Some(Decimal::from(*value as i64)),
+            Datum::SmallInt(value) => Some(Decimal::from(*value as i64)),
+            Datum::Decimal(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Add two values, like `+`, but `None` if integers overflow.
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        use Datum::*;
+
+        match (self, rhs) {
+            (Bigint(a), Bigint(b)) => a.checked_add(b).map(Bigint),
+            (Integer(a), Integer(b)) => a.checked_add(b).map(Integer),
+            (SmallInt(a), SmallInt(b)) => a.checked_add(b).map(SmallInt),
+            (a, b) => Some(a + b),
+        }
+    }
+
     pub fn encode(&self, format: Format) -> Result<Bytes, Error> {
         match self {
             Datum::Bigint(i) => i.encode(format),
             Datum::Integer(i) => i.encode(format),
             Datum::Uuid(uuid) => uuid.encode(format),
             Datum::Text(s) => s.encode(format),
+            Datum::Numeric(n) => n.encode(format),
+            Datum::Decimal(d) => d.encode(format),
+            Datum::Unknown(bytes) => Ok(bytes.clone()),
             _ => Err(Error::UnexpectedPayload),
         }
     }
@@ -149,3 +196,25 @@ pub enum DataType {
     Uuid,
     Vector,
 }
+
+impl DataType {
+    /// Data type of a built-in Postgres type.
+    pub fn from_oid(oid: i32) -> Self {
+        match oid {
+            16 => DataType::Bool,
+            20 => DataType::Bigint,
+            23 => DataType::Integer,
+            21 => DataType::SmallInt,
+            25 => DataType::Text,
+            700 => DataType::Real,
+            701 => DataType::DoublePrecision,
+            1043 => DataType::Text,
+            1114 => DataType::Timestamp,
+            1184 => DataType::TimestampTz,
+            1186 => DataType::Interval,
+            1700 => DataType::Numeric,
+            2950 => DataType::Uuid,
+            _ => DataType::Other(oid),
+        }
+    }
+}
@@ -74,6 +74,65 @@ impl ErrorResponse {
         }
     }
 
+    /// Client terminated with `KILL`.
+    pub fn terminated() -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "57P01".into(),
+            message: "terminating connection due to administrator command".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Database was disabled with `DISABLE`.
+    pub fn database_disabled(database: &str) -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "55000".into(),
+            message: format!(
+                "database \"{}\" is not currently accepting connections",
+                database
+            ),
+            ..Default::default()
+        }
+    }
+
+    /// pg_dump or pg_restore didn't pick a shard of a sharded database.
+    pub fn dump_shard(program: &str, shards: usize) -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "0A000".into(),
+            message: format!(
+                "{} through a sharded database must target one of its {} shards",
+                program, shards
+            ),
+            detail: Some(format!(
+                "run {} once per shard with PGOPTIONS=\"-c pgdog.shard=<shard>\"",
+                program
+            )),
+            ..Default::default()
+        }
+    }
+
+    /// Client was idle for too long.
+    pub fn client_idle_timeout() -> ErrorResponse {
+        ErrorResponse {
+            severity: "FATAL".into(),
+            code: "57P05".into(),
+            message: "terminating connection due to idle timeout".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Query can't be executed across shards.
+    pub fn feature_not_supported(err: &str) -> ErrorResponse {
+        ErrorResponse {
+            code: "0A000".into(),
+            message: err.into(),
+            ..Default::default()
+        }
+    }
+
     pub fn syntax(err: &str) -> ErrorResponse {
         Self {
             severity: "ERROR".into(),
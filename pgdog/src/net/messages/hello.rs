@@ -11,7 +11,19 @@ use tracing::debug;
 
 use std::{marker::Unpin, ops::Deref};
 
-use super::{super::Parameter, FromBytes, Payload, Protocol, ToBytes};
+use super::{super::Parameter, FromBytes, NegotiateProtocolVersion, Payload, Protocol, ToBytes};
+
+/// Protocol version 3.0, used with servers.
+pub const PROTOCOL_VERSION: i32 = 196608;
+
+/// Newest minor version of protocol 3 we speak with clients.
+///
+/// 3.2 only changes the length of the cancel key, which is up to us, so
+/// clients get the 4 bytes they would get with 3.0.
+pub const PROTOCOL_MINOR_LATEST: i32 = 2;
+
+/// Prefix of protocol options in the startup message.
+const PROTOCOL_OPTION: &str = "_pq_.";
 
 /// First message a client sends to the server
 /// and a server expects from a client.
@@ -22,7 +34,13 @@ pub enum Startup {
     /// SSLRequest (F)
     Ssl,
     /// StartupMessage (F)
-    Startup { params: Parameters },
+    Startup {
+        params: Parameters,
+        /// Protocol version requested by the client.
+        version: i32,
+        /// Protocol options (`_pq_.*`) requested by the client.
+        options: Vec<String>,
+    },
     /// CancelRequet (F)
     Cancel { pid: i32, secret: i32 },
 }
@@ -38,9 +56,10 @@ impl Startup {
         match code {
             // SSLRequest (F)
             80877103 => Ok(Startup::Ssl),
-            // StartupMessage (F)
-            196608 => {
+            // StartupMessage (F), any protocol 3 version.
+            version if version >> 16 == 3 => {
                 let mut params = Parameters::default();
+                let mut options = vec![];
                 loop {
                     let name = c_string(stream).await?;
 
@@ -67,12 +86,18 @@ impl Startup {
                                 }
                             }
                         }
+                    } else if name.starts_with(PROTOCOL_OPTION) {
+                        options.push(name);
                     } else {
                         params.insert(name, value);
                     }
                 }
 
-                Ok(Startup::Startup { params })
+                Ok(Startup::Startup {
+                    params,
+                    version,
+                    options,
+                })
             }
             // CancelRequest (F)
             80877102 => {
@@ -92,7 +117,26 @@ impl Startup {
     pub fn parameter(&self, name: &str) -> Option<&str> {
         match self {
             Startup::Ssl | Startup::Cancel { .. } => None,
-            Startup::Startup { params } => params.get(name).and_then(|s| s.as_str()),
+            Startup::Startup { params, .. } => params.get(name).and_then(|s| s.as_str()),
+        }
+    }
+
+    /// NegotiateProtocolVersion to send the client before authentication,
+    /// if it asked for a version newer than ours or for protocol options.
+    ///
+    /// Server connections are shared by clients, so none of the protocol
+    /// options are supported, and they aren't passed to the server.
+    pub fn negotiate(&self) -> Option<NegotiateProtocolVersion> {
+        match self {
+            Startup::Startup {
+                version, options, ..
+            } if version & 0xffff > PROTOCOL_MINOR_LATEST || !options.is_empty() => {
+                Some(NegotiateProtocolVersion {
+                    minor: (version & 0xffff).min(PROTOCOL_MINOR_LATEST),
+                    options: options.clone(),
+                })
+            }
+            _ => None,
         }
     }
 
@@ -110,6 +154,8 @@ impl Startup {
         ]);
         Self::Startup {
             params: params.into(),
+            version: PROTOCOL_VERSION,
+            options: vec![],
         }
     }
 
@@ -141,7 +187,11 @@ impl super::ToBytes for Startup {
                 Ok(payload.freeze())
             }
 
-            Startup::Startup { params } => {
+            Startup::Startup {
+                params,
+                version,
+                options,
+            } => {
                 let mut params_buf = BytesMut::new();
 
                 for (name, value) in params.deref() {
@@ -154,9 +204,15 @@ impl super::ToBytes for Startup {
                     }
                 }
 
+                for option in options {
+                    params_buf.put_slice(option.as_bytes());
+                    params_buf.put_u8(0);
+                    params_buf.put_u8(0);
+                }
+
                 let mut payload = Payload::new();
 
-                payload.put_i32(196608);
+                payload.put_i32(*version);
                 payload.put(params_buf);
                 payload.put_u8(0); // Terminating null character.
 
@@ -245,10 +301,57 @@ mod test {
                 },
             ]
             .into(),
+            version: PROTOCOL_VERSION,
+            options: vec![],
         };
 
         let bytes = startup.to_bytes().unwrap();
 
         assert_eq!(bytes.clone().get_i32(), 41);
+        assert!(startup.negotiate().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_startup_negotiate() {
+        for (version, options, negotiate) in [
+            (196610, vec![], None),
+            (
+                196611,
+                vec![],
+                Some(NegotiateProtocolVersion {
+                    minor: 2,
+                    options: vec![],
+                }),
+            ),
+            (
+                196608,
+                vec!["_pq_.compression".to_string()],
+                Some(NegotiateProtocolVersion {
+                    minor: 0,
+                    options: vec!["_pq_.compression".into()],
+                }),
+            ),
+        ] {
+            let startup = Startup::Startup {
+                params: vec![Parameter {
+                    name: "user".into(),
+                    value: "postgres".into(),
+                }]
+                .into(),
+                version,
+                options,
+            };
+            let bytes = startup.to_bytes().unwrap();
+
+            let startup = Startup::from_stream(&mut &bytes[..]).await.unwrap();
+            assert_eq!(startup.parameter("user"), Some("postgres"));
+            assert!(startup.parameter("_pq_.compression").is_none());
+            assert_eq!(startup.negotiate(), negotiate);
+        }
+
+        let mut bytes = BytesMut::new();
+        bytes.put_i32(8);
+        bytes.put_i32(131072);
+        assert!(Startup::from_stream(&mut &bytes[..]).await.is_err());
     }
 }
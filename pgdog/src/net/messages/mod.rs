@@ -16,6 +16,7 @@ pub mod error_response;
 pub mod execute;
 pub mod flush;
 pub mod hello;
+pub mod negotiate_protocol_version;
 pub mod notice_response;
 pub mod parameter_description;
 pub mod parameter_status;
@@ -47,6 +48,7 @@ pub use error_response::ErrorResponse;
 pub use execute::Execute;
 pub use flush::Flush;
 pub use hello::Startup;
+pub use negotiate_protocol_version::NegotiateProtocolVersion;
 pub use notice_response::NoticeResponse;
 pub use parameter_description::ParameterDescription;
 pub use parameter_status::ParameterStatus;
@@ -227,6 +229,11 @@ impl Message {
     pub fn in_transaction(&self) -> bool {
         self.code() == 'Z' && matches!(self.payload[5] as char, 'T' | 'E')
     }
+
+    /// ReadyForQuery (B) for a failed transaction.
+    pub fn transaction_error(&self) -> bool {
+        self.code() == 'Z' && self.payload[5] as char == 'E'
+    }
 }
 
 /// Check that the message we received is what we expected.
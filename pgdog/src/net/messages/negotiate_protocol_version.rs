@@ -0,0 +1,69 @@
+//! NegotiateProtocolVersion (B) message.
+
+use crate::net::c_string_buf;
+use crate::net::messages::code;
+use crate::net::messages::prelude::*;
+
+/// NegotiateProtocolVersion (B)
+///
+/// Sent instead of an error when the client asks for a newer protocol
+/// version or for protocol options (`_pq_.*`) we don't support.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NegotiateProtocolVersion {
+    /// Newest minor protocol version we support.
+    pub minor: i32,
+    /// Protocol options we don't support.
+    pub options: Vec<String>,
+}
+
+impl ToBytes for NegotiateProtocolVersion {
+    fn to_bytes(&self) -> Result<bytes::Bytes, crate::net::Error> {
+        let mut payload = Payload::named(self.code());
+
+        payload.put_i32(self.minor);
+        payload.put_i32(self.options.len() as i32);
+        for option in &self.options {
+            payload.put_string(option);
+        }
+
+        Ok(payload.freeze())
+    }
+}
+
+impl FromBytes for NegotiateProtocolVersion {
+    fn from_bytes(mut bytes: Bytes) -> Result<Self, Error> {
+        code!(bytes, 'v');
+
+        let _len = bytes.get_i32();
+        let minor = bytes.get_i32();
+        let options = (0..bytes.get_i32())
+            .map(|_| c_string_buf(&mut bytes))
+            .collect();
+
+        Ok(Self { minor, options })
+    }
+}
+
+impl Protocol for NegotiateProtocolVersion {
+    fn code(&self) -> char {
+        'v'
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_protocol_version() {
+        let negotiate = NegotiateProtocolVersion {
+            minor: 2,
+            options: vec!["_pq_.compression".into()],
+        };
+        let bytes = negotiate.to_bytes().unwrap();
+        assert_eq!(
+            NegotiateProtocolVersion::from_bytes(bytes).unwrap(),
+            negotiate
+        );
+    }
+}
@@ -39,7 +39,6 @@ impl Parse {
     }
 
     /// New anonymous prepared statement.
-    #[cfg(test)]
     pub fn new_anonymous(query: &str) -> Self {
         Self {
             name: Bytes::from("\0"),
@@ -86,6 +85,14 @@ impl Parse {
         parse
     }
 
+    /// Same statement, with a different query.
+    pub fn with_query(&self, query: &str) -> Parse {
+        let mut parse = self.clone();
+        parse.query = Bytes::from(query.to_owned() + "\0");
+        parse.original = None;
+        parse
+    }
+
     pub fn data_types(&self) -> DataTypesIter<'_> {
         DataTypesIter {
             data_types: &self.data_types,
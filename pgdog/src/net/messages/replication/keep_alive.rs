@@ -1,3 +1,5 @@
+use crate::net::messages::{CopyData, Message};
+
 use super::super::code;
 use super::super::prelude::*;
 
@@ -18,3 +20,28 @@ impl FromBytes for KeepAlive {
         })
     }
 }
+
+impl ToBytes for KeepAlive {
+    fn to_bytes(&self) -> Result<Bytes, Error> {
+        let mut payload = bytes::BytesMut::new();
+        payload.put_u8(self.code() as u8);
+        payload.put_i64(self.wal_end);
+        payload.put_i64(self.system_clock);
+        payload.put_u8(self.reply);
+
+        Ok(payload.freeze())
+    }
+}
+
+impl Protocol for KeepAlive {
+    fn code(&self) -> char {
+        'k'
+    }
+}
+
+impl KeepAlive {
+    /// Wrap in CopyData, as sent by the server.
+    pub fn to_message(&self) -> Result<Message, Error> {
+        Ok(Message::new(CopyData::bytes(self.to_bytes()?).to_bytes()?))
+    }
+}
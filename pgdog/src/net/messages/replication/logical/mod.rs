@@ -3,6 +3,7 @@ pub mod commit;
 pub mod delete;
 pub mod insert;
 pub mod relation;
+pub mod stream_commit;
 pub mod string;
 pub mod truncate;
 pub mod tuple_data;
@@ -0,0 +1,47 @@
+use bytes::BytesMut;
+
+use super::super::super::code;
+use super::super::super::prelude::*;
+
+/// Commit of a transaction streamed while it was in progress.
+#[derive(Debug, Clone)]
+pub struct StreamCommit {
+    pub xid: i32,
+    pub flags: i8,
+    pub commit_lsn: i64,
+    pub end_lsn: i64,
+    pub commit_timestamp: i64,
+}
+
+impl FromBytes for StreamCommit {
+    fn from_bytes(mut bytes: Bytes) -> Result<Self, Error> {
+        code!(bytes, 'c');
+        Ok(Self {
+            xid: bytes.get_i32(),
+            flags: bytes.get_i8(),
+            commit_lsn: bytes.get_i64(),
+            end_lsn: bytes.get_i64(),
+            commit_timestamp: bytes.get_i64(),
+        })
+    }
+}
+
+impl ToBytes for StreamCommit {
+    fn to_bytes(&self) -> Result<Bytes, Error> {
+        let mut bytes = BytesMut::new();
+        bytes.put_u8(self.code() as u8);
+        bytes.put_i32(self.xid);
+        bytes.put_i8(self.flags);
+        bytes.put_i64(self.commit_lsn);
+        bytes.put_i64(self.end_lsn);
+        bytes.put_i64(self.commit_timestamp);
+
+        Ok(bytes.freeze())
+    }
+}
+
+impl Protocol for StreamCommit {
+    fn code(&self) -> char {
+        'c'
+    }
+}
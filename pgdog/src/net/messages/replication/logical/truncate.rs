@@ -5,16 +5,34 @@ use super::super::super::prelude::*;
 pub struct Truncate {
     pub num_relations: i32,
     pub options: i8,
-    pub oid: i32,
+    /// Truncated tables.
+    pub oids: Vec<i32>,
+}
+
+impl Truncate {
+    /// `TRUNCATE ... CASCADE`.
+    pub fn cascade(&self) -> bool {
+        self.options & 1 != 0
+    }
+
+    /// `TRUNCATE ... RESTART IDENTITY`.
+    pub fn restart_identity(&self) -> bool {
+        self.options & 2 != 0
+    }
 }
 
 impl FromBytes for Truncate {
     fn from_bytes(mut bytes: Bytes) -> Result<Self, Error> {
         code!(bytes, 'T');
+        let num_relations = bytes.get_i32();
+        let options = bytes.get_i8();
+        let oids = (0..num_relations)
+            .map_while(|_| (bytes.remaining() >= 4).then(|| bytes.get_i32()))
+            .collect();
         Ok(Self {
-            num_relations: bytes.get_i32(),
-            options: bytes.get_i8(),
-            oid: bytes.get_i32(),
+            num_relations,
+            options,
+            oids,
         })
     }
 }
@@ -11,6 +11,7 @@ pub use logical::commit::Commit;
 pub use logical::delete::Delete;
 pub use logical::insert::Insert;
 pub use logical::relation::Relation;
+pub use logical::stream_commit::StreamCommit;
 pub use logical::truncate::Truncate;
 pub use logical::tuple_data::TupleData;
 pub use logical::update::Update;
@@ -23,3 +23,23 @@ impl FromBytes for StatusUpdate {
         })
     }
 }
+
+impl ToBytes for StatusUpdate {
+    fn to_bytes(&self) -> Result<Bytes, Error> {
+        let mut payload = bytes::BytesMut::new();
+        payload.put_u8(self.code() as u8);
+        payload.put_i64(self.last_written);
+        payload.put_i64(self.last_flushed);
+        payload.put_i64(self.last_applied);
+        payload.put_i64(self.system_clock);
+        payload.put_u8(self.reply);
+
+        Ok(payload.freeze())
+    }
+}
+
+impl Protocol for StatusUpdate {
+    fn code(&self) -> char {
+        'r'
+    }
+}
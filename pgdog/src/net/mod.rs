@@ -1,6 +1,7 @@
 pub mod decoder;
 pub mod discovery;
 pub mod error;
+pub mod http;
 pub mod messages;
 pub mod parameter;
 pub mod stream;
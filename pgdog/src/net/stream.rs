@@ -2,7 +2,10 @@
 //! connections the same across the code.
 use bytes::{BufMut, BytesMut};
 use pin_project::pin_project;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream, ReadBuf};
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream,
+    ReadBuf,
+};
 use tokio::net::TcpStream;
 use tracing::trace;
 
@@ -36,6 +39,27 @@ impl AsyncRead for Stream {
     }
 }
 
+impl AsyncBufRead for Stream {
+    fn poll_fill_buf(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> std::task::Poll<std::io::Result<&[u8]>> {
+        let project = self.project();
+        match project {
+            StreamProjection::Plain(stream) => stream.poll_fill_buf(cx),
+            StreamProjection::Tls(stream) => stream.poll_fill_buf(cx),
+        }
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let project = self.project();
+        match project {
+            StreamProjection::Plain(stream) => stream.consume(amt),
+            StreamProjection::Tls(stream) => stream.consume(amt),
+        }
+    }
+}
+
 impl AsyncWrite for Stream {
     fn poll_write(
         self: Pin<&mut Self>,
@@ -182,6 +206,18 @@ impl Stream {
         self.read_buf(&mut buf).await
     }
 
+    /// Wait for data to be available, without consuming it.
+    ///
+    /// Unlike [`Stream::read`], this is cancel-safe, so it can be used
+    /// to pick which of several streams to read from next.
+    pub async fn ready(&mut self) -> Result<(), crate::net::Error> {
+        if self.fill_buf().await?.is_empty() {
+            return Err(crate::net::Error::Eof);
+        }
+
+        Ok(())
+    }
+
     /// Read data into a buffer, avoiding unnecessary allocations.
     pub async fn read_buf(&mut self, bytes: &mut BytesMut) -> Result<Message, crate::net::Error> {
         let code = self.read_u8().await?;
@@ -1,54 +1,54 @@
 //! TLS configuration.
 
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
-use once_cell::sync::OnceCell;
+use arc_swap::ArcSwapOption;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
 use tokio_rustls::rustls::{
     self,
-    client::danger::{ServerCertVerified, ServerCertVerifier},
+    client::{
+        danger::{ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
     pki_types::pem::PemObject,
     server::danger::ClientCertVerifier,
-    ClientConfig,
+    CertificateError, ClientConfig,
 };
 use tokio_rustls::{TlsAcceptor, TlsConnector};
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::config::config;
+use crate::config::{config, ServerTlsMode};
 
 use super::Error;
 
-static ACCEPTOR: OnceCell<Option<TlsAcceptor>> = OnceCell::new();
+static ACCEPTOR: Lazy<ArcSwapOption<TlsAcceptor>> = Lazy::new(ArcSwapOption::empty);
 static CONNECTOR: OnceCell<TlsConnector> = OnceCell::new();
+static VERIFYING_CONNECTORS: Lazy<Mutex<Connectors>> = Lazy::new(|| Mutex::new(HashMap::new()));
 
-/// Get preloaded TLS acceptor.
-pub fn acceptor() -> Option<&'static TlsAcceptor> {
-    if let Some(Some(acceptor)) = ACCEPTOR.get() {
-        return Some(acceptor);
-    }
+/// Connectors verifying server certificates, by TLS mode and CA.
+type Connectors = HashMap<(ServerTlsMode, Option<PathBuf>), TlsConnector>;
 
-    None
+/// Get preloaded TLS acceptor.
+pub fn acceptor() -> Option<TlsAcceptor> {
+    ACCEPTOR
+        .load_full()
+        .map(|acceptor| acceptor.as_ref().clone())
 }
 
-/// Create a new TLS acceptor from the cert and key.
-///
-/// This is not atomic, so call it on startup only.
+/// Create a new TLS acceptor from the cert and key,
+/// if they can be read.
 pub fn load_acceptor(cert: &PathBuf, key: &PathBuf) -> Result<Option<TlsAcceptor>, Error> {
-    if let Some(acceptor) = ACCEPTOR.get() {
-        return Ok(acceptor.clone());
-    }
-
     let pem = if let Ok(pem) = CertificateDer::from_pem_file(cert) {
         pem
     } else {
-        let _ = ACCEPTOR.set(None);
         return Ok(None);
     };
 
     let key = if let Ok(key) = PrivateKeyDer::from_pem_file(key) {
         key
     } else {
-        let _ = ACCEPTOR.set(None);
         return Ok(None);
     };
 
@@ -58,12 +58,6 @@ pub fn load_acceptor(cert: &PathBuf, key: &PathBuf) -> Result<Option<TlsAcceptor
 
     let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
 
-    info!("🔑 TLS on");
-
-    // A bit of a race, but it's not a big deal unless this is called
-    // with different certificate/secret key.
-    let _ = ACCEPTOR.set(Some(acceptor.clone()));
-
     Ok(Some(acceptor))
 }
 
@@ -73,10 +67,7 @@ pub fn connector() -> Result<TlsConnector, Error> {
         return Ok(connector.clone());
     }
 
-    let mut roots = rustls::RootCertStore::empty();
-    for cert in rustls_native_certs::load_native_certs().expect("load native certs") {
-        roots.add(cert)?;
-    }
+    let roots = native_roots()?;
 
     let verifier = rustls::server::WebPkiClientVerifier::builder(roots.clone().into())
         .build()
@@ -98,19 +89,189 @@ pub fn connector() -> Result<TlsConnector, Error> {
     Ok(connector)
 }
 
-/// Preload TLS at startup.
+/// Get the TLS connector for a server, verifying its certificate
+/// if required by the TLS mode.
+pub fn server_connector(mode: ServerTlsMode, ca: Option<&PathBuf>) -> Result<TlsConnector, Error> {
+    if !mode.verify() {
+        return connector();
+    }
+
+    let key = (mode, ca.cloned());
+    if let Some(connector) = VERIFYING_CONNECTORS.lock().get(&key) {
+        return Ok(connector.clone());
+    }
+
+    let connector = verifying_connector(mode, ca)?;
+    VERIFYING_CONNECTORS.lock().insert(key, connector.clone());
+
+    Ok(connector)
+}
+
+/// Certificates trusted by the system. Ones that can't be loaded are skipped.
+fn native_roots() -> Result<rustls::RootCertStore, Error> {
+    let native = rustls_native_certs::load_native_certs();
+    for error in &native.errors {
+        warn!("skipping native certificates: {}", error);
+    }
+    if native.certs.is_empty() && !native.errors.is_empty() {
+        let errors = native
+            .errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        return Err(Error::NativeCerts(errors.join(", ")));
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    let (_, ignored) = roots.add_parsable_certificates(native.certs);
+    if ignored > 0 {
+        warn!(
+            "skipped {} native certificates that couldn't be parsed",
+            ignored
+        );
+    }
+
+    Ok(roots)
+}
+
+/// Create a TLS connector that verifies the server certificate.
+fn verifying_connector(mode: ServerTlsMode, ca: Option<&PathBuf>) -> Result<TlsConnector, Error> {
+    let roots = if let Some(ca) = ca {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(ca)? {
+            roots.add(cert?)?;
+        }
+        roots
+    } else {
+        native_roots()?
+    };
+
+    let verifier = WebPkiServerVerifier::builder(Arc::new(roots)).build()?;
+
+    let config = if mode == ServerTlsMode::VerifyFull {
+        ClientConfig::builder()
+            .with_webpki_verifier(verifier)
+            .with_no_client_auth()
+    } else {
+        ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(CaVerifier { verifier }))
+            .with_no_client_auth()
+    };
+
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+/// Preload TLS at startup, and again on reload
+/// in case certificates changed.
 pub fn load() -> Result<(), Error> {
     let config = config();
 
-    if let Some((cert, key)) = config.config.general.tls() {
-        load_acceptor(cert, key)?;
-    }
+    // Nothing is replaced unless everything loads,
+    // so a bad certificate on reload doesn't turn off TLS.
+    let acceptor = match config.config.general.tls() {
+        Some((cert, key)) => match load_acceptor(cert, key)? {
+            Some(acceptor) => Some(acceptor),
+            None if ACCEPTOR.load().is_some() => return Err(Error::TlsReload(cert.clone())),
+            None => None,
+        },
+        None => None,
+    };
 
     connector()?;
 
+    // CA certificates are read again.
+    // Check them for all databases now, instead of failing on first connection.
+    let mut connectors = HashMap::new();
+    for database in &config.config.databases {
+        if database.server_tls_ca.is_some() && !database.server_tls_mode.verify() {
+            warn!(
+                "server_tls_ca for database \"{}\" (shard {}) is only used with server_tls_mode \"verify_ca\" or \"verify_full\"",
+                database.name, database.shard
+            );
+        }
+
+        if !database.server_tls_mode.verify() {
+            continue;
+        }
+
+        let key = (database.server_tls_mode, database.server_tls_ca.clone());
+        if connectors.contains_key(&key) {
+            continue;
+        }
+
+        let connector =
+            verifying_connector(database.server_tls_mode, database.server_tls_ca.as_ref())
+                .map_err(|error| Error::ServerTls {
+                    database: database.name.clone(),
+                    shard: database.shard,
+                    error: Box::new(error),
+                })?;
+        connectors.insert(key, connector);
+    }
+
+    if acceptor.is_some() {
+        info!("🔑 TLS on");
+    }
+    ACCEPTOR.store(acceptor.map(Arc::new));
+    *VERIFYING_CONNECTORS.lock() = connectors;
+
     Ok(())
 }
 
+/// Verify the server certificate is signed by a trusted CA,
+/// without checking the host name.
+#[derive(Debug)]
+struct CaVerifier {
+    verifier: Arc<WebPkiServerVerifier>,
+}
+
+impl ServerCertVerifier for CaVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &rustls::pki_types::ServerName<'_>,
+        ocsp_response: &[u8],
+        now: rustls::pki_types::UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.verifier.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        ) {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. },
+            )) => Ok(ServerCertVerified::assertion()),
+            result => result,
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &rustls::DigitallySignedStruct,
+    ) -> Result<rustls::client::danger::HandshakeSignatureValid, rustls::Error> {
+        self.verifier.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.verifier.supported_verify_schemes()
+    }
+}
+
 #[derive(Debug)]
 struct CertificateVerifyer {
     verifier: Arc<dyn ClientCertVerifier>,
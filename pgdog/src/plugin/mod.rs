@@ -69,11 +69,7 @@ pub fn shutdown() {
 
 /// Get plugin by name.
 pub fn plugin(name: &str) -> Option<&Plugin> {
-    PLUGINS
-        .get()
-        .unwrap()
-        .iter()
-        .find(|&plugin| plugin.name() == name)
+    PLUGINS.get()?.iter().find(|&plugin| plugin.name() == name)
 }
 
 /// Get all loaded plugins.
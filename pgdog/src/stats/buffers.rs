@@ -0,0 +1,76 @@
+//! Cross-shard buffer metrics.
+use crate::backend::pool::connection::buffer::{stats, Stats};
+
+use super::*;
+
+pub struct BufferMetric {
+    name: String,
+    help: String,
+    value: usize,
+    gauge: bool,
+}
+
+pub struct Buffers {
+    stats: Stats,
+}
+
+impl Buffers {
+    pub(crate) fn load() -> Self {
+        Buffers { stats: stats() }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<Metric> {
+        vec![
+            Metric::new(BufferMetric {
+                name: "cross_shard_buffered_bytes".into(),
+                help: "Bytes buffered in memory to sort and aggregate rows from multiple shards"
+                    .into(),
+                value: self.stats.buffered,
+                gauge: true,
+            }),
+            Metric::new(BufferMetric {
+                name: "cross_shard_spills".into(),
+                help: "Sorted runs written to temporary files".into(),
+                value: self.stats.spills,
+                gauge: false,
+            }),
+            Metric::new(BufferMetric {
+                name: "cross_shard_spilled_bytes".into(),
+                help: "Bytes written to temporary files".into(),
+                value: self.stats.spilled,
+                gauge: false,
+            }),
+            Metric::new(BufferMetric {
+                name: "cross_shard_work_mem_exceeded".into(),
+                help: "Queries that returned an error because they ran out of memory".into(),
+                value: self.stats.exceeded,
+                gauge: false,
+            }),
+        ]
+    }
+}
+
+impl OpenMetric for BufferMetric {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn metric_type(&self) -> String {
+        if self.gauge {
+            "gauge".into()
+        } else {
+            "counter".into()
+        }
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(self.help.clone())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        vec![Measurement {
+            labels: vec![],
+            measurement: MeasurementType::Integer(self.value as i64),
+        }]
+    }
+}
@@ -1,8 +1,27 @@
 //! Clients metrics.
 
+use std::collections::HashMap;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
 use crate::frontend::comms::comms;
 
-use super::{Measurement, Metric, OpenMetric};
+use super::{Histogram, Measurement, Metric, OpenMetric, PoolHistogram};
+
+/// Time from connecting to logging in, by user and database.
+static CONNECT_TIME: Lazy<Mutex<HashMap<(String, String), Histogram>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Client logged in this long after connecting.
+pub fn logged_in(user: &str, database: &str, duration: Duration) {
+    CONNECT_TIME
+        .lock()
+        .entry((user.to_owned(), database.to_owned()))
+        .or_default()
+        .observe(duration);
+}
 
 pub struct Clients {
     total: usize,
@@ -13,6 +32,29 @@ impl Clients {
         let total = comms().clients_len();
         Metric::new(Self { total })
     }
+
+    /// Client connect time histograms.
+    pub fn connect_time() -> Metric {
+        let histograms = CONNECT_TIME
+            .lock()
+            .iter()
+            .map(|((user, database), histogram)| {
+                (
+                    vec![
+                        ("user".into(), user.clone()),
+                        ("database".into(), database.clone()),
+                    ],
+                    *histogram,
+                )
+            })
+            .collect();
+
+        Metric::new(PoolHistogram {
+            name: "client_connect_time_seconds".into(),
+            histograms,
+            help: "Time from connecting to logging in, including TLS and authentication.".into(),
+        })
+    }
 }
 
 impl OpenMetric for Clients {
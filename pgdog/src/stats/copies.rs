@@ -0,0 +1,113 @@
+//! COPY progress metrics.
+use std::collections::BTreeMap;
+
+use crate::frontend::router::parser::progress::{copies, totals, Progress, Totals};
+
+use super::*;
+
+pub struct CopyMetric {
+    name: String,
+    help: String,
+    measurements: Vec<Measurement>,
+    gauge: bool,
+}
+
+pub struct Copies {
+    copies: Vec<Progress>,
+    totals: Totals,
+}
+
+impl Copies {
+    pub(crate) fn load() -> Self {
+        Copies {
+            copies: copies(),
+            totals: totals(),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<Metric> {
+        let total = |value: usize| {
+            vec![Measurement {
+                labels: vec![],
+                measurement: MeasurementType::Integer(value as i64),
+            }]
+        };
+        // Running COPYs into the same table are added up, so each table is one series.
+        let mut tables: BTreeMap<&str, (usize, f64)> = BTreeMap::new();
+        for copy in &self.copies {
+            let table = tables.entry(copy.table.as_str()).or_default();
+            table.0 += copy.rows;
+            table.1 += copy.rate();
+        }
+        let each = |value: &dyn Fn(&(usize, f64)) -> MeasurementType| {
+            tables
+                .iter()
+                .map(|(table, progress)| Measurement {
+                    labels: vec![("table".into(), table.to_string())],
+                    measurement: value(progress),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        vec![
+            Metric::new(CopyMetric {
+                name: "copy_total".into(),
+                help: "COPY statements started".into(),
+                measurements: total(self.totals.copies),
+                gauge: false,
+            }),
+            Metric::new(CopyMetric {
+                name: "copy_rows".into(),
+                help: "Rows parsed from COPY statements".into(),
+                measurements: total(self.totals.rows),
+                gauge: false,
+            }),
+            Metric::new(CopyMetric {
+                name: "copy_bytes".into(),
+                help: "Bytes received from clients in COPY statements".into(),
+                measurements: total(self.totals.bytes),
+                gauge: false,
+            }),
+            Metric::new(CopyMetric {
+                name: "copy_active".into(),
+                help: "COPY statements currently running".into(),
+                measurements: total(self.copies.len()),
+                gauge: true,
+            }),
+            Metric::new(CopyMetric {
+                name: "copy_progress_rows".into(),
+                help: "Rows parsed by running COPYs into each table".into(),
+                measurements: each(&|(rows, _)| MeasurementType::Integer(*rows as i64)),
+                gauge: true,
+            }),
+            Metric::new(CopyMetric {
+                name: "copy_progress_rate".into(),
+                help: "Rows per second parsed by running COPYs into each table".into(),
+                measurements: each(&|(_, rate)| MeasurementType::Float(*rate)),
+                gauge: true,
+            }),
+        ]
+    }
+}
+
+impl OpenMetric for CopyMetric {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn metric_type(&self) -> String {
+        if self.gauge {
+            "gauge".into()
+        } else {
+            "counter".into()
+        }
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(self.help.clone())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        self.measurements.clone()
+    }
+}
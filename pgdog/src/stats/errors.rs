@@ -0,0 +1,129 @@
+//! Recent errors, shown with `SHOW ERRORS`.
+//!
+//! Bans and disconnects are usually caused by errors logged
+//! a while before. The last few are kept in memory, so operators
+//! don't need access to the logs to find them.
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::SystemTime;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::net::messages::ErrorResponse;
+
+/// Errors kept for `SHOW ERRORS`.
+const MAX_ERRORS: usize = 1_000;
+
+static ERRORS: Lazy<Mutex<Errors>> = Lazy::new(|| Mutex::new(Errors::default()));
+
+/// Error returned by a server or by PgDog itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorEvent {
+    /// When the error happened.
+    pub created_at: SystemTime,
+    /// Server address or, if we couldn't get one, the database.
+    pub pool: String,
+    /// SQLSTATE, if the error was sent to a client.
+    pub code: String,
+    /// Error message.
+    pub message: String,
+    /// Client that got the error, if any.
+    pub client: Option<SocketAddr>,
+}
+
+impl ErrorEvent {
+    /// Error sent to a client.
+    pub fn new(pool: impl ToString, error: &ErrorResponse, client: SocketAddr) -> Self {
+        Self {
+            created_at: SystemTime::now(),
+            pool: pool.to_string(),
+            code: error.code.clone(),
+            message: error.message.clone(),
+            client: Some(client),
+        }
+    }
+
+    /// Error affecting the whole pool, e.g. a ban.
+    pub fn pool(pool: impl ToString, message: impl ToString) -> Self {
+        Self {
+            created_at: SystemTime::now(),
+            pool: pool.to_string(),
+            code: String::new(),
+            message: message.to_string(),
+            client: None,
+        }
+    }
+}
+
+/// Recent errors, oldest first.
+#[derive(Debug, Default)]
+struct Errors {
+    events: VecDeque<ErrorEvent>,
+}
+
+impl Errors {
+    fn record(&mut self, event: ErrorEvent) {
+        if self.events.len() >= MAX_ERRORS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
+
+/// Remember the error, forgetting the oldest one if we have too many.
+pub fn record(event: ErrorEvent) {
+    ERRORS.lock().record(event);
+}
+
+/// Errors returned by servers that are worth keeping: lost connections,
+/// shutdowns, and running out of resources. Errors caused by queries,
+/// e.g. unique violations, are the application's business.
+pub fn connection_error(error: &ErrorResponse) -> bool {
+    ["08", "53", "57P", "58"]
+        .iter()
+        .any(|class| error.code.starts_with(class))
+}
+
+/// Recent errors, oldest first.
+pub fn errors() -> Vec<ErrorEvent> {
+    ERRORS.lock().events.iter().cloned().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_errors() {
+        let client = "127.0.0.1:1234".parse().unwrap();
+        let mut errors = Errors::default();
+        for i in 0..MAX_ERRORS + 5 {
+            errors.record(ErrorEvent::new(
+                format!("test_errors_{}", i),
+                &ErrorResponse::syntax("syntax error"),
+                client,
+            ));
+        }
+
+        assert_eq!(errors.events.len(), MAX_ERRORS);
+        assert_eq!(errors.events.front().unwrap().pool, "test_errors_5");
+        let last = errors.events.back().unwrap();
+        assert_eq!(last.pool, format!("test_errors_{}", MAX_ERRORS + 4));
+        assert_eq!(last.code, "42601");
+        assert_eq!(last.client, Some(client));
+    }
+
+    #[test]
+    fn test_connection_error() {
+        assert!(connection_error(&ErrorResponse::connection()));
+        assert!(connection_error(&ErrorResponse::shutting_down()));
+        assert!(!connection_error(&ErrorResponse::syntax("syntax error")));
+
+        // Statement timeouts are caused by queries.
+        let mut error = ErrorResponse::syntax("canceling statement due to statement timeout");
+        error.code = "57014".into();
+        assert!(!connection_error(&error));
+    }
+}
@@ -0,0 +1,376 @@
+//! Export stats snapshots into tables on one of the databases.
+//!
+//! Every `export_interval`, pool, client and query stats are inserted into
+//! `pool_stats`, `client_stats` and `query_stats` in the `export_schema`,
+//! with the time of the snapshot. The tables are created if they don't exist.
+//! History can then be queried with plain SQL, e.g.:
+//!
+//! ```sql
+//! SELECT created_at, sum(query_count) FROM pgdog.pool_stats GROUP BY 1 ORDER BY 1;
+//! ```
+//!
+//! Old snapshots are never deleted by PgDog.
+
+use std::time::Duration;
+
+use tokio::{spawn, time::sleep};
+use tracing::{info, warn};
+
+use crate::backend::{
+    databases::databases, pool::Request, protocol::ProtocolMessage, Cluster, Error,
+};
+use crate::config::{config, Stats};
+use crate::frontend::comms::comms;
+use crate::net::{
+    bind::Parameter, Bind, ErrorResponse, Execute, FromBytes, Parse, Protocol, Sync, ToBytes,
+};
+
+use super::query_stats::stats;
+
+/// Start exporting stats, if configured.
+pub fn start() {
+    let stats = config().config.stats.clone();
+    let Some(database) = stats.export_database.clone() else {
+        return;
+    };
+
+    info!(
+        "exporting stats to \"{}\" in database \"{}\"",
+        stats.export_schema, database
+    );
+
+    spawn(async move {
+        let mut created = false;
+
+        loop {
+            sleep(Duration::from_millis(stats.export_interval)).await;
+
+            if let Err(err) = export(&stats, &database, &mut created).await {
+                warn!("stats export error: {} [{}]", err, database);
+            }
+        }
+    });
+}
+
+async fn export(stats: &Stats, database: &str, created: &mut bool) -> Result<(), Error> {
+    let cluster = cluster(database, stats.export_user.as_deref())?;
+    let mut server = cluster.primary(0, &Request::default()).await?;
+    let schema = &stats.export_schema;
+
+    if !*created {
+        server.execute_checked(create_tables(schema)).await?;
+        *created = true;
+    }
+
+    let mut messages = vec![];
+    for (insert, rows) in [
+        (insert(schema, "pool_stats", POOL_COLUMNS), pool_rows()),
+        (
+            insert(schema, "client_stats", CLIENT_COLUMNS),
+            client_rows(),
+        ),
+        (insert(schema, "query_stats", QUERY_COLUMNS), query_rows()),
+    ] {
+        if rows.is_empty() {
+            continue;
+        }
+        messages.push(ProtocolMessage::from(Parse::new_anonymous(&insert)));
+        for row in rows {
+            messages.push(Bind::new_params("", &params(row)).into());
+            messages.push(Execute::new().into());
+        }
+    }
+
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    // Sent with one Sync, so it's one transaction
+    // and all rows get the same created_at.
+    messages.push(Sync::new().into());
+    server.send(&messages.into()).await?;
+
+    let mut error = None;
+    loop {
+        let message = server.read().await?;
+        match message.code() {
+            'E' => error = Some(ErrorResponse::from_bytes(message.to_bytes()?)?),
+            'Z' => break,
+            _ => (),
+        }
+    }
+
+    match error {
+        Some(error) => Err(Error::ExecutionError(Box::new(error))),
+        None => Ok(()),
+    }
+}
+
+/// Cluster of `export_user`, or of the first user of the database in users.toml.
+fn cluster(database: &str, user: Option<&str>) -> Result<Cluster, Error> {
+    let config = config();
+    let user = user
+        .or_else(|| {
+            config
+                .users
+                .users
+                .iter()
+                .find(|user| user.database == database)
+                .map(|user| user.name.as_str())
+        })
+        .ok_or(Error::NoCluster)?;
+
+    databases().cluster((user, database))
+}
+
+const POOL_COLUMNS: &[&str] = &[
+    "database",
+    "user",
+    "addr",
+    "port",
+    "shard",
+    "role",
+    "cl_waiting",
+    "sv_active",
+    "sv_idle",
+    "xact_count",
+    "query_count",
+    "received",
+    "sent",
+    "xact_time",
+    "query_time",
+    "wait_time",
+    "reads",
+    "writes",
+    "cross_shard",
+    "errors",
+];
+
+const CLIENT_COLUMNS: &[&str] = &[
+    "database",
+    "user",
+    "addr",
+    "state",
+    "transactions",
+    "queries",
+    "errors",
+    "received",
+    "sent",
+    "transaction_time",
+    "query_time",
+    "wait_time",
+];
+
+const QUERY_COLUMNS: &[&str] = &[
+    "queryid",
+    "query",
+    "calls",
+    "total_exec_time",
+    "mean_exec_time",
+    "rows",
+];
+
+fn create_tables(schema: &str) -> String {
+    let schema = identifier(schema);
+    format!(
+        r#"CREATE SCHEMA IF NOT EXISTS {schema};
+CREATE TABLE IF NOT EXISTS {schema}.pool_stats (
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    database TEXT NOT NULL,
+    "user" TEXT NOT NULL,
+    addr TEXT NOT NULL,
+    port INTEGER NOT NULL,
+    shard INTEGER NOT NULL,
+    role TEXT NOT NULL,
+    cl_waiting BIGINT NOT NULL,
+    sv_active BIGINT NOT NULL,
+    sv_idle BIGINT NOT NULL,
+    xact_count BIGINT NOT NULL,
+    query_count BIGINT NOT NULL,
+    received BIGINT NOT NULL,
+    sent BIGINT NOT NULL,
+    xact_time DOUBLE PRECISION NOT NULL,
+    query_time DOUBLE PRECISION NOT NULL,
+    wait_time DOUBLE PRECISION NOT NULL,
+    reads BIGINT NOT NULL,
+    writes BIGINT NOT NULL,
+    cross_shard BIGINT NOT NULL,
+    errors BIGINT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS {schema}.client_stats (
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    database TEXT NOT NULL,
+    "user" TEXT NOT NULL,
+    addr TEXT NOT NULL,
+    state TEXT NOT NULL,
+    transactions BIGINT NOT NULL,
+    queries BIGINT NOT NULL,
+    errors BIGINT NOT NULL,
+    received BIGINT NOT NULL,
+    sent BIGINT NOT NULL,
+    transaction_time DOUBLE PRECISION NOT NULL,
+    query_time DOUBLE PRECISION NOT NULL,
+    wait_time DOUBLE PRECISION NOT NULL
+);
+CREATE TABLE IF NOT EXISTS {schema}.query_stats (
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    queryid TEXT NOT NULL,
+    query TEXT NOT NULL,
+    calls BIGINT NOT NULL,
+    total_exec_time DOUBLE PRECISION NOT NULL,
+    mean_exec_time DOUBLE PRECISION NOT NULL,
+    rows BIGINT NOT NULL
+)"#
+    )
+}
+
+/// Stats of every pool, as in `SHOW STATS` and `SHOW POOLS`.
+fn pool_rows() -> Vec<Vec<String>> {
+    let mut rows = vec![];
+
+    for (user, cluster) in databases().all().iter() {
+        for (shard, pools) in cluster.shards().iter().enumerate() {
+            for (role, pool) in pools.pools_with_roles() {
+                let state = pool.state();
+                let counts = state.stats.counts;
+                rows.push(vec![
+                    user.database.clone(),
+                    user.user.clone(),
+                    pool.addr().host.clone(),
+                    pool.addr().port.to_string(),
+                    shard.to_string(),
+                    role.to_string(),
+                    state.waiting.to_string(),
+                    state.checked_out.to_string(),
+                    state.idle.to_string(),
+                    counts.xact_count.to_string(),
+                    counts.query_count.to_string(),
+                    counts.received.to_string(),
+                    counts.sent.to_string(),
+                    millis(counts.xact_time),
+                    millis(counts.query_time),
+                    millis(counts.wait_time),
+                    counts.reads.to_string(),
+                    counts.writes.to_string(),
+                    counts.cross_shard.to_string(),
+                    counts.errors.to_string(),
+                ]);
+            }
+        }
+    }
+
+    rows
+}
+
+/// Stats of every connected client, as in `SHOW CLIENTS`.
+fn client_rows() -> Vec<Vec<String>> {
+    comms()
+        .clients()
+        .into_values()
+        .map(|client| {
+            let user = client.paramters.get_default("user", "postgres");
+            let stats = client.stats;
+            vec![
+                client.paramters.get_default("database", user).to_string(),
+                user.to_string(),
+                client.addr.to_string(),
+                stats.state.to_string(),
+                stats.transactions.to_string(),
+                stats.queries.to_string(),
+                stats.errors.to_string(),
+                stats.bytes_received.to_string(),
+                stats.bytes_sent.to_string(),
+                millis(stats.transaction_time),
+                millis(stats.query_time),
+                millis(stats.wait_time),
+            ]
+        })
+        .collect()
+}
+
+/// Stats of every tracked query, as in `SHOW QUERY STATS`.
+fn query_rows() -> Vec<Vec<String>> {
+    stats()
+        .into_iter()
+        .map(|stats| {
+            vec![
+                stats.fingerprint.clone(),
+                stats.query.clone(),
+                stats.calls.to_string(),
+                millis(stats.total()),
+                millis(stats.mean()),
+                stats.rows.to_string(),
+            ]
+        })
+        .collect()
+}
+
+/// INSERT statement for one row, with a parameter for each column.
+fn insert(schema: &str, table: &str, columns: &[&str]) -> String {
+    let placeholders = (1..=columns.len())
+        .map(|i| format!("${}", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let columns = columns
+        .iter()
+        .map(|column| identifier(column))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "INSERT INTO {}.{} ({}) VALUES ({})",
+        identifier(schema),
+        table,
+        columns,
+        placeholders
+    )
+}
+
+/// Row values as text parameters.
+fn params(row: Vec<String>) -> Vec<Parameter> {
+    row.into_iter()
+        .map(|value| Parameter {
+            len: value.len() as i32,
+            data: value.into_bytes(),
+        })
+        .collect()
+}
+
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn millis(duration: Duration) -> String {
+    (duration.as_secs_f64() * 1000.0).to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_insert() {
+        let insert = insert("pgdog", "query_stats", &["queryid", "query", "calls"]);
+        assert_eq!(
+            insert,
+            "INSERT INTO \"pgdog\".query_stats (\"queryid\", \"query\", \"calls\") VALUES ($1, $2, $3)"
+        );
+
+        let params = params(vec!["SELECT 'it''s'".into(), "5".into()]);
+        assert_eq!(params[0].data, b"SELECT 'it''s'");
+        assert_eq!(params[1].len, 1);
+    }
+
+    #[test]
+    fn test_create_tables() {
+        let sql = create_tables("my\"stats");
+        assert!(sql.starts_with("CREATE SCHEMA IF NOT EXISTS \"my\"\"stats\";"));
+        for table in ["pool_stats", "client_stats", "query_stats"] {
+            assert!(sql.contains(&format!(
+                "CREATE TABLE IF NOT EXISTS \"my\"\"stats\".{} (",
+                table
+            )));
+        }
+        assert_eq!(sql.matches("CREATE").count(), 4);
+    }
+}
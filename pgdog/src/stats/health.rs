@@ -0,0 +1,210 @@
+//! Health endpoints served with OpenMetrics, for Kubernetes probes and load balancers.
+//!
+//! * `/livez`: the process is running.
+//! * `/readyz`: clients can be served, according to the `readiness` setting.
+//! * `/healthz`: same as `/readyz`, with the state of every pool.
+//!
+//! All return JSON, with status 200 if the check passed or 503 if it didn't.
+//!
+//! A pool is up if it's online, not banned, and its last healthcheck passed.
+//! Pools that haven't been checked yet, e.g. right after startup, count as up.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::backend::databases::databases;
+use crate::config::{config, Readiness, Role};
+use crate::frontend::comms::comms;
+
+static LISTENING: AtomicBool = AtomicBool::new(false);
+
+/// The client listener is bound and accepting connections.
+pub fn listening(listening: bool) {
+    LISTENING.store(listening, Ordering::Relaxed);
+}
+
+/// State of one connection pool.
+#[derive(Debug, Clone, PartialEq)]
+struct PoolHealth {
+    database: String,
+    user: String,
+    shard: usize,
+    role: Role,
+    addr: String,
+    online: bool,
+    banned: bool,
+    ban: Option<String>,
+    /// Result of the last healthcheck and how long ago it ran.
+    healthcheck: Option<(bool, Duration)>,
+}
+
+impl PoolHealth {
+    fn up(&self) -> bool {
+        self.online && !self.banned && self.healthcheck.is_none_or(|(passed, _)| passed)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "database": self.database,
+            "user": self.user,
+            "shard": self.shard,
+            "role": self.role.to_string(),
+            "addr": self.addr,
+            "up": self.up(),
+            "banned": self.banned,
+            "ban_reason": self.ban,
+            "healthcheck": self.healthcheck.map(|(passed, age)| json!({
+                "passed": passed,
+                "age_ms": age.as_millis() as u64,
+            })),
+        })
+    }
+}
+
+/// Everything the checks are based on.
+#[derive(Debug, Clone)]
+struct Health {
+    listening: bool,
+    accepting: bool,
+    pools: Vec<PoolHealth>,
+}
+
+impl Health {
+    fn load() -> Self {
+        let mut pools = vec![];
+        for (user, cluster) in databases().all().iter() {
+            for (shard, pools_with_roles) in cluster.shards().iter().enumerate() {
+                for (role, pool) in pools_with_roles.pools_with_roles() {
+                    let state = pool.state();
+                    pools.push(PoolHealth {
+                        database: user.database.clone(),
+                        user: user.user.clone(),
+                        shard,
+                        role,
+                        addr: pool.addr().to_string(),
+                        online: state.online,
+                        banned: state.banned,
+                        ban: state.ban.map(|ban| ban.to_string()),
+                        healthcheck: state.healthcheck,
+                    });
+                }
+            }
+        }
+
+        Self {
+            listening: LISTENING.load(Ordering::Relaxed),
+            accepting: comms().accepting(),
+            pools,
+        }
+    }
+
+    fn primaries_up(&self) -> bool {
+        self.pools
+            .iter()
+            .filter(|pool| pool.role == Role::Primary)
+            .all(|pool| pool.up())
+    }
+
+    fn pools_up(&self) -> bool {
+        self.pools.iter().all(|pool| pool.up())
+    }
+
+    /// Ready to serve clients.
+    fn ready(&self, readiness: Readiness) -> bool {
+        let criteria = match readiness {
+            Readiness::Listener => true,
+            Readiness::AllPrimaries => self.primaries_up(),
+            Readiness::AllPools => self.pools_up(),
+        };
+
+        self.listening && self.accepting && criteria
+    }
+
+    fn to_json(&self, readiness: Readiness, pools: bool) -> Value {
+        let mut health = json!({
+            "ready": self.ready(readiness),
+            "listening": self.listening,
+            "accepting": self.accepting,
+            "primaries_up": self.primaries_up(),
+            "pools_up": self.pools_up(),
+        });
+
+        if pools {
+            health["pools"] = self.pools.iter().map(|pool| pool.to_json()).collect();
+        }
+
+        health
+    }
+}
+
+/// Status code and body of a health endpoint, if the path is one.
+pub(crate) fn check(path: &str) -> Option<(bool, Value)> {
+    let readiness = config().config.general.readiness;
+
+    match path {
+        "/livez" => Some((true, json!({"live": true}))),
+        "/readyz" => {
+            let health = Health::load();
+            Some((health.ready(readiness), health.to_json(readiness, false)))
+        }
+        "/healthz" => {
+            let health = Health::load();
+            Some((health.ready(readiness), health.to_json(readiness, true)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pool(role: Role, banned: bool) -> PoolHealth {
+        PoolHealth {
+            database: "pgdog".into(),
+            user: "pgdog".into(),
+            shard: 0,
+            role,
+            addr: "127.0.0.1:5432".into(),
+            online: true,
+            banned,
+            ban: None,
+            healthcheck: Some((true, Duration::from_secs(1))),
+        }
+    }
+
+    #[test]
+    fn test_ready() {
+        let mut health = Health {
+            listening: true,
+            accepting: true,
+            pools: vec![pool(Role::Primary, false), pool(Role::Replica, true)],
+        };
+
+        assert!(health.ready(Readiness::Listener));
+        assert!(health.ready(Readiness::AllPrimaries));
+        assert!(!health.ready(Readiness::AllPools));
+
+        let json = health.to_json(Readiness::AllPrimaries, true);
+        assert_eq!(json["ready"], true);
+        assert_eq!(json["pools_up"], false);
+        assert_eq!(json["pools"][1]["banned"], true);
+        assert_eq!(json["pools"][0]["healthcheck"]["age_ms"], 1000);
+        assert!(health.to_json(Readiness::Listener, false)["pools"].is_null());
+
+        // Primary can't be reached.
+        health.pools[0].healthcheck = Some((false, Duration::from_secs(1)));
+        assert!(!health.ready(Readiness::AllPrimaries));
+
+        health.pools[0].healthcheck = None;
+        assert!(health.ready(Readiness::AllPrimaries));
+
+        health.pools[0].banned = true;
+        assert!(!health.ready(Readiness::AllPrimaries));
+
+        health.listening = false;
+        assert!(!health.ready(Readiness::Listener));
+    }
+}
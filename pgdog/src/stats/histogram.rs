@@ -0,0 +1,189 @@
+//! Latency histogram with fixed buckets.
+//!
+//! Buckets are set in `[stats]` with `histogram_buckets` and
+//! are read once, so changing them requires a restart.
+
+use std::ops::{Add, AddAssign};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+
+use crate::config::config;
+
+/// Default upper bounds of histogram buckets, in milliseconds.
+pub const DEFAULT_BUCKETS: [f64; 12] = [
+    1.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1_000.0, 2_500.0, 5_000.0, 10_000.0,
+];
+
+/// Maximum number of buckets, not counting +Inf.
+pub const MAX_BUCKETS: usize = 32;
+
+/// Upper bounds of histogram buckets, in milliseconds.
+/// The last bucket (+Inf) is implicit.
+static BUCKETS: Lazy<Vec<f64>> = Lazy::new(|| buckets(&config().config.stats.histogram_buckets));
+
+/// Sorted, positive bucket bounds, or the default ones if none are valid.
+fn buckets(configured: &[f64]) -> Vec<f64> {
+    let mut buckets = configured
+        .iter()
+        .copied()
+        .filter(|bound| bound.is_finite() && *bound > 0.0)
+        .collect::<Vec<_>>();
+    buckets.sort_by(f64::total_cmp);
+    buckets.dedup();
+    buckets.truncate(MAX_BUCKETS);
+
+    if buckets.is_empty() {
+        DEFAULT_BUCKETS.to_vec()
+    } else {
+        buckets
+    }
+}
+
+/// Bucket bound as a duration, without float rounding errors.
+fn millis(ms: f64) -> Duration {
+    Duration::from_nanos((ms * 1_000_000.0).round() as u64)
+}
+
+/// Latency histogram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Histogram {
+    counts: [u64; MAX_BUCKETS + 1],
+    sum: Duration,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            counts: [0; MAX_BUCKETS + 1],
+            sum: Duration::ZERO,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    /// Record an observation.
+    pub fn observe(&mut self, duration: Duration) {
+        let millis = duration.as_secs_f64() * 1000.0;
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| millis <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += duration;
+        self.count += 1;
+    }
+
+    /// Number of observations.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of all observations.
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    /// Cumulative bucket counts, with upper bounds.
+    /// The last bucket has no upper bound (+Inf).
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        let mut total = 0;
+        self.counts[..=BUCKETS.len()]
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                total += count;
+                (BUCKETS.get(i).copied().map(millis), total)
+            })
+            .collect()
+    }
+
+    /// Estimate a percentile, e.g. 0.99 for p99, by interpolating
+    /// inside the bucket it falls in.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+
+        let rank = (self.count as f64 * percentile).ceil().max(1.0);
+        let mut seen = 0.0;
+
+        for (i, count) in self.counts[..=BUCKETS.len()].iter().enumerate() {
+            let count = *count as f64;
+            if count > 0.0 && seen + count >= rank {
+                let lower = if i == 0 { 0.0 } else { BUCKETS[i - 1] };
+                let upper = match BUCKETS.get(i) {
+                    Some(upper) => *upper,
+                    None => return millis(BUCKETS[BUCKETS.len() - 1]),
+                };
+                return millis(lower + (upper - lower) * ((rank - seen) / count));
+            }
+            seen += count;
+        }
+
+        Duration::ZERO
+    }
+}
+
+impl Add for Histogram {
+    type Output = Histogram;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for Histogram {
+    fn add_assign(&mut self, rhs: Self) {
+        for (count, other) in self.counts.iter_mut().zip(rhs.counts) {
+            *count = count.saturating_add(other);
+        }
+        self.sum = self.sum.saturating_add(rhs.sum);
+        self.count = self.count.saturating_add(rhs.count);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.percentile(0.5), Duration::ZERO);
+
+        for _ in 0..90 {
+            histogram.observe(Duration::from_micros(500));
+        }
+        for _ in 0..9 {
+            histogram.observe(Duration::from_millis(20));
+        }
+        histogram.observe(Duration::from_secs(30));
+
+        assert_eq!(histogram.count(), 100);
+        assert!(histogram.percentile(0.5) <= Duration::from_millis(1));
+        let p95 = histogram.percentile(0.95);
+        assert!(p95 > Duration::from_millis(10) && p95 <= Duration::from_millis(25));
+        assert_eq!(histogram.percentile(1.0), Duration::from_millis(10_000));
+
+        let buckets = histogram.buckets();
+        assert_eq!(buckets[0], (Some(Duration::from_millis(1)), 90));
+        assert_eq!(buckets.last().unwrap(), &(None, 100));
+
+        let sum = histogram + histogram;
+        assert_eq!(sum.count(), 200);
+        assert_eq!(sum.buckets()[0].1, 180);
+    }
+
+    #[test]
+    fn test_buckets() {
+        assert_eq!(buckets(&[]), DEFAULT_BUCKETS.to_vec());
+        assert_eq!(buckets(&[-1.0, f64::NAN]), DEFAULT_BUCKETS.to_vec());
+        assert_eq!(buckets(&[100.0, 0.5, 10.0, 10.0]), vec![0.5, 10.0, 100.0]);
+        let many = (1..100).map(|bound| bound as f64).collect::<Vec<_>>();
+        assert_eq!(buckets(&many).len(), MAX_BUCKETS);
+    }
+}
@@ -1,18 +1,34 @@
 use std::convert::Infallible;
+use std::future::Future;
 use std::net::SocketAddr;
 
 use http_body_util::Full;
-use hyper::body::Bytes;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::CONTENT_TYPE;
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
-use hyper::{Request, Response};
+use hyper::{Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
 use tokio::net::TcpListener;
-use tracing::info;
+use tracing::{error, info};
 
-use super::{Clients, Pools, QueryCache};
+use super::{health, Buffers, Clients, Copies, Pools, QueryCache, TopQueries, WaitEventsMetric};
+
+async fn metrics(
+    request: Request<Incoming>,
+    _: SocketAddr,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    if let Some((ok, body)) = health::check(request.uri().path()) {
+        let mut response = Response::new(Full::new(Bytes::from(body.to_string())));
+        if !ok {
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        }
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, "application/json".parse().unwrap());
+        return Ok(response);
+    }
 
-async fn metrics(_: Request<hyper::body::Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
     let clients = Clients::load();
     let pools = Pools::load();
     let query_cache: Vec<_> = QueryCache::load()
@@ -21,26 +37,66 @@ async fn metrics(_: Request<hyper::body::Incoming>) -> Result<Response<Full<Byte
         .map(|m| m.to_string())
         .collect();
     let query_cache = query_cache.join("\n");
+    let buffers: Vec<_> = Buffers::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let buffers = buffers.join("\n");
+    let copies: Vec<_> = Copies::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let copies = copies.join("\n");
+    let top_queries: Vec<_> = TopQueries::load()
+        .metrics()
+        .into_iter()
+        .map(|m| m.to_string())
+        .collect();
+    let top_queries = top_queries.join("\n");
     Ok(Response::new(Full::new(Bytes::from(
-        clients.to_string() + "\n" + &pools.to_string() + "\n" + &query_cache,
+        clients.to_string()
+            + "\n"
+            + &Clients::connect_time().to_string()
+            + "\n"
+            + &WaitEventsMetric::load().to_string()
+            + "\n"
+            + &pools.to_string()
+            + "\n"
+            + &query_cache
+            + "\n"
+            + &buffers
+            + "\n"
+            + &copies
+            + "\n"
+            + &top_queries,
     ))))
 }
 
 pub async fn server(port: u16) -> std::io::Result<()> {
     info!("OpenMetrics endpoint http://0.0.0.0:{}", port);
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    let listener = TcpListener::bind(addr).await?;
+    serve("0.0.0.0", port, metrics).await
+}
+
+/// Serve HTTP/1 requests on this address with this handler.
+pub(crate) async fn serve<F, R>(host: &str, port: u16, handler: F) -> std::io::Result<()>
+where
+    F: Fn(Request<Incoming>, SocketAddr) -> R + Copy + Send + Sync + 'static,
+    R: Future<Output = Result<Response<Full<Bytes>>, Infallible>> + Send + 'static,
+{
+    let listener = TcpListener::bind((host, port)).await?;
 
     loop {
-        let (stream, _) = listener.accept().await?;
+        let (stream, peer) = listener.accept().await?;
         let io = TokioIo::new(stream);
 
         tokio::task::spawn(async move {
             if let Err(err) = http1::Builder::new()
-                .serve_connection(io, service_fn(metrics))
+                .serve_connection(io, service_fn(move |request| handler(request, peer)))
                 .await
             {
-                eprintln!("OpenMetrics endpoint error: {:?}", err);
+                error!("HTTP endpoint error on port {}: {}", port, err);
             }
         });
     }
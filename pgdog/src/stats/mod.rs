@@ -1,13 +1,27 @@
 //! Statistics.
+pub mod buffers;
 pub mod clients;
+pub mod copies;
+pub mod errors;
+pub mod export;
+pub mod health;
+pub mod histogram;
 pub mod http_server;
 pub mod open_metric;
 pub mod pools;
 pub use open_metric::*;
 pub mod logger;
 pub mod query_cache;
+pub mod query_stats;
+pub mod statsd;
+pub mod wait_events;
 
+pub use buffers::Buffers;
 pub use clients::Clients;
+pub use copies::Copies;
+pub use histogram::Histogram;
 pub use logger::Logger as StatsLogger;
-pub use pools::{PoolMetric, Pools};
+pub use pools::{PoolHistogram, PoolMetric, Pools};
 pub use query_cache::QueryCache;
+pub use query_stats::TopQueries;
+pub use wait_events::WaitEventsMetric;
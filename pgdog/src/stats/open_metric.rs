@@ -17,6 +17,14 @@ pub trait OpenMetric: Send + Sync {
     fn help(&self) -> Option<String> {
         None
     }
+    /// Measurements with a suffix added to the metric name,
+    /// e.g. `_bucket` for histograms.
+    fn samples(&self) -> Vec<(String, Measurement)> {
+        self.measurements()
+            .into_iter()
+            .map(|measurement| (String::new(), measurement))
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -112,8 +120,8 @@ impl std::fmt::Display for Metric {
             writeln!(f, "# HELP {} {}", name, help)?;
         }
 
-        for measurement in self.measurements() {
-            writeln!(f, "{}", measurement.render(&name))?;
+        for (suffix, measurement) in self.samples() {
+            writeln!(f, "{}", measurement.render(&format!("{}{}", name, suffix)))?;
         }
         Ok(())
     }
@@ -1,6 +1,8 @@
+use std::time::UNIX_EPOCH;
+
 use crate::backend::databases::databases;
 
-use super::{Measurement, Metric, OpenMetric};
+use super::{Histogram, Measurement, Metric, OpenMetric};
 
 pub struct PoolMetric {
     pub name: String,
@@ -36,6 +38,76 @@ impl OpenMetric for PoolMetric {
     }
 }
 
+/// Latency histogram for each pool.
+pub struct PoolHistogram {
+    pub name: String,
+    pub histograms: Vec<(Vec<(String, String)>, Histogram)>,
+    pub help: String,
+}
+
+impl OpenMetric for PoolHistogram {
+    fn help(&self) -> Option<String> {
+        Some(self.help.clone())
+    }
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        vec![]
+    }
+
+    fn unit(&self) -> Option<String> {
+        Some("seconds".into())
+    }
+
+    fn metric_type(&self) -> String {
+        "histogram".into()
+    }
+
+    fn samples(&self) -> Vec<(String, Measurement)> {
+        let mut samples = vec![];
+
+        for (labels, histogram) in &self.histograms {
+            for (bound, count) in histogram.buckets() {
+                let mut labels = labels.clone();
+                labels.push((
+                    "le".into(),
+                    bound
+                        .map(|bound| bound.as_secs_f64().to_string())
+                        .unwrap_or("+Inf".into()),
+                ));
+                samples.push((
+                    "_bucket".into(),
+                    Measurement {
+                        labels,
+                        measurement: (count as i64).into(),
+                    },
+                ));
+            }
+
+            samples.push((
+                "_sum".into(),
+                Measurement {
+                    labels: labels.clone(),
+                    measurement: histogram.sum().as_secs_f64().into(),
+                },
+            ));
+
+            samples.push((
+                "_count".into(),
+                Measurement {
+                    labels: labels.clone(),
+                    measurement: (histogram.count() as i64).into(),
+                },
+            ));
+        }
+
+        samples
+    }
+}
+
 pub struct Pools {
     metrics: Vec<Metric>,
 }
@@ -49,9 +121,17 @@ impl Pools {
         let mut maxwait = vec![];
         let mut errors = vec![];
         let mut out_of_sync = vec![];
+        let mut replica_fallbacks = vec![];
+        let mut replica_lag = vec![];
+        let mut replica_lag_bytes = vec![];
         let mut total_xact_count = vec![];
         let mut avg_xact_count = vec![];
         let mut total_query_count = vec![];
+        let mut total_reads = vec![];
+        let mut total_writes = vec![];
+        let mut total_cross_shard = vec![];
+        let mut total_errors = vec![];
+        let mut stats_reset = vec![];
         let mut avg_query_count = vec![];
         let mut total_sent = vec![];
         let mut avg_sent = vec![];
@@ -61,11 +141,15 @@ impl Pools {
         let mut avg_xact_time = vec![];
         let mut total_query_time = vec![];
         let mut avg_query_time = vec![];
+        let mut wait_histogram = vec![];
+        let mut query_histogram = vec![];
+        let mut xact_histogram = vec![];
         for (user, cluster) in databases().all() {
             for (shard_num, shard) in cluster.shards().iter().enumerate() {
+                let primary_lsn = shard.primary_lsn();
                 for (role, pool) in shard.pools_with_roles() {
                     let state = pool.state();
-                    let labels = vec![
+                    let mut labels = vec![
                         ("user".into(), user.user.clone()),
                         ("database".into(), user.database.clone()),
                         ("host".into(), pool.addr().host.clone()),
@@ -73,6 +157,9 @@ impl Pools {
                         ("shard".into(), shard_num.to_string()),
                         ("role".into(), role.to_string()),
                     ];
+                    if let Some(ref application_name) = user.application_name {
+                        labels.push(("application_name".into(), application_name.clone()));
+                    }
 
                     cl_waiting.push(Measurement {
                         labels: labels.clone(),
@@ -104,6 +191,25 @@ impl Pools {
                         measurement: state.out_of_sync.into(),
                     });
 
+                    replica_fallbacks.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: state.replica_fallbacks.into(),
+                    });
+
+                    if let Some(lag) = pool.replication_lag() {
+                        replica_lag.push(Measurement {
+                            labels: labels.clone(),
+                            measurement: lag.lag.as_secs_f64().into(),
+                        });
+
+                        if let Some(bytes) = lag.bytes(primary_lsn) {
+                            replica_lag_bytes.push(Measurement {
+                                labels: labels.clone(),
+                                measurement: (bytes as i64).into(),
+                            });
+                        }
+                    }
+
                     let stats = state.stats;
                     let totals = stats.counts;
                     let averages = stats.averages;
@@ -123,6 +229,36 @@ impl Pools {
                         measurement: totals.query_count.into(),
                     });
 
+                    total_reads.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: totals.reads.into(),
+                    });
+
+                    total_writes.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: totals.writes.into(),
+                    });
+
+                    total_cross_shard.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: totals.cross_shard.into(),
+                    });
+
+                    total_errors.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: totals.errors.into(),
+                    });
+
+                    stats_reset.push(Measurement {
+                        labels: labels.clone(),
+                        measurement: stats
+                            .stats_reset
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs_f64()
+                            .into(),
+                    });
+
                     avg_query_count.push(Measurement {
                         labels: labels.clone(),
                         measurement: averages.query_count.into(),
@@ -167,6 +303,11 @@ impl Pools {
                         labels: labels.clone(),
                         measurement: averages.query_time.as_millis().into(),
                     });
+
+                    let histograms = stats.histograms;
+                    wait_histogram.push((labels.clone(), histograms.wait));
+                    query_histogram.push((labels.clone(), histograms.query));
+                    xact_histogram.push((labels.clone(), histograms.xact));
                 }
             }
         }
@@ -219,6 +360,30 @@ impl Pools {
             metric_type: Some("counter".into()),
         }));
 
+        metrics.push(Metric::new(PoolMetric {
+            name: "replica_fallbacks".into(),
+            measurements: replica_fallbacks,
+            help: "Reads sent to the primary because all replicas were down.".into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "replica_lag".into(),
+            measurements: replica_lag,
+            help: "Time since the replica replayed the last transaction, if it's behind.".into(),
+            unit: Some("seconds".into()),
+            metric_type: None,
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "replica_lag_bytes".into(),
+            measurements: replica_lag_bytes,
+            help: "Bytes of WAL the replica is behind the primary.".into(),
+            unit: None,
+            metric_type: None,
+        }));
+
         metrics.push(Metric::new(PoolMetric {
             name: "total_xact_count".into(),
             measurements: total_xact_count,
@@ -243,6 +408,46 @@ impl Pools {
             metric_type: Some("counter".into()),
         }));
 
+        metrics.push(Metric::new(PoolMetric {
+            name: "total_reads".into(),
+            measurements: total_reads,
+            help: "Total number of queries routed as reads.".into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "total_writes".into(),
+            measurements: total_writes,
+            help: "Total number of queries routed as writes.".into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "total_cross_shard".into(),
+            measurements: total_cross_shard,
+            help: "Total number of queries sent to this shard and others at the same time.".into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "total_errors".into(),
+            measurements: total_errors,
+            help: "Total number of errors returned by the server.".into(),
+            unit: None,
+            metric_type: Some("counter".into()),
+        }));
+
+        metrics.push(Metric::new(PoolMetric {
+            name: "stats_reset".into(),
+            measurements: stats_reset,
+            help: "When the pool started counting statistics, as a Unix timestamp.".into(),
+            unit: Some("seconds".into()),
+            metric_type: None,
+        }));
+
         metrics.push(Metric::new(PoolMetric {
             name: "avg_query_count".into(),
             measurements: avg_query_count,
@@ -315,10 +520,35 @@ impl Pools {
             metric_type: None,
         }));
 
+        metrics.push(Metric::new(PoolHistogram {
+            name: "wait_time_seconds".into(),
+            histograms: wait_histogram,
+            help: "Time clients waited for a connection.".into(),
+        }));
+
+        metrics.push(Metric::new(PoolHistogram {
+            name: "query_time_seconds".into(),
+            histograms: query_histogram,
+            help: "Time spent executing queries.".into(),
+        }));
+
+        metrics.push(Metric::new(PoolHistogram {
+            name: "xact_time_seconds".into(),
+            histograms: xact_histogram,
+            help: "Time spent executing transactions.".into(),
+        }));
+
         Pools { metrics }
     }
 }
 
+impl Pools {
+    /// Metrics of all pools.
+    pub(crate) fn into_metrics(self) -> Vec<Metric> {
+        self.metrics
+    }
+}
+
 impl std::fmt::Display for Pools {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for pool in &self.metrics {
@@ -363,4 +593,31 @@ mod test {
             r#"maxwait{database="test_db",user="test_user"} 45.000"#
         );
     }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        histogram.observe(std::time::Duration::from_millis(3));
+        let metric = Metric::new(PoolHistogram {
+            name: "wait_time_seconds".into(),
+            histograms: vec![(vec![("database".into(), "test_db".into())], histogram)],
+            help: "Time clients waited for a connection.".into(),
+        });
+        let rendered = metric.to_string();
+        let mut lines = rendered.lines();
+        assert_eq!(lines.next().unwrap(), "# TYPE wait_time_seconds histogram");
+        assert_eq!(lines.next().unwrap(), "# UNIT wait_time_seconds seconds");
+        lines.next();
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"wait_time_seconds_bucket{database="test_db",le="0.001"} 0"#
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            r#"wait_time_seconds_bucket{database="test_db",le="0.005"} 1"#
+        );
+        assert!(rendered.contains(r#"wait_time_seconds_bucket{database="test_db",le="+Inf"} 1"#));
+        assert!(rendered.contains(r#"wait_time_seconds_sum{database="test_db"} 0.003"#));
+        assert!(rendered.contains(r#"wait_time_seconds_count{database="test_db"} 1"#));
+    }
 }
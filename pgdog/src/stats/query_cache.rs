@@ -52,6 +52,24 @@ impl QueryCache {
                 value: self.stats.size,
                 gauge: true,
             }),
+            Metric::new(QueryCacheMetric {
+                name: "route_cache_hits".into(),
+                help: "Routing decisions found in the route cache".into(),
+                value: self.stats.route_hits,
+                gauge: false,
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "route_cache_misses".into(),
+                help: "Routing decisions not found in the route cache".into(),
+                value: self.stats.route_misses,
+                gauge: false,
+            }),
+            Metric::new(QueryCacheMetric {
+                name: "route_cache_size".into(),
+                help: "Number of routing decisions in the route cache".into(),
+                value: self.stats.route_size,
+                gauge: true,
+            }),
         ]
     }
 }
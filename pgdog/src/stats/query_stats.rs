@@ -0,0 +1,344 @@
+//! Execution statistics by query fingerprint, like `pg_stat_statements`,
+//! shown in `SHOW QUERY STATS`.
+//!
+//! Queries are fingerprinted with pg_query, so the same statement with
+//! different parameters is counted once. Finished statements are sent to a
+//! background task, which fingerprints them on the blocking thread pool, so
+//! clients don't wait for the parser. Fingerprints of recently seen query
+//! texts are kept, so we don't parse the same query again.
+//!
+//! If the task falls behind by more than [`QUEUE`] statements, new ones are
+//! dropped from the statistics.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::{
+    spawn,
+    sync::mpsc::{channel, Sender},
+    task::spawn_blocking,
+};
+
+use crate::config::config;
+
+use super::*;
+
+/// Statements waiting to be recorded.
+const QUEUE: usize = 4096;
+
+static STATS: Lazy<Mutex<Inner>> = Lazy::new(|| Mutex::new(Inner::default()));
+
+static QUEUED: Lazy<Sender<(Execution, Duration)>> = Lazy::new(|| {
+    let (tx, mut rx) = channel(QUEUE);
+    spawn(async move {
+        let mut batch = vec![];
+        while rx.recv_many(&mut batch, QUEUE).await > 0 {
+            let executions = std::mem::take(&mut batch);
+            let _ = spawn_blocking(move || {
+                for (execution, duration) in executions {
+                    record(execution, duration);
+                }
+            })
+            .await;
+        }
+    });
+    tx
+});
+
+#[derive(Default)]
+struct Inner {
+    queries: HashMap<u64, QueryStats>,
+    fingerprints: Fingerprints,
+}
+
+/// Fingerprints of query texts we've seen, least recently used evicted first.
+#[derive(Default)]
+struct Fingerprints {
+    fingerprints: HashMap<String, (u64, u64)>,
+    used: BTreeMap<u64, String>,
+    counter: u64,
+}
+
+impl Fingerprints {
+    fn get(&mut self, query: &str) -> Option<u64> {
+        self.counter += 1;
+        let (fingerprint, used) = self.fingerprints.get_mut(query)?;
+        let query = self.used.remove(used)?;
+        *used = self.counter;
+        self.used.insert(self.counter, query);
+
+        Some(*fingerprint)
+    }
+
+    fn insert(&mut self, query: String, fingerprint: u64, capacity: usize) {
+        self.counter += 1;
+        if let Some((_, used)) = self
+            .fingerprints
+            .insert(query.clone(), (fingerprint, self.counter))
+        {
+            self.used.remove(&used);
+        }
+        self.used.insert(self.counter, query);
+
+        while self.fingerprints.len() > capacity {
+            let Some((_, query)) = self.used.pop_first() else {
+                break;
+            };
+            self.fingerprints.remove(&query);
+        }
+    }
+}
+
+/// Statistics of one query fingerprint.
+#[derive(Debug, Clone, Default)]
+pub struct QueryStats {
+    /// pg_query fingerprint.
+    pub fingerprint: String,
+    /// Normalized text of the first query with this fingerprint.
+    pub query: String,
+    /// Number of executions.
+    pub calls: usize,
+    /// Rows returned or affected.
+    pub rows: usize,
+    /// Execution time.
+    pub histogram: Histogram,
+    /// Executions by shard they were sent to.
+    pub shards: BTreeMap<String, usize>,
+}
+
+impl QueryStats {
+    /// Total execution time.
+    pub fn total(&self) -> Duration {
+        self.histogram.sum()
+    }
+
+    /// Mean execution time.
+    pub fn mean(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::ZERO
+        } else {
+            self.total() / self.calls as u32
+        }
+    }
+}
+
+/// Statement executed by a client, recorded once it's done.
+#[derive(Debug)]
+pub struct Execution {
+    query: String,
+    shard: String,
+    started_at: Instant,
+    rows: usize,
+}
+
+impl Execution {
+    /// Statement started executing, if query stats are enabled.
+    pub fn start(query: &str, shard: String) -> Option<Self> {
+        if !config().config.general.query_stats {
+            return None;
+        }
+
+        Some(Self {
+            query: query.to_owned(),
+            shard,
+            started_at: Instant::now(),
+            rows: 0,
+        })
+    }
+
+    /// Rows returned or affected, from `CommandComplete`.
+    pub fn rows(&mut self, rows: usize) {
+        self.rows += rows;
+    }
+
+    /// Statement finished.
+    pub fn finish(self) {
+        let duration = self.started_at.elapsed();
+        let _ = QUEUED.try_send((self, duration));
+    }
+}
+
+fn record(execution: Execution, duration: Duration) {
+    let max = config().config.general.query_stats_max;
+
+    let fingerprint = STATS.lock().fingerprints.get(&execution.query);
+    // Parse outside the lock, it's slow.
+    let (fingerprint, normalized) = match fingerprint {
+        Some(fingerprint) => (fingerprint, None),
+        None => {
+            let Ok(fingerprint) = pg_query::fingerprint(&execution.query) else {
+                return;
+            };
+            (fingerprint.value, Some(fingerprint.hex))
+        }
+    };
+
+    let mut guard = STATS.lock();
+    let Inner {
+        queries,
+        fingerprints,
+    } = &mut *guard;
+
+    if let Some(ref hex) = normalized {
+        // Queries with literals are all different, don't keep them forever.
+        fingerprints.insert(execution.query.clone(), fingerprint, max * 10);
+
+        if !queries.contains_key(&fingerprint) {
+            if queries.len() >= max {
+                return;
+            }
+            queries.insert(
+                fingerprint,
+                QueryStats {
+                    fingerprint: hex.clone(),
+                    query: pg_query::normalize(&execution.query).unwrap_or(execution.query),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    if let Some(stats) = queries.get_mut(&fingerprint) {
+        stats.calls += 1;
+        stats.rows += execution.rows;
+        stats.histogram.observe(duration);
+        *stats.shards.entry(execution.shard).or_default() += 1;
+    }
+}
+
+/// Statistics of all tracked queries, slowest in total first.
+pub fn stats() -> Vec<QueryStats> {
+    let mut stats = STATS.lock().queries.values().cloned().collect::<Vec<_>>();
+    stats.sort_by_key(|stats| Reverse(stats.total()));
+    stats
+}
+
+pub struct QueryStatsMetric {
+    name: String,
+    help: String,
+    measurements: Vec<Measurement>,
+}
+
+/// Slowest queries in total, exported to OpenMetrics.
+/// Each one is a label, so there's only a few.
+pub struct TopQueries {
+    stats: Vec<QueryStats>,
+}
+
+impl TopQueries {
+    pub(crate) fn load() -> Self {
+        let limit = config().config.general.openmetrics_query_stats;
+        TopQueries {
+            stats: stats().into_iter().take(limit).collect(),
+        }
+    }
+
+    pub(crate) fn metrics(&self) -> Vec<Metric> {
+        if self.stats.is_empty() {
+            return vec![];
+        }
+
+        let each = |value: &dyn Fn(&QueryStats) -> MeasurementType| {
+            self.stats
+                .iter()
+                .map(|stats| Measurement {
+                    labels: vec![("fingerprint".into(), stats.fingerprint.clone())],
+                    measurement: value(stats),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        vec![
+            Metric::new(QueryStatsMetric {
+                name: "query_stats_calls".into(),
+                help: "Executions of the query".into(),
+                measurements: each(&|stats| stats.calls.into()),
+            }),
+            Metric::new(QueryStatsMetric {
+                name: "query_stats_time".into(),
+                help: "Total execution time of the query, in milliseconds".into(),
+                measurements: each(&|stats| stats.total().as_millis().into()),
+            }),
+            Metric::new(QueryStatsMetric {
+                name: "query_stats_rows".into(),
+                help: "Rows returned or affected by the query".into(),
+                measurements: each(&|stats| stats.rows.into()),
+            }),
+        ]
+    }
+}
+
+impl OpenMetric for QueryStatsMetric {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn metric_type(&self) -> String {
+        "counter".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some(self.help.clone())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        self.measurements.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let execution = |query: &str, shard: &str, rows: usize| Execution {
+            query: query.into(),
+            shard: shard.into(),
+            started_at: Instant::now(),
+            rows,
+        };
+
+        record(
+            execution("SELECT * FROM test_record WHERE id = 1", "0", 1),
+            Duration::from_millis(2),
+        );
+        record(
+            execution("SELECT * FROM test_record WHERE id = 2", "1", 1),
+            Duration::from_millis(4),
+        );
+        record(
+            execution("SELECT * FROM test_record WHERE id = 1", "0", 1),
+            Duration::from_millis(6),
+        );
+
+        let stats = stats()
+            .into_iter()
+            .find(|stats| stats.query.contains("test_record"))
+            .unwrap();
+        assert_eq!(stats.query, "SELECT * FROM test_record WHERE id = $1");
+        assert_eq!(stats.calls, 3);
+        assert_eq!(stats.rows, 3);
+        assert_eq!(stats.total(), Duration::from_millis(12));
+        assert_eq!(stats.mean(), Duration::from_millis(4));
+        assert_eq!(stats.shards.get("0"), Some(&2));
+        assert_eq!(stats.shards.get("1"), Some(&1));
+    }
+
+    #[test]
+    fn test_fingerprints() {
+        let mut fingerprints = Fingerprints::default();
+        fingerprints.insert("SELECT 1".into(), 1, 2);
+        fingerprints.insert("SELECT 2".into(), 2, 2);
+        assert_eq!(fingerprints.get("SELECT 1"), Some(1));
+
+        // Least recently used is evicted, not everything.
+        fingerprints.insert("SELECT 3".into(), 3, 2);
+        assert_eq!(fingerprints.get("SELECT 2"), None);
+        assert_eq!(fingerprints.get("SELECT 1"), Some(1));
+        assert_eq!(fingerprints.get("SELECT 3"), Some(3));
+    }
+}
@@ -0,0 +1,221 @@
+//! StatsD and DogStatsD exporter.
+//!
+//! Pushes the same metrics as the OpenMetrics endpoint over UDP, for
+//! deployments that don't scrape Prometheus. Gauges are sent as they are and
+//! counters as the increase since the last push. DogStatsD gets labels as tags;
+//! plain StatsD doesn't support them, so label values are added to the metric name.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use tokio::{net::UdpSocket, spawn, time::sleep};
+use tracing::{info, warn};
+
+use crate::config::{config, StatsdFormat};
+
+use super::*;
+
+/// Largest packet that fits in a typical MTU without fragmenting.
+const MAX_PACKET: usize = 1_432;
+
+/// Push metrics to the StatsD server, if one is configured.
+pub fn start() {
+    let stats = config().config.stats.clone();
+    let Some(address) = stats.statsd else {
+        return;
+    };
+
+    info!("sending metrics to statsd at {}", address);
+
+    spawn(async move {
+        let mut counters = HashMap::new();
+
+        loop {
+            sleep(Duration::from_millis(stats.statsd_interval)).await;
+
+            let lines = lines(
+                &metrics(),
+                &stats.statsd_prefix,
+                stats.statsd_format,
+                &mut counters,
+            );
+            if let Err(err) = send(&address, &lines).await {
+                warn!("statsd error: {} [{}]", err, address);
+            }
+        }
+    });
+}
+
+async fn send(address: &str, lines: &[String]) -> std::io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.connect(address).await?;
+
+    let mut packet = String::new();
+    for line in lines {
+        if !packet.is_empty() && packet.len() + line.len() + 1 > MAX_PACKET {
+            socket.send(packet.as_bytes()).await?;
+            packet.clear();
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+    }
+    if !packet.is_empty() {
+        socket.send(packet.as_bytes()).await?;
+    }
+
+    Ok(())
+}
+
+/// Everything exported to OpenMetrics.
+fn metrics() -> Vec<Metric> {
+    let mut metrics = vec![
+        Clients::load(),
+        Clients::connect_time(),
+        WaitEventsMetric::load(),
+    ];
+    metrics.extend(Pools::load().into_metrics());
+    metrics.extend(QueryCache::load().metrics());
+    metrics.extend(Buffers::load().metrics());
+    metrics.extend(Copies::load().metrics());
+    metrics.extend(TopQueries::load().metrics());
+    metrics
+}
+
+/// StatsD lines for these metrics. Counters are sent as the increase
+/// since they were last seen, so they need to be remembered.
+fn lines(
+    metrics: &[Metric],
+    prefix: &str,
+    format: StatsdFormat,
+    counters: &mut HashMap<String, f64>,
+) -> Vec<String> {
+    let mut lines = vec![];
+
+    for metric in metrics {
+        let metric_type = metric.metric_type();
+
+        for (suffix, measurement) in metric.samples() {
+            // Bucket counts don't mean anything without Prometheus.
+            if suffix == "_bucket" {
+                continue;
+            }
+
+            let mut name = format!("{}.{}{}", prefix, metric.name(), suffix);
+            let mut tags = String::new();
+            match format {
+                StatsdFormat::Statsd => {
+                    for (_, value) in &measurement.labels {
+                        name.push('.');
+                        name.push_str(&sanitize(value));
+                    }
+                }
+                StatsdFormat::Dogstatsd => {
+                    let labels = measurement
+                        .labels
+                        .iter()
+                        .map(|(key, value)| format!("{}:{}", sanitize(key), sanitize(value)))
+                        .collect::<Vec<_>>();
+                    if !labels.is_empty() {
+                        tags = format!("|#{}", labels.join(","));
+                    }
+                }
+            }
+            let name = sanitize(&name);
+
+            let value = match measurement.measurement {
+                MeasurementType::Float(value) => value,
+                MeasurementType::Integer(value) => value as f64,
+                MeasurementType::Millis(value) => value as f64,
+            };
+
+            if metric_type == "gauge" {
+                lines.push(format!("{}:{}|g{}", name, value, tags));
+            } else {
+                let key = format!("{}{}", name, tags);
+                // Counters start from zero when pools are reloaded.
+                let delta = match counters.insert(key, value) {
+                    Some(previous) if value >= previous => value - previous,
+                    Some(_) => value,
+                    None => continue,
+                };
+                lines.push(format!("{}:{}|c{}", name, delta, tags));
+            }
+        }
+    }
+
+    lines
+}
+
+/// Remove characters that have a meaning in the StatsD protocol.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            ':' | '|' | '@' | '#' | ',' | '\n' | ' ' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn metrics(value: i64) -> Vec<Metric> {
+        let labels = vec![
+            ("user".into(), "pgdog".into()),
+            ("database".into(), "prod:1".into()),
+        ];
+        vec![
+            Metric::new(PoolMetric {
+                name: "cl_waiting".into(),
+                measurements: vec![Measurement {
+                    labels: labels.clone(),
+                    measurement: value.into(),
+                }],
+                help: "Clients waiting.".into(),
+                unit: None,
+                metric_type: None,
+            }),
+            Metric::new(PoolMetric {
+                name: "total_xact_count".into(),
+                measurements: vec![Measurement {
+                    labels,
+                    measurement: (value * 10).into(),
+                }],
+                help: "Transactions.".into(),
+                unit: None,
+                metric_type: Some("counter".into()),
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_dogstatsd() {
+        let mut counters = HashMap::new();
+
+        let sent = lines(&metrics(1), "pgdog", StatsdFormat::Dogstatsd, &mut counters);
+        assert_eq!(
+            sent,
+            vec!["pgdog.cl_waiting:1|g|#user:pgdog,database:prod_1".to_string()]
+        );
+
+        let sent = lines(&metrics(3), "pgdog", StatsdFormat::Dogstatsd, &mut counters);
+        assert_eq!(
+            sent,
+            vec![
+                "pgdog.cl_waiting:3|g|#user:pgdog,database:prod_1".to_string(),
+                "pgdog.total_xact_count:20|c|#user:pgdog,database:prod_1".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_statsd() {
+        let mut counters = HashMap::new();
+        let sent = lines(&metrics(2), "pgdog", StatsdFormat::Statsd, &mut counters);
+        assert_eq!(sent, vec!["pgdog.cl_waiting.pgdog.prod_1:2|g".to_string()]);
+    }
+}
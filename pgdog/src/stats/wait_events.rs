@@ -0,0 +1,170 @@
+//! Time clients spend in each state, by pool, shown in `SHOW WAIT EVENTS`.
+//!
+//! Like wait events in `pg_stat_activity`, but cumulative. Comparing them
+//! shows if clients are slow because the pool is too small (checkout),
+//! the queries are slow (server), the clients read slowly (client)
+//! or they hold on to transactions doing nothing (idle in transaction).
+
+use std::collections::HashMap;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::{Measurement, Metric, OpenMetric};
+
+static WAIT_EVENTS: Lazy<Mutex<HashMap<(String, String), WaitEvents>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Time spent in each state.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WaitEvents {
+    /// Waiting for a connection from the pool.
+    pub checkout: Duration,
+    /// Waiting for the server to respond.
+    pub server: Duration,
+    /// Sending server responses to the client.
+    pub client: Duration,
+    /// In a transaction, waiting for the client to send a query.
+    pub idle_in_transaction: Duration,
+}
+
+impl WaitEvents {
+    /// Time spent in all states.
+    pub fn total(&self) -> Duration {
+        self.checkout + self.server + self.client + self.idle_in_transaction
+    }
+
+    /// State clients spend most of their time in.
+    pub fn bottleneck(&self) -> &'static str {
+        [
+            (self.checkout, "checkout"),
+            (self.server, "server"),
+            (self.client, "client"),
+            (self.idle_in_transaction, "idle in transaction"),
+        ]
+        .into_iter()
+        .filter(|(time, _)| !time.is_zero())
+        .max_by_key(|(time, _)| *time)
+        .map(|(_, name)| name)
+        .unwrap_or("")
+    }
+
+    fn events(&self) -> [(&'static str, Duration); 4] {
+        [
+            ("checkout", self.checkout),
+            ("server", self.server),
+            ("client", self.client),
+            ("idle_in_transaction", self.idle_in_transaction),
+        ]
+    }
+}
+
+impl AddAssign for WaitEvents {
+    fn add_assign(&mut self, rhs: Self) {
+        self.checkout += rhs.checkout;
+        self.server += rhs.server;
+        self.client += rhs.client;
+        self.idle_in_transaction += rhs.idle_in_transaction;
+    }
+}
+
+/// Add time spent by a client of this pool.
+pub fn record(user: &str, database: &str, events: WaitEvents) {
+    if events == WaitEvents::default() {
+        return;
+    }
+
+    *WAIT_EVENTS
+        .lock()
+        .entry((user.to_owned(), database.to_owned()))
+        .or_default() += events;
+}
+
+/// Time spent by clients of each pool, by user and database.
+pub fn wait_events() -> Vec<((String, String), WaitEvents)> {
+    let mut events = WAIT_EVENTS
+        .lock()
+        .iter()
+        .map(|(pool, events)| (pool.clone(), *events))
+        .collect::<Vec<_>>();
+    events.sort_by(|a, b| a.0.cmp(&b.0));
+    events
+}
+
+/// Wait event time, exported to OpenMetrics.
+pub struct WaitEventsMetric {
+    events: Vec<((String, String), WaitEvents)>,
+}
+
+impl WaitEventsMetric {
+    pub fn load() -> Metric {
+        Metric::new(Self {
+            events: wait_events(),
+        })
+    }
+}
+
+impl OpenMetric for WaitEventsMetric {
+    fn name(&self) -> String {
+        "wait_event_time".into()
+    }
+
+    fn metric_type(&self) -> String {
+        "counter".into()
+    }
+
+    fn help(&self) -> Option<String> {
+        Some("Time clients spent in each state, in milliseconds.".into())
+    }
+
+    fn measurements(&self) -> Vec<Measurement> {
+        self.events
+            .iter()
+            .flat_map(|((user, database), events)| {
+                events.events().map(|(event, time)| Measurement {
+                    labels: vec![
+                        ("user".into(), user.clone()),
+                        ("database".into(), database.clone()),
+                        ("event".into(), event.into()),
+                    ],
+                    measurement: time.as_millis().into(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wait_events() {
+        let events = WaitEvents {
+            checkout: Duration::from_millis(5),
+            server: Duration::from_millis(20),
+            client: Duration::from_millis(1),
+            idle_in_transaction: Duration::ZERO,
+        };
+        record("test_wait_events", "db", events);
+        record("test_wait_events", "db", events);
+        record("test_wait_events", "db", WaitEvents::default());
+
+        let (_, events) = wait_events()
+            .into_iter()
+            .find(|(pool, _)| pool.0 == "test_wait_events")
+            .unwrap();
+        assert_eq!(events.server, Duration::from_millis(40));
+        assert_eq!(events.total(), Duration::from_millis(52));
+        assert_eq!(events.bottleneck(), "server");
+        assert_eq!(WaitEvents::default().bottleneck(), "");
+
+        let metric = Metric::new(WaitEventsMetric {
+            events: vec![(("user".into(), "db".into()), events)],
+        })
+        .to_string();
+        assert!(metric.contains(r#"wait_event_time{user="user",database="db",event="server"} 40"#));
+    }
+}
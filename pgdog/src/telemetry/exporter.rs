@@ -0,0 +1,149 @@
+//! Export spans to an OpenTelemetry collector, using OTLP/HTTP with JSON encoding:
+//!
+//! ```text
+//! POST <endpoint>, e.g. http://localhost:4318/v1/traces
+//! ```
+//!
+//! Spans are buffered in memory and sent every second. If the collector
+//! can't keep up, new spans are dropped.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use tokio::{
+    spawn,
+    time::{sleep, timeout},
+};
+use tracing::warn;
+
+use crate::config::config;
+use crate::net::http::{post, Error};
+
+use super::{traceparent::hex, Span};
+
+/// Spans waiting to be sent.
+const MAX_SPANS: usize = 10_000;
+
+/// How often spans are sent.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+static SPANS: Lazy<Mutex<Vec<Span>>> = Lazy::new(|| Mutex::new(vec![]));
+
+/// Queue the span for export.
+pub(super) fn export(span: Span) {
+    let mut spans = SPANS.lock();
+    if spans.len() < MAX_SPANS {
+        spans.push(span);
+    }
+}
+
+/// Send spans to the collector in the background.
+pub fn start() {
+    spawn(async move {
+        loop {
+            sleep(INTERVAL).await;
+
+            let spans = std::mem::take(&mut *SPANS.lock());
+            if spans.is_empty() {
+                continue;
+            }
+
+            let telemetry = config().config.telemetry.clone();
+            let Some(endpoint) = telemetry.endpoint else {
+                continue;
+            };
+
+            let body = body(&telemetry.service_name, &spans);
+            let export = post(&endpoint, "application/json", body.to_string());
+            match timeout(INTERVAL * 5, export).await {
+                Ok(Ok(())) => (),
+                Ok(Err(err)) => warn!("telemetry export error: {} [{}]", err, endpoint),
+                Err(_) => warn!("telemetry export error: {} [{}]", Error::Timeout, endpoint),
+            }
+        }
+    });
+}
+
+/// OTLP/JSON export request.
+fn body(service_name: &str, spans: &[Span]) -> Value {
+    let spans = spans
+        .iter()
+        .map(|span| {
+            let mut value = json!({
+                "traceId": hex(&span.context.trace_id),
+                "spanId": hex(&span.context.span_id),
+                "name": span.name,
+                "kind": span.kind as i32,
+                "startTimeUnixNano": nanos(span.start),
+                "endTimeUnixNano": nanos(span.end),
+                "attributes": attributes(&span.attributes),
+            });
+            if let Some(parent) = span.parent {
+                value["parentSpanId"] = hex(&parent).into();
+            }
+            value
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": attributes(&[("service.name".into(), service_name.into())]),
+            },
+            "scopeSpans": [{
+                "scope": { "name": "pgdog", "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+fn attributes(attributes: &[(String, String)]) -> Value {
+    attributes
+        .iter()
+        .map(|(key, value)| json!({"key": key, "value": {"stringValue": value}}))
+        .collect()
+}
+
+/// 64-bit integers are strings in OTLP/JSON.
+fn nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::telemetry::{SpanKind, TraceContext};
+
+    #[test]
+    fn test_body() {
+        let context =
+            TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        let mut span = Span::new("query", SpanKind::Client, &context, false);
+        span.attribute("db.statement", "SELECT $1");
+        span.start = UNIX_EPOCH + Duration::from_millis(1);
+        span.end = UNIX_EPOCH + Duration::from_millis(2);
+
+        let body = body("pgdog", &[span.clone()]);
+        let exported = &body["resourceSpans"][0]["scopeSpans"][0]["spans"][0];
+        assert_eq!(exported["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(exported["spanId"], hex(&span.context.span_id));
+        assert_eq!(exported["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(exported["kind"], 3);
+        assert_eq!(exported["startTimeUnixNano"], "1000000");
+        assert_eq!(exported["endTimeUnixNano"], "2000000");
+        assert_eq!(
+            exported["attributes"][0],
+            json!({"key": "db.statement", "value": {"stringValue": "SELECT $1"}})
+        );
+        assert_eq!(
+            body["resourceSpans"][0]["resource"]["attributes"][0]["value"]["stringValue"],
+            "pgdog"
+        );
+    }
+}
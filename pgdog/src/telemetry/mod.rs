@@ -0,0 +1,15 @@
+//! OpenTelemetry tracing.
+//!
+//! Client sessions, transactions and queries are exported as spans
+//! to an OTLP/HTTP collector. Applications using sqlcommenter can send
+//! a `traceparent` in a query comment: spans of that query, and of the transaction
+//! it starts, are then added to the application's trace.
+
+pub mod exporter;
+pub mod session;
+pub mod span;
+pub mod traceparent;
+
+pub use session::Session;
+pub use span::{Span, SpanKind};
+pub use traceparent::TraceContext;
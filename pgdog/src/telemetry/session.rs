@@ -0,0 +1,213 @@
+//! Spans of a client connection.
+
+use std::net::SocketAddr;
+
+use crate::config::config;
+
+use super::{Span, SpanKind, TraceContext};
+
+/// Spans of a client connection: the session itself,
+/// the current transaction, and the query being executed.
+///
+/// Queries without a `traceparent` are part of the session's trace.
+#[derive(Debug, Default)]
+pub struct Session {
+    enabled: bool,
+    attributes: Vec<(String, String)>,
+    session: Option<Span>,
+    transaction: Option<Span>,
+    query: Option<Span>,
+}
+
+impl Session {
+    /// Client logged in. Does nothing if tracing is disabled.
+    pub fn new(user: &str, database: &str, addr: SocketAddr) -> Self {
+        let telemetry = &config().config.telemetry;
+        if telemetry.endpoint.is_none() {
+            return Self::default();
+        }
+
+        let attributes = vec![
+            ("db.system".into(), "postgresql".into()),
+            ("db.user".into(), user.into()),
+            ("db.name".into(), database.into()),
+            ("client.address".into(), addr.ip().to_string()),
+        ];
+
+        let context = TraceContext::root(telemetry.sample_rate);
+        let session = context.sampled.then(|| {
+            let mut span = Span::new("session", SpanKind::Server, &context, true);
+            span.attributes = attributes.clone();
+            span
+        });
+
+        Self {
+            enabled: true,
+            attributes,
+            session,
+            transaction: None,
+            query: None,
+        }
+    }
+
+    /// Client sent a query to the servers.
+    pub fn query(&mut self, query: &str, shard: &str, backend_pids: &[i32]) {
+        if !self.enabled {
+            return;
+        }
+
+        // The application decides if its own traces are sampled.
+        let parent = TraceContext::from_query(query)
+            .or_else(|| self.transaction.as_ref().map(|span| span.context))
+            .or_else(|| self.session.as_ref().map(|span| span.context));
+
+        self.query = parent.filter(|parent| parent.sampled).map(|parent| {
+            let mut span = Span::new("query", SpanKind::Client, &parent, false);
+            span.attributes = self.attributes.clone();
+            // Don't export parameters, they could contain sensitive data.
+            let statement = pg_query::normalize(query).unwrap_or_default();
+            let pids = backend_pids
+                .iter()
+                .map(|pid| pid.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            span.attribute("db.statement", statement)
+                .attribute("pgdog.shard", shard)
+                .attribute("pgdog.backend_pids", pids);
+            span
+        });
+    }
+
+    /// Servers finished executing the query.
+    pub fn done(&mut self, in_transaction: bool) {
+        let Some(query) = self.query.take() else {
+            // Not sampled, but could've ended a sampled transaction.
+            if !in_transaction {
+                if let Some(transaction) = self.transaction.take() {
+                    transaction.end();
+                }
+            }
+            return;
+        };
+
+        // The query started a transaction. It's known only now,
+        // so the transaction is a sibling of the query.
+        if in_transaction && self.transaction.is_none() {
+            let mut transaction = query.clone();
+            transaction.name = "transaction".into();
+            transaction.kind = SpanKind::Server;
+            transaction.context = query.context.child();
+            transaction.attributes = self.attributes.clone();
+            self.transaction = Some(transaction);
+        }
+
+        query.end();
+
+        if !in_transaction {
+            if let Some(transaction) = self.transaction.take() {
+                transaction.end();
+            }
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        for span in [
+            self.query.take(),
+            self.transaction.take(),
+            self.session.take(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            span.end();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::telemetry::traceparent::hex;
+
+    fn session() -> Session {
+        let context = TraceContext::root(1.0);
+        Session {
+            enabled: true,
+            attributes: vec![],
+            session: Some(Span::new("session", SpanKind::Server, &context, true)),
+            transaction: None,
+            query: None,
+        }
+    }
+
+    #[test]
+    fn test_query_parent() {
+        let mut session = session();
+        let trace_id = session.session.as_ref().unwrap().context.trace_id;
+
+        session.query("SELECT 1", "0", &[1234]);
+        let query = session.query.clone().unwrap();
+        assert_eq!(query.context.trace_id, trace_id);
+        assert_eq!(
+            query.parent,
+            Some(session.session.as_ref().unwrap().context.span_id)
+        );
+        assert!(query
+            .attributes
+            .contains(&("db.statement".into(), "SELECT $1".into())));
+
+        session.query(
+            "SELECT 1 /*traceparent='00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01'*/",
+            "0",
+            &[],
+        );
+        let query = session.query.clone().unwrap();
+        assert_eq!(
+            hex(&query.context.trace_id),
+            "4bf92f3577b34da6a3ce929d0e0e4736"
+        );
+        assert_eq!(
+            query.parent.map(|id| hex(&id)).as_deref(),
+            Some("00f067aa0ba902b7")
+        );
+
+        // Not sampled by the application.
+        session.query(
+            "SELECT 1 /*traceparent='00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00'*/",
+            "0",
+            &[],
+        );
+        assert!(session.query.is_none());
+    }
+
+    #[test]
+    fn test_transaction() {
+        let mut session = session();
+
+        session.query("BEGIN", "0", &[]);
+        session.done(true);
+        let transaction = session.transaction.clone().unwrap();
+
+        session.query("SELECT 1", "0", &[]);
+        assert_eq!(
+            session.query.as_ref().unwrap().parent,
+            Some(transaction.context.span_id)
+        );
+        session.done(true);
+        assert!(session.transaction.is_some());
+
+        session.query("COMMIT", "0", &[]);
+        session.done(false);
+        assert!(session.transaction.is_none());
+    }
+
+    #[test]
+    fn test_disabled() {
+        let mut session = Session::default();
+        session.query("SELECT 1", "0", &[]);
+        session.done(false);
+        assert!(session.query.is_none());
+    }
+}
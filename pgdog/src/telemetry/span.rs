@@ -0,0 +1,56 @@
+//! Span exported to the collector.
+
+use std::time::SystemTime;
+
+use super::{exporter, TraceContext};
+
+/// OTLP span kind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpanKind {
+    /// Handling a request from a client.
+    Server = 2,
+    /// Request sent to a server.
+    Client = 3,
+}
+
+/// Operation traced from start to end.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub name: String,
+    pub kind: SpanKind,
+    pub context: TraceContext,
+    /// Span this one belongs to, if any.
+    pub parent: Option<[u8; 8]>,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Span {
+    /// Start a span. Root spans use the given context, others
+    /// get their own in the parent's trace.
+    pub fn new(name: &str, kind: SpanKind, parent: &TraceContext, root: bool) -> Self {
+        let now = SystemTime::now();
+        Self {
+            name: name.to_owned(),
+            kind,
+            context: if root { *parent } else { parent.child() },
+            parent: if root { None } else { Some(parent.span_id) },
+            start: now,
+            end: now,
+            attributes: vec![],
+        }
+    }
+
+    /// Add an attribute.
+    pub fn attribute(&mut self, key: &str, value: impl ToString) -> &mut Self {
+        self.attributes.push((key.to_owned(), value.to_string()));
+        self
+    }
+
+    /// The operation is done, export the span.
+    pub fn end(mut self) {
+        self.end = SystemTime::now();
+        exporter::export(self);
+    }
+}
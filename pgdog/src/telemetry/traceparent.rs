@@ -0,0 +1,161 @@
+//! W3C trace context, sent by applications in sqlcommenter comments, e.g.:
+//!
+//! ```sql
+//! SELECT * FROM users /*traceparent='00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01'*/
+//! ```
+
+use once_cell::sync::Lazy;
+use pg_query::{protobuf::Token, scan};
+use rand::Rng;
+use regex::Regex;
+
+static TRACEPARENT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"traceparent='([0-9a-fA-F\-]+)'"#).unwrap());
+
+/// Position of a span in a trace.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    /// The trace is exported.
+    pub sampled: bool,
+}
+
+impl TraceContext {
+    /// Start a new trace, sampled with this probability.
+    pub fn root(sample_rate: f64) -> Self {
+        let mut rng = rand::thread_rng();
+        Self {
+            trace_id: rng.gen(),
+            span_id: rng.gen(),
+            sampled: rng.gen::<f64>() < sample_rate,
+        }
+    }
+
+    /// Context of a new span in the same trace.
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: rand::thread_rng().gen(),
+            sampled: self.sampled,
+        }
+    }
+
+    /// Parse a `traceparent` value, e.g. `00-<trace id>-<span id>-01`.
+    pub fn parse(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.split('-');
+        let version = parts.next()?;
+        let trace_id = decode::<16>(parts.next()?)?;
+        let span_id = decode::<8>(parts.next()?)?;
+        let flags = decode::<1>(parts.next()?)?;
+
+        // Version ff is invalid and version 00 has nothing after the flags.
+        if version.len() != 2 || version.eq_ignore_ascii_case("ff") {
+            return None;
+        }
+        if version == "00" && parts.next().is_some() {
+            return None;
+        }
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags[0] & 1 == 1,
+        })
+    }
+
+    /// Find the `traceparent` in the query's comments, if any.
+    pub fn from_query(query: &str) -> Option<Self> {
+        // Skip the tokenizer for queries without it.
+        if !query.contains("traceparent") {
+            return None;
+        }
+
+        let tokens = scan(query).ok()?;
+        tokens
+            .tokens
+            .iter()
+            .filter(|token| token.token == Token::CComment as i32)
+            .filter_map(|token| {
+                let text = &query[token.start as usize..token.end as usize];
+                TRACEPARENT.captures(text)?.get(1)
+            })
+            .find_map(|traceparent| Self::parse(traceparent.as_str()))
+    }
+}
+
+/// Lowercase hex, as used by traceparent and OTLP/JSON.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    if hex.len() != N * 2 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let context =
+            TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(hex(&context.trace_id), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(hex(&context.span_id), "00f067aa0ba902b7");
+        assert!(context.sampled);
+
+        let context =
+            TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap();
+        assert!(!context.sampled);
+
+        assert!(
+            TraceContext::parse("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+                .is_none()
+        );
+        assert!(
+            TraceContext::parse("00-00000000000000000000000000000000-00f067aa0ba902b7-01")
+                .is_none()
+        );
+        assert!(
+            TraceContext::parse("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7").is_none()
+        );
+        assert!(TraceContext::parse("00-4bf92f3577b34da6-00f067aa0ba902b7-01").is_none());
+    }
+
+    #[test]
+    fn test_from_query() {
+        let context = TraceContext::from_query(
+            "SELECT * FROM users /*action='index',traceparent='00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01'*/",
+        )
+        .unwrap();
+        assert_eq!(hex(&context.span_id), "00f067aa0ba902b7");
+
+        assert!(TraceContext::from_query(
+            "SELECT 'traceparent=''00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01'''"
+        )
+        .is_none());
+        assert!(TraceContext::from_query("SELECT 1").is_none());
+    }
+
+    #[test]
+    fn test_child() {
+        let root = TraceContext::root(1.0);
+        assert!(root.sampled);
+        let child = root.child();
+        assert_eq!(child.trace_id, root.trace_id);
+        assert_ne!(child.span_id, root.span_id);
+        assert!(!TraceContext::root(0.0).sampled);
+    }
+}
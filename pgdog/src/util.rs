@@ -50,6 +50,40 @@ pub fn human_duration(duration: Duration) -> String {
     }
 }
 
+/// Match text against a pattern where `many` matches any text
+/// and `one` any single character, e.g. `%` and `_` in SQL `LIKE`.
+pub fn wildcard(pattern: &str, text: &str, many: char, one: char) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    // Position in the pattern after the last `many`, and in the text where it matched.
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(c) if *c == many => {
+                backtrack = Some((p + 1, t));
+                p += 1;
+            }
+            Some(c) if *c == one || *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    p = bp;
+                    t = bt + 1;
+                    backtrack = Some((bp, bt + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == many)
+}
+
 /// Generate a random string of length n.
 pub fn random_string(n: usize) -> String {
     rand::thread_rng()
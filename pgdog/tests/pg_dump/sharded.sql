@@ -0,0 +1,129 @@
+--
+-- PostgreSQL database dump
+--
+
+-- Dumped from database version 16.4
+-- Dumped by pg_dump version 16.4
+
+SET statement_timeout = 0;
+SET lock_timeout = 0;
+SET idle_in_transaction_session_timeout = 0;
+SET client_encoding = 'UTF8';
+SET standard_conforming_strings = on;
+SELECT pg_catalog.set_config('search_path', '', false);
+SET check_function_bodies = false;
+SET xmloption = content;
+SET client_min_messages = warning;
+SET row_security = off;
+
+SET default_tablespace = '';
+
+SET default_table_access_method = heap;
+
+--
+-- Name: sharded; Type: TABLE; Schema: public; Owner: pgdog
+--
+
+CREATE TABLE public.sharded (
+    id bigint NOT NULL,
+    value text
+);
+
+
+ALTER TABLE public.sharded OWNER TO pgdog;
+
+--
+-- Name: sharded_omni; Type: TABLE; Schema: public; Owner: pgdog
+--
+
+CREATE TABLE public.sharded_omni (
+    id bigint NOT NULL,
+    value text
+);
+
+
+ALTER TABLE public.sharded_omni OWNER TO pgdog;
+
+--
+-- Name: sharded_omni_id_seq; Type: SEQUENCE; Schema: public; Owner: pgdog
+--
+
+CREATE SEQUENCE public.sharded_omni_id_seq
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+
+ALTER SEQUENCE public.sharded_omni_id_seq OWNER TO pgdog;
+
+--
+-- Name: sharded_omni_id_seq; Type: SEQUENCE OWNED BY; Schema: public; Owner: pgdog
+--
+
+ALTER SEQUENCE public.sharded_omni_id_seq OWNED BY public.sharded_omni.id;
+
+
+--
+-- Name: sharded_omni id; Type: DEFAULT; Schema: public; Owner: pgdog
+--
+
+ALTER TABLE ONLY public.sharded_omni ALTER COLUMN id SET DEFAULT nextval('public.sharded_omni_id_seq'::regclass);
+
+
+--
+-- Data for Name: sharded; Type: TABLE DATA; Schema: public; Owner: pgdog
+--
+
+COPY public.sharded (id, value) FROM stdin;
+1	one
+2	two
+3	three
+4	four
+5	five
+6	six
+7	seven
+8	eight
+9	nine
+10	ten
+\.
+
+
+--
+-- Data for Name: sharded_omni; Type: TABLE DATA; Schema: public; Owner: pgdog
+--
+
+COPY public.sharded_omni (id, value) FROM stdin;
+1	us-east-1
+2	eu-west-1
+\.
+
+
+--
+-- Name: sharded_omni_id_seq; Type: SEQUENCE SET; Schema: public; Owner: pgdog
+--
+
+SELECT pg_catalog.setval('public.sharded_omni_id_seq', 2, true);
+
+
+--
+-- Name: sharded sharded_pkey; Type: CONSTRAINT; Schema: public; Owner: pgdog
+--
+
+ALTER TABLE ONLY public.sharded
+    ADD CONSTRAINT sharded_pkey PRIMARY KEY (id);
+
+
+--
+-- Name: sharded_omni sharded_omni_pkey; Type: CONSTRAINT; Schema: public; Owner: pgdog
+--
+
+ALTER TABLE ONLY public.sharded_omni
+    ADD CONSTRAINT sharded_omni_pkey PRIMARY KEY (id);
+
+
+--
+-- PostgreSQL database dump complete
+--
+